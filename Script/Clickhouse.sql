@@ -402,3 +402,51 @@ SELECT tenant_id,
        parseDateTimeBestEffortOrNull(updated_time) AS updated_time
 FROM default.dicom_state_meta_kafka;
 
+
+-- 删除事件, 由 wado-server 删除检查/序列/实例时发送
+create table dicom_deletion_event
+(
+    deletion_id    String,
+    tenant_id      String,
+    level          String,
+    study_uid      String,
+    series_uid     Nullable(String),
+    sop_uid        Nullable(String),
+    instance_count Int64,
+    total_bytes    Int64,
+    status         String,
+    requested_by   Nullable(String),
+    event_time     DateTime
+)
+    engine = MergeTree ORDER BY (tenant_id, study_uid, event_time)
+        SETTINGS index_granularity = 8192;
+
+create table dicom_deletion_event_kafka
+(
+    deletion_id    String,
+    tenant_id      String,
+    level          String,
+    study_uid      String,
+    series_uid     Nullable(String),
+    sop_uid        Nullable(String),
+    instance_count Int64,
+    total_bytes    Int64,
+    status         String,
+    requested_by   Nullable(String),
+    event_time     String
+)
+    engine = Kafka SETTINGS kafka_broker_list = 'redpanda:9092', kafka_topic_list = 'deletion_queue', kafka_group_name = 'medical_deletion_group', kafka_format = 'JSONEachRow', kafka_max_block_size = 1048576, kafka_skip_broken_messages = 1;
+
+CREATE MATERIALIZED VIEW default.dicom_deletion_event_mv TO default.dicom_deletion_event AS
+SELECT deletion_id,
+       tenant_id,
+       level,
+       study_uid,
+       series_uid,
+       sop_uid,
+       instance_count,
+       total_bytes,
+       status,
+       requested_by,
+       parseDateTimeBestEffort(event_time) AS event_time
+FROM default.dicom_deletion_event_kafka;
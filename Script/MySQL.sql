@@ -1,11 +1,15 @@
-create table dicomdb.dicom_state_meta
+-- MySQL 8.0+ / MariaDB 10.6+, 与 Pg.sql 的表结构一致
+-- 字符集使用 utf8mb4, 时间字段使用 datetime(6) 保存服务器本地时间
+
+drop table if exists dicom_state_meta;
+create table dicom_state_meta
 (
     tenant_id                varchar(64)  not null,
     patient_id               varchar(64)  not null,
     study_uid                varchar(64)  not null,
     series_uid               varchar(64)  not null,
-    study_uid_hash           char(20)     not null,
-    series_uid_hash          char(20)     not null,
+    study_uid_hash           varchar(20)  not null,
+    series_uid_hash          varchar(20)  not null,
     patient_name             varchar(64)  null,
     patient_sex              varchar(1)   null,
     patient_birth_date       date         null,
@@ -15,9 +19,9 @@ create table dicomdb.dicom_state_meta
     patient_weight           double       null,
     pregnancy_status         int          null,
     study_date               date         not null,
-    study_date_origin        char(8)      not null,
+    study_date_origin        varchar(8)   not null,
     study_time               time         null,
-    accession_number         varchar(16)  not null,
+    accession_number         varchar(16)  null,
     study_id                 varchar(16)  null,
     study_description        varchar(64)  null,
     modality                 varchar(16)  null,
@@ -30,24 +34,374 @@ create table dicomdb.dicom_state_meta
     series_related_instances int          null,
     created_time             datetime(6)  null,
     updated_time             datetime(6)  null,
+    storage_layout           int          not null default 0 comment 'DICOM 文件目录布局版本, 对应 local_storage.layouts, 0 为内置布局',
     primary key (tenant_id, study_uid, series_uid),
-    constraint index_state_unique unique (tenant_id, study_uid, series_uid, accession_number)
-);
-
+    unique index index_state_unique (tenant_id, study_uid, series_uid, accession_number),
+    unique index index_state_unique4patient (tenant_id, patient_id, study_uid, series_uid)
+) default charset = utf8mb4;
 
 drop table if exists dicom_json_meta;
-create table  dicom_json_meta
-(
-    tenant_id                varchar(64) not null,
-    study_uid                varchar(64) not null,
-    series_uid               varchar(64) not null,
-    study_uid_hash           varchar(20) not null,
-    series_uid_hash          varchar(20) not null,
-    study_date_origin        varchar(8)  not null,
-    flag_time                datetime(6)   not null,
-    created_time             datetime(6)   not null default current_timestamp(6),
-    json_status              int         not null default 0,
-    retry_times              int         not null default 0
-);
-create primary key  PK_dicom_json_meta(tenant_id, study_uid, series_uid)
-    on  dicom_json_meta (tenant_id, study_uid, series_uid);
\ No newline at end of file
+create table dicom_json_meta
+(
+    tenant_id         varchar(64) not null,
+    study_uid         varchar(64) not null,
+    series_uid        varchar(64) not null,
+    study_uid_hash    varchar(20) not null,
+    series_uid_hash   varchar(20) not null,
+    study_date_origin varchar(8)  not null,
+    flag_time         datetime(6) not null,
+    created_time      datetime(6) not null default current_timestamp(6),
+    json_status       int         not null default 0,
+    retry_times       int         not null default 0,
+    primary key (tenant_id, study_uid, series_uid)
+) default charset = utf8mb4;
+
+-- ---------------------dicom_image_meta-------------------------
+drop table if exists dicom_image_meta;
+create table dicom_image_meta
+(
+    tenant_id                  varchar(64)  not null comment '租户ID',
+    patient_id                 varchar(64)  not null comment '患者ID',
+    study_uid                  varchar(64)  not null comment '检查UID',
+    series_uid                 varchar(64)  not null comment '序列UID',
+    sop_uid                    varchar(64)  not null comment '实例UID',
+    study_uid_hash             varchar(20)  not null comment '检查UID哈希值',
+    series_uid_hash            varchar(20)  not null comment '序列UID哈希值',
+    content_date               date         null comment '内容日期',
+    content_time               time         null comment '内容时间',
+    instance_number            int          null comment '实例编号',
+    image_type                 varchar(128) null comment '图像类型',
+    image_orientation_patient  varchar(128) null comment '图像方向(患者坐标系)',
+    image_position_patient     varchar(64)  null comment '图像位置(患者坐标系)',
+    slice_thickness            double       null comment '层厚',
+    spacing_between_slices     double       null comment '层间距',
+    slice_location             double       null comment '切片位置',
+    samples_per_pixel          int          null comment '每个像素采样数',
+    photometric_interpretation varchar(32)  null comment '光度解释',
+    width                      int          null comment '图像行数',
+    columns                    int          null comment '图像列数',
+    bits_allocated             int          null comment '分配位数',
+    bits_stored                int          null comment '存储位数',
+    high_bit                   int          null comment '高比特位',
+    pixel_representation       int          null comment '像素表示法',
+    rescale_intercept          double       null comment '重建截距',
+    rescale_slope              double       null comment '重建斜率',
+    rescale_type               varchar(64)  null comment '重建类型',
+    window_center              varchar(64)  null comment '窗位中心',
+    window_width               varchar(64)  null comment '窗宽',
+    transfer_syntax_uid        varchar(64)  not null comment '传输语法UID',
+    pixel_data_location        varchar(512) null comment '像素数据位置',
+    thumbnail_location         varchar(512) null comment '缩略图位置',
+    sop_class_uid              varchar(64)  not null comment 'SOP类UID',
+    image_status               varchar(32)  null comment '图像状态',
+    space_size                 bigint       null comment '占用空间大小',
+    created_time               datetime(6)  null comment '创建时间',
+    updated_time               datetime(6)  null comment '更新时间',
+    checksum                   varchar(64)  null comment '文件内容MD5',
+    primary key (tenant_id, study_uid, series_uid, sop_uid),
+    -- 存储对账按文件路径查找实例
+    index idx_image_pixel_location (pixel_data_location)
+) default charset = utf8mb4;
+
+-- ---------------------dicom_object_meta-------------------------
+drop table if exists dicom_object_meta;
+create table dicom_object_meta
+(
+    trace_id            varchar(36)  not null comment '全局唯一追踪ID，作为主键',
+    worker_node_id      varchar(64)  not null comment '工作节点 ID',
+    tenant_id           varchar(64)  not null comment '租户ID',
+    patient_id          varchar(64)  not null comment '患者ID',
+    study_uid           varchar(64)  null,
+    series_uid          varchar(64)  null,
+    sop_uid             varchar(64)  null,
+    file_size           bigint       null,
+    file_path           varchar(512) null,
+    transfer_syntax_uid varchar(64)  null,
+    number_of_frames    int          null,
+    series_uid_hash     varchar(20)  null,
+    study_uid_hash      varchar(20)  null,
+    accession_number    varchar(64)  null,
+    target_ts           varchar(64)  null,
+    study_date          date         null,
+    transfer_status     varchar(64)  null,
+    source_ip           varchar(24)  null,
+    source_ae           varchar(64)  null,
+    created_time        datetime(6)  not null default current_timestamp(6),
+    checksum            varchar(64)  null,
+    primary key (trace_id),
+    index idx_dicom_object_meta (tenant_id, patient_id, study_uid, series_uid, sop_uid),
+    index idx_dicom_object_meta_date (tenant_id, study_date),
+    index idx_dicom_object_meta_createdate (tenant_id, created_time)
+) default charset = utf8mb4;
+
+-- ---------------------访问日志-------------------------
+create table if not exists dicom_access_log
+(
+    log_id           varchar(36)   not null,
+    tenant_id        varchar(64)   not null,
+    user_id          varchar(64)   not null,
+    username         varchar(128)  not null,
+    operation_type   varchar(32)   not null,
+    operation_path   varchar(512)  not null,
+    operation_method varchar(10)   not null,
+    operation_result varchar(16)   not null,
+    resource_type    varchar(32)   not null,
+    resource_id      varchar(64)   not null,
+    ip_address       varchar(45)   not null,
+    user_agent       varchar(512)  null,
+    response_time    bigint        not null,
+    description      varchar(1024) null,
+    created_time     datetime(6)   not null,
+    primary key (log_id),
+    index idx_access_log_tenant (tenant_id),
+    index idx_access_log_user (user_id),
+    index idx_access_log_resource (resource_type, resource_id),
+    index idx_access_log_time (created_time),
+    index idx_access_log_operation (operation_type, operation_result)
+) default charset = utf8mb4;
+
+-- ---------------------MPPS 执行记录-------------------------
+drop table if exists dicom_mpps_meta;
+create table dicom_mpps_meta
+(
+    tenant_id            varchar(64) not null,
+    sop_instance_uid     varchar(64) not null,
+    patient_id           varchar(64) null,
+    study_uid            varchar(64) null,
+    accession_number     varchar(16) null,
+    modality             varchar(16) null,
+    pps_id               varchar(16) null,
+    pps_status           varchar(16) not null comment 'IN PROGRESS / COMPLETED / DISCONTINUED',
+    pps_description      varchar(64) null,
+    performed_station_ae varchar(16) null,
+    start_datetime       datetime(6) null,
+    end_datetime         datetime(6) null,
+    referenced_series    text        not null comment 'PerformedSeriesSequence 引用的序列UID, JSON 数组',
+    source_ip            varchar(24) not null,
+    source_ae            varchar(64) not null,
+    created_time         datetime(6) not null,
+    updated_time         datetime(6) not null,
+    primary key (tenant_id, sop_instance_uid),
+    index idx_mpps_study (tenant_id, study_uid)
+) default charset = utf8mb4;
+
+-- ---------------------自动路由转发任务-------------------------
+drop table if exists dicom_forward_task;
+create table dicom_forward_task
+(
+    task_id         varchar(36)  not null primary key,
+    tenant_id       varchar(64)  not null,
+    rule_name       varchar(64)  not null,
+    destination     varchar(64)  not null,
+    study_uid       varchar(64)  not null,
+    series_uid      varchar(64)  not null,
+    sop_uid         varchar(64)  not null,
+    file_path       varchar(512) not null,
+    status          varchar(16)  not null comment 'PENDING / RUNNING / SUCCESS / FAILED',
+    retry_times     int          not null default 0,
+    last_error      varchar(512) null,
+    next_retry_time datetime(6)  not null,
+    created_time    datetime(6)  not null,
+    updated_time    datetime(6)  not null,
+    index idx_forward_task_due (status, next_retry_time),
+    index idx_forward_task_study (tenant_id, study_uid)
+) default charset = utf8mb4;
+
+-- ---------------------历史检查预取-------------------------
+drop table if exists dicom_prefetch_task;
+create table dicom_prefetch_task
+(
+    tenant_id         varchar(64)  not null,
+    source            varchar(64)  not null comment 'Q/R 源节点名称',
+    study_uid         varchar(64)  not null,
+    patient_id        varchar(64)  not null,
+    trigger_study_uid varchar(64)  not null,
+    study_date        date         null,
+    modalities        varchar(64)  null,
+    status            varchar(16)  not null comment 'RUNNING / SUCCESS / FAILED',
+    completed         int          not null default 0,
+    failed            int          not null default 0,
+    last_error        varchar(512) null,
+    created_time      datetime(6)  not null,
+    updated_time      datetime(6)  not null,
+    primary key (tenant_id, source, study_uid),
+    index idx_prefetch_patient (tenant_id, patient_id)
+) default charset = utf8mb4;
+
+-- ---------------------分层存储-------------------------
+drop table if exists dicom_series_tier;
+create table dicom_series_tier
+(
+    tenant_id         varchar(64) not null,
+    study_uid         varchar(64) not null,
+    series_uid        varchar(64) not null,
+    study_date_origin varchar(8)  not null,
+    tier              varchar(32) not null comment '存储层名称, 对应 local_storage.tiers, 热存储层为 hot',
+    last_access_time  datetime(6) not null comment 'WADO 最近访问时间',
+    updated_time      datetime(6) not null,
+    primary key (tenant_id, study_uid, series_uid),
+    index idx_series_tier_access (tier, last_access_time)
+) default charset = utf8mb4;
+
+-- ---------------------完整性校验-------------------------
+drop table if exists dicom_fixity_check;
+create table dicom_fixity_check
+(
+    tenant_id         varchar(64)  not null,
+    study_uid         varchar(64)  not null,
+    series_uid        varchar(64)  not null,
+    sop_uid           varchar(64)  not null,
+    file_path         varchar(512) not null,
+    expected_checksum varchar(64)  not null,
+    actual_checksum   varchar(64)  null comment '重新计算的 MD5, 文件不存在时为空',
+    status            varchar(16)  not null comment 'OK / MISMATCH / MISSING',
+    checked_time      datetime(6)  null,
+    primary key (tenant_id, study_uid, series_uid, sop_uid),
+    index idx_fixity_check_time (checked_time),
+    index idx_fixity_check_status (status, checked_time)
+) default charset = utf8mb4;
+
+-- ---------------------租户存储用量-------------------------
+drop table if exists dicom_tenant_usage;
+create table dicom_tenant_usage
+(
+    tenant_id      varchar(64) not null primary key,
+    total_bytes    bigint      not null default 0,
+    instance_count bigint      not null default 0,
+    study_count    bigint      not null default 0,
+    updated_time   datetime(6) not null
+) default charset = utf8mb4 comment '租户当前存储用量, 保存 dicom_image_meta 时增量更新';
+
+drop table if exists dicom_tenant_usage_daily;
+create table dicom_tenant_usage_daily
+(
+    tenant_id      varchar(64) not null,
+    usage_date     date        not null,
+    total_bytes    bigint      not null,
+    instance_count bigint      not null,
+    study_count    bigint      not null,
+    updated_time   datetime(6) not null,
+    primary key (tenant_id, usage_date)
+) default charset = utf8mb4 comment '租户每天最后一次更新后的存储用量';
+
+-- 升级已有数据库时, 按已保存的实例初始化用量
+insert into dicom_tenant_usage (tenant_id, total_bytes, instance_count, study_count, updated_time)
+select tenant_id, coalesce(sum(space_size), 0), count(*), count(distinct study_uid), now(6)
+from dicom_image_meta
+group by tenant_id;
+
+-- ---------------------删除记录-------------------------
+drop table if exists dicom_deletion;
+create table dicom_deletion
+(
+    deletion_id    varchar(36) not null primary key,
+    tenant_id      varchar(64) not null,
+    level          varchar(16) not null comment 'STUDY / SERIES / INSTANCE',
+    study_uid      varchar(64) not null,
+    series_uid     varchar(64) null,
+    sop_uid        varchar(64) null,
+    instance_count bigint      not null default 0,
+    total_bytes    bigint      not null default 0,
+    status         varchar(16) not null comment 'TRASHED / PURGED / RESTORED',
+    requested_by   varchar(64) null,
+    purge_after    datetime(6) null comment '回收站保留期结束时间, 未启用回收站时为空',
+    created_time   datetime(6) not null,
+    updated_time   datetime(6) not null,
+    index idx_deletion_tenant (tenant_id, created_time),
+    index idx_deletion_purge (status, purge_after)
+) default charset = utf8mb4;
+
+-- ---------------------拒绝记录(IHE IOCM)-------------------------
+drop table if exists dicom_rejection;
+create table dicom_rejection
+(
+    tenant_id      varchar(64) not null,
+    study_uid      varchar(64) not null,
+    series_uid     varchar(64) not null,
+    sop_uid        varchar(64) not null,
+    reason_code    varchar(16) not null comment '113001 质量原因 / 113037 患者安全 / 113038 错误的工作列表条目 / 113039 数据保留期已过',
+    reason_meaning varchar(64) not null,
+    kos_sop_uid    varchar(64) not null comment '拒绝说明(Key Object Selection)的 SOP Instance UID',
+    rejected_time  datetime(6) not null,
+    purged_time    datetime(6) null comment '文件清除时间, 只有 113039 会被清除',
+    primary key (tenant_id, study_uid, series_uid, sop_uid),
+    index idx_rejection_purge (reason_code, purged_time)
+) default charset = utf8mb4;
+
+-- ---------------------患者/检查属性修改审计-------------------------
+drop table if exists dicom_change_audit;
+create table dicom_change_audit
+(
+    audit_id     varchar(36) not null primary key,
+    tenant_id    varchar(64) not null,
+    operation    varchar(32) not null comment 'MERGE_PATIENT / MOVE_STUDY / UPDATE_PATIENT / UPDATE_STUDY',
+    patient_id   varchar(64) not null comment '修改前的患者ID',
+    study_uid    varchar(64) not null,
+    before_value text        not null comment '修改前的属性(JSON)',
+    after_value  text        not null comment '修改后的属性(JSON)',
+    file_count   bigint      not null default 0,
+    requested_by varchar(64) null,
+    created_time datetime(6) not null,
+    index idx_change_audit_patient (tenant_id, patient_id, created_time),
+    index idx_change_audit_study (tenant_id, study_uid, created_time)
+) default charset = utf8mb4;
+
+-- ---------------------数据保留候选-------------------------
+drop table if exists dicom_retention_candidate;
+create table dicom_retention_candidate
+(
+    tenant_id    varchar(64) not null,
+    study_uid    varchar(64) not null,
+    patient_id   varchar(64) not null,
+    policy_name  varchar(64) not null comment '决定保留期的策略(保留期最长的匹配策略)',
+    expire_date  date        not null comment '保留期结束日期',
+    status       varchar(16) not null comment 'PENDING / DELETED / KEPT',
+    deletion_id  varchar(36) null comment '删除记录ID(dicom_deletion)',
+    created_time datetime(6) not null,
+    updated_time datetime(6) not null,
+    primary key (tenant_id, study_uid),
+    index idx_retention_candidate_status (status, expire_date)
+) default charset = utf8mb4;
+
+-- ---------------------法律保全-------------------------
+drop table if exists dicom_legal_hold;
+create table dicom_legal_hold
+(
+    hold_id       varchar(36)  not null primary key,
+    tenant_id     varchar(64)  not null,
+    patient_id    varchar(64)  null comment '保全患者的所有检查, 与 study_uid 至少设置一个',
+    study_uid     varchar(64)  null,
+    reason        varchar(256) not null,
+    created_by    varchar(64)  null,
+    created_time  datetime(6)  not null,
+    released_by   varchar(64)  null,
+    released_time datetime(6)  null comment '解除时间, 为空表示保全中',
+    -- MySQL 不支持部分索引, 查询时按 released_time 过滤
+    index idx_legal_hold_patient (tenant_id, patient_id, released_time),
+    index idx_legal_hold_study (tenant_id, study_uid, released_time)
+) default charset = utf8mb4;
+
+-- ---------------------检查级汇总-------------------------
+drop table if exists dicom_study_meta;
+create table dicom_study_meta
+(
+    tenant_id           varchar(64)  not null,
+    study_uid           varchar(64)  not null,
+    patient_id          varchar(64)  not null,
+    patient_name        varchar(64)  null,
+    patient_sex         varchar(1)   null,
+    patient_birth_date  date         null,
+    study_date          date         not null,
+    study_time          time         null,
+    accession_number    varchar(16)  null,
+    study_id            varchar(16)  null,
+    study_description   varchar(64)  null,
+    modalities_in_study varchar(256) not null default '' comment '逗号分隔',
+    number_of_series    int          not null default 0,
+    number_of_instances int          not null default 0,
+    updated_time        datetime(6)  not null,
+    primary key (tenant_id, study_uid),
+    index idx_study_meta_patient (tenant_id, patient_id),
+    index idx_study_meta_date (tenant_id, study_date),
+    index idx_study_meta_accession (tenant_id, accession_number)
+) default charset = utf8mb4 comment '检查级汇总, 数量不含已拒绝的实例';
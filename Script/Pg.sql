@@ -32,9 +32,13 @@ create table dicom_state_meta
     series_related_instances integer,
     created_time             timestamp,
     updated_time             timestamp,
+    storage_layout           integer     not null default 0,
     primary key (tenant_id, study_uid, series_uid)
 );
 
+comment on column dicom_state_meta.storage_layout is 'DICOM 文件目录布局版本, 对应 local_storage.layouts, 0 为内置布局';
+-- 升级已有数据库: alter table dicom_state_meta add column if not exists storage_layout integer not null default 0;
+
 
 create unique index index_state_unique
     on dicom_state_meta (tenant_id, study_uid, series_uid, accession_number);
@@ -106,7 +110,8 @@ create table  dicom_image_meta
     image_status               varchar(32),
     space_size                 bigint,
     created_time               timestamp,
-    updated_time               timestamp
+    updated_time               timestamp,
+    checksum                   varchar(64)
 );
 
 comment on column dicom_image_meta.tenant_id is '租户ID';
@@ -183,11 +188,16 @@ comment on column dicom_image_meta.created_time is '创建时间';
 
 comment on column dicom_image_meta.updated_time is '更新时间';
 
+comment on column dicom_image_meta.checksum is '文件内容MD5';
+
 
 -- 方案2: 使用现有字段创建组合主键（需要先删除现有约束）
 ALTER TABLE dicom_image_meta
     ADD CONSTRAINT pk_dicom_image_meta PRIMARY KEY (tenant_id, study_uid, series_uid, sop_uid);
 
+-- 存储对账按文件路径查找实例
+create index idx_image_pixel_location on dicom_image_meta (pixel_data_location);
+
 
 ---------------------------------
 drop table if exists dicom_object_meta;
@@ -212,7 +222,8 @@ create table  dicom_object_meta
     transfer_status     varchar(64)   null,
     source_ip           varchar(24)   null,
     source_ae           varchar(64)   null,
-    created_time        timestamp     not null default CURRENT_TIMESTAMP
+    created_time        timestamp     not null default CURRENT_TIMESTAMP,
+    checksum            varchar(64)   null
 );
 
 comment on column dicom_object_meta.tenant_id      is '租户ID';
@@ -253,3 +264,300 @@ CREATE INDEX idx_access_log_user ON dicom_access_log (user_id);
 CREATE INDEX idx_access_log_resource ON dicom_access_log (resource_type, resource_id);
 CREATE INDEX idx_access_log_time ON dicom_access_log (created_time);
 CREATE INDEX idx_access_log_operation ON dicom_access_log (operation_type, operation_result);
+
+-----------------------MPPS 执行记录-------------------------
+drop table if exists dicom_mpps_meta;
+create table dicom_mpps_meta
+(
+    tenant_id            varchar(64) not null,
+    sop_instance_uid     varchar(64) not null,
+    patient_id           varchar(64),
+    study_uid            varchar(64),
+    accession_number     varchar(16),
+    modality             varchar(16),
+    pps_id               varchar(16),
+    pps_status           varchar(16) not null,
+    pps_description      varchar(64),
+    performed_station_ae varchar(16),
+    start_datetime       timestamp,
+    end_datetime         timestamp,
+    referenced_series    text[]      not null default '{}',
+    source_ip            varchar(24) not null,
+    source_ae            varchar(64) not null,
+    created_time         timestamp   not null,
+    updated_time         timestamp   not null,
+    primary key (tenant_id, sop_instance_uid)
+);
+
+comment on column dicom_mpps_meta.pps_status is 'IN PROGRESS / COMPLETED / DISCONTINUED';
+comment on column dicom_mpps_meta.referenced_series is 'PerformedSeriesSequence 引用的序列UID';
+
+create index idx_mpps_study on dicom_mpps_meta (tenant_id, study_uid);
+
+-----------------------自动路由转发任务-------------------------
+drop table if exists dicom_forward_task;
+create table dicom_forward_task
+(
+    task_id         varchar(36)  not null primary key,
+    tenant_id       varchar(64)  not null,
+    rule_name       varchar(64)  not null,
+    destination     varchar(64)  not null,
+    study_uid       varchar(64)  not null,
+    series_uid      varchar(64)  not null,
+    sop_uid         varchar(64)  not null,
+    file_path       varchar(512) not null,
+    status          varchar(16)  not null,
+    retry_times     int          not null default 0,
+    last_error      varchar(512),
+    next_retry_time timestamp    not null,
+    created_time    timestamp    not null,
+    updated_time    timestamp    not null
+);
+
+comment on column dicom_forward_task.status is 'PENDING / RUNNING / SUCCESS / FAILED';
+
+create index idx_forward_task_due on dicom_forward_task (status, next_retry_time);
+create index idx_forward_task_study on dicom_forward_task (tenant_id, study_uid);
+
+-----------------------历史检查预取-------------------------
+drop table if exists dicom_prefetch_task;
+create table dicom_prefetch_task
+(
+    tenant_id         varchar(64) not null,
+    source            varchar(64) not null,
+    study_uid         varchar(64) not null,
+    patient_id        varchar(64) not null,
+    trigger_study_uid varchar(64) not null,
+    study_date        date,
+    modalities        varchar(64),
+    status            varchar(16) not null,
+    completed         int         not null default 0,
+    failed            int         not null default 0,
+    last_error        varchar(512),
+    created_time      timestamp   not null,
+    updated_time      timestamp   not null,
+    primary key (tenant_id, source, study_uid)
+);
+
+comment on column dicom_prefetch_task.source is 'Q/R 源节点名称';
+comment on column dicom_prefetch_task.status is 'RUNNING / SUCCESS / FAILED';
+
+create index idx_prefetch_patient on dicom_prefetch_task (tenant_id, patient_id);
+
+-----------------------分层存储-------------------------
+drop table if exists dicom_series_tier;
+create table dicom_series_tier
+(
+    tenant_id         varchar(64) not null,
+    study_uid         varchar(64) not null,
+    series_uid        varchar(64) not null,
+    study_date_origin varchar(8)  not null,
+    tier              varchar(32) not null,
+    last_access_time  timestamp   not null,
+    updated_time      timestamp   not null,
+    primary key (tenant_id, study_uid, series_uid)
+);
+
+comment on column dicom_series_tier.tier is '存储层名称, 对应 local_storage.tiers, 热存储层为 hot';
+comment on column dicom_series_tier.last_access_time is 'WADO 最近访问时间';
+
+create index idx_series_tier_access on dicom_series_tier (tier, last_access_time);
+
+-----------------------完整性校验-------------------------
+drop table if exists dicom_fixity_check;
+create table dicom_fixity_check
+(
+    tenant_id         varchar(64)  not null,
+    study_uid         varchar(64)  not null,
+    series_uid        varchar(64)  not null,
+    sop_uid           varchar(64)  not null,
+    file_path         varchar(512) not null,
+    expected_checksum varchar(64)  not null,
+    actual_checksum   varchar(64),
+    status            varchar(16)  not null,
+    checked_time      timestamp,
+    primary key (tenant_id, study_uid, series_uid, sop_uid)
+);
+
+comment on column dicom_fixity_check.status is 'OK / MISMATCH / MISSING';
+comment on column dicom_fixity_check.actual_checksum is '重新计算的 MD5, 文件不存在时为空';
+
+create index idx_fixity_check_time on dicom_fixity_check (checked_time);
+create index idx_fixity_check_status on dicom_fixity_check (status, checked_time);
+
+-----------------------租户存储用量-------------------------
+drop table if exists dicom_tenant_usage;
+create table dicom_tenant_usage
+(
+    tenant_id      varchar(64) not null primary key,
+    total_bytes    bigint      not null default 0,
+    instance_count bigint      not null default 0,
+    study_count    bigint      not null default 0,
+    updated_time   timestamp   not null
+);
+
+comment on table dicom_tenant_usage is '租户当前存储用量, 保存 dicom_image_meta 时增量更新';
+
+drop table if exists dicom_tenant_usage_daily;
+create table dicom_tenant_usage_daily
+(
+    tenant_id      varchar(64) not null,
+    usage_date     date        not null,
+    total_bytes    bigint      not null,
+    instance_count bigint      not null,
+    study_count    bigint      not null,
+    updated_time   timestamp   not null,
+    primary key (tenant_id, usage_date)
+);
+
+comment on table dicom_tenant_usage_daily is '租户每天最后一次更新后的存储用量';
+
+-- 升级已有数据库时, 按已保存的实例初始化用量
+insert into dicom_tenant_usage (tenant_id, total_bytes, instance_count, study_count, updated_time)
+select tenant_id, coalesce(sum(space_size), 0), count(*), count(distinct study_uid), now()
+from dicom_image_meta
+group by tenant_id;
+
+-----------------------删除记录-------------------------
+drop table if exists dicom_deletion;
+create table dicom_deletion
+(
+    deletion_id    varchar(36) not null primary key,
+    tenant_id      varchar(64) not null,
+    level          varchar(16) not null,
+    study_uid      varchar(64) not null,
+    series_uid     varchar(64),
+    sop_uid        varchar(64),
+    instance_count bigint      not null default 0,
+    total_bytes    bigint      not null default 0,
+    status         varchar(16) not null,
+    requested_by   varchar(64),
+    purge_after    timestamp,
+    created_time   timestamp   not null,
+    updated_time   timestamp   not null
+);
+
+comment on column dicom_deletion.level is 'STUDY / SERIES / INSTANCE';
+comment on column dicom_deletion.status is 'TRASHED / PURGED / RESTORED';
+comment on column dicom_deletion.purge_after is '回收站保留期结束时间, 未启用回收站时为空';
+
+create index idx_deletion_tenant on dicom_deletion (tenant_id, created_time);
+create index idx_deletion_purge on dicom_deletion (status, purge_after);
+
+------------------------拒绝记录(IHE IOCM)-------------------------
+drop table if exists dicom_rejection;
+create table dicom_rejection
+(
+    tenant_id      varchar(64) not null,
+    study_uid      varchar(64) not null,
+    series_uid     varchar(64) not null,
+    sop_uid        varchar(64) not null,
+    reason_code    varchar(16) not null,
+    reason_meaning varchar(64) not null,
+    kos_sop_uid    varchar(64) not null,
+    rejected_time  timestamp   not null,
+    purged_time    timestamp,
+    primary key (tenant_id, study_uid, series_uid, sop_uid)
+);
+
+comment on column dicom_rejection.reason_code is '113001 质量原因 / 113037 患者安全 / 113038 错误的工作列表条目 / 113039 数据保留期已过';
+comment on column dicom_rejection.kos_sop_uid is '拒绝说明(Key Object Selection)的 SOP Instance UID';
+comment on column dicom_rejection.purged_time is '文件清除时间, 只有 113039 会被清除';
+
+create index idx_rejection_purge on dicom_rejection (reason_code, purged_time);
+
+------------------------患者/检查属性修改审计-------------------------
+drop table if exists dicom_change_audit;
+create table dicom_change_audit
+(
+    audit_id     varchar(36) not null primary key,
+    tenant_id    varchar(64) not null,
+    operation    varchar(32) not null,
+    patient_id   varchar(64) not null,
+    study_uid    varchar(64) not null,
+    before_value text        not null,
+    after_value  text        not null,
+    file_count   bigint      not null default 0,
+    requested_by varchar(64),
+    created_time timestamp   not null
+);
+
+comment on column dicom_change_audit.operation is 'MERGE_PATIENT / MOVE_STUDY / UPDATE_PATIENT / UPDATE_STUDY';
+comment on column dicom_change_audit.patient_id is '修改前的患者ID';
+comment on column dicom_change_audit.before_value is '修改前的属性(JSON)';
+comment on column dicom_change_audit.after_value is '修改后的属性(JSON)';
+
+create index idx_change_audit_patient on dicom_change_audit (tenant_id, patient_id, created_time);
+create index idx_change_audit_study on dicom_change_audit (tenant_id, study_uid, created_time);
+
+------------------------数据保留候选-------------------------
+drop table if exists dicom_retention_candidate;
+create table dicom_retention_candidate
+(
+    tenant_id    varchar(64) not null,
+    study_uid    varchar(64) not null,
+    patient_id   varchar(64) not null,
+    policy_name  varchar(64) not null,
+    expire_date  date        not null,
+    status       varchar(16) not null,
+    deletion_id  varchar(36),
+    created_time timestamp   not null,
+    updated_time timestamp   not null,
+    primary key (tenant_id, study_uid)
+);
+
+comment on column dicom_retention_candidate.policy_name is '决定保留期的策略(保留期最长的匹配策略)';
+comment on column dicom_retention_candidate.expire_date is '保留期结束日期';
+comment on column dicom_retention_candidate.status is 'PENDING / DELETED / KEPT';
+comment on column dicom_retention_candidate.deletion_id is '删除记录ID(dicom_deletion)';
+
+create index idx_retention_candidate_status on dicom_retention_candidate (status, expire_date);
+
+------------------------法律保全-------------------------
+drop table if exists dicom_legal_hold;
+create table dicom_legal_hold
+(
+    hold_id       varchar(36)  not null primary key,
+    tenant_id     varchar(64)  not null,
+    patient_id    varchar(64),
+    study_uid     varchar(64),
+    reason        varchar(256) not null,
+    created_by    varchar(64),
+    created_time  timestamp    not null,
+    released_by   varchar(64),
+    released_time timestamp
+);
+
+comment on column dicom_legal_hold.patient_id is '保全患者的所有检查, 与 study_uid 至少设置一个';
+comment on column dicom_legal_hold.released_time is '解除时间, 为空表示保全中';
+
+create index idx_legal_hold_patient on dicom_legal_hold (tenant_id, patient_id) where released_time is null;
+create index idx_legal_hold_study on dicom_legal_hold (tenant_id, study_uid) where released_time is null;
+
+------------------------检查级汇总-------------------------
+drop table if exists dicom_study_meta;
+create table dicom_study_meta
+(
+    tenant_id           varchar(64) not null,
+    study_uid           varchar(64) not null,
+    patient_id          varchar(64) not null,
+    patient_name        varchar(64),
+    patient_sex         varchar(1),
+    patient_birth_date  date,
+    study_date          date        not null,
+    study_time          time,
+    accession_number    varchar(16),
+    study_id            varchar(16),
+    study_description   varchar(64),
+    modalities_in_study varchar(16)[] not null default '{}',
+    number_of_series    integer     not null default 0,
+    number_of_instances integer     not null default 0,
+    updated_time        timestamp   not null,
+    primary key (tenant_id, study_uid)
+);
+
+comment on table dicom_study_meta is '检查级汇总, 数量不含已拒绝的实例';
+
+create index idx_study_meta_patient on dicom_study_meta (tenant_id, patient_id);
+create index idx_study_meta_date on dicom_study_meta (tenant_id, study_date);
+create index idx_study_meta_accession on dicom_study_meta (tenant_id, accession_number);
@@ -5,4 +5,8 @@ openssl req -x509 -newkey rsa:4096 -sha512 -days 365 \
   -keyout certs/tls.key \
   -out certs/tls.crt \
   -subj "/C=CN/ST=Medical Province/L=Hospital District/O=Medical Organization/CN=keycloak.medical.org" \
-  -addext "subjectAltName = DNS:keycloak.medical.org,DNS:auth.medical.org"
\ No newline at end of file
+  -addext "subjectAltName = DNS:keycloak.medical.org,DNS:auth.medical.org"
+
+# 本地测试 DICOM TLS (wado-storescp dicom_store_scp.tls):
+# 自签名证书同时作为 CA 文件, 客户端使用同一证书和私钥即可通过校验, 例如:
+# openssl s_client -connect 127.0.0.1:2762 -cert certs/tls.crt -key certs/tls.key -CAfile certs/tls.crt
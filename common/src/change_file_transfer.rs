@@ -5,9 +5,10 @@ use dicom_object::OpenFileOptions;
 use dicom_pixeldata::Transcode;
 use dicom_transfer_syntax_registry::entries::DEFLATED_EXPLICIT_VR_LITTLE_ENDIAN;
 use gdcm_conv::PhotometricInterpretation;
-use std::fs;
+use crate::storage_backend::copy_file_atomic;
 use std::fs::File;
 use std::io::Write;
+use std::path::Path;
 
 #[derive(Debug)]
 pub enum ChangeStatus {
@@ -89,7 +90,7 @@ pub async fn convert_ts_with_gdcm_conv(
             match output_file.write_all(&buffer) {
                 Ok(_) => {
                     if overwrite {
-                        match fs::copy(output_path, src_file) {
+                        match copy_file_atomic(Path::new(output_path), Path::new(src_file)) {
                             Ok(_) => {}
                             Err(_) => {
                                 return Err(ChangeStatus::FileWriteError(format!(
@@ -165,7 +166,7 @@ pub async fn convert_ts_with_transcode(
         }
     }
     if overwrite {
-        match fs::copy(output_path, src_file) {
+        match copy_file_atomic(Path::new(output_path), Path::new(src_file)) {
             Ok(_) => {}
             Err(_) => {
                 return Err(ChangeStatus::FileWriteError(format!(
@@ -183,6 +184,7 @@ pub async fn convert_ts_with_transcode(
 mod tests {
     use super::*;
     use rstest::rstest;
+    use std::fs;
 
     #[rstest]
     #[case("./data/DeflatedExplicitVRLittleEndian.dcm", "./data/x-0.dcm" ,"P0000")]
@@ -1,8 +1,12 @@
 use crate::server_config;
-use crate::server_config::DatabaseConfig;
-use database::dicom_dbprovider::DbProvider;
+use crate::server_config::{DatabaseConfig, DatabasePoolConfig, DatabaseSslMode};
+use database::dicom_dbprovider::{DbPoolOptions, DbProvider, DbSslMode};
+use database::dicom_migration::check_schema_version;
+use database::dicom_mysql::MySqlDbProvider;
 use database::dicom_pg::PgDbProvider;
+use database::dicom_sqlite::SqliteDbProvider;
 use std::sync::Arc;
+use std::time::Duration;
 
 // 定义自定义错误类型
 #[derive(Debug)]
@@ -10,6 +14,7 @@ pub enum DatabaseError {
     ConfigError(String),
     ConnectionError(String),
     UnsupportedDatabase(String),
+    SchemaError(String),
 }
 
 impl std::fmt::Display for DatabaseError {
@@ -18,22 +23,58 @@ impl std::fmt::Display for DatabaseError {
             DatabaseError::ConfigError(msg) => write!(f, "Config error: {}", msg),
             DatabaseError::ConnectionError(msg) => write!(f, "Connection error: {}", msg),
             DatabaseError::UnsupportedDatabase(msg) => write!(f, "Unsupported database: {}", msg),
+            DatabaseError::SchemaError(msg) => write!(f, "Schema error: {}", msg),
         }
     }
 }
 
 impl std::error::Error for DatabaseError {}
 
+fn pool_options(pool: &DatabasePoolConfig) -> DbPoolOptions {
+    DbPoolOptions {
+        max_size: pool.max_size,
+        min_size: pool.min_size,
+        wait_timeout: Duration::from_secs(pool.wait_timeout_secs),
+        connect_timeout: Duration::from_secs(pool.connect_timeout_secs),
+        health_check: pool.health_check,
+        statement_cache: pool.statement_cache,
+        ssl_mode: match pool.ssl_mode {
+            DatabaseSslMode::Disable => DbSslMode::Disable,
+            DatabaseSslMode::Prefer => DbSslMode::Prefer,
+            DatabaseSslMode::Require => DbSslMode::Require,
+        },
+        ssl_root_cert: pool.ssl_root_cert.clone(),
+        ssl_accept_invalid_certs: pool.ssl_accept_invalid_certs,
+    }
+}
+
 // 根据配置文件生成数据库实例，返回 Result 而不是直接退出
+// 实例内部持有连接池, 应在启动时创建一次并共享
+// 按 auto_migrate 执行迁移或检查数据库结构版本
 pub async fn create_db_instance(
     dbconfig: &DatabaseConfig,
 ) -> Result<Arc<dyn DbProvider>, DatabaseError> {
-    let db_type = dbconfig.dbtype.to_lowercase();
-    if !(db_type == "postgresql") {
-        return Err(DatabaseError::UnsupportedDatabase(
-            "only  postgresql is supported".to_string(),
-        ));
+    let db = connect_db_instance(dbconfig).await?;
+    if dbconfig.auto_migrate || dbconfig.dbtype.eq_ignore_ascii_case("sqlite") {
+        db.migrate()
+            .await
+            .map_err(|e| DatabaseError::SchemaError(e.to_string()))?;
+    } else {
+        let version = db
+            .schema_version()
+            .await
+            .map_err(|e| DatabaseError::SchemaError(e.to_string()))?;
+        check_schema_version(version).map_err(|e| DatabaseError::SchemaError(e.to_string()))?;
     }
+    Ok(db)
+}
+
+// 只建立连接, 不检查数据库结构, 供迁移工具使用
+pub async fn connect_db_instance(
+    dbconfig: &DatabaseConfig,
+) -> Result<Arc<dyn DbProvider>, DatabaseError> {
+    let db_type = dbconfig.dbtype.to_lowercase();
+    let options = pool_options(&dbconfig.pool);
 
     match db_type.as_str() {
         "postgresql" => {
@@ -44,12 +85,49 @@ pub async fn create_db_instance(
                     )
                 })?;
 
-            let pg_provider = PgDbProvider::new(conn_url);
+            let pg_provider = PgDbProvider::new(&conn_url, &options)
+                .map_err(|e| DatabaseError::ConfigError(e.to_string()))?;
+            pg_provider
+                .warm_up(dbconfig.pool.min_size)
+                .await
+                .map_err(|e| DatabaseError::ConnectionError(e.to_string()))?;
             Ok(Arc::new(pg_provider))
         }
+        // MariaDB 使用相同的协议和 SQL 语法
+        "mysql" | "mariadb" => {
+            let conn_url =
+                server_config::generate_mysql_database_connection(dbconfig).map_err(|_| {
+                    DatabaseError::ConfigError(
+                        "database connection string is not right".to_string(),
+                    )
+                })?;
+
+            let mysql_provider = MySqlDbProvider::new(&conn_url, &options)
+                .map_err(|e| DatabaseError::ConfigError(e.to_string()))?;
+            mysql_provider
+                .warm_up(dbconfig.pool.min_size)
+                .await
+                .map_err(|e| DatabaseError::ConnectionError(e.to_string()))?;
+            Ok(Arc::new(mysql_provider))
+        }
+        "sqlite" => {
+            let path = dbconfig.path.as_deref().ok_or_else(|| {
+                DatabaseError::ConfigError(
+                    "main_database.path must be configured for sqlite".to_string(),
+                )
+            })?;
+
+            let sqlite_provider = SqliteDbProvider::new(path, &options)
+                .map_err(|e| DatabaseError::ConfigError(e.to_string()))?;
+            sqlite_provider
+                .warm_up(dbconfig.pool.min_size)
+                .await
+                .map_err(|e| DatabaseError::ConnectionError(e.to_string()))?;
+            Ok(Arc::new(sqlite_provider))
+        }
 
         _ => Err(DatabaseError::UnsupportedDatabase(format!(
-            "Unsupported database type: {}",
+            "Unsupported database type: {}, expected postgresql, mysql or sqlite",
             db_type
         ))),
     }
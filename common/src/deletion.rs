@@ -0,0 +1,394 @@
+//! 检查/序列/实例删除.
+//!
+//! 先在一个事务中删除各表的记录并扣减租户用量, 再处理文件、JSON 元数据和 Redis 缓存, 最后发送删除事件.
+//! 文件处理失败时遗留的文件会被存储对账(reconcile)作为孤立文件发现.
+//!
+//! 配置了 deletion.trash_days 时文件移动到 `{tenant}/_trash/{deletion_id}/` 下(同一存储层), 保留期内可以恢复:
+//! 文件移回热存储层并重新发送到 topic_main, 由 wado-consumer 重新建立索引. 保留期过后由 wado-webworker 清除.
+//!
+//! 处于法律保全(legal hold)中的患者或检查不能删除, 按检查设置保全时回收站中的文件也不会被清除.
+
+use crate::message_sender_kafka::{KafkaMessagePublisher, MessagePublisher};
+use crate::logevents::DeletionEvent;
+use crate::reconcile::resolve_store_meta;
+use crate::redis_key::RedisHelper;
+use crate::server_config::{AppConfig, HOT_TIER};
+use crate::storage_backend::{StorageError, json_backend};
+use crate::storage_config::StorageConfig;
+use crate::storage_tier::tiered_storage;
+use crate::utils::{get_logger, publish_messages};
+use database::dicom_dbprovider::{DbError, DbProvider, current_time};
+use database::dicom_dbtype::BoundedString;
+use database::dicom_meta::{DicomDeletion, DicomStateMeta};
+use slog::{Logger, info, o, warn};
+use thiserror::Error;
+
+/// 回收站目录, 位于租户目录之下
+pub const TRASH_DIR: &str = "_trash";
+const RESTORE_SOURCE: &str = "RESTORE";
+
+#[derive(Error, Debug)]
+pub enum DeletionError {
+    #[error("Deletion is not enabled")]
+    Disabled,
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Deletion {0} is {1}, only TRASHED deletions can be restored")]
+    NotRestorable(String, String),
+
+    #[error("Study {0} is on legal hold")]
+    LegalHold(String),
+
+    #[error("Failed to publish messages: {0}")]
+    Publish(String),
+
+    #[error(transparent)]
+    Database(#[from] DbError),
+
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+}
+
+/// 删除目标, series_uid / sop_uid 为空时删除整个检查/序列
+#[derive(Debug, Clone, Copy)]
+pub struct DeletionTarget<'a> {
+    pub tenant_id: &'a str,
+    pub study_uid: &'a str,
+    pub series_uid: Option<&'a str>,
+    pub sop_uid: Option<&'a str>,
+}
+
+impl DeletionTarget<'_> {
+    pub fn level(&self) -> &'static str {
+        match (self.series_uid, self.sop_uid) {
+            (None, _) => DicomDeletion::LEVEL_STUDY,
+            (Some(_), None) => DicomDeletion::LEVEL_SERIES,
+            (Some(_), Some(_)) => DicomDeletion::LEVEL_INSTANCE,
+        }
+    }
+}
+
+/// 一次删除在回收站中的目录: `{tenant}/_trash/{deletion_id}`
+pub fn trash_prefix(tenant_id: &str, deletion_id: &str) -> String {
+    format!("{}/{}/{}", tenant_id, TRASH_DIR, deletion_id)
+}
+
+pub fn is_trashed(key: &str) -> bool {
+    key.split('/').nth(1) == Some(TRASH_DIR)
+}
+
+fn deletion_event(deletion: &DicomDeletion) -> DeletionEvent {
+    DeletionEvent {
+        deletion_id: deletion.deletion_id.as_str().to_string(),
+        tenant_id: deletion.tenant_id.as_str().to_string(),
+        level: deletion.level.as_str().to_string(),
+        study_uid: deletion.study_uid.as_str().to_string(),
+        series_uid: deletion.series_uid.as_ref().map(|v| v.as_str().to_string()),
+        sop_uid: deletion.sop_uid.as_ref().map(|v| v.as_str().to_string()),
+        instance_count: deletion.instance_count,
+        total_bytes: deletion.total_bytes,
+        status: deletion.status.as_str().to_string(),
+        requested_by: deletion.requested_by.as_ref().map(|v| v.as_str().to_string()),
+        event_time: current_time(),
+    }
+}
+
+async fn publish_deletion_event(app_config: &AppConfig, deletion: &DicomDeletion, logger: &Logger) {
+    let Some(topic) = &app_config.message_queue.topic_deletion else {
+        return;
+    };
+    if topic.is_empty() {
+        return;
+    }
+    let publisher = KafkaMessagePublisher::new(topic.clone());
+    if let Err(e) = publisher
+        .send_deletion_messages(&[deletion_event(deletion)])
+        .await
+    {
+        warn!(
+            logger,
+            "Failed to publish deletion event {}: {}", deletion.deletion_id, e
+        );
+    }
+}
+
+/// 删除检查/序列/实例, 返回删除记录
+pub async fn delete_objects(
+    db: &dyn DbProvider,
+    redis: &RedisHelper,
+    app_config: &AppConfig,
+    target: &DeletionTarget<'_>,
+    requested_by: Option<&str>,
+) -> Result<DicomDeletion, DeletionError> {
+    let deletion_config = app_config
+        .deletion
+        .as_ref()
+        .ok_or(DeletionError::Disabled)?;
+    remove_objects(
+        db,
+        redis,
+        app_config,
+        target,
+        requested_by,
+        deletion_config.trash_days,
+    )
+    .await
+}
+
+/// 不检查删除接口配置, trash_days 为 0 时直接删除文件
+pub(crate) async fn remove_objects(
+    db: &dyn DbProvider,
+    redis: &RedisHelper,
+    app_config: &AppConfig,
+    target: &DeletionTarget<'_>,
+    requested_by: Option<&str>,
+    trash_days: u32,
+) -> Result<DicomDeletion, DeletionError> {
+    let rlogger = get_logger();
+    let logger = rlogger.new(o!("common"=>"delete_objects"));
+    let storage = tiered_storage();
+    let storage_config = StorageConfig::make_storage_config(app_config);
+
+    let series_list: Vec<DicomStateMeta> = db
+        .get_state_metaes(target.tenant_id, target.study_uid)
+        .await?
+        .into_iter()
+        .filter(|s| target.series_uid.is_none_or(|uid| s.series_uid.as_str() == uid))
+        .collect();
+    if series_list.is_empty() {
+        return Err(DeletionError::NotFound(format!(
+            "{}/{}",
+            target.study_uid,
+            target.series_uid.unwrap_or_default()
+        )));
+    }
+    if db
+        .is_on_legal_hold(
+            target.tenant_id,
+            Some(series_list[0].patient_id.as_str()),
+            target.study_uid,
+        )
+        .await?
+    {
+        return Err(DeletionError::LegalHold(target.study_uid.to_string()));
+    }
+
+    // 先确定要处理的文件, 数据库记录删除后无法再得到目录布局
+    let mut files = vec![];
+    match (target.series_uid, target.sop_uid) {
+        (Some(series_uid), Some(sop_uid)) => {
+            if !db
+                .image_exists(target.tenant_id, target.study_uid, series_uid, sop_uid)
+                .await?
+            {
+                return Err(DeletionError::NotFound(sop_uid.to_string()));
+            }
+            let key = storage_config.dicom_file_key(&series_list[0], sop_uid)?;
+            match storage.locate(&key).await {
+                Ok(tier) => files.push((tier.to_string(), key)),
+                Err(StorageError::NotFound(_)) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        _ => {
+            for series in &series_list {
+                let prefix = storage_config.dicom_series_key(series)?;
+                files.extend(storage.list(&prefix).await?);
+            }
+        }
+    }
+
+    let now = current_time();
+    let trash = trash_days > 0;
+    let deletion = DicomDeletion {
+        deletion_id: BoundedString::make(uuid::Uuid::new_v4().to_string()),
+        tenant_id: BoundedString::make_str(target.tenant_id),
+        level: BoundedString::make_str(target.level()),
+        study_uid: BoundedString::make_str(target.study_uid),
+        series_uid: target.series_uid.map(BoundedString::make_str),
+        sop_uid: target.sop_uid.map(BoundedString::make_str),
+        instance_count: 0,
+        total_bytes: 0,
+        status: BoundedString::make_str(if trash {
+            DicomDeletion::STATUS_TRASHED
+        } else {
+            DicomDeletion::STATUS_PURGED
+        }),
+        requested_by: requested_by.map(BoundedString::make_str),
+        purge_after: trash.then(|| now + chrono::Duration::days(trash_days as i64)),
+        created_time: now,
+        updated_time: now,
+    };
+    let deletion = db.delete_dicom_objects(&deletion).await?;
+
+    let prefix = trash_prefix(target.tenant_id, deletion.deletion_id.as_str());
+    let mut failed = 0;
+    for (tier, key) in &files {
+        let result = match storage.tier(tier) {
+            Some(_) if trash => {
+                storage
+                    .move_object(tier, key, tier, &format!("{}/{}", prefix, key))
+                    .await
+            }
+            Some(backend) => backend.delete(key).await,
+            None => continue,
+        };
+        if let Err(e) = result {
+            warn!(logger, "Failed to remove {} from tier {}: {}", key, tier, e);
+            failed += 1;
+        }
+    }
+
+    // 删除实例后序列 JSON 由 wado-webworker 重新生成
+    let json = json_backend();
+    for key in std::iter::once(storage_config.json_metadata_key_for_study(&series_list[0])).chain(
+        series_list
+            .iter()
+            .map(|series| storage_config.json_metadata_key_for_series(series)),
+    ) {
+        if let Err(e) = json.delete(&key).await {
+            warn!(logger, "Failed to delete JSON metadata {}: {}", key, e);
+        }
+    }
+    if let Err(e) = redis
+        .del_study_metadata(target.tenant_id, target.study_uid)
+        .await
+    {
+        warn!(logger, "Failed to delete cached study metadata: {}", e);
+    }
+    for series in &series_list {
+        let _ = redis
+            .del_series_metadata_gererate(target.tenant_id, series.series_uid.as_str())
+            .await;
+    }
+
+    info!(
+        logger,
+        "Deleted {} {}/{}: {} instances, {} bytes, {} files ({} failed), status {}",
+        deletion.level,
+        deletion.tenant_id,
+        deletion.study_uid,
+        deletion.instance_count,
+        deletion.total_bytes,
+        files.len(),
+        failed,
+        deletion.status
+    );
+    publish_deletion_event(app_config, &deletion, &logger).await;
+    Ok(deletion)
+}
+
+/// 从回收站恢复, 文件移回热存储层后重新建立索引
+pub async fn restore_deletion(
+    db: &dyn DbProvider,
+    app_config: &AppConfig,
+    deletion_id: &str,
+) -> Result<DicomDeletion, DeletionError> {
+    let rlogger = get_logger();
+    let logger = rlogger.new(o!("common"=>"restore_deletion"));
+    let mut deletion = db
+        .get_deletion(deletion_id)
+        .await?
+        .ok_or_else(|| DeletionError::NotFound(deletion_id.to_string()))?;
+    if deletion.status.as_str() != DicomDeletion::STATUS_TRASHED {
+        return Err(DeletionError::NotRestorable(
+            deletion_id.to_string(),
+            deletion.status.as_str().to_string(),
+        ));
+    }
+    let storage = tiered_storage();
+    let storage_config = StorageConfig::make_storage_config(app_config);
+    let layouts = storage_config.all_layouts()?;
+    let hot = storage
+        .tier(HOT_TIER)
+        .ok_or_else(|| StorageError::Config("hot tier is not configured".to_string()))?;
+
+    let prefix = trash_prefix(deletion.tenant_id.as_str(), deletion_id);
+    let mut metas = vec![];
+    for (tier, key) in storage.list(&prefix).await? {
+        let Some(original) = key
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_prefix('/'))
+        else {
+            continue;
+        };
+        storage.move_object(&tier, &key, HOT_TIER, original).await?;
+        match resolve_store_meta(&storage_config, &layouts, hot, original, RESTORE_SOURCE).await {
+            Ok(Some(meta)) => metas.push(meta),
+            Ok(None) => warn!(logger, "Cannot determine tenant and layout of {}", original),
+            Err(e) => warn!(logger, "Failed to read {}: {}", original, e),
+        }
+    }
+    let publisher = KafkaMessagePublisher::new(app_config.message_queue.topic_main.clone());
+    publish_messages(&publisher, &metas)
+        .await
+        .map_err(|e| DeletionError::Publish(e.to_string()))?;
+
+    db.update_deletion_status(deletion_id, DicomDeletion::STATUS_RESTORED)
+        .await?;
+    deletion.status = BoundedString::make_str(DicomDeletion::STATUS_RESTORED);
+    deletion.updated_time = current_time();
+    info!(
+        logger,
+        "Restored deletion {}: {} files republished", deletion_id, metas.len()
+    );
+    publish_deletion_event(app_config, &deletion, &logger).await;
+    Ok(deletion)
+}
+
+/// 清除回收站保留期已过的文件, 返回处理的删除记录数
+pub async fn purge_expired_deletions(
+    db: &dyn DbProvider,
+    app_config: &AppConfig,
+    limit: i64,
+) -> Result<usize, DeletionError> {
+    let rlogger = get_logger();
+    let logger = rlogger.new(o!("common"=>"purge_expired_deletions"));
+    let storage = tiered_storage();
+    let expired = db.get_expired_deletions(current_time(), limit).await?;
+    for deletion in &expired {
+        let mut deletion = deletion.clone();
+        let prefix = trash_prefix(deletion.tenant_id.as_str(), deletion.deletion_id.as_str());
+        let removed = storage.delete_prefix(&prefix).await?;
+        db.update_deletion_status(deletion.deletion_id.as_str(), DicomDeletion::STATUS_PURGED)
+            .await?;
+        deletion.status = BoundedString::make_str(DicomDeletion::STATUS_PURGED);
+        deletion.updated_time = current_time();
+        info!(
+            logger,
+            "Purged deletion {}: {} files", deletion.deletion_id, removed
+        );
+        publish_deletion_event(app_config, &deletion, &logger).await;
+    }
+    Ok(expired.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trash_prefix() {
+        let prefix = trash_prefix("t1", "d1");
+        assert_eq!(prefix, "t1/_trash/d1");
+        assert!(is_trashed(&format!("{}/20240101/st/se/1.dcm", prefix)));
+        assert!(!is_trashed("t1/20240101/st/se/1.dcm"));
+    }
+
+    #[test]
+    fn test_deletion_level() {
+        let mut target = DeletionTarget {
+            tenant_id: "t1",
+            study_uid: "1.2",
+            series_uid: None,
+            sop_uid: None,
+        };
+        assert_eq!(target.level(), DicomDeletion::LEVEL_STUDY);
+        target.series_uid = Some("1.2.3");
+        assert_eq!(target.level(), DicomDeletion::LEVEL_SERIES);
+        target.sop_uid = Some("1.2.3.4");
+        assert_eq!(target.level(), DicomDeletion::LEVEL_INSTANCE);
+    }
+}
@@ -3,7 +3,7 @@ use crate::message_sender_kafka::KafkaMessagePublisher;
 use crate::storage_config::{StorageConfig, hash_uid};
 use crate::utils;
 use crate::utils::get_logger;
-use crate::{server_config, storage_config};
+use crate::{fixity, server_config, storage_backend, storage_layout};
 use database::dicom_dbtype::{BoundedString, FixedLengthString};
 use database::dicom_meta::{DicomStoreMeta, TransferStatus};
 use dicom_dictionary_std::tags;
@@ -15,6 +15,7 @@ use dicom_transfer_syntax_registry::TransferSyntaxRegistry;
 use slog::o;
 use slog::{error, info};
 use std::collections::HashSet;
+use std::path::Path;
 use std::sync::LazyLock;
 use uuid::Uuid;
 
@@ -105,23 +106,23 @@ pub async fn process_dicom_buffer(
     let file_obj = obj.with_exact_meta(file_meta);
 
     let study_date_str = study_date.format("%Y%m%d").to_string();
-    let dir_path = storage_config
-        .make_series_dicom_dir(
+    // 已有序列沿用原来的目录布局
+    let storage_layout = storage_layout::series_layouts()
+        .resolve(tenant_id, study_uid.as_str(), series_uid.as_str())
+        .await;
+    let file_path = storage_config
+        .make_dicom_file_key(
+            storage_layout,
             tenant_id,
-            &*study_date_str,
+            &study_date_str,
             study_uid.as_str(),
             series_uid.as_str(),
-            true,
+            sop_instance_uid,
         )
-        .whatever_context(format!(
-        "failed to get dicom series dir: tenant_id={}, study_date={}, study_uid={}, series_uid={}",
-        tenant_id, study_date, study_uid, series_uid
-    ))?;
+        .whatever_context("failed to make DICOM file path")?;
     let study_uid_hash_v = hash_uid(study_uid.as_str());
     let series_uid_hash_v = hash_uid(series_uid.as_str());
 
-    let file_path = storage_config::dicom_file_path(&dir_path, sop_instance_uid);
-
     info!(logger, "file path: {}", file_path);
     let final_ts = ts.to_string();
     let mut transcode_status = TransferStatus::NoNeedTransfer;
@@ -131,16 +132,16 @@ pub async fn process_dicom_buffer(
     } else {
         info!(logger, "not need transcode: {}", ts.to_string());
     }
+    let mut file_data = Vec::with_capacity(instance_buffer.len() + 512);
     file_obj
-        .write_to_file(&file_path)
-        .whatever_context(format!(
-            "not need transcode, save file to disk failed: {:?}",
-            file_path
-        ))?;
-    let fsize = match std::fs::metadata(&file_path) {
-        Ok(metadata) => metadata.len(),
-        Err(_) => 0u64,
-    };
+        .write_all(&mut file_data)
+        .whatever_context(format!("failed to encode DICOM file: {:?}", file_path))?;
+    let fsize = file_data.len() as u64;
+    let checksum = fixity::compute_checksum(&file_data);
+    storage_backend::dicom_backend()
+        .put(&file_path, file_data)
+        .await
+        .whatever_context(format!("save file to storage failed: {:?}", file_path))?;
 
     let uuid_v7 = Uuid::now_v7();
     let trace_uid = uuid_v7.to_string(); // 或直接用 format!("{}", uuid_v7)
@@ -168,12 +169,17 @@ pub async fn process_dicom_buffer(
         accession_number,
         source_ip: BoundedString::<24>::make_str(&ip),
         source_ae: BoundedString::<64>::make_str(&client_ae),
+        checksum: Some(BoundedString::<64>::make(checksum)),
+        storage_layout,
     })
 }
 
 pub async fn process_dicom_memobject(
     obj: &mut DefaultDicomObject,
     dicom_file_path: &String,
+    file_size: u64,
+    checksum: &str,
+    storage_layout: i32, // 生成 dicom_file_path 使用的目录布局版本
     tenant_id: &String,
     _storage_config: &StorageConfig<'_>,
 ) -> Result<DicomStoreMeta, Whatever> {
@@ -248,11 +254,7 @@ pub async fn process_dicom_memobject(
         transcode_status = TransferStatus::NeedTransfer;
     }
 
-
-    let fsize = match std::fs::metadata(&dicom_file_path) {
-        Ok(metadata) => metadata.len(),
-        Err(_) => 0u64,
-    };
+    let fsize = file_size;
     // 修改为
     let cdate = chrono::Local::now().naive_local();
 
@@ -278,13 +280,15 @@ pub async fn process_dicom_memobject(
         accession_number,
         source_ip: BoundedString::<24>::make_str("127.0.0.1"),
         source_ae: BoundedString::<64>::make_str(&"STOW-RS-API"),
+        checksum: Some(BoundedString::<64>::make_str(checksum)),
+        storage_layout,
     })
 }
 
 pub async fn process_dicom_file_from_file(
     dicom_file_path: &String,
     tenant_id: &String,
-    _storage_config: &StorageConfig<'_>,
+    storage_config: &StorageConfig<'_>,
 ) -> Result<DicomStoreMeta, Whatever> {
     let root_logger = get_logger();
     let logger = root_logger.new(o!("wado-server"=>"process_dicom_file_from_file"));
@@ -389,7 +393,13 @@ pub async fn process_dicom_file_from_file(
         }
         match transcode_status {
             TransferStatus::Success => {
-                obj.write_to_file(&dicom_file_path)
+                let mut file_data = Vec::new();
+                obj.write_all(&mut file_data)
+                    .whatever_context(format!(
+                        "transcode success, but encode file failed: {:?}",
+                        dicom_file_path
+                    ))?;
+                storage_backend::write_file_atomic(Path::new(dicom_file_path), &file_data)
                     .whatever_context(format!(
                         "transcode success, biut save file to disk failed: {:?}",
                         dicom_file_path
@@ -408,6 +418,7 @@ pub async fn process_dicom_file_from_file(
         Ok(metadata) => metadata.len(),
         Err(_) => 0u64,
     };
+    let checksum = fixity::checksum_file(Path::new(dicom_file_path)).ok();
     // 修改为
     let cdate = chrono::Local::now().naive_local();
 
@@ -433,6 +444,8 @@ pub async fn process_dicom_file_from_file(
         accession_number,
         source_ip: BoundedString::<24>::make_str("127.0.0.1"),
         source_ae: BoundedString::<64>::make_str(&"STOW-RS-API"),
+        checksum: checksum.map(BoundedString::<64>::make),
+        storage_layout: storage_config.layout_version(),
     })
 }
 /// Publishes DICOM metadata to Kafka topics
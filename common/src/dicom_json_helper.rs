@@ -6,7 +6,9 @@ use dicom_object::{DefaultDicomObject, FileDicomObject, FileMetaTableBuilder, Op
 
 use crate::dicom_utils::get_tag_values;
 use crate::encrypt_helper::{EncryptHelper, Salsa20Encryptor};
+use crate::storage_backend::{self, LocalFile};
 use crate::storage_config::StorageConfig;
+use crate::storage_tier;
 use crate::utils::get_current_time;
 use crate::{dicom_utils, server_config};
 use database::dicom_meta::DicomStateMeta;
@@ -15,9 +17,9 @@ use dicom_object::file::CharacterSetOverride;
 use rayon::iter::ParallelIterator;
 use rayon::prelude::IntoParallelRefIterator;
 use serde_json::{json, Map, Value};
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{Error, Write};
+use std::collections::{HashMap, HashSet};
+use std::io::Error;
+use std::sync::Arc;
 use tokio::task;
 
 pub fn file_exists(file_path: &PathBuf) -> bool {
@@ -78,6 +80,18 @@ pub fn generate_study_json(
         eprintln!("No DICOM files found in the directory: {:?}", file);
         return Ok(());
     }
+    let study_json = build_study_json(tenant_id, study_uid, &files, &HashSet::new())?;
+    storage_backend::write_file_atomic(json_save_to, study_json.as_bytes())?;
+    Ok(())
+}
+
+/// 由检查下的所有 DICOM 文件生成检查级 JSON
+pub fn build_study_json(
+    tenant_id: &str,
+    study_uid: &str,
+    files: &[PathBuf],
+    rejected: &HashSet<String>,
+) -> Result<String, Box<dyn std::error::Error>> {
     let media_storage_sop_instance_uid = "DHZ.1.2.25.280986007.1.65029756031778";
     let empty_meta = FileMetaTableBuilder::new()
         .transfer_syntax(dicom_transfer_syntax_registry::entries::EXPLICIT_VR_LITTLE_ENDIAN.uid())
@@ -99,8 +113,10 @@ pub fn generate_study_json(
                 .open_file(&file)
                 .unwrap_or_else(|_| FileDicomObject::new_empty_with_meta(empty_meta.clone()));
 
+            // 被拒绝说明撤回的实例不出现在元数据中
             if get_string(tags::MEDIA_STORAGE_SOP_INSTANCE_UID, &obj)
                 != media_storage_sop_instance_uid
+                && !rejected.contains(&get_string(tags::SOP_INSTANCE_UID, &obj))
             {
                 let series_uid = get_string(tags::SERIES_INSTANCE_UID, &obj);
                 // 只有当series_uid非空时才处理
@@ -259,20 +275,98 @@ pub fn generate_study_json(
        "created": expires_str,
        "token":encrypted_data
     });
-    let file = File::create(json_save_to);
-    match file {
-        Ok(mut file) => {
-            file.write_all(study_json.to_string().as_bytes())
-                .expect("写入文件失败");
-        }
-        Err(error) => {
-            println!("写入文件时发生错误: {}", error);
+    Ok(study_json.to_string())
+}
+
+/// 将存储中的 DICOM 文件准备为本地文件, 返回的 LocalFile 需在使用期间保持存活
+/// 文件可能分布在多个存储层中
+async fn localize_dicom_files(prefix: &str) -> Result<Vec<LocalFile>, Error> {
+    let storage = storage_tier::tiered_storage();
+    let keys = storage.list(prefix).await.map_err(|e| {
+        Error::other(
+            format!("Failed to list DICOM files {}: {}", prefix, e),
+        )
+    })?;
+    let mut files = Vec::with_capacity(keys.len());
+    for (tier, key) in keys {
+        let file = storage.local_file_in(&tier, &key).await.map_err(|e| {
+            Error::other(
+                format!("Failed to read DICOM file {}: {}", key, e),
+            )
+        })?;
+        files.push(file);
+    }
+    Ok(files)
+}
+
+/// 生成检查级 JSON 并保存到 JSON 存储, series_infos 为同一检查下的序列.
+/// 序列可能使用不同的目录布局, 因此按布局分别读取检查目录. rejected 为被拒绝实例的 SOP Instance UID
+pub async fn generate_study_json_from_storage(
+    series_infos: &[DicomStateMeta],
+    rejected: &HashSet<String>,
+) -> Result<String, Error> {
+    let Some(study_info) = series_infos.first() else {
+        return Err(Error::new(
+            std::io::ErrorKind::NotFound,
+            "No series to generate study JSON",
+        ));
+    };
+    let app_config = server_config::load_config().map_err(|e| {
+        Error::other(
+            format!("server_config::load_config failed for generate: {}", e),
+        )
+    })?;
+    let storage_config = StorageConfig::make_storage_config(&app_config);
+    let mut study_keys = vec![];
+    for series_info in series_infos {
+        let study_key = storage_config
+            .dicom_study_key(series_info)
+            .map_err(|e| Error::other(e.to_string()))?;
+        if !study_keys.contains(&study_key) {
+            study_keys.push(study_key);
         }
     }
-    Ok(())
+    let study_key = study_keys.join(", ");
+    let mut local_files = vec![];
+    for key in &study_keys {
+        local_files.extend(localize_dicom_files(key).await?);
+    }
+    if local_files.is_empty() {
+        return Err(Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("No DICOM files found in the directory:{}", &study_key),
+        ));
+    }
+
+    let files: Vec<PathBuf> = local_files.iter().map(|f| f.path().to_path_buf()).collect();
+    let tenant_id = study_info.tenant_id.as_str().to_string();
+    let study_uid = study_info.study_uid.as_str().to_string();
+    let rejected = rejected.clone();
+    let json = task::spawn_blocking(move || {
+        build_study_json(&tenant_id, &study_uid, &files, &rejected).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| Error::other(e.to_string()))?
+    .map_err(Error::other)?;
+    drop(local_files);
+
+    let json_key = storage_config.json_metadata_key_for_study(study_info);
+    storage_backend::json_backend()
+        .put(&json_key, json.clone().into_bytes())
+        .await
+        .map_err(|e| {
+            Error::other(
+                format!("Failed to write JSON {}: {}", json_key, e),
+            )
+        })?;
+    Ok(json)
 }
 
-pub async fn generate_series_json(series_info: &DicomStateMeta) -> Result<String, Error> {
+/// 生成序列级 JSON 并保存到 JSON 存储, rejected 中的实例被跳过
+pub async fn generate_series_json(
+    series_info: &DicomStateMeta,
+    rejected: &HashSet<String>,
+) -> Result<String, Error> {
     let app_config = match server_config::load_config() {
         Ok(v) => v,
         Err(e) => {
@@ -284,37 +378,13 @@ pub async fn generate_series_json(series_info: &DicomStateMeta) -> Result<String
     };
     let storage_config = StorageConfig::make_storage_config(&app_config);
 
-    let json_file_path = match storage_config.json_metadata_path_for_series(series_info, true) {
-        Ok(v) => v,
-        Err(e) => {
-            return Err(Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to get json_file_path for generate: {}", e),
-            ));
-        }
-    };
-
-    let dicom_dir = match storage_config.dicom_series_dir(series_info, false) {
-        Ok(vv) => vv,
-        Err(_) => {
-            return Err(Error::new(
-                std::io::ErrorKind::Other,
-                "Failed to retrieve dicom_dir",
-            ));
-        }
-    };
-
-    let files = match walk_directory(&dicom_dir) {
-        Ok(files) => files,
-        Err(e) => {
-            return Err(Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to walk directory: {}", e),
-            ));
-        }
-    };
+    let json_file_key = storage_config.json_metadata_key_for_series(series_info);
+    let dicom_dir = storage_config
+        .dicom_series_key(series_info)
+        .map_err(|e| Error::other(e.to_string()))?;
 
-    if files.is_empty() {
+    let local_files = localize_dicom_files(&dicom_dir).await?;
+    if local_files.is_empty() {
         return Err(Error::new(
             std::io::ErrorKind::Other,
             format!("No DICOM files found in the directory:{}", &dicom_dir),
@@ -322,10 +392,12 @@ pub async fn generate_series_json(series_info: &DicomStateMeta) -> Result<String
     };
 
     let mut handles = vec![];
+    let rejected = Arc::new(rejected.clone());
 
-    for file_path in &files {
+    for local_file in &local_files {
         // 读取 DICOM 文件内容
-        let file_path_clone = file_path.clone(); // 克隆路径供异步任务使用
+        let file_path_clone = local_file.path().to_path_buf(); // 克隆路径供异步任务使用
+        let rejected = Arc::clone(&rejected);
         let handle = task::spawn_blocking(move || {
             // 读取 DICOM 文件内容
             let sop_json = match OpenFileOptions::new()
@@ -333,6 +405,11 @@ pub async fn generate_series_json(series_info: &DicomStateMeta) -> Result<String
                 .read_until(tags::PIXEL_DATA)
                 .open_file(&file_path_clone)
             {
+                Ok(dicom_object)
+                    if rejected.contains(&get_string(tags::SOP_INSTANCE_UID, &dicom_object)) =>
+                {
+                    Ok(None)
+                }
                 Ok(dicom_object) => {
                     let mut dicom_json = Map::new();
                     dicom_object.tags().into_iter().for_each(|tag| {
@@ -345,7 +422,7 @@ pub async fn generate_series_json(series_info: &DicomStateMeta) -> Result<String
                         });
                         dicom_json.insert(tag_key, element_json);
                     });
-                    Ok(dicom_json)
+                    Ok(Some(dicom_json))
                 }
                 Err(e) => Err(format!(
                     "Failed to read DICOM file {}: {}",
@@ -363,7 +440,8 @@ pub async fn generate_series_json(series_info: &DicomStateMeta) -> Result<String
     for handle in handles {
         match handle.await {
             Ok(result) => match result {
-                Ok(sop_json) => arr.push(sop_json),
+                Ok(Some(sop_json)) => arr.push(sop_json),
+                Ok(None) => {}
                 Err(e) => {
                     return Err(Error::new(
                         std::io::ErrorKind::Other,
@@ -380,9 +458,14 @@ pub async fn generate_series_json(series_info: &DicomStateMeta) -> Result<String
         }
     }
 
+    drop(local_files);
+
     let json = match serde_json::to_string(&arr) {
         Ok(json) => {
-            if let Err(e) = fs::write(&json_file_path, &json) {
+            if let Err(e) = storage_backend::json_backend()
+                .put(&json_file_key, json.clone().into_bytes())
+                .await
+            {
                 return Err(Error::new(
                     std::io::ErrorKind::PermissionDenied,
                     format!("Failed to write  JSON to file: {}", e),
@@ -1,6 +1,7 @@
 use crate::dicom_utils;
 
 use crate::storage_config::hash_uid;
+use crate::storage_layout::LEGACY_LAYOUT_VERSION;
 use crate::utils::get_current_time;
 use chrono::NaiveDate;
 use database::dicom_dbtype::{BoundedString, DicomDateString};
@@ -239,6 +240,7 @@ pub fn make_image_info(
         sop_class_uid: BoundedString::<64>::make(common_meta.sop_class_uid),
         image_status,
         space_size,
+        checksum: None,
         created_time: now,
         updated_time: now,
     })
@@ -351,6 +353,8 @@ pub fn make_state_info(
         // 时间戳
         created_time: now,
         updated_time: now,
+        // 由调用方按 DicomStoreMeta.storage_layout 设置
+        storage_layout: LEGACY_LAYOUT_VERSION,
     })
 }
 
@@ -0,0 +1,665 @@
+//! DICOM SCU: 向远程节点发送 C-ECHO / C-STORE / C-FIND / C-MOVE
+//!
+//! 基于 dicom-ul 的同步关联实现, 在异步上下文中请通过 spawn_blocking 调用.
+
+use crate::server_config::DicomNodeConfig;
+use dicom_core::{dicom_value, DataElement, Tag, VR};
+use dicom_dictionary_std::tags;
+use dicom_encoding::{TransferSyntax, TransferSyntaxIndex};
+use dicom_object::mem::InMemElement;
+use dicom_object::{InMemDicomObject, OpenFileOptions, StandardDataDictionary};
+use dicom_transfer_syntax_registry::entries::{
+    EXPLICIT_VR_LITTLE_ENDIAN, IMPLICIT_VR_LITTLE_ENDIAN,
+};
+use dicom_transfer_syntax_registry::TransferSyntaxRegistry;
+use dicom_ul::association::client::{ClientAssociation, ClientAssociationOptions};
+use dicom_ul::pdu::{PDataValue, PDataValueType, Pdu, PresentationContextNegotiated};
+use std::io::Write;
+use std::net::TcpStream;
+use std::time::Duration;
+use thiserror::Error;
+
+const MAX_PDU_LENGTH: u32 = 16384;
+const SCU_TIMEOUT: Duration = Duration::from_secs(60);
+/// C-MOVE 期间远程节点可能长时间不发送 Pending 响应
+const MOVE_TIMEOUT: Duration = Duration::from_secs(600);
+const ECHO_TIMEOUT: Duration = Duration::from_secs(10);
+/// 一个关联最多提议 128 个表示上下文 (ID 为 1~255 的奇数)
+const MAX_PRESENTATION_CONTEXTS: usize = 128;
+
+pub const VERIFICATION: &str = "1.2.840.10008.1.1";
+
+pub const STUDY_ROOT_FIND: &str = "1.2.840.10008.5.1.4.1.2.2.1";
+pub const STUDY_ROOT_MOVE: &str = "1.2.840.10008.5.1.4.1.2.2.2";
+
+#[derive(Error, Debug, Clone)]
+pub enum ScuError {
+    #[error("Failed to read DICOM file {0}: {1}")]
+    ReadFile(String, String),
+
+    #[error("Association with {0} failed: {1}")]
+    Association(String, String),
+
+    #[error("Presentation context rejected by {0}")]
+    NoPresentationContext(String),
+
+    #[error("Transfer syntax {0} can not be sent as {1}")]
+    TransferSyntaxMismatch(String, String),
+
+    #[error("DIMSE error: {0}")]
+    Dimse(String),
+
+    #[error("{0} failed with status {1:#06x}")]
+    Failed(&'static str, u16),
+}
+
+/// C-MOVE 子操作统计
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MoveResult {
+    pub completed: u16,
+    pub failed: u16,
+    pub warning: u16,
+}
+
+/// 0x0000 成功, 0xB000/0xB006/0xB007 为警告, 也视为已存储
+pub fn is_store_success(status: u16) -> bool {
+    matches!(status, 0x0000 | 0xB000 | 0xB006 | 0xB007)
+}
+
+fn is_pending(status: u16) -> bool {
+    matches!(status, 0xFF00 | 0xFF01)
+}
+
+fn is_native_transfer_syntax(uid: &str) -> bool {
+    TransferSyntaxRegistry
+        .get(uid)
+        .map(|ts| ts.is_codec_free())
+        .unwrap_or(false)
+}
+
+/// 建立关联时提议的传输语法: 文件本身的传输语法优先, 未压缩时追加显式/隐式 VR LE
+pub fn proposed_transfer_syntaxes(file_ts: &str) -> Vec<String> {
+    let file_ts = file_ts.trim_end_matches('\0').trim().to_string();
+    let mut proposed = vec![file_ts.clone()];
+    if is_native_transfer_syntax(&file_ts) {
+        for uid in [EXPLICIT_VR_LITTLE_ENDIAN.uid(), IMPLICIT_VR_LITTLE_ENDIAN.uid()] {
+            if !proposed.iter().any(|v| v == uid) {
+                proposed.push(uid.to_string());
+            }
+        }
+    }
+    proposed
+}
+
+fn create_request(
+    command_field: u16,
+    message_id: u16,
+    sop_class_uid: &str,
+    has_dataset: bool,
+    extra: Vec<InMemElement>,
+) -> InMemDicomObject<StandardDataDictionary> {
+    let mut elements = vec![
+        DataElement::new(
+            tags::AFFECTED_SOP_CLASS_UID,
+            VR::UI,
+            dicom_value!(Str, sop_class_uid),
+        ),
+        DataElement::new(tags::COMMAND_FIELD, VR::US, dicom_value!(U16, [command_field])),
+        DataElement::new(tags::MESSAGE_ID, VR::US, dicom_value!(U16, [message_id])),
+        DataElement::new(
+            tags::COMMAND_DATA_SET_TYPE,
+            VR::US,
+            dicom_value!(U16, [if has_dataset { 0x0000 } else { 0x0101 }]),
+        ),
+    ];
+    // C-ECHO-RQ 不包含 Priority
+    if command_field != 0x0030 {
+        elements.push(DataElement::new(
+            tags::PRIORITY,
+            VR::US,
+            dicom_value!(U16, [0x0000]),
+        ));
+    }
+    elements.extend(extra);
+    InMemDicomObject::command_from_element_iter(elements)
+}
+
+fn create_cstore_request(
+    message_id: u16,
+    sop_class_uid: &str,
+    sop_instance_uid: &str,
+) -> InMemDicomObject<StandardDataDictionary> {
+    create_request(
+        0x0001,
+        message_id,
+        sop_class_uid,
+        true,
+        vec![DataElement::new(
+            tags::AFFECTED_SOP_INSTANCE_UID,
+            VR::UI,
+            dicom_value!(Str, sop_instance_uid),
+        )],
+    )
+}
+
+pub(crate) fn node_address(node: &DicomNodeConfig) -> String {
+    format!("{}:{}", node.host, node.port)
+}
+
+pub(crate) fn establish(
+    node: &DicomNodeConfig,
+    calling_ae: &str,
+    abstract_syntax: &str,
+    transfer_syntaxes: Vec<String>,
+    timeout: Duration,
+) -> Result<ClientAssociation<TcpStream>, ScuError> {
+    establish_contexts(
+        node,
+        calling_ae,
+        vec![(abstract_syntax.to_string(), transfer_syntaxes)],
+        timeout,
+    )
+}
+
+fn establish_contexts(
+    node: &DicomNodeConfig,
+    calling_ae: &str,
+    contexts: Vec<(String, Vec<String>)>,
+    timeout: Duration,
+) -> Result<ClientAssociation<TcpStream>, ScuError> {
+    let mut options = ClientAssociationOptions::new()
+        .calling_ae_title(calling_ae.to_string())
+        .called_ae_title(node.ae_title.clone())
+        .max_pdu_length(MAX_PDU_LENGTH)
+        .read_timeout(timeout)
+        .write_timeout(SCU_TIMEOUT);
+    for (abstract_syntax, transfer_syntaxes) in contexts {
+        options = options.with_presentation_context(abstract_syntax, transfer_syntaxes);
+    }
+    options
+        .establish(node_address(node))
+        .map_err(|e| ScuError::Association(node.name.clone(), e.to_string()))
+}
+
+/// 返回协商通过的表示上下文 ID 及传输语法
+fn negotiated(
+    association: &ClientAssociation<TcpStream>,
+    node: &DicomNodeConfig,
+) -> Result<(u8, String), ScuError> {
+    association
+        .presentation_contexts()
+        .first()
+        .map(|pc| (pc.id, pc.transfer_syntax.trim_end_matches('\0').to_string()))
+        .ok_or_else(|| ScuError::NoPresentationContext(node.name.clone()))
+}
+
+fn lookup_ts(uid: &str) -> Result<&'static TransferSyntax, ScuError> {
+    TransferSyntaxRegistry
+        .get(uid)
+        .ok_or_else(|| ScuError::Dimse(format!("unknown transfer syntax {}", uid)))
+}
+
+/// 发送命令, 有数据集时随后发送数据集(按最大 PDU 长度自动分片)
+fn send_message(
+    association: &mut ClientAssociation<TcpStream>,
+    pc_id: u8,
+    command: &InMemDicomObject,
+    dataset: Option<&[u8]>,
+) -> Result<(), ScuError> {
+    let mut command_data = Vec::new();
+    command
+        .write_dataset_with_ts(&mut command_data, &IMPLICIT_VR_LITTLE_ENDIAN.erased())
+        .map_err(|e| ScuError::Dimse(e.to_string()))?;
+    association
+        .send(&Pdu::PData {
+            data: vec![PDataValue {
+                presentation_context_id: pc_id,
+                value_type: PDataValueType::Command,
+                is_last: true,
+                data: command_data,
+            }],
+        })
+        .map_err(|e| ScuError::Dimse(e.to_string()))?;
+    if let Some(dataset) = dataset {
+        let mut writer = association.send_pdata(pc_id);
+        writer
+            .write_all(dataset)
+            .map_err(|e| ScuError::Dimse(e.to_string()))?;
+    }
+    Ok(())
+}
+
+fn command_u16(command: &InMemDicomObject, tag: Tag) -> Option<u16> {
+    command.element(tag).ok().and_then(|e| e.uint16().ok())
+}
+
+/// 接收一条完整的 DIMSE 消息: 命令及可能跟随的数据集
+fn receive_message(
+    association: &mut ClientAssociation<TcpStream>,
+) -> Result<(InMemDicomObject, Option<Vec<u8>>), ScuError> {
+    let ts = IMPLICIT_VR_LITTLE_ENDIAN.erased();
+    let mut command_data = Vec::new();
+    let mut command: Option<InMemDicomObject> = None;
+    let mut dataset = Vec::new();
+    loop {
+        let data = match association.receive() {
+            Ok(Pdu::PData { data }) => data,
+            Ok(pdu) => return Err(ScuError::Dimse(format!("unexpected PDU: {:?}", pdu))),
+            Err(e) => return Err(ScuError::Dimse(e.to_string())),
+        };
+        for mut value in data {
+            match value.value_type {
+                PDataValueType::Command => {
+                    command_data.append(&mut value.data);
+                    if value.is_last {
+                        command = Some(
+                            InMemDicomObject::read_dataset_with_ts(command_data.as_slice(), &ts)
+                                .map_err(|e| ScuError::Dimse(e.to_string()))?,
+                        );
+                    }
+                }
+                PDataValueType::Data => {
+                    dataset.append(&mut value.data);
+                    if value.is_last {
+                        let command = command
+                            .take()
+                            .ok_or_else(|| ScuError::Dimse("dataset before command".to_string()))?;
+                        return Ok((command, Some(dataset)));
+                    }
+                }
+            }
+        }
+        if let Some(obj) = command.take() {
+            if command_u16(&obj, tags::COMMAND_DATA_SET_TYPE) == Some(0x0101) {
+                return Ok((obj, None));
+            }
+            command = Some(obj);
+        }
+    }
+}
+
+fn response_status(command: &InMemDicomObject) -> Result<u16, ScuError> {
+    command_u16(command, tags::STATUS)
+        .ok_or_else(|| ScuError::Dimse("response without status".to_string()))
+}
+
+/// 将本地 DICOM 文件通过 C-STORE 发送到远程节点, 返回响应状态
+pub fn store_file(
+    node: &DicomNodeConfig,
+    calling_ae: &str,
+    file_path: &str,
+) -> Result<u16, ScuError> {
+    store_files(node, calling_ae, &[file_path.to_string()])
+        .pop()
+        .unwrap_or_else(|| Err(ScuError::NoPresentationContext(node.name.clone())))
+}
+
+/// 待发送文件的 SOP Class / SOP Instance / 传输语法
+struct StoreItem {
+    sop_class_uid: String,
+    sop_instance_uid: String,
+    transfer_syntax: String,
+    file: dicom_object::DefaultDicomObject,
+}
+
+/// 每种 SOP Class + 文件传输语法组合提议一个表示上下文, 超过上限的组合不再提议
+fn store_contexts<'a>(
+    files: impl Iterator<Item = (&'a str, &'a str)>,
+) -> Vec<(String, Vec<String>)> {
+    let mut keys: Vec<(&str, &str)> = vec![];
+    for key in files {
+        if !keys.contains(&key) && keys.len() < MAX_PRESENTATION_CONTEXTS {
+            keys.push(key);
+        }
+    }
+    keys.into_iter()
+        .map(|(sop_class_uid, ts)| (sop_class_uid.to_string(), proposed_transfer_syntaxes(ts)))
+        .collect()
+}
+
+/// 为文件选择协商通过的表示上下文: 优先使用文件本身的传输语法,
+/// 未压缩的文件也可以按其他未压缩传输语法发送
+fn select_context(
+    contexts: &[PresentationContextNegotiated],
+    sop_class_uid: &str,
+    file_ts: &str,
+) -> Option<(u8, String)> {
+    let candidates = contexts
+        .iter()
+        .filter(|pc| pc.abstract_syntax.trim_end_matches('\0') == sop_class_uid)
+        .map(|pc| (pc.id, pc.transfer_syntax.trim_end_matches('\0').to_string()));
+    let mut fallback = None;
+    for (id, ts) in candidates {
+        if ts == file_ts {
+            return Some((id, ts));
+        }
+        if fallback.is_none() && is_native_transfer_syntax(file_ts) && is_native_transfer_syntax(&ts)
+        {
+            fallback = Some((id, ts));
+        }
+    }
+    fallback
+}
+
+fn open_store_item(file_path: &str) -> Result<StoreItem, ScuError> {
+    let file = OpenFileOptions::new()
+        .open_file(file_path)
+        .map_err(|e| ScuError::ReadFile(file_path.to_string(), e.to_string()))?;
+    let meta = file.meta();
+    Ok(StoreItem {
+        sop_class_uid: meta.media_storage_sop_class_uid.trim_end_matches('\0').to_string(),
+        sop_instance_uid: meta
+            .media_storage_sop_instance_uid
+            .trim_end_matches('\0')
+            .to_string(),
+        transfer_syntax: meta.transfer_syntax.trim_end_matches('\0').to_string(),
+        file,
+    })
+}
+
+fn store_item(
+    association: &mut ClientAssociation<TcpStream>,
+    node: &DicomNodeConfig,
+    message_id: u16,
+    item: &StoreItem,
+) -> Result<u16, ScuError> {
+    let Some((pc_id, negotiated_ts)) = select_context(
+        association.presentation_contexts(),
+        &item.sop_class_uid,
+        &item.transfer_syntax,
+    ) else {
+        let accepted = association
+            .presentation_contexts()
+            .iter()
+            .find(|pc| pc.abstract_syntax.trim_end_matches('\0') == item.sop_class_uid);
+        return Err(match accepted {
+            Some(pc) => ScuError::TransferSyntaxMismatch(
+                item.transfer_syntax.clone(),
+                pc.transfer_syntax.trim_end_matches('\0').to_string(),
+            ),
+            None => ScuError::NoPresentationContext(node.name.clone()),
+        });
+    };
+    let ts = lookup_ts(&negotiated_ts)?;
+    let mut object_data = Vec::new();
+    item.file
+        .write_dataset_with_ts(&mut object_data, ts)
+        .map_err(|e| ScuError::Dimse(e.to_string()))?;
+
+    let command = create_cstore_request(message_id, &item.sop_class_uid, &item.sop_instance_uid);
+    send_message(association, pc_id, &command, Some(&object_data))?;
+    let (response, _) = receive_message(association)?;
+    let status = response_status(&response)?;
+    if is_store_success(status) {
+        Ok(status)
+    } else {
+        Err(ScuError::Failed("C-STORE", status))
+    }
+}
+
+/// 通过一个关联将多个本地文件(通常属于同一检查)发送到远程节点,
+/// 按 file_paths 的顺序返回每个文件的结果
+pub fn store_files(
+    node: &DicomNodeConfig,
+    calling_ae: &str,
+    file_paths: &[String],
+) -> Vec<Result<u16, ScuError>> {
+    let items: Vec<Result<StoreItem, ScuError>> =
+        file_paths.iter().map(|path| open_store_item(path)).collect();
+    let contexts = store_contexts(
+        items
+            .iter()
+            .flatten()
+            .map(|item| (item.sop_class_uid.as_str(), item.transfer_syntax.as_str())),
+    );
+    if contexts.is_empty() {
+        // 没有可读取的文件
+        return items.into_iter().filter_map(Result::err).map(Err).collect();
+    }
+
+    let mut association = match establish_contexts(node, calling_ae, contexts, SCU_TIMEOUT) {
+        Ok(association) => association,
+        Err(e) => {
+            return items
+                .into_iter()
+                .map(|item| item.and_then(|_| Err(e.clone())))
+                .collect();
+        }
+    };
+    let mut message_id: u16 = 0;
+    // DIMSE 收发失败后关联不可再用, 剩余文件直接返回该错误
+    let mut broken: Option<ScuError> = None;
+    let results = items
+        .into_iter()
+        .map(|item| {
+            let item = item?;
+            if let Some(e) = &broken {
+                return Err(e.clone());
+            }
+            message_id = message_id.wrapping_add(1).max(1);
+            let result = store_item(&mut association, node, message_id, &item);
+            if let Err(e @ ScuError::Dimse(_)) = &result {
+                broken = Some(e.clone());
+            }
+            result
+        })
+        .collect();
+    if broken.is_some() {
+        let _ = association.abort();
+    } else {
+        let _ = association.release();
+    }
+    results
+}
+
+fn native_transfer_syntaxes() -> Vec<String> {
+    vec![
+        EXPLICIT_VR_LITTLE_ENDIAN.uid().to_string(),
+        IMPLICIT_VR_LITTLE_ENDIAN.uid().to_string(),
+    ]
+}
+
+/// C-ECHO 验证远程节点连通性, 返回响应状态
+pub fn echo(node: &DicomNodeConfig, calling_ae: &str) -> Result<u16, ScuError> {
+    let mut association = establish(
+        node,
+        calling_ae,
+        VERIFICATION,
+        native_transfer_syntaxes(),
+        ECHO_TIMEOUT,
+    )?;
+    let (pc_id, _) = negotiated(&association, node)?;
+    let command = create_request(0x0030, 1, VERIFICATION, false, vec![]);
+    send_message(&mut association, pc_id, &command, None)?;
+    let (response, _) = receive_message(&mut association)?;
+    let status = response_status(&response)?;
+    let _ = association.release();
+    if status == 0x0000 {
+        Ok(status)
+    } else {
+        Err(ScuError::Failed("C-ECHO", status))
+    }
+}
+
+/// Study Root C-FIND, 返回所有匹配结果
+pub fn find(
+    node: &DicomNodeConfig,
+    calling_ae: &str,
+    identifier: &InMemDicomObject,
+) -> Result<Vec<InMemDicomObject>, ScuError> {
+    let mut association = establish(
+        node,
+        calling_ae,
+        STUDY_ROOT_FIND,
+        native_transfer_syntaxes(),
+        SCU_TIMEOUT,
+    )?;
+    let (pc_id, negotiated_ts) = negotiated(&association, node)?;
+    let ts = lookup_ts(&negotiated_ts)?;
+
+    let mut identifier_data = Vec::new();
+    identifier
+        .write_dataset_with_ts(&mut identifier_data, ts)
+        .map_err(|e| ScuError::Dimse(e.to_string()))?;
+    let command = create_request(0x0020, 1, STUDY_ROOT_FIND, true, vec![]);
+    send_message(&mut association, pc_id, &command, Some(&identifier_data))?;
+
+    let mut results = vec![];
+    loop {
+        let (response, dataset) = receive_message(&mut association)?;
+        let status = response_status(&response)?;
+        if is_pending(status) {
+            if let Some(dataset) = dataset {
+                let obj = InMemDicomObject::read_dataset_with_ts(dataset.as_slice(), ts)
+                    .map_err(|e| ScuError::Dimse(e.to_string()))?;
+                results.push(obj);
+            }
+            continue;
+        }
+        let _ = association.release();
+        return if status == 0x0000 {
+            Ok(results)
+        } else {
+            Err(ScuError::Failed("C-FIND", status))
+        };
+    }
+}
+
+/// Study Root C-MOVE, 将整个检查发送到 destination_ae
+pub fn move_study(
+    node: &DicomNodeConfig,
+    calling_ae: &str,
+    destination_ae: &str,
+    study_uid: &str,
+) -> Result<MoveResult, ScuError> {
+    let mut association = establish(
+        node,
+        calling_ae,
+        STUDY_ROOT_MOVE,
+        native_transfer_syntaxes(),
+        MOVE_TIMEOUT,
+    )?;
+    let (pc_id, negotiated_ts) = negotiated(&association, node)?;
+    let ts = lookup_ts(&negotiated_ts)?;
+
+    let identifier = InMemDicomObject::from_element_iter([
+        DataElement::new(tags::QUERY_RETRIEVE_LEVEL, VR::CS, dicom_value!(Str, "STUDY")),
+        DataElement::new(tags::STUDY_INSTANCE_UID, VR::UI, dicom_value!(Str, study_uid)),
+    ]);
+    let mut identifier_data = Vec::new();
+    identifier
+        .write_dataset_with_ts(&mut identifier_data, ts)
+        .map_err(|e| ScuError::Dimse(e.to_string()))?;
+    let command = create_request(
+        0x0021,
+        1,
+        STUDY_ROOT_MOVE,
+        true,
+        vec![DataElement::new(
+            tags::MOVE_DESTINATION,
+            VR::AE,
+            dicom_value!(Str, destination_ae),
+        )],
+    );
+    send_message(&mut association, pc_id, &command, Some(&identifier_data))?;
+
+    let mut result = MoveResult::default();
+    loop {
+        let (response, _) = receive_message(&mut association)?;
+        let status = response_status(&response)?;
+        result.completed =
+            command_u16(&response, tags::NUMBER_OF_COMPLETED_SUBOPERATIONS).unwrap_or(result.completed);
+        result.failed =
+            command_u16(&response, tags::NUMBER_OF_FAILED_SUBOPERATIONS).unwrap_or(result.failed);
+        result.warning =
+            command_u16(&response, tags::NUMBER_OF_WARNING_SUBOPERATIONS).unwrap_or(result.warning);
+        if is_pending(status) {
+            continue;
+        }
+        let _ = association.release();
+        // 0xB000: 部分子操作失败
+        return if status == 0x0000 || status == 0xB000 {
+            Ok(result)
+        } else {
+            Err(ScuError::Failed("C-MOVE", status))
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_store_success() {
+        assert!(is_store_success(0x0000));
+        assert!(is_store_success(0xB007));
+        assert!(!is_store_success(0xA700));
+        assert!(!is_store_success(0x0122));
+    }
+
+    #[test]
+    fn test_proposed_transfer_syntaxes() {
+        let explicit = "1.2.840.10008.1.2.1";
+        let implicit = "1.2.840.10008.1.2";
+        let jpeg2000 = "1.2.840.10008.1.2.4.90";
+
+        assert_eq!(
+            proposed_transfer_syntaxes(implicit),
+            vec![implicit.to_string(), explicit.to_string()]
+        );
+        assert_eq!(
+            proposed_transfer_syntaxes(jpeg2000),
+            vec![jpeg2000.to_string()]
+        );
+    }
+
+    #[test]
+    fn test_store_contexts() {
+        let ct = "1.2.840.10008.5.1.4.1.1.2";
+        let sr = "1.2.840.10008.5.1.4.1.1.88.22";
+        let explicit = "1.2.840.10008.1.2.1";
+        let jpeg2000 = "1.2.840.10008.1.2.4.90";
+
+        let contexts = store_contexts(
+            [(ct, explicit), (ct, explicit), (ct, jpeg2000), (sr, explicit)].into_iter(),
+        );
+        assert_eq!(contexts.len(), 3);
+        assert_eq!(contexts[1], (ct.to_string(), vec![jpeg2000.to_string()]));
+    }
+
+    #[test]
+    fn test_select_context() {
+        let ct = "1.2.840.10008.5.1.4.1.1.2";
+        let explicit = "1.2.840.10008.1.2.1";
+        let implicit = "1.2.840.10008.1.2";
+        let jpeg2000 = "1.2.840.10008.1.2.4.90";
+        let make = |id: u8, ts: &str| PresentationContextNegotiated {
+            id,
+            reason: dicom_ul::pdu::PresentationContextResultReason::Acceptance,
+            transfer_syntax: ts.to_string(),
+            abstract_syntax: ct.to_string(),
+        };
+        let contexts = vec![make(1, implicit), make(3, jpeg2000)];
+
+        assert_eq!(select_context(&contexts, ct, jpeg2000), Some((3, jpeg2000.to_string())));
+        // 未压缩文件可以按隐式 VR LE 发送
+        assert_eq!(select_context(&contexts, ct, explicit), Some((1, implicit.to_string())));
+        assert_eq!(select_context(&contexts[..1], ct, jpeg2000), None);
+        assert_eq!(select_context(&contexts, "1.2.3", implicit), None);
+    }
+
+    #[test]
+    fn test_create_request() {
+        let command = create_request(0x0021, 7, STUDY_ROOT_MOVE, true, vec![]);
+        assert_eq!(command_u16(&command, tags::COMMAND_FIELD), Some(0x0021));
+        assert_eq!(command_u16(&command, tags::MESSAGE_ID), Some(7));
+        assert_eq!(command_u16(&command, tags::COMMAND_DATA_SET_TYPE), Some(0x0000));
+
+        assert_eq!(command_u16(&command, tags::PRIORITY), Some(0x0000));
+
+        let command = create_request(0x0030, 1, VERIFICATION, false, vec![]);
+        assert_eq!(command_u16(&command, tags::COMMAND_DATA_SET_TYPE), Some(0x0101));
+        assert_eq!(command_u16(&command, tags::PRIORITY), None);
+    }
+}
@@ -0,0 +1,24 @@
+//! 文件内容校验值: 收图时计算并随 DicomStoreMeta/DicomImageMeta 保存,
+//! wado-webworker 定期重新计算以发现磁盘上的静默损坏.
+
+use std::path::Path;
+
+/// 内容 MD5, 十六进制小写
+pub fn compute_checksum(data: &[u8]) -> String {
+    format!("{:x}", md5::compute(data))
+}
+
+pub fn checksum_file(path: &Path) -> std::io::Result<String> {
+    std::fs::read(path).map(|data| compute_checksum(&data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compute_checksum() {
+        assert_eq!(compute_checksum(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+        assert_ne!(compute_checksum(b"abc"), compute_checksum(b"abd"));
+    }
+}
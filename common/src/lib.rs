@@ -6,10 +6,13 @@ pub mod change_file_transfer;
 pub mod cornerstonejs;
 
 pub mod database_factory;
+pub mod deletion;
 
 pub mod extraction_error;
+pub mod fixity;
 pub mod dicom_json_helper;
 pub mod dicom_object_meta;
+pub mod dicom_scu;
 pub mod dicom_utils;
 pub mod message_sender;
 pub mod message_sender_kafka;
@@ -19,8 +22,17 @@ pub mod server_config;
 pub mod utils;
 
 pub mod encrypt_helper;
+pub mod patient_update;
+pub mod quota;
+pub mod reconcile;
+pub mod rejection;
+pub mod retention;
 pub mod redis_key;
+pub mod storage_backend;
 pub mod storage_config;
+pub mod storage_crypto;
+pub mod storage_layout;
+pub mod storage_tier;
 pub mod dicom_file_handler;
 pub mod logevents;
 
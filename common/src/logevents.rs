@@ -17,3 +17,62 @@ pub struct ApiLogEvent {
     pub content_length: String,
     pub duration_ms: u64,
 }
+
+/// 检查完成事件, 由 MPPS N-SET (COMPLETED) 触发.
+/// webworker 收到后立即生成该检查的 JSON 元数据, 无需等待 interval_minute 轮询.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StudyCompleteEvent {
+    pub tenant_id: String,
+    pub mpps_uid: String,
+    pub patient_id: String,
+    pub study_uid: String,
+    pub accession_number: String,
+    pub referenced_series: Vec<String>,
+    pub completed_time: NaiveDateTime,
+}
+
+/// 关联(Association)或 C-STORE 被拒绝事件, 写入收图日志队列(topic_dicom_receive).
+/// reason 为拒绝原因, 例如 CallingAETitleNotRecognized / SOPClassNotAllowed.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AssociationRejectEvent {
+    pub trace_id: String,
+    pub event_type: String,
+    pub tenant_id: String,
+    pub calling_ae: String,
+    pub called_ae: String,
+    pub source_ip: String,
+    pub sop_class_uid: String,
+    pub reason: String,
+    pub created_time: NaiveDateTime,
+}
+
+/// 定期校验发现文件损坏或丢失的事件, status 为 MISMATCH / MISSING
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FixityEvent {
+    pub tenant_id: String,
+    pub study_uid: String,
+    pub series_uid: String,
+    pub sop_uid: String,
+    pub file_path: String,
+    pub expected_checksum: String,
+    pub actual_checksum: Option<String>,
+    pub status: String,
+    pub detected_time: NaiveDateTime,
+}
+
+/// 检查/序列/实例删除或恢复事件, status 为 TRASHED / PURGED / RESTORED.
+/// ClickHouse / Doris 按 deletion_id 同步删除对应记录
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DeletionEvent {
+    pub deletion_id: String,
+    pub tenant_id: String,
+    pub level: String,
+    pub study_uid: String,
+    pub series_uid: Option<String>,
+    pub sop_uid: Option<String>,
+    pub instance_count: i64,
+    pub total_bytes: i64,
+    pub status: String,
+    pub requested_by: Option<String>,
+    pub event_time: NaiveDateTime,
+}
@@ -1,4 +1,6 @@
-use crate::logevents::ApiLogEvent;
+use crate::logevents::{
+    ApiLogEvent, AssociationRejectEvent, DeletionEvent, FixityEvent, StudyCompleteEvent,
+};
 use async_trait::async_trait;
 use database::dicom_meta::{DicomImageMeta, DicomStateMeta, DicomStoreMeta};
 use std::error::Error;
@@ -29,6 +31,26 @@ pub trait MessagePublisher: Sync + Send {
         messages: &[ApiLogEvent],
     ) -> Result<(), Box<dyn Error>>;
 
+    async fn send_study_complete_messages(
+        &self,
+        messages: &[StudyCompleteEvent],
+    ) -> Result<(), Box<dyn Error>>;
+
+    async fn send_reject_messages(
+        &self,
+        messages: &[AssociationRejectEvent],
+    ) -> Result<(), Box<dyn Error>>;
+
+    async fn send_fixity_messages(
+        &self,
+        messages: &[FixityEvent],
+    ) -> Result<(), Box<dyn Error>>;
+
+    async fn send_deletion_messages(
+        &self,
+        messages: &[DeletionEvent],
+    ) -> Result<(), Box<dyn Error>>;
+
     // ... 其他方法
     // fn clone_box(&self) -> Box<dyn MessagePublisher>;
 
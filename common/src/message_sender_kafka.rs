@@ -12,7 +12,9 @@ use std::sync::Arc;
 use std::time::Duration;
 use tracing::{debug, error, info};
 use database::dicom_meta::{DicomImageMeta, DicomStateMeta, DicomStoreMeta};
-use crate::logevents::ApiLogEvent;
+use crate::logevents::{
+    ApiLogEvent, AssociationRejectEvent, DeletionEvent, FixityEvent, StudyCompleteEvent,
+};
 
 pub struct KafkaMessagePublisher {
     producer: Arc<FutureProducer>,
@@ -359,6 +361,254 @@ impl MessagePublisher for KafkaMessagePublisher {
             Ok(())
         }
     }
+
+    async fn send_study_complete_messages(
+        &self,
+        messages: &[StudyCompleteEvent],
+    ) -> Result<(), Box<dyn Error>> {
+        info!(
+            "KafkaMessagePublisher send_study_complete_messages: {} to topic {}",
+            messages.len(),
+            self.topic
+        );
+
+        let mut wait_message = HashMap::new();
+
+        for msg in messages {
+            match serde_json::to_vec(msg) {
+                Ok(payload) => {
+                    // 同一个检查的事件使用相同的键, 保证分区内有序
+                    let key_source = format!("{}_{}", msg.tenant_id, msg.study_uid);
+                    let key = format!("{:x}", md5::compute(key_source));
+                    wait_message.insert(key, payload);
+                }
+                Err(e) => {
+                    error!("Failed to serialize StudyCompleteEvent message: {:?}", e);
+                    return Err(Box::new(e));
+                }
+            }
+        }
+
+        let futures: Vec<_> = wait_message
+            .iter()
+            .map(|(key, payload)| {
+                let record = FutureRecord::to(&self.topic)
+                    .key(&key[..])
+                    .payload(&payload[..]);
+                self.producer
+                    .send(record, Timeout::After(Duration::from_secs(10)))
+            })
+            .collect();
+
+        let results = join_all(futures).await;
+
+        let mut success_count = 0;
+        let mut error_count = 0;
+
+        for result in results {
+            match result {
+                Ok(_) => success_count += 1,
+                Err(e) => {
+                    error!("Failed to send StudyCompleteEvent message: {:?}", e);
+                    error_count += 1;
+                }
+            }
+        }
+
+        info!(
+            "✅ 批量发送 StudyCompleteEvent 完成: 成功 {} 条, 失败 {} 条",
+            success_count, error_count
+        );
+
+        if error_count > 0 {
+            Err("Some StudyCompleteEvent messages failed to send".into())
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn send_reject_messages(
+        &self,
+        messages: &[AssociationRejectEvent],
+    ) -> Result<(), Box<dyn Error>> {
+        info!(
+            "KafkaMessagePublisher send_reject_messages: {} to topic {}",
+            messages.len(),
+            self.topic
+        );
+
+        let mut wait_message = HashMap::new();
+
+        for msg in messages {
+            match serde_json::to_vec(msg) {
+                Ok(payload) => {
+                    wait_message.insert(msg.trace_id.clone(), payload);
+                }
+                Err(e) => {
+                    error!("Failed to serialize AssociationRejectEvent message: {:?}", e);
+                    return Err(Box::new(e));
+                }
+            }
+        }
+
+        let futures: Vec<_> = wait_message
+            .iter()
+            .map(|(key, payload)| {
+                let record = FutureRecord::to(&self.topic)
+                    .key(&key[..])
+                    .payload(&payload[..]);
+                self.producer
+                    .send(record, Timeout::After(Duration::from_secs(10)))
+            })
+            .collect();
+
+        let results = join_all(futures).await;
+
+        let mut success_count = 0;
+        let mut error_count = 0;
+
+        for result in results {
+            match result {
+                Ok(_) => success_count += 1,
+                Err(e) => {
+                    error!("Failed to send AssociationRejectEvent message: {:?}", e);
+                    error_count += 1;
+                }
+            }
+        }
+
+        info!(
+            "✅ 批量发送 AssociationRejectEvent 完成: 成功 {} 条, 失败 {} 条",
+            success_count, error_count
+        );
+
+        if error_count > 0 {
+            Err("Some AssociationRejectEvent messages failed to send".into())
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn send_fixity_messages(&self, messages: &[FixityEvent]) -> Result<(), Box<dyn Error>> {
+        info!(
+            "KafkaMessagePublisher send_fixity_messages: {} to topic {}",
+            messages.len(),
+            self.topic
+        );
+
+        let mut wait_message = HashMap::new();
+
+        for msg in messages {
+            match serde_json::to_vec(msg) {
+                Ok(payload) => {
+                    let key_source = format!("{}_{}", msg.tenant_id, msg.sop_uid);
+                    let key = format!("{:x}", md5::compute(key_source));
+                    wait_message.insert(key, payload);
+                }
+                Err(e) => {
+                    error!("Failed to serialize FixityEvent message: {:?}", e);
+                    return Err(Box::new(e));
+                }
+            }
+        }
+
+        let futures: Vec<_> = wait_message
+            .iter()
+            .map(|(key, payload)| {
+                let record = FutureRecord::to(&self.topic)
+                    .key(&key[..])
+                    .payload(&payload[..]);
+                self.producer
+                    .send(record, Timeout::After(Duration::from_secs(10)))
+            })
+            .collect();
+
+        let results = join_all(futures).await;
+
+        let mut success_count = 0;
+        let mut error_count = 0;
+
+        for result in results {
+            match result {
+                Ok(_) => success_count += 1,
+                Err(e) => {
+                    error!("Failed to send FixityEvent message: {:?}", e);
+                    error_count += 1;
+                }
+            }
+        }
+
+        info!(
+            "✅ 批量发送 FixityEvent 完成: 成功 {} 条, 失败 {} 条",
+            success_count, error_count
+        );
+
+        if error_count > 0 {
+            Err("Some FixityEvent messages failed to send".into())
+        } else {
+            Ok(())
+        }
+    }
+    async fn send_deletion_messages(&self, messages: &[DeletionEvent]) -> Result<(), Box<dyn Error>> {
+        info!(
+            "KafkaMessagePublisher send_deletion_messages: {} to topic {}",
+            messages.len(),
+            self.topic
+        );
+
+        let mut wait_message = HashMap::new();
+
+        for msg in messages {
+            match serde_json::to_vec(msg) {
+                Ok(payload) => {
+                    let key_source = format!("{}_{}", msg.tenant_id, msg.deletion_id);
+                    let key = format!("{:x}", md5::compute(key_source));
+                    wait_message.insert(key, payload);
+                }
+                Err(e) => {
+                    error!("Failed to serialize DeletionEvent message: {:?}", e);
+                    return Err(Box::new(e));
+                }
+            }
+        }
+
+        let futures: Vec<_> = wait_message
+            .iter()
+            .map(|(key, payload)| {
+                let record = FutureRecord::to(&self.topic)
+                    .key(&key[..])
+                    .payload(&payload[..]);
+                self.producer
+                    .send(record, Timeout::After(Duration::from_secs(10)))
+            })
+            .collect();
+
+        let results = join_all(futures).await;
+
+        let mut success_count = 0;
+        let mut error_count = 0;
+
+        for result in results {
+            match result {
+                Ok(_) => success_count += 1,
+                Err(e) => {
+                    error!("Failed to send DeletionEvent message: {:?}", e);
+                    error_count += 1;
+                }
+            }
+        }
+
+        info!(
+            "✅ 批量发送 DeletionEvent 完成: 成功 {} 条, 失败 {} 条",
+            success_count, error_count
+        );
+
+        if error_count > 0 {
+            Err("Some DeletionEvent messages failed to send".into())
+        } else {
+            Ok(())
+        }
+    }
     // fn clone_box(&self) -> Box<dyn MessagePublisher> {
     //     Box::new(self.clone())
     // }
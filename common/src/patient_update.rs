@@ -0,0 +1,752 @@
+//! 患者合并、检查移动和患者/检查属性修改, 用于 ADT 更正.
+//!
+//! 先将受影响检查的所有 DICOM 文件重新写入暂存目录 `{tenant}/_staging/{audit_id}/`(原文件不变),
+//! 再在一个事务中更新 dicom_state_meta / dicom_image_meta 并为每个检查保存修改前后的审计记录,
+//! 提交后才用暂存文件替换原文件. 提交前失败时删除暂存文件; 替换失败时恢复原文件并回滚索引.
+//! 最后删除 JSON 元数据和 Redis 缓存(序列 JSON 由 wado-webworker 重新生成),
+//! 并将修改后的序列发送到 topic_dicom_state.
+//! 检查日期决定文件目录和 JSON 元数据的位置, 因此不允许修改.
+
+use crate::dicom_json_helper::get_string;
+use crate::fixity;
+use crate::message_sender_kafka::{KafkaMessagePublisher, MessagePublisher};
+use crate::redis_key::RedisHelper;
+use crate::server_config::AppConfig;
+use crate::storage_backend::{StorageError, json_backend};
+use crate::storage_config::StorageConfig;
+use crate::storage_tier::tiered_storage;
+use crate::utils::get_logger;
+use database::dicom_dbprovider::{DbError, DbProvider, current_time};
+use database::dicom_dbtype::BoundedString;
+use database::dicom_meta::{
+    DicomAttributeChange, DicomAttributeUpdate, DicomChangeAudit, DicomRewrittenFile,
+    DicomStateMeta,
+};
+use dicom_core::{DataElement, PrimitiveValue, VR};
+use dicom_dictionary_std::tags;
+use dicom_object::OpenFileOptions;
+use dicom_object::file::CharacterSetOverride;
+use slog::{Logger, error, info, o, warn};
+use std::collections::BTreeMap;
+use std::path::Path;
+use thiserror::Error;
+
+/// 新的属性值包含非 ASCII 字符时使用 UTF-8
+const UTF8_CHARACTER_SET: &str = "ISO_IR 192";
+
+#[derive(Error, Debug)]
+pub enum PatientUpdateError {
+    #[error("Patient update is not enabled")]
+    Disabled,
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Invalid request: {0}")]
+    Invalid(String),
+
+    #[error("Failed to rewrite {0}: {1}")]
+    Rewrite(String, String),
+
+    #[error(transparent)]
+    Database(#[from] DbError),
+
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+}
+
+fn patient_fields(update: &DicomAttributeUpdate) -> DicomAttributeUpdate {
+    DicomAttributeUpdate {
+        patient_id: update.patient_id.clone(),
+        patient_name: update.patient_name.clone(),
+        patient_sex: update.patient_sex.clone(),
+        patient_birth_date: update.patient_birth_date,
+        ..Default::default()
+    }
+}
+
+fn study_fields(update: &DicomAttributeUpdate) -> DicomAttributeUpdate {
+    DicomAttributeUpdate {
+        accession_number: update.accession_number.clone(),
+        study_id: update.study_id.clone(),
+        study_description: update.study_description.clone(),
+        ..Default::default()
+    }
+}
+
+fn check_length(name: &str, value: &Option<String>, max: usize) -> Result<(), PatientUpdateError> {
+    match value {
+        Some(value) if value.is_empty() || value.len() > max => Err(PatientUpdateError::Invalid(
+            format!("{} must be 1 to {} characters", name, max),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// 校验属性长度与 dicom_state_meta 的列定义一致
+pub fn validate_update(update: &DicomAttributeUpdate) -> Result<(), PatientUpdateError> {
+    if *update == DicomAttributeUpdate::default() {
+        return Err(PatientUpdateError::Invalid(
+            "no attribute to update".to_string(),
+        ));
+    }
+    check_length("patient_id", &update.patient_id, 64)?;
+    check_length("patient_name", &update.patient_name, 64)?;
+    check_length("accession_number", &update.accession_number, 16)?;
+    check_length("study_id", &update.study_id, 16)?;
+    check_length("study_description", &update.study_description, 64)?;
+    if let Some(sex) = &update.patient_sex
+        && !["M", "F", "O"].contains(&sex.as_str())
+    {
+        return Err(PatientUpdateError::Invalid(
+            "patient_sex must be M, F or O".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// 序列记录中的当前属性
+fn current_attributes(state: &DicomStateMeta) -> DicomAttributeUpdate {
+    DicomAttributeUpdate {
+        patient_id: Some(state.patient_id.as_str().to_string()),
+        patient_name: state.patient_name.as_ref().map(|v| v.as_str().to_string()),
+        patient_sex: state.patient_sex.as_ref().map(|v| v.as_str().to_string()),
+        patient_birth_date: state.patient_birth_date,
+        accession_number: state
+            .accession_number
+            .as_ref()
+            .map(|v| v.as_str().to_string()),
+        study_id: state.study_id.as_ref().map(|v| v.as_str().to_string()),
+        study_description: state
+            .study_description
+            .as_ref()
+            .map(|v| v.as_str().to_string()),
+    }
+}
+
+/// update 中与当前值不同的字段
+fn changed_fields(
+    current: &DicomAttributeUpdate,
+    update: &DicomAttributeUpdate,
+) -> DicomAttributeUpdate {
+    fn changed<T: Clone + PartialEq>(current: &Option<T>, update: &Option<T>) -> Option<T> {
+        update
+            .as_ref()
+            .filter(|v| current.as_ref() != Some(*v))
+            .cloned()
+    }
+    DicomAttributeUpdate {
+        patient_id: changed(&current.patient_id, &update.patient_id),
+        patient_name: changed(&current.patient_name, &update.patient_name),
+        patient_sex: changed(&current.patient_sex, &update.patient_sex),
+        patient_birth_date: changed(&current.patient_birth_date, &update.patient_birth_date),
+        accession_number: changed(&current.accession_number, &update.accession_number),
+        study_id: changed(&current.study_id, &update.study_id),
+        study_description: changed(&current.study_description, &update.study_description),
+    }
+}
+
+/// current 中与 fields 已设置字段对应的值, 作为审计记录的修改前属性
+fn select_fields(
+    current: &DicomAttributeUpdate,
+    fields: &DicomAttributeUpdate,
+) -> DicomAttributeUpdate {
+    fn select<T: Clone, U>(current: &Option<T>, field: &Option<U>) -> Option<T> {
+        field.as_ref().and(current.clone())
+    }
+    DicomAttributeUpdate {
+        patient_id: select(&current.patient_id, &fields.patient_id),
+        patient_name: select(&current.patient_name, &fields.patient_name),
+        patient_sex: select(&current.patient_sex, &fields.patient_sex),
+        patient_birth_date: select(&current.patient_birth_date, &fields.patient_birth_date),
+        accession_number: select(&current.accession_number, &fields.accession_number),
+        study_id: select(&current.study_id, &fields.study_id),
+        study_description: select(&current.study_description, &fields.study_description),
+    }
+}
+
+/// 修改 DICOM 文件中的属性并写入 output, 返回 (SeriesInstanceUID, SOPInstanceUID)
+fn rewrite_dicom(
+    path: &Path,
+    update: &DicomAttributeUpdate,
+    output: &Path,
+) -> Result<(String, String), String> {
+    let mut obj = OpenFileOptions::new()
+        .charset_override(CharacterSetOverride::AnyVr)
+        .open_file(path)
+        .map_err(|e| e.to_string())?;
+    let texts = [
+        (tags::PATIENT_ID, VR::LO, &update.patient_id),
+        (tags::PATIENT_NAME, VR::PN, &update.patient_name),
+        (tags::PATIENT_SEX, VR::CS, &update.patient_sex),
+        (tags::ACCESSION_NUMBER, VR::SH, &update.accession_number),
+        (tags::STUDY_ID, VR::SH, &update.study_id),
+        (tags::STUDY_DESCRIPTION, VR::LO, &update.study_description),
+    ];
+    if texts
+        .iter()
+        .any(|(_, _, value)| matches!(value, Some(v) if !v.is_ascii()))
+    {
+        obj.put(DataElement::new(
+            tags::SPECIFIC_CHARACTER_SET,
+            VR::CS,
+            PrimitiveValue::from(UTF8_CHARACTER_SET),
+        ));
+    }
+    for (tag, vr, value) in texts {
+        if let Some(value) = value {
+            obj.put(DataElement::new(
+                tag,
+                vr,
+                PrimitiveValue::from(value.as_str()),
+            ));
+        }
+    }
+    if let Some(birth_date) = update.patient_birth_date {
+        obj.put(DataElement::new(
+            tags::PATIENT_BIRTH_DATE,
+            VR::DA,
+            PrimitiveValue::from(birth_date.format("%Y%m%d").to_string()),
+        ));
+    }
+    obj.write_to_file(output).map_err(|e| e.to_string())?;
+    Ok((
+        get_string(tags::SERIES_INSTANCE_UID, &obj),
+        get_string(tags::SOP_INSTANCE_UID, &obj),
+    ))
+}
+
+/// 重新写入的文件在切换前存放的目录: `{tenant}/_staging/{audit_id}/new/...`,
+/// 切换时原文件移动到 `{tenant}/_staging/{audit_id}/old/...`, 全部切换完成后删除
+pub const STAGING_DIR: &str = "_staging";
+
+/// 一次修改的暂存目录: `{tenant}/_staging/{audit_id}`
+fn staging_prefix(tenant_id: &str, audit_id: &str) -> String {
+    format!("{}/{}/{}", tenant_id, STAGING_DIR, audit_id)
+}
+
+/// key 在暂存目录中的位置, kind 为 new(修改后的文件) 或 old(原文件)
+fn staging_key(prefix: &str, kind: &str, key: &str) -> String {
+    let rest = key.split_once('/').map_or(key, |(_, rest)| rest);
+    format!("{}/{}/{}", prefix, kind, rest)
+}
+
+pub fn is_staged(key: &str) -> bool {
+    key.split('/').nth(1) == Some(STAGING_DIR)
+}
+
+/// 已写入暂存目录的文件, previous 为原文件的校验值和大小(回滚时使用)
+struct StagedFile {
+    tier: String,
+    key: String,
+    staged: String,
+    backup: String,
+    file: DicomRewrittenFile,
+    previous: DicomRewrittenFile,
+}
+
+/// 重新写入序列目录下的所有文件, 写入原存储层的暂存目录, 原文件保持不变
+async fn rewrite_series(
+    prefix: &str,
+    staging: &str,
+    update: &DicomAttributeUpdate,
+) -> Result<Vec<StagedFile>, PatientUpdateError> {
+    let storage = tiered_storage();
+    let mut staged = vec![];
+    for (tier, key) in storage.list(prefix).await? {
+        let Some(backend) = storage.tier(&tier) else {
+            continue;
+        };
+        let local_file = storage.local_file_in(&tier, &key).await?;
+        let output = tempfile::Builder::new()
+            .suffix(".dcm")
+            .tempfile()
+            .map_err(|e| StorageError::Io(e.to_string()))?;
+        let (source, target, file_update) = (
+            local_file.path().to_path_buf(),
+            output.path().to_path_buf(),
+            update.clone(),
+        );
+        let (series_uid, sop_uid) =
+            tokio::task::spawn_blocking(move || rewrite_dicom(&source, &file_update, &target))
+                .await
+                .map_err(|e| PatientUpdateError::Rewrite(key.clone(), e.to_string()))?
+                .map_err(|e| PatientUpdateError::Rewrite(key.clone(), e))?;
+        let file_info = |path: &Path| -> Result<(String, i64), StorageError> {
+            let checksum = fixity::checksum_file(path).map_err(|e| StorageError::Io(e.to_string()))?;
+            let size = std::fs::metadata(path)
+                .map_err(|e| StorageError::Io(e.to_string()))?
+                .len() as i64;
+            Ok((checksum, size))
+        };
+        let (previous_checksum, previous_size) = file_info(local_file.path())?;
+        let (checksum, space_size) = file_info(output.path())?;
+        let staged_key = staging_key(staging, "new", &key);
+        backend.put_file(&staged_key, output.path()).await?;
+        let file = DicomRewrittenFile {
+            series_uid: BoundedString::make_str(&series_uid),
+            sop_uid: BoundedString::make_str(&sop_uid),
+            checksum: Some(BoundedString::make(checksum)),
+            space_size,
+        };
+        staged.push(StagedFile {
+            tier,
+            backup: staging_key(staging, "old", &key),
+            key,
+            staged: staged_key,
+            previous: DicomRewrittenFile {
+                checksum: Some(BoundedString::make(previous_checksum)),
+                space_size: previous_size,
+                ..file.clone()
+            },
+            file,
+        });
+    }
+    Ok(staged)
+}
+
+/// 用暂存目录中的文件替换原文件, 原文件先移动到 backup. switched 为已移走原文件的数量,
+/// 失败时这些文件需要由 restore_files 恢复
+async fn switch_files(staged: &[&StagedFile], switched: &mut usize) -> Result<(), StorageError> {
+    let storage = tiered_storage();
+    for file in staged {
+        storage
+            .move_object(&file.tier, &file.key, &file.tier, &file.backup)
+            .await?;
+        *switched += 1;
+        storage
+            .move_object(&file.tier, &file.staged, &file.tier, &file.key)
+            .await?;
+    }
+    Ok(())
+}
+
+/// 将 backup 中的原文件移回原位置, 返回无法恢复的 key
+async fn restore_files(staged: &[&StagedFile], logger: &Logger) -> Vec<String> {
+    let storage = tiered_storage();
+    let mut failed = vec![];
+    for file in staged {
+        if let Err(e) = storage
+            .move_object(&file.tier, &file.backup, &file.tier, &file.key)
+            .await
+        {
+            error!(logger, "Failed to restore {} from {}: {}", file.key, file.backup, e);
+            failed.push(file.key.clone());
+        }
+    }
+    failed
+}
+
+/// 删除暂存目录, 失败时只记录日志
+async fn remove_staging(prefixes: &[String], logger: &Logger) {
+    let storage = tiered_storage();
+    for prefix in prefixes {
+        if let Err(e) = storage.delete_prefix(&format!("{}/", prefix)).await {
+            warn!(logger, "Failed to remove staging files {}: {}", prefix, e);
+        }
+    }
+}
+
+/// 删除检查的 JSON 元数据和缓存, 并发送修改后的序列
+async fn refresh_study(
+    db: &dyn DbProvider,
+    redis: &RedisHelper,
+    app_config: &AppConfig,
+    tenant_id: &str,
+    study_uid: &str,
+    logger: &Logger,
+) -> Result<(), PatientUpdateError> {
+    let series_list = db.get_state_metaes(tenant_id, study_uid).await?;
+    let Some(study_info) = series_list.first() else {
+        return Ok(());
+    };
+    let storage_config = StorageConfig::make_storage_config(app_config);
+    let json = json_backend();
+    for key in std::iter::once(storage_config.json_metadata_key_for_study(study_info)).chain(
+        series_list
+            .iter()
+            .map(|series| storage_config.json_metadata_key_for_series(series)),
+    ) {
+        if let Err(e) = json.delete(&key).await {
+            warn!(logger, "Failed to delete JSON metadata {}: {}", key, e);
+        }
+    }
+    if let Err(e) = redis.del_study_metadata(tenant_id, study_uid).await {
+        warn!(logger, "Failed to delete cached study metadata: {}", e);
+    }
+    let publisher = KafkaMessagePublisher::new(app_config.message_queue.topic_dicom_state.clone());
+    if let Err(e) = publisher.send_state_messages(&series_list).await {
+        warn!(
+            logger,
+            "Failed to publish updated study {}: {}", study_uid, e
+        );
+    }
+    Ok(())
+}
+
+/// 回滚已提交的修改: 恢复原属性和原文件的校验值, 并记录一条 {operation}_ROLLBACK 审计记录
+fn rollback_change(change: &DicomAttributeChange, files: &[StagedFile]) -> DicomAttributeChange {
+    let audit = &change.audit;
+    DicomAttributeChange {
+        audit: DicomChangeAudit {
+            audit_id: BoundedString::make(uuid::Uuid::new_v4().to_string()),
+            operation: BoundedString::make(format!("{}_ROLLBACK", audit.operation)),
+            before_value: audit.after_value.clone(),
+            after_value: audit.before_value.clone(),
+            created_time: current_time(),
+            ..audit.clone()
+        },
+        update: serde_json::from_str(&audit.before_value).unwrap_or_default(),
+        files: files.iter().map(|file| file.previous.clone()).collect(),
+    }
+}
+
+/// 对 series_list 涉及的每个检查应用修改, 返回审计记录. 属性没有变化的检查被跳过
+async fn apply_update(
+    db: &dyn DbProvider,
+    redis: &RedisHelper,
+    app_config: &AppConfig,
+    operation: &str,
+    series_list: Vec<DicomStateMeta>,
+    update: &DicomAttributeUpdate,
+    requested_by: Option<&str>,
+) -> Result<Vec<DicomChangeAudit>, PatientUpdateError> {
+    let rlogger = get_logger();
+    let logger = rlogger.new(o!("common"=>"patient_update"));
+    let storage_config = StorageConfig::make_storage_config(app_config);
+
+    let mut studies: BTreeMap<String, Vec<DicomStateMeta>> = BTreeMap::new();
+    for series in series_list {
+        studies
+            .entry(series.study_uid.as_str().to_string())
+            .or_default()
+            .push(series);
+    }
+    let mut changes = vec![];
+    let mut staged: Vec<Vec<StagedFile>> = vec![];
+    let mut staging = vec![];
+    for (study_uid, series_list) in &studies {
+        let current = current_attributes(&series_list[0]);
+        let changed = changed_fields(&current, update);
+        if changed == DicomAttributeUpdate::default() {
+            continue;
+        }
+        let audit_id = uuid::Uuid::new_v4().to_string();
+        let prefix = staging_prefix(series_list[0].tenant_id.as_str(), &audit_id);
+        staging.push(prefix.clone());
+        let mut study_files = vec![];
+        for series in series_list {
+            let series_prefix = match storage_config.dicom_series_key(series) {
+                Ok(series_prefix) => series_prefix,
+                Err(e) => {
+                    remove_staging(&staging, &logger).await;
+                    return Err(e.into());
+                }
+            };
+            match rewrite_series(&series_prefix, &prefix, &changed).await {
+                Ok(files) => study_files.extend(files),
+                Err(e) => {
+                    remove_staging(&staging, &logger).await;
+                    return Err(e);
+                }
+            }
+        }
+        let files: Vec<DicomRewrittenFile> =
+            study_files.iter().map(|file| file.file.clone()).collect();
+        let audit = DicomChangeAudit {
+            audit_id: BoundedString::make(audit_id),
+            tenant_id: series_list[0].tenant_id.clone(),
+            operation: BoundedString::make_str(operation),
+            patient_id: series_list[0].patient_id.clone(),
+            study_uid: BoundedString::make_str(study_uid),
+            before_value: serde_json::to_string(&select_fields(&current, &changed))
+                .unwrap_or_default(),
+            after_value: serde_json::to_string(&changed).unwrap_or_default(),
+            file_count: files.len() as i64,
+            requested_by: requested_by.map(BoundedString::make_str),
+            created_time: current_time(),
+        };
+        info!(
+            logger,
+            "{} {}/{}: {} -> {}, {} files rewritten",
+            operation,
+            audit.tenant_id,
+            study_uid,
+            audit.before_value,
+            audit.after_value,
+            files.len()
+        );
+        changes.push(DicomAttributeChange {
+            audit,
+            update: changed,
+            files,
+        });
+        staged.push(study_files);
+    }
+    // 先提交索引和审计记录, 失败时原文件没有被修改
+    if let Err(e) = db.save_attribute_changes(&changes).await {
+        remove_staging(&staging, &logger).await;
+        return Err(e.into());
+    }
+
+    let all_files: Vec<&StagedFile> = staged.iter().flatten().collect();
+    let mut switched = 0;
+    if let Err(e) = switch_files(&all_files, &mut switched).await {
+        error!(logger, "Failed to replace rewritten files, rolling back: {}", e);
+        let failed = restore_files(&all_files[..switched], &logger).await;
+        if !failed.is_empty() {
+            // 原文件无法全部恢复, 保留暂存目录以便手工处理
+            error!(
+                logger,
+                "{} files could not be restored, staging files kept in {:?}",
+                failed.len(),
+                staging
+            );
+            return Err(e.into());
+        }
+        let rollback: Vec<DicomAttributeChange> = changes
+            .iter()
+            .zip(&staged)
+            .map(|(change, files)| rollback_change(change, files))
+            .collect();
+        if let Err(e) = db.save_attribute_changes(&rollback).await {
+            error!(logger, "Failed to roll back attribute changes: {}", e);
+        }
+        remove_staging(&staging, &logger).await;
+        return Err(e.into());
+    }
+    remove_staging(&staging, &logger).await;
+
+    for change in &changes {
+        refresh_study(
+            db,
+            redis,
+            app_config,
+            change.audit.tenant_id.as_str(),
+            change.audit.study_uid.as_str(),
+            &logger,
+        )
+        .await?;
+    }
+    Ok(changes.into_iter().map(|change| change.audit).collect())
+}
+
+fn ensure_enabled(app_config: &AppConfig) -> Result<(), PatientUpdateError> {
+    match app_config.patient_update {
+        Some(_) => Ok(()),
+        None => Err(PatientUpdateError::Disabled),
+    }
+}
+
+/// 患者的序列, 用于取得患者的当前属性
+async fn patient_series(
+    db: &dyn DbProvider,
+    tenant_id: &str,
+    patient_id: &str,
+) -> Result<Vec<DicomStateMeta>, PatientUpdateError> {
+    let series_list = db.get_patient_state_metaes(tenant_id, patient_id).await?;
+    if series_list.is_empty() {
+        return Err(PatientUpdateError::NotFound(patient_id.to_string()));
+    }
+    Ok(series_list)
+}
+
+/// 修改患者属性, 应用到患者的所有检查. 修改 patient_id 相当于重新编号
+pub async fn update_patient(
+    db: &dyn DbProvider,
+    redis: &RedisHelper,
+    app_config: &AppConfig,
+    tenant_id: &str,
+    patient_id: &str,
+    update: &DicomAttributeUpdate,
+    requested_by: Option<&str>,
+) -> Result<Vec<DicomChangeAudit>, PatientUpdateError> {
+    ensure_enabled(app_config)?;
+    validate_update(update)?;
+    if study_fields(update) != DicomAttributeUpdate::default() {
+        return Err(PatientUpdateError::Invalid(
+            "study attributes must be updated per study".to_string(),
+        ));
+    }
+    let series_list = patient_series(db, tenant_id, patient_id).await?;
+    apply_update(
+        db,
+        redis,
+        app_config,
+        DicomChangeAudit::OP_UPDATE_PATIENT,
+        series_list,
+        update,
+        requested_by,
+    )
+    .await
+}
+
+/// 修改检查属性, 患者属性需要通过移动检查修改
+pub async fn update_study(
+    db: &dyn DbProvider,
+    redis: &RedisHelper,
+    app_config: &AppConfig,
+    tenant_id: &str,
+    study_uid: &str,
+    update: &DicomAttributeUpdate,
+    requested_by: Option<&str>,
+) -> Result<Vec<DicomChangeAudit>, PatientUpdateError> {
+    ensure_enabled(app_config)?;
+    validate_update(update)?;
+    if patient_fields(update) != DicomAttributeUpdate::default() {
+        return Err(PatientUpdateError::Invalid(
+            "move the study to change its patient".to_string(),
+        ));
+    }
+    let series_list = db.get_state_metaes(tenant_id, study_uid).await?;
+    if series_list.is_empty() {
+        return Err(PatientUpdateError::NotFound(study_uid.to_string()));
+    }
+    apply_update(
+        db,
+        redis,
+        app_config,
+        DicomChangeAudit::OP_UPDATE_STUDY,
+        series_list,
+        update,
+        requested_by,
+    )
+    .await
+}
+
+/// 将患者 from_patient_id 合并到 to_patient_id: 所有检查使用目标患者的属性
+pub async fn merge_patient(
+    db: &dyn DbProvider,
+    redis: &RedisHelper,
+    app_config: &AppConfig,
+    tenant_id: &str,
+    from_patient_id: &str,
+    to_patient_id: &str,
+    requested_by: Option<&str>,
+) -> Result<Vec<DicomChangeAudit>, PatientUpdateError> {
+    ensure_enabled(app_config)?;
+    if from_patient_id == to_patient_id {
+        return Err(PatientUpdateError::Invalid(
+            "cannot merge a patient into itself".to_string(),
+        ));
+    }
+    let target = patient_series(db, tenant_id, to_patient_id).await?;
+    let update = patient_fields(&current_attributes(&target[0]));
+    let series_list = patient_series(db, tenant_id, from_patient_id).await?;
+    apply_update(
+        db,
+        redis,
+        app_config,
+        DicomChangeAudit::OP_MERGE_PATIENT,
+        series_list,
+        &update,
+        requested_by,
+    )
+    .await
+}
+
+/// 将检查移动到患者 to_patient_id 下, 使用目标患者的属性
+pub async fn move_study(
+    db: &dyn DbProvider,
+    redis: &RedisHelper,
+    app_config: &AppConfig,
+    tenant_id: &str,
+    study_uid: &str,
+    to_patient_id: &str,
+    requested_by: Option<&str>,
+) -> Result<Vec<DicomChangeAudit>, PatientUpdateError> {
+    ensure_enabled(app_config)?;
+    let target = patient_series(db, tenant_id, to_patient_id).await?;
+    let update = patient_fields(&current_attributes(&target[0]));
+    let series_list = db.get_state_metaes(tenant_id, study_uid).await?;
+    if series_list.is_empty() {
+        return Err(PatientUpdateError::NotFound(study_uid.to_string()));
+    }
+    apply_update(
+        db,
+        redis,
+        app_config,
+        DicomChangeAudit::OP_MOVE_STUDY,
+        series_list,
+        &update,
+        requested_by,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn current() -> DicomAttributeUpdate {
+        DicomAttributeUpdate {
+            patient_id: Some("P1".to_string()),
+            patient_name: Some("Doe^John".to_string()),
+            patient_sex: Some("M".to_string()),
+            patient_birth_date: NaiveDate::from_ymd_opt(1990, 1, 1),
+            accession_number: Some("A1".to_string()),
+            study_id: None,
+            study_description: Some("CT".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_changed_fields() {
+        let update = DicomAttributeUpdate {
+            patient_id: Some("P2".to_string()),
+            patient_name: Some("Doe^John".to_string()),
+            study_id: Some("1".to_string()),
+            ..Default::default()
+        };
+        let changed = changed_fields(&current(), &update);
+        assert_eq!(changed.patient_id.as_deref(), Some("P2"));
+        assert_eq!(changed.patient_name, None);
+        assert_eq!(changed.study_id.as_deref(), Some("1"));
+
+        let before = select_fields(&current(), &changed);
+        assert_eq!(before.patient_id.as_deref(), Some("P1"));
+        assert_eq!(before.patient_name, None);
+        assert_eq!(before.study_id, None);
+        assert_eq!(
+            serde_json::to_string(&before).unwrap(),
+            r#"{"patient_id":"P1"}"#
+        );
+    }
+
+    #[test]
+    fn test_validate_update() {
+        assert!(validate_update(&DicomAttributeUpdate::default()).is_err());
+        let update = DicomAttributeUpdate {
+            patient_sex: Some("X".to_string()),
+            ..Default::default()
+        };
+        assert!(validate_update(&update).is_err());
+        let update = DicomAttributeUpdate {
+            accession_number: Some("A".repeat(17)),
+            ..Default::default()
+        };
+        assert!(validate_update(&update).is_err());
+        assert!(validate_update(&current()).is_ok());
+    }
+
+    #[test]
+    fn test_staging_key() {
+        let prefix = staging_prefix("t1", "a1");
+        assert_eq!(prefix, "t1/_staging/a1");
+        let key = staging_key(&prefix, "new", "t1/20240101/st/se/1.dcm");
+        assert_eq!(key, "t1/_staging/a1/new/20240101/st/se/1.dcm");
+        assert!(is_staged(&key));
+        assert!(!is_staged("t1/20240101/st/se/1.dcm"));
+    }
+
+    #[test]
+    fn test_patient_and_study_fields() {
+        let patient = patient_fields(&current());
+        assert_eq!(patient.accession_number, None);
+        assert_eq!(patient.patient_sex.as_deref(), Some("M"));
+        let study = study_fields(&current());
+        assert_eq!(study.patient_id, None);
+        assert_eq!(study.study_description.as_deref(), Some("CT"));
+    }
+}
@@ -0,0 +1,349 @@
+//! 租户存储配额检查.
+//!
+//! 用量来自 dicom_tenant_usage, 按 refresh_secs 缓存. 本进程接收的数据记录接收时间后累加到缓存中,
+//! 刷新时只去掉用量最后更新(updated_time)之前接收的数据, 之后接收的数据可能仍在 Kafka 中等待
+//! wado-consumer 入库, 继续计入. 写入失败的数据从缓存中退回.
+
+use crate::server_config::{QuotaConfig, QuotaLimit};
+use crate::utils::get_logger;
+use chrono::NaiveDateTime;
+use database::dicom_dbprovider::{DbProvider, current_time};
+use slog::{o, warn};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const MB: u64 = 1024 * 1024;
+/// 同一租户超过软限制的告警间隔
+const SOFT_WARN_INTERVAL: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaStatus {
+    Ok,
+    SoftExceeded,
+    HardExceeded,
+}
+
+/// used_bytes 为已使用量, incoming 为本次写入的大小
+pub fn evaluate(limit: &QuotaLimit, used_bytes: u64, incoming: u64) -> QuotaStatus {
+    let total = used_bytes.saturating_add(incoming);
+    if limit
+        .hard_limit_mb
+        .is_some_and(|hard| total > hard.saturating_mul(MB))
+    {
+        QuotaStatus::HardExceeded
+    } else if limit
+        .soft_limit_mb
+        .is_some_and(|soft| total > soft.saturating_mul(MB))
+    {
+        QuotaStatus::SoftExceeded
+    } else {
+        QuotaStatus::Ok
+    }
+}
+
+/// 本进程计入的一次写入
+struct PendingBytes {
+    id: u64,
+    reserved: NaiveDateTime,
+    bytes: u64,
+}
+
+struct CachedUsage {
+    /// 数据库中的用量
+    stored_bytes: u64,
+    /// 数据库用量中可能还未包含的写入
+    pending: Vec<PendingBytes>,
+    refreshed: Instant,
+    soft_warned: Option<Instant>,
+}
+
+impl CachedUsage {
+    fn new(stored_bytes: u64) -> Self {
+        CachedUsage {
+            stored_bytes,
+            pending: vec![],
+            refreshed: Instant::now(),
+            soft_warned: None,
+        }
+    }
+
+    fn used_bytes(&self) -> u64 {
+        self.pending
+            .iter()
+            .fold(self.stored_bytes, |total, pending| total.saturating_add(pending.bytes))
+    }
+
+    /// persisted 为数据库用量的最后更新时间, 之前接收的数据视为已入库, 没有用量记录时为 None
+    fn refresh(&mut self, stored_bytes: u64, persisted: Option<NaiveDateTime>) {
+        self.stored_bytes = stored_bytes;
+        if let Some(persisted) = persisted {
+            self.pending.retain(|pending| pending.reserved > persisted);
+        }
+        self.refreshed = Instant::now();
+    }
+}
+
+/// check 的结果. 未超出硬限制时 incoming 已计入缓存用量,
+/// 写入成功后调用 commit, 未 commit 就丢弃(写入失败)时退回
+#[must_use]
+pub struct QuotaReservation<'a> {
+    guard: &'a QuotaGuard,
+    tenant_id: String,
+    /// 计入的 PendingBytes, 未计入时为 None
+    pending_id: Option<u64>,
+    status: QuotaStatus,
+}
+
+impl QuotaReservation<'_> {
+    pub fn status(&self) -> QuotaStatus {
+        self.status
+    }
+
+    /// 数据已保存, 保留计入的用量
+    pub fn commit(mut self) {
+        self.pending_id = None;
+    }
+}
+
+impl Drop for QuotaReservation<'_> {
+    fn drop(&mut self) {
+        if let Some(id) = self.pending_id {
+            self.guard.release(&self.tenant_id, id);
+        }
+    }
+}
+
+pub struct QuotaGuard {
+    config: Option<QuotaConfig>,
+    /// 与进程其他部分共享的数据库连接池, 未配置配额时为 None
+    db: Option<Arc<dyn DbProvider>>,
+    usage: Mutex<HashMap<String, CachedUsage>>,
+    next_pending_id: AtomicU64,
+}
+
+impl QuotaGuard {
+    pub fn new(config: Option<QuotaConfig>, db: Arc<dyn DbProvider>) -> Arc<Self> {
+        Arc::new(QuotaGuard {
+            config,
+            db: Some(db),
+            usage: Mutex::new(HashMap::new()),
+            next_pending_id: AtomicU64::new(0),
+        })
+    }
+
+    /// 未配置配额时使用, 不访问数据库
+    pub fn disabled() -> Arc<Self> {
+        Arc::new(QuotaGuard {
+            config: None,
+            db: None,
+            usage: Mutex::new(HashMap::new()),
+            next_pending_id: AtomicU64::new(0),
+        })
+    }
+
+    fn needs_refresh(&self, tenant_id: &str, refresh: Duration) -> bool {
+        self.usage
+            .lock()
+            .unwrap()
+            .get(tenant_id)
+            .is_none_or(|cached| cached.refreshed.elapsed() >= refresh)
+    }
+
+    /// 数据库中的用量及其最后更新时间
+    async fn load_stored_bytes(
+        &self,
+        tenant_id: &str,
+    ) -> Result<(u64, Option<NaiveDateTime>), String> {
+        let db = self
+            .db
+            .as_ref()
+            .ok_or_else(|| "no database provider".to_string())?;
+        let usage = db
+            .get_tenant_usage(Some(tenant_id))
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(usage
+            .first()
+            .map(|u| (u.total_bytes.max(0) as u64, Some(u.updated_time)))
+            .unwrap_or((0, None)))
+    }
+
+    fn release(&self, tenant_id: &str, id: u64) {
+        let mut usage = self.usage.lock().unwrap();
+        if let Some(cached) = usage.get_mut(tenant_id) {
+            cached.pending.retain(|pending| pending.id != id);
+        }
+    }
+
+    fn reservation(&self, tenant_id: &str, status: QuotaStatus) -> QuotaReservation<'_> {
+        QuotaReservation {
+            guard: self,
+            tenant_id: tenant_id.to_string(),
+            pending_id: None,
+            status,
+        }
+    }
+
+    /// 检查租户写入 incoming 字节后是否超出配额, 未超出硬限制时计入缓存用量.
+    /// 数据库不可用时使用缓存的用量, 从未读取成功则不限制
+    pub async fn check(&self, tenant_id: &str, incoming: u64) -> QuotaReservation<'_> {
+        let Some(config) = &self.config else {
+            return self.reservation(tenant_id, QuotaStatus::Ok);
+        };
+        let Some(limit) = config.limit_for(tenant_id) else {
+            return self.reservation(tenant_id, QuotaStatus::Ok);
+        };
+        let rlogger = get_logger();
+        let logger = rlogger.new(o!("common"=>"quota_check"));
+
+        let refresh = Duration::from_secs(config.refresh_secs);
+        if self.needs_refresh(tenant_id, refresh) {
+            match self.load_stored_bytes(tenant_id).await {
+                Ok((stored_bytes, persisted)) => {
+                    let mut usage = self.usage.lock().unwrap();
+                    usage
+                        .entry(tenant_id.to_string())
+                        .or_insert_with(|| CachedUsage::new(stored_bytes))
+                        .refresh(stored_bytes, persisted);
+                }
+                Err(e) => warn!(logger, "Failed to load usage of tenant {}: {}", tenant_id, e),
+            }
+        }
+
+        let mut usage = self.usage.lock().unwrap();
+        let Some(cached) = usage.get_mut(tenant_id) else {
+            return self.reservation(tenant_id, QuotaStatus::Ok);
+        };
+        let status = evaluate(limit, cached.used_bytes(), incoming);
+        match status {
+            QuotaStatus::HardExceeded => {
+                warn!(
+                    logger,
+                    "Tenant {} exceeded hard quota ({:?} MB), rejecting {} bytes",
+                    tenant_id,
+                    limit.hard_limit_mb,
+                    incoming
+                );
+                return self.reservation(tenant_id, status);
+            }
+            QuotaStatus::SoftExceeded => {
+                if cached
+                    .soft_warned
+                    .is_none_or(|warned| warned.elapsed() >= SOFT_WARN_INTERVAL)
+                {
+                    warn!(
+                        logger,
+                        "Tenant {} exceeded soft quota ({:?} MB)", tenant_id, limit.soft_limit_mb
+                    );
+                    cached.soft_warned = Some(Instant::now());
+                }
+            }
+            QuotaStatus::Ok => {}
+        }
+        let id = self.next_pending_id.fetch_add(1, Ordering::Relaxed);
+        cached.pending.push(PendingBytes {
+            id,
+            reserved: current_time(),
+            bytes: incoming,
+        });
+        QuotaReservation {
+            guard: self,
+            tenant_id: tenant_id.to_string(),
+            pending_id: Some(id),
+            status,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        let limit = QuotaLimit {
+            soft_limit_mb: Some(8),
+            hard_limit_mb: Some(10),
+        };
+        assert_eq!(evaluate(&limit, 0, MB), QuotaStatus::Ok);
+        assert_eq!(evaluate(&limit, 8 * MB, 1), QuotaStatus::SoftExceeded);
+        assert_eq!(evaluate(&limit, 10 * MB, 0), QuotaStatus::SoftExceeded);
+        assert_eq!(evaluate(&limit, 10 * MB, 1), QuotaStatus::HardExceeded);
+        assert_eq!(
+            evaluate(&QuotaLimit::default(), u64::MAX, u64::MAX),
+            QuotaStatus::Ok
+        );
+    }
+
+    fn guard_with_usage(stored_bytes: u64) -> QuotaGuard {
+        let guard = QuotaGuard {
+            config: Some(QuotaConfig {
+                refresh_secs: 3600,
+                default: Some(QuotaLimit {
+                    soft_limit_mb: None,
+                    hard_limit_mb: Some(10),
+                }),
+                tenants: vec![],
+            }),
+            db: None,
+            usage: Mutex::new(HashMap::new()),
+            next_pending_id: AtomicU64::new(0),
+        };
+        guard
+            .usage
+            .lock()
+            .unwrap()
+            .insert("t1".to_string(), CachedUsage::new(stored_bytes));
+        guard
+    }
+
+    fn pending_bytes(guard: &QuotaGuard) -> u64 {
+        let usage = guard.usage.lock().unwrap();
+        usage["t1"].used_bytes() - usage["t1"].stored_bytes
+    }
+
+    #[tokio::test]
+    async fn test_reservation_released_on_failure() {
+        crate::utils::set_global_logger(slog::Logger::root(slog::Discard, o!()));
+        let guard = guard_with_usage(8 * MB);
+
+        let reservation = guard.check("t1", MB).await;
+        assert_eq!(reservation.status(), QuotaStatus::Ok);
+        assert_eq!(pending_bytes(&guard), MB);
+        reservation.commit();
+        assert_eq!(pending_bytes(&guard), MB);
+
+        // 写入失败, 退回计入的用量
+        let reservation = guard.check("t1", MB).await;
+        assert_eq!(pending_bytes(&guard), 2 * MB);
+        drop(reservation);
+        assert_eq!(pending_bytes(&guard), MB);
+
+        // 超出硬限制时不计入
+        let reservation = guard.check("t1", 2 * MB).await;
+        assert_eq!(reservation.status(), QuotaStatus::HardExceeded);
+        assert_eq!(pending_bytes(&guard), MB);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_keeps_unpersisted() {
+        crate::utils::set_global_logger(slog::Logger::root(slog::Discard, o!()));
+        let guard = guard_with_usage(MB);
+        guard.check("t1", MB).await.commit();
+        let persisted = current_time();
+        std::thread::sleep(Duration::from_millis(2));
+        guard.check("t1", 2 * MB).await.commit();
+        assert_eq!(pending_bytes(&guard), 3 * MB);
+
+        // 数据库用量包含第一次写入, 第二次写入尚未入库
+        let mut usage = guard.usage.lock().unwrap();
+        let cached = usage.get_mut("t1").unwrap();
+        cached.refresh(2 * MB, Some(persisted));
+        assert_eq!(cached.used_bytes(), 4 * MB);
+        // 没有用量记录时所有写入都未入库
+        cached.refresh(0, None);
+        assert_eq!(cached.used_bytes(), 2 * MB);
+    }
+}
@@ -0,0 +1,391 @@
+//! 存储与索引对账.
+//!
+//! 收图时先写文件再经 Kafka 由 wado-consumer 建立索引, 中途失败会留下没有索引的孤立文件;
+//! 文件被误删时留下找不到文件的悬空索引. 对账遍历所有存储层与 dicom_image_meta 进行比较:
+//! - 孤立文件: 按 GcConfig.orphan_action 只报告、重新建立索引或移动到隔离目录.
+//!   已删除(未恢复)或被拒绝的实例只报告, 不重新建立索引
+//! - 悬空索引: 按 mark_dangling 将 image_status 标记为 MISSING
+//!
+//! 修改时间(文件)或创建时间(索引)在宽限时间内的不处理, dry_run 时只报告. 隔离目录和回收站中的文件不参与对账.
+//! 文件按 batch_size 分页遍历, 不一次性列出全部对象.
+
+use crate::deletion::{TRASH_DIR, is_trashed};
+use crate::patient_update::{STAGING_DIR, is_staged};
+use crate::dicom_file_handler::process_dicom_memobject;
+use crate::dicom_utils::{get_date_value_dicom, get_text_value};
+use crate::fixity;
+use crate::message_sender_kafka::KafkaMessagePublisher;
+use crate::server_config::{AppConfig, GcConfig, HOT_TIER, OrphanAction};
+use crate::storage_backend::{StorageBackend, StorageError};
+use crate::storage_config::StorageConfig;
+use crate::storage_layout::{LayoutInput, StorageLayout};
+use crate::storage_tier::tiered_storage;
+use crate::utils::{get_logger, publish_messages};
+use database::dicom_dbprovider::{DbProvider, current_time};
+use database::dicom_dbtype::BoundedString;
+use database::dicom_meta::{DicomImageFile, DicomStoreMeta};
+use dicom_dictionary_std::tags;
+use dicom_object::OpenFileOptions;
+use serde::Serialize;
+use slog::{Logger, o, warn};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// 隔离目录, 位于 key 的第一级目录(租户)之下, 以便继续使用原租户的加密密钥
+pub const QUARANTINE_DIR: &str = "_quarantine";
+const RECONCILE_SOURCE: &str = "RECONCILE";
+
+#[derive(Debug, Clone)]
+pub struct ReconcileOptions {
+    pub orphan_action: OrphanAction,
+    pub mark_dangling: bool,
+    pub grace: Duration,
+    pub batch_size: i64,
+    pub dry_run: bool,
+}
+
+impl From<&GcConfig> for ReconcileOptions {
+    fn from(config: &GcConfig) -> Self {
+        ReconcileOptions {
+            orphan_action: config.orphan_action,
+            mark_dangling: config.mark_dangling,
+            grace: Duration::from_secs(config.grace_hours * 3600),
+            batch_size: config.batch_size.max(1),
+            dry_run: config.dry_run,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ReconcileReport {
+    pub scanned_files: usize,
+    /// 在宽限时间内未处理的孤立文件
+    pub skipped_recent: usize,
+    /// (存储层, key)
+    pub orphans: Vec<(String, String)>,
+    pub reindexed: usize,
+    pub quarantined: usize,
+    pub scanned_records: usize,
+    /// 找不到文件的实例文件路径
+    pub dangling: Vec<String>,
+    pub marked_missing: usize,
+}
+
+/// 隔离后的 key: `{第一级目录}/_quarantine/{其余部分}`
+pub fn quarantine_key(key: &str) -> String {
+    match key.split_once('/') {
+        Some((first, rest)) => format!("{}/{}/{}", first, QUARANTINE_DIR, rest),
+        None => format!("{}/{}", QUARANTINE_DIR, key),
+    }
+}
+
+pub fn is_quarantined(key: &str) -> bool {
+    key.split('/').take(2).any(|segment| segment == QUARANTINE_DIR)
+}
+
+/// 分页遍历的下一个起点. key 位于隔离目录、回收站或暂存目录时跳过整个目录:
+/// `{租户}/_trash0` 排在 `{租户}/_trash/` 下所有 key 之后('0' 紧跟在 '/' 之后)
+fn next_cursor(key: &str) -> String {
+    let mut segments = key.splitn(3, '/');
+    if let (Some(tenant), Some(dir), Some(_)) = (segments.next(), segments.next(), segments.next())
+        && (dir == TRASH_DIR || dir == QUARANTINE_DIR || dir == STAGING_DIR)
+    {
+        return format!("{}/{}0", tenant, dir);
+    }
+    key.to_string()
+}
+
+fn within_grace(modified: SystemTime, now: SystemTime, grace: Duration) -> bool {
+    now.duration_since(modified).map_or(true, |age| age < grace)
+}
+
+/// 读取文件头, 按目录布局反推租户和布局版本, 生成重新建立索引所需的 DicomStoreMeta.
+/// key 与任何布局都不匹配时返回 None, source 写入 worker_node_id 和 source_ae
+pub async fn resolve_store_meta(
+    storage_config: &StorageConfig<'_>,
+    layouts: &[StorageLayout],
+    backend: &Arc<dyn StorageBackend>,
+    key: &str,
+    source: &str,
+) -> Result<Option<DicomStoreMeta>, Box<dyn std::error::Error>> {
+    let local_file = backend.local_file(key).await?;
+    let file_size = std::fs::metadata(local_file.path())?.len();
+    let checksum = fixity::checksum_file(local_file.path())?;
+    let mut obj = OpenFileOptions::new()
+        .read_until(tags::PIXEL_DATA)
+        .open_file(local_file.path())?;
+    let (Some(study_uid), Some(series_uid), Some(sop_uid), Some(study_date)) = (
+        get_text_value(&obj, tags::STUDY_INSTANCE_UID),
+        get_text_value(&obj, tags::SERIES_INSTANCE_UID),
+        get_text_value(&obj, tags::SOP_INSTANCE_UID),
+        get_date_value_dicom(&obj, tags::STUDY_DATE),
+    ) else {
+        return Ok(None);
+    };
+    let study_date = study_date.format("%Y%m%d").to_string();
+    for layout in layouts {
+        let Some(tenant_id) = layout.tenant_of(key) else {
+            continue;
+        };
+        let input = LayoutInput {
+            tenant_id,
+            study_date: &study_date,
+            study_uid: &study_uid,
+            series_uid: &series_uid,
+        };
+        if layout.file_key(&input, &sop_uid) != key {
+            continue;
+        }
+        let mut meta = process_dicom_memobject(
+            &mut obj,
+            &key.to_string(),
+            file_size,
+            &checksum,
+            layout.version(),
+            &tenant_id.to_string(),
+            storage_config,
+        )
+        .await?;
+        meta.worker_node_id = BoundedString::make_str(source);
+        meta.source_ae = BoundedString::make_str(source);
+        return Ok(Some(meta));
+    }
+    Ok(None)
+}
+
+async fn reconcile_files(
+    db: &dyn DbProvider,
+    app_config: &AppConfig,
+    options: &ReconcileOptions,
+    report: &mut ReconcileReport,
+    logger: &Logger,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let storage = tiered_storage();
+    let storage_config = StorageConfig::make_storage_config(app_config);
+    let layouts = storage_config.all_layouts()?;
+    let publisher = KafkaMessagePublisher::new(app_config.message_queue.topic_main.clone());
+    let now = SystemTime::now();
+
+    let batch_size = options.batch_size as usize;
+    let mut cursor: Option<String> = None;
+    loop {
+        let page = storage.list_page("", cursor.as_deref(), batch_size).await?;
+        let Some((_, last)) = page.last() else {
+            break;
+        };
+        cursor = Some(next_cursor(last));
+        let last_page = page.len() < batch_size;
+        let chunk: Vec<(String, String)> = page
+            .into_iter()
+            .filter(|(_, key)| !is_quarantined(key) && !is_trashed(key) && !is_staged(key))
+            .collect();
+        report.scanned_files += chunk.len();
+
+        let keys: Vec<String> = chunk.iter().map(|(_, key)| key.clone()).collect();
+        let indexed: HashSet<String> = db.find_indexed_files(&keys).await?.into_iter().collect();
+        let mut reindex = vec![];
+        for (tier, key) in &chunk {
+            if indexed.contains(key) {
+                continue;
+            }
+            let Some(backend) = storage.tier(tier) else {
+                continue;
+            };
+            match backend.modified(key).await {
+                Ok(modified) if within_grace(modified, now, options.grace) => {
+                    report.skipped_recent += 1;
+                    continue;
+                }
+                Ok(_) => {}
+                // 遍历后被删除或迁移
+                Err(StorageError::NotFound(_)) => continue,
+                Err(e) => {
+                    warn!(logger, "Failed to stat {}: {}", key, e);
+                    continue;
+                }
+            }
+            // 早期数据没有记录 pixel_data_location, 按 UID 确认是否已建立索引
+            let resolved = match resolve_store_meta(
+                &storage_config,
+                &layouts,
+                backend,
+                key,
+                RECONCILE_SOURCE,
+            )
+            .await
+            {
+                Ok(resolved) => resolved,
+                Err(e) => {
+                    warn!(logger, "Failed to read {}: {}", key, e);
+                    None
+                }
+            };
+            if let Some(meta) = &resolved
+                && db
+                    .image_exists(
+                        meta.tenant_id.as_str(),
+                        meta.study_uid.as_str(),
+                        meta.series_uid.as_str(),
+                        meta.sop_uid.as_str(),
+                    )
+                    .await?
+            {
+                continue;
+            }
+            report.orphans.push((tier.clone(), key.clone()));
+            if options.dry_run {
+                continue;
+            }
+            match options.orphan_action {
+                OrphanAction::Report => {}
+                // wado-consumer 只从热存储层读取文件
+                OrphanAction::Reindex => match resolved {
+                    // 删除或拒绝后文件未能清除, 重新建立索引会恢复已删除的实例
+                    Some(meta)
+                        if db
+                            .has_removal_record(
+                                meta.tenant_id.as_str(),
+                                meta.study_uid.as_str(),
+                                meta.series_uid.as_str(),
+                                meta.sop_uid.as_str(),
+                            )
+                            .await? =>
+                    {
+                        warn!(logger, "Skip reindex of deleted or rejected {}", key)
+                    }
+                    Some(meta) if tier == HOT_TIER => reindex.push(meta),
+                    Some(_) => warn!(logger, "Skip reindex of {} in tier {}", key, tier),
+                    None => warn!(logger, "Cannot determine tenant and layout of {}", key),
+                },
+                OrphanAction::Quarantine => match storage
+                    .move_object(tier, key, tier, &quarantine_key(key))
+                    .await
+                {
+                    Ok(()) => report.quarantined += 1,
+                    Err(e) => warn!(logger, "Failed to quarantine {}: {}", key, e),
+                },
+            }
+        }
+        if !reindex.is_empty() {
+            publish_messages(&publisher, &reindex).await?;
+            report.reindexed += reindex.len();
+        }
+        if last_page {
+            break;
+        }
+    }
+    Ok(())
+}
+
+async fn reconcile_records(
+    db: &dyn DbProvider,
+    app_config: &AppConfig,
+    options: &ReconcileOptions,
+    report: &mut ReconcileReport,
+    logger: &Logger,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let storage = tiered_storage();
+    let storage_config = StorageConfig::make_storage_config(app_config);
+    let created_before = current_time() - chrono::Duration::from_std(options.grace)?;
+
+    let mut after: Option<DicomImageFile> = None;
+    loop {
+        let page = db.get_image_files(after.as_ref(), options.batch_size).await?;
+        report.scanned_records += page.len();
+        let mut missing = vec![];
+        for image in &page {
+            if image
+                .image_status
+                .as_ref()
+                .is_some_and(|status| status.as_str() == DicomImageFile::STATUS_MISSING)
+                || image.created_time.is_some_and(|t| t >= created_before)
+            {
+                continue;
+            }
+            let path = match &image.file_path {
+                Some(path) => path.as_str().to_string(),
+                None => match storage_config.make_dicom_file_key(
+                    image.storage_layout,
+                    image.tenant_id.as_str(),
+                    image.study_date_origin.as_str(),
+                    image.study_uid.as_str(),
+                    image.series_uid.as_str(),
+                    image.sop_uid.as_str(),
+                ) {
+                    Ok(path) => path,
+                    Err(e) => {
+                        warn!(logger, "Failed to locate {}: {}", image.sop_uid, e);
+                        continue;
+                    }
+                },
+            };
+            match storage.locate(&path).await {
+                Ok(_) => {}
+                Err(StorageError::NotFound(_)) => {
+                    report.dangling.push(path);
+                    missing.push(image.clone());
+                }
+                Err(e) => warn!(logger, "Failed to check {}: {}", path, e),
+            }
+        }
+        if options.mark_dangling && !options.dry_run && !missing.is_empty() {
+            db.mark_images_missing(&missing).await?;
+            report.marked_missing += missing.len();
+        }
+        if page.len() < options.batch_size as usize {
+            break;
+        }
+        after = page.into_iter().last();
+    }
+    Ok(())
+}
+
+/// 执行一次完整对账
+pub async fn reconcile(
+    db: &dyn DbProvider,
+    app_config: &AppConfig,
+    options: &ReconcileOptions,
+) -> Result<ReconcileReport, Box<dyn std::error::Error>> {
+    let rlogger = get_logger();
+    let logger = rlogger.new(o!("common"=>"reconcile"));
+    let mut report = ReconcileReport::default();
+    reconcile_files(db, app_config, options, &mut report, &logger).await?;
+    reconcile_records(db, app_config, options, &mut report, &logger).await?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quarantine_key() {
+        let key = quarantine_key("t1/20240101/st/se/1.dcm");
+        assert_eq!(key, "t1/_quarantine/20240101/st/se/1.dcm");
+        assert!(is_quarantined(&key));
+        assert!(!is_quarantined("t1/20240101/st/se/1.dcm"));
+        assert!(is_quarantined(&quarantine_key("1.dcm")));
+    }
+
+    #[test]
+    fn test_next_cursor() {
+        assert_eq!(next_cursor("t1/20240101/st/se/1.dcm"), "t1/20240101/st/se/1.dcm");
+        let cursor = next_cursor("t1/_trash/d1/20240101/st/se/1.dcm");
+        assert_eq!(cursor, "t1/_trash0");
+        assert!(cursor.as_str() > "t1/_trash/zzz/1.dcm");
+        assert!(cursor.as_str() < "t1/_trashx/1.dcm");
+        assert_eq!(next_cursor(&quarantine_key("t1/20240101/1.dcm")), "t1/_quarantine0");
+        assert_eq!(next_cursor("t1/_staging/a1/new/20240101/1.dcm"), "t1/_staging0");
+    }
+
+    #[test]
+    fn test_within_grace() {
+        let now = SystemTime::now();
+        let grace = Duration::from_secs(3600);
+        assert!(within_grace(now - Duration::from_secs(60), now, grace));
+        assert!(!within_grace(now - Duration::from_secs(7200), now, grace));
+        // 修改时间晚于当前时间(时钟偏差)时视为新文件
+        assert!(within_grace(now + Duration::from_secs(60), now, grace));
+    }
+}
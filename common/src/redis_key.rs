@@ -304,6 +304,7 @@ mod tests {
             series_related_instances: Some(1),
             created_time: get_current_time(),
             updated_time: get_current_time(),
+            storage_layout: 0,
         }];
 
         // Set study metadata
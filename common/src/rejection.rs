@@ -0,0 +1,313 @@
+//! IHE IOCM 拒绝说明(Rejection Note).
+//!
+//! 技师通过 Key Object Selection 文档撤回图像, 文档标题(Concept Name Code Sequence)为拒绝原因,
+//! Current Requested Procedure Evidence Sequence 中列出被撤回的实例.
+//! wado-consumer 收到拒绝说明后在 dicom_rejection 中记录被引用的实例并标记为 REJECTED,
+//! WADO-RS 元数据和实例接口不再返回这些实例. 之后收到的被拒绝实例同样标记为 REJECTED.
+//! 因 Data Retention Policy Expired 被拒绝的实例由 wado-webworker 定期删除.
+
+use crate::deletion::{DeletionError, DeletionTarget, remove_objects};
+use crate::dicom_utils::get_text_value;
+use crate::redis_key::RedisHelper;
+use crate::server_config::AppConfig;
+use crate::storage_backend::json_backend;
+use crate::storage_config::StorageConfig;
+use crate::utils::get_logger;
+use database::dicom_dbprovider::{DbError, DbProvider, current_time};
+use database::dicom_dbtype::BoundedString;
+use database::dicom_meta::DicomRejection;
+use dicom_core::Tag;
+use dicom_dictionary_std::tags;
+use dicom_object::InMemDicomObject;
+use slog::{info, o, warn};
+use std::collections::HashSet;
+
+pub const KEY_OBJECT_SELECTION_SOP_CLASS: &str = "1.2.840.10008.5.1.4.1.1.88.59";
+const DCM_CODING_SCHEME: &str = "DCM";
+/// 清除被拒绝实例时记录的删除发起者
+const PURGE_REQUESTER: &str = "IOCM";
+
+/// 作为拒绝说明识别的文档标题(DCM 代码)
+const REJECTION_REASONS: &[(&str, &str)] = &[
+    (
+        DicomRejection::REASON_QUALITY,
+        "Rejected for Quality Reasons",
+    ),
+    (
+        DicomRejection::REASON_PATIENT_SAFETY,
+        "Rejected for Patient Safety Reasons",
+    ),
+    (
+        DicomRejection::REASON_INCORRECT_MWL,
+        "Incorrect Modality Worklist Entry",
+    ),
+    (
+        DicomRejection::REASON_RETENTION_EXPIRED,
+        "Data Retention Policy Expired",
+    ),
+];
+
+/// 拒绝原因的含义, 不是拒绝原因时返回 None
+pub fn rejection_reason(code_value: &str) -> Option<&'static str> {
+    REJECTION_REASONS
+        .iter()
+        .find(|(code, _)| *code == code_value)
+        .map(|(_, meaning)| *meaning)
+}
+
+fn sequence_items(obj: &InMemDicomObject, tag: Tag) -> &[InMemDicomObject] {
+    obj.element(tag)
+        .ok()
+        .and_then(|e| e.items())
+        .unwrap_or_default()
+}
+
+/// 解析拒绝说明, 返回被拒绝的实例. 不是拒绝说明时返回 None
+pub fn parse_rejection_note(
+    tenant_id: &str,
+    obj: &InMemDicomObject,
+) -> Option<Vec<DicomRejection>> {
+    if get_text_value(obj, tags::SOP_CLASS_UID)? != KEY_OBJECT_SELECTION_SOP_CLASS {
+        return None;
+    }
+    let title = sequence_items(obj, tags::CONCEPT_NAME_CODE_SEQUENCE).first()?;
+    if get_text_value(title, tags::CODING_SCHEME_DESIGNATOR)? != DCM_CODING_SCHEME {
+        return None;
+    }
+    let code = get_text_value(title, tags::CODE_VALUE)?;
+    let meaning = rejection_reason(&code)?;
+    let kos_sop_uid = get_text_value(obj, tags::SOP_INSTANCE_UID)?;
+
+    let now = current_time();
+    let mut rejections = vec![];
+    for study in sequence_items(obj, tags::CURRENT_REQUESTED_PROCEDURE_EVIDENCE_SEQUENCE) {
+        let Some(study_uid) = get_text_value(study, tags::STUDY_INSTANCE_UID) else {
+            continue;
+        };
+        for series in sequence_items(study, tags::REFERENCED_SERIES_SEQUENCE) {
+            let Some(series_uid) = get_text_value(series, tags::SERIES_INSTANCE_UID) else {
+                continue;
+            };
+            for sop in sequence_items(series, tags::REFERENCED_SOP_SEQUENCE) {
+                let Some(sop_uid) = get_text_value(sop, tags::REFERENCED_SOP_INSTANCE_UID) else {
+                    continue;
+                };
+                rejections.push(DicomRejection {
+                    tenant_id: BoundedString::make_str(tenant_id),
+                    study_uid: BoundedString::make_str(&study_uid),
+                    series_uid: BoundedString::make_str(&series_uid),
+                    sop_uid: BoundedString::make_str(&sop_uid),
+                    reason_code: BoundedString::make_str(&code),
+                    reason_meaning: BoundedString::make_str(meaning),
+                    kos_sop_uid: BoundedString::make_str(&kos_sop_uid),
+                    rejected_time: now,
+                    purged_time: None,
+                });
+            }
+        }
+    }
+    Some(rejections)
+}
+
+/// 检查下被拒绝实例的 SOP Instance UID, 生成元数据和读取实例时排除
+pub async fn rejected_sop_uids(
+    db: &dyn DbProvider,
+    tenant_id: &str,
+    study_uid: &str,
+) -> Result<HashSet<String>, DbError> {
+    Ok(db
+        .get_rejected_sop_uids(tenant_id, study_uid)
+        .await?
+        .into_iter()
+        .collect())
+}
+
+/// 保存拒绝记录, 并删除受影响检查的 JSON 元数据和缓存, 返回被标记的实例数
+pub async fn apply_rejections(
+    db: &dyn DbProvider,
+    redis: &RedisHelper,
+    app_config: &AppConfig,
+    rejections: &[DicomRejection],
+) -> Result<u64, DbError> {
+    if rejections.is_empty() {
+        return Ok(0);
+    }
+    let rlogger = get_logger();
+    let logger = rlogger.new(o!("common"=>"apply_rejections"));
+    let marked = db.save_rejections(rejections).await?;
+
+    let storage_config = StorageConfig::make_storage_config(app_config);
+    let json = json_backend();
+    let mut studies: Vec<(&str, &str)> = rejections
+        .iter()
+        .map(|r| (r.tenant_id.as_str(), r.study_uid.as_str()))
+        .collect();
+    studies.sort_unstable();
+    studies.dedup();
+    for (tenant_id, study_uid) in studies {
+        // 拒绝说明可能先于被引用的实例到达, 此时检查还不存在
+        let series_list = db.get_state_metaes(tenant_id, study_uid).await?;
+        let Some(study_info) = series_list.first() else {
+            continue;
+        };
+        let series_keys = series_list
+            .iter()
+            .filter(|series| {
+                rejections.iter().any(|r| {
+                    r.tenant_id.as_str() == tenant_id
+                        && r.study_uid.as_str() == study_uid
+                        && r.series_uid.as_str() == series.series_uid.as_str()
+                })
+            })
+            .map(|series| storage_config.json_metadata_key_for_series(series));
+        for key in std::iter::once(storage_config.json_metadata_key_for_study(study_info))
+            .chain(series_keys)
+        {
+            if let Err(e) = json.delete(&key).await {
+                warn!(logger, "Failed to delete JSON metadata {}: {}", key, e);
+            }
+        }
+        if let Err(e) = redis.del_study_metadata(tenant_id, study_uid).await {
+            warn!(logger, "Failed to delete cached study metadata: {}", e);
+        }
+    }
+    info!(
+        logger,
+        "Saved {} rejected instances, {} already received",
+        rejections.len(),
+        marked
+    );
+    Ok(marked)
+}
+
+/// 删除因 Data Retention Policy Expired 被拒绝的实例, 返回处理的拒绝记录数.
+/// 文件直接删除, 不进入回收站
+pub async fn purge_rejected_instances(
+    db: &dyn DbProvider,
+    redis: &RedisHelper,
+    app_config: &AppConfig,
+    limit: i64,
+) -> Result<usize, DeletionError> {
+    let rlogger = get_logger();
+    let logger = rlogger.new(o!("common"=>"purge_rejected_instances"));
+    let rejections = db
+        .get_rejections_to_purge(DicomRejection::REASON_RETENTION_EXPIRED, limit)
+        .await?;
+    let mut purged = Vec::with_capacity(rejections.len());
+    for rejection in rejections {
+        let target = DeletionTarget {
+            tenant_id: rejection.tenant_id.as_str(),
+            study_uid: rejection.study_uid.as_str(),
+            series_uid: Some(rejection.series_uid.as_str()),
+            sop_uid: Some(rejection.sop_uid.as_str()),
+        };
+        match remove_objects(db, redis, app_config, &target, Some(PURGE_REQUESTER), 0).await {
+            // 实例从未收到或已被删除
+            Ok(_) | Err(DeletionError::NotFound(_)) => purged.push(rejection),
+            Err(e) => warn!(
+                logger,
+                "Failed to purge rejected instance {}: {}", rejection.sop_uid, e
+            ),
+        }
+    }
+    db.mark_rejections_purged(&purged).await?;
+    Ok(purged.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom_core::value::DataSetSequence;
+    use dicom_core::{DataElement, PrimitiveValue, VR};
+
+    fn code_item(code: &str, scheme: &str) -> InMemDicomObject {
+        InMemDicomObject::from_element_iter([
+            DataElement::new(tags::CODE_VALUE, VR::SH, PrimitiveValue::from(code)),
+            DataElement::new(
+                tags::CODING_SCHEME_DESIGNATOR,
+                VR::SH,
+                PrimitiveValue::from(scheme),
+            ),
+        ])
+    }
+
+    fn sequence(tag: Tag, items: Vec<InMemDicomObject>) -> DataElement<InMemDicomObject> {
+        DataElement::new(tag, VR::SQ, DataSetSequence::from(items))
+    }
+
+    fn rejection_note(sop_class: &str, title: InMemDicomObject) -> InMemDicomObject {
+        let sop = |uid: &str| {
+            InMemDicomObject::from_element_iter([DataElement::new(
+                tags::REFERENCED_SOP_INSTANCE_UID,
+                VR::UI,
+                PrimitiveValue::from(uid),
+            )])
+        };
+        let series = InMemDicomObject::from_element_iter([
+            DataElement::new(
+                tags::SERIES_INSTANCE_UID,
+                VR::UI,
+                PrimitiveValue::from("1.2.3.1"),
+            ),
+            sequence(
+                tags::REFERENCED_SOP_SEQUENCE,
+                vec![sop("1.2.3.1.1"), sop("1.2.3.1.2")],
+            ),
+        ]);
+        let study = InMemDicomObject::from_element_iter([
+            DataElement::new(
+                tags::STUDY_INSTANCE_UID,
+                VR::UI,
+                PrimitiveValue::from("1.2.3"),
+            ),
+            sequence(tags::REFERENCED_SERIES_SEQUENCE, vec![series]),
+        ]);
+        InMemDicomObject::from_element_iter([
+            DataElement::new(tags::SOP_CLASS_UID, VR::UI, PrimitiveValue::from(sop_class)),
+            DataElement::new(
+                tags::SOP_INSTANCE_UID,
+                VR::UI,
+                PrimitiveValue::from("9.9.9"),
+            ),
+            sequence(tags::CONCEPT_NAME_CODE_SEQUENCE, vec![title]),
+            sequence(
+                tags::CURRENT_REQUESTED_PROCEDURE_EVIDENCE_SEQUENCE,
+                vec![study],
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_parse_rejection_note() {
+        let obj = rejection_note(
+            KEY_OBJECT_SELECTION_SOP_CLASS,
+            code_item(DicomRejection::REASON_RETENTION_EXPIRED, "DCM"),
+        );
+        let rejections = parse_rejection_note("t1", &obj).unwrap();
+        assert_eq!(rejections.len(), 2);
+        assert_eq!(rejections[0].tenant_id.as_str(), "t1");
+        assert_eq!(rejections[0].study_uid.as_str(), "1.2.3");
+        assert_eq!(rejections[0].series_uid.as_str(), "1.2.3.1");
+        assert_eq!(rejections[1].sop_uid.as_str(), "1.2.3.1.2");
+        assert_eq!(rejections[0].reason_code.as_str(), "113039");
+        assert_eq!(
+            rejections[0].reason_meaning.as_str(),
+            "Data Retention Policy Expired"
+        );
+        assert_eq!(rejections[0].kos_sop_uid.as_str(), "9.9.9");
+    }
+
+    #[test]
+    fn test_parse_other_documents() {
+        // 普通的关键图像说明
+        let obj = rejection_note(KEY_OBJECT_SELECTION_SOP_CLASS, code_item("113000", "DCM"));
+        assert!(parse_rejection_note("t1", &obj).is_none());
+        let obj = rejection_note(
+            KEY_OBJECT_SELECTION_SOP_CLASS,
+            code_item("113001", "99LOCAL"),
+        );
+        assert!(parse_rejection_note("t1", &obj).is_none());
+        let obj = rejection_note("1.2.840.10008.5.1.4.1.1.2", code_item("113001", "DCM"));
+        assert!(parse_rejection_note("t1", &obj).is_none());
+    }
+}
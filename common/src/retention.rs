@@ -0,0 +1,423 @@
+//! 数据保留策略和法律保全(legal hold).
+//!
+//! wado-webworker 定期按 retention.policies 评估检查的保留期: 检查日期后保留 retain_years 年,
+//! 配置 majority_age 时未成年患者的检查保留到成年后再过 retain_years 年. 一个检查匹配多个策略时使用保留期最长的.
+//! 保留期已过的检查记录为候选(PENDING), 由管理接口审核删除或保留; 配置 auto_delete 时直接通过删除流程删除.
+//! 处于法律保全中的患者或检查不会成为候选, 删除流程也会拒绝删除.
+
+use crate::deletion::{DeletionError, DeletionTarget, remove_objects};
+use crate::redis_key::RedisHelper;
+use crate::server_config::{AppConfig, RetentionConfig, RetentionPolicy};
+use crate::utils::get_logger;
+use chrono::{Months, NaiveDate};
+use database::dicom_dbprovider::{DbError, DbProvider, current_time};
+use database::dicom_dbtype::BoundedString;
+use database::dicom_meta::{
+    DicomDeletion, DicomLegalHold, DicomRetentionCandidate, DicomRetentionStudy,
+};
+use slog::{info, o, warn};
+use thiserror::Error;
+
+/// 自动删除时记录的删除发起者
+const RETENTION_REQUESTER: &str = "RETENTION";
+
+#[derive(Error, Debug)]
+pub enum RetentionError {
+    #[error("Retention is not enabled")]
+    Disabled,
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Invalid request: {0}")]
+    Invalid(String),
+
+    #[error("Retention candidate {0} is {1}, only PENDING candidates can be reviewed")]
+    NotPending(String, String),
+
+    #[error(transparent)]
+    Deletion(#[from] DeletionError),
+
+    #[error(transparent)]
+    Database(#[from] DbError),
+}
+
+fn add_years(date: NaiveDate, years: u32) -> NaiveDate {
+    date.checked_add_months(Months::new(years * 12))
+        .unwrap_or(NaiveDate::MAX)
+}
+
+fn policy_matches(policy: &RetentionPolicy, study: &DicomRetentionStudy) -> bool {
+    let matches_any = |values: &[String], study_values: &[String]| {
+        values.is_empty() || values.iter().any(|v| study_values.contains(v))
+    };
+    (policy.tenant_ids.is_empty()
+        || policy
+            .tenant_ids
+            .iter()
+            .any(|t| t == study.tenant_id.as_str()))
+        && matches_any(&policy.modalities, &study.modalities)
+        && matches_any(&policy.sop_class_uids, &study.sop_class_uids)
+}
+
+/// 策略规定的保留期结束日期. 需要出生日期但检查没有时无法确定, 返回 None
+fn policy_expire_date(policy: &RetentionPolicy, study: &DicomRetentionStudy) -> Option<NaiveDate> {
+    let expire_date = add_years(study.study_date, policy.retain_years);
+    match policy.majority_age {
+        Some(age) => {
+            let birth_date = study.patient_birth_date?;
+            Some(expire_date.max(add_years(birth_date, age + policy.retain_years)))
+        }
+        None => Some(expire_date),
+    }
+}
+
+/// 检查的保留期结束日期及决定保留期的策略. 没有匹配的策略或保留期无法确定时返回 None, 检查一直保留
+pub fn retention_expire_date<'a>(
+    policies: &'a [RetentionPolicy],
+    study: &DicomRetentionStudy,
+) -> Option<(&'a RetentionPolicy, NaiveDate)> {
+    let mut result: Option<(&RetentionPolicy, NaiveDate)> = None;
+    for policy in policies.iter().filter(|p| policy_matches(p, study)) {
+        let expire_date = policy_expire_date(policy, study)?;
+        if result.is_none_or(|(_, date)| expire_date > date) {
+            result = Some((policy, expire_date));
+        }
+    }
+    result
+}
+
+/// 查找保留期在 today 之前(含)结束的检查并保存为候选, 返回新增的候选数
+pub async fn find_retention_candidates(
+    db: &dyn DbProvider,
+    retention: &RetentionConfig,
+    today: NaiveDate,
+) -> Result<u64, DbError> {
+    let Some(min_years) = retention.policies.iter().map(|p| p.retain_years).min() else {
+        return Ok(0);
+    };
+    // 检查日期晚于该日期的检查在任何策略下都未到期
+    let study_date_before = today
+        .checked_sub_months(Months::new(min_years * 12))
+        .unwrap_or(NaiveDate::MIN);
+    let (mut after_tenant_id, mut after_study_uid) = (String::new(), String::new());
+    let mut saved = 0;
+    loop {
+        let studies = db
+            .get_retention_studies(
+                study_date_before,
+                &after_tenant_id,
+                &after_study_uid,
+                retention.batch_size,
+            )
+            .await?;
+        let now = current_time();
+        let candidates: Vec<DicomRetentionCandidate> = studies
+            .iter()
+            .filter_map(|study| {
+                let (policy, expire_date) = retention_expire_date(&retention.policies, study)?;
+                (expire_date <= today).then(|| DicomRetentionCandidate {
+                    tenant_id: study.tenant_id.clone(),
+                    study_uid: study.study_uid.clone(),
+                    patient_id: study.patient_id.clone(),
+                    policy_name: BoundedString::make_str(&policy.name),
+                    expire_date,
+                    status: BoundedString::make_str(DicomRetentionCandidate::STATUS_PENDING),
+                    deletion_id: None,
+                    created_time: now,
+                    updated_time: now,
+                })
+            })
+            .collect();
+        saved += db.save_retention_candidates(&candidates).await?;
+        match studies.last() {
+            Some(last) if studies.len() as i64 >= retention.batch_size => {
+                after_tenant_id = last.tenant_id.as_str().to_string();
+                after_study_uid = last.study_uid.as_str().to_string();
+            }
+            _ => return Ok(saved),
+        }
+    }
+}
+
+/// 通过删除流程删除候选检查并标记为 DELETED. 检查已不存在时同样标记
+async fn delete_candidate(
+    db: &dyn DbProvider,
+    redis: &RedisHelper,
+    app_config: &AppConfig,
+    candidate: &DicomRetentionCandidate,
+    requested_by: Option<&str>,
+) -> Result<Option<DicomDeletion>, RetentionError> {
+    let target = DeletionTarget {
+        tenant_id: candidate.tenant_id.as_str(),
+        study_uid: candidate.study_uid.as_str(),
+        series_uid: None,
+        sop_uid: None,
+    };
+    let trash_days = app_config.deletion.as_ref().map_or(0, |d| d.trash_days);
+    let deletion =
+        match remove_objects(db, redis, app_config, &target, requested_by, trash_days).await {
+            Ok(deletion) => Some(deletion),
+            Err(DeletionError::NotFound(_)) => None,
+            Err(e) => return Err(e.into()),
+        };
+    db.update_retention_candidate(
+        target.tenant_id,
+        target.study_uid,
+        DicomRetentionCandidate::STATUS_DELETED,
+        deletion.as_ref().map(|d| d.deletion_id.as_str()),
+    )
+    .await?;
+    Ok(deletion)
+}
+
+/// 自动删除等待中的候选(法律保全中的除外), 返回删除的检查数
+pub async fn auto_delete_candidates(
+    db: &dyn DbProvider,
+    redis: &RedisHelper,
+    app_config: &AppConfig,
+    limit: i64,
+) -> Result<usize, DbError> {
+    let rlogger = get_logger();
+    let logger = rlogger.new(o!("common"=>"auto_delete_candidates"));
+    let mut deleted = 0;
+    for candidate in db.get_deletable_retention_candidates(limit).await? {
+        match delete_candidate(db, redis, app_config, &candidate, Some(RETENTION_REQUESTER)).await {
+            Ok(_) => deleted += 1,
+            Err(e) => warn!(
+                logger,
+                "Failed to delete retention candidate {}/{}: {}",
+                candidate.tenant_id,
+                candidate.study_uid,
+                e
+            ),
+        }
+    }
+    Ok(deleted)
+}
+
+fn ensure_enabled(app_config: &AppConfig) -> Result<(), RetentionError> {
+    match app_config.retention {
+        Some(_) => Ok(()),
+        None => Err(RetentionError::Disabled),
+    }
+}
+
+async fn pending_candidate(
+    db: &dyn DbProvider,
+    app_config: &AppConfig,
+    tenant_id: &str,
+    study_uid: &str,
+) -> Result<DicomRetentionCandidate, RetentionError> {
+    ensure_enabled(app_config)?;
+    let candidate = db
+        .get_retention_candidate(tenant_id, study_uid)
+        .await?
+        .ok_or_else(|| RetentionError::NotFound(study_uid.to_string()))?;
+    if candidate.status.as_str() != DicomRetentionCandidate::STATUS_PENDING {
+        return Err(RetentionError::NotPending(
+            study_uid.to_string(),
+            candidate.status.as_str().to_string(),
+        ));
+    }
+    Ok(candidate)
+}
+
+/// 审核通过: 删除候选检查, 返回更新后的候选记录
+pub async fn approve_candidate(
+    db: &dyn DbProvider,
+    redis: &RedisHelper,
+    app_config: &AppConfig,
+    tenant_id: &str,
+    study_uid: &str,
+    requested_by: Option<&str>,
+) -> Result<DicomRetentionCandidate, RetentionError> {
+    let mut candidate = pending_candidate(db, app_config, tenant_id, study_uid).await?;
+    let deletion = delete_candidate(db, redis, app_config, &candidate, requested_by).await?;
+    candidate.status = BoundedString::make_str(DicomRetentionCandidate::STATUS_DELETED);
+    candidate.deletion_id = deletion.map(|d| d.deletion_id);
+    candidate.updated_time = current_time();
+    Ok(candidate)
+}
+
+/// 审核决定保留, 该检查不再作为候选
+pub async fn keep_candidate(
+    db: &dyn DbProvider,
+    app_config: &AppConfig,
+    tenant_id: &str,
+    study_uid: &str,
+) -> Result<DicomRetentionCandidate, RetentionError> {
+    let mut candidate = pending_candidate(db, app_config, tenant_id, study_uid).await?;
+    db.update_retention_candidate(
+        tenant_id,
+        study_uid,
+        DicomRetentionCandidate::STATUS_KEPT,
+        None,
+    )
+    .await?;
+    candidate.status = BoundedString::make_str(DicomRetentionCandidate::STATUS_KEPT);
+    candidate.updated_time = current_time();
+    Ok(candidate)
+}
+
+fn check_hold_value(name: &str, value: Option<&str>, max: usize) -> Result<(), RetentionError> {
+    match value {
+        Some(value) if value.is_empty() || value.len() > max => Err(RetentionError::Invalid(
+            format!("{} must be 1 to {} characters", name, max),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// 对患者或检查设置法律保全
+pub async fn place_legal_hold(
+    db: &dyn DbProvider,
+    app_config: &AppConfig,
+    tenant_id: &str,
+    patient_id: Option<&str>,
+    study_uid: Option<&str>,
+    reason: &str,
+    created_by: Option<&str>,
+) -> Result<DicomLegalHold, RetentionError> {
+    ensure_enabled(app_config)?;
+    if patient_id.is_none() && study_uid.is_none() {
+        return Err(RetentionError::Invalid(
+            "patient_id or study_uid is required".to_string(),
+        ));
+    }
+    check_hold_value("patient_id", patient_id, 64)?;
+    check_hold_value("study_uid", study_uid, 64)?;
+    check_hold_value("reason", Some(reason), 256)?;
+    let hold = DicomLegalHold {
+        hold_id: BoundedString::make(uuid::Uuid::new_v4().to_string()),
+        tenant_id: BoundedString::make_str(tenant_id),
+        patient_id: patient_id.map(BoundedString::make_str),
+        study_uid: study_uid.map(BoundedString::make_str),
+        reason: reason.to_string(),
+        created_by: created_by.map(BoundedString::make_str),
+        created_time: current_time(),
+        released_by: None,
+        released_time: None,
+    };
+    db.save_legal_hold(&hold).await?;
+    let rlogger = get_logger();
+    let logger = rlogger.new(o!("common"=>"place_legal_hold"));
+    info!(
+        logger,
+        "Legal hold {} placed on {}: patient {:?} study {:?} by {:?}",
+        hold.hold_id,
+        tenant_id,
+        patient_id,
+        study_uid,
+        created_by
+    );
+    Ok(hold)
+}
+
+/// 解除法律保全, 之后的评估中检查可以重新成为候选
+pub async fn release_legal_hold(
+    db: &dyn DbProvider,
+    app_config: &AppConfig,
+    tenant_id: &str,
+    hold_id: &str,
+    released_by: Option<&str>,
+) -> Result<(), RetentionError> {
+    ensure_enabled(app_config)?;
+    if !db
+        .release_legal_hold(tenant_id, hold_id, released_by)
+        .await?
+    {
+        return Err(RetentionError::NotFound(hold_id.to_string()));
+    }
+    let rlogger = get_logger();
+    let logger = rlogger.new(o!("common"=>"release_legal_hold"));
+    info!(
+        logger,
+        "Legal hold {} on {} released by {:?}", hold_id, tenant_id, released_by
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(name: &str, retain_years: u32, majority_age: Option<u32>) -> RetentionPolicy {
+        RetentionPolicy {
+            name: name.to_string(),
+            retain_years,
+            majority_age,
+            tenant_ids: vec![],
+            modalities: vec![],
+            sop_class_uids: vec![],
+        }
+    }
+
+    fn study(study_date: &str, birth_date: Option<&str>, modality: &str) -> DicomRetentionStudy {
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        DicomRetentionStudy {
+            tenant_id: BoundedString::make_str("t1"),
+            patient_id: BoundedString::make_str("P1"),
+            study_uid: BoundedString::make_str("1.2.3"),
+            study_date: date(study_date),
+            patient_birth_date: birth_date.map(date),
+            modalities: vec![modality.to_string()],
+            sop_class_uids: vec![],
+        }
+    }
+
+    fn expire(
+        policies: &[RetentionPolicy],
+        study: &DicomRetentionStudy,
+    ) -> Option<(String, String)> {
+        retention_expire_date(policies, study)
+            .map(|(policy, date)| (policy.name.clone(), date.to_string()))
+    }
+
+    #[test]
+    fn test_adult_and_pediatric_retention() {
+        let policies = vec![policy("default", 10, Some(18))];
+        // 成年患者: 检查日期后 10 年
+        let adult = study("2010-03-01", Some("1970-01-01"), "CT");
+        assert_eq!(
+            expire(&policies, &adult),
+            Some(("default".to_string(), "2020-03-01".to_string()))
+        );
+        // 未成年患者: 18 岁后再保留 10 年
+        let child = study("2010-03-01", Some("2008-06-15"), "CT");
+        assert_eq!(
+            expire(&policies, &child),
+            Some(("default".to_string(), "2036-06-15".to_string()))
+        );
+        // 没有出生日期时无法确定
+        assert_eq!(expire(&policies, &study("2010-03-01", None, "CT")), None);
+        assert_eq!(
+            expire(
+                &[policy("adult", 10, None)],
+                &study("2012-02-29", None, "CT")
+            ),
+            Some(("adult".to_string(), "2022-02-28".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_longest_matching_policy_wins() {
+        let mut mammography = policy("mammography", 20, None);
+        mammography.modalities = vec!["MG".to_string()];
+        let mut other_tenant = policy("other", 30, None);
+        other_tenant.tenant_ids = vec!["t2".to_string()];
+        let policies = vec![policy("default", 10, None), mammography, other_tenant];
+        assert_eq!(
+            expire(&policies, &study("2000-01-01", None, "MG")),
+            Some(("mammography".to_string(), "2020-01-01".to_string()))
+        );
+        assert_eq!(
+            expire(&policies, &study("2000-01-01", None, "CT")),
+            Some(("default".to_string(), "2010-01-01".to_string()))
+        );
+        assert_eq!(
+            expire(&policies[1..], &study("2000-01-01", None, "CT")),
+            None
+        );
+    }
+}
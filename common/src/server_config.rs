@@ -1,6 +1,7 @@
 use config::{Config, ConfigError, Environment, File};
 use dicom_encoding::TransferSyntaxIndex;
 use dicom_transfer_syntax_registry::TransferSyntaxRegistry;
+use crate::storage_layout::{LEGACY_LAYOUT_VERSION, StorageLayout};
 use dotenv::dotenv;
 use serde::Deserialize;
 use std::path::PathBuf;
@@ -17,12 +18,101 @@ pub struct RedisConfig {
 // 定义配置结构体
 #[derive(Debug, Deserialize, Clone)]
 pub struct DatabaseConfig {
-    pub dbtype: String, //数据库类型 POSTGRES  MYSQL SQLITE
+    pub dbtype: String, //数据库类型 postgresql / mysql / mariadb / sqlite
+    // 连接参数, sqlite 不需要
+    #[serde(default)]
     pub host: String,
+    #[serde(default)]
     pub port: u16,
+    #[serde(default)]
     pub username: String,
+    #[serde(default)]
     pub password: String,
+    #[serde(default)]
     pub database: String,
+    /// SQLite 数据库文件路径, 文件不存在时自动创建, dbtype 为 sqlite 时必须配置
+    #[serde(default)]
+    pub path: Option<String>,
+    /// 启动时执行尚未执行的迁移, 关闭时数据库版本低于程序要求则拒绝启动. sqlite 总是执行
+    #[serde(default)]
+    pub auto_migrate: bool,
+    /// 连接池设置, 未配置时使用默认值
+    #[serde(default)]
+    pub pool: DatabasePoolConfig,
+}
+
+/// 数据库 TLS 模式, 与 libpq 的 sslmode 含义相同
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DatabaseSslMode {
+    #[default]
+    Disable,
+    Prefer,
+    Require,
+}
+
+fn default_pool_max_size() -> usize {
+    16
+}
+
+fn default_pool_min_size() -> usize {
+    1
+}
+
+fn default_pool_wait_timeout() -> u64 {
+    30
+}
+
+fn default_pool_connect_timeout() -> u64 {
+    10
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// 数据库连接池设置
+#[derive(Debug, Deserialize, Clone)]
+pub struct DatabasePoolConfig {
+    #[serde(default = "default_pool_max_size")]
+    pub max_size: usize,
+    /// 保持的最少连接数, 启动时预先建立
+    #[serde(default = "default_pool_min_size")]
+    pub min_size: usize,
+    /// 等待空闲连接的超时时间(秒)
+    #[serde(default = "default_pool_wait_timeout")]
+    pub wait_timeout_secs: u64,
+    #[serde(default = "default_pool_connect_timeout")]
+    pub connect_timeout_secs: u64,
+    /// 取出连接时检查连接是否可用
+    #[serde(default = "default_true")]
+    pub health_check: bool,
+    /// 每个连接缓存准备语句, 经 PgBouncer 事务模式访问时需要关闭
+    #[serde(default = "default_true")]
+    pub statement_cache: bool,
+    #[serde(default)]
+    pub ssl_mode: DatabaseSslMode,
+    /// 校验服务器证书的 CA 证书文件(PEM)
+    #[serde(default)]
+    pub ssl_root_cert: Option<String>,
+    #[serde(default)]
+    pub ssl_accept_invalid_certs: bool,
+}
+
+impl Default for DatabasePoolConfig {
+    fn default() -> Self {
+        DatabasePoolConfig {
+            max_size: default_pool_max_size(),
+            min_size: default_pool_min_size(),
+            wait_timeout_secs: default_pool_wait_timeout(),
+            connect_timeout_secs: default_pool_connect_timeout(),
+            health_check: true,
+            statement_cache: true,
+            ssl_mode: DatabaseSslMode::Disable,
+            ssl_root_cert: None,
+            ssl_accept_invalid_certs: false,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -32,10 +122,163 @@ pub struct ServerConfig {
     pub allow_origin: Vec<String>,
 }
 
+/// 存储类型
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum StorageType {
+    #[default]
+    Disk,
+    /// S3 兼容对象存储(AWS S3 / MinIO 等)
+    S3,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct LocalStorageConfig {
+    /// DISK: 文件直接保存在 dicm_store_path/json_store_path 下; S3: 保存到对象存储
+    #[serde(default, rename = "type")]
+    pub storage_type: StorageType,
     pub dicm_store_path: String,
     pub json_store_path: String,
+    /// type 为 S3 时必须配置
+    #[serde(default)]
+    pub s3: Option<S3StorageConfig>,
+    /// 附加存储层(如大容量磁盘、对象存储), 上面配置的存储为热存储层 "hot"
+    #[serde(default)]
+    pub tiers: Vec<StorageTierConfig>,
+    /// 静态加密, 对所有存储层及 JSON 元数据生效
+    #[serde(default)]
+    pub encryption: Option<EncryptionConfig>,
+    /// 新序列使用的目录布局版本, 0 为内置布局 {tenant}/{study_date}/{study_uid}/{series_uid}/{sop}.dcm
+    #[serde(default)]
+    pub layout_version: i32,
+    /// 自定义目录布局, 已有数据按序列记录的版本读取, 不再使用的版本也需保留
+    #[serde(default)]
+    pub layouts: Vec<StorageLayoutConfig>,
+}
+
+/// DICOM 文件目录布局模板, 占位符见 storage_layout 模块
+#[derive(Debug, Deserialize, Clone)]
+pub struct StorageLayoutConfig {
+    /// 版本号, 必须大于 0
+    pub version: i32,
+    pub template: String,
+}
+
+fn default_forwarder_http_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_master_key_env() -> String {
+    "DICOM_MASTER_KEY".to_string()
+}
+
+fn default_encryption_chunk_size() -> u32 {
+    64 * 1024
+}
+
+/// 静态加密配置: 每个租户一个数据密钥, 由主密钥包装后保存在 key_store_path
+#[derive(Debug, Deserialize, Clone)]
+pub struct EncryptionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// 保存包装后数据密钥的目录, 所有服务必须使用同一目录
+    pub key_store_path: String,
+    /// 主密钥文件(base64 编码的 32 字节), 未配置时读取环境变量 master_key_env
+    #[serde(default)]
+    pub master_key_file: Option<String>,
+    #[serde(default = "default_master_key_env")]
+    pub master_key_env: String,
+    /// 分段加密的明文长度(字节)
+    #[serde(default = "default_encryption_chunk_size")]
+    pub chunk_size: u32,
+}
+
+/// 附加存储层, 只用于存放迁移后的 DICOM 文件
+#[derive(Debug, Deserialize, Clone)]
+pub struct StorageTierConfig {
+    /// 存储层名称, 迁移策略通过名称引用, 不能为 "hot"
+    pub name: String,
+    #[serde(rename = "type")]
+    pub storage_type: StorageType,
+    /// type 为 DISK 时的根目录
+    #[serde(default)]
+    pub path: Option<String>,
+    /// type 为 S3 时必须配置, 使用其中的 dicom_prefix
+    #[serde(default)]
+    pub s3: Option<S3StorageConfig>,
+}
+
+fn default_tiering_interval() -> u64 {
+    3600
+}
+
+fn default_tiering_batch_size() -> i64 {
+    100
+}
+
+/// local_storage 本身对应的存储层名称
+pub const HOT_TIER: &str = "hot";
+
+fn default_hot_tier() -> String {
+    HOT_TIER.to_string()
+}
+
+/// 分层存储迁移配置, 由 wado-webworker 执行
+#[derive(Debug, Deserialize, Clone)]
+pub struct TieringConfig {
+    /// 迁移任务执行间隔(秒)
+    #[serde(default = "default_tiering_interval")]
+    pub interval_secs: u64,
+    /// 每个策略每轮最多迁移的序列数
+    #[serde(default = "default_tiering_batch_size")]
+    pub batch_size: i64,
+    /// WADO 访问非热存储层的序列时, 是否将其迁回热存储层
+    #[serde(default)]
+    pub recall_on_access: bool,
+    #[serde(default)]
+    pub policies: Vec<TierPolicy>,
+}
+
+/// 迁移策略: 最近访问时间早于 after_days 天的序列从 from_tier 迁移到 to_tier
+#[derive(Debug, Deserialize, Clone)]
+pub struct TierPolicy {
+    pub name: String,
+    #[serde(default = "default_hot_tier")]
+    pub from_tier: String,
+    pub to_tier: String,
+    pub after_days: u32,
+    /// 适用的租户, 为空表示所有租户
+    #[serde(default)]
+    pub tenant_ids: Vec<String>,
+}
+
+fn default_dicom_prefix() -> String {
+    "dicom".to_string()
+}
+
+fn default_json_prefix() -> String {
+    "json".to_string()
+}
+
+/// S3 兼容对象存储配置
+#[derive(Debug, Deserialize, Clone)]
+pub struct S3StorageConfig {
+    /// 自定义服务地址, 如 MinIO: http://127.0.0.1:9000, 未配置时使用 AWS S3
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    pub region: String,
+    pub bucket: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// 允许使用 http 访问 endpoint
+    #[serde(default)]
+    pub allow_http: bool,
+    /// DICOM 文件的对象前缀
+    #[serde(default = "default_dicom_prefix")]
+    pub dicom_prefix: String,
+    /// JSON 元数据的对象前缀
+    #[serde(default = "default_json_prefix")]
+    pub json_prefix: String,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -46,6 +289,105 @@ pub struct DicomStoreScpConfig {
     pub cornerstonejs_supported_transfer_syntax: Vec<String>,
     pub tenant_group: String,   // "0x1211",
     pub tenant_element: String, // "0x1217",
+    /// 允许接入的 Calling AE 注册表, 为空时不校验 Calling AE
+    #[serde(default)]
+    pub ae_registry: Vec<AeRegistryEntry>,
+    /// DICOM TLS 监听配置, 未配置时只监听普通 TCP 端口
+    #[serde(default)]
+    pub tls: Option<DicomTlsConfig>,
+    /// SOP Class 及传输语法接受策略, 未配置时使用内置的 SOP Class 列表
+    #[serde(default)]
+    pub acceptance: Option<ScpAcceptanceConfig>,
+    /// 关联并发限制及超时, 未配置时使用默认值
+    #[serde(default)]
+    pub limits: Option<AssociationLimitsConfig>,
+}
+
+/// 关联并发限制及超时配置
+#[derive(Debug, Deserialize, Clone)]
+pub struct AssociationLimitsConfig {
+    /// 全局最大并发关联数
+    pub max_associations: usize,
+    /// 每个 Calling AE 的最大并发关联数, 0 表示不限制
+    #[serde(default)]
+    pub max_associations_per_ae: usize,
+    /// 达到全局上限时排队等待的最长时间(秒), 0 表示立即拒绝
+    #[serde(default)]
+    pub queue_timeout_secs: u64,
+    /// ARTIM 超时(秒): 建立连接后等待 A-ASSOCIATE-RQ 的最长时间
+    pub artim_timeout_secs: u64,
+    /// 关联空闲超时(秒): 超过该时间未收到任何 PDU 则断开, 0 表示不限制
+    pub idle_timeout_secs: u64,
+}
+
+impl Default for AssociationLimitsConfig {
+    fn default() -> Self {
+        AssociationLimitsConfig {
+            max_associations: 64,
+            max_associations_per_ae: 0,
+            queue_timeout_secs: 10,
+            artim_timeout_secs: 30,
+            idle_timeout_secs: 300,
+        }
+    }
+}
+
+/// SCP 接受策略, 同时作用于同步和异步模式
+#[derive(Debug, Deserialize, Clone)]
+pub struct ScpAcceptanceConfig {
+    /// 接受 DICOM 标准中全部存储类 SOP Class
+    #[serde(default)]
+    pub include_all_storage: bool,
+    /// 接受的 SOP Class 及各自的传输语法
+    #[serde(default)]
+    pub sop_classes: Vec<SopClassPolicy>,
+    /// 全局传输语法优先顺序, 为空时接受所有已支持的传输语法
+    #[serde(default)]
+    pub transfer_syntaxes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SopClassPolicy {
+    pub uid: String,
+    /// 该 SOP Class 首选的传输语法(按优先顺序), 协商时一并提供, 不符合时只记录日志
+    #[serde(default)]
+    pub transfer_syntaxes: Vec<String>,
+}
+
+/// DICOM TLS 配置, 使用独立端口(通常为 2762)
+#[derive(Debug, Deserialize, Clone)]
+pub struct DicomTlsConfig {
+    pub port: u16,
+    /// 服务端证书(PEM, 可包含证书链)
+    pub cert_file: String,
+    /// 服务端私钥(PEM)
+    pub key_file: String,
+    /// CA 证书(PEM), 配置后要求客户端提供证书并进行校验
+    pub ca_file: Option<String>,
+    /// 客户端证书 Subject CN 与租户/AE 的映射
+    #[serde(default)]
+    pub subject_mappings: Vec<TlsSubjectMapping>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TlsSubjectMapping {
+    pub subject_cn: String,
+    pub tenant_id: String,
+    /// 配置后该证书只能以此 Calling AE 建立关联
+    pub ae_title: Option<String>,
+}
+
+/// AE 注册表项: 允许接入的 Calling AE 及其来源地址、SOP Class、租户约束
+#[derive(Debug, Deserialize, Clone)]
+pub struct AeRegistryEntry {
+    pub ae_title: String,
+    /// 允许的来源地址, 支持单个 IP 或 CIDR(如 192.168.1.0/24), 为空表示不限制
+    #[serde(default)]
+    pub ip_ranges: Vec<String>,
+    /// 允许存储的 SOP Class UID, 为空表示不限制
+    #[serde(default)]
+    pub sop_classes: Vec<String>,
+    pub tenant_id: String,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -73,6 +415,15 @@ pub struct MessageQueueConfig {
     pub topic_dicom_image: String,
     /// WADO-RS, STOW-RS Access Record
     pub topic_webapi_access: String,
+    /// 检查完成事件(MPPS COMPLETED), 未配置时不发送
+    #[serde(default)]
+    pub topic_study_complete: Option<String>,
+    /// 定期校验发现的文件损坏/丢失事件, 未配置时不发送
+    #[serde(default)]
+    pub topic_fixity: Option<String>,
+    /// 检查/序列/实例删除及恢复事件, 未配置时不发送
+    #[serde(default)]
+    pub topic_deletion: Option<String>,
 }
 
 // --- 配置结构 ---
@@ -105,6 +456,247 @@ pub struct WebWorkerConfig {
     pub memory_usage: u16,
 }
 
+fn default_fixity_interval() -> u64 {
+    3600
+}
+
+fn default_fixity_verify_after_days() -> u32 {
+    30
+}
+
+fn default_fixity_batch_size() -> i64 {
+    200
+}
+
+fn default_fixity_resource_usage() -> u16 {
+    50
+}
+
+/// 文件定期校验配置, 由 wado-webworker 执行
+#[derive(Debug, Deserialize, Clone)]
+pub struct FixityConfig {
+    /// 校验任务执行间隔(秒)
+    #[serde(default = "default_fixity_interval")]
+    pub interval_secs: u64,
+    /// 距上次校验超过该天数的文件重新校验
+    #[serde(default = "default_fixity_verify_after_days")]
+    pub verify_after_days: u32,
+    /// 每轮最多校验的文件数
+    #[serde(default = "default_fixity_batch_size")]
+    pub batch_size: i64,
+    /// CPU 使用率超过该值时暂停校验
+    #[serde(default = "default_fixity_resource_usage")]
+    pub cpu_usage: u16,
+    /// 内存使用率超过该值时暂停校验
+    #[serde(default = "default_fixity_resource_usage")]
+    pub memory_usage: u16,
+}
+
+fn default_gc_interval() -> u64 {
+    86400
+}
+
+fn default_gc_grace_hours() -> u64 {
+    24
+}
+
+fn default_gc_batch_size() -> i64 {
+    500
+}
+
+/// 对账宽限时间下限(小时)
+pub const MIN_GC_GRACE_HOURS: u64 = 1;
+
+/// 对账时对孤立文件(存储中有文件但没有索引)的处理方式
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum OrphanAction {
+    /// 只报告
+    #[default]
+    Report,
+    /// 重新发送到 topic_main 由 wado-consumer 建立索引
+    Reindex,
+    /// 移动到 _quarantine/ 目录
+    Quarantine,
+}
+
+/// 存储与索引对账配置, 由 wado-webworker 定期执行, 也可通过 wado-gc 手动执行
+#[derive(Debug, Deserialize, Clone)]
+pub struct GcConfig {
+    /// 对账任务执行间隔(秒)
+    #[serde(default = "default_gc_interval")]
+    pub interval_secs: u64,
+    /// 修改时间(文件)或创建时间(索引)在该时间内的不处理, 避免与正在进行的收图冲突
+    #[serde(default = "default_gc_grace_hours")]
+    pub grace_hours: u64,
+    #[serde(default)]
+    pub orphan_action: OrphanAction,
+    /// 是否将文件不存在的实例标记为 MISSING
+    #[serde(default)]
+    pub mark_dangling: bool,
+    /// 每批查询数据库的记录数
+    #[serde(default = "default_gc_batch_size")]
+    pub batch_size: i64,
+    /// 只报告, 不修改存储和数据库
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        GcConfig {
+            interval_secs: default_gc_interval(),
+            grace_hours: default_gc_grace_hours(),
+            orphan_action: OrphanAction::default(),
+            mark_dangling: false,
+            batch_size: default_gc_batch_size(),
+            dry_run: false,
+        }
+    }
+}
+
+fn default_purge_interval() -> u64 {
+    3600
+}
+
+/// 管理接口(DICOM 节点、文件校验、存储用量)配置, 未配置时这些接口返回 403
+#[derive(Debug, Deserialize, Clone)]
+pub struct AdminConfig {
+    /// 管理接口要求的权限, 替换 wado_oauth2.permissions 进行校验
+    pub permissions: RoleRule,
+    /// 令牌中调用方可管理的租户列表(JSONPath), 未配置时只能管理 x-tenant 请求头指定的租户
+    #[serde(default)]
+    pub tenant_claim: Option<String>,
+}
+
+/// 删除接口配置, 未配置时 DELETE 接口返回 403
+#[derive(Debug, Deserialize, Clone)]
+pub struct DeletionConfig {
+    /// 删除接口要求的权限, 替换 wado_oauth2.permissions 进行校验
+    pub permissions: RoleRule,
+    /// 回收站保留天数, 期间可以恢复; 为 0 时立即删除文件
+    #[serde(default)]
+    pub trash_days: u32,
+    /// wado-webworker 清除过期回收站的间隔(秒)
+    #[serde(default = "default_purge_interval")]
+    pub purge_interval_secs: u64,
+}
+
+/// 患者合并、检查移动和属性修改接口配置, 未配置时接口返回 403
+#[derive(Debug, Deserialize, Clone)]
+pub struct PatientUpdateConfig {
+    /// 修改接口要求的权限, 替换 wado_oauth2.permissions 进行校验
+    pub permissions: RoleRule,
+}
+
+fn default_rejection_batch_size() -> i64 {
+    100
+}
+
+/// IHE IOCM 拒绝说明处理. 拒绝说明总是会被识别并隐藏对应实例,
+/// 配置后 wado-webworker 定期清除因 Data Retention Policy Expired 被拒绝的实例
+#[derive(Debug, Deserialize, Clone)]
+pub struct RejectionConfig {
+    /// 清除间隔(秒)
+    #[serde(default = "default_purge_interval")]
+    pub purge_interval_secs: u64,
+    /// 每次最多清除的实例数
+    #[serde(default = "default_rejection_batch_size")]
+    pub batch_size: i64,
+}
+
+fn default_retention_interval() -> u64 {
+    86400
+}
+
+fn default_retention_batch_size() -> i64 {
+    100
+}
+
+/// 数据保留策略. 配置后 wado-webworker 定期查找保留期已过的检查作为待删除候选,
+/// 管理接口用于审核候选和设置法律保全(legal hold)
+#[derive(Debug, Deserialize, Clone)]
+pub struct RetentionConfig {
+    /// 管理接口要求的权限, 替换 wado_oauth2.permissions 进行校验
+    pub permissions: RoleRule,
+    /// 评估间隔(秒)
+    #[serde(default = "default_retention_interval")]
+    pub interval_secs: u64,
+    /// 每批查询的检查数, 同时是每次自动删除的最大检查数
+    #[serde(default = "default_retention_batch_size")]
+    pub batch_size: i64,
+    /// 是否直接通过删除流程删除候选检查, 否则等待审核
+    #[serde(default)]
+    pub auto_delete: bool,
+    #[serde(default)]
+    pub policies: Vec<RetentionPolicy>,
+}
+
+/// 保留策略: 检查日期后保留 retain_years 年.
+/// 配置 majority_age 时, 未成年患者的检查至少保留到患者年满 majority_age 岁后再过 retain_years 年.
+/// 一个检查匹配多个策略时使用保留期最长的策略
+#[derive(Debug, Deserialize, Clone)]
+pub struct RetentionPolicy {
+    pub name: String,
+    pub retain_years: u32,
+    #[serde(default)]
+    pub majority_age: Option<u32>,
+    /// 适用的租户, 为空表示所有租户
+    #[serde(default)]
+    pub tenant_ids: Vec<String>,
+    /// 检查包含其中任一设备类型时适用, 为空表示所有设备类型
+    #[serde(default)]
+    pub modalities: Vec<String>,
+    /// 检查包含其中任一 SOP Class 时适用, 为空表示所有 SOP Class
+    #[serde(default)]
+    pub sop_class_uids: Vec<String>,
+}
+
+fn default_quota_refresh_secs() -> u64 {
+    30
+}
+
+/// 租户存储配额, 单位 MB. 未配置的限制不检查
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct QuotaLimit {
+    /// 超过后仍然接收, 记录告警日志
+    #[serde(default)]
+    pub soft_limit_mb: Option<u64>,
+    /// 超过后拒绝 C-STORE / STOW-RS (Out of Resources)
+    #[serde(default)]
+    pub hard_limit_mb: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TenantQuotaConfig {
+    pub tenant_id: String,
+    #[serde(flatten)]
+    pub limit: QuotaLimit,
+}
+
+/// 存储配额配置, 由 wado-storescp 和 wado-server(STOW-RS) 执行
+#[derive(Debug, Deserialize, Clone)]
+pub struct QuotaConfig {
+    /// 从数据库刷新租户用量的间隔(秒)
+    #[serde(default = "default_quota_refresh_secs")]
+    pub refresh_secs: u64,
+    /// 未单独配置的租户使用该限制
+    #[serde(default)]
+    pub default: Option<QuotaLimit>,
+    #[serde(default)]
+    pub tenants: Vec<TenantQuotaConfig>,
+}
+
+impl QuotaConfig {
+    pub fn limit_for(&self, tenant_id: &str) -> Option<&QuotaLimit> {
+        self.tenants
+            .iter()
+            .find(|t| t.tenant_id == tenant_id)
+            .map(|t| &t.limit)
+            .or(self.default.as_ref())
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct AppConfig {
     pub redis: RedisConfig,
@@ -117,6 +709,144 @@ pub struct AppConfig {
     pub wado_oauth2: Option<OAuth2Config>,
     pub stow_oauth2: Option<OAuth2Config>,
     pub webworker: Option<WebWorkerConfig>,
+    /// 远程 DICOM 节点(转发目标、Q/R 源等)
+    #[serde(default)]
+    pub dicom_nodes: Vec<DicomNodeConfig>,
+    /// 自动路由转发, 未配置时 wado-forwarder 不启动
+    #[serde(default)]
+    pub forwarder: Option<ForwarderConfig>,
+    /// 历史检查预取, 未配置时 wado-prefetch 不启动
+    #[serde(default)]
+    pub prefetch: Option<PrefetchConfig>,
+    /// 远程节点 C-ECHO 健康检查, 未配置时使用默认值
+    #[serde(default)]
+    pub node_monitor: Option<NodeMonitorConfig>,
+    /// 分层存储迁移策略, 未配置时不迁移
+    #[serde(default)]
+    pub tiering: Option<TieringConfig>,
+    /// 文件定期校验, 未配置时不校验
+    #[serde(default)]
+    pub fixity: Option<FixityConfig>,
+    /// 租户存储配额, 未配置时不限制
+    #[serde(default)]
+    pub quota: Option<QuotaConfig>,
+    /// 存储与索引对账, 未配置时 wado-webworker 不执行
+    #[serde(default)]
+    pub gc: Option<GcConfig>,
+    /// 管理接口, 未配置时不开放
+    #[serde(default)]
+    pub admin: Option<AdminConfig>,
+    /// 检查/序列/实例删除, 未配置时不允许删除
+    #[serde(default)]
+    pub deletion: Option<DeletionConfig>,
+    /// 清除数据保留期已过的被拒绝实例, 未配置时不清除
+    #[serde(default)]
+    pub rejection: Option<RejectionConfig>,
+    /// 患者/检查属性修改, 未配置时不允许修改
+    #[serde(default)]
+    pub patient_update: Option<PatientUpdateConfig>,
+    /// 数据保留策略和法律保全, 未配置时不评估保留期
+    #[serde(default)]
+    pub retention: Option<RetentionConfig>,
+}
+
+/// 远程节点健康检查配置
+#[derive(Debug, Deserialize, Clone)]
+pub struct NodeMonitorConfig {
+    /// C-ECHO 间隔(秒)
+    pub interval_secs: u64,
+    /// 作为 SCU 时使用的 AE, 未配置时使用 dicom_store_scp.ae_title
+    #[serde(default)]
+    pub calling_ae_title: Option<String>,
+}
+
+impl Default for NodeMonitorConfig {
+    fn default() -> Self {
+        NodeMonitorConfig {
+            interval_secs: 60,
+            calling_ae_title: None,
+        }
+    }
+}
+
+fn default_node_concurrency() -> usize {
+    2
+}
+
+/// 远程 DICOM 节点
+#[derive(Debug, Deserialize, Clone)]
+pub struct DicomNodeConfig {
+    /// 节点名称, 转发规则等通过名称引用
+    pub name: String,
+    pub ae_title: String,
+    pub host: String,
+    pub port: u16,
+    /// 向该节点发送时的最大并发关联数
+    #[serde(default = "default_node_concurrency")]
+    pub max_concurrency: usize,
+}
+
+/// 自动路由转发配置
+#[derive(Debug, Deserialize, Clone)]
+pub struct ForwarderConfig {
+    /// 转发任务查询/重试接口监听地址, 接口没有鉴权, 默认只监听本机
+    #[serde(default = "default_forwarder_http_host")]
+    pub http_host: String,
+    /// 转发任务查询/重试接口端口
+    pub http_port: u16,
+    /// 作为 SCU 时使用的 AE, 未配置时使用 dicom_store_scp.ae_title
+    #[serde(default)]
+    pub calling_ae_title: Option<String>,
+    /// 最大重试次数, 超过后任务标记为失败
+    pub max_retries: i32,
+    /// 重试间隔(秒), 每次失败后按 2 的指数递增
+    pub retry_interval_secs: u64,
+    #[serde(default)]
+    pub rules: Vec<ForwardRule>,
+}
+
+/// 历史检查预取配置: 收到新检查后从 Q/R 源查询同一患者的历史检查并 C-MOVE 到本地 SCP
+#[derive(Debug, Deserialize, Clone)]
+pub struct PrefetchConfig {
+    /// Q/R 源节点名称, 对应 dicom_nodes 中的 name
+    pub sources: Vec<String>,
+    /// 作为 SCU 时使用的 AE, 未配置时使用 dicom_store_scp.ae_title
+    #[serde(default)]
+    pub calling_ae_title: Option<String>,
+    /// C-MOVE 目标 AE, 未配置时使用 dicom_store_scp.ae_title
+    #[serde(default)]
+    pub move_destination_ae: Option<String>,
+    /// 只预取包含这些检查类型的历史检查, 为空表示不限制
+    #[serde(default)]
+    pub modalities: Vec<String>,
+    /// 只预取包含这些检查部位的历史检查, 为空表示不限制
+    #[serde(default)]
+    pub body_parts: Vec<String>,
+    /// 只预取最近 N 年内的历史检查
+    pub years_back: u32,
+    /// 每个患者最多预取的历史检查数(按检查日期倒序)
+    pub max_priors: usize,
+}
+
+/// 转发规则, 各匹配条件为空表示不限制, 所有条件同时满足时转发到 destinations
+#[derive(Debug, Deserialize, Clone)]
+pub struct ForwardRule {
+    pub name: String,
+    /// 目标节点名称, 对应 dicom_nodes 中的 name
+    pub destinations: Vec<String>,
+    #[serde(default)]
+    pub tenant_ids: Vec<String>,
+    #[serde(default)]
+    pub source_aes: Vec<String>,
+    #[serde(default)]
+    pub modalities: Vec<String>,
+    #[serde(default)]
+    pub sop_classes: Vec<String>,
+    #[serde(default)]
+    pub body_parts: Vec<String>,
+    /// 匹配 StudyDescription 或 SeriesDescription 的正则表达式
+    #[serde(default)]
+    pub description_regex: Option<String>,
 }
 
 static APP_ENV: &str = "APP_ENV";
@@ -226,6 +956,27 @@ fn load_config_internal() -> Result<AppConfig, ConfigError> {
     app_config.local_storage.dicm_store_path = expand_path(&app_config.local_storage.dicm_store_path)?;
     app_config.local_storage.json_store_path = expand_path(&app_config.local_storage.json_store_path)?;
 
+    if app_config.main_database.dbtype.eq_ignore_ascii_case("sqlite") {
+        let path = match &app_config.main_database.path {
+            Some(path) if !path.trim().is_empty() => expand_path(path)?,
+            _ => {
+                return Err(ConfigError::Message(
+                    "main_database.path must be configured when main_database.dbtype is sqlite"
+                        .to_string(),
+                ));
+            }
+        };
+        app_config.main_database.path = Some(path);
+    }
+
+    let pool = &app_config.main_database.pool;
+    if pool.max_size == 0 || pool.min_size > pool.max_size {
+        return Err(ConfigError::Message(
+            "main_database.pool.max_size must be greater than 0 and not less than min_size"
+                .to_string(),
+        ));
+    }
+
     // 验证本地存储路径
     validate_and_create_path(&app_config.local_storage.dicm_store_path, "dicm_store_path")?;
 
@@ -239,6 +990,132 @@ fn load_config_internal() -> Result<AppConfig, ConfigError> {
         app_config.local_storage.json_store_path.pop();
     }
 
+    if app_config.local_storage.storage_type == StorageType::S3
+        && app_config.local_storage.s3.is_none()
+    {
+        return Err(ConfigError::Message(
+            "local_storage.s3 must be configured when local_storage.type is S3".to_string(),
+        ));
+    }
+
+    if let Some(encryption) = &app_config.local_storage.encryption
+        && encryption.enabled
+    {
+        validate_and_create_path(&encryption.key_store_path, "key_store_path")?;
+        if encryption.chunk_size == 0 {
+            return Err(ConfigError::Message(
+                "local_storage.encryption.chunk_size must be greater than 0".to_string(),
+            ));
+        }
+    }
+
+    // 验证目录布局
+    let mut layout_versions = vec![LEGACY_LAYOUT_VERSION];
+    for layout in &app_config.local_storage.layouts {
+        if layout.version <= LEGACY_LAYOUT_VERSION || layout_versions.contains(&layout.version) {
+            return Err(ConfigError::Message(format!(
+                "storage layout version {} must be unique and greater than 0",
+                layout.version
+            )));
+        }
+        StorageLayout::parse(layout.version, &layout.template).map_err(|e| {
+            ConfigError::Message(format!("invalid storage layout {}: {}", layout.version, e))
+        })?;
+        layout_versions.push(layout.version);
+    }
+    if !layout_versions.contains(&app_config.local_storage.layout_version) {
+        return Err(ConfigError::Message(format!(
+            "local_storage.layout_version {} is not configured in layouts",
+            app_config.local_storage.layout_version
+        )));
+    }
+
+    if let Some(gc) = &app_config.gc
+        && (gc.grace_hours < MIN_GC_GRACE_HOURS || gc.batch_size <= 0)
+    {
+        return Err(ConfigError::Message(format!(
+            "gc.grace_hours must be at least {} and gc.batch_size greater than 0",
+            MIN_GC_GRACE_HOURS
+        )));
+    }
+
+    if let Some(admin) = &app_config.admin
+        && admin.permissions.required_values.is_empty()
+    {
+        return Err(ConfigError::Message(
+            "admin.permissions.values must not be empty".to_string(),
+        ));
+    }
+
+    if let Some(deletion) = &app_config.deletion
+        && deletion.permissions.required_values.is_empty()
+    {
+        return Err(ConfigError::Message(
+            "deletion.permissions.values must not be empty".to_string(),
+        ));
+    }
+
+    if let Some(patient_update) = &app_config.patient_update
+        && patient_update.permissions.required_values.is_empty()
+    {
+        return Err(ConfigError::Message(
+            "patient_update.permissions.values must not be empty".to_string(),
+        ));
+    }
+
+    if let Some(rejection) = &app_config.rejection
+        && rejection.batch_size <= 0
+    {
+        return Err(ConfigError::Message(
+            "rejection.batch_size must be greater than 0".to_string(),
+        ));
+    }
+
+    if let Some(retention) = &app_config.retention {
+        if retention.permissions.required_values.is_empty() || retention.batch_size <= 0 {
+            return Err(ConfigError::Message(
+                "retention.permissions.values must not be empty and retention.batch_size greater than 0"
+                    .to_string(),
+            ));
+        }
+        for policy in &retention.policies {
+            if policy.name.is_empty() || policy.retain_years == 0 {
+                return Err(ConfigError::Message(
+                    "retention policy must have a name and retain_years greater than 0"
+                        .to_string(),
+                ));
+            }
+        }
+    }
+
+    // 验证存储层及迁移策略
+    let mut tier_names = vec![HOT_TIER.to_string()];
+    for tier in &app_config.local_storage.tiers {
+        if tier_names.contains(&tier.name) {
+            return Err(ConfigError::Message(format!(
+                "duplicate storage tier name: {}",
+                tier.name
+            )));
+        }
+        tier_names.push(tier.name.clone());
+    }
+    if let Some(tiering) = &app_config.tiering {
+        for policy in &tiering.policies {
+            if !tier_names.contains(&policy.from_tier) || !tier_names.contains(&policy.to_tier) {
+                return Err(ConfigError::Message(format!(
+                    "tiering policy {} references unknown storage tier",
+                    policy.name
+                )));
+            }
+            if policy.from_tier == policy.to_tier {
+                return Err(ConfigError::Message(format!(
+                    "tiering policy {} has the same from_tier and to_tier",
+                    policy.name
+                )));
+            }
+        }
+    }
+
     // 验证传输语法
     if !TransferSyntaxRegistry
         .get(&app_config.dicom_store_scp.unsupported_ts_change_to)
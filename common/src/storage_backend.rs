@@ -0,0 +1,914 @@
+//! 存储后端: 统一访问 DICOM 文件及 JSON 元数据, 支持本地磁盘和 S3 兼容对象存储
+//!
+//! 对象通过相对 key 访问(如 `tenant/20240101/study/series/sop.dcm`), 由后端决定实际位置.
+//! 为兼容历史数据, 磁盘后端也接受位于存储根目录下的绝对路径, 其他绝对路径一律拒绝.
+//! 磁盘后端先写入同目录下的临时文件, fsync 后再重命名, 进程崩溃时不会留下不完整的文件.
+//! 启用 local_storage.encryption 时, 各后端由 storage_crypto::EncryptedBackend 包装.
+
+use crate::server_config::{
+    self, EncryptionConfig, LocalStorageConfig, S3StorageConfig, StorageTierConfig, StorageType,
+};
+use crate::storage_crypto::wrap_encryption;
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path as ObjectPath;
+use object_store::{ObjectStore, PutPayload};
+use std::io::{SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, SystemTime};
+use tempfile::NamedTempFile;
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+#[derive(Error, Debug)]
+pub enum StorageError {
+    #[error("Object not found: {0}")]
+    NotFound(String),
+
+    #[error("Invalid storage key: {0}")]
+    InvalidKey(String),
+
+    #[error("Storage IO error: {0}")]
+    Io(String),
+
+    #[error("Object storage error: {0}")]
+    ObjectStore(String),
+
+    #[error("Storage config error: {0}")]
+    Config(String),
+}
+
+pub type ByteStream = BoxStream<'static, Result<Bytes, StorageError>>;
+
+/// 需要按文件路径处理对象时(转码、dicom-object 解析等)使用.
+/// 磁盘后端直接返回文件路径, 对象存储下载到临时文件, 离开作用域时删除.
+pub enum LocalFile {
+    Path(PathBuf),
+    Temp(NamedTempFile),
+}
+
+impl LocalFile {
+    pub fn path(&self) -> &Path {
+        match self {
+            LocalFile::Path(path) => path.as_path(),
+            LocalFile::Temp(file) => file.path(),
+        }
+    }
+}
+
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), StorageError>;
+
+    /// 将本地文件保存为 key, 本地文件由调用方负责清理
+    async fn put_file(&self, key: &str, local: &Path) -> Result<(), StorageError>;
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError>;
+
+    /// 读取 [start, end) 范围内的数据, 超出对象长度的部分被截断
+    async fn get_range(&self, key: &str, range: Range<u64>) -> Result<Vec<u8>, StorageError>;
+
+    async fn stream(&self, key: &str) -> Result<ByteStream, StorageError>;
+
+    /// 删除对象, 对象不存在时不报错
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+
+    /// 返回 prefix 目录下所有对象的 key (递归), prefix 为空时列出全部对象
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, StorageError>;
+
+    /// 按 key 顺序返回 prefix 目录下 start_after 之后的最多 limit 个对象, 用于分批遍历大量对象
+    async fn list_page(
+        &self,
+        prefix: &str,
+        start_after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>, StorageError> {
+        let mut keys = self.list(prefix).await?;
+        if let Some(start_after) = start_after {
+            keys.retain(|key| key.as_str() > start_after);
+        }
+        keys.truncate(limit);
+        Ok(keys)
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, StorageError>;
+
+    /// 对象最后修改时间
+    async fn modified(&self, key: &str) -> Result<SystemTime, StorageError>;
+
+    async fn local_file(&self, key: &str) -> Result<LocalFile, StorageError>;
+
+    /// 清理写入中断遗留的临时文件, 返回删除的数量. 对象存储的上传是原子的, 无需清理
+    async fn cleanup_temp_files(&self) -> Result<usize, StorageError> {
+        Ok(0)
+    }
+}
+
+/// 临时文件命名为 `.{文件名}.{uuid}.partial`
+const TEMP_FILE_SUFFIX: &str = ".partial";
+/// 启动清理时只删除超过该时间的临时文件, 避免误删其他进程正在写入的文件
+const TEMP_FILE_MAX_AGE: Duration = Duration::from_secs(3600);
+
+fn temp_path_for(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(
+        ".{}.{}{}",
+        name,
+        uuid::Uuid::new_v4().simple(),
+        TEMP_FILE_SUFFIX
+    ))
+}
+
+fn is_temp_file(path: &Path) -> bool {
+    path.file_name()
+        .map(|name| name.to_string_lossy())
+        .is_some_and(|name| name.starts_with('.') && name.ends_with(TEMP_FILE_SUFFIX))
+}
+
+/// 重命名后同步目录, 保证新文件名在断电后仍然存在
+fn sync_parent_dir(path: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        std::fs::File::open(parent)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+fn commit_temp_file(temp: &Path, path: &Path) -> std::io::Result<()> {
+    std::fs::rename(temp, path)?;
+    sync_parent_dir(path)
+}
+
+/// 原子写入: 写入同目录下的临时文件并 fsync, 然后重命名为目标文件.
+/// 任何一步失败都会删除临时文件, 目标文件要么不存在要么是完整内容.
+pub fn write_file_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let temp = temp_path_for(path);
+    let result = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        })
+        .and_then(|_| commit_temp_file(&temp, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result
+}
+
+/// 原子复制, 要求与 write_file_atomic 相同
+pub fn copy_file_atomic(src: &Path, path: &Path) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let temp = temp_path_for(path);
+    let result = std::fs::copy(src, &temp)
+        .and_then(|_| std::fs::OpenOptions::new().write(true).open(&temp))
+        .and_then(|file| file.sync_all())
+        .and_then(|_| commit_temp_file(&temp, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result
+}
+
+/// 删除 root 下修改时间早于 max_age 的临时文件
+fn remove_stale_temp_files(root: &Path, max_age: Duration) -> std::io::Result<usize> {
+    let mut removed = 0;
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return Ok(0);
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            removed += remove_stale_temp_files(&path, max_age)?;
+        } else if is_temp_file(&path) {
+            let stale = std::fs::metadata(&path)
+                .and_then(|meta| meta.modified())
+                .ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                .is_some_and(|age| age >= max_age);
+            if stale && std::fs::remove_file(&path).is_ok() {
+                removed += 1;
+            }
+        }
+    }
+    Ok(removed)
+}
+
+/// 只接受相对 key, 拒绝绝对路径和包含 `..` 的 key, 避免访问存储根目录之外的位置
+fn validate_key(key: &str) -> Result<(), StorageError> {
+    if key.is_empty()
+        || key.starts_with('/')
+        || Path::new(key)
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(StorageError::InvalidKey(key.to_string()));
+    }
+    Ok(())
+}
+
+fn io_error(key: &str, e: std::io::Error) -> StorageError {
+    if e.kind() == std::io::ErrorKind::NotFound {
+        StorageError::NotFound(key.to_string())
+    } else {
+        StorageError::Io(format!("{}: {}", key, e))
+    }
+}
+
+pub struct LocalDiskBackend {
+    root: PathBuf,
+}
+
+impl LocalDiskBackend {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        LocalDiskBackend { root: root.into() }
+    }
+
+    fn resolve(&self, key: &str) -> Result<PathBuf, StorageError> {
+        let path = Path::new(key);
+        // 历史数据记录的是根目录下的绝对路径, 先转换为相对 key 再校验
+        let relative = if path.is_absolute() {
+            path.strip_prefix(&self.root)
+                .ok()
+                .and_then(|relative| relative.to_str())
+                .ok_or_else(|| StorageError::InvalidKey(key.to_string()))?
+        } else {
+            key
+        };
+        validate_key(relative)?;
+        Ok(self.root.join(relative))
+    }
+
+    fn walk(&self, dir: &Path, keys: &mut Vec<String>) -> std::io::Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                self.walk(&path, keys)?;
+            } else if is_temp_file(&path) {
+                continue;
+            } else if let Ok(relative) = path.strip_prefix(&self.root) {
+                keys.push(relative.to_string_lossy().replace('\\', "/"));
+            }
+        }
+        Ok(())
+    }
+
+    /// 按 key 顺序遍历, 跳过 start_after 及之前的对象, 收集到 limit 个为止.
+    /// 目录按 `{路径}/` 参与排序, 与目录下完整 key 的字符串顺序一致
+    fn walk_page(
+        &self,
+        dir: &Path,
+        start_after: Option<&str>,
+        limit: usize,
+        keys: &mut Vec<String>,
+    ) -> std::io::Result<()> {
+        let mut entries = vec![];
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let Ok(relative) = path.strip_prefix(&self.root) else {
+                continue;
+            };
+            let mut key = relative.to_string_lossy().replace('\\', "/");
+            let is_dir = path.is_dir();
+            if is_dir {
+                key.push('/');
+            } else if is_temp_file(&path) {
+                continue;
+            }
+            entries.push((key, path, is_dir));
+        }
+        entries.sort();
+        for (key, path, is_dir) in entries {
+            if keys.len() >= limit {
+                break;
+            }
+            match start_after {
+                // 目录下的对象都在 start_after 之前
+                Some(after) if is_dir && key.as_str() < after && !after.starts_with(&key) => {
+                    continue;
+                }
+                Some(after) if !is_dir && key.as_str() <= after => continue,
+                _ => {}
+            }
+            if is_dir {
+                self.walk_page(&path, start_after, limit, keys)?;
+            } else {
+                keys.push(key);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalDiskBackend {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), StorageError> {
+        let path = self.resolve(key)?;
+        tokio::task::spawn_blocking(move || write_file_atomic(&path, &data))
+            .await
+            .map_err(|e| StorageError::Io(format!("{}: {}", key, e)))?
+            .map_err(|e| io_error(key, e))
+    }
+
+    async fn put_file(&self, key: &str, local: &Path) -> Result<(), StorageError> {
+        let path = self.resolve(key)?;
+        if path == local {
+            return Ok(());
+        }
+        let local = local.to_path_buf();
+        tokio::task::spawn_blocking(move || copy_file_atomic(&local, &path))
+            .await
+            .map_err(|e| StorageError::Io(format!("{}: {}", key, e)))?
+            .map_err(|e| io_error(key, e))
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        let path = self.resolve(key)?;
+        tokio::fs::read(&path).await.map_err(|e| io_error(key, e))
+    }
+
+    async fn get_range(&self, key: &str, range: Range<u64>) -> Result<Vec<u8>, StorageError> {
+        let path = self.resolve(key)?;
+        let mut file = tokio::fs::File::open(&path)
+            .await
+            .map_err(|e| io_error(key, e))?;
+        file.seek(SeekFrom::Start(range.start))
+            .await
+            .map_err(|e| io_error(key, e))?;
+        let mut data = Vec::new();
+        file.take(range.end.saturating_sub(range.start))
+            .read_to_end(&mut data)
+            .await
+            .map_err(|e| io_error(key, e))?;
+        Ok(data)
+    }
+
+    async fn stream(&self, key: &str) -> Result<ByteStream, StorageError> {
+        let path = self.resolve(key)?;
+        let file = tokio::fs::File::open(&path)
+            .await
+            .map_err(|e| io_error(key, e))?;
+        let key = key.to_string();
+        Ok(ReaderStream::new(file)
+            .map_err(move |e| io_error(&key, e))
+            .boxed())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        let path = self.resolve(key)?;
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(io_error(key, e)),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+        let dir = if prefix.is_empty() {
+            self.root.clone()
+        } else {
+            self.resolve(prefix)?
+        };
+        if !dir.is_dir() {
+            return Ok(vec![]);
+        }
+        let mut keys = vec![];
+        self.walk(&dir, &mut keys)
+            .map_err(|e| io_error(prefix, e))?;
+        keys.sort();
+        Ok(keys)
+    }
+
+    async fn list_page(
+        &self,
+        prefix: &str,
+        start_after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>, StorageError> {
+        let dir = if prefix.is_empty() {
+            self.root.clone()
+        } else {
+            self.resolve(prefix)?
+        };
+        if !dir.is_dir() {
+            return Ok(vec![]);
+        }
+        let mut keys = vec![];
+        self.walk_page(&dir, start_after, limit, &mut keys)
+            .map_err(|e| io_error(prefix, e))?;
+        Ok(keys)
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, StorageError> {
+        let path = self.resolve(key)?;
+        tokio::fs::try_exists(&path)
+            .await
+            .map_err(|e| io_error(key, e))
+    }
+
+    async fn modified(&self, key: &str) -> Result<SystemTime, StorageError> {
+        let path = self.resolve(key)?;
+        tokio::fs::metadata(&path)
+            .await
+            .and_then(|metadata| metadata.modified())
+            .map_err(|e| io_error(key, e))
+    }
+
+    async fn local_file(&self, key: &str) -> Result<LocalFile, StorageError> {
+        let path = self.resolve(key)?;
+        if !tokio::fs::try_exists(&path)
+            .await
+            .map_err(|e| io_error(key, e))?
+        {
+            return Err(StorageError::NotFound(key.to_string()));
+        }
+        Ok(LocalFile::Path(path))
+    }
+
+    async fn cleanup_temp_files(&self) -> Result<usize, StorageError> {
+        let root = self.root.clone();
+        tokio::task::spawn_blocking(move || remove_stale_temp_files(&root, TEMP_FILE_MAX_AGE))
+            .await
+            .map_err(|e| StorageError::Io(e.to_string()))?
+            .map_err(|e| io_error(&self.root.to_string_lossy(), e))
+    }
+}
+
+fn object_store_error(key: &str, e: object_store::Error) -> StorageError {
+    match e {
+        object_store::Error::NotFound { .. } => StorageError::NotFound(key.to_string()),
+        e => StorageError::ObjectStore(format!("{}: {}", key, e)),
+    }
+}
+
+/// S3 兼容对象存储, key 保存为 `{prefix}/{key}`
+pub struct S3Backend {
+    store: Arc<dyn ObjectStore>,
+    prefix: String,
+}
+
+impl S3Backend {
+    pub fn new(config: &S3StorageConfig, prefix: &str) -> Result<Self, StorageError> {
+        let mut builder = AmazonS3Builder::new()
+            .with_region(&config.region)
+            .with_bucket_name(&config.bucket)
+            .with_access_key_id(&config.access_key_id)
+            .with_secret_access_key(&config.secret_access_key)
+            .with_allow_http(config.allow_http);
+        if let Some(endpoint) = &config.endpoint {
+            // MinIO 等自建服务使用 path-style 访问
+            builder = builder
+                .with_endpoint(endpoint)
+                .with_virtual_hosted_style_request(false);
+        }
+        let store = builder
+            .build()
+            .map_err(|e| StorageError::Config(e.to_string()))?;
+        Ok(S3Backend {
+            store: Arc::new(store),
+            prefix: prefix.trim_matches('/').to_string(),
+        })
+    }
+
+    fn object_path(&self, key: &str) -> Result<ObjectPath, StorageError> {
+        validate_key(key)?;
+        let key = key.trim_start_matches('/');
+        if self.prefix.is_empty() {
+            Ok(ObjectPath::from(key))
+        } else {
+            Ok(ObjectPath::from(format!("{}/{}", self.prefix, key)))
+        }
+    }
+
+    fn object_key(&self, location: &ObjectPath) -> String {
+        let location = location.as_ref();
+        if self.prefix.is_empty() {
+            location.to_string()
+        } else {
+            location
+                .strip_prefix(&format!("{}/", self.prefix))
+                .unwrap_or(location)
+                .to_string()
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for S3Backend {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), StorageError> {
+        let path = self.object_path(key)?;
+        self.store
+            .put(&path, PutPayload::from(data))
+            .await
+            .map(|_| ())
+            .map_err(|e| object_store_error(key, e))
+    }
+
+    async fn put_file(&self, key: &str, local: &Path) -> Result<(), StorageError> {
+        // DICOM 文件通常不大, 整体读入后上传
+        let data = tokio::fs::read(local)
+            .await
+            .map_err(|e| io_error(&local.to_string_lossy(), e))?;
+        self.put(key, data).await
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        let path = self.object_path(key)?;
+        let result = self
+            .store
+            .get(&path)
+            .await
+            .map_err(|e| object_store_error(key, e))?;
+        result
+            .bytes()
+            .await
+            .map(|data| data.to_vec())
+            .map_err(|e| object_store_error(key, e))
+    }
+
+    async fn get_range(&self, key: &str, range: Range<u64>) -> Result<Vec<u8>, StorageError> {
+        let path = self.object_path(key)?;
+        let meta = self
+            .store
+            .head(&path)
+            .await
+            .map_err(|e| object_store_error(key, e))?;
+        let range = range.start.min(meta.size)..range.end.min(meta.size);
+        if range.is_empty() {
+            return Ok(vec![]);
+        }
+        self.store
+            .get_range(&path, range)
+            .await
+            .map(|data| data.to_vec())
+            .map_err(|e| object_store_error(key, e))
+    }
+
+    async fn stream(&self, key: &str) -> Result<ByteStream, StorageError> {
+        let path = self.object_path(key)?;
+        let result = self
+            .store
+            .get(&path)
+            .await
+            .map_err(|e| object_store_error(key, e))?;
+        let key = key.to_string();
+        Ok(result
+            .into_stream()
+            .map_err(move |e| object_store_error(&key, e))
+            .boxed())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        let path = self.object_path(key)?;
+        match self.store.delete(&path).await {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(e) => Err(object_store_error(key, e)),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+        let path = if prefix.is_empty() {
+            (!self.prefix.is_empty()).then(|| ObjectPath::from(self.prefix.as_str()))
+        } else {
+            Some(self.object_path(prefix)?)
+        };
+        let mut keys: Vec<String> = self
+            .store
+            .list(path.as_ref())
+            .map_ok(|meta| self.object_key(&meta.location))
+            .try_collect()
+            .await
+            .map_err(|e| object_store_error(prefix, e))?;
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// S3 按 key 的字典序返回对象
+    async fn list_page(
+        &self,
+        prefix: &str,
+        start_after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>, StorageError> {
+        let path = if prefix.is_empty() {
+            (!self.prefix.is_empty()).then(|| ObjectPath::from(self.prefix.as_str()))
+        } else {
+            Some(self.object_path(prefix)?)
+        };
+        let objects = match start_after {
+            Some(start_after) => self
+                .store
+                .list_with_offset(path.as_ref(), &self.object_path(start_after)?),
+            None => self.store.list(path.as_ref()),
+        };
+        let mut keys: Vec<String> = objects
+            .take(limit)
+            .map_ok(|meta| self.object_key(&meta.location))
+            .try_collect()
+            .await
+            .map_err(|e| object_store_error(prefix, e))?;
+        keys.sort();
+        Ok(keys)
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, StorageError> {
+        let path = self.object_path(key)?;
+        match self.store.head(&path).await {
+            Ok(_) => Ok(true),
+            Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(e) => Err(object_store_error(key, e)),
+        }
+    }
+
+    async fn modified(&self, key: &str) -> Result<SystemTime, StorageError> {
+        let path = self.object_path(key)?;
+        let meta = self
+            .store
+            .head(&path)
+            .await
+            .map_err(|e| object_store_error(key, e))?;
+        Ok(meta.last_modified.into())
+    }
+
+    async fn local_file(&self, key: &str) -> Result<LocalFile, StorageError> {
+        let data = self.get(key).await?;
+        let file = tempfile::Builder::new()
+            .suffix(".dcm")
+            .tempfile()
+            .map_err(|e| io_error(key, e))?;
+        tokio::fs::write(file.path(), data)
+            .await
+            .map_err(|e| io_error(key, e))?;
+        Ok(LocalFile::Temp(file))
+    }
+}
+
+/// DICOM 文件与 JSON 元数据分别使用独立的存储区域
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageArea {
+    Dicom,
+    Json,
+}
+
+pub fn make_backend(
+    config: &LocalStorageConfig,
+    area: StorageArea,
+) -> Result<Arc<dyn StorageBackend>, StorageError> {
+    let backend: Arc<dyn StorageBackend> = match config.storage_type {
+        StorageType::Disk => {
+            let root = match area {
+                StorageArea::Dicom => &config.dicm_store_path,
+                StorageArea::Json => &config.json_store_path,
+            };
+            Arc::new(LocalDiskBackend::new(root))
+        }
+        StorageType::S3 => {
+            let s3 = config.s3.as_ref().ok_or_else(|| {
+                StorageError::Config("local_storage.s3 is not configured".to_string())
+            })?;
+            let prefix = match area {
+                StorageArea::Dicom => &s3.dicom_prefix,
+                StorageArea::Json => &s3.json_prefix,
+            };
+            Arc::new(S3Backend::new(s3, prefix)?)
+        }
+    };
+    wrap_encryption(backend, &config.encryption)
+}
+
+/// 附加存储层只存放 DICOM 文件
+pub fn make_tier_backend(
+    config: &StorageTierConfig,
+    encryption: &Option<EncryptionConfig>,
+) -> Result<Arc<dyn StorageBackend>, StorageError> {
+    let backend: Arc<dyn StorageBackend> = match config.storage_type {
+        StorageType::Disk => {
+            let root = config.path.as_ref().ok_or_else(|| {
+                StorageError::Config(format!("storage tier {} has no path", config.name))
+            })?;
+            Arc::new(LocalDiskBackend::new(root))
+        }
+        StorageType::S3 => {
+            let s3 = config.s3.as_ref().ok_or_else(|| {
+                StorageError::Config(format!("storage tier {} has no s3 config", config.name))
+            })?;
+            Arc::new(S3Backend::new(s3, &s3.dicom_prefix)?)
+        }
+    };
+    wrap_encryption(backend, encryption)
+}
+
+static DICOM_BACKEND: LazyLock<Arc<dyn StorageBackend>> = LazyLock::new(|| {
+    // load_config 是INIT_ONCE 封装的, 不会重新加载
+    let config = server_config::load_config().unwrap();
+    make_backend(&config.local_storage, StorageArea::Dicom)
+        .expect("failed to create DICOM storage backend")
+});
+
+static JSON_BACKEND: LazyLock<Arc<dyn StorageBackend>> = LazyLock::new(|| {
+    let config = server_config::load_config().unwrap();
+    make_backend(&config.local_storage, StorageArea::Json)
+        .expect("failed to create JSON storage backend")
+});
+
+/// DICOM 文件存储后端
+pub fn dicom_backend() -> Arc<dyn StorageBackend> {
+    DICOM_BACKEND.clone()
+}
+
+/// JSON 元数据存储后端
+pub fn json_backend() -> Arc<dyn StorageBackend> {
+    JSON_BACKEND.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_key() {
+        assert!(validate_key("tenant/20240101/1.2.3/1.2.3.4/1.2.3.4.5.dcm").is_ok());
+        assert!(validate_key("/data/xdcm/tenant/1.dcm").is_err());
+        assert!(validate_key("tenant/../../etc/passwd").is_err());
+        assert!(validate_key("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_local_disk_backend() {
+        let root = tempfile::tempdir().unwrap();
+        let backend = LocalDiskBackend::new(root.path());
+
+        backend
+            .put("t1/20240101/st/se/1.dcm", b"0123456789".to_vec())
+            .await
+            .unwrap();
+        backend
+            .put("t1/20240101/st/se/2.dcm", b"abc".to_vec())
+            .await
+            .unwrap();
+        assert!(backend.exists("t1/20240101/st/se/1.dcm").await.unwrap());
+        assert!(!backend.exists("t1/20240101/st/se/3.dcm").await.unwrap());
+
+        assert_eq!(
+            backend.get_range("t1/20240101/st/se/1.dcm", 2..5).await.unwrap(),
+            b"234"
+        );
+        assert_eq!(
+            backend.get_range("t1/20240101/st/se/1.dcm", 8..100).await.unwrap(),
+            b"89"
+        );
+
+        let streamed: Vec<Bytes> = backend
+            .stream("t1/20240101/st/se/2.dcm")
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(streamed.concat(), b"abc");
+
+        assert_eq!(
+            backend.list("t1/20240101/st").await.unwrap(),
+            vec!["t1/20240101/st/se/1.dcm", "t1/20240101/st/se/2.dcm"]
+        );
+        assert!(backend.list("t2").await.unwrap().is_empty());
+
+        // 根目录下的绝对路径兼容历史数据, 根目录外的绝对路径被拒绝
+        let absolute = root.path().join("t1/20240101/st/se/2.dcm");
+        assert_eq!(
+            backend.get(absolute.to_str().unwrap()).await.unwrap(),
+            b"abc"
+        );
+        assert!(matches!(
+            backend.get("/etc/passwd").await,
+            Err(StorageError::InvalidKey(_))
+        ));
+        let escaped = format!("{}/../outside.dcm", root.path().display());
+        assert!(matches!(
+            backend.put(&escaped, b"x".to_vec()).await,
+            Err(StorageError::InvalidKey(_))
+        ));
+
+        backend.delete("t1/20240101/st/se/1.dcm").await.unwrap();
+        backend.delete("t1/20240101/st/se/1.dcm").await.unwrap();
+        assert!(matches!(
+            backend.get("t1/20240101/st/se/1.dcm").await,
+            Err(StorageError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_local_list_page() {
+        let root = tempfile::tempdir().unwrap();
+        let backend = LocalDiskBackend::new(root.path());
+        for key in ["t1/a/1.dcm", "t1/a/2.dcm", "t1/a.x", "t1/b/1.dcm", "t2/1.dcm"] {
+            backend.put(key, b"x".to_vec()).await.unwrap();
+        }
+        let all = backend.list("").await.unwrap();
+
+        let mut paged = vec![];
+        let mut start_after: Option<String> = None;
+        loop {
+            let page = backend
+                .list_page("", start_after.as_deref(), 2)
+                .await
+                .unwrap();
+            paged.extend(page.iter().cloned());
+            if page.len() < 2 {
+                break;
+            }
+            start_after = page.last().cloned();
+        }
+        assert_eq!(paged, all);
+        assert_eq!(
+            backend.list_page("t1", Some("t1/a/2.dcm"), 10).await.unwrap(),
+            vec!["t1/b/1.dcm"]
+        );
+        // 跳过整个目录
+        assert_eq!(
+            backend.list_page("", Some("t1/a0"), 10).await.unwrap(),
+            vec!["t1/b/1.dcm", "t2/1.dcm"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_atomic_write_and_cleanup() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("t1/st/se/1.dcm");
+        write_file_atomic(&path, b"first").unwrap();
+        write_file_atomic(&path, b"second").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"second");
+
+        // 模拟写入中断遗留的临时文件
+        let temp = temp_path_for(&path);
+        std::fs::write(&temp, b"trunc").unwrap();
+        assert!(is_temp_file(&temp));
+        assert!(!is_temp_file(&path));
+
+        let backend = LocalDiskBackend::new(root.path());
+        assert_eq!(backend.list("t1").await.unwrap(), vec!["t1/st/se/1.dcm"]);
+        // 新的临时文件可能属于其他正在写入的进程, 不清理
+        assert_eq!(backend.cleanup_temp_files().await.unwrap(), 0);
+        assert_eq!(remove_stale_temp_files(root.path(), Duration::ZERO).unwrap(), 1);
+        assert!(!temp.exists());
+        assert!(path.exists());
+    }
+
+    /// 需要本地 MinIO, 例如:
+    /// docker run -p 9000:9000 -e MINIO_ROOT_USER=minioadmin -e MINIO_ROOT_PASSWORD=minioadmin minio/minio server /data
+    /// 创建 bucket 后执行: MINIO_TEST_BUCKET=dicom cargo test -p common -- --ignored test_s3_backend
+    #[tokio::test]
+    #[ignore]
+    async fn test_s3_backend() {
+        let bucket = std::env::var("MINIO_TEST_BUCKET").unwrap_or_else(|_| "dicom".to_string());
+        let config = S3StorageConfig {
+            endpoint: Some(
+                std::env::var("MINIO_TEST_ENDPOINT")
+                    .unwrap_or_else(|_| "http://127.0.0.1:9000".to_string()),
+            ),
+            region: "us-east-1".to_string(),
+            bucket,
+            access_key_id: "minioadmin".to_string(),
+            secret_access_key: "minioadmin".to_string(),
+            allow_http: true,
+            dicom_prefix: "dicom-test".to_string(),
+            json_prefix: "json-test".to_string(),
+        };
+        let backend = S3Backend::new(&config, &config.dicom_prefix).unwrap();
+        backend
+            .put("t1/st/se/1.dcm", b"0123456789".to_vec())
+            .await
+            .unwrap();
+        assert!(backend.exists("t1/st/se/1.dcm").await.unwrap());
+        assert_eq!(
+            backend.get_range("t1/st/se/1.dcm", 2..5).await.unwrap(),
+            b"234"
+        );
+        assert_eq!(
+            backend.list("t1/st").await.unwrap(),
+            vec!["t1/st/se/1.dcm"]
+        );
+        let local = backend.local_file("t1/st/se/1.dcm").await.unwrap();
+        assert_eq!(std::fs::read(local.path()).unwrap(), b"0123456789");
+        backend.delete("t1/st/se/1.dcm").await.unwrap();
+        assert!(!backend.exists("t1/st/se/1.dcm").await.unwrap());
+    }
+}
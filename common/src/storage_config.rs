@@ -1,138 +1,142 @@
-use crate::server_config::AppConfig;
+use crate::server_config::{AppConfig, StorageType};
+use crate::storage_backend::StorageError;
+use crate::storage_layout::{LEGACY_LAYOUT_VERSION, LayoutInput, StorageLayout};
 use database::dicom_meta::DicomStateMeta;
 use seahash::SeaHasher;
 use std::hash::Hasher;
+/// 生成存储 key, 实际位置由 storage_backend 决定(本地磁盘或对象存储)
 pub struct StorageConfig<'a> {
     app_config: &'a AppConfig,
 }
 
+fn layout_input(study_info: &DicomStateMeta) -> LayoutInput<'_> {
+    LayoutInput {
+        tenant_id: study_info.tenant_id.as_str(),
+        study_date: study_info.study_date_origin.as_str(),
+        study_uid: study_info.study_uid.as_str(),
+        series_uid: study_info.series_uid.as_str(),
+    }
+}
+
 impl<'a> StorageConfig<'a> {
     pub fn make_storage_config(app_config: &'a AppConfig) -> Self {
         StorageConfig { app_config }
     }
-    pub fn dicom_study_dir(
-        &self,
-        study_info: &DicomStateMeta,
-        create_not_exists: bool,
-    ) -> Result<String, std::io::Error> {
-        let dicom_store_path = &self.app_config.local_storage.dicm_store_path;
-        let study_dir = format!(
-            "{}/{}/{}/{}",
-            dicom_store_path,
-            study_info.tenant_id.as_str(),
-            study_info.study_date_origin.as_str(),
-            study_info.study_uid.as_str()
-        );
-        if create_not_exists {
-            std::fs::create_dir_all(&study_dir).map_err(|e| {
-                std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("create dicom_study_dir failed: {} with:'{}'", study_dir, e),
-                )
-            })?;
-        }
-        Ok(study_dir)
+
+    pub fn storage_type(&self) -> StorageType {
+        self.app_config.local_storage.storage_type
     }
 
-    pub fn dicom_series_dir(
-        &self,
-        study_info: &DicomStateMeta,
-        create_when_not_exists: bool,
-    ) -> Result<String, std::io::Error> {
-        let dicom_store_path = &self.app_config.local_storage.dicm_store_path;
-        let study_dir = format!(
-            "{}/{}/{}/{}/{}",
-            dicom_store_path,
-            study_info.tenant_id.as_str(),
-            study_info.study_date_origin.as_str(),
-            study_info.study_uid.as_str(),
-            study_info.series_uid.as_str()
-        );
-        if create_when_not_exists {
-            std::fs::create_dir_all(&study_dir).map_err(|e| {
-                std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("create dicom_series_dir failed: {} with:'{}'", study_dir, e),
-                )
+    /// 新序列使用的目录布局版本
+    pub fn layout_version(&self) -> i32 {
+        self.app_config.local_storage.layout_version
+    }
+
+    pub fn layout(&self, version: i32) -> Result<StorageLayout, StorageError> {
+        if version == LEGACY_LAYOUT_VERSION {
+            return Ok(StorageLayout::legacy());
+        }
+        let layout = self
+            .app_config
+            .local_storage
+            .layouts
+            .iter()
+            .find(|layout| layout.version == version)
+            .ok_or_else(|| {
+                StorageError::Config(format!("storage layout {} is not configured", version))
             })?;
+        StorageLayout::parse(version, &layout.template).map_err(StorageError::Config)
+    }
+
+    /// 所有目录布局, 包括内置布局
+    pub fn all_layouts(&self) -> Result<Vec<StorageLayout>, StorageError> {
+        let mut layouts = vec![StorageLayout::legacy()];
+        for layout in &self.app_config.local_storage.layouts {
+            layouts.push(self.layout(layout.version)?);
         }
-        Ok(study_dir)
+        Ok(layouts)
+    }
+
+    /// DICOM 检查目录, 按序列记录的布局版本生成
+    pub fn dicom_study_key(&self, study_info: &DicomStateMeta) -> Result<String, StorageError> {
+        Ok(self
+            .layout(study_info.storage_layout)?
+            .study_key(&layout_input(study_info)))
+    }
+
+    /// DICOM 序列目录, 按序列记录的布局版本生成
+    pub fn dicom_series_key(&self, study_info: &DicomStateMeta) -> Result<String, StorageError> {
+        Ok(self
+            .layout(study_info.storage_layout)?
+            .series_key(&layout_input(study_info)))
     }
 
-    pub fn json_metadata_path_for_study(
+    /// DICOM 文件, 按序列记录的布局版本生成
+    pub fn dicom_file_key(
         &self,
         study_info: &DicomStateMeta,
-        create_when_not_exists: bool,
-    ) -> Result<String, std::io::Error> {
-        let json_store_path = &self.app_config.local_storage.json_store_path;
-        let study_dir = format!(
-            "{}/{}/metadata/{}",
-            json_store_path,
-            study_info.tenant_id.as_str(),
-            study_info.study_date_origin.as_str()
-        );
-        let json_path = format!("{}/{}.json", study_dir, study_info.study_uid.as_str());
-        if create_when_not_exists {
-            std::fs::create_dir_all(&study_dir).map_err(|e| {
-                std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("Failed to create directory:  '{}': {}", study_dir, e),
-                )
-            })?
-        }
-        Ok(json_path)
+        sop_uid: &str,
+    ) -> Result<String, StorageError> {
+        Ok(self
+            .layout(study_info.storage_layout)?
+            .file_key(&layout_input(study_info), sop_uid))
     }
 
-    pub fn json_metadata_path_for_series(
+    pub fn make_series_dicom_key(
         &self,
-        study_info: &DicomStateMeta,
-        create_when_not_exists: bool,
-    ) -> Result<String, std::io::Error> {
-        let json_store_path = &self.app_config.local_storage.json_store_path;
-        let study_dir = format!(
-            "{}/{}/metadata/{}/{}",
-            json_store_path,
-            study_info.tenant_id.as_str(),
-            study_info.study_date_origin.as_str(),
-            study_info.study_uid.as_str()
-        );
-        let json_path = format!("{}/{}.json", study_dir, study_info.series_uid.as_str());
-        if create_when_not_exists {
-            std::fs::create_dir_all(&study_dir).map_err(|e| {
-                std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("Failed to create directory:  '{}': {}", study_dir, e),
-                )
-            })?
-        }
-        Ok(json_path)
+        layout_version: i32,
+        tenant_id: &str,
+        study_date: &str,
+        study_uid: &str,
+        series_uid: &str,
+    ) -> Result<String, StorageError> {
+        let input = LayoutInput {
+            tenant_id,
+            study_date,
+            study_uid,
+            series_uid,
+        };
+        Ok(self.layout(layout_version)?.series_key(&input))
     }
 
-    pub fn make_series_dicom_dir(
+    /// 收图时生成 DICOM 文件 key, layout_version 见 storage_layout::series_layouts
+    pub fn make_dicom_file_key(
         &self,
+        layout_version: i32,
         tenant_id: &str,
         study_date: &str,
         study_uid: &str,
         series_uid: &str,
-        create_when_not_exists: bool,
-    ) -> Result<String, std::io::Error> {
-        let dicom_store_path = &self.app_config.local_storage.dicm_store_path;
-        let study_dir = format!(
-            "{}/{}/{}/{}/{}",
-            dicom_store_path, tenant_id, study_date, study_uid, series_uid
-        );
-        if create_when_not_exists {
-            std::fs::create_dir_all(&study_dir).map_err(|e| {
-                std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!(
-                        "create make_series_dicom_dir failed: {} with:'{}'",
-                        study_dir, e
-                    ),
-                )
-            })?;
-        }
-        Ok(study_dir)
+        sop_uid: &str,
+    ) -> Result<String, StorageError> {
+        let input = LayoutInput {
+            tenant_id,
+            study_date,
+            study_uid,
+            series_uid,
+        };
+        Ok(self.layout(layout_version)?.file_key(&input, sop_uid))
+    }
+
+    /// 检查级 JSON 元数据: tenant/metadata/study_date/study_uid.json
+    pub fn json_metadata_key_for_study(&self, study_info: &DicomStateMeta) -> String {
+        format!(
+            "{}/metadata/{}/{}.json",
+            study_info.tenant_id.as_str(),
+            study_info.study_date_origin.as_str(),
+            study_info.study_uid.as_str()
+        )
+    }
+
+    /// 序列级 JSON 元数据: tenant/metadata/study_date/study_uid/series_uid.json
+    pub fn json_metadata_key_for_series(&self, study_info: &DicomStateMeta) -> String {
+        format!(
+            "{}/metadata/{}/{}/{}.json",
+            study_info.tenant_id.as_str(),
+            study_info.study_date_origin.as_str(),
+            study_info.study_uid.as_str(),
+            study_info.series_uid.as_str()
+        )
     }
 }
 
@@ -144,7 +148,3 @@ pub fn hash_uid(uid: &str) -> String {
     // 将 u64 转换为字符串，并用前导零填充到 20 位
     format!("{:020}", hash_value)
 }
-
-pub fn dicom_file_path(dir: &str, sop_uid: &str) -> String {
-    format!("{}/{}.dcm", dir, sop_uid)
-}
@@ -0,0 +1,871 @@
+//! 静态加密: 每个租户使用独立的数据密钥, 数据密钥由主密钥以 AES-256-GCM 包装后保存在 key_store_path.
+//!
+//! 文件格式: `DXE2 | 租户长度(1) | 租户 | salt(32) | 分段长度(4, BE) | 密文分段...`.
+//! 每个文件使用 HKDF-SHA256(数据密钥, salt) 派生的独立密钥, 避免长期使用的数据密钥下随机 nonce 重复.
+//! 明文按 chunk_size 分段加密, 每段 nonce 为 `0(7) | 序号(4, BE) | 末段标记(1)`, 文件头作为附加数据,
+//! 分段被截断、重排或篡改时解密失败. 不带文件头的对象按明文读取, 兼容启用加密前保存的数据.
+//! 旧格式 `DXE1` (数据密钥直接加密, 文件头保存 7 字节随机 nonce 前缀) 仍可读取, 不再写入.
+
+use crate::server_config::EncryptionConfig;
+use crate::storage_backend::{ByteStream, LocalFile, StorageBackend, StorageError};
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use async_trait::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose;
+use bytes::Bytes;
+use futures::StreamExt;
+use hkdf::Hkdf;
+use rand::RngCore;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+const MAGIC: &[u8; 4] = b"DXE2";
+/// 旧格式, 只读
+const LEGACY_MAGIC: &[u8; 4] = b"DXE1";
+const NONCE_PREFIX_LEN: usize = 7;
+const SALT_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+const MAX_HEADER_LEN: usize = MAGIC.len() + 1 + u8::MAX as usize + SALT_LEN + 4;
+const FILE_KEY_INFO: &[u8] = b"dicom-storage-file-key";
+/// 绝对路径 key (历史数据) 无法确定租户, 使用该数据密钥
+pub const DEFAULT_KEY_TENANT: &str = "_default";
+
+pub type DataKey = [u8; 32];
+
+fn crypto_error(msg: impl Into<String>) -> StorageError {
+    StorageError::Io(format!("encryption: {}", msg.into()))
+}
+
+/// 读取主密钥(base64 编码的 32 字节): 优先 master_key_file, 否则读取环境变量 master_key_env
+pub fn load_master_key(config: &EncryptionConfig) -> Result<DataKey, StorageError> {
+    let encoded = match &config.master_key_file {
+        Some(file) => std::fs::read_to_string(file)
+            .map_err(|e| StorageError::Config(format!("read master key {}: {}", file, e)))?,
+        None => std::env::var(&config.master_key_env).map_err(|_| {
+            StorageError::Config(format!(
+                "master key environment variable {} is not set",
+                config.master_key_env
+            ))
+        })?,
+    };
+    decode_master_key(&encoded)
+}
+
+pub fn decode_master_key(encoded: &str) -> Result<DataKey, StorageError> {
+    let raw = general_purpose::STANDARD
+        .decode(encoded.trim())
+        .map_err(|e| StorageError::Config(format!("invalid master key: {}", e)))?;
+    raw.try_into()
+        .map_err(|_| StorageError::Config("master key must be 32 bytes".to_string()))
+}
+
+/// 生成随机主密钥, 返回 base64 编码
+pub fn generate_master_key() -> String {
+    general_purpose::STANDARD.encode(random_key())
+}
+
+fn random_key() -> DataKey {
+    let mut key = [0u8; 32];
+    OsRng.fill_bytes(&mut key);
+    key
+}
+
+/// key 的第一级目录为租户
+pub fn tenant_of_key(key: &str) -> &str {
+    if Path::new(key).is_absolute() {
+        return DEFAULT_KEY_TENANT;
+    }
+    key.split('/').find(|s| !s.is_empty()).unwrap_or(DEFAULT_KEY_TENANT)
+}
+
+/// 包装后的数据密钥, 保存为 key_store_path/{tenant}.json
+#[derive(Serialize, Deserialize)]
+struct WrappedKey {
+    tenant_id: String,
+    nonce: String,
+    wrapped_key: String,
+}
+
+impl WrappedKey {
+    fn wrap(master: &DataKey, tenant_id: &str, key: &DataKey) -> Result<Self, StorageError> {
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut nonce);
+        let wrapped = Aes256Gcm::new(&(*master).into())
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: key,
+                    aad: tenant_id.as_bytes(),
+                },
+            )
+            .map_err(|_| crypto_error("failed to wrap data key"))?;
+        Ok(WrappedKey {
+            tenant_id: tenant_id.to_string(),
+            nonce: general_purpose::STANDARD.encode(nonce),
+            wrapped_key: general_purpose::STANDARD.encode(wrapped),
+        })
+    }
+
+    fn unwrap(&self, master: &DataKey) -> Result<DataKey, StorageError> {
+        let nonce = general_purpose::STANDARD
+            .decode(&self.nonce)
+            .map_err(|e| crypto_error(e.to_string()))?;
+        let wrapped = general_purpose::STANDARD
+            .decode(&self.wrapped_key)
+            .map_err(|e| crypto_error(e.to_string()))?;
+        if nonce.len() != 12 {
+            return Err(crypto_error("invalid data key nonce"));
+        }
+        let key = Aes256Gcm::new(&(*master).into())
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &wrapped,
+                    aad: self.tenant_id.as_bytes(),
+                },
+            )
+            .map_err(|_| {
+                crypto_error(format!(
+                    "failed to unwrap data key of tenant {}, wrong master key?",
+                    self.tenant_id
+                ))
+            })?;
+        key.try_into()
+            .map_err(|_| crypto_error("invalid data key length"))
+    }
+}
+
+/// 管理各租户的数据密钥, 多个服务共享同一目录
+pub struct KeyStore {
+    dir: PathBuf,
+    master: DataKey,
+    cache: Mutex<HashMap<String, DataKey>>,
+}
+
+impl KeyStore {
+    pub fn new<P: Into<PathBuf>>(dir: P, master: DataKey) -> Self {
+        KeyStore {
+            dir: dir.into(),
+            master,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn key_path(&self, tenant_id: &str) -> Result<PathBuf, StorageError> {
+        if tenant_id.is_empty()
+            || tenant_id.len() > u8::MAX as usize
+            || tenant_id.starts_with('.')
+            || tenant_id.contains(['/', '\\'])
+        {
+            return Err(StorageError::InvalidKey(tenant_id.to_string()));
+        }
+        Ok(self.dir.join(format!("{}.json", tenant_id)))
+    }
+
+    fn read_key(&self, tenant_id: &str) -> Result<Option<DataKey>, StorageError> {
+        let path = self.key_path(tenant_id)?;
+        let content = match std::fs::read(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(crypto_error(format!("{}: {}", path.display(), e))),
+        };
+        let wrapped: WrappedKey = serde_json::from_slice(&content)
+            .map_err(|e| crypto_error(format!("{}: {}", path.display(), e)))?;
+        wrapped.unwrap(&self.master).map(Some)
+    }
+
+    /// 创建新的数据密钥. 多个进程同时创建时只有一个成功, 其余进程读取已创建的密钥
+    fn create_key(&self, tenant_id: &str) -> Result<DataKey, StorageError> {
+        let path = self.key_path(tenant_id)?;
+        let key = random_key();
+        let content = serde_json::to_vec_pretty(&WrappedKey::wrap(&self.master, tenant_id, &key)?)
+            .map_err(|e| crypto_error(e.to_string()))?;
+        std::fs::create_dir_all(&self.dir).map_err(|e| crypto_error(e.to_string()))?;
+        let temp = self
+            .dir
+            .join(format!(".{}.{}.partial", tenant_id, uuid::Uuid::new_v4().simple()));
+        let result = std::fs::File::create(&temp)
+            .and_then(|mut file| {
+                file.write_all(&content)?;
+                file.sync_all()
+            })
+            .and_then(|_| std::fs::hard_link(&temp, &path));
+        let _ = std::fs::remove_file(&temp);
+        match result {
+            Ok(()) => Ok(key),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => self
+                .read_key(tenant_id)?
+                .ok_or_else(|| crypto_error(format!("data key of {} disappeared", tenant_id))),
+            Err(e) => Err(crypto_error(format!("{}: {}", path.display(), e))),
+        }
+    }
+
+    /// 读取租户的数据密钥, create 为 true 时不存在则创建
+    pub fn data_key(&self, tenant_id: &str, create: bool) -> Result<DataKey, StorageError> {
+        if let Some(key) = self.cache.lock().unwrap().get(tenant_id) {
+            return Ok(*key);
+        }
+        let key = match self.read_key(tenant_id)? {
+            Some(key) => key,
+            None if create => self.create_key(tenant_id)?,
+            None => {
+                return Err(crypto_error(format!(
+                    "data key of tenant {} not found",
+                    tenant_id
+                )));
+            }
+        };
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(tenant_id.to_string(), key);
+        Ok(key)
+    }
+
+    /// 用新主密钥重新包装所有数据密钥, 数据文件无需改动.
+    /// 已由新主密钥包装的密钥跳过, 中途失败可以重新执行. 返回重新包装的数量
+    pub fn rotate(&self, new_master: &DataKey) -> Result<usize, StorageError> {
+        let entries = std::fs::read_dir(&self.dir).map_err(|e| crypto_error(e.to_string()))?;
+        let mut rotated = 0;
+        for entry in entries {
+            let path = entry.map_err(|e| crypto_error(e.to_string()))?.path();
+            let is_key_file = path.extension().is_some_and(|ext| ext == "json")
+                && !path
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if !is_key_file {
+                continue;
+            }
+            let content = std::fs::read(&path).map_err(|e| crypto_error(e.to_string()))?;
+            let wrapped: WrappedKey = serde_json::from_slice(&content)
+                .map_err(|e| crypto_error(format!("{}: {}", path.display(), e)))?;
+            let key = match wrapped.unwrap(&self.master) {
+                Ok(key) => key,
+                Err(e) => {
+                    if wrapped.unwrap(new_master).is_ok() {
+                        continue;
+                    }
+                    return Err(e);
+                }
+            };
+            let rewrapped = WrappedKey::wrap(new_master, &wrapped.tenant_id, &key)?;
+            let content =
+                serde_json::to_vec_pretty(&rewrapped).map_err(|e| crypto_error(e.to_string()))?;
+            crate::storage_backend::write_file_atomic(&path, &content)
+                .map_err(|e| crypto_error(format!("{}: {}", path.display(), e)))?;
+            rotated += 1;
+        }
+        Ok(rotated)
+    }
+}
+
+/// 数据是否以加密文件头开始, 数据不足 4 字节时按前缀判断
+fn has_magic(data: &[u8]) -> bool {
+    let len = data.len().min(MAGIC.len());
+    data[..len] == MAGIC[..len] || data[..len] == LEGACY_MAGIC[..len]
+}
+
+fn chunk_nonce(prefix: &[u8], counter: u32, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..NONCE_PREFIX_LEN].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_LEN..11].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+
+struct Header {
+    tenant_id: String,
+    /// 派生文件密钥的 salt, 旧格式为 None
+    salt: Option<[u8; SALT_LEN]>,
+    /// 旧格式的随机 nonce 前缀, 新格式为 0
+    nonce_prefix: [u8; NONCE_PREFIX_LEN],
+    chunk_size: usize,
+    /// 序列化后的文件头, 作为每段的附加数据
+    bytes: Vec<u8>,
+}
+
+impl Header {
+    fn new(tenant_id: &str, chunk_size: usize) -> Result<Self, StorageError> {
+        if tenant_id.len() > u8::MAX as usize {
+            return Err(StorageError::InvalidKey(tenant_id.to_string()));
+        }
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let mut bytes = Vec::with_capacity(MAX_HEADER_LEN);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(tenant_id.len() as u8);
+        bytes.extend_from_slice(tenant_id.as_bytes());
+        bytes.extend_from_slice(&salt);
+        bytes.extend_from_slice(&(chunk_size as u32).to_be_bytes());
+        Ok(Header {
+            tenant_id: tenant_id.to_string(),
+            salt: Some(salt),
+            nonce_prefix: [0u8; NONCE_PREFIX_LEN],
+            chunk_size,
+            bytes,
+        })
+    }
+
+    /// 解析文件头, 数据不足时返回 None
+    fn parse(data: &[u8]) -> Result<Option<Self>, StorageError> {
+        let tenant_start = MAGIC.len() + 1;
+        if data.len() < tenant_start {
+            return Ok(None);
+        }
+        let legacy = data.starts_with(LEGACY_MAGIC);
+        let tenant_end = tenant_start + data[MAGIC.len()] as usize;
+        let header_len = tenant_end + if legacy { NONCE_PREFIX_LEN } else { SALT_LEN } + 4;
+        if data.len() < header_len {
+            return Ok(None);
+        }
+        let tenant_id = std::str::from_utf8(&data[tenant_start..tenant_end])
+            .map_err(|_| crypto_error("invalid tenant in header"))?
+            .to_string();
+        let mut salt = None;
+        let mut nonce_prefix = [0u8; NONCE_PREFIX_LEN];
+        if legacy {
+            nonce_prefix.copy_from_slice(&data[tenant_end..tenant_end + NONCE_PREFIX_LEN]);
+        } else {
+            let mut file_salt = [0u8; SALT_LEN];
+            file_salt.copy_from_slice(&data[tenant_end..tenant_end + SALT_LEN]);
+            salt = Some(file_salt);
+        }
+        let chunk_size = u32::from_be_bytes(
+            data[header_len - 4..header_len].try_into().unwrap(),
+        ) as usize;
+        if chunk_size == 0 {
+            return Err(crypto_error("invalid chunk size in header"));
+        }
+        Ok(Some(Header {
+            tenant_id,
+            salt,
+            nonce_prefix,
+            chunk_size,
+            bytes: data[..header_len].to_vec(),
+        }))
+    }
+
+    fn encrypted_chunk_size(&self) -> usize {
+        self.chunk_size + TAG_LEN
+    }
+
+    /// 由数据密钥派生该文件的密钥, 旧格式直接使用数据密钥
+    fn cipher(&self, key: &DataKey) -> Result<Aes256Gcm, StorageError> {
+        let Some(salt) = &self.salt else {
+            return Ok(Aes256Gcm::new(&(*key).into()));
+        };
+        let mut file_key = [0u8; 32];
+        Hkdf::<Sha256>::new(Some(salt), key)
+            .expand(FILE_KEY_INFO, &mut file_key)
+            .map_err(|_| crypto_error("failed to derive file key"))?;
+        Ok(Aes256Gcm::new(&file_key.into()))
+    }
+}
+
+/// 分段加密, 最后一段在 finish 时输出
+pub struct ChunkEncryptor {
+    cipher: Aes256Gcm,
+    header: Header,
+    header_written: bool,
+    counter: u32,
+    buffer: Vec<u8>,
+}
+
+impl ChunkEncryptor {
+    pub fn new(key: &DataKey, tenant_id: &str, chunk_size: usize) -> Result<Self, StorageError> {
+        let header = Header::new(tenant_id, chunk_size.max(1))?;
+        Ok(ChunkEncryptor {
+            cipher: header.cipher(key)?,
+            header,
+            header_written: false,
+            counter: 0,
+            buffer: vec![],
+        })
+    }
+
+    fn seal(&mut self, chunk: &[u8], last: bool, out: &mut Vec<u8>) -> Result<(), StorageError> {
+        let nonce = chunk_nonce(&self.header.nonce_prefix, self.counter, last);
+        let sealed = self
+            .cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: chunk,
+                    aad: &self.header.bytes,
+                },
+            )
+            .map_err(|_| crypto_error("failed to encrypt chunk"))?;
+        self.counter = self
+            .counter
+            .checked_add(1)
+            .ok_or_else(|| crypto_error("too many chunks"))?;
+        out.extend_from_slice(&sealed);
+        Ok(())
+    }
+
+    pub fn update(&mut self, data: &[u8]) -> Result<Vec<u8>, StorageError> {
+        let mut out = vec![];
+        if !self.header_written {
+            out.extend_from_slice(&self.header.bytes);
+            self.header_written = true;
+        }
+        self.buffer.extend_from_slice(data);
+        let chunk_size = self.header.chunk_size;
+        // 保留最后一段, 直到确认后面没有数据
+        let mut consumed = 0;
+        while self.buffer.len() - consumed > chunk_size {
+            let chunk = self.buffer[consumed..consumed + chunk_size].to_vec();
+            self.seal(&chunk, false, &mut out)?;
+            consumed += chunk_size;
+        }
+        self.buffer.drain(..consumed);
+        Ok(out)
+    }
+
+    pub fn finish(mut self) -> Result<Vec<u8>, StorageError> {
+        let mut out = self.update(&[])?;
+        let chunk = std::mem::take(&mut self.buffer);
+        self.seal(&chunk, true, &mut out)?;
+        Ok(out)
+    }
+}
+
+enum DecryptState {
+    /// 尚未读到完整文件头
+    Header,
+    Plain,
+    Encrypted(Box<Aes256Gcm>, Header),
+}
+
+/// 分段解密, 不带文件头的数据原样输出
+pub struct ChunkDecryptor {
+    keys: Arc<KeyStore>,
+    state: DecryptState,
+    counter: u32,
+    buffer: Vec<u8>,
+}
+
+impl ChunkDecryptor {
+    pub fn new(keys: Arc<KeyStore>) -> Self {
+        ChunkDecryptor {
+            keys,
+            state: DecryptState::Header,
+            counter: 0,
+            buffer: vec![],
+        }
+    }
+
+    fn open(
+        cipher: &Aes256Gcm,
+        header: &Header,
+        counter: u32,
+        chunk: &[u8],
+        last: bool,
+    ) -> Result<Vec<u8>, StorageError> {
+        let nonce = chunk_nonce(&header.nonce_prefix, counter, last);
+        cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: chunk,
+                    aad: &header.bytes,
+                },
+            )
+            .map_err(|_| crypto_error("decryption failed, data corrupted or truncated"))
+    }
+
+    fn read_header(&mut self) -> Result<(), StorageError> {
+        if !has_magic(&self.buffer) {
+            self.state = DecryptState::Plain;
+            return Ok(());
+        }
+        if let Some(header) = Header::parse(&self.buffer)? {
+            let key = self.keys.data_key(&header.tenant_id, false)?;
+            self.buffer.drain(..header.bytes.len());
+            self.state = DecryptState::Encrypted(Box::new(header.cipher(&key)?), header);
+        }
+        Ok(())
+    }
+
+    pub fn update(&mut self, data: &[u8]) -> Result<Vec<u8>, StorageError> {
+        self.buffer.extend_from_slice(data);
+        if let DecryptState::Header = self.state {
+            self.read_header()?;
+        }
+        match &self.state {
+            DecryptState::Header => Ok(vec![]),
+            DecryptState::Plain => Ok(std::mem::take(&mut self.buffer)),
+            DecryptState::Encrypted(cipher, header) => {
+                let size = header.encrypted_chunk_size();
+                let mut out = vec![];
+                let mut consumed = 0;
+                while self.buffer.len() - consumed > size {
+                    let chunk = &self.buffer[consumed..consumed + size];
+                    out.extend(Self::open(cipher, header, self.counter, chunk, false)?);
+                    self.counter += 1;
+                    consumed += size;
+                }
+                self.buffer.drain(..consumed);
+                Ok(out)
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> Result<Vec<u8>, StorageError> {
+        let mut out = self.update(&[])?;
+        match &self.state {
+            // 数据比文件头短, 按明文处理
+            DecryptState::Header | DecryptState::Plain => out.append(&mut self.buffer),
+            DecryptState::Encrypted(cipher, header) => {
+                out.extend(Self::open(cipher, header, self.counter, &self.buffer, true)?);
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// 对其他存储后端透明加密, 写入时加密, 读取时解密
+pub struct EncryptedBackend {
+    inner: Arc<dyn StorageBackend>,
+    keys: Arc<KeyStore>,
+    chunk_size: usize,
+}
+
+impl EncryptedBackend {
+    pub fn new(inner: Arc<dyn StorageBackend>, keys: Arc<KeyStore>, chunk_size: usize) -> Self {
+        EncryptedBackend {
+            inner,
+            keys,
+            chunk_size: chunk_size.max(1),
+        }
+    }
+
+    pub fn from_config(
+        inner: Arc<dyn StorageBackend>,
+        config: &EncryptionConfig,
+    ) -> Result<Self, StorageError> {
+        let master = load_master_key(config)?;
+        Ok(Self::new(
+            inner,
+            Arc::new(KeyStore::new(&config.key_store_path, master)),
+            config.chunk_size as usize,
+        ))
+    }
+
+    pub fn encrypt(&self, key: &str, data: &[u8]) -> Result<Vec<u8>, StorageError> {
+        let tenant_id = tenant_of_key(key);
+        let data_key = self.keys.data_key(tenant_id, true)?;
+        let mut encryptor = ChunkEncryptor::new(&data_key, tenant_id, self.chunk_size)?;
+        let mut out = encryptor.update(data)?;
+        out.extend(encryptor.finish()?);
+        Ok(out)
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, StorageError> {
+        let mut decryptor = ChunkDecryptor::new(self.keys.clone());
+        let mut out = decryptor.update(data)?;
+        out.extend(decryptor.finish()?);
+        Ok(out)
+    }
+}
+
+#[async_trait]
+impl StorageBackend for EncryptedBackend {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), StorageError> {
+        let encrypted = self.encrypt(key, &data)?;
+        self.inner.put(key, encrypted).await
+    }
+
+    async fn put_file(&self, key: &str, local: &Path) -> Result<(), StorageError> {
+        let data = tokio::fs::read(local)
+            .await
+            .map_err(|e| StorageError::Io(format!("{}: {}", local.display(), e)))?;
+        self.put(key, data).await
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        let data = self.inner.get(key).await?;
+        self.decrypt(&data)
+    }
+
+    /// 只读取并解密覆盖 range 的分段
+    async fn get_range(&self, key: &str, range: Range<u64>) -> Result<Vec<u8>, StorageError> {
+        if range.is_empty() {
+            return Ok(vec![]);
+        }
+        let head = self.inner.get_range(key, 0..MAX_HEADER_LEN as u64).await?;
+        if !head.starts_with(MAGIC) && !head.starts_with(LEGACY_MAGIC) {
+            return self.inner.get_range(key, range).await;
+        }
+        let header = Header::parse(&head)?.ok_or_else(|| crypto_error("truncated header"))?;
+        let cipher = header.cipher(&self.keys.data_key(&header.tenant_id, false)?)?;
+        let chunk_size = header.chunk_size as u64;
+        let encrypted_size = header.encrypted_chunk_size() as u64;
+        let first = range.start / chunk_size;
+        let last = (range.end - 1) / chunk_size;
+        let start = header.bytes.len() as u64 + first * encrypted_size;
+        let end = header.bytes.len() as u64 + (last + 1) * encrypted_size;
+        // 多读一个字节, 判断最后一段是否为文件的末段
+        let data = self.inner.get_range(key, start..end + 1).await?;
+        let is_file_end = data.len() as u64 <= end - start;
+        let data = &data[..data.len().min((end - start) as usize)];
+        let pieces: Vec<&[u8]> = data.chunks(encrypted_size as usize).collect();
+        let mut plain = vec![];
+        for (i, piece) in pieces.iter().enumerate() {
+            let final_piece = is_file_end && i + 1 == pieces.len();
+            plain.extend(ChunkDecryptor::open(
+                &cipher,
+                &header,
+                first as u32 + i as u32,
+                piece,
+                final_piece,
+            )?);
+        }
+        let offset = (range.start - first * chunk_size) as usize;
+        let len = (range.end - range.start) as usize;
+        Ok(plain.into_iter().skip(offset).take(len).collect())
+    }
+
+    async fn stream(&self, key: &str) -> Result<ByteStream, StorageError> {
+        let inner = self.inner.stream(key).await?;
+        let state = (inner, Some(ChunkDecryptor::new(self.keys.clone())));
+        Ok(futures::stream::unfold(state, |(mut inner, decryptor)| async move {
+            let mut decryptor = decryptor?;
+            loop {
+                match inner.next().await {
+                    Some(Ok(data)) => match decryptor.update(&data) {
+                        Ok(out) if out.is_empty() => continue,
+                        Ok(out) => return Some((Ok(Bytes::from(out)), (inner, Some(decryptor)))),
+                        Err(e) => return Some((Err(e), (inner, None))),
+                    },
+                    Some(Err(e)) => return Some((Err(e), (inner, None))),
+                    None => {
+                        return Some((decryptor.finish().map(Bytes::from), (inner, None)));
+                    }
+                }
+            }
+        })
+        .boxed())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        self.inner.delete(key).await
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, StorageError> {
+        self.inner.list(prefix).await
+    }
+
+    async fn list_page(
+        &self,
+        prefix: &str,
+        start_after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<String>, StorageError> {
+        self.inner.list_page(prefix, start_after, limit).await
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, StorageError> {
+        self.inner.exists(key).await
+    }
+
+    async fn modified(&self, key: &str) -> Result<SystemTime, StorageError> {
+        self.inner.modified(key).await
+    }
+
+    /// 解密到临时文件, 离开作用域时删除
+    async fn local_file(&self, key: &str) -> Result<LocalFile, StorageError> {
+        let data = self.get(key).await?;
+        let file = tempfile::Builder::new()
+            .suffix(".dcm")
+            .tempfile()
+            .map_err(|e| StorageError::Io(format!("{}: {}", key, e)))?;
+        tokio::fs::write(file.path(), data)
+            .await
+            .map_err(|e| StorageError::Io(format!("{}: {}", key, e)))?;
+        Ok(LocalFile::Temp(file))
+    }
+
+    async fn cleanup_temp_files(&self) -> Result<usize, StorageError> {
+        self.inner.cleanup_temp_files().await
+    }
+}
+
+/// 按配置为存储后端启用静态加密
+pub fn wrap_encryption(
+    backend: Arc<dyn StorageBackend>,
+    config: &Option<EncryptionConfig>,
+) -> Result<Arc<dyn StorageBackend>, StorageError> {
+    match config {
+        Some(config) if config.enabled => {
+            Ok(Arc::new(EncryptedBackend::from_config(backend, config)?))
+        }
+        _ => Ok(backend),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage_backend::LocalDiskBackend;
+    use futures::TryStreamExt;
+
+    fn make_backend(root: &Path, keys: &Path, master: DataKey) -> EncryptedBackend {
+        EncryptedBackend::new(
+            Arc::new(LocalDiskBackend::new(root)),
+            Arc::new(KeyStore::new(keys, master)),
+            16,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_backend() {
+        let root = tempfile::tempdir().unwrap();
+        let keys = tempfile::tempdir().unwrap();
+        let backend = make_backend(root.path(), keys.path(), random_key());
+        let data: Vec<u8> = (0..100u8).collect();
+
+        backend.put("t1/st/se/1.dcm", data.clone()).await.unwrap();
+        let stored = std::fs::read(root.path().join("t1/st/se/1.dcm")).unwrap();
+        assert!(stored.starts_with(MAGIC));
+        assert!(keys.path().join("t1.json").exists());
+
+        assert_eq!(backend.get("t1/st/se/1.dcm").await.unwrap(), data);
+        assert_eq!(
+            backend.get_range("t1/st/se/1.dcm", 10..40).await.unwrap(),
+            &data[10..40]
+        );
+        assert_eq!(
+            backend.get_range("t1/st/se/1.dcm", 90..200).await.unwrap(),
+            &data[90..]
+        );
+        let streamed: Vec<Bytes> = backend
+            .stream("t1/st/se/1.dcm")
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(streamed.concat(), data);
+
+        // 空文件及分段长度整数倍
+        backend.put("t1/st/se/2.dcm", vec![]).await.unwrap();
+        assert!(backend.get("t1/st/se/2.dcm").await.unwrap().is_empty());
+        backend.put("t1/st/se/3.dcm", data[..32].to_vec()).await.unwrap();
+        assert_eq!(backend.get("t1/st/se/3.dcm").await.unwrap(), &data[..32]);
+
+        // 启用加密前保存的明文文件
+        std::fs::write(root.path().join("t1/st/se/4.dcm"), b"plain").unwrap();
+        assert_eq!(backend.get("t1/st/se/4.dcm").await.unwrap(), b"plain");
+        assert_eq!(backend.get_range("t1/st/se/4.dcm", 1..3).await.unwrap(), b"la");
+
+        // 截断或篡改后无法解密
+        let path = root.path().join("t1/st/se/1.dcm");
+        std::fs::write(&path, &stored[..stored.len() - 32]).unwrap();
+        assert!(backend.get("t1/st/se/1.dcm").await.is_err());
+        let mut tampered = stored.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        std::fs::write(&path, &tampered).unwrap();
+        assert!(backend.get("t1/st/se/1.dcm").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_file_keys() {
+        let root = tempfile::tempdir().unwrap();
+        let keys = tempfile::tempdir().unwrap();
+        let backend = make_backend(root.path(), keys.path(), random_key());
+        let data = b"same plaintext".to_vec();
+        backend.put("t1/st/se/1.dcm", data.clone()).await.unwrap();
+        backend.put("t1/st/se/2.dcm", data.clone()).await.unwrap();
+
+        // 每个文件使用不同的 salt 派生密钥, 相同明文的密文不同
+        let first = std::fs::read(root.path().join("t1/st/se/1.dcm")).unwrap();
+        let second = std::fs::read(root.path().join("t1/st/se/2.dcm")).unwrap();
+        let h1 = Header::parse(&first).unwrap().unwrap();
+        let h2 = Header::parse(&second).unwrap().unwrap();
+        assert_ne!(h1.salt, h2.salt);
+        assert_eq!(h1.nonce_prefix, [0u8; NONCE_PREFIX_LEN]);
+        assert_ne!(first[h1.bytes.len()..], second[h2.bytes.len()..]);
+
+        // 数据密钥不能直接解密
+        let data_key = backend.keys.data_key("t1", false).unwrap();
+        let raw = Aes256Gcm::new(&data_key.into());
+        let piece = &first[h1.bytes.len()..];
+        assert!(ChunkDecryptor::open(&raw, &h1, 0, piece, true).is_err());
+        assert_eq!(backend.get("t1/st/se/2.dcm").await.unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn test_legacy_format() {
+        let root = tempfile::tempdir().unwrap();
+        let keys = tempfile::tempdir().unwrap();
+        let backend = make_backend(root.path(), keys.path(), random_key());
+        let data_key = backend.keys.data_key("t1", true).unwrap();
+
+        // 旧格式: 数据密钥直接加密, 文件头保存 nonce 前缀
+        let data: Vec<u8> = (0..20u8).collect();
+        let prefix = [7u8; NONCE_PREFIX_LEN];
+        let mut file = LEGACY_MAGIC.to_vec();
+        file.push(2);
+        file.extend_from_slice(b"t1");
+        file.extend_from_slice(&prefix);
+        file.extend_from_slice(&16u32.to_be_bytes());
+        let header = file.clone();
+        let cipher = Aes256Gcm::new(&data_key.into());
+        for (i, chunk) in data.chunks(16).enumerate() {
+            let nonce = chunk_nonce(&prefix, i as u32, i == 1);
+            let payload = Payload {
+                msg: chunk,
+                aad: &header,
+            };
+            file.extend(cipher.encrypt(Nonce::from_slice(&nonce), payload).unwrap());
+        }
+        std::fs::create_dir_all(root.path().join("t1/st/se")).unwrap();
+        std::fs::write(root.path().join("t1/st/se/1.dcm"), &file).unwrap();
+
+        assert_eq!(backend.get("t1/st/se/1.dcm").await.unwrap(), data);
+        assert_eq!(
+            backend.get_range("t1/st/se/1.dcm", 14..18).await.unwrap(),
+            &data[14..18]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rotate_master_key() {
+        let root = tempfile::tempdir().unwrap();
+        let keys = tempfile::tempdir().unwrap();
+        let old_master = random_key();
+        let new_master = random_key();
+        let backend = make_backend(root.path(), keys.path(), old_master);
+        backend.put("t1/st/se/1.dcm", b"t1 data".to_vec()).await.unwrap();
+        backend.put("t2/st/se/1.dcm", b"t2 data".to_vec()).await.unwrap();
+
+        let store = KeyStore::new(keys.path(), old_master);
+        assert_eq!(store.rotate(&new_master).unwrap(), 2);
+        // 重复执行时跳过已轮换的密钥
+        assert_eq!(store.rotate(&new_master).unwrap(), 0);
+
+        let rotated = make_backend(root.path(), keys.path(), new_master);
+        assert_eq!(rotated.get("t1/st/se/1.dcm").await.unwrap(), b"t1 data");
+        assert_eq!(rotated.get("t2/st/se/1.dcm").await.unwrap(), b"t2 data");
+        let stale = make_backend(root.path(), keys.path(), old_master);
+        assert!(stale.get("t1/st/se/1.dcm").await.is_err());
+    }
+
+    #[test]
+    fn test_tenant_of_key() {
+        assert_eq!(tenant_of_key("t1/20240101/st/se/1.dcm"), "t1");
+        assert_eq!(tenant_of_key("t1/metadata/20240101/st.json"), "t1");
+        assert_eq!(tenant_of_key("/data/xdcm/t1/1.dcm"), DEFAULT_KEY_TENANT);
+        assert!(decode_master_key(&generate_master_key()).is_ok());
+        assert!(decode_master_key("c2hvcnQ=").is_err());
+    }
+}
@@ -0,0 +1,422 @@
+//! DICOM 文件目录布局模板.
+//!
+//! 模板以 `/` 分隔目录, 最后一段为文件名, 支持的占位符:
+//! - `{tenant}` 租户, `{study_date}` 检查日期 YYYYMMDD, `{yyyy}` `{mm}` `{dd}` 检查日期的年月日
+//! - `{study_uid}` `{series_uid}` `{sop}` 对应的 UID
+//! - `{study_hash}` `{series_hash}` UID 哈希值(hash_uid), `{study_hash:N}` 取哈希值末 N 位用于分片.
+//!   哈希值为前置补 0 的十进制, 高位分布不均, 因此取末尾.
+//!
+//! 第一级目录必须是 `{tenant}`. 检查目录为最后一个包含检查占位符的目录, 序列目录为文件名之前的全部目录,
+//! 因此检查占位符必须位于序列占位符之前, 序列目录必须包含完整的 `{series_uid}` 或 `{series_hash}`.
+//! 每个序列记录写入时使用的布局版本(dicom_state_meta.storage_layout), 修改布局后已有数据仍按原版本读取.
+
+use crate::server_config;
+use crate::storage_config::hash_uid;
+use crate::utils::get_logger;
+use database::dicom_dbprovider::DbProvider;
+use slog::{o, warn};
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex, OnceLock};
+
+/// 内置布局版本, 未配置 layouts 时使用
+pub const LEGACY_LAYOUT_VERSION: i32 = 0;
+pub const LEGACY_LAYOUT_TEMPLATE: &str = "{tenant}/{study_date}/{study_uid}/{series_uid}/{sop}.dcm";
+/// UID 哈希值长度, 见 hash_uid
+const HASH_LEN: usize = 20;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    Tenant,
+    StudyDate,
+    Year,
+    Month,
+    Day,
+    StudyUid,
+    SeriesUid,
+    Sop,
+    StudyHash(Option<usize>),
+    SeriesHash(Option<usize>),
+}
+
+impl Part {
+    fn parse(name: &str) -> Result<Part, String> {
+        let (name, width) = match name.split_once(':') {
+            Some((name, width)) => {
+                let width: usize = width
+                    .parse()
+                    .map_err(|_| format!("invalid hash width in {{{}:{}}}", name, width))?;
+                if width == 0 || width > HASH_LEN {
+                    return Err(format!("hash width of {{{}}} must be 1..={}", name, HASH_LEN));
+                }
+                (name, Some(width))
+            }
+            None => (name, None),
+        };
+        let part = match name {
+            "study_hash" => Part::StudyHash(width),
+            "series_hash" => Part::SeriesHash(width),
+            _ if width.is_some() => return Err(format!("{{{}}} does not take a width", name)),
+            "tenant" => Part::Tenant,
+            "study_date" => Part::StudyDate,
+            "yyyy" => Part::Year,
+            "mm" => Part::Month,
+            "dd" => Part::Day,
+            "study_uid" => Part::StudyUid,
+            "series_uid" => Part::SeriesUid,
+            "sop" => Part::Sop,
+            _ => return Err(format!("unknown placeholder {{{}}}", name)),
+        };
+        Ok(part)
+    }
+
+    fn is_study(&self) -> bool {
+        matches!(self, Part::StudyUid | Part::StudyHash(_))
+    }
+
+    fn is_series(&self) -> bool {
+        matches!(self, Part::SeriesUid | Part::SeriesHash(_))
+    }
+}
+
+/// 生成路径所需的序列信息, study_date 为 YYYYMMDD
+pub struct LayoutInput<'a> {
+    pub tenant_id: &'a str,
+    pub study_date: &'a str,
+    pub study_uid: &'a str,
+    pub series_uid: &'a str,
+}
+
+#[derive(Debug, Clone)]
+pub struct StorageLayout {
+    version: i32,
+    segments: Vec<Vec<Part>>,
+    /// 检查目录包含的目录段数
+    study_depth: usize,
+}
+
+fn parse_segment(segment: &str) -> Result<Vec<Part>, String> {
+    let mut parts = vec![];
+    let mut rest = segment;
+    while !rest.is_empty() {
+        match rest.find('{') {
+            Some(0) => {
+                let end = rest
+                    .find('}')
+                    .ok_or_else(|| format!("unclosed placeholder in {}", segment))?;
+                parts.push(Part::parse(&rest[1..end])?);
+                rest = &rest[end + 1..];
+            }
+            Some(start) => {
+                parts.push(Part::Literal(rest[..start].to_string()));
+                rest = &rest[start..];
+            }
+            None => {
+                parts.push(Part::Literal(rest.to_string()));
+                rest = "";
+            }
+        }
+    }
+    if parts
+        .iter()
+        .any(|p| matches!(p, Part::Literal(s) if s.contains('}')))
+    {
+        return Err(format!("unexpected '}}' in {}", segment));
+    }
+    Ok(parts)
+}
+
+fn tail(value: &str, width: Option<usize>) -> &str {
+    match width {
+        Some(width) if width < value.len() => &value[value.len() - width..],
+        _ => value,
+    }
+}
+
+impl StorageLayout {
+    pub fn parse(version: i32, template: &str) -> Result<Self, String> {
+        if template.is_empty() || template.starts_with('/') || template.ends_with('/') {
+            return Err("template must be a relative path to a file".to_string());
+        }
+        let segments = template
+            .split('/')
+            .map(|segment| {
+                if segment.is_empty() || segment == "." || segment == ".." {
+                    return Err(format!("invalid path segment '{}'", segment));
+                }
+                parse_segment(segment)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        // 第一级目录为租户, 租户隔离、加密密钥和配额都依赖于此
+        if segments[0] != [Part::Tenant] {
+            return Err("first directory must be exactly {tenant}".to_string());
+        }
+        let (file_name, dirs) = segments.split_last().unwrap();
+        if !file_name.contains(&Part::Sop) {
+            return Err("file name must contain {sop}".to_string());
+        }
+        if dirs.iter().flatten().any(|p| *p == Part::Sop) {
+            return Err("{sop} is only allowed in the file name".to_string());
+        }
+        let study_depth = dirs
+            .iter()
+            .rposition(|segment| segment.iter().any(Part::is_study))
+            .map(|i| i + 1)
+            .ok_or_else(|| "template has no study directory".to_string())?;
+        let study_dirs = &dirs[..study_depth];
+        if !study_dirs
+            .iter()
+            .flatten()
+            .any(|p| matches!(p, Part::StudyUid | Part::StudyHash(None)))
+        {
+            return Err("study directory must contain {study_uid} or {study_hash}".to_string());
+        }
+        if study_dirs.iter().flatten().any(Part::is_series) {
+            return Err("series placeholders must come after the study directory".to_string());
+        }
+        if !dirs[study_depth..]
+            .iter()
+            .flatten()
+            .any(|p| matches!(p, Part::SeriesUid | Part::SeriesHash(None)))
+        {
+            return Err("series directory must contain {series_uid} or {series_hash}".to_string());
+        }
+        Ok(StorageLayout {
+            version,
+            segments,
+            study_depth,
+        })
+    }
+
+    pub fn legacy() -> Self {
+        Self::parse(LEGACY_LAYOUT_VERSION, LEGACY_LAYOUT_TEMPLATE).unwrap()
+    }
+
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+
+    fn render(&self, segments: &[Vec<Part>], input: &LayoutInput<'_>, sop_uid: &str) -> String {
+        let study_hash = hash_uid(input.study_uid);
+        let series_hash = hash_uid(input.series_uid);
+        let date = |range: std::ops::Range<usize>| input.study_date.get(range).unwrap_or_default();
+        segments
+            .iter()
+            .map(|segment| {
+                segment
+                    .iter()
+                    .map(|part| match part {
+                        Part::Literal(s) => s.as_str(),
+                        Part::Tenant => input.tenant_id,
+                        Part::StudyDate => input.study_date,
+                        Part::Year => date(0..4),
+                        Part::Month => date(4..6),
+                        Part::Day => date(6..8),
+                        Part::StudyUid => input.study_uid,
+                        Part::SeriesUid => input.series_uid,
+                        Part::Sop => sop_uid,
+                        Part::StudyHash(width) => tail(&study_hash, *width),
+                        Part::SeriesHash(width) => tail(&series_hash, *width),
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// 检查目录, 检查下所有序列的文件都位于该目录下
+    pub fn study_key(&self, input: &LayoutInput<'_>) -> String {
+        self.render(&self.segments[..self.study_depth], input, "")
+    }
+
+    /// 序列目录
+    pub fn series_key(&self, input: &LayoutInput<'_>) -> String {
+        self.render(&self.segments[..self.segments.len() - 1], input, "")
+    }
+
+    pub fn file_key(&self, input: &LayoutInput<'_>, sop_uid: &str) -> String {
+        self.render(&self.segments, input, sop_uid)
+    }
+
+    /// 从按本布局生成的文件 key 中取出租户, 目录层数不同或没有单独的 {tenant} 目录时返回 None.
+    /// 调用方需用 file_key 重新生成 key 进行确认
+    pub fn tenant_of<'k>(&self, key: &'k str) -> Option<&'k str> {
+        let parts: Vec<&str> = key.split('/').collect();
+        if parts.len() != self.segments.len() {
+            return None;
+        }
+        self.segments
+            .iter()
+            .position(|segment| segment.as_slice() == [Part::Tenant])
+            .map(|i| parts[i])
+    }
+}
+
+/// 按 (tenant, study_uid, series_uid) 缓存的序列布局版本上限, 超过后清空
+const MAX_CACHED_SERIES: usize = 100_000;
+
+/// 收图时确定序列使用的布局版本: 已有序列沿用记录的版本, 新序列使用 layout_version.
+/// 布局版本只在服务重启时变化, 因此每个序列只查询一次数据库.
+pub struct SeriesLayoutResolver {
+    current: i32,
+    /// 未配置自定义布局时所有序列都是内置布局, 不查询数据库
+    lookup: bool,
+    /// 由进程启动时通过 set_db_provider 注入, 与进程其他部分共享连接池
+    db: OnceLock<Arc<dyn DbProvider>>,
+    cache: Mutex<HashMap<(String, String, String), i32>>,
+}
+
+impl SeriesLayoutResolver {
+    pub fn new(current: i32, lookup: bool) -> Self {
+        SeriesLayoutResolver {
+            current,
+            lookup,
+            db: OnceLock::new(),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 查询序列布局使用的数据库, 只有第一次设置生效
+    pub fn set_db_provider(&self, db: Arc<dyn DbProvider>) {
+        let _ = self.db.set(db);
+    }
+
+    /// 配置了自定义布局时需要查询数据库
+    pub fn needs_db_provider(&self) -> bool {
+        self.lookup
+    }
+
+    pub async fn resolve(&self, tenant_id: &str, study_uid: &str, series_uid: &str) -> i32 {
+        if !self.lookup {
+            return self.current;
+        }
+        let key = (
+            tenant_id.to_string(),
+            study_uid.to_string(),
+            series_uid.to_string(),
+        );
+        if let Some(version) = self.cache.lock().unwrap().get(&key) {
+            return *version;
+        }
+        let stored = match self.db.get() {
+            Some(db) => db
+                .get_series_layout(tenant_id, study_uid, series_uid)
+                .await
+                .map_err(|e| e.to_string()),
+            None => Err("no database provider".to_string()),
+        };
+        let version = match stored {
+            Ok(version) => version.unwrap_or(self.current),
+            Err(e) => {
+                // 查询失败时不缓存, 使用当前布局
+                let rlogger = get_logger();
+                let logger = rlogger.new(o!("common"=>"resolve_series_layout"));
+                warn!(logger, "Failed to load layout of series {}: {}", series_uid, e);
+                return self.current;
+            }
+        };
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= MAX_CACHED_SERIES {
+            cache.clear();
+        }
+        cache.insert(key, version);
+        version
+    }
+}
+
+static SERIES_LAYOUTS: LazyLock<Arc<SeriesLayoutResolver>> = LazyLock::new(|| {
+    let config = server_config::load_config().unwrap();
+    Arc::new(SeriesLayoutResolver::new(
+        config.local_storage.layout_version,
+        !config.local_storage.layouts.is_empty(),
+    ))
+});
+
+pub fn series_layouts() -> Arc<SeriesLayoutResolver> {
+    SERIES_LAYOUTS.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input() -> LayoutInput<'static> {
+        LayoutInput {
+            tenant_id: "t1",
+            study_date: "20240315",
+            study_uid: "1.2.3",
+            series_uid: "1.2.3.4",
+        }
+    }
+
+    #[test]
+    fn test_legacy_layout() {
+        let layout = StorageLayout::legacy();
+        assert_eq!(layout.study_key(&input()), "t1/20240315/1.2.3");
+        assert_eq!(layout.series_key(&input()), "t1/20240315/1.2.3/1.2.3.4");
+        assert_eq!(
+            layout.file_key(&input(), "1.2.3.4.5"),
+            "t1/20240315/1.2.3/1.2.3.4/1.2.3.4.5.dcm"
+        );
+    }
+
+    #[test]
+    fn test_sharded_layout() {
+        let layout = StorageLayout::parse(
+            1,
+            "{tenant}/{yyyy}/{mm}/{study_hash:2}/{study_hash}/{series_hash}/{sop}.dcm",
+        )
+        .unwrap();
+        let study_hash = hash_uid("1.2.3");
+        let series_hash = hash_uid("1.2.3.4");
+        let study_key = format!("t1/2024/03/{}/{}", &study_hash[18..], study_hash);
+        assert_eq!(layout.study_key(&input()), study_key);
+        assert_eq!(
+            layout.series_key(&input()),
+            format!("{}/{}", study_key, series_hash)
+        );
+        assert_eq!(
+            layout.file_key(&input(), "9.9"),
+            format!("{}/{}/9.9.dcm", study_key, series_hash)
+        );
+        assert!(!layout.file_key(&input(), "9.9").contains("1.2.3"));
+        assert_eq!(layout.tenant_of(&layout.file_key(&input(), "9.9")), Some("t1"));
+        assert_eq!(layout.tenant_of("t1/2024/03/x.dcm"), None);
+    }
+
+    #[test]
+    fn test_invalid_layouts() {
+        for template in [
+            "",
+            "/{tenant}/{study_uid}/{series_uid}/{sop}.dcm",
+            "{tenant}/{study_uid}/{series_uid}",
+            "{tenant}/{study_uid}/{series_uid}/{sop}/x.dcm",
+            "{tenant}/{series_uid}/{sop}.dcm",
+            "{tenant}/{study_uid}/{series_hash:2}/{sop}.dcm",
+            "{tenant}/{series_uid}/{study_uid}/{sop}.dcm",
+            "{tenant}/{study_hash:2}/{series_uid}/{sop}.dcm",
+            "{tenant}/{study_uid}/../{series_uid}/{sop}.dcm",
+            "{tenant}/{study_uid}/{series_uid}/{sop.dcm",
+            "{tenant}/{study_uid}/{series_uid}/{uid}.dcm",
+            "{tenant:2}/{study_uid}/{series_uid}/{sop}.dcm",
+            "{tenant}/{study_hash:0}/{study_uid}/{series_uid}/{sop}.dcm",
+        ] {
+            assert!(StorageLayout::parse(1, template).is_err(), "{}", template);
+        }
+    }
+
+    #[test]
+    fn test_tenant_must_be_first_directory() {
+        for template in [
+            "{study_date}/{tenant}/{study_uid}/{series_uid}/{sop}.dcm",
+            "data/{tenant}/{study_uid}/{series_uid}/{sop}.dcm",
+            "t_{tenant}/{study_uid}/{series_uid}/{sop}.dcm",
+            "{tenant}{yyyy}/{study_uid}/{series_uid}/{sop}.dcm",
+            "{study_uid}/{series_uid}/{sop}.dcm",
+        ] {
+            let err = StorageLayout::parse(1, template).unwrap_err();
+            assert!(err.contains("{tenant}"), "{}: {}", template, err);
+        }
+        assert!(StorageLayout::parse(1, "{tenant}/{study_uid}/{series_uid}/{sop}.dcm").is_ok());
+    }
+}
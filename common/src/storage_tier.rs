@@ -0,0 +1,298 @@
+//! 分层存储: local_storage 为热存储层, 附加存储层(大容量磁盘、对象存储)存放迁移后的序列.
+//!
+//! 各层使用相同的 key, 读取时依次查找热存储层和附加存储层, 调用方无需关心数据所在位置.
+
+use crate::server_config::{self, HOT_TIER, LocalStorageConfig};
+use crate::storage_backend::{
+    LocalFile, StorageBackend, StorageError, dicom_backend, json_backend, make_tier_backend,
+};
+use crate::utils::get_logger;
+use slog::{info, o, warn};
+use std::collections::HashSet;
+use std::sync::{Arc, LazyLock};
+
+pub struct TieredStorage {
+    /// 第一个为热存储层
+    tiers: Vec<(String, Arc<dyn StorageBackend>)>,
+}
+
+impl TieredStorage {
+    pub fn new(hot: Arc<dyn StorageBackend>, others: Vec<(String, Arc<dyn StorageBackend>)>) -> Self {
+        let mut tiers = vec![(HOT_TIER.to_string(), hot)];
+        tiers.extend(others);
+        TieredStorage { tiers }
+    }
+
+    pub fn from_config(
+        hot: Arc<dyn StorageBackend>,
+        config: &LocalStorageConfig,
+    ) -> Result<Self, StorageError> {
+        let mut others = Vec::with_capacity(config.tiers.len());
+        for tier in &config.tiers {
+            others.push((tier.name.clone(), make_tier_backend(tier, &config.encryption)?));
+        }
+        Ok(Self::new(hot, others))
+    }
+
+    pub fn tier(&self, name: &str) -> Option<&Arc<dyn StorageBackend>> {
+        self.tiers
+            .iter()
+            .find(|(tier, _)| tier == name)
+            .map(|(_, backend)| backend)
+    }
+
+    fn require_tier(&self, name: &str) -> Result<&Arc<dyn StorageBackend>, StorageError> {
+        self.tier(name)
+            .ok_or_else(|| StorageError::Config(format!("unknown storage tier: {}", name)))
+    }
+
+    /// 返回保存了 key 的存储层名称, 优先热存储层
+    pub async fn locate(&self, key: &str) -> Result<&str, StorageError> {
+        for (name, backend) in &self.tiers {
+            if backend.exists(key).await? {
+                return Ok(name);
+            }
+        }
+        Err(StorageError::NotFound(key.to_string()))
+    }
+
+    pub async fn get(&self, key: &str) -> Result<(String, Vec<u8>), StorageError> {
+        let tier = self.locate(key).await?;
+        let data = self.require_tier(tier)?.get(key).await?;
+        Ok((tier.to_string(), data))
+    }
+
+    pub async fn local_file(&self, key: &str) -> Result<(String, LocalFile), StorageError> {
+        let tier = self.locate(key).await?;
+        let local = self.local_file_in(tier, key).await?;
+        Ok((tier.to_string(), local))
+    }
+
+    pub async fn local_file_in(&self, tier: &str, key: &str) -> Result<LocalFile, StorageError> {
+        self.require_tier(tier)?.local_file(key).await
+    }
+
+    /// 列出所有存储层中 prefix 下的对象, 返回 (存储层, key).
+    /// 序列迁移过程中同一 key 可能同时存在于两层, 只保留靠前的存储层.
+    pub async fn list(&self, prefix: &str) -> Result<Vec<(String, String)>, StorageError> {
+        let mut seen = HashSet::new();
+        let mut result = vec![];
+        for (name, backend) in &self.tiers {
+            for key in backend.list(prefix).await? {
+                if seen.insert(key.clone()) {
+                    result.push((name.clone(), key));
+                }
+            }
+        }
+        result.sort_by(|a, b| a.1.cmp(&b.1));
+        Ok(result)
+    }
+
+    /// 分页版本的 list, 按 key 顺序返回 start_after 之后的最多 limit 个对象
+    pub async fn list_page(
+        &self,
+        prefix: &str,
+        start_after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, String)>, StorageError> {
+        let mut seen = HashSet::new();
+        let mut result = vec![];
+        for (name, backend) in &self.tiers {
+            for key in backend.list_page(prefix, start_after, limit).await? {
+                if seen.insert(key.clone()) {
+                    result.push((name.clone(), key));
+                }
+            }
+        }
+        result.sort_by(|a, b| a.1.cmp(&b.1));
+        result.truncate(limit);
+        Ok(result)
+    }
+
+    /// 将 prefix 下的对象从 from 层迁移到 to 层, 全部复制并校验后再删除源对象.
+    /// 返回迁移的对象数量, 中途失败时源对象保持不变, 可以重新执行.
+    pub async fn migrate(&self, prefix: &str, from: &str, to: &str) -> Result<usize, StorageError> {
+        if from == to {
+            return Err(StorageError::Config(format!(
+                "cannot migrate {} to the same tier {}",
+                prefix, from
+            )));
+        }
+        let source = self.require_tier(from)?;
+        let target = self.require_tier(to)?;
+        let keys = source.list(prefix).await?;
+        for key in &keys {
+            copy_verified(source, key, target, key).await?;
+        }
+        for key in &keys {
+            source.delete(key).await?;
+        }
+        Ok(keys.len())
+    }
+
+    /// 将 from_tier 层的 from_key 移动为 to_tier 层的 to_key, 复制并校验后再删除源对象
+    pub async fn move_object(
+        &self,
+        from_tier: &str,
+        from_key: &str,
+        to_tier: &str,
+        to_key: &str,
+    ) -> Result<(), StorageError> {
+        let source = self.require_tier(from_tier)?;
+        let target = self.require_tier(to_tier)?;
+        copy_verified(source, from_key, target, to_key).await?;
+        source.delete(from_key).await
+    }
+
+    /// 从所有存储层删除 key, 对象不存在时不报错
+    pub async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        for (_, backend) in &self.tiers {
+            backend.delete(key).await?;
+        }
+        Ok(())
+    }
+
+    /// 删除所有存储层中 prefix 下的对象, 返回删除的数量
+    pub async fn delete_prefix(&self, prefix: &str) -> Result<usize, StorageError> {
+        let mut removed = 0;
+        for (_, backend) in &self.tiers {
+            for key in backend.list(prefix).await? {
+                backend.delete(&key).await?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+async fn copy_verified(
+    source: &Arc<dyn StorageBackend>,
+    from_key: &str,
+    target: &Arc<dyn StorageBackend>,
+    to_key: &str,
+) -> Result<(), StorageError> {
+    let data = source.get(from_key).await?;
+    let size = data.len();
+    target.put(to_key, data).await?;
+    // 读回校验长度, 多读一个字节以发现目标对象比源对象长的情况
+    let copied = target.get_range(to_key, 0..size as u64 + 1).await?;
+    if copied.len() != size {
+        return Err(StorageError::Io(format!(
+            "{}: size mismatch after copy to {}",
+            from_key, to_key
+        )));
+    }
+    Ok(())
+}
+
+static TIERED_STORAGE: LazyLock<Arc<TieredStorage>> = LazyLock::new(|| {
+    let config = server_config::load_config().unwrap();
+    Arc::new(
+        TieredStorage::from_config(dicom_backend(), &config.local_storage)
+            .expect("failed to create storage tiers"),
+    )
+});
+
+/// DICOM 文件分层存储, 热存储层与 dicom_backend() 相同
+pub fn tiered_storage() -> Arc<TieredStorage> {
+    TIERED_STORAGE.clone()
+}
+
+/// 启动时清理所有存储层及 JSON 存储中写入中断遗留的临时文件
+pub async fn cleanup_interrupted_writes() -> Result<usize, StorageError> {
+    let mut removed = json_backend().cleanup_temp_files().await?;
+    for (_, backend) in &tiered_storage().tiers {
+        removed += backend.cleanup_temp_files().await?;
+    }
+    Ok(removed)
+}
+
+/// 在后台执行 cleanup_interrupted_writes, 各写入存储的服务启动时调用
+pub fn spawn_cleanup_interrupted_writes() {
+    tokio::spawn(async {
+        let rlogger = get_logger();
+        let logger = rlogger.new(o!("common"=>"cleanup_interrupted_writes"));
+        match cleanup_interrupted_writes().await {
+            Ok(0) => {}
+            Ok(removed) => info!(logger, "Removed {} interrupted temp files", removed),
+            Err(e) => warn!(logger, "Failed to clean up interrupted temp files: {}", e),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage_backend::LocalDiskBackend;
+
+    #[tokio::test]
+    async fn test_tiered_storage_move_and_delete() {
+        let hot_root = tempfile::tempdir().unwrap();
+        let cold_root = tempfile::tempdir().unwrap();
+        let storage = TieredStorage::new(
+            Arc::new(LocalDiskBackend::new(hot_root.path())),
+            vec![(
+                "cold".to_string(),
+                Arc::new(LocalDiskBackend::new(cold_root.path())) as Arc<dyn StorageBackend>,
+            )],
+        );
+        let cold = storage.tier("cold").unwrap().clone();
+        cold.put("t1/20240101/st/se1/1.dcm", b"111".to_vec()).await.unwrap();
+        cold.put("t1/20240101/st/se1/2.dcm", b"222".to_vec()).await.unwrap();
+
+        storage
+            .move_object("cold", "t1/20240101/st/se1/1.dcm", HOT_TIER, "t1/_trash/d1/1.dcm")
+            .await
+            .unwrap();
+        assert!(!cold.exists("t1/20240101/st/se1/1.dcm").await.unwrap());
+        assert_eq!(storage.locate("t1/_trash/d1/1.dcm").await.unwrap(), HOT_TIER);
+
+        assert_eq!(storage.delete_prefix("t1").await.unwrap(), 2);
+        assert!(storage.list("t1").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_tiered_storage_migrate() {
+        let hot_root = tempfile::tempdir().unwrap();
+        let cold_root = tempfile::tempdir().unwrap();
+        let storage = TieredStorage::new(
+            Arc::new(LocalDiskBackend::new(hot_root.path())),
+            vec![(
+                "cold".to_string(),
+                Arc::new(LocalDiskBackend::new(cold_root.path())) as Arc<dyn StorageBackend>,
+            )],
+        );
+        let hot = storage.tier(HOT_TIER).unwrap().clone();
+        hot.put("t1/20240101/st/se1/1.dcm", b"111".to_vec()).await.unwrap();
+        hot.put("t1/20240101/st/se1/2.dcm", b"222".to_vec()).await.unwrap();
+        hot.put("t1/20240101/st/se2/3.dcm", b"333".to_vec()).await.unwrap();
+
+        assert_eq!(
+            storage.migrate("t1/20240101/st/se1", HOT_TIER, "cold").await.unwrap(),
+            2
+        );
+        assert!(!hot.exists("t1/20240101/st/se1/1.dcm").await.unwrap());
+        assert_eq!(storage.locate("t1/20240101/st/se1/1.dcm").await.unwrap(), "cold");
+        assert_eq!(
+            storage.get("t1/20240101/st/se1/2.dcm").await.unwrap(),
+            ("cold".to_string(), b"222".to_vec())
+        );
+
+        // 检查下的序列分布在不同存储层时合并列出
+        let listed = storage.list("t1/20240101/st").await.unwrap();
+        assert_eq!(
+            listed,
+            vec![
+                ("cold".to_string(), "t1/20240101/st/se1/1.dcm".to_string()),
+                ("cold".to_string(), "t1/20240101/st/se1/2.dcm".to_string()),
+                (HOT_TIER.to_string(), "t1/20240101/st/se2/3.dcm".to_string()),
+            ]
+        );
+
+        // 迁回热存储层
+        storage.migrate("t1/20240101/st/se1", "cold", HOT_TIER).await.unwrap();
+        assert_eq!(storage.locate("t1/20240101/st/se1/1.dcm").await.unwrap(), HOT_TIER);
+        assert!(storage.migrate("t1/20240101/st/se1", "cold", "cold").await.is_err());
+        assert!(storage.migrate("t1/20240101/st/se1", "cold", "archive").await.is_err());
+    }
+}
@@ -3,8 +3,12 @@ use std::option::Option;
 use crate::dicom_object_meta::{make_image_info, make_state_info};
 use crate::dicom_utils::get_int_value;
 use crate::message_sender::MessagePublisher;
+use crate::{fixity, rejection, storage_backend};
 use dashmap::DashMap;
-use database::dicom_meta::{DicomImageMeta, DicomStateMeta, DicomStoreMeta, TransferStatus};
+use database::dicom_dbtype::BoundedString;
+use database::dicom_meta::{
+    DicomImageMeta, DicomRejection, DicomStateMeta, DicomStoreMeta, TransferStatus,
+};
 use dicom_dictionary_std::tags;
 use dicom_encoding::snafu::Whatever;
 use dicom_object::file::CharacterSetOverride;
@@ -157,7 +161,7 @@ pub fn deduplicate_state_metas(state_metas: Vec<DicomStateMeta>) -> Vec<DicomSta
 use futures::StreamExt;
 pub async fn group_dicom_state(
     messages: &[DicomStoreMeta],
-) -> Result<(Vec<DicomStateMeta>, Vec<DicomImageMeta>), ReadError> {
+) -> Result<(Vec<DicomStateMeta>, Vec<DicomImageMeta>, Vec<DicomRejection>), ReadError> {
     let logger = get_logger();
     let logger = logger.new(o!("thread" => "group_dicom_state"));
     info!(
@@ -201,49 +205,77 @@ pub async fn group_dicom_state(
             }
         })
         .buffer_unordered(concurrency)
-        .collect::<Vec<Option<(DicomStateMeta, DicomImageMeta)>>>()
+        .collect::<Vec<Option<(DicomStateMeta, DicomImageMeta, Vec<DicomRejection>)>>>()
         .await;
 
     // 4. 汇总结果
     let mut state_metas = Vec::with_capacity(messages.len());
     let mut image_entities = Vec::with_capacity(messages.len());
+    let mut rejections = vec![];
 
     for res in results.into_iter().flatten() {
         state_metas.push(res.0);
         image_entities.push(res.1);
+        rejections.extend(res.2);
     }
 
     let state_metas = deduplicate_state_metas(state_metas);
-    Ok((state_metas, image_entities))
+    Ok((state_metas, image_entities, rejections))
 }
 
 /// 封装原有的单个文件处理逻辑（保持逻辑清晰）
 async fn process_single_dicom(
     message: &DicomStoreMeta,
     logger: &Logger,
-) -> Option<(DicomStateMeta, DicomImageMeta)> {
+) -> Option<(DicomStateMeta, DicomImageMeta, Vec<DicomRejection>)> {
     let space_size = Option::from(message.file_size);
 
-    // 检查存在性
-    if !fs::exists(&message.file_path.as_str()).unwrap_or(false) {
-        error!(logger, "File not found: {}", message.file_path);
-        return None;
-    }
+    // 检查存在性, 对象存储时下载到临时文件
+    let backend = storage_backend::dicom_backend();
+    let local_file = match backend.local_file(message.file_path.as_str()).await {
+        Ok(local_file) => local_file,
+        Err(e) => {
+            error!(logger, "File not found: {} ({})", message.file_path, e);
+            return None;
+        }
+    };
+    let local_path = local_file.path().to_string_lossy().to_string();
 
     // 权限检查
-    if !can_read_file(&message.file_path.as_str()) {
+    if !can_read_file(&local_path.as_str()) {
         error!(logger, "No read permission: {}", message.file_path);
         return None;
     }
 
+    let mut checksum = message.checksum.clone();
     // 转换传输语法（此时受 DashMap 保护，不会有并发冲突）
     if message.transfer_status == TransferStatus::NeedTransfer {
-        if let Err(e) = change_transfersyntax(&message.file_path.as_str()).await {
-            warn!(
-                logger,
-                "change_transfersyntax Error: {:?} for {}", e, message.file_path
-            );
-            // 决定是否在转换失败时继续...
+        match change_transfersyntax(&local_path).await {
+            Ok(()) => {
+                // 磁盘存储时文件已原地替换, put_file 不做任何操作
+                match backend
+                    .put_file(message.file_path.as_str(), local_file.path())
+                    .await
+                {
+                    // 文件内容已变化, 重新计算校验值
+                    Ok(()) => {
+                        checksum = fixity::checksum_file(local_file.path())
+                            .ok()
+                            .map(BoundedString::<64>::make)
+                    }
+                    Err(e) => warn!(
+                        logger,
+                        "Failed to save transcoded file {}: {}", message.file_path, e
+                    ),
+                }
+            }
+            Err(e) => {
+                warn!(
+                    logger,
+                    "change_transfersyntax Error: {:?} for {}", e, message.file_path
+                );
+                // 决定是否在转换失败时继续...
+            }
         }
     }
 
@@ -251,13 +283,31 @@ async fn process_single_dicom(
     match OpenFileOptions::new()
         .charset_override(CharacterSetOverride::AnyVr)
         .read_until(tags::PIXEL_DATA)
-        .open_file(&message.file_path.as_str())
+        .open_file(&local_path)
     {
         Ok(dicom_obj) => {
             let state_meta = make_state_info(&message.tenant_id.as_str(), &dicom_obj);
             let image_entity = make_image_info(&message.tenant_id.as_str(), &dicom_obj, space_size);
-            if state_meta.is_ok() && image_entity.is_ok() {
-                Some((state_meta.unwrap(), image_entity.unwrap()))
+            if let Ok(mut state_meta) = state_meta
+                && let Ok(mut image_entity) = image_entity
+            {
+                state_meta.storage_layout = message.storage_layout;
+                image_entity.pixel_data_location =
+                    Some(BoundedString::<512>::make_str(message.file_path.as_str()));
+                image_entity.checksum = checksum;
+                // IHE IOCM 拒绝说明, 本身作为普通实例保存
+                let rejections =
+                    rejection::parse_rejection_note(message.tenant_id.as_str(), &dicom_obj)
+                        .unwrap_or_default();
+                if !rejections.is_empty() {
+                    info!(
+                        logger,
+                        "Rejection note {} references {} instances",
+                        message.sop_uid,
+                        rejections.len()
+                    );
+                }
+                Some((state_meta, image_entity, rejections))
             } else {
                 error!(
                     logger,
@@ -385,7 +435,8 @@ pub async fn change_transfersyntax(src_file: &str) -> Result<(), Whatever> {
         // Second Transfer Syntax conversion
         gdcm_conv::TransferSyntax::ImplicitVRLittleEndian,
     ) {
-        Ok(buffer) => match fs::write(src_file, buffer) {
+        // 原子替换, 写入中断时原文件保持不变
+        Ok(buffer) => match storage_backend::write_file_atomic(Path::new(src_file), &buffer) {
             Ok(()) => {}
             Err(e) => {
                 whatever!("Failed to write to buffer: {}", e);
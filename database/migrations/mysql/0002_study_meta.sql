@@ -0,0 +1,50 @@
+-- 版本 2: 检查级汇总 dicom_study_meta, 供 QIDO-RS / C-FIND 检查级查询
+-- 保存序列和实例时按检查重新计算, 这里根据已有的数据生成
+
+create table if not exists dicom_study_meta
+(
+    tenant_id           varchar(64)  not null,
+    study_uid           varchar(64)  not null,
+    patient_id          varchar(64)  not null,
+    patient_name        varchar(64)  null,
+    patient_sex         varchar(1)   null,
+    patient_birth_date  date         null,
+    study_date          date         not null,
+    study_time          time         null,
+    accession_number    varchar(16)  null,
+    study_id            varchar(16)  null,
+    study_description   varchar(64)  null,
+    modalities_in_study varchar(256) not null default '' comment '逗号分隔',
+    number_of_series    int          not null default 0,
+    number_of_instances int          not null default 0,
+    updated_time        datetime(6)  not null,
+    primary key (tenant_id, study_uid),
+    index idx_study_meta_patient (tenant_id, patient_id),
+    index idx_study_meta_date (tenant_id, study_date),
+    index idx_study_meta_accession (tenant_id, accession_number)
+) default charset = utf8mb4 comment '检查级汇总, 数量不含已拒绝的实例';
+
+insert ignore into dicom_study_meta (tenant_id, study_uid, patient_id, patient_name, patient_sex, patient_birth_date,
+                                     study_date, study_time, accession_number, study_id, study_description,
+                                     modalities_in_study, number_of_series, number_of_instances, updated_time)
+select s.tenant_id,
+       s.study_uid,
+       max(s.patient_id),
+       max(s.patient_name),
+       max(s.patient_sex),
+       max(s.patient_birth_date),
+       max(s.study_date),
+       max(s.study_time),
+       max(s.accession_number),
+       max(s.study_id),
+       max(s.study_description),
+       coalesce(group_concat(distinct s.modality order by s.modality separator ','), ''),
+       count(*),
+       (select count(*)
+        from dicom_image_meta i
+        where i.tenant_id = s.tenant_id
+          and i.study_uid = s.study_uid
+          and (i.image_status is null or i.image_status <> 'REJECTED')),
+       now(6)
+from dicom_state_meta s
+group by s.tenant_id, s.study_uid;
@@ -0,0 +1,523 @@
+-- 版本 1: 初始结构, 与 Script/Pg.sql 建好的结构一致
+-- 所有语句都可以在已有的数据库上执行. 按早期 Script/Pg.sql 建好的数据库会补充之后增加的列、索引和表,
+-- 并按已保存的实例初始化租户用量, 已有数据保持不变
+
+create table if not exists dicom_state_meta
+(
+    tenant_id                varchar(64) not null,
+    patient_id               varchar(64) not null,
+    study_uid                varchar(64) not null,
+    series_uid               varchar(64) not null,
+    study_uid_hash           varchar(20) not null,
+    series_uid_hash          varchar(20) not null,
+    patient_name             varchar(64),
+    patient_sex              varchar(1),
+    patient_birth_date       date,
+    patient_birth_time       time,
+    patient_age              varchar(16),
+    patient_size             double precision,
+    patient_weight           double precision,
+    pregnancy_status         integer,
+    study_date               date        not null,
+    study_date_origin        varchar(8)  not null,
+    study_time               time,
+    accession_number         varchar(16),
+    study_id                 varchar(16),
+    study_description        varchar(64),
+    modality                 varchar(16),
+    series_number            integer,
+    series_date              date,
+    series_time              time,
+    series_description       varchar(256),
+    body_part_examined       varchar(64),
+    protocol_name            varchar(64),
+    series_related_instances integer,
+    created_time             timestamp,
+    updated_time             timestamp,
+    storage_layout           integer     not null default 0,
+    primary key (tenant_id, study_uid, series_uid)
+);
+
+-- 早于 storage_layout 的数据库
+alter table dicom_state_meta add column if not exists storage_layout integer not null default 0;
+comment on column dicom_state_meta.storage_layout is 'DICOM 文件目录布局版本, 对应 local_storage.layouts, 0 为内置布局';
+
+
+create unique index if not exists index_state_unique
+    on dicom_state_meta (tenant_id, study_uid, series_uid, accession_number);
+
+create unique index if not exists index_state_unique4study
+    on dicom_state_meta (tenant_id, study_uid,series_uid);
+
+create unique index if not exists index_state_unique4patient
+    on dicom_state_meta (tenant_id,patient_id, study_uid,series_uid);
+
+create table if not exists dicom_json_meta
+(
+    tenant_id                varchar(64) not null,
+    study_uid                varchar(64) not null,
+    series_uid               varchar(64) not null,
+    study_uid_hash           varchar(20) not null,
+    series_uid_hash          varchar(20) not null,
+    study_date_origin        varchar(8)  not null,
+    flag_time                timestamp   not null,
+    created_time             timestamp   not null default current_timestamp(6),
+    json_status              int         not null default 0,
+    retry_times              int         not null default 0,
+    constraint PK_dicom_json_meta primary key (tenant_id, study_uid, series_uid)
+);
+
+
+--------------------------
+---------------dicom_image_meta------------------
+----------------------------
+create table if not exists dicom_image_meta
+(
+    tenant_id                  varchar(64) not null,
+    patient_id                 varchar(64) not null,
+    study_uid                  varchar(64) not null,
+    series_uid                 varchar(64) not null,
+    sop_uid                    varchar(64) not null,
+    study_uid_hash             varchar(20) not null,
+    series_uid_hash            varchar(20) not null,
+    content_date               date,
+    content_time               time,
+    instance_number            integer,
+    image_type                 varchar(128),
+    image_orientation_patient  varchar(128),
+    image_position_patient     varchar(64),
+    slice_thickness            double precision,
+    spacing_between_slices     double precision,
+    slice_location             double precision,
+    samples_per_pixel          integer,
+    photometric_interpretation varchar(32),
+    width                      integer,
+    columns                    integer,
+    bits_allocated             integer,
+    bits_stored                integer,
+    high_bit                   integer,
+    pixel_representation       integer,
+    rescale_intercept          double precision,
+    rescale_slope              double precision,
+    rescale_type               varchar(64),
+    window_center              varchar(64),
+    window_width               varchar(64),
+    transfer_syntax_uid        varchar(64) not null,
+    pixel_data_location        varchar(512),
+    thumbnail_location         varchar(512),
+    sop_class_uid              varchar(64) not null,
+    image_status               varchar(32),
+    space_size                 bigint,
+    created_time               timestamp,
+    updated_time               timestamp,
+    checksum                   varchar(64),
+    constraint pk_dicom_image_meta primary key (tenant_id, study_uid, series_uid, sop_uid)
+);
+
+-- 早于 checksum 的数据库
+alter table dicom_image_meta add column if not exists checksum varchar(64);
+
+comment on column dicom_image_meta.tenant_id is '租户ID';
+
+comment on column dicom_image_meta.patient_id is '患者ID';
+
+comment on column dicom_image_meta.study_uid is '检查UID';
+
+comment on column dicom_image_meta.series_uid is '序列UID';
+
+comment on column dicom_image_meta.sop_uid is '实例UID';
+
+comment on column dicom_image_meta.study_uid_hash is '检查UID哈希值';
+
+comment on column dicom_image_meta.series_uid_hash is '序列UID哈希值';
+
+comment on column dicom_image_meta.content_date is '内容日期';
+
+comment on column dicom_image_meta.content_time is '内容时间';
+
+comment on column dicom_image_meta.instance_number is '实例编号';
+
+comment on column dicom_image_meta.image_type is '图像类型';
+
+comment on column dicom_image_meta.image_orientation_patient is '图像方向(患者坐标系)';
+
+comment on column dicom_image_meta.image_position_patient is '图像位置(患者坐标系)';
+
+comment on column dicom_image_meta.slice_thickness is '层厚';
+
+comment on column dicom_image_meta.spacing_between_slices is '层间距';
+
+comment on column dicom_image_meta.slice_location is '切片位置';
+
+comment on column dicom_image_meta.samples_per_pixel is '每个像素采样数';
+
+comment on column dicom_image_meta.photometric_interpretation is '光度解释';
+
+comment on column dicom_image_meta.width is '图像行数';
+
+comment on column dicom_image_meta.columns is '图像列数';
+
+comment on column dicom_image_meta.bits_allocated is '分配位数';
+
+comment on column dicom_image_meta.bits_stored is '存储位数';
+
+comment on column dicom_image_meta.high_bit is '高比特位';
+
+comment on column dicom_image_meta.pixel_representation is '像素表示法';
+
+comment on column dicom_image_meta.rescale_intercept is '重建截距';
+
+comment on column dicom_image_meta.rescale_slope is '重建斜率';
+
+comment on column dicom_image_meta.rescale_type is '重建类型';
+
+comment on column dicom_image_meta.window_center is '窗位中心';
+
+comment on column dicom_image_meta.window_width is '窗宽';
+
+comment on column dicom_image_meta.transfer_syntax_uid is '传输语法UID';
+
+comment on column dicom_image_meta.pixel_data_location is '像素数据位置';
+
+comment on column dicom_image_meta.thumbnail_location is '缩略图位置';
+
+comment on column dicom_image_meta.sop_class_uid is 'SOP类UID';
+
+comment on column dicom_image_meta.image_status is '图像状态';
+
+comment on column dicom_image_meta.space_size is '占用空间大小';
+
+comment on column dicom_image_meta.created_time is '创建时间';
+
+comment on column dicom_image_meta.updated_time is '更新时间';
+
+comment on column dicom_image_meta.checksum is '文件内容MD5';
+
+-- 存储对账按文件路径查找实例
+create index if not exists idx_image_pixel_location on dicom_image_meta (pixel_data_location);
+
+
+---------------------------------
+create table if not exists dicom_object_meta
+(
+    trace_id            varchar(36)   not null,
+    worker_node_id      varchar(64)   not null,
+    tenant_id           varchar(64)   not null,
+    patient_id          varchar(64)   not null,
+    study_uid           varchar(64)   null,
+    series_uid          varchar(64)   null,
+    sop_uid             varchar(64)   null,
+    file_size           bigint        null,
+    file_path           varchar(512) null,
+    transfer_syntax_uid varchar(64)   null,
+    number_of_frames    int           null,
+    series_uid_hash     VARCHAR(20)   null,
+    study_uid_hash      VARCHAR(20)   null,
+    accession_number    varchar(64)   null,
+    target_ts           varchar(64)   null,
+    study_date          date          null,
+    transfer_status     varchar(64)   null,
+    source_ip           varchar(24)   null,
+    source_ae           varchar(64)   null,
+    created_time        timestamp     not null default CURRENT_TIMESTAMP,
+    checksum            varchar(64)   null,
+    constraint pk_dicom_object_meta primary key (trace_id)
+);
+
+-- 早于 checksum 的数据库
+alter table dicom_object_meta add column if not exists checksum varchar(64) null;
+
+comment on column dicom_object_meta.tenant_id      is '租户ID';
+comment on column dicom_object_meta.patient_id     is '患者ID';
+comment on column dicom_object_meta.trace_id       is '全局唯一追踪ID，作为主键';
+comment on column dicom_object_meta.worker_node_id is '工作节点 ID';
+
+create index if not exists idx_dicom_object_meta on dicom_object_meta (tenant_id, patient_id,study_uid,series_uid,sop_uid);
+create index if not exists idx_dicom_object_meta_date on dicom_object_meta (tenant_id, study_date);
+create index if not exists idx_dicom_object_meta_createdate on dicom_object_meta (tenant_id, created_time);
+
+-----------------------收图记录-------------------------
+-- 创建访问日志表
+CREATE TABLE IF NOT EXISTS dicom_access_log (
+    log_id              VARCHAR(36)   NOT NULL,
+    tenant_id           VARCHAR(64)   NOT NULL,
+    user_id             VARCHAR(64)   NOT NULL,
+    username            VARCHAR(128)  NOT NULL,
+    operation_type      VARCHAR(32)   NOT NULL,
+    operation_path      VARCHAR(512)  NOT NULL,
+    operation_method    VARCHAR(10)   NOT NULL,
+    operation_result    VARCHAR(16)   NOT NULL,
+    resource_type       VARCHAR(32)   NOT NULL,
+    resource_id         VARCHAR(64)   NOT NULL,
+    ip_address          VARCHAR(45)   NOT NULL,
+    user_agent          VARCHAR(512)  NULL,
+    response_time       BIGINT        NOT NULL,
+    description         VARCHAR(1024) NULL,
+    created_time        TIMESTAMP     NOT NULL,
+    PRIMARY KEY (log_id)
+);
+
+-- 为访问日志表创建索引
+create index if not exists idx_access_log_tenant ON dicom_access_log (tenant_id);
+create index if not exists idx_access_log_user ON dicom_access_log (user_id);
+create index if not exists idx_access_log_resource ON dicom_access_log (resource_type, resource_id);
+create index if not exists idx_access_log_time ON dicom_access_log (created_time);
+create index if not exists idx_access_log_operation ON dicom_access_log (operation_type, operation_result);
+
+-----------------------MPPS 执行记录-------------------------
+create table if not exists dicom_mpps_meta
+(
+    tenant_id            varchar(64) not null,
+    sop_instance_uid     varchar(64) not null,
+    patient_id           varchar(64),
+    study_uid            varchar(64),
+    accession_number     varchar(16),
+    modality             varchar(16),
+    pps_id               varchar(16),
+    pps_status           varchar(16) not null,
+    pps_description      varchar(64),
+    performed_station_ae varchar(16),
+    start_datetime       timestamp,
+    end_datetime         timestamp,
+    referenced_series    text[]      not null default '{}',
+    source_ip            varchar(24) not null,
+    source_ae            varchar(64) not null,
+    created_time         timestamp   not null,
+    updated_time         timestamp   not null,
+    primary key (tenant_id, sop_instance_uid)
+);
+
+comment on column dicom_mpps_meta.pps_status is 'IN PROGRESS / COMPLETED / DISCONTINUED';
+comment on column dicom_mpps_meta.referenced_series is 'PerformedSeriesSequence 引用的序列UID';
+
+create index if not exists idx_mpps_study on dicom_mpps_meta (tenant_id, study_uid);
+
+-----------------------自动路由转发任务-------------------------
+create table if not exists dicom_forward_task
+(
+    task_id         varchar(36)  not null primary key,
+    tenant_id       varchar(64)  not null,
+    rule_name       varchar(64)  not null,
+    destination     varchar(64)  not null,
+    study_uid       varchar(64)  not null,
+    series_uid      varchar(64)  not null,
+    sop_uid         varchar(64)  not null,
+    file_path       varchar(512) not null,
+    status          varchar(16)  not null,
+    retry_times     int          not null default 0,
+    last_error      varchar(512),
+    next_retry_time timestamp    not null,
+    created_time    timestamp    not null,
+    updated_time    timestamp    not null
+);
+
+comment on column dicom_forward_task.status is 'PENDING / RUNNING / SUCCESS / FAILED';
+
+create index if not exists idx_forward_task_due on dicom_forward_task (status, next_retry_time);
+create index if not exists idx_forward_task_study on dicom_forward_task (tenant_id, study_uid);
+
+-----------------------历史检查预取-------------------------
+create table if not exists dicom_prefetch_task
+(
+    tenant_id         varchar(64) not null,
+    source            varchar(64) not null,
+    study_uid         varchar(64) not null,
+    patient_id        varchar(64) not null,
+    trigger_study_uid varchar(64) not null,
+    study_date        date,
+    modalities        varchar(64),
+    status            varchar(16) not null,
+    completed         int         not null default 0,
+    failed            int         not null default 0,
+    last_error        varchar(512),
+    created_time      timestamp   not null,
+    updated_time      timestamp   not null,
+    primary key (tenant_id, source, study_uid)
+);
+
+comment on column dicom_prefetch_task.source is 'Q/R 源节点名称';
+comment on column dicom_prefetch_task.status is 'RUNNING / SUCCESS / FAILED';
+
+create index if not exists idx_prefetch_patient on dicom_prefetch_task (tenant_id, patient_id);
+
+-----------------------分层存储-------------------------
+create table if not exists dicom_series_tier
+(
+    tenant_id         varchar(64) not null,
+    study_uid         varchar(64) not null,
+    series_uid        varchar(64) not null,
+    study_date_origin varchar(8)  not null,
+    tier              varchar(32) not null,
+    last_access_time  timestamp   not null,
+    updated_time      timestamp   not null,
+    primary key (tenant_id, study_uid, series_uid)
+);
+
+comment on column dicom_series_tier.tier is '存储层名称, 对应 local_storage.tiers, 热存储层为 hot';
+comment on column dicom_series_tier.last_access_time is 'WADO 最近访问时间';
+
+create index if not exists idx_series_tier_access on dicom_series_tier (tier, last_access_time);
+
+-----------------------完整性校验-------------------------
+create table if not exists dicom_fixity_check
+(
+    tenant_id         varchar(64)  not null,
+    study_uid         varchar(64)  not null,
+    series_uid        varchar(64)  not null,
+    sop_uid           varchar(64)  not null,
+    file_path         varchar(512) not null,
+    expected_checksum varchar(64)  not null,
+    actual_checksum   varchar(64),
+    status            varchar(16)  not null,
+    checked_time      timestamp,
+    primary key (tenant_id, study_uid, series_uid, sop_uid)
+);
+
+comment on column dicom_fixity_check.status is 'OK / MISMATCH / MISSING';
+comment on column dicom_fixity_check.actual_checksum is '重新计算的 MD5, 文件不存在时为空';
+
+create index if not exists idx_fixity_check_time on dicom_fixity_check (checked_time);
+create index if not exists idx_fixity_check_status on dicom_fixity_check (status, checked_time);
+
+-----------------------租户存储用量-------------------------
+create table if not exists dicom_tenant_usage
+(
+    tenant_id      varchar(64) not null primary key,
+    total_bytes    bigint      not null default 0,
+    instance_count bigint      not null default 0,
+    study_count    bigint      not null default 0,
+    updated_time   timestamp   not null
+);
+
+comment on table dicom_tenant_usage is '租户当前存储用量, 保存 dicom_image_meta 时增量更新';
+
+create table if not exists dicom_tenant_usage_daily
+(
+    tenant_id      varchar(64) not null,
+    usage_date     date        not null,
+    total_bytes    bigint      not null,
+    instance_count bigint      not null,
+    study_count    bigint      not null,
+    updated_time   timestamp   not null,
+    primary key (tenant_id, usage_date)
+);
+
+comment on table dicom_tenant_usage_daily is '租户每天最后一次更新后的存储用量';
+
+-- 升级已有数据库时, 按已保存的实例初始化用量
+insert into dicom_tenant_usage (tenant_id, total_bytes, instance_count, study_count, updated_time)
+select tenant_id, coalesce(sum(space_size), 0), count(*), count(distinct study_uid), now()
+from dicom_image_meta
+group by tenant_id
+on conflict (tenant_id) do nothing;
+
+-----------------------删除记录-------------------------
+create table if not exists dicom_deletion
+(
+    deletion_id    varchar(36) not null primary key,
+    tenant_id      varchar(64) not null,
+    level          varchar(16) not null,
+    study_uid      varchar(64) not null,
+    series_uid     varchar(64),
+    sop_uid        varchar(64),
+    instance_count bigint      not null default 0,
+    total_bytes    bigint      not null default 0,
+    status         varchar(16) not null,
+    requested_by   varchar(64),
+    purge_after    timestamp,
+    created_time   timestamp   not null,
+    updated_time   timestamp   not null
+);
+
+comment on column dicom_deletion.level is 'STUDY / SERIES / INSTANCE';
+comment on column dicom_deletion.status is 'TRASHED / PURGED / RESTORED';
+comment on column dicom_deletion.purge_after is '回收站保留期结束时间, 未启用回收站时为空';
+
+create index if not exists idx_deletion_tenant on dicom_deletion (tenant_id, created_time);
+create index if not exists idx_deletion_purge on dicom_deletion (status, purge_after);
+
+------------------------拒绝记录(IHE IOCM)-------------------------
+create table if not exists dicom_rejection
+(
+    tenant_id      varchar(64) not null,
+    study_uid      varchar(64) not null,
+    series_uid     varchar(64) not null,
+    sop_uid        varchar(64) not null,
+    reason_code    varchar(16) not null,
+    reason_meaning varchar(64) not null,
+    kos_sop_uid    varchar(64) not null,
+    rejected_time  timestamp   not null,
+    purged_time    timestamp,
+    primary key (tenant_id, study_uid, series_uid, sop_uid)
+);
+
+comment on column dicom_rejection.reason_code is '113001 质量原因 / 113037 患者安全 / 113038 错误的工作列表条目 / 113039 数据保留期已过';
+comment on column dicom_rejection.kos_sop_uid is '拒绝说明(Key Object Selection)的 SOP Instance UID';
+comment on column dicom_rejection.purged_time is '文件清除时间, 只有 113039 会被清除';
+
+create index if not exists idx_rejection_purge on dicom_rejection (reason_code, purged_time);
+
+------------------------患者/检查属性修改审计-------------------------
+create table if not exists dicom_change_audit
+(
+    audit_id     varchar(36) not null primary key,
+    tenant_id    varchar(64) not null,
+    operation    varchar(32) not null,
+    patient_id   varchar(64) not null,
+    study_uid    varchar(64) not null,
+    before_value text        not null,
+    after_value  text        not null,
+    file_count   bigint      not null default 0,
+    requested_by varchar(64),
+    created_time timestamp   not null
+);
+
+comment on column dicom_change_audit.operation is 'MERGE_PATIENT / MOVE_STUDY / UPDATE_PATIENT / UPDATE_STUDY';
+comment on column dicom_change_audit.patient_id is '修改前的患者ID';
+comment on column dicom_change_audit.before_value is '修改前的属性(JSON)';
+comment on column dicom_change_audit.after_value is '修改后的属性(JSON)';
+
+create index if not exists idx_change_audit_patient on dicom_change_audit (tenant_id, patient_id, created_time);
+create index if not exists idx_change_audit_study on dicom_change_audit (tenant_id, study_uid, created_time);
+
+------------------------数据保留候选-------------------------
+create table if not exists dicom_retention_candidate
+(
+    tenant_id    varchar(64) not null,
+    study_uid    varchar(64) not null,
+    patient_id   varchar(64) not null,
+    policy_name  varchar(64) not null,
+    expire_date  date        not null,
+    status       varchar(16) not null,
+    deletion_id  varchar(36),
+    created_time timestamp   not null,
+    updated_time timestamp   not null,
+    primary key (tenant_id, study_uid)
+);
+
+comment on column dicom_retention_candidate.policy_name is '决定保留期的策略(保留期最长的匹配策略)';
+comment on column dicom_retention_candidate.expire_date is '保留期结束日期';
+comment on column dicom_retention_candidate.status is 'PENDING / DELETED / KEPT';
+comment on column dicom_retention_candidate.deletion_id is '删除记录ID(dicom_deletion)';
+
+create index if not exists idx_retention_candidate_status on dicom_retention_candidate (status, expire_date);
+
+------------------------法律保全-------------------------
+create table if not exists dicom_legal_hold
+(
+    hold_id       varchar(36)  not null primary key,
+    tenant_id     varchar(64)  not null,
+    patient_id    varchar(64),
+    study_uid     varchar(64),
+    reason        varchar(256) not null,
+    created_by    varchar(64),
+    created_time  timestamp    not null,
+    released_by   varchar(64),
+    released_time timestamp
+);
+
+comment on column dicom_legal_hold.patient_id is '保全患者的所有检查, 与 study_uid 至少设置一个';
+comment on column dicom_legal_hold.released_time is '解除时间, 为空表示保全中';
+
+create index if not exists idx_legal_hold_patient on dicom_legal_hold (tenant_id, patient_id) where released_time is null;
+create index if not exists idx_legal_hold_study on dicom_legal_hold (tenant_id, study_uid) where released_time is null;
@@ -0,0 +1,57 @@
+-- 版本 2: 检查级汇总 dicom_study_meta, 供 QIDO-RS / C-FIND 检查级查询
+-- 保存序列和实例时按检查重新计算, 这里根据已有的数据生成
+
+create table if not exists dicom_study_meta
+(
+    tenant_id           varchar(64) not null,
+    study_uid           varchar(64) not null,
+    patient_id          varchar(64) not null,
+    patient_name        varchar(64),
+    patient_sex         varchar(1),
+    patient_birth_date  date,
+    study_date          date        not null,
+    study_time          time,
+    accession_number    varchar(16),
+    study_id            varchar(16),
+    study_description   varchar(64),
+    modalities_in_study varchar(16)[] not null default '{}',
+    number_of_series    integer     not null default 0,
+    number_of_instances integer     not null default 0,
+    updated_time        timestamp   not null,
+    primary key (tenant_id, study_uid)
+);
+
+comment on table dicom_study_meta is '检查级汇总, 数量不含已拒绝的实例';
+
+create index if not exists idx_study_meta_patient
+    on dicom_study_meta (tenant_id, patient_id);
+create index if not exists idx_study_meta_date
+    on dicom_study_meta (tenant_id, study_date);
+create index if not exists idx_study_meta_accession
+    on dicom_study_meta (tenant_id, accession_number);
+
+insert into dicom_study_meta (tenant_id, study_uid, patient_id, patient_name, patient_sex, patient_birth_date,
+                              study_date, study_time, accession_number, study_id, study_description,
+                              modalities_in_study, number_of_series, number_of_instances, updated_time)
+select s.tenant_id,
+       s.study_uid,
+       max(s.patient_id),
+       max(s.patient_name),
+       max(s.patient_sex),
+       max(s.patient_birth_date),
+       max(s.study_date),
+       max(s.study_time),
+       max(s.accession_number),
+       max(s.study_id),
+       max(s.study_description),
+       coalesce(array_agg(distinct s.modality order by s.modality) filter (where s.modality is not null), '{}'),
+       count(*),
+       (select count(*)
+        from dicom_image_meta i
+        where i.tenant_id = s.tenant_id
+          and i.study_uid = s.study_uid
+          and i.image_status is distinct from 'REJECTED'),
+       localtimestamp
+from dicom_state_meta s
+group by s.tenant_id, s.study_uid
+on conflict (tenant_id, study_uid) do nothing;
@@ -0,0 +1,390 @@
+-- 版本 1: 初始结构, 与 Script/Pg.sql 一致, SQLite 3.35+
+-- 时间字段按文本保存本地时间, 格式为 YYYY-MM-DD HH:MM:SS.SSS
+
+create table if not exists dicom_state_meta
+(
+    tenant_id                varchar(64)  not null,
+    patient_id               varchar(64)  not null,
+    study_uid                varchar(64)  not null,
+    series_uid               varchar(64)  not null,
+    study_uid_hash           varchar(20)  not null,
+    series_uid_hash          varchar(20)  not null,
+    patient_name             varchar(64)  null,
+    patient_sex              varchar(1)   null,
+    patient_birth_date       date         null,
+    patient_birth_time       time         null,
+    patient_age              varchar(16)  null,
+    patient_size             double       null,
+    patient_weight           double       null,
+    pregnancy_status         int          null,
+    study_date               date         not null,
+    study_date_origin        varchar(8)   not null,
+    study_time               time         null,
+    accession_number         varchar(16)  null,
+    study_id                 varchar(16)  null,
+    study_description        varchar(64)  null,
+    modality                 varchar(16)  null,
+    series_number            int          null,
+    series_date              date         null,
+    series_time              time         null,
+    series_description       varchar(256) null,
+    body_part_examined       varchar(64)  null,
+    protocol_name            varchar(64)  null,
+    series_related_instances int          null,
+    created_time             datetime     null,
+    updated_time             datetime     null,
+    -- DICOM 文件目录布局版本, 对应 local_storage.layouts, 0 为内置布局
+    storage_layout           int          not null default 0,
+    primary key (tenant_id, study_uid, series_uid)
+);
+create unique index if not exists index_state_unique
+    on dicom_state_meta (tenant_id, study_uid, series_uid, accession_number);
+create unique index if not exists index_state_unique4patient
+    on dicom_state_meta (tenant_id, patient_id, study_uid, series_uid);
+
+create table if not exists dicom_json_meta
+(
+    tenant_id         varchar(64) not null,
+    study_uid         varchar(64) not null,
+    series_uid        varchar(64) not null,
+    study_uid_hash    varchar(20) not null,
+    series_uid_hash   varchar(20) not null,
+    study_date_origin varchar(8)  not null,
+    flag_time         datetime    not null,
+    created_time      datetime    not null default current_timestamp,
+    json_status       int         not null default 0,
+    retry_times       int         not null default 0,
+    primary key (tenant_id, study_uid, series_uid)
+);
+
+-- ---------------------dicom_image_meta-------------------------
+create table if not exists dicom_image_meta
+(
+    tenant_id                  varchar(64)  not null,
+    patient_id                 varchar(64)  not null,
+    study_uid                  varchar(64)  not null,
+    series_uid                 varchar(64)  not null,
+    sop_uid                    varchar(64)  not null,
+    study_uid_hash             varchar(20)  not null,
+    series_uid_hash            varchar(20)  not null,
+    content_date               date         null,
+    content_time               time         null,
+    instance_number            int          null,
+    image_type                 varchar(128) null,
+    image_orientation_patient  varchar(128) null,
+    image_position_patient     varchar(64)  null,
+    slice_thickness            double       null,
+    spacing_between_slices     double       null,
+    slice_location             double       null,
+    samples_per_pixel          int          null,
+    photometric_interpretation varchar(32)  null,
+    width                      int          null,
+    columns                    int          null,
+    bits_allocated             int          null,
+    bits_stored                int          null,
+    high_bit                   int          null,
+    pixel_representation       int          null,
+    rescale_intercept          double       null,
+    rescale_slope              double       null,
+    rescale_type               varchar(64)  null,
+    window_center              varchar(64)  null,
+    window_width               varchar(64)  null,
+    transfer_syntax_uid        varchar(64)  not null,
+    pixel_data_location        varchar(512) null,
+    thumbnail_location         varchar(512) null,
+    sop_class_uid              varchar(64)  not null,
+    image_status               varchar(32)  null,
+    space_size                 bigint       null,
+    created_time               datetime     null,
+    updated_time               datetime     null,
+    -- 文件内容MD5
+    checksum                   varchar(64)  null,
+    primary key (tenant_id, study_uid, series_uid, sop_uid)
+);
+-- 存储对账按文件路径查找实例
+create index if not exists idx_image_pixel_location on dicom_image_meta (pixel_data_location);
+
+-- ---------------------dicom_object_meta-------------------------
+create table if not exists dicom_object_meta
+(
+    trace_id            varchar(36)  not null primary key,
+    worker_node_id      varchar(64)  not null,
+    tenant_id           varchar(64)  not null,
+    patient_id          varchar(64)  not null,
+    study_uid           varchar(64)  null,
+    series_uid          varchar(64)  null,
+    sop_uid             varchar(64)  null,
+    file_size           bigint       null,
+    file_path           varchar(512) null,
+    transfer_syntax_uid varchar(64)  null,
+    number_of_frames    int          null,
+    series_uid_hash     varchar(20)  null,
+    study_uid_hash      varchar(20)  null,
+    accession_number    varchar(64)  null,
+    target_ts           varchar(64)  null,
+    study_date          date         null,
+    transfer_status     varchar(64)  null,
+    source_ip           varchar(24)  null,
+    source_ae           varchar(64)  null,
+    created_time        datetime     not null default current_timestamp,
+    checksum            varchar(64)  null
+);
+create index if not exists idx_dicom_object_meta
+    on dicom_object_meta (tenant_id, patient_id, study_uid, series_uid, sop_uid);
+create index if not exists idx_dicom_object_meta_date on dicom_object_meta (tenant_id, study_date);
+create index if not exists idx_dicom_object_meta_createdate on dicom_object_meta (tenant_id, created_time);
+
+-- ---------------------访问日志-------------------------
+create table if not exists dicom_access_log
+(
+    log_id           varchar(36)   not null primary key,
+    tenant_id        varchar(64)   not null,
+    user_id          varchar(64)   not null,
+    username         varchar(128)  not null,
+    operation_type   varchar(32)   not null,
+    operation_path   varchar(512)  not null,
+    operation_method varchar(10)   not null,
+    operation_result varchar(16)   not null,
+    resource_type    varchar(32)   not null,
+    resource_id      varchar(64)   not null,
+    ip_address       varchar(45)   not null,
+    user_agent       varchar(512)  null,
+    response_time    bigint        not null,
+    description      varchar(1024) null,
+    created_time     datetime      not null
+);
+create index if not exists idx_access_log_tenant on dicom_access_log (tenant_id);
+create index if not exists idx_access_log_user on dicom_access_log (user_id);
+create index if not exists idx_access_log_resource on dicom_access_log (resource_type, resource_id);
+create index if not exists idx_access_log_time on dicom_access_log (created_time);
+create index if not exists idx_access_log_operation on dicom_access_log (operation_type, operation_result);
+
+-- ---------------------MPPS 执行记录-------------------------
+create table if not exists dicom_mpps_meta
+(
+    tenant_id            varchar(64) not null,
+    sop_instance_uid     varchar(64) not null,
+    patient_id           varchar(64) null,
+    study_uid            varchar(64) null,
+    accession_number     varchar(16) null,
+    modality             varchar(16) null,
+    pps_id               varchar(16) null,
+    -- IN PROGRESS / COMPLETED / DISCONTINUED
+    pps_status           varchar(16) not null,
+    pps_description      varchar(64) null,
+    performed_station_ae varchar(16) null,
+    start_datetime       datetime    null,
+    end_datetime         datetime    null,
+    -- PerformedSeriesSequence 引用的序列UID, JSON 数组
+    referenced_series    text        not null default '[]',
+    source_ip            varchar(24) not null,
+    source_ae            varchar(64) not null,
+    created_time         datetime    not null,
+    updated_time         datetime    not null,
+    primary key (tenant_id, sop_instance_uid)
+);
+create index if not exists idx_mpps_study on dicom_mpps_meta (tenant_id, study_uid);
+
+-- ---------------------自动路由转发任务-------------------------
+create table if not exists dicom_forward_task
+(
+    task_id         varchar(36)  not null primary key,
+    tenant_id       varchar(64)  not null,
+    rule_name       varchar(64)  not null,
+    destination     varchar(64)  not null,
+    study_uid       varchar(64)  not null,
+    series_uid      varchar(64)  not null,
+    sop_uid         varchar(64)  not null,
+    file_path       varchar(512) not null,
+    -- PENDING / RUNNING / SUCCESS / FAILED
+    status          varchar(16)  not null,
+    retry_times     int          not null default 0,
+    last_error      varchar(512) null,
+    next_retry_time datetime     not null,
+    created_time    datetime     not null,
+    updated_time    datetime     not null
+);
+create index if not exists idx_forward_task_due on dicom_forward_task (status, next_retry_time);
+create index if not exists idx_forward_task_study on dicom_forward_task (tenant_id, study_uid);
+
+-- ---------------------历史检查预取-------------------------
+create table if not exists dicom_prefetch_task
+(
+    tenant_id         varchar(64)  not null,
+    -- Q/R 源节点名称
+    source            varchar(64)  not null,
+    study_uid         varchar(64)  not null,
+    patient_id        varchar(64)  not null,
+    trigger_study_uid varchar(64)  not null,
+    study_date        date         null,
+    modalities        varchar(64)  null,
+    -- RUNNING / SUCCESS / FAILED
+    status            varchar(16)  not null,
+    completed         int          not null default 0,
+    failed            int          not null default 0,
+    last_error        varchar(512) null,
+    created_time      datetime     not null,
+    updated_time      datetime     not null,
+    primary key (tenant_id, source, study_uid)
+);
+create index if not exists idx_prefetch_patient on dicom_prefetch_task (tenant_id, patient_id);
+
+-- ---------------------分层存储-------------------------
+create table if not exists dicom_series_tier
+(
+    tenant_id         varchar(64) not null,
+    study_uid         varchar(64) not null,
+    series_uid        varchar(64) not null,
+    study_date_origin varchar(8)  not null,
+    -- 存储层名称, 对应 local_storage.tiers, 热存储层为 hot
+    tier              varchar(32) not null,
+    -- WADO 最近访问时间
+    last_access_time  datetime    not null,
+    updated_time      datetime    not null,
+    primary key (tenant_id, study_uid, series_uid)
+);
+create index if not exists idx_series_tier_access on dicom_series_tier (tier, last_access_time);
+
+-- ---------------------完整性校验-------------------------
+create table if not exists dicom_fixity_check
+(
+    tenant_id         varchar(64)  not null,
+    study_uid         varchar(64)  not null,
+    series_uid        varchar(64)  not null,
+    sop_uid           varchar(64)  not null,
+    file_path         varchar(512) not null,
+    expected_checksum varchar(64)  not null,
+    -- 重新计算的 MD5, 文件不存在时为空
+    actual_checksum   varchar(64)  null,
+    -- OK / MISMATCH / MISSING
+    status            varchar(16)  not null,
+    checked_time      datetime     null,
+    primary key (tenant_id, study_uid, series_uid, sop_uid)
+);
+create index if not exists idx_fixity_check_time on dicom_fixity_check (checked_time);
+create index if not exists idx_fixity_check_status on dicom_fixity_check (status, checked_time);
+
+-- ---------------------租户存储用量-------------------------
+-- 租户当前存储用量, 保存 dicom_image_meta 时增量更新
+create table if not exists dicom_tenant_usage
+(
+    tenant_id      varchar(64) not null primary key,
+    total_bytes    bigint      not null default 0,
+    instance_count bigint      not null default 0,
+    study_count    bigint      not null default 0,
+    updated_time   datetime    not null
+);
+
+-- 租户每天最后一次更新后的存储用量
+create table if not exists dicom_tenant_usage_daily
+(
+    tenant_id      varchar(64) not null,
+    usage_date     date        not null,
+    total_bytes    bigint      not null,
+    instance_count bigint      not null,
+    study_count    bigint      not null,
+    updated_time   datetime    not null,
+    primary key (tenant_id, usage_date)
+);
+
+-- ---------------------删除记录-------------------------
+create table if not exists dicom_deletion
+(
+    deletion_id    varchar(36) not null primary key,
+    tenant_id      varchar(64) not null,
+    -- STUDY / SERIES / INSTANCE
+    level          varchar(16) not null,
+    study_uid      varchar(64) not null,
+    series_uid     varchar(64) null,
+    sop_uid        varchar(64) null,
+    instance_count bigint      not null default 0,
+    total_bytes    bigint      not null default 0,
+    -- TRASHED / PURGED / RESTORED
+    status         varchar(16) not null,
+    requested_by   varchar(64) null,
+    -- 回收站保留期结束时间, 未启用回收站时为空
+    purge_after    datetime    null,
+    created_time   datetime    not null,
+    updated_time   datetime    not null
+);
+create index if not exists idx_deletion_tenant on dicom_deletion (tenant_id, created_time);
+create index if not exists idx_deletion_purge on dicom_deletion (status, purge_after);
+
+-- ---------------------拒绝记录(IHE IOCM)-------------------------
+create table if not exists dicom_rejection
+(
+    tenant_id      varchar(64) not null,
+    study_uid      varchar(64) not null,
+    series_uid     varchar(64) not null,
+    sop_uid        varchar(64) not null,
+    -- 113001 质量原因 / 113037 患者安全 / 113038 错误的工作列表条目 / 113039 数据保留期已过
+    reason_code    varchar(16) not null,
+    reason_meaning varchar(64) not null,
+    -- 拒绝说明(Key Object Selection)的 SOP Instance UID
+    kos_sop_uid    varchar(64) not null,
+    rejected_time  datetime    not null,
+    -- 文件清除时间, 只有 113039 会被清除
+    purged_time    datetime    null,
+    primary key (tenant_id, study_uid, series_uid, sop_uid)
+);
+create index if not exists idx_rejection_purge on dicom_rejection (reason_code, purged_time);
+
+-- ---------------------患者/检查属性修改审计-------------------------
+create table if not exists dicom_change_audit
+(
+    audit_id     varchar(36) not null primary key,
+    tenant_id    varchar(64) not null,
+    -- MERGE_PATIENT / MOVE_STUDY / UPDATE_PATIENT / UPDATE_STUDY
+    operation    varchar(32) not null,
+    -- 修改前的患者ID
+    patient_id   varchar(64) not null,
+    study_uid    varchar(64) not null,
+    -- 修改前后的属性(JSON)
+    before_value text        not null,
+    after_value  text        not null,
+    file_count   bigint      not null default 0,
+    requested_by varchar(64) null,
+    created_time datetime    not null
+);
+create index if not exists idx_change_audit_patient on dicom_change_audit (tenant_id, patient_id, created_time);
+create index if not exists idx_change_audit_study on dicom_change_audit (tenant_id, study_uid, created_time);
+
+-- ---------------------数据保留候选-------------------------
+create table if not exists dicom_retention_candidate
+(
+    tenant_id    varchar(64) not null,
+    study_uid    varchar(64) not null,
+    patient_id   varchar(64) not null,
+    -- 决定保留期的策略(保留期最长的匹配策略)
+    policy_name  varchar(64) not null,
+    -- 保留期结束日期
+    expire_date  date        not null,
+    -- PENDING / DELETED / KEPT
+    status       varchar(16) not null,
+    -- 删除记录ID(dicom_deletion)
+    deletion_id  varchar(36) null,
+    created_time datetime    not null,
+    updated_time datetime    not null,
+    primary key (tenant_id, study_uid)
+);
+create index if not exists idx_retention_candidate_status on dicom_retention_candidate (status, expire_date);
+
+-- ---------------------法律保全-------------------------
+create table if not exists dicom_legal_hold
+(
+    hold_id       varchar(36)  not null primary key,
+    tenant_id     varchar(64)  not null,
+    -- 保全患者的所有检查, 与 study_uid 至少设置一个
+    patient_id    varchar(64)  null,
+    study_uid     varchar(64)  null,
+    reason        varchar(256) not null,
+    created_by    varchar(64)  null,
+    created_time  datetime     not null,
+    released_by   varchar(64)  null,
+    -- 解除时间, 为空表示保全中
+    released_time datetime     null
+);
+create index if not exists idx_legal_hold_patient on dicom_legal_hold (tenant_id, patient_id)
+    where released_time is null;
+create index if not exists idx_legal_hold_study on dicom_legal_hold (tenant_id, study_uid)
+    where released_time is null;
@@ -1,5 +1,10 @@
-use crate::dicom_meta::{DicomImageMeta, DicomJsonMeta, DicomStateMeta, DicomStoreMeta};
+use crate::dicom_meta::{
+    DicomAttributeChange, DicomChangeAudit, DicomDeletion, DicomFixityCheck, DicomForwardTask, DicomImageFile, DicomImageMeta, DicomJsonMeta, DicomLegalHold, DicomMppsMeta, DicomPrefetchTask,
+    DicomRejection, DicomRetentionCandidate, DicomRetentionStudy, DicomSeriesTier, DicomStateMeta, DicomStoreMeta, DicomStudyMeta,
+    DicomStudyQuery, DicomPatientMeta, DicomTenantUsage,
+};
 use async_trait::async_trait;
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -18,7 +23,56 @@ pub enum DbError {
 
     #[error("Transaction failed: {0}")]
     TransactionFailed(String),
+
+    #[error(
+        "Database schema version {0} is older than required version {1}, run wado-dbtool migrate or enable main_database.auto_migrate"
+    )]
+    SchemaOutdated(i64, i64),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DbSslMode {
+    #[default]
+    Disable,
+    /// 服务器支持时使用 TLS
+    Prefer,
+    Require,
+}
+
+/// 数据库连接池选项, 各数据库实现共用
+#[derive(Debug, Clone)]
+pub struct DbPoolOptions {
+    pub max_size: usize,
+    /// 保持的最少连接数, 创建后预先建立
+    pub min_size: usize,
+    /// 等待空闲连接的超时时间
+    pub wait_timeout: Duration,
+    pub connect_timeout: Duration,
+    /// 取出连接时执行空查询, 丢弃已断开的连接
+    pub health_check: bool,
+    pub statement_cache: bool,
+    pub ssl_mode: DbSslMode,
+    /// 校验服务器证书的 CA 证书(PEM), 为空时使用系统证书
+    pub ssl_root_cert: Option<String>,
+    pub ssl_accept_invalid_certs: bool,
+}
+
+impl Default for DbPoolOptions {
+    fn default() -> Self {
+        DbPoolOptions {
+            max_size: 16,
+            min_size: 1,
+            wait_timeout: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(10),
+            health_check: true,
+            statement_cache: true,
+            ssl_mode: DbSslMode::Disable,
+            ssl_root_cert: None,
+            ssl_accept_invalid_certs: false,
+        }
+    }
 }
+
 pub fn current_time() -> chrono::NaiveDateTime {
     chrono::Local::now().naive_local()
 }
@@ -40,6 +94,21 @@ pub trait DbProvider: Send + Sync {
         study_uid: &str,
     ) -> Result<Vec<DicomStateMeta>, DbError>;
 
+    /// 查询患者的所有序列, 按检查排序
+    async fn get_patient_state_metaes(
+        &self,
+        tenant_id: &str,
+        patient_id: &str,
+    ) -> Result<Vec<DicomStateMeta>, DbError>;
+
+    /// 查询序列的目录布局版本, 序列不存在时返回 None
+    async fn get_series_layout(
+        &self,
+        tenant_id: &str,
+        study_uid: &str,
+        series_uid: &str,
+    ) -> Result<Option<i32>, DbError>;
+
     /*
      * 获取需要生成JSON格式的Metadata的序列信息.
      * end_time: 截止时间.
@@ -55,4 +124,294 @@ pub trait DbProvider: Send + Sync {
         study_uid: &str,
         series_uid: &str,
     ) -> Result<DicomJsonMeta, DbError>;
+
+    /// 保存 MPPS 记录, 按 (tenant_id, sop_instance_uid) 进行 upsert
+    async fn save_mpps_info(&self, mpps_meta: &DicomMppsMeta) -> Result<(), DbError>;
+
+    async fn get_mpps_info(
+        &self,
+        tenant_id: &str,
+        sop_instance_uid: &str,
+    ) -> Result<DicomMppsMeta, DbError>;
+
+    /// 保存转发任务, task_id 已存在时忽略
+    async fn save_forward_tasks(&self, tasks: &[DicomForwardTask]) -> Result<(), DbError>;
+
+    /*
+     * 领取到期的转发任务并标记为 RUNNING.
+     * 处于 RUNNING 超过 stale_before 的任务视为上次执行中断, 也会被重新领取.
+     */
+    async fn claim_forward_tasks(
+        &self,
+        now: chrono::NaiveDateTime,
+        stale_before: chrono::NaiveDateTime,
+        limit: i64,
+    ) -> Result<Vec<DicomForwardTask>, DbError>;
+
+    /// 更新任务的执行结果(status, retry_times, last_error, next_retry_time)
+    async fn update_forward_task(&self, task: &DicomForwardTask) -> Result<(), DbError>;
+
+    /// 按状态查询转发任务, status 为 None 时查询全部, 按创建时间倒序
+    async fn get_forward_tasks(
+        &self,
+        status: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<DicomForwardTask>, DbError>;
+
+    /// 将任务重新置为 PENDING 并清零重试次数
+    async fn requeue_forward_task(&self, task_id: &str) -> Result<(), DbError>;
+
+    /// 保存预取任务, 按 (tenant_id, source, study_uid) 进行 upsert
+    async fn save_prefetch_task(&self, task: &DicomPrefetchTask) -> Result<(), DbError>;
+
+    async fn get_prefetch_task(
+        &self,
+        tenant_id: &str,
+        source: &str,
+        study_uid: &str,
+    ) -> Result<DicomPrefetchTask, DbError>;
+
+    /// 领取预取任务: 记录不存在时插入 task; 已存在且不是 SUCCESS、也不是 stale_before
+    /// 之后仍在更新的 RUNNING 时, 更新为 task. 返回 false 表示已预取成功或正由其他进程预取
+    async fn claim_prefetch_task(
+        &self,
+        task: &DicomPrefetchTask,
+        stale_before: chrono::NaiveDateTime,
+    ) -> Result<bool, DbError>;
+
+    /// 更新序列最近访问时间, 没有记录时按热存储层新建
+    async fn touch_series_access(
+        &self,
+        tenant_id: &str,
+        study_uid: &str,
+        series_uid: &str,
+        study_date_origin: &str,
+        access_time: chrono::NaiveDateTime,
+    ) -> Result<(), DbError>;
+
+    /// 保存序列所在存储层, 不修改已有的最近访问时间
+    async fn save_series_tier(&self, series_tier: &DicomSeriesTier) -> Result<(), DbError>;
+
+    /// 查询位于 tier 且最近访问时间早于 accessed_before 的序列, tenant_ids 为空时不限租户
+    async fn get_series_tier_candidates(
+        &self,
+        tier: &str,
+        tenant_ids: &[String],
+        accessed_before: chrono::NaiveDateTime,
+        limit: i64,
+    ) -> Result<Vec<DicomSeriesTier>, DbError>;
+
+    /// 查询需要校验的实例: 有 checksum 且从未校验或上次校验早于 checked_before
+    async fn get_fixity_candidates(
+        &self,
+        checked_before: chrono::NaiveDateTime,
+        limit: i64,
+    ) -> Result<Vec<DicomFixityCheck>, DbError>;
+
+    /// 保存校验结果, 按 (tenant_id, study_uid, series_uid, sop_uid) 进行 upsert
+    async fn save_fixity_checks(&self, checks: &[DicomFixityCheck]) -> Result<(), DbError>;
+
+    /// 查询校验失败(MISMATCH / MISSING)的实例, tenant_id 为空时不限租户
+    async fn get_fixity_failures(
+        &self,
+        tenant_id: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<DicomFixityCheck>, DbError>;
+
+    /// 按主键顺序分页查询实例文件记录, after 为上一页的最后一条
+    async fn get_image_files(
+        &self,
+        after: Option<&DicomImageFile>,
+        limit: i64,
+    ) -> Result<Vec<DicomImageFile>, DbError>;
+
+    /// 返回 paths 中已记录在 dicom_image_meta.pixel_data_location 的路径
+    async fn find_indexed_files(&self, paths: &[String]) -> Result<Vec<String>, DbError>;
+
+    async fn image_exists(
+        &self,
+        tenant_id: &str,
+        study_uid: &str,
+        series_uid: &str,
+        sop_uid: &str,
+    ) -> Result<bool, DbError>;
+
+    /// 实例所在的检查/序列/实例有未恢复的删除记录, 或实例有拒绝记录.
+    /// 对账时这些实例的孤立文件不重新建立索引
+    async fn has_removal_record(
+        &self,
+        tenant_id: &str,
+        study_uid: &str,
+        series_uid: &str,
+        sop_uid: &str,
+    ) -> Result<bool, DbError>;
+
+    /// 将实例标记为文件不存在(image_status = MISSING)
+    async fn mark_images_missing(&self, images: &[DicomImageFile]) -> Result<(), DbError>;
+
+    /// 在同一事务中删除 deletion 指定的检查/序列/实例在各表中的记录, 扣减租户用量并保存删除记录.
+    /// 返回填写了 instance_count 和 total_bytes 的删除记录
+    async fn delete_dicom_objects(&self, deletion: &DicomDeletion) -> Result<DicomDeletion, DbError>;
+
+    async fn get_deletion(&self, deletion_id: &str) -> Result<Option<DicomDeletion>, DbError>;
+
+    /// 查询删除记录, 最近的在前, tenant_id / status 为空时不限
+    async fn get_deletions(
+        &self,
+        tenant_id: Option<&str>,
+        status: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<DicomDeletion>, DbError>;
+
+    /// 查询回收站保留期已过(purge_after < before)的删除记录, 检查处于法律保全中的除外
+    async fn get_expired_deletions(
+        &self,
+        before: chrono::NaiveDateTime,
+        limit: i64,
+    ) -> Result<Vec<DicomDeletion>, DbError>;
+
+    async fn update_deletion_status(&self, deletion_id: &str, status: &str) -> Result<(), DbError>;
+
+    /// 保存拒绝记录, 将已收到的实例标记为 REJECTED 并更新所在序列的 updated_time, 以便重新生成 JSON.
+    /// 返回被标记的实例数
+    async fn save_rejections(&self, rejections: &[DicomRejection]) -> Result<u64, DbError>;
+
+    /// 检查下被拒绝的实例 SOP Instance UID
+    async fn get_rejected_sop_uids(
+        &self,
+        tenant_id: &str,
+        study_uid: &str,
+    ) -> Result<Vec<String>, DbError>;
+
+    /// 查询指定原因且文件尚未清除的拒绝记录, 最早的在前
+    async fn get_rejections_to_purge(
+        &self,
+        reason_code: &str,
+        limit: i64,
+    ) -> Result<Vec<DicomRejection>, DbError>;
+
+    async fn mark_rejections_purged(&self, rejections: &[DicomRejection]) -> Result<(), DbError>;
+
+    /// 在同一事务中修改检查的患者/检查属性、重新写入文件的校验值和大小, 并保存审计记录
+    async fn save_attribute_changes(&self, changes: &[DicomAttributeChange]) -> Result<(), DbError>;
+
+    /// 查询属性修改审计记录, 最近的在前, patient_id 同时匹配修改前后的患者
+    async fn get_change_audits(
+        &self,
+        tenant_id: &str,
+        patient_id: Option<&str>,
+        study_uid: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<DicomChangeAudit>, DbError>;
+
+    /// 按 (tenant_id, study_uid) 顺序查询 (after_tenant_id, after_study_uid) 之后、检查日期早于 study_date_before 的检查,
+    /// 已有保留候选记录或处于法律保全中的检查除外
+    async fn get_retention_studies(
+        &self,
+        study_date_before: chrono::NaiveDate,
+        after_tenant_id: &str,
+        after_study_uid: &str,
+        limit: i64,
+    ) -> Result<Vec<DicomRetentionStudy>, DbError>;
+
+    /// 保存保留候选, 已存在的检查不覆盖. 返回新增的记录数
+    async fn save_retention_candidates(
+        &self,
+        candidates: &[DicomRetentionCandidate],
+    ) -> Result<u64, DbError>;
+
+    /// 查询保留候选, 到期最早的在前, tenant_id / status 为空时不限
+    async fn get_retention_candidates(
+        &self,
+        tenant_id: Option<&str>,
+        status: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<DicomRetentionCandidate>, DbError>;
+
+    /// 查询等待删除且不在法律保全中的保留候选, 到期最早的在前
+    async fn get_deletable_retention_candidates(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<DicomRetentionCandidate>, DbError>;
+
+    async fn get_retention_candidate(
+        &self,
+        tenant_id: &str,
+        study_uid: &str,
+    ) -> Result<Option<DicomRetentionCandidate>, DbError>;
+
+    async fn update_retention_candidate(
+        &self,
+        tenant_id: &str,
+        study_uid: &str,
+        status: &str,
+        deletion_id: Option<&str>,
+    ) -> Result<(), DbError>;
+
+    async fn save_legal_hold(&self, hold: &DicomLegalHold) -> Result<(), DbError>;
+
+    /// 查询租户的法律保全, 最近的在前. include_released 为 false 时只返回未解除的
+    async fn get_legal_holds(
+        &self,
+        tenant_id: &str,
+        include_released: bool,
+        limit: i64,
+    ) -> Result<Vec<DicomLegalHold>, DbError>;
+
+    /// 解除法律保全, 返回是否存在未解除的保全
+    async fn release_legal_hold(
+        &self,
+        tenant_id: &str,
+        hold_id: &str,
+        released_by: Option<&str>,
+    ) -> Result<bool, DbError>;
+
+    /// 患者或检查是否处于法律保全中, patient_id 为空时只检查 study_uid
+    async fn is_on_legal_hold(
+        &self,
+        tenant_id: &str,
+        patient_id: Option<&str>,
+        study_uid: &str,
+    ) -> Result<bool, DbError>;
+
+    /// 查询租户当前存储用量, tenant_id 为空时返回所有租户
+    async fn get_tenant_usage(
+        &self,
+        tenant_id: Option<&str>,
+    ) -> Result<Vec<DicomTenantUsage>, DbError>;
+
+    /// 查询租户在 [from, to] 期间每天的存储用量
+    async fn get_tenant_usage_history(
+        &self,
+        tenant_id: &str,
+        from: chrono::NaiveDate,
+        to: chrono::NaiveDate,
+    ) -> Result<Vec<DicomTenantUsage>, DbError>;
+
+    /// 查询检查级汇总, 检查不存在时返回 None
+    async fn get_study_meta(
+        &self,
+        tenant_id: &str,
+        study_uid: &str,
+    ) -> Result<Option<DicomStudyMeta>, DbError>;
+
+    /// 按条件查询检查级汇总, 用于 QIDO-RS / C-FIND
+    async fn search_study_metas(
+        &self,
+        tenant_id: &str,
+        query: &DicomStudyQuery,
+    ) -> Result<Vec<DicomStudyMeta>, DbError>;
+
+    /// 查询患者级汇总, 患者没有检查时返回 None
+    async fn get_patient_meta(
+        &self,
+        tenant_id: &str,
+        patient_id: &str,
+    ) -> Result<Option<DicomPatientMeta>, DbError>;
+
+    /// 已执行的最高迁移版本, 未执行过迁移时为 0
+    async fn schema_version(&self) -> Result<i64, DbError>;
+
+    /// 执行尚未执行的迁移, 返回本次执行的版本
+    async fn migrate(&self) -> Result<Vec<i64>, DbError>;
 }
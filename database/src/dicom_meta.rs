@@ -70,6 +70,12 @@ pub struct DicomStoreMeta {
     pub source_ip: BoundedString<24>,
     #[serde(rename = "source_ae")]
     pub source_ae: BoundedString<64>,
+    /// 文件内容 MD5(十六进制), 用于定期完整性校验
+    #[serde(rename = "checksum", default)]
+    pub checksum: Option<BoundedString<64>>,
+    /// 文件路径使用的目录布局版本
+    #[serde(rename = "storage_layout", default)]
+    pub storage_layout: i32,
 }
 // 为 DicomObjectMeta 实现 Hash trait 以便可以在 HashSet 中使用
 impl Hash for DicomStoreMeta {
@@ -179,6 +185,9 @@ pub struct DicomStateMeta {
     pub created_time: NaiveDateTime,
     #[serde(rename = "updated_time")]
     pub updated_time: NaiveDateTime,
+    /// 序列目录布局版本, 取序列第一次入库时的值, 之后不再修改
+    #[serde(rename = "storage_layout", default)]
+    pub storage_layout: i32,
 }
 
 impl DicomStateMeta {
@@ -299,9 +308,541 @@ pub struct DicomImageMeta {
     #[serde(rename = "space_size")]
     pub space_size: Option<i64>,
 
+    /// 文件内容 MD5(十六进制), 转码后为转码后文件的值
+    #[serde(rename = "checksum", default)]
+    pub checksum: Option<BoundedString<64>>,
+
+    #[serde(rename = "created_time")]
+    pub created_time: NaiveDateTime,
+
+    #[serde(rename = "updated_time")]
+    pub updated_time: NaiveDateTime,
+}
+
+/// DicomMppsMeta 用于记录 MPPS (Modality Performed Procedure Step) 的执行状态.
+/// N-CREATE 时创建, 状态为 IN PROGRESS; N-SET 时更新为 COMPLETED 或 DISCONTINUED.
+/// referenced_series 为 PerformedSeriesSequence 中引用的 SeriesInstanceUID 列表.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DicomMppsMeta {
+    #[serde(rename = "tenant_id")]
+    pub tenant_id: BoundedString<64>,
+    #[serde(rename = "sop_instance_uid")]
+    pub sop_instance_uid: BoundedString<64>,
+    #[serde(rename = "patient_id")]
+    pub patient_id: Option<BoundedString<64>>,
+    #[serde(rename = "study_uid")]
+    pub study_uid: Option<BoundedString<64>>,
+    #[serde(rename = "accession_number")]
+    pub accession_number: Option<BoundedString<16>>,
+    #[serde(rename = "modality")]
+    pub modality: Option<BoundedString<16>>,
+    #[serde(rename = "pps_id")]
+    pub pps_id: Option<BoundedString<16>>,
+    #[serde(rename = "pps_status")]
+    pub pps_status: BoundedString<16>,
+    #[serde(rename = "pps_description")]
+    pub pps_description: Option<BoundedString<64>>,
+    #[serde(rename = "performed_station_ae")]
+    pub performed_station_ae: Option<BoundedString<16>>,
+    #[serde(rename = "start_datetime")]
+    pub start_datetime: Option<NaiveDateTime>,
+    #[serde(rename = "end_datetime")]
+    pub end_datetime: Option<NaiveDateTime>,
+    #[serde(rename = "referenced_series")]
+    pub referenced_series: Vec<String>,
+    #[serde(rename = "source_ip")]
+    pub source_ip: BoundedString<24>,
+    #[serde(rename = "source_ae")]
+    pub source_ae: BoundedString<64>,
+    #[serde(rename = "created_time")]
+    pub created_time: NaiveDateTime,
+    #[serde(rename = "updated_time")]
+    pub updated_time: NaiveDateTime,
+}
+
+impl DicomMppsMeta {
+    pub const STATUS_IN_PROGRESS: &'static str = "IN PROGRESS";
+    pub const STATUS_COMPLETED: &'static str = "COMPLETED";
+    pub const STATUS_DISCONTINUED: &'static str = "DISCONTINUED";
+
+    /// COMPLETED 或 DISCONTINUED 之后, MPPS 不允许再被修改
+    pub fn is_final(&self) -> bool {
+        let status = self.pps_status.as_str();
+        status == Self::STATUS_COMPLETED || status == Self::STATUS_DISCONTINUED
+    }
+}
+
+/// DicomForwardTask 记录一个实例转发到远程节点的任务, 作为持久化的重试队列.
+/// 状态流转: PENDING -> RUNNING -> SUCCESS, 失败时回到 PENDING 并推迟 next_retry_time,
+/// 超过最大重试次数后为 FAILED, 可通过接口重新入队.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DicomForwardTask {
+    #[serde(rename = "task_id")]
+    pub task_id: FixedLengthString<36>,
+    #[serde(rename = "tenant_id")]
+    pub tenant_id: BoundedString<64>,
+    #[serde(rename = "rule_name")]
+    pub rule_name: BoundedString<64>,
+    #[serde(rename = "destination")]
+    pub destination: BoundedString<64>,
+    #[serde(rename = "study_uid")]
+    pub study_uid: BoundedString<64>,
+    #[serde(rename = "series_uid")]
+    pub series_uid: BoundedString<64>,
+    #[serde(rename = "sop_uid")]
+    pub sop_uid: BoundedString<64>,
+    #[serde(rename = "file_path")]
+    pub file_path: BoundedString<512>,
+    #[serde(rename = "status")]
+    pub status: BoundedString<16>,
+    #[serde(rename = "retry_times")]
+    pub retry_times: i32,
+    #[serde(rename = "last_error")]
+    pub last_error: Option<BoundedString<512>>,
+    #[serde(rename = "next_retry_time")]
+    pub next_retry_time: NaiveDateTime,
+    #[serde(rename = "created_time")]
+    pub created_time: NaiveDateTime,
+    #[serde(rename = "updated_time")]
+    pub updated_time: NaiveDateTime,
+}
+
+impl DicomForwardTask {
+    pub const STATUS_PENDING: &'static str = "PENDING";
+    pub const STATUS_RUNNING: &'static str = "RUNNING";
+    pub const STATUS_SUCCESS: &'static str = "SUCCESS";
+    pub const STATUS_FAILED: &'static str = "FAILED";
+}
+
+/// DicomPrefetchTask 记录从远程 Q/R 源预取历史检查的状态.
+/// 以 (tenant_id, source, study_uid) 唯一, SUCCESS 的检查不会重复预取.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DicomPrefetchTask {
+    #[serde(rename = "tenant_id")]
+    pub tenant_id: BoundedString<64>,
+    #[serde(rename = "source")]
+    pub source: BoundedString<64>,
+    #[serde(rename = "study_uid")]
+    pub study_uid: BoundedString<64>,
+    #[serde(rename = "patient_id")]
+    pub patient_id: BoundedString<64>,
+    /// 触发预取的新检查
+    #[serde(rename = "trigger_study_uid")]
+    pub trigger_study_uid: BoundedString<64>,
+    #[serde(rename = "study_date")]
+    pub study_date: Option<NaiveDate>,
+    #[serde(rename = "modalities")]
+    pub modalities: Option<BoundedString<64>>,
+    #[serde(rename = "status")]
+    pub status: BoundedString<16>,
+    #[serde(rename = "completed")]
+    pub completed: i32,
+    #[serde(rename = "failed")]
+    pub failed: i32,
+    #[serde(rename = "last_error")]
+    pub last_error: Option<BoundedString<512>>,
+    #[serde(rename = "created_time")]
+    pub created_time: NaiveDateTime,
+    #[serde(rename = "updated_time")]
+    pub updated_time: NaiveDateTime,
+}
+
+impl DicomPrefetchTask {
+    pub const STATUS_RUNNING: &'static str = "RUNNING";
+    pub const STATUS_SUCCESS: &'static str = "SUCCESS";
+    pub const STATUS_FAILED: &'static str = "FAILED";
+}
+
+/// DicomSeriesTier 记录序列所在的存储层及最近访问时间, 用于冷热数据迁移.
+/// 没有记录的序列视为位于热存储层, 最近访问时间取 dicom_state_meta.updated_time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DicomSeriesTier {
+    #[serde(rename = "tenant_id")]
+    pub tenant_id: BoundedString<64>,
+    #[serde(rename = "study_uid")]
+    pub study_uid: BoundedString<64>,
+    #[serde(rename = "series_uid")]
+    pub series_uid: BoundedString<64>,
+    #[serde(rename = "study_date_origin")]
+    pub study_date_origin: DicomDateString,
+    #[serde(rename = "tier")]
+    pub tier: BoundedString<32>,
+    #[serde(rename = "last_access_time")]
+    pub last_access_time: NaiveDateTime,
+    #[serde(rename = "updated_time")]
+    pub updated_time: NaiveDateTime,
+    /// 序列目录布局版本, 来自 dicom_state_meta, 不保存到 dicom_series_tier
+    #[serde(rename = "storage_layout", default)]
+    pub storage_layout: i32,
+}
+
+/// DicomFixityCheck 记录实例文件最近一次完整性校验的结果.
+/// expected_checksum 来自 dicom_image_meta.checksum, actual_checksum 为重新计算的值, 文件不存在时为空.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DicomFixityCheck {
+    #[serde(rename = "tenant_id")]
+    pub tenant_id: BoundedString<64>,
+    #[serde(rename = "study_uid")]
+    pub study_uid: BoundedString<64>,
+    #[serde(rename = "series_uid")]
+    pub series_uid: BoundedString<64>,
+    #[serde(rename = "sop_uid")]
+    pub sop_uid: BoundedString<64>,
+    #[serde(rename = "file_path")]
+    pub file_path: BoundedString<512>,
+    #[serde(rename = "expected_checksum")]
+    pub expected_checksum: BoundedString<64>,
+    #[serde(rename = "actual_checksum")]
+    pub actual_checksum: Option<BoundedString<64>>,
+    #[serde(rename = "status")]
+    pub status: BoundedString<16>,
+    #[serde(rename = "checked_time")]
+    pub checked_time: Option<NaiveDateTime>,
+}
+
+impl DicomFixityCheck {
+    /// 尚未校验
+    pub const STATUS_PENDING: &'static str = "PENDING";
+    pub const STATUS_OK: &'static str = "OK";
+    pub const STATUS_MISMATCH: &'static str = "MISMATCH";
+    pub const STATUS_MISSING: &'static str = "MISSING";
+}
+
+/// DicomTenantUsage 记录租户的存储用量, 在保存 dicom_image_meta 时增量更新.
+/// dicom_tenant_usage 保存当前用量, dicom_tenant_usage_daily 保存每天最后一次更新后的用量.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DicomTenantUsage {
+    #[serde(rename = "tenant_id")]
+    pub tenant_id: BoundedString<64>,
+    #[serde(rename = "usage_date")]
+    pub usage_date: NaiveDate,
+    #[serde(rename = "total_bytes")]
+    pub total_bytes: i64,
+    #[serde(rename = "instance_count")]
+    pub instance_count: i64,
+    #[serde(rename = "study_count")]
+    pub study_count: i64,
+    #[serde(rename = "updated_time")]
+    pub updated_time: NaiveDateTime,
+}
+
+/// DicomImageFile 为存储对账使用的实例文件记录.
+/// file_path 取自 dicom_image_meta.pixel_data_location, 早期数据为空时按序列的目录布局生成.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DicomImageFile {
+    #[serde(rename = "tenant_id")]
+    pub tenant_id: BoundedString<64>,
+    #[serde(rename = "study_uid")]
+    pub study_uid: BoundedString<64>,
+    #[serde(rename = "series_uid")]
+    pub series_uid: BoundedString<64>,
+    #[serde(rename = "sop_uid")]
+    pub sop_uid: BoundedString<64>,
+    #[serde(rename = "file_path")]
+    pub file_path: Option<BoundedString<512>>,
+    #[serde(rename = "study_date_origin")]
+    pub study_date_origin: DicomDateString,
+    #[serde(rename = "storage_layout")]
+    pub storage_layout: i32,
+    #[serde(rename = "image_status")]
+    pub image_status: Option<BoundedString<32>>,
+    #[serde(rename = "created_time")]
+    pub created_time: Option<NaiveDateTime>,
+}
+
+impl DicomImageFile {
+    /// 文件不存在, 由存储对账标记
+    pub const STATUS_MISSING: &'static str = "MISSING";
+}
+
+/// DicomDeletion 记录一次检查/序列/实例删除.
+/// 配置了回收站保留期时文件先移动到回收站(TRASHED), 到期后由 wado-webworker 清除(PURGED), 期间可以恢复(RESTORED).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DicomDeletion {
+    #[serde(rename = "deletion_id")]
+    pub deletion_id: BoundedString<36>,
+    #[serde(rename = "tenant_id")]
+    pub tenant_id: BoundedString<64>,
+    /// STUDY / SERIES / INSTANCE
+    #[serde(rename = "level")]
+    pub level: BoundedString<16>,
+    #[serde(rename = "study_uid")]
+    pub study_uid: BoundedString<64>,
+    #[serde(rename = "series_uid")]
+    pub series_uid: Option<BoundedString<64>>,
+    #[serde(rename = "sop_uid")]
+    pub sop_uid: Option<BoundedString<64>>,
+    #[serde(rename = "instance_count")]
+    pub instance_count: i64,
+    #[serde(rename = "total_bytes")]
+    pub total_bytes: i64,
+    #[serde(rename = "status")]
+    pub status: BoundedString<16>,
+    #[serde(rename = "requested_by")]
+    pub requested_by: Option<BoundedString<64>>,
+    #[serde(rename = "purge_after")]
+    pub purge_after: Option<NaiveDateTime>,
     #[serde(rename = "created_time")]
     pub created_time: NaiveDateTime,
+    #[serde(rename = "updated_time")]
+    pub updated_time: NaiveDateTime,
+}
+
+impl DicomDeletion {
+    pub const LEVEL_STUDY: &'static str = "STUDY";
+    pub const LEVEL_SERIES: &'static str = "SERIES";
+    pub const LEVEL_INSTANCE: &'static str = "INSTANCE";
+    /// 文件在回收站中, 可以恢复
+    pub const STATUS_TRASHED: &'static str = "TRASHED";
+    pub const STATUS_PURGED: &'static str = "PURGED";
+    pub const STATUS_RESTORED: &'static str = "RESTORED";
+}
 
+/// DicomRejection 记录被 IHE IOCM 拒绝说明(Key Object Selection)撤回的实例.
+/// 记录在实例删除后仍然保留, 之后重新收到的同一实例也标记为 REJECTED.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DicomRejection {
+    #[serde(rename = "tenant_id")]
+    pub tenant_id: BoundedString<64>,
+    #[serde(rename = "study_uid")]
+    pub study_uid: BoundedString<64>,
+    #[serde(rename = "series_uid")]
+    pub series_uid: BoundedString<64>,
+    #[serde(rename = "sop_uid")]
+    pub sop_uid: BoundedString<64>,
+    /// 拒绝原因代码(DCM 1130xx)
+    #[serde(rename = "reason_code")]
+    pub reason_code: BoundedString<16>,
+    #[serde(rename = "reason_meaning")]
+    pub reason_meaning: BoundedString<64>,
+    /// 拒绝说明本身的 SOP Instance UID
+    #[serde(rename = "kos_sop_uid")]
+    pub kos_sop_uid: BoundedString<64>,
+    #[serde(rename = "rejected_time")]
+    pub rejected_time: NaiveDateTime,
+    /// 文件被清除的时间, 只有 Data Retention Policy Expired 会被清除
+    #[serde(rename = "purged_time")]
+    pub purged_time: Option<NaiveDateTime>,
+}
+
+impl DicomRejection {
+    /// dicom_image_meta.image_status
+    pub const IMAGE_STATUS: &'static str = "REJECTED";
+    pub const REASON_QUALITY: &'static str = "113001";
+    pub const REASON_PATIENT_SAFETY: &'static str = "113037";
+    pub const REASON_INCORRECT_MWL: &'static str = "113038";
+    pub const REASON_RETENTION_EXPIRED: &'static str = "113039";
+}
+
+/// 患者/检查属性修改, 为空的字段保持不变
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DicomAttributeUpdate {
+    #[serde(rename = "patient_id", default, skip_serializing_if = "Option::is_none")]
+    pub patient_id: Option<String>,
+    #[serde(rename = "patient_name", default, skip_serializing_if = "Option::is_none")]
+    pub patient_name: Option<String>,
+    #[serde(rename = "patient_sex", default, skip_serializing_if = "Option::is_none")]
+    pub patient_sex: Option<String>,
+    #[serde(rename = "patient_birth_date", default, skip_serializing_if = "Option::is_none")]
+    pub patient_birth_date: Option<NaiveDate>,
+    #[serde(rename = "accession_number", default, skip_serializing_if = "Option::is_none")]
+    pub accession_number: Option<String>,
+    #[serde(rename = "study_id", default, skip_serializing_if = "Option::is_none")]
+    pub study_id: Option<String>,
+    #[serde(rename = "study_description", default, skip_serializing_if = "Option::is_none")]
+    pub study_description: Option<String>,
+}
+
+/// 修改属性后重新写入的 DICOM 文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DicomRewrittenFile {
+    #[serde(rename = "series_uid")]
+    pub series_uid: BoundedString<64>,
+    #[serde(rename = "sop_uid")]
+    pub sop_uid: BoundedString<64>,
+    #[serde(rename = "checksum")]
+    pub checksum: Option<BoundedString<64>>,
+    #[serde(rename = "space_size")]
+    pub space_size: i64,
+}
+
+/// DicomChangeAudit 记录一次检查的属性修改, before_value / after_value 为修改前后的属性(JSON).
+/// 合并患者时每个检查各有一条记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DicomChangeAudit {
+    #[serde(rename = "audit_id")]
+    pub audit_id: BoundedString<36>,
+    #[serde(rename = "tenant_id")]
+    pub tenant_id: BoundedString<64>,
+    /// MERGE_PATIENT / MOVE_STUDY / UPDATE_PATIENT / UPDATE_STUDY
+    #[serde(rename = "operation")]
+    pub operation: BoundedString<32>,
+    /// 修改前的患者ID
+    #[serde(rename = "patient_id")]
+    pub patient_id: BoundedString<64>,
+    #[serde(rename = "study_uid")]
+    pub study_uid: BoundedString<64>,
+    #[serde(rename = "before_value")]
+    pub before_value: String,
+    #[serde(rename = "after_value")]
+    pub after_value: String,
+    /// 重新写入的文件数
+    #[serde(rename = "file_count")]
+    pub file_count: i64,
+    #[serde(rename = "requested_by")]
+    pub requested_by: Option<BoundedString<64>>,
+    #[serde(rename = "created_time")]
+    pub created_time: NaiveDateTime,
+}
+
+impl DicomChangeAudit {
+    pub const OP_MERGE_PATIENT: &'static str = "MERGE_PATIENT";
+    pub const OP_MOVE_STUDY: &'static str = "MOVE_STUDY";
+    pub const OP_UPDATE_PATIENT: &'static str = "UPDATE_PATIENT";
+    pub const OP_UPDATE_STUDY: &'static str = "UPDATE_STUDY";
+}
+
+/// 一个检查的属性修改, 在同一事务中更新索引、文件校验值并保存审计记录
+#[derive(Debug, Clone)]
+pub struct DicomAttributeChange {
+    pub audit: DicomChangeAudit,
+    pub update: DicomAttributeUpdate,
+    pub files: Vec<DicomRewrittenFile>,
+}
+
+/// 保留策略评估使用的检查概要, modalities / sop_class_uids 为检查包含的所有值
+#[derive(Debug, Clone)]
+pub struct DicomRetentionStudy {
+    pub tenant_id: BoundedString<64>,
+    pub patient_id: BoundedString<64>,
+    pub study_uid: BoundedString<64>,
+    pub study_date: NaiveDate,
+    pub patient_birth_date: Option<NaiveDate>,
+    pub modalities: Vec<String>,
+    pub sop_class_uids: Vec<String>,
+}
+
+/// DicomRetentionCandidate 记录保留期已过的检查.
+/// PENDING 等待审核或自动删除, 删除后为 DELETED, 审核决定保留时为 KEPT(不再作为候选)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DicomRetentionCandidate {
+    #[serde(rename = "tenant_id")]
+    pub tenant_id: BoundedString<64>,
+    #[serde(rename = "study_uid")]
+    pub study_uid: BoundedString<64>,
+    #[serde(rename = "patient_id")]
+    pub patient_id: BoundedString<64>,
+    #[serde(rename = "policy_name")]
+    pub policy_name: BoundedString<64>,
+    #[serde(rename = "expire_date")]
+    pub expire_date: NaiveDate,
+    #[serde(rename = "status")]
+    pub status: BoundedString<16>,
+    #[serde(rename = "deletion_id")]
+    pub deletion_id: Option<BoundedString<36>>,
+    #[serde(rename = "created_time")]
+    pub created_time: NaiveDateTime,
+    #[serde(rename = "updated_time")]
+    pub updated_time: NaiveDateTime,
+}
+
+impl DicomRetentionCandidate {
+    pub const STATUS_PENDING: &'static str = "PENDING";
+    pub const STATUS_DELETED: &'static str = "DELETED";
+    pub const STATUS_KEPT: &'static str = "KEPT";
+}
+
+/// DicomLegalHold 法律保全: 患者或检查在保全解除前不能被删除.
+/// patient_id / study_uid 至少设置一个, 解除后保留记录并设置 released_time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DicomLegalHold {
+    #[serde(rename = "hold_id")]
+    pub hold_id: BoundedString<36>,
+    #[serde(rename = "tenant_id")]
+    pub tenant_id: BoundedString<64>,
+    #[serde(rename = "patient_id")]
+    pub patient_id: Option<BoundedString<64>>,
+    #[serde(rename = "study_uid")]
+    pub study_uid: Option<BoundedString<64>>,
+    #[serde(rename = "reason")]
+    pub reason: String,
+    #[serde(rename = "created_by")]
+    pub created_by: Option<BoundedString<64>>,
+    #[serde(rename = "created_time")]
+    pub created_time: NaiveDateTime,
+    #[serde(rename = "released_by")]
+    pub released_by: Option<BoundedString<64>>,
+    #[serde(rename = "released_time")]
+    pub released_time: Option<NaiveDateTime>,
+}
+
+/// DicomStudyMeta 为检查级汇总, 每个检查一行, 供 QIDO-RS / C-FIND 检查级查询.
+/// 患者和检查属性取自 dicom_state_meta, 数量不含已拒绝的实例.
+/// 在 save_state_list / save_image_list / 拒绝 / 删除时按检查重新计算
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DicomStudyMeta {
+    #[serde(rename = "tenant_id")]
+    pub tenant_id: BoundedString<64>,
+    #[serde(rename = "study_uid")]
+    pub study_uid: BoundedString<64>,
+    #[serde(rename = "patient_id")]
+    pub patient_id: BoundedString<64>,
+    #[serde(rename = "patient_name")]
+    pub patient_name: Option<BoundedString<64>>,
+    #[serde(rename = "patient_sex")]
+    pub patient_sex: Option<BoundedString<1>>,
+    #[serde(rename = "patient_birth_date")]
+    pub patient_birth_date: Option<NaiveDate>,
+    #[serde(rename = "study_date")]
+    pub study_date: NaiveDate,
+    #[serde(rename = "study_time")]
+    pub study_time: Option<NaiveTime>,
+    #[serde(rename = "accession_number")]
+    pub accession_number: Option<BoundedString<16>>,
+    #[serde(rename = "study_id")]
+    pub study_id: Option<BoundedString<16>>,
+    #[serde(rename = "study_description")]
+    pub study_description: Option<BoundedString<64>>,
+    #[serde(rename = "modalities_in_study")]
+    pub modalities_in_study: Vec<String>,
+    #[serde(rename = "number_of_series")]
+    pub number_of_series: i32,
+    #[serde(rename = "number_of_instances")]
+    pub number_of_instances: i32,
     #[serde(rename = "updated_time")]
     pub updated_time: NaiveDateTime,
 }
+
+/// DicomPatientMeta 为患者级汇总, 由 dicom_study_meta 按患者聚合
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DicomPatientMeta {
+    #[serde(rename = "tenant_id")]
+    pub tenant_id: BoundedString<64>,
+    #[serde(rename = "patient_id")]
+    pub patient_id: BoundedString<64>,
+    #[serde(rename = "patient_name")]
+    pub patient_name: Option<BoundedString<64>>,
+    #[serde(rename = "patient_sex")]
+    pub patient_sex: Option<BoundedString<1>>,
+    #[serde(rename = "patient_birth_date")]
+    pub patient_birth_date: Option<NaiveDate>,
+    #[serde(rename = "number_of_studies")]
+    pub number_of_studies: i32,
+    #[serde(rename = "number_of_series")]
+    pub number_of_series: i32,
+    #[serde(rename = "number_of_instances")]
+    pub number_of_instances: i32,
+}
+
+/// 检查级查询条件, 为空的条件不参与过滤. 结果按检查日期倒序
+#[derive(Debug, Clone, Default)]
+pub struct DicomStudyQuery {
+    pub patient_id: Option<String>,
+    pub accession_number: Option<String>,
+    pub study_date_from: Option<NaiveDate>,
+    pub study_date_to: Option<NaiveDate>,
+    /// 检查中包含该检查设备类型
+    pub modality: Option<String>,
+    pub limit: i64,
+    pub offset: i64,
+}
@@ -0,0 +1,161 @@
+//! 数据库结构迁移
+//!
+//! 每种数据库的迁移脚本放在 `database/migrations/<数据库>/` 下, 编译时嵌入程序.
+//! 已执行的版本记录在 `dicom_schema_migration` 表中, 启动时数据库版本低于
+//! [`SCHEMA_VERSION`] 则拒绝启动.
+//!
+//! 已发布的脚本不能修改, 结构变更需要追加新版本的脚本, 并同时更新 `Script/` 下的完整建表脚本.
+//! MySQL 的 DDL 不能回滚, 脚本中的语句应当可以重复执行.
+
+use crate::dicom_dbprovider::DbError;
+
+/// 当前程序需要的数据库结构版本, 即各数据库最后一个迁移的版本
+pub const SCHEMA_VERSION: i64 = 2;
+
+/// 一个版本的迁移脚本
+#[derive(Debug)]
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+impl Migration {
+    /// 脚本内容的 md5, 用于发现已执行的脚本被修改
+    pub fn checksum(&self) -> String {
+        format!("{:x}", md5::compute(self.sql))
+    }
+}
+
+/// 数据库中记录的已执行迁移
+#[derive(Debug, Clone)]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub checksum: String,
+}
+
+pub const POSTGRES_MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial",
+        sql: include_str!("../migrations/postgresql/0001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        description: "study meta",
+        sql: include_str!("../migrations/postgresql/0002_study_meta.sql"),
+    },
+];
+
+pub const MYSQL_MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial",
+        sql: include_str!("../migrations/mysql/0001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        description: "study meta",
+        sql: include_str!("../migrations/mysql/0002_study_meta.sql"),
+    },
+];
+
+pub const SQLITE_MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial",
+        sql: include_str!("../migrations/sqlite/0001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        description: "study meta",
+        sql: include_str!("../migrations/sqlite/0002_study_meta.sql"),
+    },
+];
+
+/// 返回尚未执行的迁移, 按版本排序. 已执行的脚本内容与程序中的不一致时返回错误
+pub fn pending_migrations<'a>(
+    migrations: &'a [Migration],
+    applied: &[AppliedMigration],
+) -> Result<Vec<&'a Migration>, DbError> {
+    let mut pending = Vec::new();
+    for migration in migrations {
+        match applied.iter().find(|a| a.version == migration.version) {
+            Some(a) if a.checksum != migration.checksum() => {
+                return Err(DbError::DatabaseError(format!(
+                    "checksum of applied migration {} ({}) does not match the embedded script",
+                    migration.version, migration.description
+                )));
+            }
+            Some(_) => {}
+            None => pending.push(migration),
+        }
+    }
+    pending.sort_by_key(|m| m.version);
+    Ok(pending)
+}
+
+/// 检查数据库结构版本是否满足程序要求
+pub fn check_schema_version(version: i64) -> Result<(), DbError> {
+    if version < SCHEMA_VERSION {
+        return Err(DbError::SchemaOutdated(version, SCHEMA_VERSION));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIGRATIONS: &[Migration] = &[
+        Migration {
+            version: 1,
+            description: "initial",
+            sql: "create table a (id integer);",
+        },
+        Migration {
+            version: 2,
+            description: "add b",
+            sql: "create table b (id integer);",
+        },
+    ];
+
+    #[test]
+    fn test_pending_migrations() {
+        let applied = vec![AppliedMigration {
+            version: 1,
+            checksum: MIGRATIONS[0].checksum(),
+        }];
+        let pending = pending_migrations(MIGRATIONS, &applied).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].version, 2);
+
+        let pending = pending_migrations(MIGRATIONS, &[]).unwrap();
+        assert_eq!(pending.len(), 2);
+    }
+
+    #[test]
+    fn test_modified_migration_is_rejected() {
+        let applied = vec![AppliedMigration {
+            version: 1,
+            checksum: "0".to_string(),
+        }];
+        assert!(pending_migrations(MIGRATIONS, &applied).is_err());
+    }
+
+    #[test]
+    fn test_check_schema_version() {
+        assert!(check_schema_version(SCHEMA_VERSION).is_ok());
+        assert!(matches!(
+            check_schema_version(0),
+            Err(DbError::SchemaOutdated(0, SCHEMA_VERSION))
+        ));
+    }
+
+    #[test]
+    fn test_last_migration_matches_schema_version() {
+        for migrations in [POSTGRES_MIGRATIONS, MYSQL_MIGRATIONS, SQLITE_MIGRATIONS] {
+            assert_eq!(migrations.last().unwrap().version, SCHEMA_VERSION);
+        }
+    }
+}
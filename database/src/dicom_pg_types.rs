@@ -103,3 +103,15 @@ impl<const N: usize> ToSql for FixedLengthString<N> {
         self.to_sql(ty, out)
     }
 }
+
+impl<const N: usize> FromSql<'_> for FixedLengthString<N> {
+    fn from_sql(ty: &Type, raw: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        let str_val = <&str as FromSql>::from_sql(ty, raw)?;
+        Ok(FixedLengthString::from_str(str_val)
+            .map_err(|e| format!("Failed to create FixedLengthString FromSql: {}", e))?)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <&str as FromSql>::accepts(ty)
+    }
+}
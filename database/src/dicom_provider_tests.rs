@@ -0,0 +1,1596 @@
+//! DbProvider 实现共用的测试, 由各数据库实现的测试模块按各自的连接串调用
+
+use crate::dicom_dbprovider::{DbProvider, current_time};
+use crate::dicom_dbtype::*;
+use crate::dicom_meta::{
+    DicomAttributeChange, DicomAttributeUpdate, DicomChangeAudit, DicomDeletion, DicomFixityCheck,
+    DicomForwardTask, DicomImageMeta, DicomJsonMeta, DicomLegalHold, DicomPrefetchTask,
+    DicomRejection, DicomRetentionCandidate, DicomRewrittenFile, DicomSeriesTier, DicomStateMeta,
+    DicomStoreMeta, TransferStatus,
+};
+use chrono::{NaiveDate, NaiveTime};
+use ctor::ctor;
+use dotenv::dotenv;
+use std::ops::Sub;
+
+#[ctor]
+fn init_tests() {
+    // 这个函数会在所有测试运行之前执行一次
+    dotenv().ok();
+    println!("Initializing tests...");
+    // 可以在这里进行全局的测试设置
+}
+
+struct TestCleanup {
+    tenant_ids: Vec<String>,
+}
+
+impl TestCleanup {
+    fn new() -> Self {
+        Self {
+            tenant_ids: Vec::new(),
+        }
+    }
+
+    fn add_tenant(&mut self, tenant_id: String) {
+        self.tenant_ids.push(tenant_id);
+    }
+}
+
+impl Drop for TestCleanup {
+    fn drop(&mut self) {
+        // 在这里执行清理操作
+        println!("Cleaning up test data...");
+        // 在实际实现中，这里应该执行数据库清理操作
+        // 例如删除测试创建的记录
+        for tenant_id in &self.tenant_ids {
+            // 执行清理SQL
+            println!(
+                "exec sql: DELETE FROM dicom_state_meta WHERE tenant_id = {}",
+                tenant_id
+            );
+        }
+    }
+}
+
+pub(crate) async fn save_state_info(
+    db_provider: &dyn DbProvider,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let t_id = "test_tenant_123";
+    // 构造 TestCleanup 实例
+    let mut cleanup = TestCleanup::new();
+    // 注册需要清理的 tenant_id
+    cleanup.add_tenant(t_id.to_string());
+    // 创建测试数据
+    let tenant_id = BoundedString::<64>::try_from(t_id.to_string())?;
+    let patient_id = BoundedString::<64>::try_from("test_patient_456".to_string())?;
+    let study_uid = BoundedString::<64>::try_from("1.2.3.4.5.6.7.8.9".to_string())?;
+    let series_uid = BoundedString::<64>::try_from("9.8.7.6.5.4.3.2.1".to_string())?;
+    let study_uid_hash = BoundedString::<20>::make_str("1.2.3.4.5.6.7.8.9");
+    let series_uid_hash = BoundedString::<20>::make_str("9.8.7.6.5.4.3.2.1");
+    let study_date_origin = DicomDateString::from_str("20231201")?;
+    let accession_number = BoundedString::<16>::make_str("ACC123456");
+    let modality = Some(BoundedString::<16>::make_str("CT"));
+    let series_number = Some(1);
+    let series_date = Some(NaiveDate::from_ymd_opt(2023, 12, 1).unwrap());
+    let series_time = Some(NaiveTime::parse_from_str("120000", "%H%M%S")?);
+    let series_description = Some(BoundedString::<256>::try_from("Test Series".to_string())?);
+    let body_part_examined = Some(BoundedString::<64>::try_from("CHEST".to_string())?);
+    let protocol_name = Some(BoundedString::<64>::try_from("CHEST CT".to_string())?);
+    let study_date = NaiveDate::from_ymd_opt(2023, 12, 1).unwrap();
+    let study_time = Some(NaiveTime::parse_from_str("120000", "%H%M%S")?);
+    let study_id = Some(BoundedString::<16>::try_from("STUDY123".to_string())?);
+    let study_description = Some(BoundedString::<64>::try_from("Test Study".to_string())?);
+    let patient_age = Some(BoundedString::<16>::try_from("045Y".to_string())?);
+    let patient_sex = Some(BoundedString::<1>::try_from("M".to_string())?);
+    let patient_name = Some(BoundedString::<64>::try_from("TEST^PATIENT".to_string())?);
+    let patient_birth_date = Some(NaiveDate::from_ymd_opt(1978, 1, 1).unwrap());
+    let patient_birth_time = Some(NaiveTime::parse_from_str("080000", "%H%M%S")?);
+    let created_time = current_time();
+    let updated_time = current_time();
+
+    // 创建 DicomStateMeta 实例
+    let state_meta = DicomStateMeta {
+        tenant_id,
+        patient_id,
+        study_uid,
+        series_uid,
+        study_uid_hash,
+        series_uid_hash,
+        study_date_origin,
+        patient_name,
+        patient_sex,
+        patient_birth_date,
+        patient_birth_time,
+        patient_age,
+        patient_size: Some(175.5),
+        patient_weight: Some(70.2),
+        study_date,
+        study_time,
+        accession_number: Some(accession_number),
+        study_id,
+        study_description,
+        modality,
+        series_number,
+        series_date,
+        series_time,
+        series_description,
+        body_part_examined,
+        protocol_name,
+        series_related_instances: None,
+        created_time,
+        updated_time,
+        storage_layout: 0,
+    };
+
+    // 执行保存操作
+    let result = db_provider.save_state_info(&state_meta).await;
+
+    // 验证保存成功
+    assert!(
+        result.is_ok(),
+        "Failed to save DicomStateMeta: {:?}",
+        result.err()
+    );
+
+    Ok(()) //   cleanup 会在函数结束时自动调用 Drop
+}
+
+pub(crate) async fn get_state_metaes(
+    db_provider: &dyn DbProvider,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tenant_id = "1234567890";
+    let study_uid = "1.2.156.112605.0.1685486876.2025061710152134339.2.1.1";
+
+    // 执行查询操作
+    let result = db_provider.get_state_metaes(tenant_id, study_uid).await;
+
+    // 验证查询成功
+    assert!(
+        result.is_ok(),
+        "Failed to get DicomStateMeta list: {:?}",
+        result.err()
+    );
+
+    let state_meta_list = result?;
+
+    // 验证返回结果不为空
+    assert!(!state_meta_list.is_empty(), "Expected non-empty result");
+
+    // 验证每条记录的 tenant_id 和 study_uid 是否正确
+    for state_meta in state_meta_list {
+        assert_eq!(state_meta.tenant_id.as_str(), tenant_id);
+        assert_eq!(state_meta.study_uid.as_str(), study_uid);
+        let json = serde_json::to_string_pretty(&state_meta)?;
+        println!("DicomStateMeta JSON: {}", json);
+    }
+
+    Ok(())
+}
+pub(crate) async fn get_json_metaes(
+    db_provider: &dyn DbProvider,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let cd = current_time();
+    let cd = cd.sub(chrono::Duration::minutes(3));
+    // 执行查询操作
+    let result = db_provider.get_json_metaes(cd).await;
+
+    // 验证查询成功
+    assert!(
+        result.is_ok(),
+        "Failed to get DicomStateMeta list: {:?}",
+        result.err()
+    );
+
+    let state_meta_list = result?;
+
+    // 验证返回结果不为空
+    assert!(!state_meta_list.is_empty(), "Expected non-empty result");
+
+    if state_meta_list.is_empty() {
+        return Ok(());
+    }
+    // 验证每条记录的 tenant_id 和 study_uid 是否正确
+    for state_meta in state_meta_list {
+        let json = serde_json::to_string_pretty(&state_meta)?;
+        println!("DicomStateMeta JSON: {}", json);
+    }
+
+    Ok(())
+}
+pub(crate) async fn save_state_list(
+    db_provider: &dyn DbProvider,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // 创建测试数据列表
+    let mut state_meta_list = Vec::new();
+
+    // 创建第一个测试数据
+    let tenant_id = BoundedString::<64>::try_from("test_tenant_list_123".to_string())?;
+    let patient_id = BoundedString::<64>::try_from("test_patient_list_456".to_string())?;
+    let study_uid = BoundedString::<64>::try_from("1.2.3.4.5.6.7.8.9.list".to_string())?;
+    let series_uid = BoundedString::<64>::try_from("9.8.7.6.5.4.3.2.1.list".to_string())?;
+    let study_uid_hash = BoundedString::<20>::from_str("0AA07C2AA455BEB01D5A")?;
+    let series_uid_hash = BoundedString::<20>::from_str("0AB07C2AA455BEB01D5A")?;
+    let study_date_origin = DicomDateString::make("20231202");
+    let accession_number = BoundedString::<16>::try_from("ACC123457".to_string())?;
+    let modality = Some(BoundedString::<16>::try_from("MRI".to_string())?);
+    let series_number = Some(2);
+    let series_date = Some(NaiveDate::from_ymd_opt(2023, 12, 2).unwrap());
+    let series_time = Some(NaiveTime::parse_from_str("130000", "%H%M%S")?);
+    let series_description = Some(BoundedString::<256>::try_from(
+        "Test Series List".to_string(),
+    )?);
+    let body_part_examined = Some(BoundedString::<64>::try_from("HEAD".to_string())?);
+    let protocol_name = Some(BoundedString::<64>::try_from("HEAD MRI".to_string())?);
+    let study_date = NaiveDate::from_ymd_opt(2023, 12, 2).unwrap();
+    let study_time = Some(NaiveTime::parse_from_str("130000", "%H%M%S")?);
+    let study_id = Some(BoundedString::<16>::try_from("STUDY124".to_string())?);
+    let study_description = Some(BoundedString::<64>::try_from(
+        "Test Study List".to_string(),
+    )?);
+    let patient_age = Some(BoundedString::<16>::try_from("046Y".to_string())?);
+    let patient_sex = Some(BoundedString::<1>::try_from("F".to_string())?);
+    let patient_name = Some(BoundedString::<64>::try_from("TEST^PATIENT2".to_string())?);
+    let patient_birth_date = Some(NaiveDate::from_ymd_opt(1977, 1, 1).unwrap());
+    let patient_birth_time = Some(NaiveTime::parse_from_str("090000", "%H%M%S")?);
+    let created_time = current_time();
+    let updated_time = current_time();
+
+    let state_meta = DicomStateMeta {
+        tenant_id,
+        patient_id,
+        study_uid,
+        series_uid,
+        study_uid_hash,
+        series_uid_hash,
+        study_date_origin,
+        patient_name,
+        patient_sex,
+        patient_birth_date,
+        patient_birth_time,
+        patient_age,
+        patient_size: Some(165.5),
+        patient_weight: Some(60.2),
+        study_date,
+        study_time,
+        accession_number: Some(accession_number),
+        study_id,
+        study_description,
+        modality,
+        series_number,
+        series_date,
+        series_time,
+        series_description,
+        body_part_examined,
+        protocol_name,
+        series_related_instances: None,
+        created_time,
+        updated_time,
+        storage_layout: 0,
+    };
+
+    state_meta_list.push(state_meta);
+
+    // 执行批量保存操作
+    let result = db_provider.save_state_list(&state_meta_list).await;
+
+    // 验证保存成功并打印详细错误信息
+    match &result {
+        Ok(_) => {
+            println!("Successfully saved DicomStateMeta list");
+        }
+        Err(e) => {
+            eprintln!("Failed to save DicomStateMeta list with error: {:?}", e);
+            eprintln!("Error details: {}", e);
+        }
+    }
+
+    assert!(
+        result.is_ok(),
+        "Failed to save DicomStateMeta list: {:?}",
+        result.err()
+    );
+
+    Ok(())
+}
+pub(crate) async fn save_json_list(
+    db_provider: &dyn DbProvider,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // 创建测试数据列表
+    let mut json_meta_list = Vec::new();
+
+    // 创建测试数据
+    let tenant_id = BoundedString::<64>::try_from("test_tenant_json_123".to_string())?;
+    let study_uid = BoundedString::<64>::try_from("1.2.3.4.5.6.7.8.9.json".to_string())?;
+    let series_uid = BoundedString::<64>::try_from("9.8.7.6.5.4.3.2.1.json".to_string())?;
+    let study_uid_hash = BoundedString::<20>::from_str("0AC07C2AA455BEB01D5A")?;
+    let series_uid_hash = BoundedString::<20>::from_str("0AD07C2AA455BEB01D5A")?;
+    let study_date_origin = DicomDateString::make("20231203");
+    let flag_time = current_time();
+    let created_time = current_time();
+    let json_status = 0;
+    let retry_times = 0;
+
+    let json_meta = DicomJsonMeta {
+        tenant_id: tenant_id.clone(),
+        study_uid: study_uid.clone(),
+        series_uid: series_uid.clone(),
+        study_uid_hash,
+        series_uid_hash,
+        study_date_origin,
+        flag_time,
+        created_time,
+        json_status,
+        retry_times,
+    };
+
+    json_meta_list.push(json_meta);
+
+    // 执行批量保存操作
+    let result = db_provider.save_json_list(&json_meta_list).await;
+
+    // 验证保存成功
+    assert!(
+        result.is_ok(),
+        "Failed to save DicomJsonMeta list: {:?}",
+        result.err()
+    );
+
+    Ok(())
+}
+pub(crate) async fn save_store_info(
+    db_provider: &dyn DbProvider,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // 创建测试数据
+    let trace_id = FixedLengthString::<36>::from_str("123e4567-e89b-12d3-a456-426614174000")?;
+    let worker_node_id = BoundedString::<64>::try_from("TEST_WORKER_NODE".to_string())?;
+    let tenant_id = BoundedString::<64>::try_from("test_tenant_store_123".to_string())?;
+    let patient_id = BoundedString::<64>::try_from("test_patient_store_456".to_string())?;
+    let study_uid = BoundedString::<64>::try_from("1.2.3.4.5.6.7.8.9.store".to_string())?;
+    let series_uid = BoundedString::<64>::try_from("9.8.7.6.5.4.3.2.1.store".to_string())?;
+    let sop_uid =
+        BoundedString::<64>::try_from("1.3.6.1.4.1.5962.1.1.0.0.0.1234567891".to_string())?;
+    let file_size = 1024i64;
+    let file_path = BoundedString::<512>::try_from("/data/test/file.dcm".to_string())?;
+    let transfer_syntax_uid = BoundedString::<64>::try_from("1.2.840.10008.1.2.1".to_string())?;
+    let number_of_frames = 1;
+    let series_uid_hash = BoundedString::<20>::from_str("0BA07C2AA455BEB01D5A")?;
+    let study_uid_hash = BoundedString::<20>::from_str("0BB07C2AA455BEB01D5A")?;
+    let accession_number = BoundedString::<16>::try_from("ACC123458".to_string())?;
+    let target_ts = BoundedString::<64>::try_from("1.2.840.10008.1.2.1".to_string())?;
+    let study_date = NaiveDate::from_ymd_opt(2023, 12, 5).unwrap();
+    let transfer_status = TransferStatus::Success;
+    let source_ip = BoundedString::<24>::try_from("192.168.1.100".to_string())?;
+    let source_ae = BoundedString::<64>::try_from("TEST_AE".to_string())?;
+    let created_time = current_time();
+
+    let store_meta = DicomStoreMeta {
+        trace_id,
+        worker_node_id,
+        tenant_id,
+        patient_id,
+        study_uid,
+        series_uid,
+        sop_uid,
+        file_size,
+        file_path,
+        transfer_syntax_uid,
+        number_of_frames,
+        series_uid_hash,
+        study_uid_hash,
+        accession_number: Some(accession_number),
+        target_ts,
+        study_date,
+        transfer_status,
+        source_ip,
+        source_ae,
+        created_time,
+        checksum: None,
+        storage_layout: 0,
+    };
+
+    // 创建存储元数据列表
+    let store_meta_list = vec![store_meta];
+
+    // 执行保存操作
+    let result = db_provider.save_store_list(&store_meta_list).await;
+
+    // 验证保存成功
+    assert!(
+        result.is_ok(),
+        "Failed to save DicomStoreMeta list: {:?}",
+        result.err()
+    );
+
+    Ok(())
+}
+
+pub(crate) async fn save_image_list(
+    db_provider: &dyn DbProvider,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // 创建测试数据列表
+    let mut image_meta_list = Vec::new();
+
+    // 创建测试数据
+    let tenant_id = BoundedString::<64>::try_from("test_tenant_image_123".to_string())?;
+    let patient_id = BoundedString::<64>::try_from("test_patient_image_456".to_string())?;
+    let study_uid = BoundedString::<64>::try_from("1.2.3.4.5.6.7.8.9.image".to_string())?;
+    let series_uid = BoundedString::<64>::try_from("9.8.7.6.5.4.3.2.1.image".to_string())?;
+    let sop_uid =
+        BoundedString::<64>::try_from("1.3.6.1.4.1.5962.1.1.0.0.0.1234567890".to_string())?;
+    let study_uid_hash = BoundedString::<20>::from_str("0AE07C2AA455BEB01D5A")?;
+    let series_uid_hash = BoundedString::<20>::from_str("0AF07C2AA455BEB01D5A")?;
+
+    let content_date = Some(NaiveDate::parse_from_str("20231204", "%Y%m%d")?);
+    let content_time = Some(NaiveTime::parse_from_str("120000", "%H%M%S")?);
+    let instance_number = Some(1);
+    let image_type = Some(BoundedString::<128>::try_from(
+        "ORIGINAL\\PRIMARY\\AXIAL".to_string(),
+    )?);
+    let image_orientation_patient = Some(BoundedString::<128>::try_from(
+        "1.0\\0.0\\0.0\\0.0\\1.0\\0.0".to_string(),
+    )?);
+    let image_position_patient = Some(BoundedString::<64>::try_from(
+        "-125.0\\-125.0\\0.0".to_string(),
+    )?);
+    let slice_thickness = Some(5.0);
+    let spacing_between_slices = Some(5.0);
+    let slice_location = Some(0.0);
+    let samples_per_pixel = Some(1);
+    let photometric_interpretation =
+        Some(BoundedString::<32>::try_from("MONOCHROME2".to_string())?);
+    let width = Some(512);
+    let columns = Some(512);
+    let bits_allocated = Some(16);
+    let bits_stored = Some(12);
+    let high_bit = Some(11);
+    let pixel_representation = Some(0);
+    let rescale_intercept = Some(0.0);
+    let rescale_slope = Some(1.0);
+    let rescale_type = Some(BoundedString::<64>::make_str("US"));
+    let window_center = Some(BoundedString::<64>::make_str("50"));
+    let window_width = Some(BoundedString::<64>::make_str("400"));
+    let transfer_syntax_uid = BoundedString::<64>::make_str("1.2.840.10008.1.2.1");
+    let pixel_data_location = Some(BoundedString::<512>::make_str("/data/pixel/1234567890"));
+    let thumbnail_location = Some(BoundedString::<512>::make_str("/data/thumb/1234567890"));
+    let sop_class_uid = BoundedString::<64>::make_str("1.2.840.10008.5.1.4.1.1.2");
+    let image_status = Some(BoundedString::<32>::make_str("AVAILABLE"));
+    let space_size = Some(2049i64);
+    let created_time = current_time();
+    let updated_time = current_time();
+
+    let image_meta = DicomImageMeta {
+        tenant_id,
+        patient_id,
+        study_uid,
+        series_uid,
+        sop_uid,
+        study_uid_hash,
+        series_uid_hash,
+        content_date,
+        content_time,
+        instance_number,
+        image_type,
+        image_orientation_patient,
+        image_position_patient,
+        slice_thickness,
+        spacing_between_slices,
+        slice_location,
+        samples_per_pixel,
+        photometric_interpretation,
+        width,
+        columns,
+        bits_allocated,
+        bits_stored,
+        high_bit,
+        pixel_representation,
+        rescale_intercept,
+        rescale_slope,
+        rescale_type,
+        window_center,
+        window_width,
+        transfer_syntax_uid,
+        pixel_data_location,
+        thumbnail_location,
+        sop_class_uid,
+        image_status,
+        space_size,
+        checksum: None,
+        created_time,
+        updated_time,
+    };
+
+    image_meta_list.push(image_meta);
+
+    // 执行批量保存操作
+    let result = db_provider.save_image_list(&image_meta_list).await;
+
+    // 验证保存成功
+    assert!(
+        result.is_ok(),
+        "Failed to save DicomImageMeta list: {:?}",
+        result.err()
+    );
+
+    Ok(())
+}
+
+pub(crate) async fn claim_prefetch_task(
+    db_provider: &dyn DbProvider,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let now = current_time();
+    let study_uid = format!("1.2.826.0.1.3680043.{}", now.and_utc().timestamp_micros());
+    let mut task = DicomPrefetchTask {
+        tenant_id: BoundedString::<64>::make_str("test_tenant_prefetch"),
+        source: BoundedString::<64>::make_str("pacs"),
+        study_uid: BoundedString::<64>::make_str(&study_uid),
+        patient_id: BoundedString::<64>::make_str("PID0001"),
+        trigger_study_uid: BoundedString::<64>::make_str("1.2.3"),
+        study_date: NaiveDate::from_ymd_opt(2024, 1, 1),
+        modalities: Some(BoundedString::<64>::make_str("CT")),
+        status: BoundedString::<16>::make_str(DicomPrefetchTask::STATUS_RUNNING),
+        completed: 0,
+        failed: 0,
+        last_error: None,
+        created_time: now,
+        updated_time: now,
+    };
+    let stale_before = now - chrono::Duration::minutes(60);
+
+    assert!(db_provider.claim_prefetch_task(&task, stale_before).await?);
+    // 其他进程正在预取
+    assert!(!db_provider.claim_prefetch_task(&task, stale_before).await?);
+    // RUNNING 超时后可以接管
+    let later = now + chrono::Duration::minutes(1);
+    assert!(db_provider.claim_prefetch_task(&task, later).await?);
+
+    task.status = BoundedString::<16>::make_str(DicomPrefetchTask::STATUS_SUCCESS);
+    db_provider.save_prefetch_task(&task).await?;
+    assert!(!db_provider.claim_prefetch_task(&task, later).await?);
+
+    task.status = BoundedString::<16>::make_str(DicomPrefetchTask::STATUS_FAILED);
+    db_provider.save_prefetch_task(&task).await?;
+    task.status = BoundedString::<16>::make_str(DicomPrefetchTask::STATUS_RUNNING);
+    assert!(db_provider.claim_prefetch_task(&task, stale_before).await?);
+    let saved = db_provider
+        .get_prefetch_task(task.tenant_id.as_str(), task.source.as_str(), &study_uid)
+        .await?;
+    assert_eq!(saved.status.as_str(), DicomPrefetchTask::STATUS_RUNNING);
+    Ok(())
+}
+
+pub(crate) async fn has_removal_record(
+    db_provider: &dyn DbProvider,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let now = current_time();
+    let tenant_id = "test_tenant_removal";
+    let study_uid = format!("1.2.826.0.1.3680043.{}", now.and_utc().timestamp_micros());
+    let series_uid = format!("{}.1", study_uid);
+    let removed = |sop_uid: &'static str| {
+        let study_uid = study_uid.clone();
+        let series_uid = series_uid.clone();
+        async move {
+            db_provider
+                .has_removal_record(tenant_id, &study_uid, &series_uid, sop_uid)
+                .await
+        }
+    };
+    assert!(!removed("1").await?);
+
+    let rejection = DicomRejection {
+        tenant_id: BoundedString::<64>::make_str(tenant_id),
+        study_uid: BoundedString::<64>::make_str(&study_uid),
+        series_uid: BoundedString::<64>::make_str(&series_uid),
+        sop_uid: BoundedString::<64>::make_str("1"),
+        reason_code: BoundedString::<16>::make_str(DicomRejection::REASON_QUALITY),
+        reason_meaning: BoundedString::<64>::make_str("Rejected for Quality Reasons"),
+        kos_sop_uid: BoundedString::<64>::make_str("9"),
+        rejected_time: now,
+        purged_time: None,
+    };
+    db_provider.save_rejections(&[rejection]).await?;
+    assert!(removed("1").await?);
+    assert!(!removed("2").await?);
+
+    // 序列级删除覆盖序列下的所有实例, 恢复后不再生效
+    let deletion_id = uuid::Uuid::new_v4().to_string();
+    let deletion = DicomDeletion {
+        deletion_id: BoundedString::<36>::make_str(&deletion_id),
+        tenant_id: BoundedString::<64>::make_str(tenant_id),
+        level: BoundedString::<16>::make_str(DicomDeletion::LEVEL_SERIES),
+        study_uid: BoundedString::<64>::make_str(&study_uid),
+        series_uid: Some(BoundedString::<64>::make_str(&series_uid)),
+        sop_uid: None,
+        instance_count: 0,
+        total_bytes: 0,
+        status: BoundedString::<16>::make_str(DicomDeletion::STATUS_TRASHED),
+        requested_by: None,
+        purge_after: None,
+        created_time: now,
+        updated_time: now,
+    };
+    db_provider.delete_dicom_objects(&deletion).await?;
+    assert!(removed("2").await?);
+    db_provider
+        .update_deletion_status(&deletion_id, DicomDeletion::STATUS_RESTORED)
+        .await?;
+    assert!(!removed("2").await?);
+    Ok(())
+}
+
+/// 每次运行使用不同的租户和 UID, 共用数据库中已有的数据不影响结果
+fn unique_tenant() -> String {
+    format!("test_tenant_{}", uuid::Uuid::new_v4().simple())
+}
+
+fn unique_uid() -> String {
+    format!("2.25.{}", uuid::Uuid::new_v4().as_u128())
+}
+
+fn make_state_meta(
+    tenant_id: &str,
+    patient_id: &str,
+    study_uid: &str,
+    series_uid: &str,
+    study_date: NaiveDate,
+) -> DicomStateMeta {
+    let updated_time = current_time() - chrono::Duration::days(10);
+    DicomStateMeta {
+        tenant_id: BoundedString::<64>::make_str(tenant_id),
+        patient_id: BoundedString::<64>::make_str(patient_id),
+        study_uid: BoundedString::<64>::make_str(study_uid),
+        series_uid: BoundedString::<64>::make_str(series_uid),
+        study_uid_hash: BoundedString::<20>::make_str(study_uid),
+        series_uid_hash: BoundedString::<20>::make_str(series_uid),
+        study_date_origin: DicomDateString::make(&study_date.format("%Y%m%d").to_string()),
+        patient_name: Some(BoundedString::<64>::make_str("TEST^PATIENT")),
+        patient_sex: None,
+        patient_birth_date: NaiveDate::from_ymd_opt(1990, 1, 1),
+        patient_birth_time: None,
+        patient_age: None,
+        patient_size: None,
+        patient_weight: None,
+        study_date,
+        study_time: None,
+        accession_number: None,
+        study_id: None,
+        study_description: None,
+        modality: Some(BoundedString::<16>::make_str("CT")),
+        series_number: Some(1),
+        series_date: None,
+        series_time: None,
+        series_description: None,
+        body_part_examined: None,
+        protocol_name: None,
+        series_related_instances: None,
+        created_time: updated_time,
+        updated_time,
+        storage_layout: 0,
+    }
+}
+
+fn make_image_meta(
+    state: &DicomStateMeta,
+    sop_uid: &str,
+    space_size: i64,
+    checksum: Option<&str>,
+) -> DicomImageMeta {
+    let now = current_time();
+    DicomImageMeta {
+        tenant_id: state.tenant_id.clone(),
+        patient_id: state.patient_id.clone(),
+        study_uid: state.study_uid.clone(),
+        series_uid: state.series_uid.clone(),
+        sop_uid: BoundedString::<64>::make_str(sop_uid),
+        study_uid_hash: state.study_uid_hash.clone(),
+        series_uid_hash: state.series_uid_hash.clone(),
+        content_date: None,
+        content_time: None,
+        instance_number: Some(1),
+        image_type: None,
+        image_orientation_patient: None,
+        image_position_patient: None,
+        slice_thickness: None,
+        spacing_between_slices: None,
+        slice_location: None,
+        samples_per_pixel: None,
+        photometric_interpretation: None,
+        width: None,
+        columns: None,
+        bits_allocated: None,
+        bits_stored: None,
+        high_bit: None,
+        pixel_representation: None,
+        rescale_intercept: None,
+        rescale_slope: None,
+        rescale_type: None,
+        window_center: None,
+        window_width: None,
+        transfer_syntax_uid: BoundedString::<64>::make_str("1.2.840.10008.1.2.1"),
+        pixel_data_location: Some(BoundedString::<512>::make(format!(
+            "{}/{}/{}/{}.dcm",
+            state.tenant_id, state.study_uid, state.series_uid, sop_uid
+        ))),
+        thumbnail_location: None,
+        sop_class_uid: BoundedString::<64>::make_str("1.2.840.10008.5.1.4.1.1.2"),
+        image_status: None,
+        space_size: Some(space_size),
+        checksum: checksum.map(BoundedString::<64>::make_str),
+        created_time: now,
+        updated_time: now,
+    }
+}
+
+fn make_deletion(tenant_id: &str, level: &str, study_uid: &str) -> DicomDeletion {
+    let now = current_time();
+    DicomDeletion {
+        deletion_id: BoundedString::<36>::make(uuid::Uuid::new_v4().to_string()),
+        tenant_id: BoundedString::<64>::make_str(tenant_id),
+        level: BoundedString::<16>::make_str(level),
+        study_uid: BoundedString::<64>::make_str(study_uid),
+        series_uid: None,
+        sop_uid: None,
+        instance_count: 0,
+        total_bytes: 0,
+        status: BoundedString::<16>::make_str(DicomDeletion::STATUS_TRASHED),
+        requested_by: Some(BoundedString::<64>::make_str("tester")),
+        purge_after: Some(now - chrono::Duration::minutes(1)),
+        created_time: now,
+        updated_time: now,
+    }
+}
+
+/// 租户当前用量 (total_bytes, instance_count, study_count), 没有记录时为 0
+async fn usage_of(
+    db_provider: &dyn DbProvider,
+    tenant_id: &str,
+) -> Result<(i64, i64, i64), Box<dyn std::error::Error>> {
+    Ok(db_provider
+        .get_tenant_usage(Some(tenant_id))
+        .await?
+        .first()
+        .map_or((0, 0, 0), |usage| {
+            (usage.total_bytes, usage.instance_count, usage.study_count)
+        }))
+}
+
+pub(crate) async fn delete_dicom_objects(
+    db_provider: &dyn DbProvider,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tenant_id = unique_tenant();
+    let study_uid = unique_uid();
+    let study_date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+    let series_a = make_state_meta(&tenant_id, "PID0001", &study_uid, &unique_uid(), study_date);
+    let series_b = make_state_meta(&tenant_id, "PID0001", &study_uid, &unique_uid(), study_date);
+    db_provider
+        .save_state_list(&[series_a.clone(), series_b.clone()])
+        .await?;
+    db_provider
+        .save_image_list(&[
+            make_image_meta(&series_a, "1", 100, None),
+            make_image_meta(&series_a, "2", 200, None),
+            make_image_meta(&series_b, "3", 300, None),
+        ])
+        .await?;
+    assert_eq!(usage_of(db_provider, &tenant_id).await?, (600, 3, 1));
+
+    // 删除序列 A
+    let mut deletion = make_deletion(&tenant_id, DicomDeletion::LEVEL_SERIES, &study_uid);
+    deletion.series_uid = Some(series_a.series_uid.clone());
+    let deleted = db_provider.delete_dicom_objects(&deletion).await?;
+    assert_eq!(deleted.instance_count, 2);
+    assert_eq!(deleted.total_bytes, 300);
+    let series_list = db_provider.get_state_metaes(&tenant_id, &study_uid).await?;
+    assert_eq!(series_list.len(), 1);
+    assert_eq!(series_list[0].series_uid, series_b.series_uid);
+    assert!(
+        !db_provider
+            .image_exists(&tenant_id, &study_uid, series_a.series_uid.as_str(), "1")
+            .await?
+    );
+    assert!(
+        db_provider
+            .image_exists(&tenant_id, &study_uid, series_b.series_uid.as_str(), "3")
+            .await?
+    );
+    assert_eq!(usage_of(db_provider, &tenant_id).await?, (300, 1, 1));
+
+    let deletion_id = deleted.deletion_id.as_str();
+    let saved = db_provider.get_deletion(deletion_id).await?.unwrap();
+    assert_eq!(saved.instance_count, 2);
+    assert_eq!(saved.status.as_str(), DicomDeletion::STATUS_TRASHED);
+    let deletions = db_provider
+        .get_deletions(Some(&tenant_id), Some(DicomDeletion::STATUS_TRASHED), 10)
+        .await?;
+    assert_eq!(deletions.len(), 1);
+    assert!(
+        db_provider
+            .get_expired_deletions(current_time(), 10000)
+            .await?
+            .iter()
+            .any(|deletion| deletion.deletion_id.as_str() == deletion_id)
+    );
+    db_provider
+        .update_deletion_status(deletion_id, DicomDeletion::STATUS_PURGED)
+        .await?;
+    let saved = db_provider.get_deletion(deletion_id).await?.unwrap();
+    assert_eq!(saved.status.as_str(), DicomDeletion::STATUS_PURGED);
+    assert!(
+        db_provider
+            .get_deletions(Some(&tenant_id), Some(DicomDeletion::STATUS_TRASHED), 10)
+            .await?
+            .is_empty()
+    );
+
+    // 删除整个检查
+    let deletion = make_deletion(&tenant_id, DicomDeletion::LEVEL_STUDY, &study_uid);
+    let deleted = db_provider.delete_dicom_objects(&deletion).await?;
+    assert_eq!(deleted.instance_count, 1);
+    assert_eq!(deleted.total_bytes, 300);
+    assert!(
+        db_provider
+            .get_state_metaes(&tenant_id, &study_uid)
+            .await?
+            .is_empty()
+    );
+    assert_eq!(usage_of(db_provider, &tenant_id).await?, (0, 0, 0));
+    assert!(db_provider.get_deletion("not-exists").await?.is_none());
+    Ok(())
+}
+
+pub(crate) async fn save_attribute_changes(
+    db_provider: &dyn DbProvider,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tenant_id = unique_tenant();
+    let study_uid = unique_uid();
+    let state = make_state_meta(
+        &tenant_id,
+        "PID0001",
+        &study_uid,
+        &unique_uid(),
+        NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+    );
+    db_provider.save_state_info(&state).await?;
+    db_provider
+        .save_image_list(&[make_image_meta(&state, "1", 100, Some("a"))])
+        .await?;
+
+    let update = DicomAttributeUpdate {
+        patient_id: Some("PID0002".to_string()),
+        patient_name: Some("NEW^NAME".to_string()),
+        ..Default::default()
+    };
+    let change = DicomAttributeChange {
+        audit: DicomChangeAudit {
+            audit_id: BoundedString::<36>::make(uuid::Uuid::new_v4().to_string()),
+            tenant_id: state.tenant_id.clone(),
+            operation: BoundedString::<32>::make_str(DicomChangeAudit::OP_UPDATE_PATIENT),
+            patient_id: state.patient_id.clone(),
+            study_uid: state.study_uid.clone(),
+            before_value: r#"{"patient_id":"PID0001","patient_name":"TEST^PATIENT"}"#.to_string(),
+            after_value: serde_json::to_string(&update)?,
+            file_count: 1,
+            requested_by: Some(BoundedString::<64>::make_str("tester")),
+            created_time: current_time(),
+        },
+        update,
+        files: vec![DicomRewrittenFile {
+            series_uid: state.series_uid.clone(),
+            sop_uid: BoundedString::<64>::make_str("1"),
+            checksum: Some(BoundedString::<64>::make_str("b")),
+            space_size: 150,
+        }],
+    };
+    db_provider.save_attribute_changes(&[change]).await?;
+
+    let series_list = db_provider.get_state_metaes(&tenant_id, &study_uid).await?;
+    assert_eq!(series_list[0].patient_id.as_str(), "PID0002");
+    assert_eq!(
+        series_list[0].patient_name.as_ref().map(|name| name.as_str()),
+        Some("NEW^NAME")
+    );
+    assert_eq!(
+        db_provider
+            .get_patient_state_metaes(&tenant_id, "PID0002")
+            .await?
+            .len(),
+        1
+    );
+    // 文件大小的变化计入用量
+    assert_eq!(usage_of(db_provider, &tenant_id).await?, (150, 1, 1));
+    let candidates = db_provider
+        .get_fixity_candidates(current_time(), 10000)
+        .await?;
+    let check = candidates
+        .iter()
+        .find(|check| check.tenant_id.as_str() == tenant_id)
+        .unwrap();
+    assert_eq!(check.expected_checksum.as_str(), "b");
+
+    // 修改前后的患者都能查到审计记录
+    for patient_id in ["PID0001", "PID0002"] {
+        let audits = db_provider
+            .get_change_audits(&tenant_id, Some(patient_id), None, 10)
+            .await?;
+        assert_eq!(audits.len(), 1);
+        assert_eq!(audits[0].file_count, 1);
+    }
+    assert_eq!(
+        db_provider
+            .get_change_audits(&tenant_id, None, Some(&study_uid), 10)
+            .await?
+            .len(),
+        1
+    );
+    assert!(
+        db_provider
+            .get_change_audits(&tenant_id, Some("PID0003"), None, 10)
+            .await?
+            .is_empty()
+    );
+    Ok(())
+}
+
+pub(crate) async fn save_rejections(
+    db_provider: &dyn DbProvider,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tenant_id = unique_tenant();
+    let study_uid = unique_uid();
+    let state = make_state_meta(
+        &tenant_id,
+        "PID0001",
+        &study_uid,
+        &unique_uid(),
+        NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+    );
+    db_provider.save_state_info(&state).await?;
+    db_provider
+        .save_image_list(&[
+            make_image_meta(&state, "1", 100, None),
+            make_image_meta(&state, "2", 100, None),
+        ])
+        .await?;
+
+    let rejection = |sop_uid: &str, reason_code: &str| DicomRejection {
+        tenant_id: state.tenant_id.clone(),
+        study_uid: state.study_uid.clone(),
+        series_uid: state.series_uid.clone(),
+        sop_uid: BoundedString::<64>::make_str(sop_uid),
+        reason_code: BoundedString::<16>::make_str(reason_code),
+        reason_meaning: BoundedString::<64>::make_str("Rejected"),
+        kos_sop_uid: BoundedString::<64>::make_str("9"),
+        rejected_time: current_time(),
+        purged_time: None,
+    };
+    // 实例 3 尚未收到, 只保存拒绝记录
+    let marked = db_provider
+        .save_rejections(&[
+            rejection("1", DicomRejection::REASON_RETENTION_EXPIRED),
+            rejection("3", DicomRejection::REASON_QUALITY),
+        ])
+        .await?;
+    assert_eq!(marked, 1);
+    let mut rejected = db_provider
+        .get_rejected_sop_uids(&tenant_id, &study_uid)
+        .await?;
+    rejected.sort();
+    assert_eq!(rejected, vec!["1".to_string(), "3".to_string()]);
+
+    let to_purge: Vec<DicomRejection> = db_provider
+        .get_rejections_to_purge(DicomRejection::REASON_RETENTION_EXPIRED, 10000)
+        .await?
+        .into_iter()
+        .filter(|rejection| rejection.tenant_id.as_str() == tenant_id)
+        .collect();
+    assert_eq!(to_purge.len(), 1);
+    assert_eq!(to_purge[0].sop_uid.as_str(), "1");
+    db_provider.mark_rejections_purged(&to_purge).await?;
+    assert!(
+        !db_provider
+            .get_rejections_to_purge(DicomRejection::REASON_RETENTION_EXPIRED, 10000)
+            .await?
+            .iter()
+            .any(|rejection| rejection.tenant_id.as_str() == tenant_id)
+    );
+    Ok(())
+}
+
+pub(crate) async fn retention(
+    db_provider: &dyn DbProvider,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tenant_id = unique_tenant();
+    let old_study = unique_uid();
+    let new_study = unique_uid();
+    let old_state = make_state_meta(
+        &tenant_id,
+        "PID0001",
+        &old_study,
+        &unique_uid(),
+        NaiveDate::from_ymd_opt(2000, 1, 1).unwrap(),
+    );
+    let new_state = make_state_meta(
+        &tenant_id,
+        "PID0001",
+        &new_study,
+        &unique_uid(),
+        NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+    );
+    db_provider
+        .save_state_list(&[old_state.clone(), new_state.clone()])
+        .await?;
+    db_provider
+        .save_image_list(&[make_image_meta(&old_state, "1", 100, None)])
+        .await?;
+
+    let before = NaiveDate::from_ymd_opt(2010, 1, 1).unwrap();
+    let retention_studies = |before: NaiveDate| {
+        let tenant_id = tenant_id.clone();
+        async move {
+            db_provider
+                .get_retention_studies(before, &tenant_id, "", 10000)
+                .await
+                .map(|studies| {
+                    studies
+                        .into_iter()
+                        .filter(|study| study.tenant_id.as_str() == tenant_id)
+                        .collect::<Vec<_>>()
+                })
+        }
+    };
+    let studies = retention_studies(before).await?;
+    assert_eq!(studies.len(), 1);
+    assert_eq!(studies[0].study_uid.as_str(), old_study);
+    assert_eq!(studies[0].modalities, vec!["CT".to_string()]);
+    assert_eq!(
+        studies[0].sop_class_uids,
+        vec!["1.2.840.10008.5.1.4.1.1.2".to_string()]
+    );
+    assert_eq!(studies[0].patient_birth_date, NaiveDate::from_ymd_opt(1990, 1, 1));
+
+    let now = current_time();
+    let candidate = DicomRetentionCandidate {
+        tenant_id: old_state.tenant_id.clone(),
+        study_uid: old_state.study_uid.clone(),
+        patient_id: old_state.patient_id.clone(),
+        policy_name: BoundedString::<64>::make_str("default"),
+        expire_date: before,
+        status: BoundedString::<16>::make_str(DicomRetentionCandidate::STATUS_PENDING),
+        deletion_id: None,
+        created_time: now,
+        updated_time: now,
+    };
+    assert_eq!(
+        db_provider
+            .save_retention_candidates(std::slice::from_ref(&candidate))
+            .await?,
+        1
+    );
+    // 已存在的候选不覆盖
+    assert_eq!(db_provider.save_retention_candidates(&[candidate]).await?, 0);
+    assert!(retention_studies(before).await?.is_empty());
+    assert_eq!(
+        db_provider
+            .get_retention_candidates(
+                Some(&tenant_id),
+                Some(DicomRetentionCandidate::STATUS_PENDING),
+                10
+            )
+            .await?
+            .len(),
+        1
+    );
+    let deletable = |tenant_id: String| async move {
+        db_provider
+            .get_deletable_retention_candidates(10000)
+            .await
+            .map(|candidates| {
+                candidates
+                    .iter()
+                    .any(|candidate| candidate.tenant_id.as_str() == tenant_id)
+            })
+    };
+    assert!(deletable(tenant_id.clone()).await?);
+
+    // 法律保全中的检查不能删除, 也不成为保留候选
+    let hold_id = uuid::Uuid::new_v4().to_string();
+    db_provider
+        .save_legal_hold(&DicomLegalHold {
+            hold_id: BoundedString::<36>::make_str(&hold_id),
+            tenant_id: old_state.tenant_id.clone(),
+            patient_id: None,
+            study_uid: Some(old_state.study_uid.clone()),
+            reason: "litigation".to_string(),
+            created_by: Some(BoundedString::<64>::make_str("tester")),
+            created_time: now,
+            released_by: None,
+            released_time: None,
+        })
+        .await?;
+    assert!(
+        db_provider
+            .is_on_legal_hold(&tenant_id, None, &old_study)
+            .await?
+    );
+    assert!(
+        !db_provider
+            .is_on_legal_hold(&tenant_id, None, &new_study)
+            .await?
+    );
+    assert!(!deletable(tenant_id.clone()).await?);
+    assert_eq!(
+        db_provider
+            .get_legal_holds(&tenant_id, false, 10)
+            .await?
+            .len(),
+        1
+    );
+    assert!(
+        db_provider
+            .release_legal_hold(&tenant_id, &hold_id, Some("tester"))
+            .await?
+    );
+    assert!(
+        !db_provider
+            .release_legal_hold(&tenant_id, &hold_id, Some("tester"))
+            .await?
+    );
+    assert!(
+        db_provider
+            .get_legal_holds(&tenant_id, false, 10)
+            .await?
+            .is_empty()
+    );
+    let holds = db_provider.get_legal_holds(&tenant_id, true, 10).await?;
+    assert_eq!(holds.len(), 1);
+    assert!(holds[0].released_time.is_some());
+    assert!(deletable(tenant_id.clone()).await?);
+
+    // 按患者保全覆盖患者的所有检查
+    db_provider
+        .save_legal_hold(&DicomLegalHold {
+            hold_id: BoundedString::<36>::make(uuid::Uuid::new_v4().to_string()),
+            tenant_id: old_state.tenant_id.clone(),
+            patient_id: Some(old_state.patient_id.clone()),
+            study_uid: None,
+            reason: "litigation".to_string(),
+            created_by: None,
+            created_time: now,
+            released_by: None,
+            released_time: None,
+        })
+        .await?;
+    assert!(
+        db_provider
+            .is_on_legal_hold(&tenant_id, Some("PID0001"), &new_study)
+            .await?
+    );
+    assert!(
+        retention_studies(NaiveDate::from_ymd_opt(2030, 1, 1).unwrap())
+            .await?
+            .is_empty()
+    );
+
+    let deletion_id = uuid::Uuid::new_v4().to_string();
+    db_provider
+        .update_retention_candidate(
+            &tenant_id,
+            &old_study,
+            DicomRetentionCandidate::STATUS_DELETED,
+            Some(&deletion_id),
+        )
+        .await?;
+    let candidate = db_provider
+        .get_retention_candidate(&tenant_id, &old_study)
+        .await?
+        .unwrap();
+    assert_eq!(
+        candidate.status.as_str(),
+        DicomRetentionCandidate::STATUS_DELETED
+    );
+    assert_eq!(
+        candidate.deletion_id.as_ref().map(|id| id.as_str()),
+        Some(deletion_id.as_str())
+    );
+    assert!(
+        db_provider
+            .get_retention_candidate(&tenant_id, &new_study)
+            .await?
+            .is_none()
+    );
+    Ok(())
+}
+
+pub(crate) async fn tenant_usage(
+    db_provider: &dyn DbProvider,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tenant_id = unique_tenant();
+    let study_date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+    let study_a = make_state_meta(&tenant_id, "PID0001", &unique_uid(), &unique_uid(), study_date);
+    let study_b = make_state_meta(&tenant_id, "PID0002", &unique_uid(), &unique_uid(), study_date);
+    db_provider
+        .save_state_list(&[study_a.clone(), study_b.clone()])
+        .await?;
+    let images = [
+        make_image_meta(&study_a, "1", 100, None),
+        make_image_meta(&study_a, "2", 200, None),
+        make_image_meta(&study_b, "3", 400, None),
+    ];
+    db_provider.save_image_list(&images).await?;
+    // 重复接收同一实例不重复计算用量
+    db_provider.save_image_list(&images[..1]).await?;
+    assert_eq!(usage_of(db_provider, &tenant_id).await?, (700, 3, 2));
+    assert!(
+        db_provider
+            .get_tenant_usage(None)
+            .await?
+            .iter()
+            .any(|usage| usage.tenant_id.as_str() == tenant_id)
+    );
+
+    let today = current_time().date();
+    let history = db_provider
+        .get_tenant_usage_history(&tenant_id, today, today)
+        .await?;
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].usage_date, today);
+    assert_eq!(history[0].total_bytes, 700);
+    assert!(
+        db_provider
+            .get_tenant_usage_history(&tenant_id, today.pred_opt().unwrap(), today.pred_opt().unwrap())
+            .await?
+            .is_empty()
+    );
+    assert!(db_provider.get_tenant_usage(Some("not-exists")).await?.is_empty());
+    Ok(())
+}
+
+pub(crate) async fn study_meta_instances(
+    db_provider: &dyn DbProvider,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tenant_id = unique_tenant();
+    let study_uid = unique_uid();
+    let state = make_state_meta(
+        &tenant_id,
+        "PID0001",
+        &study_uid,
+        &unique_uid(),
+        NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+    );
+    let instances = || async {
+        Ok::<_, Box<dyn std::error::Error>>(
+            db_provider
+                .get_study_meta(&tenant_id, &study_uid)
+                .await?
+                .map(|study| (study.number_of_series, study.number_of_instances)),
+        )
+    };
+
+    // 实例先于序列保存时没有检查汇总, 保存序列后完整计算
+    db_provider
+        .save_image_list(&[
+            make_image_meta(&state, "1", 100, None),
+            make_image_meta(&state, "2", 100, None),
+        ])
+        .await?;
+    assert_eq!(instances().await?, None);
+    db_provider.save_state_info(&state).await?;
+    assert_eq!(instances().await?, Some((1, 2)));
+
+    // 实例 4 收到前已有拒绝记录
+    let rejection = |sop_uid: &str| DicomRejection {
+        tenant_id: state.tenant_id.clone(),
+        study_uid: state.study_uid.clone(),
+        series_uid: state.series_uid.clone(),
+        sop_uid: BoundedString::<64>::make_str(sop_uid),
+        reason_code: BoundedString::<16>::make_str(DicomRejection::REASON_QUALITY),
+        reason_meaning: BoundedString::<64>::make_str("Rejected"),
+        kos_sop_uid: BoundedString::<64>::make_str("9"),
+        rejected_time: current_time(),
+        purged_time: None,
+    };
+    db_provider.save_rejections(&[rejection("4")]).await?;
+    // 同一批次中重复的实例只计算一次
+    db_provider
+        .save_image_list(&[
+            make_image_meta(&state, "3", 100, None),
+            make_image_meta(&state, "3", 100, None),
+            make_image_meta(&state, "4", 100, None),
+            make_image_meta(&state, "2", 100, None),
+        ])
+        .await?;
+    assert_eq!(instances().await?, Some((1, 3)));
+
+    // 被拒绝的实例重新收到时仍不计入
+    db_provider.save_rejections(&[rejection("1")]).await?;
+    assert_eq!(instances().await?, Some((1, 2)));
+    db_provider
+        .save_image_list(&[make_image_meta(&state, "1", 100, None)])
+        .await?;
+    assert_eq!(instances().await?, Some((1, 2)));
+    Ok(())
+}
+
+pub(crate) async fn series_tier(
+    db_provider: &dyn DbProvider,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tenant_id = unique_tenant();
+    let tenant_ids = vec![tenant_id.clone()];
+    let state = make_state_meta(
+        &tenant_id,
+        "PID0001",
+        &unique_uid(),
+        &unique_uid(),
+        NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+    );
+    db_provider.save_state_info(&state).await?;
+    let now = current_time();
+    let day_ago = now - chrono::Duration::days(1);
+
+    // 没有分层记录时位于热存储层, 以收图时间作为最近访问时间
+    let candidates = db_provider
+        .get_series_tier_candidates("hot", &tenant_ids, day_ago, 10)
+        .await?;
+    assert_eq!(candidates.len(), 1);
+    assert_eq!(candidates[0].series_uid, state.series_uid);
+    assert_eq!(candidates[0].tier.as_str(), "hot");
+
+    db_provider
+        .touch_series_access(
+            &tenant_id,
+            state.study_uid.as_str(),
+            state.series_uid.as_str(),
+            state.study_date_origin.as_str(),
+            now,
+        )
+        .await?;
+    assert!(
+        db_provider
+            .get_series_tier_candidates("hot", &tenant_ids, day_ago, 10)
+            .await?
+            .is_empty()
+    );
+
+    // 迁移到冷存储层不修改最近访问时间
+    db_provider
+        .save_series_tier(&DicomSeriesTier {
+            tenant_id: state.tenant_id.clone(),
+            study_uid: state.study_uid.clone(),
+            series_uid: state.series_uid.clone(),
+            study_date_origin: state.study_date_origin.clone(),
+            tier: BoundedString::<32>::make_str("cold"),
+            last_access_time: day_ago - chrono::Duration::days(30),
+            updated_time: now,
+            storage_layout: 0,
+        })
+        .await?;
+    let later = now + chrono::Duration::minutes(1);
+    assert!(
+        db_provider
+            .get_series_tier_candidates("hot", &tenant_ids, later, 10)
+            .await?
+            .is_empty()
+    );
+    assert!(
+        db_provider
+            .get_series_tier_candidates("cold", &tenant_ids, day_ago, 10)
+            .await?
+            .is_empty()
+    );
+    let candidates = db_provider
+        .get_series_tier_candidates("cold", &tenant_ids, later, 10)
+        .await?;
+    assert_eq!(candidates.len(), 1);
+    assert_eq!(candidates[0].tier.as_str(), "cold");
+    Ok(())
+}
+
+pub(crate) async fn fixity(db_provider: &dyn DbProvider) -> Result<(), Box<dyn std::error::Error>> {
+    let tenant_id = unique_tenant();
+    let state = make_state_meta(
+        &tenant_id,
+        "PID0001",
+        &unique_uid(),
+        &unique_uid(),
+        NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+    );
+    db_provider.save_state_info(&state).await?;
+    let image = make_image_meta(&state, "1", 100, Some("abc"));
+    db_provider
+        .save_image_list(&[image.clone(), make_image_meta(&state, "2", 100, None)])
+        .await?;
+    let candidates_of = |checked_before: chrono::NaiveDateTime| {
+        let tenant_id = tenant_id.clone();
+        async move {
+            db_provider
+                .get_fixity_candidates(checked_before, 100000)
+                .await
+                .map(|candidates| {
+                    candidates
+                        .into_iter()
+                        .filter(|check| check.tenant_id.as_str() == tenant_id)
+                        .collect::<Vec<_>>()
+                })
+        }
+    };
+
+    // 没有 checksum 的实例不校验
+    let now = current_time();
+    let candidates = candidates_of(now).await?;
+    assert_eq!(candidates.len(), 1);
+    let mut check = candidates[0].clone();
+    assert_eq!(check.sop_uid.as_str(), "1");
+    assert_eq!(check.expected_checksum.as_str(), "abc");
+    assert_eq!(check.status.as_str(), DicomFixityCheck::STATUS_PENDING);
+    assert!(check.checked_time.is_none());
+
+    check.actual_checksum = Some(BoundedString::<64>::make_str("def"));
+    check.status = BoundedString::<16>::make_str(DicomFixityCheck::STATUS_MISMATCH);
+    check.checked_time = Some(now);
+    db_provider.save_fixity_checks(&[check.clone()]).await?;
+    assert!(
+        candidates_of(now - chrono::Duration::minutes(1))
+            .await?
+            .is_empty()
+    );
+    assert_eq!(
+        candidates_of(now + chrono::Duration::minutes(1))
+            .await?
+            .len(),
+        1
+    );
+    let failures = db_provider.get_fixity_failures(Some(&tenant_id), 10).await?;
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].status.as_str(), DicomFixityCheck::STATUS_MISMATCH);
+
+    check.actual_checksum = Some(BoundedString::<64>::make_str("abc"));
+    check.status = BoundedString::<16>::make_str(DicomFixityCheck::STATUS_OK);
+    db_provider.save_fixity_checks(&[check]).await?;
+    assert!(
+        db_provider
+            .get_fixity_failures(Some(&tenant_id), 10)
+            .await?
+            .is_empty()
+    );
+
+    let path = image.pixel_data_location.unwrap().as_str().to_string();
+    assert_eq!(
+        db_provider
+            .find_indexed_files(&[path.clone(), format!("{}.missing", path)])
+            .await?,
+        vec![path]
+    );
+    Ok(())
+}
+
+pub(crate) async fn forward_tasks(
+    db_provider: &dyn DbProvider,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tenant_id = unique_tenant();
+    let now = current_time();
+    let make_task = |next_retry_time: chrono::NaiveDateTime| DicomForwardTask {
+        task_id: FixedLengthString::<36>::make(uuid::Uuid::new_v4().to_string()),
+        tenant_id: BoundedString::<64>::make_str(&tenant_id),
+        rule_name: BoundedString::<64>::make_str("rule"),
+        destination: BoundedString::<64>::make_str("PACS"),
+        study_uid: BoundedString::<64>::make_str("1.2.3"),
+        series_uid: BoundedString::<64>::make_str("1.2.3.4"),
+        sop_uid: BoundedString::<64>::make_str("1.2.3.4.5"),
+        file_path: BoundedString::<512>::make_str("t/1.dcm"),
+        status: BoundedString::<16>::make_str(DicomForwardTask::STATUS_PENDING),
+        retry_times: 0,
+        last_error: None,
+        next_retry_time,
+        created_time: now,
+        updated_time: now,
+    };
+    let due = make_task(now - chrono::Duration::minutes(1));
+    let later = make_task(now + chrono::Duration::hours(1));
+    db_provider
+        .save_forward_tasks(&[due.clone(), later.clone()])
+        .await?;
+    // task_id 已存在时忽略
+    db_provider.save_forward_tasks(std::slice::from_ref(&due)).await?;
+
+    let claim = |stale_before: chrono::NaiveDateTime| {
+        let tenant_id = tenant_id.clone();
+        async move {
+            db_provider
+                .claim_forward_tasks(current_time(), stale_before, 100000)
+                .await
+                .map(|tasks| {
+                    tasks
+                        .into_iter()
+                        .filter(|task| task.tenant_id.as_str() == tenant_id)
+                        .collect::<Vec<_>>()
+                })
+        }
+    };
+    let claimed = claim(now - chrono::Duration::hours(1)).await?;
+    assert_eq!(claimed.len(), 1);
+    assert_eq!(claimed[0].task_id, due.task_id);
+    assert_eq!(claimed[0].status.as_str(), DicomForwardTask::STATUS_RUNNING);
+    // 正在执行的任务不重复领取, 超时后可以重新领取
+    assert!(claim(now - chrono::Duration::hours(1)).await?.is_empty());
+    let claimed = claim(current_time() + chrono::Duration::minutes(1)).await?;
+    assert_eq!(claimed.len(), 1);
+
+    let mut task = claimed[0].clone();
+    task.status = BoundedString::<16>::make_str(DicomForwardTask::STATUS_FAILED);
+    task.retry_times = 3;
+    task.last_error = Some(BoundedString::<512>::make_str("connection refused"));
+    db_provider.update_forward_task(&task).await?;
+    let find = |status: &'static str, task_id: String| async move {
+        db_provider
+            .get_forward_tasks(Some(status), 100000)
+            .await
+            .map(|tasks| tasks.into_iter().find(|task| task.task_id.as_str() == task_id))
+    };
+    let failed = find(DicomForwardTask::STATUS_FAILED, task.task_id.as_str().to_string())
+        .await?
+        .unwrap();
+    assert_eq!(failed.retry_times, 3);
+    assert_eq!(
+        failed.last_error.as_ref().map(|error| error.as_str()),
+        Some("connection refused")
+    );
+
+    db_provider.requeue_forward_task(task.task_id.as_str()).await?;
+    let requeued = find(DicomForwardTask::STATUS_PENDING, task.task_id.as_str().to_string())
+        .await?
+        .unwrap();
+    assert_eq!(requeued.retry_times, 0);
+
+    // 结束后不留下待执行的任务
+    for mut task in [requeued, later] {
+        task.status = BoundedString::<16>::make_str(DicomForwardTask::STATUS_SUCCESS);
+        db_provider.update_forward_task(&task).await?;
+    }
+    Ok(())
+}
+
+pub(crate) async fn save_prefetch_task(
+    db_provider: &dyn DbProvider,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tenant_id = unique_tenant();
+    let study_uid = unique_uid();
+    let now = current_time();
+    let mut task = DicomPrefetchTask {
+        tenant_id: BoundedString::<64>::make_str(&tenant_id),
+        source: BoundedString::<64>::make_str("pacs"),
+        study_uid: BoundedString::<64>::make_str(&study_uid),
+        patient_id: BoundedString::<64>::make_str("PID0001"),
+        trigger_study_uid: BoundedString::<64>::make_str("1.2.3"),
+        study_date: NaiveDate::from_ymd_opt(2024, 1, 1),
+        modalities: None,
+        status: BoundedString::<16>::make_str(DicomPrefetchTask::STATUS_RUNNING),
+        completed: 0,
+        failed: 0,
+        last_error: None,
+        created_time: now,
+        updated_time: now,
+    };
+    db_provider.save_prefetch_task(&task).await?;
+
+    // 按 (tenant_id, source, study_uid) 更新
+    task.status = BoundedString::<16>::make_str(DicomPrefetchTask::STATUS_FAILED);
+    task.completed = 3;
+    task.failed = 1;
+    task.last_error = Some(BoundedString::<512>::make_str("C-MOVE failed"));
+    db_provider.save_prefetch_task(&task).await?;
+    let saved = db_provider
+        .get_prefetch_task(&tenant_id, "pacs", &study_uid)
+        .await?;
+    assert_eq!(saved.status.as_str(), DicomPrefetchTask::STATUS_FAILED);
+    assert_eq!((saved.completed, saved.failed), (3, 1));
+    assert_eq!(
+        saved.last_error.as_ref().map(|error| error.as_str()),
+        Some("C-MOVE failed")
+    );
+    assert!(
+        db_provider
+            .get_prefetch_task(&tenant_id, "other", &study_uid)
+            .await
+            .is_err()
+    );
+    Ok(())
+}
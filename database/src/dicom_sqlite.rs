@@ -0,0 +1,3105 @@
+//! SQLite 实现, 用于单机部署和开发环境, 不依赖外部数据库服务.
+//!
+//! 启动时执行嵌入的迁移脚本建表. SQL 与 MySqlDbProvider 基本一致, ON DUPLICATE KEY UPDATE
+//! 改写为 ON CONFLICT, UPDATE ... JOIN 改写为相关子查询. SQLite 同一时间只允许一个写事务,
+//! 写事务使用 BEGIN IMMEDIATE 在开始时获取写锁, 代替 SELECT ... FOR UPDATE.
+
+use crate::dicom_dbprovider::{DbError, DbPoolOptions, DbProvider, current_time};
+use crate::dicom_meta::{
+    DicomAttributeChange, DicomChangeAudit, DicomDeletion, DicomFixityCheck, DicomForwardTask,
+    DicomImageFile, DicomImageMeta, DicomJsonMeta, DicomLegalHold, DicomMppsMeta, DicomPatientMeta,
+    DicomPrefetchTask, DicomRejection, DicomRetentionCandidate, DicomRetentionStudy,
+    DicomSeriesTier, DicomStateMeta, DicomStoreMeta, DicomStudyMeta, DicomStudyQuery,
+    DicomTenantUsage,
+};
+use crate::dicom_migration::{AppliedMigration, SQLITE_MIGRATIONS, pending_migrations};
+use crate::dicom_mysql::{
+    DELETION_SCOPE, FIXITY_CHECK_COLUMNS, IMAGE_META_COLUMNS, JSON_META_COLUMNS, MPPS_COLUMNS,
+    PREFETCH_TASK_COLUMNS, STORE_META_COLUMNS, placeholders, split_list,
+};
+use crate::dicom_pg::{
+    CHANGE_AUDIT_COLUMNS, DELETION_COLUMNS, FORWARD_TASK_COLUMNS, LEGAL_HOLD_COLUMNS,
+    ON_LEGAL_HOLD, REJECTION_COLUMNS, RETENTION_CANDIDATE_COLUMNS, STATE_META_COLUMNS,
+    STUDY_META_COLUMNS, StudyInstanceDelta, UsageDelta,
+};
+use async_trait::async_trait;
+use sqlx::query::Query;
+use sqlx::sqlite::{
+    SqliteArguments, SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions,
+    SqliteRow, SqliteSynchronous,
+};
+use sqlx::{Executor, QueryBuilder, Row, Sqlite, Transaction};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// SQLite 实现, 连接来自 sqlx 连接池
+#[derive(Clone)]
+pub struct SqliteDbProvider {
+    pool: SqlitePool,
+}
+
+impl SqliteDbProvider {
+    /// 创建连接池, 数据库文件不存在时自动创建, 连接在第一次使用时建立
+    pub fn new(path: &str, options: &DbPoolOptions) -> Result<Self, DbError> {
+        if let Some(parent) = Path::new(path)
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent).map_err(|e| {
+                DbError::DatabaseError(format!(
+                    "failed to create directory {}: {}",
+                    parent.display(),
+                    e
+                ))
+            })?;
+        }
+        let connect_options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+            // WAL 模式下读操作不会被写事务阻塞
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal)
+            // 等待其他连接释放写锁
+            .busy_timeout(options.wait_timeout)
+            .statement_cache_capacity(if options.statement_cache { 100 } else { 0 });
+        let pool = SqlitePoolOptions::new()
+            .max_connections(options.max_size as u32)
+            .min_connections(options.min_size as u32)
+            .acquire_timeout(options.wait_timeout.max(options.connect_timeout))
+            .test_before_acquire(options.health_check)
+            .connect_lazy_with(connect_options);
+        Ok(Self { pool })
+    }
+
+    /// 预先建立 min_size 个连接, 同时检查数据库文件是否可以打开
+    pub async fn warm_up(&self, min_size: usize) -> Result<(), DbError> {
+        let max_size = self.pool.options().get_max_connections() as usize;
+        let mut connections = Vec::with_capacity(min_size);
+        for _ in 0..min_size.min(max_size) {
+            connections.push(
+                self.pool
+                    .acquire()
+                    .await
+                    .map_err(|e| DbError::DatabaseError(e.to_string()))?,
+            );
+        }
+        Ok(())
+    }
+
+    /// 写事务, 开始时即获取写锁, 避免读后写时锁升级失败
+    async fn begin(&self) -> Result<Transaction<'static, Sqlite>, DbError> {
+        self.pool
+            .begin_with("BEGIN IMMEDIATE")
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))
+    }
+
+    fn state_meta_from_row(row: &SqliteRow) -> DicomStateMeta {
+        DicomStateMeta {
+            tenant_id: row.get(0),
+            patient_id: row.get(1),
+            study_uid: row.get(2),
+            series_uid: row.get(3),
+            study_uid_hash: row.get(4),
+            series_uid_hash: row.get(5),
+            study_date_origin: row.get(6),
+            patient_name: row.get(7),
+            patient_sex: row.get(8),
+            patient_birth_date: row.get(9),
+            patient_birth_time: row.get(10),
+            patient_age: row.get(11),
+            patient_size: row.get(12),
+            patient_weight: row.get(13),
+            study_date: row.get(14),
+            study_time: row.get(15),
+            accession_number: row.get(16),
+            study_id: row.get(17),
+            study_description: row.get(18),
+            modality: row.get(19),
+            series_number: row.get(20),
+            series_date: row.get(21),
+            series_time: row.get(22),
+            series_description: row.get(23),
+            body_part_examined: row.get(24),
+            protocol_name: row.get(25),
+            series_related_instances: row.get(26),
+            created_time: row.get(27),
+            updated_time: row.get(28),
+            storage_layout: row.get(29),
+        }
+    }
+
+    fn forward_task_from_row(row: &SqliteRow) -> DicomForwardTask {
+        DicomForwardTask {
+            task_id: row.get(0),
+            tenant_id: row.get(1),
+            rule_name: row.get(2),
+            destination: row.get(3),
+            study_uid: row.get(4),
+            series_uid: row.get(5),
+            sop_uid: row.get(6),
+            file_path: row.get(7),
+            status: row.get(8),
+            retry_times: row.get(9),
+            last_error: row.get(10),
+            next_retry_time: row.get(11),
+            created_time: row.get(12),
+            updated_time: row.get(13),
+        }
+    }
+
+    fn fixity_check_from_row(row: &SqliteRow) -> DicomFixityCheck {
+        DicomFixityCheck {
+            tenant_id: row.get(0),
+            study_uid: row.get(1),
+            series_uid: row.get(2),
+            sop_uid: row.get(3),
+            file_path: row.get(4),
+            expected_checksum: row.get(5),
+            actual_checksum: row.get(6),
+            status: row.get(7),
+            checked_time: row.get(8),
+        }
+    }
+
+    fn image_file_from_row(row: &SqliteRow) -> DicomImageFile {
+        DicomImageFile {
+            tenant_id: row.get(0),
+            study_uid: row.get(1),
+            series_uid: row.get(2),
+            sop_uid: row.get(3),
+            file_path: row.get(4),
+            study_date_origin: row.get(5),
+            storage_layout: row.get(6),
+            image_status: row.get(7),
+            created_time: row.get(8),
+        }
+    }
+
+    fn deletion_from_row(row: &SqliteRow) -> DicomDeletion {
+        DicomDeletion {
+            deletion_id: row.get(0),
+            tenant_id: row.get(1),
+            level: row.get(2),
+            study_uid: row.get(3),
+            series_uid: row.get(4),
+            sop_uid: row.get(5),
+            instance_count: row.get(6),
+            total_bytes: row.get(7),
+            status: row.get(8),
+            requested_by: row.get(9),
+            purge_after: row.get(10),
+            created_time: row.get(11),
+            updated_time: row.get(12),
+        }
+    }
+
+    fn rejection_from_row(row: &SqliteRow) -> DicomRejection {
+        DicomRejection {
+            tenant_id: row.get(0),
+            study_uid: row.get(1),
+            series_uid: row.get(2),
+            sop_uid: row.get(3),
+            reason_code: row.get(4),
+            reason_meaning: row.get(5),
+            kos_sop_uid: row.get(6),
+            rejected_time: row.get(7),
+            purged_time: row.get(8),
+        }
+    }
+
+    fn change_audit_from_row(row: &SqliteRow) -> DicomChangeAudit {
+        DicomChangeAudit {
+            audit_id: row.get(0),
+            tenant_id: row.get(1),
+            operation: row.get(2),
+            patient_id: row.get(3),
+            study_uid: row.get(4),
+            before_value: row.get(5),
+            after_value: row.get(6),
+            file_count: row.get(7),
+            requested_by: row.get(8),
+            created_time: row.get(9),
+        }
+    }
+
+    fn retention_study_from_row(row: &SqliteRow) -> DicomRetentionStudy {
+        DicomRetentionStudy {
+            tenant_id: row.get(0),
+            patient_id: row.get(1),
+            study_uid: row.get(2),
+            study_date: row.get(3),
+            patient_birth_date: row.get(4),
+            modalities: split_list(row.get(5)),
+            sop_class_uids: split_list(row.get(6)),
+        }
+    }
+
+    fn retention_candidate_from_row(row: &SqliteRow) -> DicomRetentionCandidate {
+        DicomRetentionCandidate {
+            tenant_id: row.get(0),
+            study_uid: row.get(1),
+            patient_id: row.get(2),
+            policy_name: row.get(3),
+            expire_date: row.get(4),
+            status: row.get(5),
+            deletion_id: row.get(6),
+            created_time: row.get(7),
+            updated_time: row.get(8),
+        }
+    }
+
+    fn legal_hold_from_row(row: &SqliteRow) -> DicomLegalHold {
+        DicomLegalHold {
+            hold_id: row.get(0),
+            tenant_id: row.get(1),
+            patient_id: row.get(2),
+            study_uid: row.get(3),
+            reason: row.get(4),
+            created_by: row.get(5),
+            created_time: row.get(6),
+            released_by: row.get(7),
+            released_time: row.get(8),
+        }
+    }
+
+    fn study_meta_from_row(row: &SqliteRow) -> DicomStudyMeta {
+        DicomStudyMeta {
+            tenant_id: row.get(0),
+            study_uid: row.get(1),
+            patient_id: row.get(2),
+            patient_name: row.get(3),
+            patient_sex: row.get(4),
+            patient_birth_date: row.get(5),
+            study_date: row.get(6),
+            study_time: row.get(7),
+            accession_number: row.get(8),
+            study_id: row.get(9),
+            study_description: row.get(10),
+            modalities_in_study: split_list(row.get(11)),
+            number_of_series: row.get(12),
+            number_of_instances: row.get(13),
+            updated_time: row.get(14),
+        }
+    }
+
+    fn patient_meta_from_row(row: &SqliteRow) -> DicomPatientMeta {
+        DicomPatientMeta {
+            tenant_id: row.get(0),
+            patient_id: row.get(1),
+            patient_name: row.get(2),
+            patient_sex: row.get(3),
+            patient_birth_date: row.get(4),
+            number_of_studies: row.get(5),
+            number_of_series: row.get(6),
+            number_of_instances: row.get(7),
+        }
+    }
+
+    fn tenant_usage_from_row(row: &SqliteRow) -> DicomTenantUsage {
+        DicomTenantUsage {
+            tenant_id: row.get(0),
+            usage_date: row.get(1),
+            total_bytes: row.get(2),
+            instance_count: row.get(3),
+            study_count: row.get(4),
+            updated_time: row.get(5),
+        }
+    }
+
+    /// 在同一事务中累加租户用量, 并更新当天的用量快照
+    async fn apply_usage_delta(
+        transaction: &mut Transaction<'_, Sqlite>,
+        tenant_id: &str,
+        delta: &UsageDelta,
+    ) -> Result<(), DbError> {
+        let now = current_time();
+        let row = sqlx::query(
+            "INSERT INTO dicom_tenant_usage (
+                tenant_id, total_bytes, instance_count, study_count, updated_time
+            ) VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT (tenant_id) DO UPDATE SET
+                total_bytes = total_bytes + excluded.total_bytes,
+                instance_count = instance_count + excluded.instance_count,
+                study_count = study_count + excluded.study_count,
+                updated_time = excluded.updated_time
+            RETURNING total_bytes, instance_count, study_count",
+        )
+        .bind(tenant_id)
+        .bind(delta.bytes)
+        .bind(delta.instances)
+        .bind(delta.studies)
+        .bind(now)
+        .fetch_one(&mut **transaction)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        let (total_bytes, instance_count, study_count): (i64, i64, i64) =
+            (row.get(0), row.get(1), row.get(2));
+        sqlx::query(
+            "INSERT INTO dicom_tenant_usage_daily (
+                tenant_id, usage_date, total_bytes, instance_count, study_count, updated_time
+            ) VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT (tenant_id, usage_date) DO UPDATE SET
+                total_bytes = excluded.total_bytes,
+                instance_count = excluded.instance_count,
+                study_count = excluded.study_count,
+                updated_time = excluded.updated_time",
+        )
+        .bind(tenant_id)
+        .bind(now.date())
+        .bind(total_bytes)
+        .bind(instance_count)
+        .bind(study_count)
+        .bind(now)
+        .execute(&mut **transaction)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// 在同一事务中重新计算检查级汇总, 检查已删除时删除汇总
+    async fn refresh_study_meta(
+        transaction: &mut Transaction<'_, Sqlite>,
+        tenant_id: &str,
+        study_uid: &str,
+    ) -> Result<(), DbError> {
+        sqlx::query(
+            "DELETE FROM dicom_study_meta
+            WHERE tenant_id = ? AND study_uid = ?
+            AND NOT EXISTS (SELECT 1 FROM dicom_state_meta
+                WHERE tenant_id = ? AND study_uid = ?)",
+        )
+        .bind(tenant_id)
+        .bind(study_uid)
+        .bind(tenant_id)
+        .bind(study_uid)
+        .execute(&mut **transaction)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        sqlx::query(STUDY_META_REFRESH)
+            .bind(DicomRejection::IMAGE_STATUS)
+            .bind(current_time())
+            .bind(tenant_id)
+            .bind(study_uid)
+            .execute(&mut **transaction)
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// 保存序列后只重新计算检查属性、检查设备类型和序列数, 汇总不存在时完整计算
+    async fn refresh_study_series(
+        transaction: &mut Transaction<'_, Sqlite>,
+        tenant_id: &str,
+        study_uid: &str,
+    ) -> Result<(), DbError> {
+        let updated = sqlx::query(STUDY_SERIES_REFRESH)
+            .bind(current_time())
+            .bind(tenant_id)
+            .bind(study_uid)
+            .bind(tenant_id)
+            .bind(study_uid)
+            .execute(&mut **transaction)
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))?
+            .rows_affected();
+        if updated == 0 {
+            Self::refresh_study_meta(transaction, tenant_id, study_uid).await?;
+        }
+        Ok(())
+    }
+
+    /// 按增量更新检查实例数, 汇总不存在时完整计算
+    async fn add_study_instances(
+        transaction: &mut Transaction<'_, Sqlite>,
+        tenant_id: &str,
+        study_uid: &str,
+        delta: i32,
+    ) -> Result<(), DbError> {
+        let updated = sqlx::query(
+            "UPDATE dicom_study_meta
+            SET number_of_instances = number_of_instances + ?, updated_time = ?
+            WHERE tenant_id = ? AND study_uid = ?",
+        )
+        .bind(delta)
+        .bind(current_time())
+        .bind(tenant_id)
+        .bind(study_uid)
+        .execute(&mut **transaction)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?
+        .rows_affected();
+        if updated == 0 {
+            Self::refresh_study_meta(transaction, tenant_id, study_uid).await?;
+        }
+        Ok(())
+    }
+
+    /// 检查下有拒绝记录的实例 (series_uid, sop_uid)
+    async fn rejected_instances(
+        transaction: &mut Transaction<'_, Sqlite>,
+        tenant_id: &str,
+        study_uid: &str,
+    ) -> Result<HashSet<(String, String)>, DbError> {
+        let rows = sqlx::query(
+            "SELECT series_uid, sop_uid FROM dicom_rejection WHERE tenant_id = ? AND study_uid = ?",
+        )
+        .bind(tenant_id)
+        .bind(study_uid)
+        .fetch_all(&mut **transaction)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+    }
+
+    /// 将检查下有拒绝记录的实例标记为 REJECTED, 已标记的实例除外, 返回标记的实例数
+    async fn mark_rejected_images(
+        transaction: &mut Transaction<'_, Sqlite>,
+        tenant_id: &str,
+        study_uid: &str,
+        updated_time: Option<chrono::NaiveDateTime>,
+    ) -> Result<u64, DbError> {
+        let result = sqlx::query(
+            "UPDATE dicom_image_meta AS i
+            SET image_status = ?, updated_time = COALESCE(?, i.updated_time)
+            WHERE i.tenant_id = ? AND i.study_uid = ? AND i.image_status IS NOT ?
+            AND EXISTS (SELECT 1 FROM dicom_rejection r
+                WHERE r.tenant_id = i.tenant_id AND r.study_uid = i.study_uid
+                AND r.series_uid = i.series_uid AND r.sop_uid = i.sop_uid)",
+        )
+        .bind(DicomRejection::IMAGE_STATUS)
+        .bind(updated_time)
+        .bind(tenant_id)
+        .bind(study_uid)
+        .bind(DicomRejection::IMAGE_STATUS)
+        .execute(&mut **transaction)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(result.rows_affected())
+    }
+}
+
+fn bind_deletion_scope<'q>(
+    query: Query<'q, Sqlite, SqliteArguments<'q>>,
+    tenant_id: &'q str,
+    study_uid: &'q str,
+    series_uid: Option<&'q str>,
+    sop_uid: Option<&'q str>,
+) -> Query<'q, Sqlite, SqliteArguments<'q>> {
+    query
+        .bind(tenant_id)
+        .bind(study_uid)
+        .bind(series_uid)
+        .bind(series_uid)
+        .bind(sop_uid)
+        .bind(sop_uid)
+}
+
+/// 新增或更新序列, 与 PgDbProvider 相同, created_time / storage_layout 保持首次写入的值
+/// 重新计算一个检查的汇总, 参数依次为 REJECTED / 更新时间 / tenant_id / study_uid
+const STUDY_META_REFRESH: &str = "INSERT INTO dicom_study_meta (
+    tenant_id, study_uid, patient_id, patient_name, patient_sex, patient_birth_date, study_date, study_time,
+    accession_number, study_id, study_description, modalities_in_study, number_of_series, number_of_instances,
+    updated_time
+)
+SELECT
+    s.tenant_id,
+    s.study_uid,
+    MAX(s.patient_id),
+    MAX(s.patient_name),
+    MAX(s.patient_sex),
+    MAX(s.patient_birth_date),
+    MAX(s.study_date),
+    MAX(s.study_time),
+    MAX(s.accession_number),
+    MAX(s.study_id),
+    MAX(s.study_description),
+    COALESCE(group_concat(DISTINCT s.modality), ''),
+    COUNT(*),
+    (SELECT COUNT(*) FROM dicom_image_meta i
+        WHERE i.tenant_id = s.tenant_id AND i.study_uid = s.study_uid
+        AND i.image_status IS NOT ?),
+    ?
+FROM dicom_state_meta s
+WHERE s.tenant_id = ? AND s.study_uid = ?
+GROUP BY s.tenant_id, s.study_uid
+ON CONFLICT (tenant_id, study_uid) DO UPDATE SET
+    patient_id = excluded.patient_id,
+    patient_name = excluded.patient_name,
+    patient_sex = excluded.patient_sex,
+    patient_birth_date = excluded.patient_birth_date,
+    study_date = excluded.study_date,
+    study_time = excluded.study_time,
+    accession_number = excluded.accession_number,
+    study_id = excluded.study_id,
+    study_description = excluded.study_description,
+    modalities_in_study = excluded.modalities_in_study,
+    number_of_series = excluded.number_of_series,
+    number_of_instances = excluded.number_of_instances,
+    updated_time = excluded.updated_time";
+
+/// 重新计算检查属性、检查设备类型和序列数, 实例数保持不变. 检查没有序列时不更新
+const STUDY_SERIES_REFRESH: &str = "UPDATE dicom_study_meta SET
+    (patient_id, patient_name, patient_sex, patient_birth_date, study_date, study_time, accession_number,
+     study_id, study_description, modalities_in_study, number_of_series) = (
+        SELECT
+            MAX(s.patient_id),
+            MAX(s.patient_name),
+            MAX(s.patient_sex),
+            MAX(s.patient_birth_date),
+            MAX(s.study_date),
+            MAX(s.study_time),
+            MAX(s.accession_number),
+            MAX(s.study_id),
+            MAX(s.study_description),
+            COALESCE(group_concat(DISTINCT s.modality), ''),
+            COUNT(*)
+        FROM dicom_state_meta s
+        WHERE s.tenant_id = dicom_study_meta.tenant_id AND s.study_uid = dicom_study_meta.study_uid
+    ),
+    updated_time = ?
+WHERE tenant_id = ? AND study_uid = ?
+AND EXISTS (SELECT 1 FROM dicom_state_meta s WHERE s.tenant_id = ? AND s.study_uid = ?)";
+
+const STATE_META_UPSERT: &str = "ON CONFLICT (tenant_id, study_uid, series_uid) DO UPDATE SET
+    patient_id = excluded.patient_id,
+    study_uid_hash = excluded.study_uid_hash,
+    series_uid_hash = excluded.series_uid_hash,
+    study_date_origin = excluded.study_date_origin,
+    patient_name = excluded.patient_name,
+    patient_sex = excluded.patient_sex,
+    patient_birth_date = excluded.patient_birth_date,
+    patient_birth_time = excluded.patient_birth_time,
+    patient_age = excluded.patient_age,
+    patient_size = excluded.patient_size,
+    patient_weight = excluded.patient_weight,
+    study_date = excluded.study_date,
+    study_time = excluded.study_time,
+    accession_number = excluded.accession_number,
+    study_id = excluded.study_id,
+    study_description = excluded.study_description,
+    modality = excluded.modality,
+    series_number = excluded.series_number,
+    series_date = excluded.series_date,
+    series_time = excluded.series_time,
+    series_description = excluded.series_description,
+    body_part_examined = excluded.body_part_examined,
+    protocol_name = excluded.protocol_name,
+    series_related_instances = excluded.series_related_instances,
+    updated_time = excluded.updated_time";
+
+fn bind_state_meta<'q>(
+    query: Query<'q, Sqlite, SqliteArguments<'q>>,
+    state_meta: &'q DicomStateMeta,
+) -> Query<'q, Sqlite, SqliteArguments<'q>> {
+    query
+        .bind(&state_meta.tenant_id)
+        .bind(&state_meta.patient_id)
+        .bind(&state_meta.study_uid)
+        .bind(&state_meta.series_uid)
+        .bind(&state_meta.study_uid_hash)
+        .bind(&state_meta.series_uid_hash)
+        .bind(&state_meta.study_date_origin)
+        .bind(&state_meta.patient_name)
+        .bind(&state_meta.patient_sex)
+        .bind(state_meta.patient_birth_date)
+        .bind(state_meta.patient_birth_time)
+        .bind(&state_meta.patient_age)
+        .bind(state_meta.patient_size)
+        .bind(state_meta.patient_weight)
+        .bind(state_meta.study_date)
+        .bind(state_meta.study_time)
+        .bind(&state_meta.accession_number)
+        .bind(&state_meta.study_id)
+        .bind(&state_meta.study_description)
+        .bind(&state_meta.modality)
+        .bind(state_meta.series_number)
+        .bind(state_meta.series_date)
+        .bind(state_meta.series_time)
+        .bind(&state_meta.series_description)
+        .bind(&state_meta.body_part_examined)
+        .bind(&state_meta.protocol_name)
+        .bind(state_meta.series_related_instances)
+        .bind(state_meta.created_time)
+        .bind(state_meta.updated_time)
+        .bind(state_meta.storage_layout)
+}
+
+#[async_trait]
+impl DbProvider for SqliteDbProvider {
+    async fn save_store_list(&self, store_meta_list: &[DicomStoreMeta]) -> Result<(), DbError> {
+        if store_meta_list.is_empty() {
+            return Ok(());
+        }
+        let mut transaction = self.begin().await?;
+        let sql = format!(
+            "INSERT INTO dicom_object_meta ({}) VALUES ({})
+            ON CONFLICT (trace_id) DO UPDATE SET
+                worker_node_id = excluded.worker_node_id,
+                tenant_id = excluded.tenant_id,
+                patient_id = excluded.patient_id,
+                study_uid = excluded.study_uid,
+                series_uid = excluded.series_uid,
+                sop_uid = excluded.sop_uid,
+                file_size = excluded.file_size,
+                file_path = excluded.file_path,
+                transfer_syntax_uid = excluded.transfer_syntax_uid,
+                number_of_frames = excluded.number_of_frames,
+                series_uid_hash = excluded.series_uid_hash,
+                study_uid_hash = excluded.study_uid_hash,
+                accession_number = excluded.accession_number,
+                target_ts = excluded.target_ts,
+                study_date = excluded.study_date,
+                transfer_status = excluded.transfer_status,
+                source_ip = excluded.source_ip,
+                source_ae = excluded.source_ae,
+                created_time = excluded.created_time,
+                checksum = excluded.checksum",
+            STORE_META_COLUMNS,
+            placeholders(21)
+        );
+        for store_meta in store_meta_list {
+            sqlx::query(&sql)
+                .bind(&store_meta.trace_id)
+                .bind(&store_meta.worker_node_id)
+                .bind(&store_meta.tenant_id)
+                .bind(&store_meta.patient_id)
+                .bind(&store_meta.study_uid)
+                .bind(&store_meta.series_uid)
+                .bind(&store_meta.sop_uid)
+                .bind(store_meta.file_size)
+                .bind(&store_meta.file_path)
+                .bind(&store_meta.transfer_syntax_uid)
+                .bind(store_meta.number_of_frames)
+                .bind(&store_meta.series_uid_hash)
+                .bind(&store_meta.study_uid_hash)
+                .bind(&store_meta.accession_number)
+                .bind(&store_meta.target_ts)
+                .bind(store_meta.study_date)
+                .bind(store_meta.transfer_status.to_string())
+                .bind(&store_meta.source_ip)
+                .bind(&store_meta.source_ae)
+                .bind(store_meta.created_time)
+                .bind(&store_meta.checksum)
+                .execute(&mut *transaction)
+                .await
+                .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        }
+        transaction
+            .commit()
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))
+    }
+
+    async fn save_state_info(&self, state_meta: &DicomStateMeta) -> Result<(), DbError> {
+        let sql = format!(
+            "INSERT INTO dicom_state_meta ({}) VALUES ({}) {}",
+            STATE_META_COLUMNS,
+            placeholders(30),
+            STATE_META_UPSERT
+        );
+        let mut transaction = self.begin().await?;
+        bind_state_meta(sqlx::query(&sql), state_meta)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Self::refresh_study_series(
+            &mut transaction,
+            state_meta.tenant_id.as_str(),
+            state_meta.study_uid.as_str(),
+        )
+        .await?;
+        transaction
+            .commit()
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))
+    }
+
+    async fn save_state_list(&self, state_meta_list: &[DicomStateMeta]) -> Result<(), DbError> {
+        // 使用事务确保所有数据要么全部保存成功，要么全部失败
+        let mut transaction = self.begin().await?;
+        let sql = format!(
+            "INSERT INTO dicom_state_meta ({}) VALUES ({}) {}",
+            STATE_META_COLUMNS,
+            placeholders(30),
+            STATE_META_UPSERT
+        );
+        for state_meta in state_meta_list {
+            bind_state_meta(sqlx::query(&sql), state_meta)
+                .execute(&mut *transaction)
+                .await
+                .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        }
+        let mut studies: Vec<(&str, &str)> = state_meta_list
+            .iter()
+            .map(|state| (state.tenant_id.as_str(), state.study_uid.as_str()))
+            .collect();
+        studies.sort_unstable();
+        studies.dedup();
+        for (tenant_id, study_uid) in studies {
+            Self::refresh_study_series(&mut transaction, tenant_id, study_uid).await?;
+        }
+        transaction
+            .commit()
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))
+    }
+
+    async fn save_image_list(&self, image_meta_list: &[DicomImageMeta]) -> Result<(), DbError> {
+        if image_meta_list.is_empty() {
+            return Ok(());
+        }
+        let mut transaction = self.begin().await?;
+        let sql = format!(
+            "INSERT INTO dicom_image_meta ({}) VALUES ({})
+            ON CONFLICT (tenant_id, study_uid, series_uid, sop_uid) DO UPDATE SET
+                patient_id = excluded.patient_id,
+                study_uid_hash = excluded.study_uid_hash,
+                series_uid_hash = excluded.series_uid_hash,
+                content_date = excluded.content_date,
+                content_time = excluded.content_time,
+                instance_number = excluded.instance_number,
+                image_type = excluded.image_type,
+                image_orientation_patient = excluded.image_orientation_patient,
+                image_position_patient = excluded.image_position_patient,
+                slice_thickness = excluded.slice_thickness,
+                spacing_between_slices = excluded.spacing_between_slices,
+                slice_location = excluded.slice_location,
+                samples_per_pixel = excluded.samples_per_pixel,
+                photometric_interpretation = excluded.photometric_interpretation,
+                width = excluded.width,
+                columns = excluded.columns,
+                bits_allocated = excluded.bits_allocated,
+                bits_stored = excluded.bits_stored,
+                high_bit = excluded.high_bit,
+                pixel_representation = excluded.pixel_representation,
+                rescale_intercept = excluded.rescale_intercept,
+                rescale_slope = excluded.rescale_slope,
+                rescale_type = excluded.rescale_type,
+                window_center = excluded.window_center,
+                window_width = excluded.window_width,
+                transfer_syntax_uid = excluded.transfer_syntax_uid,
+                pixel_data_location = excluded.pixel_data_location,
+                thumbnail_location = excluded.thumbnail_location,
+                sop_class_uid = excluded.sop_class_uid,
+                image_status = excluded.image_status,
+                space_size = excluded.space_size,
+                updated_time = excluded.updated_time,
+                checksum = excluded.checksum",
+            IMAGE_META_COLUMNS,
+            placeholders(38)
+        );
+        // 用量统计: 重复接收的实例只计算大小变化
+        let mut usage: HashMap<String, UsageDelta> = HashMap::new();
+        let mut instances = StudyInstanceDelta::default();
+        for image_meta in image_meta_list {
+            let old: Option<(i64, Option<String>)> = sqlx::query(
+                "SELECT COALESCE(space_size, 0), image_status FROM dicom_image_meta
+                WHERE tenant_id = ? AND study_uid = ? AND series_uid = ? AND sop_uid = ?",
+            )
+            .bind(&image_meta.tenant_id)
+            .bind(&image_meta.study_uid)
+            .bind(&image_meta.series_uid)
+            .bind(&image_meta.sop_uid)
+            .fetch_optional(&mut *transaction)
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))?
+            .map(|row| (row.get(0), row.get(1)));
+            let old_size = old.as_ref().map(|(size, _)| *size);
+            instances.add_instance(
+                image_meta,
+                old.is_some_and(|(_, status)| {
+                    status.as_deref() != Some(DicomRejection::IMAGE_STATUS)
+                }),
+            );
+            let study_exists = match old_size {
+                Some(_) => true,
+                None => sqlx::query(
+                    "SELECT EXISTS (SELECT 1 FROM dicom_image_meta WHERE tenant_id = ? AND study_uid = ?)",
+                )
+                .bind(&image_meta.tenant_id)
+                .bind(&image_meta.study_uid)
+                .fetch_one(&mut *transaction)
+                .await
+                .map_err(|e| DbError::DatabaseError(e.to_string()))?
+                .get(0),
+            };
+            usage
+                .entry(image_meta.tenant_id.as_str().to_string())
+                .or_default()
+                .add_instance(image_meta.space_size.unwrap_or(0), old_size, study_exists);
+
+            sqlx::query(&sql)
+                .bind(&image_meta.tenant_id)
+                .bind(&image_meta.patient_id)
+                .bind(&image_meta.study_uid)
+                .bind(&image_meta.series_uid)
+                .bind(&image_meta.sop_uid)
+                .bind(&image_meta.study_uid_hash)
+                .bind(&image_meta.series_uid_hash)
+                .bind(image_meta.content_date)
+                .bind(image_meta.content_time)
+                .bind(image_meta.instance_number)
+                .bind(&image_meta.image_type)
+                .bind(&image_meta.image_orientation_patient)
+                .bind(&image_meta.image_position_patient)
+                .bind(image_meta.slice_thickness)
+                .bind(image_meta.spacing_between_slices)
+                .bind(image_meta.slice_location)
+                .bind(image_meta.samples_per_pixel)
+                .bind(&image_meta.photometric_interpretation)
+                .bind(image_meta.width)
+                .bind(image_meta.columns)
+                .bind(image_meta.bits_allocated)
+                .bind(image_meta.bits_stored)
+                .bind(image_meta.high_bit)
+                .bind(image_meta.pixel_representation)
+                .bind(image_meta.rescale_intercept)
+                .bind(image_meta.rescale_slope)
+                .bind(&image_meta.rescale_type)
+                .bind(&image_meta.window_center)
+                .bind(&image_meta.window_width)
+                .bind(&image_meta.transfer_syntax_uid)
+                .bind(&image_meta.pixel_data_location)
+                .bind(&image_meta.thumbnail_location)
+                .bind(&image_meta.sop_class_uid)
+                .bind(&image_meta.image_status)
+                .bind(image_meta.space_size)
+                .bind(image_meta.created_time)
+                .bind(image_meta.updated_time)
+                .bind(&image_meta.checksum)
+                .execute(&mut *transaction)
+                .await
+                .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        }
+        for (tenant_id, delta) in &usage {
+            Self::apply_usage_delta(&mut transaction, tenant_id, delta).await?;
+        }
+        // 已被拒绝说明撤回的实例重新收到时仍保持 REJECTED
+        for (tenant_id, study_uid) in instances.studies() {
+            Self::mark_rejected_images(&mut transaction, tenant_id, study_uid, None).await?;
+            let rejected = Self::rejected_instances(&mut transaction, tenant_id, study_uid).await?;
+            let delta = instances.delta(tenant_id, study_uid, &rejected);
+            Self::add_study_instances(&mut transaction, tenant_id, study_uid, delta).await?;
+        }
+        transaction
+            .commit()
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))
+    }
+
+    async fn save_json_list(&self, json_meta_list: &[DicomJsonMeta]) -> Result<(), DbError> {
+        if json_meta_list.is_empty() {
+            return Ok(());
+        }
+        let mut transaction = self.begin().await?;
+        let insert_sql = format!(
+            "INSERT INTO dicom_json_meta ({}) VALUES ({})
+            ON CONFLICT (tenant_id, study_uid, series_uid) DO UPDATE SET
+                study_uid_hash = excluded.study_uid_hash,
+                series_uid_hash = excluded.series_uid_hash,
+                study_date_origin = excluded.study_date_origin,
+                flag_time = excluded.flag_time,
+                created_time = excluded.created_time,
+                json_status = excluded.json_status,
+                retry_times = excluded.retry_times",
+            JSON_META_COLUMNS,
+            placeholders(10)
+        );
+        for json_meta in json_meta_list {
+            sqlx::query(&insert_sql)
+                .bind(&json_meta.tenant_id)
+                .bind(&json_meta.study_uid)
+                .bind(&json_meta.series_uid)
+                .bind(&json_meta.study_uid_hash)
+                .bind(&json_meta.series_uid_hash)
+                .bind(&json_meta.study_date_origin)
+                .bind(json_meta.flag_time)
+                .bind(json_meta.created_time)
+                .bind(json_meta.json_status)
+                .bind(json_meta.retry_times)
+                .execute(&mut *transaction)
+                .await
+                .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+
+            // 序列下没有实例时保持原值
+            sqlx::query(
+                "UPDATE dicom_state_meta AS dsm
+                SET series_related_instances = (SELECT COUNT(*) FROM dicom_image_meta i
+                    WHERE i.tenant_id = dsm.tenant_id AND i.study_uid = dsm.study_uid
+                    AND i.series_uid = dsm.series_uid)
+                WHERE dsm.tenant_id = ? AND dsm.study_uid = ? AND dsm.series_uid = ?
+                AND EXISTS (SELECT 1 FROM dicom_image_meta i
+                    WHERE i.tenant_id = dsm.tenant_id AND i.study_uid = dsm.study_uid
+                    AND i.series_uid = dsm.series_uid)",
+            )
+            .bind(&json_meta.tenant_id)
+            .bind(&json_meta.study_uid)
+            .bind(&json_meta.series_uid)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        }
+        transaction
+            .commit()
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))
+    }
+
+    async fn get_state_metaes(
+        &self,
+        tenant_id: &str,
+        study_uid: &str,
+    ) -> Result<Vec<DicomStateMeta>, DbError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM dicom_state_meta WHERE tenant_id = ? AND study_uid = ?",
+            STATE_META_COLUMNS
+        ))
+        .bind(tenant_id)
+        .bind(study_uid)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(rows.iter().map(Self::state_meta_from_row).collect())
+    }
+
+    async fn get_patient_state_metaes(
+        &self,
+        tenant_id: &str,
+        patient_id: &str,
+    ) -> Result<Vec<DicomStateMeta>, DbError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM dicom_state_meta WHERE tenant_id = ? AND patient_id = ?
+            ORDER BY study_uid, series_uid",
+            STATE_META_COLUMNS
+        ))
+        .bind(tenant_id)
+        .bind(patient_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(rows.iter().map(Self::state_meta_from_row).collect())
+    }
+
+    async fn get_series_layout(
+        &self,
+        tenant_id: &str,
+        study_uid: &str,
+        series_uid: &str,
+    ) -> Result<Option<i32>, DbError> {
+        let row = sqlx::query(
+            "SELECT storage_layout FROM dicom_state_meta
+            WHERE tenant_id = ? AND study_uid = ? AND series_uid = ?",
+        )
+        .bind(tenant_id)
+        .bind(study_uid)
+        .bind(series_uid)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(row.map(|row| row.get(0)))
+    }
+
+    async fn get_json_metaes(
+        &self,
+        end_time: chrono::NaiveDateTime,
+    ) -> Result<Vec<DicomStateMeta>, DbError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM (SELECT dsm.*
+                  FROM dicom_state_meta dsm
+                           LEFT JOIN dicom_json_meta djm
+                                     ON dsm.tenant_id = djm.tenant_id
+                                         AND dsm.study_uid = djm.study_uid
+                                         AND dsm.series_uid = djm.series_uid
+                  WHERE djm.tenant_id IS NULL AND dsm.updated_time < ?
+                  UNION ALL
+                  SELECT dsm.*
+                  FROM dicom_state_meta dsm
+                           INNER JOIN dicom_json_meta djm
+                                      ON dsm.tenant_id = djm.tenant_id
+                                          AND dsm.study_uid = djm.study_uid
+                                          AND dsm.series_uid = djm.series_uid
+                  WHERE dsm.updated_time != djm.flag_time AND dsm.updated_time < ?
+                  ) AS t
+            ORDER BY t.updated_time ASC LIMIT 10",
+            STATE_META_COLUMNS
+        ))
+        .bind(end_time)
+        .bind(end_time)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(rows.iter().map(Self::state_meta_from_row).collect())
+    }
+
+    async fn get_json_meta(
+        &self,
+        tenant_id: &str,
+        study_uid: &str,
+        series_uid: &str,
+    ) -> Result<DicomJsonMeta, DbError> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM dicom_json_meta
+            WHERE series_uid = ? AND tenant_id = ? AND study_uid = ?",
+            JSON_META_COLUMNS
+        ))
+        .bind(series_uid)
+        .bind(tenant_id)
+        .bind(study_uid)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+
+        let Some(row) = row else {
+            return Err(DbError::RecordNotExists(format!(
+                "DicomJsonMeta with series_uid {} not found",
+                series_uid
+            )));
+        };
+        Ok(DicomJsonMeta {
+            tenant_id: row.get(0),
+            study_uid: row.get(1),
+            series_uid: row.get(2),
+            study_uid_hash: row.get(3),
+            series_uid_hash: row.get(4),
+            study_date_origin: row.get(5),
+            flag_time: row.get(6),
+            created_time: row.get(7),
+            json_status: row.get(8),
+            retry_times: row.get(9),
+        })
+    }
+
+    async fn save_mpps_info(&self, mpps_meta: &DicomMppsMeta) -> Result<(), DbError> {
+        // SQLite 没有数组类型, 引用的序列按 JSON 数组保存
+        let referenced_series = serde_json::to_string(&mpps_meta.referenced_series)
+            .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        sqlx::query(&format!(
+            "INSERT INTO dicom_mpps_meta ({}) VALUES ({})
+            ON CONFLICT (tenant_id, sop_instance_uid) DO UPDATE SET
+                patient_id = COALESCE(excluded.patient_id, patient_id),
+                study_uid = COALESCE(excluded.study_uid, study_uid),
+                accession_number = COALESCE(excluded.accession_number, accession_number),
+                modality = COALESCE(excluded.modality, modality),
+                pps_id = COALESCE(excluded.pps_id, pps_id),
+                pps_status = excluded.pps_status,
+                pps_description = COALESCE(excluded.pps_description, pps_description),
+                performed_station_ae = COALESCE(excluded.performed_station_ae, performed_station_ae),
+                start_datetime = COALESCE(excluded.start_datetime, start_datetime),
+                end_datetime = COALESCE(excluded.end_datetime, end_datetime),
+                referenced_series = excluded.referenced_series,
+                updated_time = excluded.updated_time",
+            MPPS_COLUMNS,
+            placeholders(17)
+        ))
+        .bind(&mpps_meta.tenant_id)
+        .bind(&mpps_meta.sop_instance_uid)
+        .bind(&mpps_meta.patient_id)
+        .bind(&mpps_meta.study_uid)
+        .bind(&mpps_meta.accession_number)
+        .bind(&mpps_meta.modality)
+        .bind(&mpps_meta.pps_id)
+        .bind(&mpps_meta.pps_status)
+        .bind(&mpps_meta.pps_description)
+        .bind(&mpps_meta.performed_station_ae)
+        .bind(mpps_meta.start_datetime)
+        .bind(mpps_meta.end_datetime)
+        .bind(referenced_series)
+        .bind(&mpps_meta.source_ip)
+        .bind(&mpps_meta.source_ae)
+        .bind(mpps_meta.created_time)
+        .bind(mpps_meta.updated_time)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn get_mpps_info(
+        &self,
+        tenant_id: &str,
+        sop_instance_uid: &str,
+    ) -> Result<DicomMppsMeta, DbError> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM dicom_mpps_meta WHERE tenant_id = ? AND sop_instance_uid = ?",
+            MPPS_COLUMNS
+        ))
+        .bind(tenant_id)
+        .bind(sop_instance_uid)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+
+        let Some(row) = row else {
+            return Err(DbError::RecordNotExists(format!(
+                "DicomMppsMeta with sop_instance_uid {} not found",
+                sop_instance_uid
+            )));
+        };
+        let referenced_series: String = row.get(12);
+        Ok(DicomMppsMeta {
+            tenant_id: row.get(0),
+            sop_instance_uid: row.get(1),
+            patient_id: row.get(2),
+            study_uid: row.get(3),
+            accession_number: row.get(4),
+            modality: row.get(5),
+            pps_id: row.get(6),
+            pps_status: row.get(7),
+            pps_description: row.get(8),
+            performed_station_ae: row.get(9),
+            start_datetime: row.get(10),
+            end_datetime: row.get(11),
+            referenced_series: serde_json::from_str(&referenced_series)
+                .map_err(|e| DbError::ExtractionFailed(e.to_string()))?,
+            source_ip: row.get(13),
+            source_ae: row.get(14),
+            created_time: row.get(15),
+            updated_time: row.get(16),
+        })
+    }
+
+    async fn save_forward_tasks(&self, tasks: &[DicomForwardTask]) -> Result<(), DbError> {
+        if tasks.is_empty() {
+            return Ok(());
+        }
+        let mut transaction = self.begin().await?;
+        let sql = format!(
+            "INSERT INTO dicom_forward_task ({}) VALUES ({})
+            ON CONFLICT (task_id) DO NOTHING",
+            FORWARD_TASK_COLUMNS,
+            placeholders(14)
+        );
+        for task in tasks {
+            sqlx::query(&sql)
+                .bind(&task.task_id)
+                .bind(&task.tenant_id)
+                .bind(&task.rule_name)
+                .bind(&task.destination)
+                .bind(&task.study_uid)
+                .bind(&task.series_uid)
+                .bind(&task.sop_uid)
+                .bind(&task.file_path)
+                .bind(&task.status)
+                .bind(task.retry_times)
+                .bind(&task.last_error)
+                .bind(task.next_retry_time)
+                .bind(task.created_time)
+                .bind(task.updated_time)
+                .execute(&mut *transaction)
+                .await
+                .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        }
+        transaction
+            .commit()
+            .await
+            .map_err(|e| DbError::TransactionFailed(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn claim_forward_tasks(
+        &self,
+        now: chrono::NaiveDateTime,
+        stale_before: chrono::NaiveDateTime,
+        limit: i64,
+    ) -> Result<Vec<DicomForwardTask>, DbError> {
+        // SQLite 同一时间只有一个写操作, 单条 UPDATE 领取任务不会与其他转发进程重复
+        let rows = sqlx::query(&format!(
+            "UPDATE dicom_forward_task SET status = ?, updated_time = ?
+            WHERE task_id IN (SELECT task_id FROM dicom_forward_task
+                WHERE (status = ? AND next_retry_time <= ?)
+                   OR (status = ? AND updated_time < ?)
+                ORDER BY next_retry_time
+                LIMIT ?)
+            RETURNING {}",
+            FORWARD_TASK_COLUMNS
+        ))
+        .bind(DicomForwardTask::STATUS_RUNNING)
+        .bind(now)
+        .bind(DicomForwardTask::STATUS_PENDING)
+        .bind(now)
+        .bind(DicomForwardTask::STATUS_RUNNING)
+        .bind(stale_before)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+
+        // RETURNING 不保证顺序
+        let mut tasks: Vec<DicomForwardTask> =
+            rows.iter().map(Self::forward_task_from_row).collect();
+        tasks.sort_by_key(|task| task.next_retry_time);
+        Ok(tasks)
+    }
+
+    async fn update_forward_task(&self, task: &DicomForwardTask) -> Result<(), DbError> {
+        sqlx::query(
+            "UPDATE dicom_forward_task
+            SET status = ?, retry_times = ?, last_error = ?, next_retry_time = ?, updated_time = ?
+            WHERE task_id = ?",
+        )
+        .bind(&task.status)
+        .bind(task.retry_times)
+        .bind(&task.last_error)
+        .bind(task.next_retry_time)
+        .bind(task.updated_time)
+        .bind(&task.task_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn get_forward_tasks(
+        &self,
+        status: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<DicomForwardTask>, DbError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM dicom_forward_task
+            WHERE (? IS NULL OR status = ?)
+            ORDER BY created_time DESC
+            LIMIT ?",
+            FORWARD_TASK_COLUMNS
+        ))
+        .bind(status)
+        .bind(status)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(rows.iter().map(Self::forward_task_from_row).collect())
+    }
+
+    async fn requeue_forward_task(&self, task_id: &str) -> Result<(), DbError> {
+        let now = current_time();
+        let updated = sqlx::query(
+            "UPDATE dicom_forward_task
+            SET status = ?, retry_times = 0, last_error = NULL, next_retry_time = ?, updated_time = ?
+            WHERE task_id = ?",
+        )
+        .bind(DicomForwardTask::STATUS_PENDING)
+        .bind(now)
+        .bind(now)
+        .bind(task_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?
+        .rows_affected();
+
+        if updated == 0 {
+            return Err(DbError::RecordNotExists(format!(
+                "DicomForwardTask with task_id {} not found",
+                task_id
+            )));
+        }
+        Ok(())
+    }
+
+    async fn save_prefetch_task(&self, task: &DicomPrefetchTask) -> Result<(), DbError> {
+        sqlx::query(&format!(
+            "INSERT INTO dicom_prefetch_task ({}) VALUES ({})
+            ON CONFLICT (tenant_id, source, study_uid) DO UPDATE SET
+                trigger_study_uid = excluded.trigger_study_uid,
+                status = excluded.status,
+                completed = excluded.completed,
+                failed = excluded.failed,
+                last_error = excluded.last_error,
+                updated_time = excluded.updated_time",
+            PREFETCH_TASK_COLUMNS,
+            placeholders(13)
+        ))
+        .bind(&task.tenant_id)
+        .bind(&task.source)
+        .bind(&task.study_uid)
+        .bind(&task.patient_id)
+        .bind(&task.trigger_study_uid)
+        .bind(task.study_date)
+        .bind(&task.modalities)
+        .bind(&task.status)
+        .bind(task.completed)
+        .bind(task.failed)
+        .bind(&task.last_error)
+        .bind(task.created_time)
+        .bind(task.updated_time)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn get_prefetch_task(
+        &self,
+        tenant_id: &str,
+        source: &str,
+        study_uid: &str,
+    ) -> Result<DicomPrefetchTask, DbError> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM dicom_prefetch_task
+            WHERE tenant_id = ? AND source = ? AND study_uid = ?",
+            PREFETCH_TASK_COLUMNS
+        ))
+        .bind(tenant_id)
+        .bind(source)
+        .bind(study_uid)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+
+        let Some(row) = row else {
+            return Err(DbError::RecordNotExists(format!(
+                "DicomPrefetchTask with study_uid {} not found",
+                study_uid
+            )));
+        };
+        Ok(DicomPrefetchTask {
+            tenant_id: row.get(0),
+            source: row.get(1),
+            study_uid: row.get(2),
+            patient_id: row.get(3),
+            trigger_study_uid: row.get(4),
+            study_date: row.get(5),
+            modalities: row.get(6),
+            status: row.get(7),
+            completed: row.get(8),
+            failed: row.get(9),
+            last_error: row.get(10),
+            created_time: row.get(11),
+            updated_time: row.get(12),
+        })
+    }
+
+    async fn claim_prefetch_task(
+        &self,
+        task: &DicomPrefetchTask,
+        stale_before: chrono::NaiveDateTime,
+    ) -> Result<bool, DbError> {
+        // 冲突时由 WHERE 条件决定是否接管, 插入和接管都在同一条语句内完成
+        let claimed = sqlx::query(&format!(
+            "INSERT INTO dicom_prefetch_task ({}) VALUES ({})
+            ON CONFLICT (tenant_id, source, study_uid) DO UPDATE SET
+                trigger_study_uid = excluded.trigger_study_uid,
+                status = excluded.status,
+                completed = excluded.completed,
+                failed = excluded.failed,
+                last_error = excluded.last_error,
+                updated_time = excluded.updated_time
+            WHERE dicom_prefetch_task.status NOT IN (?, ?)
+               OR (dicom_prefetch_task.status = ? AND dicom_prefetch_task.updated_time < ?)",
+            PREFETCH_TASK_COLUMNS,
+            placeholders(13)
+        ))
+        .bind(&task.tenant_id)
+        .bind(&task.source)
+        .bind(&task.study_uid)
+        .bind(&task.patient_id)
+        .bind(&task.trigger_study_uid)
+        .bind(task.study_date)
+        .bind(&task.modalities)
+        .bind(&task.status)
+        .bind(task.completed)
+        .bind(task.failed)
+        .bind(&task.last_error)
+        .bind(task.created_time)
+        .bind(task.updated_time)
+        .bind(DicomPrefetchTask::STATUS_SUCCESS)
+        .bind(DicomPrefetchTask::STATUS_RUNNING)
+        .bind(DicomPrefetchTask::STATUS_RUNNING)
+        .bind(stale_before)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?
+        .rows_affected();
+        Ok(claimed > 0)
+    }
+
+    async fn touch_series_access(
+        &self,
+        tenant_id: &str,
+        study_uid: &str,
+        series_uid: &str,
+        study_date_origin: &str,
+        access_time: chrono::NaiveDateTime,
+    ) -> Result<(), DbError> {
+        sqlx::query(
+            "INSERT INTO dicom_series_tier (
+                tenant_id,
+                study_uid,
+                series_uid,
+                study_date_origin,
+                tier,
+                last_access_time,
+                updated_time
+            ) VALUES (?, ?, ?, ?, 'hot', ?, ?)
+            ON CONFLICT (tenant_id, study_uid, series_uid) DO UPDATE SET last_access_time = excluded.last_access_time",
+        )
+        .bind(tenant_id)
+        .bind(study_uid)
+        .bind(series_uid)
+        .bind(study_date_origin)
+        .bind(access_time)
+        .bind(access_time)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn save_series_tier(&self, series_tier: &DicomSeriesTier) -> Result<(), DbError> {
+        sqlx::query(
+            "INSERT INTO dicom_series_tier (
+                tenant_id,
+                study_uid,
+                series_uid,
+                study_date_origin,
+                tier,
+                last_access_time,
+                updated_time
+            ) VALUES (?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT (tenant_id, study_uid, series_uid) DO UPDATE SET
+                tier = excluded.tier,
+                updated_time = excluded.updated_time",
+        )
+        .bind(&series_tier.tenant_id)
+        .bind(&series_tier.study_uid)
+        .bind(&series_tier.series_uid)
+        .bind(&series_tier.study_date_origin)
+        .bind(&series_tier.tier)
+        .bind(series_tier.last_access_time)
+        .bind(series_tier.updated_time)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn get_series_tier_candidates(
+        &self,
+        tier: &str,
+        tenant_ids: &[String],
+        accessed_before: chrono::NaiveDateTime,
+        limit: i64,
+    ) -> Result<Vec<DicomSeriesTier>, DbError> {
+        // 没有分层记录的序列位于热存储层, 以收图时间作为最近访问时间
+        let mut builder = QueryBuilder::<Sqlite>::new(
+            "SELECT
+                s.tenant_id,
+                s.study_uid,
+                s.series_uid,
+                s.study_date_origin,
+                COALESCE(t.tier, 'hot'),
+                COALESCE(t.last_access_time, s.updated_time),
+                COALESCE(t.updated_time, s.updated_time),
+                s.storage_layout
+            FROM dicom_state_meta s
+            LEFT JOIN dicom_series_tier t
+                ON t.tenant_id = s.tenant_id
+                AND t.study_uid = s.study_uid
+                AND t.series_uid = s.series_uid
+            WHERE COALESCE(t.tier, 'hot') = ",
+        );
+        builder
+            .push_bind(tier)
+            .push(" AND COALESCE(t.last_access_time, s.updated_time) < ")
+            .push_bind(accessed_before);
+        if !tenant_ids.is_empty() {
+            builder.push(" AND s.tenant_id IN (");
+            let mut ids = builder.separated(", ");
+            for tenant_id in tenant_ids {
+                ids.push_bind(tenant_id.as_str());
+            }
+            ids.push_unseparated(")");
+        }
+        builder
+            .push(" ORDER BY COALESCE(t.last_access_time, s.updated_time) LIMIT ")
+            .push_bind(limit);
+        let rows = builder
+            .build()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+
+        Ok(rows
+            .iter()
+            .map(|row| DicomSeriesTier {
+                tenant_id: row.get(0),
+                study_uid: row.get(1),
+                series_uid: row.get(2),
+                study_date_origin: row.get(3),
+                tier: row.get(4),
+                last_access_time: row.get(5),
+                updated_time: row.get(6),
+                storage_layout: row.get(7),
+            })
+            .collect())
+    }
+
+    async fn get_fixity_candidates(
+        &self,
+        checked_before: chrono::NaiveDateTime,
+        limit: i64,
+    ) -> Result<Vec<DicomFixityCheck>, DbError> {
+        // SQLite 升序排列时 NULL 在前, 从未校验的实例优先
+        let rows = sqlx::query(
+            "SELECT
+                m.tenant_id,
+                m.study_uid,
+                m.series_uid,
+                m.sop_uid,
+                m.pixel_data_location,
+                m.checksum,
+                f.actual_checksum,
+                COALESCE(f.status, 'PENDING'),
+                f.checked_time
+            FROM dicom_image_meta m
+            LEFT JOIN dicom_fixity_check f
+                ON f.tenant_id = m.tenant_id
+                AND f.study_uid = m.study_uid
+                AND f.series_uid = m.series_uid
+                AND f.sop_uid = m.sop_uid
+            WHERE m.checksum IS NOT NULL
+                AND m.pixel_data_location IS NOT NULL
+                AND (f.checked_time IS NULL OR f.checked_time < ?)
+            ORDER BY f.checked_time
+            LIMIT ?",
+        )
+        .bind(checked_before)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(rows.iter().map(Self::fixity_check_from_row).collect())
+    }
+
+    async fn save_fixity_checks(&self, checks: &[DicomFixityCheck]) -> Result<(), DbError> {
+        if checks.is_empty() {
+            return Ok(());
+        }
+        let mut transaction = self.begin().await?;
+        let sql = format!(
+            "INSERT INTO dicom_fixity_check ({}) VALUES ({})
+            ON CONFLICT (tenant_id, study_uid, series_uid, sop_uid) DO UPDATE SET
+                file_path = excluded.file_path,
+                expected_checksum = excluded.expected_checksum,
+                actual_checksum = excluded.actual_checksum,
+                status = excluded.status,
+                checked_time = excluded.checked_time",
+            FIXITY_CHECK_COLUMNS,
+            placeholders(9)
+        );
+        for check in checks {
+            sqlx::query(&sql)
+                .bind(&check.tenant_id)
+                .bind(&check.study_uid)
+                .bind(&check.series_uid)
+                .bind(&check.sop_uid)
+                .bind(&check.file_path)
+                .bind(&check.expected_checksum)
+                .bind(&check.actual_checksum)
+                .bind(&check.status)
+                .bind(check.checked_time)
+                .execute(&mut *transaction)
+                .await
+                .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        }
+        transaction
+            .commit()
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))
+    }
+
+    async fn get_fixity_failures(
+        &self,
+        tenant_id: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<DicomFixityCheck>, DbError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM dicom_fixity_check
+            WHERE status <> 'OK'
+                AND (? IS NULL OR tenant_id = ?)
+            ORDER BY checked_time DESC
+            LIMIT ?",
+            FIXITY_CHECK_COLUMNS
+        ))
+        .bind(tenant_id)
+        .bind(tenant_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(rows.iter().map(Self::fixity_check_from_row).collect())
+    }
+
+    async fn get_image_files(
+        &self,
+        after: Option<&DicomImageFile>,
+        limit: i64,
+    ) -> Result<Vec<DicomImageFile>, DbError> {
+        let mut builder = QueryBuilder::<Sqlite>::new(
+            "SELECT
+                m.tenant_id,
+                m.study_uid,
+                m.series_uid,
+                m.sop_uid,
+                m.pixel_data_location,
+                s.study_date_origin,
+                s.storage_layout,
+                m.image_status,
+                m.created_time
+            FROM dicom_image_meta m
+            JOIN dicom_state_meta s
+                ON s.tenant_id = m.tenant_id
+                AND s.study_uid = m.study_uid
+                AND s.series_uid = m.series_uid",
+        );
+        if let Some(f) = after {
+            builder.push(" WHERE (m.tenant_id, m.study_uid, m.series_uid, m.sop_uid) > (");
+            let mut key = builder.separated(", ");
+            key.push_bind(f.tenant_id.as_str())
+                .push_bind(f.study_uid.as_str())
+                .push_bind(f.series_uid.as_str())
+                .push_bind(f.sop_uid.as_str());
+            key.push_unseparated(")");
+        }
+        builder
+            .push(" ORDER BY m.tenant_id, m.study_uid, m.series_uid, m.sop_uid LIMIT ")
+            .push_bind(limit);
+        let rows = builder
+            .build()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(rows.iter().map(Self::image_file_from_row).collect())
+    }
+
+    async fn find_indexed_files(&self, paths: &[String]) -> Result<Vec<String>, DbError> {
+        if paths.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut builder = QueryBuilder::<Sqlite>::new(
+            "SELECT pixel_data_location FROM dicom_image_meta WHERE pixel_data_location IN (",
+        );
+        let mut values = builder.separated(", ");
+        for path in paths {
+            values.push_bind(path.as_str());
+        }
+        values.push_unseparated(")");
+        let rows = builder
+            .build()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    async fn image_exists(
+        &self,
+        tenant_id: &str,
+        study_uid: &str,
+        series_uid: &str,
+        sop_uid: &str,
+    ) -> Result<bool, DbError> {
+        let row = sqlx::query(
+            "SELECT 1 FROM dicom_image_meta
+            WHERE tenant_id = ? AND study_uid = ? AND series_uid = ? AND sop_uid = ?",
+        )
+        .bind(tenant_id)
+        .bind(study_uid)
+        .bind(series_uid)
+        .bind(sop_uid)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(row.is_some())
+    }
+
+    async fn has_removal_record(
+        &self,
+        tenant_id: &str,
+        study_uid: &str,
+        series_uid: &str,
+        sop_uid: &str,
+    ) -> Result<bool, DbError> {
+        let row = sqlx::query(
+            "SELECT 1 FROM dicom_deletion
+            WHERE tenant_id = ? AND study_uid = ? AND status <> ?
+                AND (series_uid IS NULL OR series_uid = ?)
+                AND (sop_uid IS NULL OR sop_uid = ?)
+            UNION ALL
+            SELECT 1 FROM dicom_rejection
+            WHERE tenant_id = ? AND study_uid = ? AND series_uid = ? AND sop_uid = ?
+            LIMIT 1",
+        )
+        .bind(tenant_id)
+        .bind(study_uid)
+        .bind(DicomDeletion::STATUS_RESTORED)
+        .bind(series_uid)
+        .bind(sop_uid)
+        .bind(tenant_id)
+        .bind(study_uid)
+        .bind(series_uid)
+        .bind(sop_uid)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(row.is_some())
+    }
+
+    async fn mark_images_missing(&self, images: &[DicomImageFile]) -> Result<(), DbError> {
+        if images.is_empty() {
+            return Ok(());
+        }
+        let mut transaction = self.begin().await?;
+        let now = current_time();
+        for image in images {
+            sqlx::query(
+                "UPDATE dicom_image_meta SET image_status = ?, updated_time = ?
+                WHERE tenant_id = ? AND study_uid = ? AND series_uid = ? AND sop_uid = ?",
+            )
+            .bind(DicomImageFile::STATUS_MISSING)
+            .bind(now)
+            .bind(&image.tenant_id)
+            .bind(&image.study_uid)
+            .bind(&image.series_uid)
+            .bind(&image.sop_uid)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        }
+        transaction
+            .commit()
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))
+    }
+
+    async fn delete_dicom_objects(
+        &self,
+        deletion: &DicomDeletion,
+    ) -> Result<DicomDeletion, DbError> {
+        let mut transaction = self.begin().await?;
+        let tenant_id = deletion.tenant_id.as_str();
+        let study_uid = deletion.study_uid.as_str();
+        let series_uid = deletion.series_uid.as_ref().map(|v| v.as_str());
+        let sop_uid = deletion.sop_uid.as_ref().map(|v| v.as_str());
+
+        let count_sql = format!(
+            "SELECT COUNT(*), COALESCE(SUM(space_size), 0) FROM dicom_image_meta
+            WHERE {}",
+            DELETION_SCOPE
+        );
+        let row = bind_deletion_scope(
+            sqlx::query(&count_sql),
+            tenant_id,
+            study_uid,
+            series_uid,
+            sop_uid,
+        )
+        .fetch_one(&mut *transaction)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        let mut deleted = deletion.clone();
+        deleted.instance_count = row.get(0);
+        deleted.total_bytes = row.get(1);
+
+        // 实例级记录
+        for table in [
+            "dicom_image_meta",
+            "dicom_object_meta",
+            "dicom_fixity_check",
+            "dicom_forward_task",
+        ] {
+            let delete_sql = format!("DELETE FROM {} WHERE {}", table, DELETION_SCOPE);
+            bind_deletion_scope(
+                sqlx::query(&delete_sql),
+                tenant_id,
+                study_uid,
+                series_uid,
+                sop_uid,
+            )
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        }
+
+        let now = current_time();
+        match sop_uid {
+            // 删除单个实例时保留序列, 更新 updated_time 以重新生成 JSON 元数据
+            Some(_) => {
+                sqlx::query(
+                    "UPDATE dicom_state_meta
+                    SET series_related_instances = MAX(COALESCE(series_related_instances, 0) - ?, 0),
+                        updated_time = ?
+                    WHERE tenant_id = ? AND study_uid = ? AND series_uid = ?",
+                )
+                .bind(deleted.instance_count as i32)
+                .bind(now)
+                .bind(tenant_id)
+                .bind(study_uid)
+                .bind(series_uid)
+                .execute(&mut *transaction)
+                .await
+                .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+            }
+            None => {
+                for table in ["dicom_state_meta", "dicom_json_meta", "dicom_series_tier"] {
+                    sqlx::query(&format!(
+                        "DELETE FROM {} WHERE tenant_id = ? AND study_uid = ?
+                        AND (? IS NULL OR series_uid = ?)",
+                        table
+                    ))
+                    .bind(tenant_id)
+                    .bind(study_uid)
+                    .bind(series_uid)
+                    .bind(series_uid)
+                    .execute(&mut *transaction)
+                    .await
+                    .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+                }
+            }
+        }
+        // 删除检查后允许重新预取
+        if series_uid.is_none() {
+            sqlx::query("DELETE FROM dicom_prefetch_task WHERE tenant_id = ? AND study_uid = ?")
+                .bind(tenant_id)
+                .bind(study_uid)
+                .execute(&mut *transaction)
+                .await
+                .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        }
+
+        if deleted.instance_count > 0 {
+            let study_exists: bool = sqlx::query(
+                "SELECT EXISTS (SELECT 1 FROM dicom_image_meta WHERE tenant_id = ? AND study_uid = ?)",
+            )
+            .bind(tenant_id)
+            .bind(study_uid)
+            .fetch_one(&mut *transaction)
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))?
+            .get(0);
+            let delta = UsageDelta {
+                bytes: -deleted.total_bytes,
+                instances: -deleted.instance_count,
+                studies: if study_exists { 0 } else { -1 },
+            };
+            Self::apply_usage_delta(&mut transaction, tenant_id, &delta).await?;
+        }
+        Self::refresh_study_meta(&mut transaction, tenant_id, study_uid).await?;
+
+        sqlx::query(&format!(
+            "INSERT INTO dicom_deletion ({}) VALUES ({})",
+            DELETION_COLUMNS,
+            placeholders(13)
+        ))
+        .bind(&deleted.deletion_id)
+        .bind(&deleted.tenant_id)
+        .bind(&deleted.level)
+        .bind(&deleted.study_uid)
+        .bind(&deleted.series_uid)
+        .bind(&deleted.sop_uid)
+        .bind(deleted.instance_count)
+        .bind(deleted.total_bytes)
+        .bind(&deleted.status)
+        .bind(&deleted.requested_by)
+        .bind(deleted.purge_after)
+        .bind(deleted.created_time)
+        .bind(deleted.updated_time)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        transaction
+            .commit()
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(deleted)
+    }
+
+    async fn get_deletion(&self, deletion_id: &str) -> Result<Option<DicomDeletion>, DbError> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM dicom_deletion WHERE deletion_id = ?",
+            DELETION_COLUMNS
+        ))
+        .bind(deletion_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(row.as_ref().map(Self::deletion_from_row))
+    }
+
+    async fn get_deletions(
+        &self,
+        tenant_id: Option<&str>,
+        status: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<DicomDeletion>, DbError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM dicom_deletion
+            WHERE (? IS NULL OR tenant_id = ?)
+                AND (? IS NULL OR status = ?)
+            ORDER BY created_time DESC
+            LIMIT ?",
+            DELETION_COLUMNS
+        ))
+        .bind(tenant_id)
+        .bind(tenant_id)
+        .bind(status)
+        .bind(status)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(rows.iter().map(Self::deletion_from_row).collect())
+    }
+
+    async fn get_expired_deletions(
+        &self,
+        before: chrono::NaiveDateTime,
+        limit: i64,
+    ) -> Result<Vec<DicomDeletion>, DbError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM dicom_deletion d
+            WHERE status = ? AND purge_after < ?
+            AND NOT EXISTS (SELECT 1 FROM dicom_legal_hold h
+                WHERE h.released_time IS NULL AND h.tenant_id = d.tenant_id AND h.study_uid = d.study_uid)
+            ORDER BY purge_after
+            LIMIT ?",
+            DELETION_COLUMNS
+        ))
+        .bind(DicomDeletion::STATUS_TRASHED)
+        .bind(before)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(rows.iter().map(Self::deletion_from_row).collect())
+    }
+
+    async fn update_deletion_status(&self, deletion_id: &str, status: &str) -> Result<(), DbError> {
+        sqlx::query("UPDATE dicom_deletion SET status = ?, updated_time = ? WHERE deletion_id = ?")
+            .bind(status)
+            .bind(current_time())
+            .bind(deletion_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn save_rejections(&self, rejections: &[DicomRejection]) -> Result<u64, DbError> {
+        if rejections.is_empty() {
+            return Ok(0);
+        }
+        let mut transaction = self.begin().await?;
+        // 同一实例再次被拒绝时以最新的说明为准, 已清除的时间保持不变
+        let insert_sql = format!(
+            "INSERT INTO dicom_rejection ({}) VALUES ({})
+            ON CONFLICT (tenant_id, study_uid, series_uid, sop_uid) DO UPDATE SET
+                reason_code = excluded.reason_code,
+                reason_meaning = excluded.reason_meaning,
+                kos_sop_uid = excluded.kos_sop_uid,
+                rejected_time = excluded.rejected_time",
+            REJECTION_COLUMNS,
+            placeholders(9)
+        );
+        for rejection in rejections {
+            sqlx::query(&insert_sql)
+                .bind(&rejection.tenant_id)
+                .bind(&rejection.study_uid)
+                .bind(&rejection.series_uid)
+                .bind(&rejection.sop_uid)
+                .bind(&rejection.reason_code)
+                .bind(&rejection.reason_meaning)
+                .bind(&rejection.kos_sop_uid)
+                .bind(rejection.rejected_time)
+                .bind(rejection.purged_time)
+                .execute(&mut *transaction)
+                .await
+                .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        }
+
+        let now = current_time();
+        let mut series: Vec<(&str, &str, &str)> = rejections
+            .iter()
+            .map(|r| {
+                (
+                    r.tenant_id.as_str(),
+                    r.study_uid.as_str(),
+                    r.series_uid.as_str(),
+                )
+            })
+            .collect();
+        series.sort_unstable();
+        series.dedup();
+        let mut studies: Vec<(&str, &str)> = series
+            .iter()
+            .map(|(tenant_id, study_uid, _)| (*tenant_id, *study_uid))
+            .collect();
+        studies.dedup();
+        let mut marked = 0;
+        for (tenant_id, study_uid) in &studies {
+            marked += Self::mark_rejected_images(&mut transaction, tenant_id, study_uid, Some(now))
+                .await?;
+            Self::refresh_study_meta(&mut transaction, tenant_id, study_uid).await?;
+        }
+        // 序列 updated_time 变化后 wado-webworker 会重新生成序列 JSON
+        for (tenant_id, study_uid, series_uid) in &series {
+            sqlx::query(
+                "UPDATE dicom_state_meta SET updated_time = ?
+                WHERE tenant_id = ? AND study_uid = ? AND series_uid = ?",
+            )
+            .bind(now)
+            .bind(tenant_id)
+            .bind(study_uid)
+            .bind(series_uid)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        }
+        transaction
+            .commit()
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(marked)
+    }
+
+    async fn get_rejected_sop_uids(
+        &self,
+        tenant_id: &str,
+        study_uid: &str,
+    ) -> Result<Vec<String>, DbError> {
+        let rows = sqlx::query(
+            "SELECT sop_uid FROM dicom_rejection WHERE tenant_id = ? AND study_uid = ?",
+        )
+        .bind(tenant_id)
+        .bind(study_uid)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    async fn get_rejections_to_purge(
+        &self,
+        reason_code: &str,
+        limit: i64,
+    ) -> Result<Vec<DicomRejection>, DbError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM dicom_rejection
+            WHERE reason_code = ? AND purged_time IS NULL
+            ORDER BY rejected_time LIMIT ?",
+            REJECTION_COLUMNS
+        ))
+        .bind(reason_code)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(rows.iter().map(Self::rejection_from_row).collect())
+    }
+
+    async fn mark_rejections_purged(&self, rejections: &[DicomRejection]) -> Result<(), DbError> {
+        if rejections.is_empty() {
+            return Ok(());
+        }
+        let mut transaction = self.begin().await?;
+        let now = current_time();
+        for rejection in rejections {
+            sqlx::query(
+                "UPDATE dicom_rejection SET purged_time = ?
+                WHERE tenant_id = ? AND study_uid = ? AND series_uid = ? AND sop_uid = ?",
+            )
+            .bind(now)
+            .bind(&rejection.tenant_id)
+            .bind(&rejection.study_uid)
+            .bind(&rejection.series_uid)
+            .bind(&rejection.sop_uid)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        }
+        transaction
+            .commit()
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))
+    }
+
+    async fn save_attribute_changes(
+        &self,
+        changes: &[DicomAttributeChange],
+    ) -> Result<(), DbError> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let mut transaction = self.begin().await?;
+        let audit_sql = format!(
+            "INSERT INTO dicom_change_audit ({}) VALUES ({})",
+            CHANGE_AUDIT_COLUMNS,
+            placeholders(10)
+        );
+        let now = current_time();
+        let mut usage: HashMap<String, UsageDelta> = HashMap::new();
+        for change in changes {
+            let audit = &change.audit;
+            let update = &change.update;
+            // 为空的字段保持原值
+            sqlx::query(
+                "UPDATE dicom_state_meta SET
+                    patient_id = COALESCE(?, patient_id),
+                    patient_name = COALESCE(?, patient_name),
+                    patient_sex = COALESCE(?, patient_sex),
+                    patient_birth_date = COALESCE(?, patient_birth_date),
+                    accession_number = COALESCE(?, accession_number),
+                    study_id = COALESCE(?, study_id),
+                    study_description = COALESCE(?, study_description),
+                    updated_time = ?
+                WHERE tenant_id = ? AND study_uid = ?",
+            )
+            .bind(&update.patient_id)
+            .bind(&update.patient_name)
+            .bind(&update.patient_sex)
+            .bind(update.patient_birth_date)
+            .bind(&update.accession_number)
+            .bind(&update.study_id)
+            .bind(&update.study_description)
+            .bind(now)
+            .bind(&audit.tenant_id)
+            .bind(&audit.study_uid)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+            sqlx::query(
+                "UPDATE dicom_image_meta SET patient_id = COALESCE(?, patient_id), updated_time = ?
+                WHERE tenant_id = ? AND study_uid = ?",
+            )
+            .bind(&update.patient_id)
+            .bind(now)
+            .bind(&audit.tenant_id)
+            .bind(&audit.study_uid)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+            Self::refresh_study_meta(
+                &mut transaction,
+                audit.tenant_id.as_str(),
+                audit.study_uid.as_str(),
+            )
+            .await?;
+            // 重新写入后文件大小略有变化, 同步更新租户用量
+            for file in &change.files {
+                let old_size: Option<i64> = sqlx::query(
+                    "SELECT COALESCE(space_size, 0) FROM dicom_image_meta
+                    WHERE tenant_id = ? AND study_uid = ? AND series_uid = ? AND sop_uid = ?",
+                )
+                .bind(&audit.tenant_id)
+                .bind(&audit.study_uid)
+                .bind(&file.series_uid)
+                .bind(&file.sop_uid)
+                .fetch_optional(&mut *transaction)
+                .await
+                .map_err(|e| DbError::DatabaseError(e.to_string()))?
+                .map(|row| row.get(0));
+                // 文件尚未建立索引
+                let Some(old_size) = old_size else {
+                    continue;
+                };
+                sqlx::query(
+                    "UPDATE dicom_image_meta SET checksum = ?, space_size = ?
+                    WHERE tenant_id = ? AND study_uid = ? AND series_uid = ? AND sop_uid = ?",
+                )
+                .bind(&file.checksum)
+                .bind(file.space_size)
+                .bind(&audit.tenant_id)
+                .bind(&audit.study_uid)
+                .bind(&file.series_uid)
+                .bind(&file.sop_uid)
+                .execute(&mut *transaction)
+                .await
+                .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+                usage
+                    .entry(audit.tenant_id.as_str().to_string())
+                    .or_default()
+                    .bytes += file.space_size - old_size;
+            }
+            sqlx::query(&audit_sql)
+                .bind(&audit.audit_id)
+                .bind(&audit.tenant_id)
+                .bind(&audit.operation)
+                .bind(&audit.patient_id)
+                .bind(&audit.study_uid)
+                .bind(&audit.before_value)
+                .bind(&audit.after_value)
+                .bind(audit.file_count)
+                .bind(&audit.requested_by)
+                .bind(audit.created_time)
+                .execute(&mut *transaction)
+                .await
+                .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        }
+        for (tenant_id, delta) in &usage {
+            if delta.bytes != 0 {
+                Self::apply_usage_delta(&mut transaction, tenant_id, delta).await?;
+            }
+        }
+        transaction
+            .commit()
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))
+    }
+
+    async fn get_change_audits(
+        &self,
+        tenant_id: &str,
+        patient_id: Option<&str>,
+        study_uid: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<DicomChangeAudit>, DbError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM dicom_change_audit
+            WHERE tenant_id = ?
+            AND (? IS NULL OR patient_id = ?
+                OR json_extract(after_value, '$.patient_id') = ?)
+            AND (? IS NULL OR study_uid = ?)
+            ORDER BY created_time DESC LIMIT ?",
+            CHANGE_AUDIT_COLUMNS
+        ))
+        .bind(tenant_id)
+        .bind(patient_id)
+        .bind(patient_id)
+        .bind(patient_id)
+        .bind(study_uid)
+        .bind(study_uid)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(rows.iter().map(Self::change_audit_from_row).collect())
+    }
+
+    async fn get_retention_studies(
+        &self,
+        study_date_before: chrono::NaiveDate,
+        after_tenant_id: &str,
+        after_study_uid: &str,
+        limit: i64,
+    ) -> Result<Vec<DicomRetentionStudy>, DbError> {
+        // group_concat 忽略 NULL, 默认以逗号分隔, 结果在 split_list 中拆分
+        let rows = sqlx::query(&format!(
+            "SELECT
+                s.tenant_id,
+                MIN(s.patient_id),
+                s.study_uid,
+                MIN(s.study_date),
+                MIN(s.patient_birth_date),
+                group_concat(DISTINCT s.modality),
+                (SELECT group_concat(DISTINCT i.sop_class_uid)
+                    FROM dicom_image_meta i
+                    WHERE i.tenant_id = s.tenant_id AND i.study_uid = s.study_uid)
+            FROM dicom_state_meta s
+            WHERE s.study_date < ? AND (s.tenant_id, s.study_uid) > (?, ?)
+            AND NOT EXISTS (SELECT 1 FROM dicom_retention_candidate c
+                WHERE c.tenant_id = s.tenant_id AND c.study_uid = s.study_uid)
+            AND NOT {}
+            GROUP BY s.tenant_id, s.study_uid
+            ORDER BY s.tenant_id, s.study_uid
+            LIMIT ?",
+            ON_LEGAL_HOLD
+        ))
+        .bind(study_date_before)
+        .bind(after_tenant_id)
+        .bind(after_study_uid)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(rows.iter().map(Self::retention_study_from_row).collect())
+    }
+
+    async fn save_retention_candidates(
+        &self,
+        candidates: &[DicomRetentionCandidate],
+    ) -> Result<u64, DbError> {
+        if candidates.is_empty() {
+            return Ok(0);
+        }
+        let mut transaction = self.begin().await?;
+        // 已存在的检查不插入, 影响行数为 0
+        let sql = format!(
+            "INSERT INTO dicom_retention_candidate ({}) VALUES ({})
+            ON CONFLICT (tenant_id, study_uid) DO NOTHING",
+            RETENTION_CANDIDATE_COLUMNS,
+            placeholders(9)
+        );
+        let mut saved = 0;
+        for candidate in candidates {
+            saved += sqlx::query(&sql)
+                .bind(&candidate.tenant_id)
+                .bind(&candidate.study_uid)
+                .bind(&candidate.patient_id)
+                .bind(&candidate.policy_name)
+                .bind(candidate.expire_date)
+                .bind(&candidate.status)
+                .bind(&candidate.deletion_id)
+                .bind(candidate.created_time)
+                .bind(candidate.updated_time)
+                .execute(&mut *transaction)
+                .await
+                .map_err(|e| DbError::DatabaseError(e.to_string()))?
+                .rows_affected();
+        }
+        transaction
+            .commit()
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(saved)
+    }
+
+    async fn get_retention_candidates(
+        &self,
+        tenant_id: Option<&str>,
+        status: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<DicomRetentionCandidate>, DbError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM dicom_retention_candidate
+            WHERE (? IS NULL OR tenant_id = ?)
+                AND (? IS NULL OR status = ?)
+            ORDER BY expire_date, tenant_id, study_uid
+            LIMIT ?",
+            RETENTION_CANDIDATE_COLUMNS
+        ))
+        .bind(tenant_id)
+        .bind(tenant_id)
+        .bind(status)
+        .bind(status)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(rows
+            .iter()
+            .map(Self::retention_candidate_from_row)
+            .collect())
+    }
+
+    async fn get_deletable_retention_candidates(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<DicomRetentionCandidate>, DbError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM dicom_retention_candidate s
+            WHERE status = ? AND NOT {}
+            ORDER BY expire_date, tenant_id, study_uid
+            LIMIT ?",
+            RETENTION_CANDIDATE_COLUMNS, ON_LEGAL_HOLD
+        ))
+        .bind(DicomRetentionCandidate::STATUS_PENDING)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(rows
+            .iter()
+            .map(Self::retention_candidate_from_row)
+            .collect())
+    }
+
+    async fn get_retention_candidate(
+        &self,
+        tenant_id: &str,
+        study_uid: &str,
+    ) -> Result<Option<DicomRetentionCandidate>, DbError> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM dicom_retention_candidate WHERE tenant_id = ? AND study_uid = ?",
+            RETENTION_CANDIDATE_COLUMNS
+        ))
+        .bind(tenant_id)
+        .bind(study_uid)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(row.as_ref().map(Self::retention_candidate_from_row))
+    }
+
+    async fn update_retention_candidate(
+        &self,
+        tenant_id: &str,
+        study_uid: &str,
+        status: &str,
+        deletion_id: Option<&str>,
+    ) -> Result<(), DbError> {
+        sqlx::query(
+            "UPDATE dicom_retention_candidate SET status = ?, deletion_id = ?, updated_time = ?
+            WHERE tenant_id = ? AND study_uid = ?",
+        )
+        .bind(status)
+        .bind(deletion_id)
+        .bind(current_time())
+        .bind(tenant_id)
+        .bind(study_uid)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn save_legal_hold(&self, hold: &DicomLegalHold) -> Result<(), DbError> {
+        sqlx::query(&format!(
+            "INSERT INTO dicom_legal_hold ({}) VALUES ({})",
+            LEGAL_HOLD_COLUMNS,
+            placeholders(9)
+        ))
+        .bind(&hold.hold_id)
+        .bind(&hold.tenant_id)
+        .bind(&hold.patient_id)
+        .bind(&hold.study_uid)
+        .bind(&hold.reason)
+        .bind(&hold.created_by)
+        .bind(hold.created_time)
+        .bind(&hold.released_by)
+        .bind(hold.released_time)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn get_legal_holds(
+        &self,
+        tenant_id: &str,
+        include_released: bool,
+        limit: i64,
+    ) -> Result<Vec<DicomLegalHold>, DbError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM dicom_legal_hold
+            WHERE tenant_id = ? AND (? OR released_time IS NULL)
+            ORDER BY created_time DESC
+            LIMIT ?",
+            LEGAL_HOLD_COLUMNS
+        ))
+        .bind(tenant_id)
+        .bind(include_released)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(rows.iter().map(Self::legal_hold_from_row).collect())
+    }
+
+    async fn release_legal_hold(
+        &self,
+        tenant_id: &str,
+        hold_id: &str,
+        released_by: Option<&str>,
+    ) -> Result<bool, DbError> {
+        let updated = sqlx::query(
+            "UPDATE dicom_legal_hold SET released_by = ?, released_time = ?
+            WHERE tenant_id = ? AND hold_id = ? AND released_time IS NULL",
+        )
+        .bind(released_by)
+        .bind(current_time())
+        .bind(tenant_id)
+        .bind(hold_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?
+        .rows_affected();
+        Ok(updated > 0)
+    }
+
+    async fn is_on_legal_hold(
+        &self,
+        tenant_id: &str,
+        patient_id: Option<&str>,
+        study_uid: &str,
+    ) -> Result<bool, DbError> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM (SELECT ? AS tenant_id, ? AS patient_id, ? AS study_uid) s",
+            ON_LEGAL_HOLD
+        ))
+        .bind(tenant_id)
+        .bind(patient_id)
+        .bind(study_uid)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(row.get(0))
+    }
+
+    async fn get_tenant_usage(
+        &self,
+        tenant_id: Option<&str>,
+    ) -> Result<Vec<DicomTenantUsage>, DbError> {
+        let rows = sqlx::query(
+            "SELECT
+                tenant_id,
+                date(updated_time),
+                total_bytes,
+                instance_count,
+                study_count,
+                updated_time
+            FROM dicom_tenant_usage
+            WHERE ? IS NULL OR tenant_id = ?
+            ORDER BY tenant_id",
+        )
+        .bind(tenant_id)
+        .bind(tenant_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(rows.iter().map(Self::tenant_usage_from_row).collect())
+    }
+
+    async fn get_tenant_usage_history(
+        &self,
+        tenant_id: &str,
+        from: chrono::NaiveDate,
+        to: chrono::NaiveDate,
+    ) -> Result<Vec<DicomTenantUsage>, DbError> {
+        let rows = sqlx::query(
+            "SELECT
+                tenant_id,
+                usage_date,
+                total_bytes,
+                instance_count,
+                study_count,
+                updated_time
+            FROM dicom_tenant_usage_daily
+            WHERE tenant_id = ? AND usage_date BETWEEN ? AND ?
+            ORDER BY usage_date",
+        )
+        .bind(tenant_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(rows.iter().map(Self::tenant_usage_from_row).collect())
+    }
+
+    async fn get_study_meta(
+        &self,
+        tenant_id: &str,
+        study_uid: &str,
+    ) -> Result<Option<DicomStudyMeta>, DbError> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM dicom_study_meta WHERE tenant_id = ? AND study_uid = ?",
+            STUDY_META_COLUMNS
+        ))
+        .bind(tenant_id)
+        .bind(study_uid)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(row.as_ref().map(Self::study_meta_from_row))
+    }
+
+    async fn search_study_metas(
+        &self,
+        tenant_id: &str,
+        query: &DicomStudyQuery,
+    ) -> Result<Vec<DicomStudyMeta>, DbError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM dicom_study_meta
+            WHERE tenant_id = ?
+            AND (? IS NULL OR patient_id = ?)
+            AND (? IS NULL OR accession_number = ?)
+            AND (? IS NULL OR study_date >= ?)
+            AND (? IS NULL OR study_date <= ?)
+            AND (? IS NULL OR instr(',' || modalities_in_study || ',', ',' || ? || ',') > 0)
+            ORDER BY study_date DESC, study_uid
+            LIMIT ? OFFSET ?",
+            STUDY_META_COLUMNS
+        ))
+        .bind(tenant_id)
+        .bind(&query.patient_id)
+        .bind(&query.patient_id)
+        .bind(&query.accession_number)
+        .bind(&query.accession_number)
+        .bind(query.study_date_from)
+        .bind(query.study_date_from)
+        .bind(query.study_date_to)
+        .bind(query.study_date_to)
+        .bind(&query.modality)
+        .bind(&query.modality)
+        .bind(query.limit)
+        .bind(query.offset)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(rows.iter().map(Self::study_meta_from_row).collect())
+    }
+
+    async fn get_patient_meta(
+        &self,
+        tenant_id: &str,
+        patient_id: &str,
+    ) -> Result<Option<DicomPatientMeta>, DbError> {
+        let row = sqlx::query(
+            "SELECT
+                tenant_id,
+                patient_id,
+                MAX(patient_name),
+                MAX(patient_sex),
+                MAX(patient_birth_date),
+                COUNT(*),
+                SUM(number_of_series),
+                SUM(number_of_instances)
+            FROM dicom_study_meta
+            WHERE tenant_id = ? AND patient_id = ?
+            GROUP BY tenant_id, patient_id",
+        )
+        .bind(tenant_id)
+        .bind(patient_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        Ok(row.as_ref().map(Self::patient_meta_from_row))
+    }
+
+    async fn schema_version(&self) -> Result<i64, DbError> {
+        let exists: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM sqlite_master
+            WHERE type = 'table' AND name = 'dicom_schema_migration'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+        if exists == 0 {
+            return Ok(0);
+        }
+        sqlx::query_scalar("SELECT COALESCE(MAX(version), 0) FROM dicom_schema_migration")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))
+    }
+
+    async fn migrate(&self) -> Result<Vec<i64>, DbError> {
+        // BEGIN IMMEDIATE 持有写锁, 同时启动的进程依次执行
+        let mut tx = self.begin().await?;
+        (&mut *tx)
+            .execute(sqlx::raw_sql(
+                "CREATE TABLE IF NOT EXISTS dicom_schema_migration (
+                version      integer      not null primary key,
+                description  varchar(256) not null,
+                checksum     varchar(64)  not null,
+                applied_time text         not null
+            )",
+            ))
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+
+        let applied: Vec<AppliedMigration> =
+            sqlx::query("SELECT version, checksum FROM dicom_schema_migration")
+                .fetch_all(&mut *tx)
+                .await
+                .map_err(|e| DbError::DatabaseError(e.to_string()))?
+                .iter()
+                .map(|row| AppliedMigration {
+                    version: row.get(0),
+                    checksum: row.get(1),
+                })
+                .collect();
+
+        let mut versions = Vec::new();
+        for migration in pending_migrations(SQLITE_MIGRATIONS, &applied)? {
+            (&mut *tx)
+                .execute(sqlx::raw_sql(migration.sql))
+                .await
+                .map_err(|e| {
+                    DbError::DatabaseError(format!("migration {} failed: {}", migration.version, e))
+                })?;
+            sqlx::query(
+                "INSERT INTO dicom_schema_migration (version, description, checksum, applied_time)
+                VALUES (?, ?, ?, ?)",
+            )
+            .bind(migration.version)
+            .bind(migration.description)
+            .bind(migration.checksum())
+            .bind(current_time())
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DbError::DatabaseError(e.to_string()))?;
+            versions.push(migration.version);
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| DbError::TransactionFailed(e.to_string()))?;
+        Ok(versions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dicom_dbtype::{BoundedString, DicomDateString};
+    use crate::dicom_migration::SCHEMA_VERSION;
+    use crate::dicom_provider_tests as provider_tests;
+    use chrono::NaiveDate;
+    use tempfile::TempDir;
+
+    /// 临时目录中的数据库, TempDir 释放时删除数据库文件
+    async fn test_provider() -> Result<(TempDir, SqliteDbProvider), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("dicom").join("dicom.db");
+        let provider = SqliteDbProvider::new(path.to_str().unwrap(), &DbPoolOptions::default())?;
+        provider.migrate().await?;
+        seed_state_meta(&provider).await?;
+        Ok((dir, provider))
+    }
+
+    /// 共用测试查询的序列, 更新时间早于 get_json_metaes 的截止时间
+    async fn seed_state_meta(
+        provider: &SqliteDbProvider,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let study_uid = "1.2.156.112605.0.1685486876.2025061710152134339.2.1.1";
+        let series_uid = "1.2.156.112605.0.1685486876.2025061710152134339.3.1.1";
+        let updated_time = current_time() - chrono::Duration::minutes(10);
+        let state_meta = DicomStateMeta {
+            tenant_id: BoundedString::<64>::make_str("1234567890"),
+            patient_id: BoundedString::<64>::make_str("PID0001"),
+            study_uid: BoundedString::<64>::make_str(study_uid),
+            series_uid: BoundedString::<64>::make_str(series_uid),
+            study_uid_hash: BoundedString::<20>::make_str(study_uid),
+            series_uid_hash: BoundedString::<20>::make_str(series_uid),
+            study_date_origin: DicomDateString::make("20250617"),
+            patient_name: None,
+            patient_sex: None,
+            patient_birth_date: None,
+            patient_birth_time: None,
+            patient_age: None,
+            patient_size: None,
+            patient_weight: None,
+            study_date: NaiveDate::from_ymd_opt(2025, 6, 17).unwrap(),
+            study_time: None,
+            accession_number: None,
+            study_id: None,
+            study_description: None,
+            modality: Some(BoundedString::<16>::make_str("CT")),
+            series_number: Some(1),
+            series_date: None,
+            series_time: None,
+            series_description: None,
+            body_part_examined: None,
+            protocol_name: None,
+            series_related_instances: None,
+            created_time: updated_time,
+            updated_time,
+            storage_layout: 0,
+        };
+        provider.save_state_info(&state_meta).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_save_state_info() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, db_provider) = test_provider().await?;
+        provider_tests::save_state_info(&db_provider).await
+    }
+
+    #[tokio::test]
+    async fn test_get_state_metaes() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, db_provider) = test_provider().await?;
+        provider_tests::get_state_metaes(&db_provider).await
+    }
+
+    #[tokio::test]
+    async fn test_get_json_metaes() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, db_provider) = test_provider().await?;
+        provider_tests::get_json_metaes(&db_provider).await
+    }
+
+    #[tokio::test]
+    async fn test_save_state_list() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, db_provider) = test_provider().await?;
+        provider_tests::save_state_list(&db_provider).await
+    }
+
+    #[tokio::test]
+    async fn test_save_json_list() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, db_provider) = test_provider().await?;
+        provider_tests::save_json_list(&db_provider).await
+    }
+
+    #[tokio::test]
+    async fn test_save_store_info() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, db_provider) = test_provider().await?;
+        provider_tests::save_store_info(&db_provider).await
+    }
+
+    #[tokio::test]
+    async fn test_claim_prefetch_task() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, db_provider) = test_provider().await?;
+        provider_tests::claim_prefetch_task(&db_provider).await
+    }
+
+    #[tokio::test]
+    async fn test_has_removal_record() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, db_provider) = test_provider().await?;
+        provider_tests::has_removal_record(&db_provider).await
+    }
+
+    #[tokio::test]
+    async fn test_delete_dicom_objects() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, db_provider) = test_provider().await?;
+        provider_tests::delete_dicom_objects(&db_provider).await
+    }
+
+    #[tokio::test]
+    async fn test_save_attribute_changes() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, db_provider) = test_provider().await?;
+        provider_tests::save_attribute_changes(&db_provider).await
+    }
+
+    #[tokio::test]
+    async fn test_save_rejections() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, db_provider) = test_provider().await?;
+        provider_tests::save_rejections(&db_provider).await
+    }
+
+    #[tokio::test]
+    async fn test_retention() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, db_provider) = test_provider().await?;
+        provider_tests::retention(&db_provider).await
+    }
+
+    #[tokio::test]
+    async fn test_tenant_usage() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, db_provider) = test_provider().await?;
+        provider_tests::tenant_usage(&db_provider).await
+    }
+
+    #[tokio::test]
+    async fn test_study_meta_instances_delta() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, db_provider) = test_provider().await?;
+        provider_tests::study_meta_instances(&db_provider).await
+    }
+
+    #[tokio::test]
+    async fn test_series_tier() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, db_provider) = test_provider().await?;
+        provider_tests::series_tier(&db_provider).await
+    }
+
+    #[tokio::test]
+    async fn test_fixity() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, db_provider) = test_provider().await?;
+        provider_tests::fixity(&db_provider).await
+    }
+
+    #[tokio::test]
+    async fn test_forward_tasks() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, db_provider) = test_provider().await?;
+        provider_tests::forward_tasks(&db_provider).await
+    }
+
+    #[tokio::test]
+    async fn test_save_prefetch_task() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, db_provider) = test_provider().await?;
+        provider_tests::save_prefetch_task(&db_provider).await
+    }
+
+    #[tokio::test]
+    async fn test_save_image_list() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, db_provider) = test_provider().await?;
+        provider_tests::save_image_list(&db_provider).await?;
+        // 重复接收同一实例不重复计算用量
+        provider_tests::save_image_list(&db_provider).await?;
+        let usage = db_provider
+            .get_tenant_usage(Some("test_tenant_image_123"))
+            .await?;
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].total_bytes, 2049);
+        assert_eq!(usage[0].instance_count, 1);
+        assert_eq!(usage[0].study_count, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_study_meta() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, db_provider) = test_provider().await?;
+        let tenant_id = "1234567890";
+        let study_uid = "1.2.156.112605.0.1685486876.2025061710152134339.2.1.1";
+        let study = db_provider
+            .get_study_meta(tenant_id, study_uid)
+            .await?
+            .unwrap();
+        assert_eq!(study.patient_id.as_str(), "PID0001");
+        assert_eq!(study.modalities_in_study, vec!["CT".to_string()]);
+        assert_eq!(study.number_of_series, 1);
+        assert_eq!(study.number_of_instances, 0);
+
+        // 同一检查增加 PT 序列
+        let mut state_meta = db_provider
+            .get_state_metaes(tenant_id, study_uid)
+            .await?
+            .remove(0);
+        state_meta.series_uid =
+            BoundedString::<64>::make_str("1.2.156.112605.0.1685486876.2025061710152134339.3.1.2");
+        state_meta.modality = Some(BoundedString::<16>::make_str("PT"));
+        db_provider.save_state_list(&[state_meta]).await?;
+        let mut study = db_provider
+            .get_study_meta(tenant_id, study_uid)
+            .await?
+            .unwrap();
+        // sqlite 的 group_concat 不保证顺序
+        study.modalities_in_study.sort();
+        assert_eq!(
+            study.modalities_in_study,
+            vec!["CT".to_string(), "PT".to_string()]
+        );
+        assert_eq!(study.number_of_series, 2);
+
+        let query = DicomStudyQuery {
+            modality: Some("PT".to_string()),
+            limit: 10,
+            ..Default::default()
+        };
+        assert_eq!(
+            db_provider
+                .search_study_metas(tenant_id, &query)
+                .await?
+                .len(),
+            1
+        );
+        let query = DicomStudyQuery {
+            modality: Some("MR".to_string()),
+            limit: 10,
+            ..Default::default()
+        };
+        assert!(
+            db_provider
+                .search_study_metas(tenant_id, &query)
+                .await?
+                .is_empty()
+        );
+
+        let patient = db_provider
+            .get_patient_meta(tenant_id, "PID0001")
+            .await?
+            .unwrap();
+        assert_eq!(patient.number_of_studies, 1);
+        assert_eq!(patient.number_of_series, 2);
+        assert!(
+            db_provider
+                .get_patient_meta(tenant_id, "PID0002")
+                .await?
+                .is_none()
+        );
+
+        let now = current_time();
+        let deletion = DicomDeletion {
+            deletion_id: BoundedString::<36>::make_str("a6f1c0e2-6c1f-4b7e-9d1a-4f3e2b1c0d9e"),
+            tenant_id: BoundedString::<64>::make_str(tenant_id),
+            level: BoundedString::<16>::make_str(DicomDeletion::LEVEL_STUDY),
+            study_uid: BoundedString::<64>::make_str(study_uid),
+            series_uid: None,
+            sop_uid: None,
+            instance_count: 0,
+            total_bytes: 0,
+            status: BoundedString::<16>::make_str(DicomDeletion::STATUS_PURGED),
+            requested_by: None,
+            purge_after: None,
+            created_time: now,
+            updated_time: now,
+        };
+        db_provider.delete_dicom_objects(&deletion).await?;
+        assert!(
+            db_provider
+                .get_study_meta(tenant_id, study_uid)
+                .await?
+                .is_none()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_study_meta_instances() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, db_provider) = test_provider().await?;
+        let tenant_id = "test_tenant_image_123";
+        let study_uid = "1.2.3.4.5.6.7.8.9.image";
+        let series_uid = "9.8.7.6.5.4.3.2.1.image";
+        let sop_uid = "1.3.6.1.4.1.5962.1.1.0.0.0.1234567890";
+        let mut state_meta = db_provider
+            .get_state_metaes(
+                "1234567890",
+                "1.2.156.112605.0.1685486876.2025061710152134339.2.1.1",
+            )
+            .await?
+            .remove(0);
+        state_meta.tenant_id = BoundedString::<64>::make_str(tenant_id);
+        state_meta.study_uid = BoundedString::<64>::make_str(study_uid);
+        state_meta.series_uid = BoundedString::<64>::make_str(series_uid);
+        db_provider.save_state_list(&[state_meta]).await?;
+        provider_tests::save_image_list(&db_provider).await?;
+        let study = db_provider
+            .get_study_meta(tenant_id, study_uid)
+            .await?
+            .unwrap();
+        assert_eq!(study.number_of_instances, 1);
+
+        // 被拒绝的实例不计入
+        let rejection = DicomRejection {
+            tenant_id: BoundedString::<64>::make_str(tenant_id),
+            study_uid: BoundedString::<64>::make_str(study_uid),
+            series_uid: BoundedString::<64>::make_str(series_uid),
+            sop_uid: BoundedString::<64>::make_str(sop_uid),
+            reason_code: BoundedString::<16>::make_str(DicomRejection::REASON_QUALITY),
+            reason_meaning: BoundedString::<64>::make_str("Rejected for Quality Reasons"),
+            kos_sop_uid: BoundedString::<64>::make_str("1.2.3.4.5.6.7.8.9.kos"),
+            rejected_time: current_time(),
+            purged_time: None,
+        };
+        db_provider.save_rejections(&[rejection]).await?;
+        let study = db_provider
+            .get_study_meta(tenant_id, study_uid)
+            .await?
+            .unwrap();
+        assert_eq!(study.number_of_instances, 0);
+        assert_eq!(study.number_of_series, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_migrate_twice() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, db_provider) = test_provider().await?;
+        assert!(db_provider.migrate().await?.is_empty());
+        assert_eq!(db_provider.schema_version().await?, SCHEMA_VERSION);
+        let state_metas = db_provider
+            .get_state_metaes(
+                "1234567890",
+                "1.2.156.112605.0.1685486876.2025061710152134339.2.1.1",
+            )
+            .await?;
+        assert_eq!(state_metas.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_schema_matches_columns() -> Result<(), Box<dyn std::error::Error>> {
+        let (_dir, db_provider) = test_provider().await?;
+        let tables = [
+            ("dicom_state_meta", STATE_META_COLUMNS),
+            ("dicom_json_meta", JSON_META_COLUMNS),
+            ("dicom_image_meta", IMAGE_META_COLUMNS),
+            ("dicom_object_meta", STORE_META_COLUMNS),
+            ("dicom_mpps_meta", MPPS_COLUMNS),
+            ("dicom_prefetch_task", PREFETCH_TASK_COLUMNS),
+            ("dicom_fixity_check", FIXITY_CHECK_COLUMNS),
+            ("dicom_change_audit", CHANGE_AUDIT_COLUMNS),
+            ("dicom_deletion", DELETION_COLUMNS),
+            ("dicom_forward_task", FORWARD_TASK_COLUMNS),
+            ("dicom_legal_hold", LEGAL_HOLD_COLUMNS),
+            ("dicom_rejection", REJECTION_COLUMNS),
+            ("dicom_retention_candidate", RETENTION_CANDIDATE_COLUMNS),
+            ("dicom_study_meta", STUDY_META_COLUMNS),
+        ];
+        for (table, columns) in tables {
+            sqlx::query(&format!("SELECT {} FROM {} LIMIT 0", columns, table))
+                .fetch_all(&db_provider.pool)
+                .await
+                .map_err(|e| format!("{}: {}", table, e))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+//! sqlx 类型映射, 按字符串读写 BoundedString / FixedLengthString / DicomDateString.
+//! 不限定具体数据库, MySQL 和其他 sqlx 驱动共用.
+
+use crate::dicom_dbtype::{BoundedString, DicomDateString, FixedLengthString};
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::{Database, Decode, Encode, Type};
+
+impl<const N: usize, DB: Database> Type<DB> for BoundedString<N>
+where
+    String: Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <String as Type<DB>>::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <String as Type<DB>>::compatible(ty)
+    }
+}
+
+impl<'q, const N: usize, DB: Database> Encode<'q, DB> for BoundedString<N>
+where
+    String: Encode<'q, DB>,
+{
+    fn encode_by_ref(
+        &self,
+        buf: &mut <DB as Database>::ArgumentBuffer<'q>,
+    ) -> Result<IsNull, BoxDynError> {
+        <String as Encode<'q, DB>>::encode(self.as_str().to_string(), buf)
+    }
+}
+
+impl<'r, const N: usize, DB: Database> Decode<'r, DB> for BoundedString<N>
+where
+    String: Decode<'r, DB>,
+{
+    fn decode(value: <DB as Database>::ValueRef<'r>) -> Result<Self, BoxDynError> {
+        let str_val = <String as Decode<'r, DB>>::decode(value)?;
+        Ok(BoundedString::try_from(str_val)
+            .map_err(|e| format!("Failed to create BoundedString Decode: {}", e))?)
+    }
+}
+
+impl<const N: usize, DB: Database> Type<DB> for FixedLengthString<N>
+where
+    String: Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <String as Type<DB>>::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <String as Type<DB>>::compatible(ty)
+    }
+}
+
+impl<'q, const N: usize, DB: Database> Encode<'q, DB> for FixedLengthString<N>
+where
+    String: Encode<'q, DB>,
+{
+    fn encode_by_ref(
+        &self,
+        buf: &mut <DB as Database>::ArgumentBuffer<'q>,
+    ) -> Result<IsNull, BoxDynError> {
+        <String as Encode<'q, DB>>::encode(self.as_str().to_string(), buf)
+    }
+}
+
+impl<'r, const N: usize, DB: Database> Decode<'r, DB> for FixedLengthString<N>
+where
+    String: Decode<'r, DB>,
+{
+    fn decode(value: <DB as Database>::ValueRef<'r>) -> Result<Self, BoxDynError> {
+        let str_val = <String as Decode<'r, DB>>::decode(value)?;
+        Ok(FixedLengthString::from_str(&str_val)
+            .map_err(|e| format!("Failed to create FixedLengthString Decode: {}", e))?)
+    }
+}
+
+impl<DB: Database> Type<DB> for DicomDateString
+where
+    String: Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <String as Type<DB>>::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <String as Type<DB>>::compatible(ty)
+    }
+}
+
+impl<'q, DB: Database> Encode<'q, DB> for DicomDateString
+where
+    String: Encode<'q, DB>,
+{
+    fn encode_by_ref(
+        &self,
+        buf: &mut <DB as Database>::ArgumentBuffer<'q>,
+    ) -> Result<IsNull, BoxDynError> {
+        <String as Encode<'q, DB>>::encode(self.as_str().to_string(), buf)
+    }
+}
+
+impl<'r, DB: Database> Decode<'r, DB> for DicomDateString
+where
+    String: Decode<'r, DB>,
+{
+    fn decode(value: <DB as Database>::ValueRef<'r>) -> Result<Self, BoxDynError> {
+        let str_val = <String as Decode<'r, DB>>::decode(value)?;
+        Ok(DicomDateString::try_from(str_val)
+            .map_err(|e| format!("Failed to create DicomDateString Decode: {}", e))?)
+    }
+}
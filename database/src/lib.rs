@@ -1,9 +1,16 @@
 pub mod dicom_dbprovider;
 pub mod dicom_dbtype;
 pub mod dicom_meta;
+pub mod dicom_migration;
+pub mod dicom_mysql;
 pub mod dicom_pg;
+pub mod dicom_pg_pool;
 pub mod dicom_pg_types;
+pub mod dicom_sqlite;
+pub mod dicom_sqlx_types;
 pub mod dicom_access_log;
+#[cfg(test)]
+mod dicom_provider_tests;
 
 pub fn add(left: u64, right: u64) -> u64 {
     left + right
@@ -1,6 +1,10 @@
 use common::message_sender_kafka::KafkaMessagePublisher;
+use common::redis_key::RedisHelper;
+use common::rejection::apply_rejections;
 use common::utils::{get_logger, group_dicom_state};
+use common::storage_tier::spawn_cleanup_interrupted_writes;
 use common::{database_factory, server_config};
+use database::dicom_dbprovider::DbProvider;
 use database::dicom_meta::{DicomImageMeta, DicomStateMeta, DicomStoreMeta};
 use futures::StreamExt;
 use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
@@ -11,6 +15,7 @@ use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
 use tokio::runtime::Handle;
+use tokio::sync::OnceCell;
 
 pub async fn start_process() {
     // 设置全局logger
@@ -30,6 +35,9 @@ pub async fn start_process() {
         }
     };
 
+    // 清理上次异常退出时遗留的临时文件
+    spawn_cleanup_interrupted_writes();
+
     let kafka_config = config.kafka;
 
     let queue_config = config.message_queue;
@@ -188,7 +196,7 @@ async fn persist_message_loop(
             return;
         }
     };
-    let queue_config = app_config.message_queue;
+    let queue_config = &app_config.message_queue;
 
     let topic_state = &queue_config.topic_dicom_state.as_str();
     let topic_image = &queue_config.topic_dicom_image.as_str();
@@ -210,6 +218,9 @@ async fn persist_message_loop(
 
     let state_producer = KafkaMessagePublisher::new(topic_dicom_state);
     let image_producer = KafkaMessagePublisher::new(topic_dicom_image);
+    let redis_helper = RedisHelper::new(app_config.redis.clone());
+    // 数据库实例持有连接池, 第一次使用时创建, 之后各批次共享
+    let db_instance: OnceCell<Arc<dyn DbProvider>> = OnceCell::new();
     loop {
         let should_process = {
             let vec = vec.lock().unwrap();
@@ -251,13 +262,14 @@ async fn persist_message_loop(
             tokio::time::sleep(Duration::from_secs(1)).await;
             continue;
         }
-        let (state_metas, image_entities) = match group_dicom_state(&messages_to_process).await {
-            Ok((state_metas, image_entities)) => (state_metas, image_entities),
-            Err(e) => {
-                error!(logger, "Failed to group dicom state: {}", e);
-                (vec![] ,vec![])
-            }
-        };
+        let (state_metas, image_entities, rejections) =
+            match group_dicom_state(&messages_to_process).await {
+                Ok(grouped) => grouped,
+                Err(e) => {
+                    error!(logger, "Failed to group dicom state: {}", e);
+                    (vec![], vec![], vec![])
+                }
+            };
         if state_metas.is_empty() && image_entities.is_empty() {
             // 批量处理完成，更新最后处理时间
             let mut time = last_process_time.lock().unwrap();
@@ -273,7 +285,10 @@ async fn persist_message_loop(
             info!(logger, "ImageEntity: {:?}", image_entity);
         }
 
-        match database_factory::create_db_instance(&app_config.main_database).await {
+        match db_instance
+            .get_or_try_init(|| database_factory::create_db_instance(&app_config.main_database))
+            .await
+        {
             Ok(db) => {
                 // 保存收图记录
                 match db.save_store_list(&messages_to_process).await {
@@ -290,6 +305,12 @@ async fn persist_message_loop(
                     Ok(_) => {}
                     Err(e) => error!(logger, "Failed to save_image_list: {}", e),
                 }
+                // 拒绝说明引用的实例可能在同一批次中, 因此在保存实例之后处理
+                if let Err(e) =
+                    apply_rejections(db.as_ref(), &redis_helper, &app_config, &rejections).await
+                {
+                    error!(logger, "Failed to apply_rejections: {}", e);
+                }
             }
             Err(e) => {
                 error!(logger, "Failed to create database: {}", e);
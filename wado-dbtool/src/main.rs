@@ -0,0 +1,66 @@
+//! 数据库结构迁移工具
+//!
+//! 升级时先停止各服务, 执行 migrate 后再启动新版本. 也可以在配置中开启 main_database.auto_migrate,
+//! 由服务启动时执行.
+
+use clap::{Parser, Subcommand};
+use common::database_factory;
+use common::server_config;
+use database::dicom_migration::SCHEMA_VERSION;
+use std::process::ExitCode;
+
+/// Database schema migration
+#[derive(Debug, Parser)]
+#[command(version)]
+struct App {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Apply pending schema migrations
+    Migrate,
+    /// Print the schema version of the database and of this binary
+    Status,
+}
+
+async fn run(command: Command) -> Result<(), Box<dyn std::error::Error>> {
+    let config = server_config::load_config()?;
+    let db = database_factory::connect_db_instance(&config.main_database).await?;
+    match command {
+        Command::Migrate => {
+            let versions = db.migrate().await?;
+            if versions.is_empty() {
+                println!(
+                    "Schema is up to date (version {})",
+                    db.schema_version().await?
+                );
+            } else {
+                for version in versions {
+                    println!("Applied migration {}", version);
+                }
+            }
+        }
+        Command::Status => {
+            let version = db.schema_version().await?;
+            println!("Database schema version: {}", version);
+            println!("Required schema version: {}", SCHEMA_VERSION);
+            if version < SCHEMA_VERSION {
+                println!("Run `wado-dbtool migrate` to upgrade the schema");
+            }
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run(App::parse().command).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Schema migration failed: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
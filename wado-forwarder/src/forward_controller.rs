@@ -0,0 +1,64 @@
+use crate::ForwarderState;
+use actix_web::{HttpResponse, Responder, get, post, web};
+use database::dicom_dbprovider::DbError;
+use database::dicom_meta::DicomForwardTask;
+use serde::Deserialize;
+use slog::error;
+
+const DEFAULT_QUERY_LIMIT: i64 = 100;
+const MAX_QUERY_LIMIT: i64 = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct ForwardTaskQuery {
+    /// PENDING / RUNNING / SUCCESS / FAILED, 不传时查询全部
+    pub status: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// 查询转发任务, 如: GET /forward/tasks?status=FAILED&limit=50
+#[get("/forward/tasks")]
+pub async fn list_forward_tasks(
+    state: web::Data<ForwarderState>,
+    query: web::Query<ForwardTaskQuery>,
+) -> impl Responder {
+    let status = query.status.as_deref().map(|s| s.to_uppercase());
+    if let Some(status) = &status {
+        let valid = [
+            DicomForwardTask::STATUS_PENDING,
+            DicomForwardTask::STATUS_RUNNING,
+            DicomForwardTask::STATUS_SUCCESS,
+            DicomForwardTask::STATUS_FAILED,
+        ];
+        if !valid.contains(&status.as_str()) {
+            return HttpResponse::BadRequest().body(format!("invalid status: {}", status));
+        }
+    }
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_QUERY_LIMIT)
+        .clamp(1, MAX_QUERY_LIMIT);
+    match state.db.get_forward_tasks(status.as_deref(), limit).await {
+        Ok(tasks) => HttpResponse::Ok().json(tasks),
+        Err(e) => {
+            error!(state.log, "get_forward_tasks error: {}", e);
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+/// 将任务重新入队, 立即重新发送
+#[post("/forward/tasks/{task_id}/requeue")]
+pub async fn requeue_forward_task(
+    state: web::Data<ForwarderState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let task_id = path.into_inner();
+    match state.db.requeue_forward_task(&task_id).await {
+        Ok(()) => HttpResponse::Ok().body("Success"),
+        Err(DbError::RecordNotExists(msg)) => HttpResponse::NotFound().body(msg),
+        Err(e) => {
+            error!(state.log, "requeue_forward_task error: {}", e);
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
@@ -0,0 +1,91 @@
+mod forward_controller;
+mod rules;
+mod store_listener;
+mod worker;
+
+use actix_web::{App, HttpServer, web};
+use common::utils::setup_logging;
+use common::{database_factory, server_config};
+use database::dicom_dbprovider::DbProvider;
+use slog::{Logger, error, info};
+use std::sync::Arc;
+
+#[derive(Clone)]
+struct ForwarderState {
+    log: Logger,
+    db: Arc<dyn DbProvider + Send + Sync>,
+}
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    let log = setup_logging("wado-forwarder");
+    let config = match server_config::load_config() {
+        Ok(config) => config,
+        Err(e) => {
+            error!(log, "Error loading config: {:?}", e);
+            return Err(std::io::Error::other(e));
+        }
+    };
+    let forwarder_config = match &config.forwarder {
+        Some(forwarder_config) => forwarder_config.clone(),
+        None => {
+            info!(log, "forwarder is not configured, exit");
+            return Ok(());
+        }
+    };
+
+    let rules = match rules::compile_rules(&forwarder_config.rules, &config.dicom_nodes) {
+        Ok(rules) => Arc::new(rules),
+        Err(e) => {
+            error!(log, "Invalid forward rules: {}", e);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, e));
+        }
+    };
+    info!(log, "Loaded {} forward rules", rules.len());
+
+    let db = match database_factory::create_db_instance(&config.main_database).await {
+        Ok(db) => db as Arc<dyn DbProvider + Send + Sync>,
+        Err(e) => {
+            error!(log, "create_db_instance error: {:?}", e);
+            return Err(std::io::Error::other(format!(
+                "create_db_instance error: {:?}",
+                e
+            )));
+        }
+    };
+
+    let calling_ae = forwarder_config
+        .calling_ae_title
+        .clone()
+        .unwrap_or_else(|| config.dicom_store_scp.ae_title.clone());
+    let worker = worker::ForwardWorker::new(
+        db.clone(),
+        &config.dicom_nodes,
+        &forwarder_config,
+        calling_ae,
+    );
+    tokio::spawn(worker.run());
+    tokio::spawn(store_listener::store_listener(
+        config.clone(),
+        rules,
+        db.clone(),
+    ));
+
+    let state = ForwarderState {
+        log: log.clone(),
+        db,
+    };
+    info!(
+        log,
+        "Starting forwarder api at {}:{}", forwarder_config.http_host, forwarder_config.http_port
+    );
+    HttpServer::new(move || {
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .service(forward_controller::list_forward_tasks)
+            .service(forward_controller::requeue_forward_task)
+    })
+    .bind((forwarder_config.http_host.as_str(), forwarder_config.http_port))?
+    .run()
+    .await
+}
@@ -0,0 +1,193 @@
+//! 转发规则匹配
+
+use common::server_config::{DicomNodeConfig, ForwardRule};
+use regex::Regex;
+
+/// 规则匹配所需的实例信息, 来自 DicomStoreMeta 及文件头
+#[derive(Debug, Clone, Default)]
+pub struct ForwardContext {
+    pub tenant_id: String,
+    pub source_ae: String,
+    pub modality: Option<String>,
+    pub sop_class_uid: Option<String>,
+    pub body_part: Option<String>,
+    pub study_description: Option<String>,
+    pub series_description: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CompiledRule {
+    rule: ForwardRule,
+    description_regex: Option<Regex>,
+}
+
+/// 列表为空表示不限制, 否则值必须存在且在列表中(忽略大小写)
+fn match_list(list: &[String], value: Option<&str>) -> bool {
+    if list.is_empty() {
+        return true;
+    }
+    match value {
+        Some(value) => list
+            .iter()
+            .any(|item| item.trim().eq_ignore_ascii_case(value.trim())),
+        None => false,
+    }
+}
+
+impl CompiledRule {
+    pub fn compile(rule: ForwardRule) -> Result<Self, regex::Error> {
+        let description_regex = match &rule.description_regex {
+            Some(pattern) if !pattern.is_empty() => Some(Regex::new(pattern)?),
+            _ => None,
+        };
+        Ok(CompiledRule {
+            rule,
+            description_regex,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.rule.name
+    }
+
+    pub fn destinations(&self) -> &[String] {
+        &self.rule.destinations
+    }
+
+    pub fn matches(&self, ctx: &ForwardContext) -> bool {
+        let rule = &self.rule;
+        if !match_list(&rule.tenant_ids, Some(&ctx.tenant_id))
+            || !match_list(&rule.source_aes, Some(&ctx.source_ae))
+            || !match_list(&rule.modalities, ctx.modality.as_deref())
+            || !match_list(&rule.sop_classes, ctx.sop_class_uid.as_deref())
+            || !match_list(&rule.body_parts, ctx.body_part.as_deref())
+        {
+            return false;
+        }
+        match &self.description_regex {
+            None => true,
+            Some(regex) => [&ctx.study_description, &ctx.series_description]
+                .iter()
+                .filter_map(|v| v.as_deref())
+                .any(|desc| regex.is_match(desc)),
+        }
+    }
+}
+
+/// 编译规则并校验目标节点均已在 dicom_nodes 中配置
+pub fn compile_rules(
+    rules: &[ForwardRule],
+    nodes: &[DicomNodeConfig],
+) -> Result<Vec<CompiledRule>, String> {
+    let mut compiled = Vec::with_capacity(rules.len());
+    for rule in rules {
+        for destination in &rule.destinations {
+            if !nodes.iter().any(|node| &node.name == destination) {
+                return Err(format!(
+                    "forward rule {}: unknown destination {}",
+                    rule.name, destination
+                ));
+            }
+        }
+        let rule = CompiledRule::compile(rule.clone())
+            .map_err(|e| format!("forward rule {}: invalid regex: {}", rule.name, e))?;
+        compiled.push(rule);
+    }
+    Ok(compiled)
+}
+
+/// 返回 (规则名称, 目标节点) 列表, 同一目标只转发一次
+pub fn match_rules<'a>(rules: &'a [CompiledRule], ctx: &ForwardContext) -> Vec<(&'a str, &'a str)> {
+    let mut matched: Vec<(&str, &str)> = vec![];
+    for rule in rules.iter().filter(|rule| rule.matches(ctx)) {
+        for destination in rule.destinations() {
+            if !matched.iter().any(|(_, d)| *d == destination.as_str()) {
+                matched.push((rule.name(), destination.as_str()));
+            }
+        }
+    }
+    matched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(name: &str, destinations: &[&str]) -> ForwardRule {
+        ForwardRule {
+            name: name.to_string(),
+            destinations: destinations.iter().map(|s| s.to_string()).collect(),
+            tenant_ids: vec![],
+            source_aes: vec![],
+            modalities: vec![],
+            sop_classes: vec![],
+            body_parts: vec![],
+            description_regex: None,
+        }
+    }
+
+    fn node(name: &str) -> DicomNodeConfig {
+        DicomNodeConfig {
+            name: name.to_string(),
+            ae_title: name.to_uppercase(),
+            host: "127.0.0.1".to_string(),
+            port: 104,
+            max_concurrency: 2,
+        }
+    }
+
+    fn ct_context() -> ForwardContext {
+        ForwardContext {
+            tenant_id: "tenant1".to_string(),
+            source_ae: "CT01".to_string(),
+            modality: Some("CT".to_string()),
+            sop_class_uid: Some("1.2.840.10008.5.1.4.1.1.2".to_string()),
+            body_part: Some("CHEST".to_string()),
+            study_description: Some("CT Chest Low Dose".to_string()),
+            series_description: None,
+        }
+    }
+
+    #[test]
+    fn test_rule_matches() {
+        let mut ai = rule("lung-ai", &["ai"]);
+        ai.modalities = vec!["ct".to_string()];
+        ai.body_parts = vec!["CHEST".to_string()];
+        ai.description_regex = Some("(?i)low dose".to_string());
+        let ai = CompiledRule::compile(ai).unwrap();
+        assert!(ai.matches(&ct_context()));
+
+        let mut mr = ct_context();
+        mr.modality = Some("MR".to_string());
+        assert!(!ai.matches(&mr));
+
+        let mut no_body_part = ct_context();
+        no_body_part.body_part = None;
+        assert!(!ai.matches(&no_body_part));
+
+        let mut tenant_only = rule("tenant2", &["archive"]);
+        tenant_only.tenant_ids = vec!["tenant2".to_string()];
+        let tenant_only = CompiledRule::compile(tenant_only).unwrap();
+        assert!(!tenant_only.matches(&ct_context()));
+    }
+
+    #[test]
+    fn test_match_rules_dedup_destinations() {
+        let rules = compile_rules(
+            &[rule("all", &["archive", "ai"]), rule("again", &["archive"])],
+            &[node("archive"), node("ai")],
+        )
+        .unwrap();
+        let matched = match_rules(&rules, &ct_context());
+        assert_eq!(matched, vec![("all", "archive"), ("all", "ai")]);
+    }
+
+    #[test]
+    fn test_compile_rules_errors() {
+        assert!(compile_rules(&[rule("r", &["missing"])], &[node("archive")]).is_err());
+
+        let mut bad_regex = rule("r", &["archive"]);
+        bad_regex.description_regex = Some("(".to_string());
+        assert!(compile_rules(&[bad_regex], &[node("archive")]).is_err());
+    }
+}
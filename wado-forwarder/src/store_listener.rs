@@ -0,0 +1,179 @@
+//! 监听 storage_queue, 对新接收的实例进行规则匹配并生成转发任务
+
+use crate::rules::{CompiledRule, ForwardContext, match_rules};
+use common::dicom_utils::get_text_value;
+use common::server_config::AppConfig;
+use common::storage_backend::dicom_backend;
+use common::utils::get_logger;
+use database::dicom_dbprovider::{DbProvider, current_time};
+use database::dicom_dbtype::{BoundedString, FixedLengthString};
+use database::dicom_meta::{DicomForwardTask, DicomStoreMeta};
+use dicom_dictionary_std::tags;
+use dicom_object::OpenFileOptions;
+use futures::StreamExt;
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::{ClientConfig, Message};
+use slog::{error, info, o, warn};
+use std::sync::Arc;
+
+/// 读取文件头补充规则匹配需要的标签, 文件无法读取时只使用收图记录中的信息
+pub async fn make_context(store_meta: &DicomStoreMeta) -> ForwardContext {
+    let mut ctx = ForwardContext {
+        tenant_id: store_meta.tenant_id.as_str().to_string(),
+        source_ae: store_meta.source_ae.as_str().to_string(),
+        ..Default::default()
+    };
+    let Ok(local_file) = dicom_backend().local_file(store_meta.file_path.as_str()).await else {
+        return ctx;
+    };
+    if let Ok(obj) = OpenFileOptions::new()
+        .read_until(tags::PIXEL_DATA)
+        .open_file(local_file.path())
+    {
+        ctx.sop_class_uid = Some(
+            obj.meta()
+                .media_storage_sop_class_uid
+                .trim_end_matches('\0')
+                .to_string(),
+        );
+        ctx.modality = get_text_value(&obj, tags::MODALITY);
+        ctx.body_part = get_text_value(&obj, tags::BODY_PART_EXAMINED);
+        ctx.study_description = get_text_value(&obj, tags::STUDY_DESCRIPTION);
+        ctx.series_description = get_text_value(&obj, tags::SERIES_DESCRIPTION);
+    }
+    ctx
+}
+
+/// 同一条收图消息被重复消费时生成相同的 task_id, 保存时自动去重
+pub fn make_task_id(trace_id: &str, rule_name: &str, destination: &str) -> String {
+    let digest = format!(
+        "{:x}",
+        md5::compute(format!("{}|{}|{}", trace_id, rule_name, destination))
+    );
+    format!(
+        "{}-{}-{}-{}-{}",
+        &digest[0..8],
+        &digest[8..12],
+        &digest[12..16],
+        &digest[16..20],
+        &digest[20..32]
+    )
+}
+
+pub async fn make_tasks(
+    rules: &[CompiledRule],
+    store_meta: &DicomStoreMeta,
+) -> Vec<DicomForwardTask> {
+    let ctx = make_context(store_meta).await;
+    let now = current_time();
+    match_rules(rules, &ctx)
+        .into_iter()
+        .map(|(rule_name, destination)| DicomForwardTask {
+            task_id: FixedLengthString::make(make_task_id(
+                store_meta.trace_id.as_str(),
+                rule_name,
+                destination,
+            )),
+            tenant_id: store_meta.tenant_id.clone(),
+            rule_name: BoundedString::make_str(rule_name),
+            destination: BoundedString::make_str(destination),
+            study_uid: store_meta.study_uid.clone(),
+            series_uid: store_meta.series_uid.clone(),
+            sop_uid: store_meta.sop_uid.clone(),
+            file_path: store_meta.file_path.clone(),
+            status: BoundedString::make_str(DicomForwardTask::STATUS_PENDING),
+            retry_times: 0,
+            last_error: None,
+            next_retry_time: now,
+            created_time: now,
+            updated_time: now,
+        })
+        .collect()
+}
+
+pub async fn store_listener(
+    config: AppConfig,
+    rules: Arc<Vec<CompiledRule>>,
+    db: Arc<dyn DbProvider + Send + Sync>,
+) {
+    let rlogger = get_logger();
+    let logger = rlogger.new(o!("wado-forwarder"=>"store_listener"));
+
+    // 使用独立的消费组, 与 wado-consumer 各自完整消费 storage_queue
+    let group_id = format!("{}-forwarder", config.message_queue.consumer_group_id);
+    let consumer: StreamConsumer = match ClientConfig::new()
+        .set("group.id", group_id.as_str())
+        .set("bootstrap.servers", config.kafka.brokers.as_str())
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", "earliest")
+        .set("session.timeout.ms", "6000")
+        .set("enable.partition.eof", "false")
+        .create()
+    {
+        Ok(consumer) => consumer,
+        Err(e) => {
+            error!(logger, "Failed to create forwarder consumer: {}", e);
+            return;
+        }
+    };
+
+    let topic = config.message_queue.topic_main.as_str();
+    if let Err(e) = consumer.subscribe(&[topic]) {
+        error!(logger, "Failed to subscribe to topic {}: {}", topic, e);
+        return;
+    }
+    info!(logger, "Successfully subscribed to topic: {}", topic);
+
+    let mut message_stream = consumer.stream();
+    while let Some(result) = message_stream.next().await {
+        let message = match result {
+            Ok(message) => message,
+            Err(e) => {
+                error!(logger, "Error receiving message: {}", e);
+                continue;
+            }
+        };
+        match message
+            .payload()
+            .map(serde_json::from_slice::<DicomStoreMeta>)
+        {
+            Some(Ok(store_meta)) => {
+                let tasks = make_tasks(&rules, &store_meta).await;
+                if !tasks.is_empty() {
+                    if let Err(e) = db.save_forward_tasks(&tasks).await {
+                        // 不提交偏移量, 重启或再均衡后重新消费
+                        error!(
+                            logger,
+                            "Failed to save forward tasks for {}: {}", store_meta.sop_uid, e
+                        );
+                        continue;
+                    }
+                    info!(
+                        logger,
+                        "Created {} forward tasks for {}",
+                        tasks.len(),
+                        store_meta.sop_uid
+                    );
+                }
+            }
+            Some(Err(e)) => warn!(logger, "Failed to deserialize message: {}", e),
+            None => warn!(logger, "Received message with no payload"),
+        }
+        if let Err(e) = consumer.commit_message(&message, CommitMode::Async) {
+            error!(logger, "Failed to commit message: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_make_task_id() {
+        let id = make_task_id("trace-1", "archive-all", "archive");
+        assert_eq!(id.len(), 36);
+        assert_eq!(id, make_task_id("trace-1", "archive-all", "archive"));
+        assert_ne!(id, make_task_id("trace-1", "archive-all", "ai"));
+    }
+}
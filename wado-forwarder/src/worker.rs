@@ -0,0 +1,364 @@
+//! 转发任务执行: 从数据库领取到期任务, 按目标节点限制并发, 通过 C-STORE 发送.
+//! 同一检查发往同一目标的任务共用一个关联.
+
+use chrono::{Duration as ChronoDuration, NaiveDateTime};
+use common::dicom_scu;
+use common::server_config::{DicomNodeConfig, ForwarderConfig};
+use common::storage_tier::tiered_storage;
+use common::utils::get_logger;
+use database::dicom_dbprovider::{DbProvider, current_time};
+use database::dicom_dbtype::BoundedString;
+use database::dicom_meta::DicomForwardTask;
+use slog::{error, info, o, warn};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+/// 每轮最多领取的任务数
+const MAX_CLAIM_PER_ROUND: i64 = 100;
+/// 处于 RUNNING 超过该时间未更新的任务视为中断, 重新领取
+const STALE_RUNNING_MINUTES: i64 = 30;
+/// 发送期间按该间隔刷新任务的 updated_time, 必须远小于 STALE_RUNNING_MINUTES
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const IDLE_SLEEP: Duration = Duration::from_secs(2);
+
+struct Destination {
+    node: DicomNodeConfig,
+    permits: Arc<Semaphore>,
+}
+
+pub struct ForwardWorker {
+    db: Arc<dyn DbProvider + Send + Sync>,
+    destinations: HashMap<String, Destination>,
+    /// 所有目标节点并发数之和, 避免领取的任务长时间排队
+    capacity: Arc<Semaphore>,
+    calling_ae: String,
+    max_retries: i32,
+    retry_interval_secs: u64,
+}
+
+/// 根据发送结果更新任务状态, 失败时按指数退避安排下次重试
+pub fn apply_result(
+    mut task: DicomForwardTask,
+    result: Result<(), String>,
+    max_retries: i32,
+    retry_interval_secs: u64,
+    now: NaiveDateTime,
+) -> DicomForwardTask {
+    task.updated_time = now;
+    match result {
+        Ok(()) => {
+            task.status = BoundedString::make_str(DicomForwardTask::STATUS_SUCCESS);
+            task.last_error = None;
+        }
+        Err(e) => {
+            task.retry_times += 1;
+            task.last_error = Some(BoundedString::make(
+                e.chars().take(128).collect::<String>(),
+            ));
+            if task.retry_times >= max_retries {
+                task.status = BoundedString::make_str(DicomForwardTask::STATUS_FAILED);
+            } else {
+                let backoff = retry_interval_secs.saturating_mul(1 << (task.retry_times - 1).min(10));
+                task.status = BoundedString::make_str(DicomForwardTask::STATUS_PENDING);
+                task.next_retry_time = now + ChronoDuration::seconds(backoff as i64);
+            }
+        }
+    }
+    task
+}
+
+/// 按 (目标节点, 租户, 检查) 分组, 保持领取顺序
+pub fn group_by_study(tasks: Vec<DicomForwardTask>) -> Vec<Vec<DicomForwardTask>> {
+    let mut groups: Vec<Vec<DicomForwardTask>> = vec![];
+    for task in tasks {
+        let group = groups.iter_mut().find(|group| {
+            let first = &group[0];
+            first.destination == task.destination
+                && first.tenant_id == task.tenant_id
+                && first.study_uid == task.study_uid
+        });
+        match group {
+            Some(group) => group.push(task),
+            None => groups.push(vec![task]),
+        }
+    }
+    groups
+}
+
+/// 执行 work, 完成前每隔 interval 调用一次 beat
+pub async fn with_heartbeat<T, F: Future<Output = ()>>(
+    work: impl Future<Output = T>,
+    interval: Duration,
+    mut beat: impl FnMut() -> F,
+) -> T {
+    tokio::pin!(work);
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    loop {
+        tokio::select! {
+            result = &mut work => return result,
+            _ = ticker.tick() => beat().await,
+        }
+    }
+}
+
+impl ForwardWorker {
+    pub fn new(
+        db: Arc<dyn DbProvider + Send + Sync>,
+        nodes: &[DicomNodeConfig],
+        config: &ForwarderConfig,
+        calling_ae: String,
+    ) -> Arc<Self> {
+        let destinations: HashMap<String, Destination> = nodes
+            .iter()
+            .map(|node| {
+                (
+                    node.name.clone(),
+                    Destination {
+                        node: node.clone(),
+                        permits: Arc::new(Semaphore::new(node.max_concurrency.max(1))),
+                    },
+                )
+            })
+            .collect();
+        let capacity = destinations
+            .values()
+            .map(|d| d.node.max_concurrency.max(1))
+            .sum::<usize>()
+            .max(1);
+        Arc::new(ForwardWorker {
+            db,
+            destinations,
+            capacity: Arc::new(Semaphore::new(capacity)),
+            calling_ae,
+            max_retries: config.max_retries.max(1),
+            retry_interval_secs: config.retry_interval_secs,
+        })
+    }
+
+    pub async fn run(self: Arc<Self>) {
+        let rlogger = get_logger();
+        let logger = rlogger.new(o!("wado-forwarder"=>"worker"));
+        info!(logger, "Forward worker started, calling AE: {}", self.calling_ae);
+        loop {
+            let available = self.capacity.available_permits() as i64;
+            if available == 0 {
+                tokio::time::sleep(IDLE_SLEEP).await;
+                continue;
+            }
+            let now = current_time();
+            let stale_before = now - ChronoDuration::minutes(STALE_RUNNING_MINUTES);
+            let tasks = match self
+                .db
+                .claim_forward_tasks(now, stale_before, available.min(MAX_CLAIM_PER_ROUND))
+                .await
+            {
+                Ok(tasks) => tasks,
+                Err(e) => {
+                    error!(logger, "Failed to claim forward tasks: {}", e);
+                    tokio::time::sleep(IDLE_SLEEP).await;
+                    continue;
+                }
+            };
+            if tasks.is_empty() {
+                tokio::time::sleep(IDLE_SLEEP).await;
+                continue;
+            }
+            for tasks in group_by_study(tasks) {
+                let Ok(slot) = Arc::clone(&self.capacity).acquire_owned().await else {
+                    return;
+                };
+                let worker = Arc::clone(&self);
+                tokio::spawn(async move {
+                    let _slot = slot;
+                    worker.forward_study(tasks).await;
+                });
+            }
+        }
+    }
+
+    /// 同一检查的任务通过一个关联发送, 每个任务单独记录结果
+    async fn send_study(&self, tasks: &[DicomForwardTask]) -> Vec<Result<(), String>> {
+        let destination_name = tasks[0].destination.as_str();
+        let Some(destination) = self.destinations.get(destination_name) else {
+            let error = format!("destination {} is not configured", destination_name);
+            return tasks.iter().map(|_| Err(error.clone())).collect();
+        };
+        let _permit = destination.permits.acquire().await;
+        let node = destination.node.clone();
+        let calling_ae = self.calling_ae.clone();
+
+        // 重试时文件可能已迁移到其他存储层
+        let mut results: Vec<Option<Result<(), String>>> = Vec::with_capacity(tasks.len());
+        let mut local_files = vec![];
+        for task in tasks {
+            match tiered_storage().local_file(task.file_path.as_str()).await {
+                Ok((_tier, local_file)) => {
+                    results.push(None);
+                    local_files.push(local_file);
+                }
+                Err(e) => results.push(Some(Err(e.to_string()))),
+            }
+        }
+        if !local_files.is_empty() {
+            let sent = tokio::task::spawn_blocking(move || {
+                let file_paths: Vec<String> = local_files
+                    .iter()
+                    .map(|local_file| local_file.path().to_string_lossy().to_string())
+                    .collect();
+                dicom_scu::store_files(&node, &calling_ae, &file_paths)
+            })
+            .await;
+            let sent: Vec<Result<(), String>> = match sent {
+                Ok(sent) => sent
+                    .into_iter()
+                    .map(|result| result.map(|_status| ()).map_err(|e| e.to_string()))
+                    .collect(),
+                Err(e) => vec![Err(format!("forward task panicked: {}", e)); results.len()],
+            };
+            let mut sent = sent.into_iter();
+            for result in results.iter_mut().filter(|result| result.is_none()) {
+                *result = sent.next();
+            }
+        }
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| Err("no C-STORE result".to_string())))
+            .collect()
+    }
+
+    async fn forward_study(&self, tasks: Vec<DicomForwardTask>) {
+        let rlogger = get_logger();
+        let logger = rlogger.new(o!("wado-forwarder"=>"forward"));
+        // 大检查经慢速链路发送可能超过 STALE_RUNNING_MINUTES, 刷新 updated_time 避免被重新领取后重复发送
+        let results = with_heartbeat(self.send_study(&tasks), HEARTBEAT_INTERVAL, || async {
+            let now = current_time();
+            for task in &tasks {
+                let mut task = task.clone();
+                task.updated_time = now;
+                if let Err(e) = self.db.update_forward_task(&task).await {
+                    warn!(logger, "Failed to refresh forward task {}: {}", task.task_id.as_str(), e);
+                }
+            }
+        })
+        .await;
+        for (task, result) in tasks.into_iter().zip(results) {
+            match &result {
+                Ok(()) => info!(
+                    logger,
+                    "Forwarded {} to {} (rule: {})", task.sop_uid, task.destination, task.rule_name
+                ),
+                Err(e) => warn!(
+                    logger,
+                    "Failed to forward {} to {}: {}", task.sop_uid, task.destination, e
+                ),
+            }
+            let task = apply_result(
+                task,
+                result,
+                self.max_retries,
+                self.retry_interval_secs,
+                current_time(),
+            );
+            if let Err(e) = self.db.update_forward_task(&task).await {
+                error!(logger, "Failed to update forward task {}: {}", task.task_id.as_str(), e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use database::dicom_dbtype::FixedLengthString;
+
+    fn make_task(now: NaiveDateTime) -> DicomForwardTask {
+        DicomForwardTask {
+            task_id: FixedLengthString::make_str("00000000-0000-0000-0000-000000000000"),
+            tenant_id: BoundedString::make_str("tenant1"),
+            rule_name: BoundedString::make_str("archive-all"),
+            destination: BoundedString::make_str("archive"),
+            study_uid: BoundedString::make_str("1.2.3"),
+            series_uid: BoundedString::make_str("1.2.3.4"),
+            sop_uid: BoundedString::make_str("1.2.3.4.5"),
+            file_path: BoundedString::make_str("/tmp/1.2.3.4.5.dcm"),
+            status: BoundedString::make_str(DicomForwardTask::STATUS_RUNNING),
+            retry_times: 0,
+            last_error: None,
+            next_retry_time: now,
+            created_time: now,
+            updated_time: now,
+        }
+    }
+
+    #[test]
+    fn test_group_by_study() {
+        let now = current_time();
+        let mut other_study = make_task(now);
+        other_study.study_uid = BoundedString::make_str("1.2.4");
+        let mut other_destination = make_task(now);
+        other_destination.destination = BoundedString::make_str("lung-ai");
+
+        let groups = group_by_study(vec![
+            make_task(now),
+            other_study,
+            make_task(now),
+            other_destination,
+        ]);
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0].len(), 2);
+        assert_eq!(groups[1][0].study_uid.as_str(), "1.2.4");
+        assert_eq!(groups[2][0].destination.as_str(), "lung-ai");
+    }
+
+    #[tokio::test]
+    async fn test_with_heartbeat() {
+        let mut beats = 0;
+        let result = with_heartbeat(
+            async {
+                tokio::time::sleep(Duration::from_millis(250)).await;
+                "sent"
+            },
+            Duration::from_millis(100),
+            || {
+                beats += 1;
+                async {}
+            },
+        )
+        .await;
+        assert_eq!(result, "sent");
+        assert_eq!(beats, 2);
+
+        // 在第一次间隔前完成时不刷新
+        let mut beats = 0;
+        with_heartbeat(async {}, Duration::from_millis(100), || {
+            beats += 1;
+            async {}
+        })
+        .await;
+        assert_eq!(beats, 0);
+    }
+
+    #[test]
+    fn test_apply_result() {
+        let now = current_time();
+
+        let task = apply_result(make_task(now), Ok(()), 3, 60, now);
+        assert_eq!(task.status.as_str(), DicomForwardTask::STATUS_SUCCESS);
+
+        let task = apply_result(make_task(now), Err("refused".to_string()), 3, 60, now);
+        assert_eq!(task.status.as_str(), DicomForwardTask::STATUS_PENDING);
+        assert_eq!(task.retry_times, 1);
+        assert_eq!(task.next_retry_time, now + ChronoDuration::seconds(60));
+
+        // 第二次失败, 退避时间翻倍
+        let task = apply_result(task, Err("refused".to_string()), 3, 60, now);
+        assert_eq!(task.next_retry_time, now + ChronoDuration::seconds(120));
+
+        let task = apply_result(task, Err("refused".to_string()), 3, 60, now);
+        assert_eq!(task.status.as_str(), DicomForwardTask::STATUS_FAILED);
+        assert_eq!(task.last_error.unwrap().as_str(), "refused");
+    }
+}
@@ -0,0 +1,81 @@
+//! 存储与索引对账工具
+//!
+//! 参数未指定时使用配置文件中 gc 的取值, 建议先以 --dry-run 执行并检查报告.
+
+use clap::{Parser, ValueEnum};
+use common::database_factory;
+use common::reconcile::{ReconcileOptions, ReconcileReport, reconcile};
+use common::server_config::{self, MIN_GC_GRACE_HOURS, OrphanAction};
+use std::process::ExitCode;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Orphans {
+    Report,
+    Reindex,
+    Quarantine,
+}
+
+impl From<Orphans> for OrphanAction {
+    fn from(value: Orphans) -> Self {
+        match value {
+            Orphans::Report => OrphanAction::Report,
+            Orphans::Reindex => OrphanAction::Reindex,
+            Orphans::Quarantine => OrphanAction::Quarantine,
+        }
+    }
+}
+
+/// Reconcile stored DICOM files with the database index
+#[derive(Debug, Parser)]
+#[command(version)]
+struct App {
+    /// Only report, do not modify storage or database
+    #[arg(long = "dry-run")]
+    dry_run: bool,
+    /// Action for files without index records
+    #[arg(long = "orphans", value_enum)]
+    orphans: Option<Orphans>,
+    /// Mark index records without files as MISSING
+    #[arg(long = "mark-dangling")]
+    mark_dangling: bool,
+    /// Skip files and records newer than this many hours
+    #[arg(long = "grace-hours")]
+    grace_hours: Option<u64>,
+    /// Number of files or records per database query
+    #[arg(long = "batch-size")]
+    batch_size: Option<i64>,
+}
+
+async fn run(app: App) -> Result<ReconcileReport, Box<dyn std::error::Error>> {
+    let config = server_config::load_config()?;
+    let mut options = ReconcileOptions::from(&config.gc.clone().unwrap_or_default());
+    options.dry_run |= app.dry_run;
+    options.mark_dangling |= app.mark_dangling;
+    if let Some(orphans) = app.orphans {
+        options.orphan_action = orphans.into();
+    }
+    if let Some(hours) = app.grace_hours {
+        // 宽限时间过短会误判正在收图或建立索引的文件
+        options.grace = Duration::from_secs(hours.max(MIN_GC_GRACE_HOURS) * 3600);
+    }
+    if let Some(batch_size) = app.batch_size {
+        options.batch_size = batch_size.max(1);
+    }
+    let db = database_factory::create_db_instance(&config.main_database).await?;
+    reconcile(db.as_ref(), &config, &options).await
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run(App::parse()).await {
+        Ok(report) => {
+            println!("{}", serde_json::to_string_pretty(&report).unwrap_or_default());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Reconcile failed: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! 静态加密密钥管理工具
+//!
+//! 轮换主密钥: 先生成新主密钥, 执行 rotate 重新包装所有数据密钥, 再更新各服务的主密钥配置并重启.
+//! 数据文件只依赖数据密钥, 轮换时无需重新加密.
+
+use clap::{Parser, Subcommand};
+use common::server_config;
+use common::storage_crypto::{KeyStore, decode_master_key, generate_master_key, load_master_key};
+use std::process::ExitCode;
+
+/// Encryption key management
+#[derive(Debug, Parser)]
+#[command(version)]
+struct App {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Print a new random master key (base64)
+    GenerateMasterKey,
+    /// Re-wrap all tenant data keys with a new master key
+    Rotate {
+        /// File containing the new base64 master key
+        #[arg(long = "new-key-file")]
+        new_key_file: String,
+    },
+}
+
+fn rotate(new_key_file: &str) -> Result<usize, Box<dyn std::error::Error>> {
+    let config = server_config::load_config()?;
+    let encryption = config
+        .local_storage
+        .encryption
+        .ok_or("local_storage.encryption is not configured")?;
+    let current = load_master_key(&encryption)?;
+    let new_key = decode_master_key(&std::fs::read_to_string(new_key_file)?)?;
+    if new_key == current {
+        return Err("the new master key is the same as the current one".into());
+    }
+    let store = KeyStore::new(&encryption.key_store_path, current);
+    Ok(store.rotate(&new_key)?)
+}
+
+fn main() -> ExitCode {
+    match App::parse().command {
+        Command::GenerateMasterKey => {
+            println!("{}", generate_master_key());
+            ExitCode::SUCCESS
+        }
+        Command::Rotate { new_key_file } => match rotate(&new_key_file) {
+            Ok(count) => {
+                println!("Re-wrapped {} data keys, update the master key of all services now", count);
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("Key rotation failed: {}", e);
+                ExitCode::FAILURE
+            }
+        },
+    }
+}
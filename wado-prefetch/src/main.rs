@@ -0,0 +1,78 @@
+mod prefetcher;
+mod state_listener;
+
+use common::server_config::DicomNodeConfig;
+use common::utils::setup_logging;
+use common::{database_factory, server_config};
+use database::dicom_dbprovider::DbProvider;
+use slog::{error, info};
+use std::sync::Arc;
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let log = setup_logging("wado-prefetch");
+    let config = match server_config::load_config() {
+        Ok(config) => config,
+        Err(e) => {
+            error!(log, "Error loading config: {:?}", e);
+            return Err(std::io::Error::other(e));
+        }
+    };
+    let prefetch_config = match &config.prefetch {
+        Some(prefetch_config) => prefetch_config.clone(),
+        None => {
+            info!(log, "prefetch is not configured, exit");
+            return Ok(());
+        }
+    };
+
+    let mut sources: Vec<DicomNodeConfig> = vec![];
+    for name in &prefetch_config.sources {
+        match config.dicom_nodes.iter().find(|node| &node.name == name) {
+            Some(node) => sources.push(node.clone()),
+            None => {
+                error!(log, "Unknown prefetch source: {}", name);
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("unknown prefetch source {}", name),
+                ));
+            }
+        }
+    }
+
+    let db = match database_factory::create_db_instance(&config.main_database).await {
+        Ok(db) => db as Arc<dyn DbProvider + Send + Sync>,
+        Err(e) => {
+            error!(log, "create_db_instance error: {:?}", e);
+            return Err(std::io::Error::other(format!(
+                "create_db_instance error: {:?}",
+                e
+            )));
+        }
+    };
+
+    let local_ae = config.dicom_store_scp.ae_title.clone();
+    let calling_ae = prefetch_config
+        .calling_ae_title
+        .clone()
+        .unwrap_or_else(|| local_ae.clone());
+    let move_destination = prefetch_config
+        .move_destination_ae
+        .clone()
+        .unwrap_or(local_ae);
+    info!(
+        log,
+        "Prefetch from {} sources, move destination: {}",
+        sources.len(),
+        move_destination
+    );
+    let prefetcher = prefetcher::Prefetcher::new(
+        db,
+        sources,
+        prefetch_config,
+        calling_ae,
+        move_destination,
+    );
+    state_listener::state_listener(config, prefetcher).await;
+    Ok(())
+}
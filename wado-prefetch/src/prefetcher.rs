@@ -0,0 +1,367 @@
+//! 历史检查预取: C-FIND 查询同一患者的历史检查, 按条件筛选后 C-MOVE 到本地 SCP
+
+use chrono::{Duration as ChronoDuration, Months, NaiveDate};
+use common::dicom_scu;
+use common::dicom_utils::get_text_value;
+use common::server_config::{DicomNodeConfig, PrefetchConfig};
+use common::utils::get_logger;
+use database::dicom_dbprovider::{DbProvider, current_time};
+use database::dicom_dbtype::BoundedString;
+use database::dicom_meta::{DicomPrefetchTask, DicomStateMeta};
+use dicom_core::{DataElement, PrimitiveValue, VR, dicom_value};
+use dicom_dictionary_std::tags;
+use dicom_object::InMemDicomObject;
+use slog::{Logger, error, info, o, warn};
+use std::sync::Arc;
+
+/// RUNNING 超过该时间视为中断, 允许重新预取
+const STALE_RUNNING_MINUTES: i64 = 60;
+
+/// 远程 Q/R 源返回的历史检查
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriorStudy {
+    pub study_uid: String,
+    pub study_date: Option<NaiveDate>,
+    pub modalities: Vec<String>,
+}
+
+/// 预取的起始日期: 触发检查日期往前 years_back 年
+pub fn since_date(study_date: NaiveDate, years_back: u32) -> NaiveDate {
+    study_date
+        .checked_sub_months(Months::new(years_back.saturating_mul(12)))
+        .unwrap_or(NaiveDate::MIN)
+}
+
+pub fn make_study_query(patient_id: &str, since: NaiveDate) -> InMemDicomObject {
+    let date_range = format!("{}-", since.format("%Y%m%d"));
+    InMemDicomObject::from_element_iter([
+        DataElement::new(tags::QUERY_RETRIEVE_LEVEL, VR::CS, dicom_value!(Str, "STUDY")),
+        DataElement::new(tags::PATIENT_ID, VR::LO, dicom_value!(Str, patient_id)),
+        DataElement::new(tags::STUDY_DATE, VR::DA, dicom_value!(Str, date_range)),
+        DataElement::new(tags::STUDY_INSTANCE_UID, VR::UI, PrimitiveValue::Empty),
+        DataElement::new(tags::MODALITIES_IN_STUDY, VR::CS, PrimitiveValue::Empty),
+    ])
+}
+
+pub fn make_series_query(study_uid: &str) -> InMemDicomObject {
+    InMemDicomObject::from_element_iter([
+        DataElement::new(tags::QUERY_RETRIEVE_LEVEL, VR::CS, dicom_value!(Str, "SERIES")),
+        DataElement::new(tags::STUDY_INSTANCE_UID, VR::UI, dicom_value!(Str, study_uid)),
+        DataElement::new(tags::SERIES_INSTANCE_UID, VR::UI, PrimitiveValue::Empty),
+        DataElement::new(tags::BODY_PART_EXAMINED, VR::CS, PrimitiveValue::Empty),
+    ])
+}
+
+fn split_values(value: Option<String>) -> Vec<String> {
+    value
+        .map(|v| {
+            v.split('\\')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn parse_prior_study(obj: &InMemDicomObject) -> Option<PriorStudy> {
+    let study_uid = get_text_value(obj, tags::STUDY_INSTANCE_UID).filter(|s| !s.is_empty())?;
+    let study_date = get_text_value(obj, tags::STUDY_DATE)
+        .and_then(|s| NaiveDate::parse_from_str(s.trim(), "%Y%m%d").ok());
+    Some(PriorStudy {
+        study_uid,
+        study_date,
+        modalities: split_values(get_text_value(obj, tags::MODALITIES_IN_STUDY)),
+    })
+}
+
+/// 列表为空表示不限制, 否则至少有一个值在列表中(忽略大小写)
+fn match_any(filter: &[String], values: &[String]) -> bool {
+    filter.is_empty()
+        || values
+            .iter()
+            .any(|v| filter.iter().any(|f| f.trim().eq_ignore_ascii_case(v)))
+}
+
+/// 排除触发检查并按检查类型过滤, 按检查日期倒序排列
+pub fn select_priors(
+    mut candidates: Vec<PriorStudy>,
+    trigger_study_uid: &str,
+    modalities: &[String],
+) -> Vec<PriorStudy> {
+    candidates.retain(|study| {
+        study.study_uid != trigger_study_uid && match_any(modalities, &study.modalities)
+    });
+    candidates.sort_by_key(|study| std::cmp::Reverse(study.study_date));
+    candidates.dedup_by(|a, b| a.study_uid == b.study_uid);
+    candidates
+}
+
+pub struct Prefetcher {
+    db: Arc<dyn DbProvider + Send + Sync>,
+    sources: Vec<DicomNodeConfig>,
+    config: PrefetchConfig,
+    calling_ae: String,
+    move_destination: String,
+}
+
+impl Prefetcher {
+    pub fn new(
+        db: Arc<dyn DbProvider + Send + Sync>,
+        sources: Vec<DicomNodeConfig>,
+        config: PrefetchConfig,
+        calling_ae: String,
+        move_destination: String,
+    ) -> Arc<Self> {
+        Arc::new(Prefetcher {
+            db,
+            sources,
+            config,
+            calling_ae,
+            move_destination,
+        })
+    }
+
+    /// 对触发检查所属患者, 依次从各 Q/R 源预取历史检查
+    pub async fn prefetch(&self, state: &DicomStateMeta) {
+        let rlogger = get_logger();
+        let logger = rlogger.new(o!("wado-prefetch"=>"prefetch"));
+        let since = since_date(state.study_date, self.config.years_back);
+        for source in &self.sources {
+            if let Err(e) = self.prefetch_from(&logger, source, state, since).await {
+                warn!(
+                    logger,
+                    "Prefetch from {} for patient {} failed: {}", source.name, state.patient_id, e
+                );
+            }
+        }
+    }
+
+    async fn find(
+        &self,
+        source: &DicomNodeConfig,
+        query: InMemDicomObject,
+    ) -> Result<Vec<InMemDicomObject>, String> {
+        let node = source.clone();
+        let calling_ae = self.calling_ae.clone();
+        tokio::task::spawn_blocking(move || dicom_scu::find(&node, &calling_ae, &query))
+            .await
+            .map_err(|e| format!("C-FIND task panicked: {}", e))?
+            .map_err(|e| e.to_string())
+    }
+
+    /// 通过 SERIES 级别查询检查部位, 检查中任一序列匹配即可
+    async fn match_body_part(&self, source: &DicomNodeConfig, study_uid: &str) -> bool {
+        match self.find(source, make_series_query(study_uid)).await {
+            Ok(series) => {
+                let body_parts: Vec<String> = series
+                    .iter()
+                    .filter_map(|obj| get_text_value(obj, tags::BODY_PART_EXAMINED))
+                    .collect();
+                match_any(&self.config.body_parts, &body_parts)
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// 已存在于本地的检查不再预取
+    async fn exists_locally(&self, tenant_id: &str, study_uid: &str) -> bool {
+        matches!(
+            self.db.get_state_metaes(tenant_id, study_uid).await,
+            Ok(local) if !local.is_empty()
+        )
+    }
+
+    async fn prefetch_from(
+        &self,
+        logger: &Logger,
+        source: &DicomNodeConfig,
+        state: &DicomStateMeta,
+        since: NaiveDate,
+    ) -> Result<(), String> {
+        let tenant_id = state.tenant_id.as_str();
+        let results = self
+            .find(source, make_study_query(state.patient_id.as_str(), since))
+            .await?;
+        let candidates = select_priors(
+            results.iter().filter_map(parse_prior_study).collect(),
+            state.study_uid.as_str(),
+            &self.config.modalities,
+        );
+
+        let mut fetched = 0;
+        for prior in candidates {
+            if fetched >= self.config.max_priors {
+                break;
+            }
+            if !self.config.body_parts.is_empty()
+                && !self.match_body_part(source, &prior.study_uid).await
+            {
+                continue;
+            }
+            if self.exists_locally(tenant_id, &prior.study_uid).await {
+                continue;
+            }
+            // 已预取成功或正由其他进程预取的检查不计入 max_priors
+            let Some(task) = self.claim_prior(logger, source, state, &prior).await else {
+                continue;
+            };
+            fetched += 1;
+            self.move_prior(logger, source, &prior, task).await;
+        }
+        Ok(())
+    }
+
+    /// 原子地领取预取任务, 领取成功时返回 RUNNING 状态的任务
+    async fn claim_prior(
+        &self,
+        logger: &Logger,
+        source: &DicomNodeConfig,
+        state: &DicomStateMeta,
+        prior: &PriorStudy,
+    ) -> Option<DicomPrefetchTask> {
+        let now = current_time();
+        let task = DicomPrefetchTask {
+            tenant_id: state.tenant_id.clone(),
+            source: BoundedString::make_str(&source.name),
+            study_uid: BoundedString::make_str(&prior.study_uid),
+            patient_id: state.patient_id.clone(),
+            trigger_study_uid: state.study_uid.clone(),
+            study_date: prior.study_date,
+            modalities: Some(BoundedString::make(
+                prior.modalities.join("\\").chars().take(64).collect::<String>(),
+            )),
+            status: BoundedString::make_str(DicomPrefetchTask::STATUS_RUNNING),
+            completed: 0,
+            failed: 0,
+            last_error: None,
+            created_time: now,
+            updated_time: now,
+        };
+        let stale_before = now - ChronoDuration::minutes(STALE_RUNNING_MINUTES);
+        match self.db.claim_prefetch_task(&task, stale_before).await {
+            Ok(true) => Some(task),
+            Ok(false) => None,
+            Err(e) => {
+                error!(logger, "Failed to claim prefetch task {}: {}", prior.study_uid, e);
+                None
+            }
+        }
+    }
+
+    async fn move_prior(
+        &self,
+        logger: &Logger,
+        source: &DicomNodeConfig,
+        prior: &PriorStudy,
+        mut task: DicomPrefetchTask,
+    ) {
+        let node = source.clone();
+        let calling_ae = self.calling_ae.clone();
+        let destination = self.move_destination.clone();
+        let study_uid = prior.study_uid.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            dicom_scu::move_study(&node, &calling_ae, &destination, &study_uid)
+        })
+        .await
+        .map_err(|e| format!("C-MOVE task panicked: {}", e))
+        .and_then(|r| r.map_err(|e| e.to_string()));
+
+        task.updated_time = current_time();
+        match result {
+            Ok(moved) => {
+                info!(
+                    logger,
+                    "Prefetched study {} from {}: completed {}, failed {}",
+                    prior.study_uid,
+                    source.name,
+                    moved.completed,
+                    moved.failed
+                );
+                task.completed = moved.completed as i32;
+                task.failed = moved.failed as i32;
+                task.status = BoundedString::make_str(if moved.failed == 0 {
+                    DicomPrefetchTask::STATUS_SUCCESS
+                } else {
+                    DicomPrefetchTask::STATUS_FAILED
+                });
+            }
+            Err(e) => {
+                warn!(
+                    logger,
+                    "Failed to prefetch study {} from {}: {}", prior.study_uid, source.name, e
+                );
+                task.status = BoundedString::make_str(DicomPrefetchTask::STATUS_FAILED);
+                task.last_error = Some(BoundedString::make(
+                    e.chars().take(128).collect::<String>(),
+                ));
+            }
+        }
+        if let Err(e) = self.db.save_prefetch_task(&task).await {
+            error!(logger, "Failed to update prefetch task {}: {}", prior.study_uid, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prior(study_uid: &str, date: &str, modalities: &[&str]) -> PriorStudy {
+        PriorStudy {
+            study_uid: study_uid.to_string(),
+            study_date: NaiveDate::parse_from_str(date, "%Y%m%d").ok(),
+            modalities: modalities.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_since_date() {
+        let date = NaiveDate::from_ymd_opt(2024, 2, 29).unwrap();
+        assert_eq!(
+            since_date(date, 3),
+            NaiveDate::from_ymd_opt(2021, 2, 28).unwrap()
+        );
+        assert_eq!(since_date(date, 0), date);
+    }
+
+    #[test]
+    fn test_parse_prior_study() {
+        let obj = InMemDicomObject::from_element_iter([
+            DataElement::new(tags::STUDY_INSTANCE_UID, VR::UI, dicom_value!(Str, "1.2.3")),
+            DataElement::new(tags::STUDY_DATE, VR::DA, dicom_value!(Str, "20230115")),
+            DataElement::new(
+                tags::MODALITIES_IN_STUDY,
+                VR::CS,
+                dicom_value!(Strs, ["CT".to_string(), "SR".to_string()]),
+            ),
+        ]);
+        assert_eq!(
+            parse_prior_study(&obj),
+            Some(prior("1.2.3", "20230115", &["CT", "SR"]))
+        );
+
+        let no_uid = InMemDicomObject::from_element_iter([DataElement::new(
+            tags::STUDY_DATE,
+            VR::DA,
+            dicom_value!(Str, "20230115"),
+        )]);
+        assert_eq!(parse_prior_study(&no_uid), None);
+    }
+
+    #[test]
+    fn test_select_priors() {
+        let candidates = vec![
+            prior("1", "20200101", &["CT"]),
+            prior("2", "20230101", &["MR"]),
+            prior("3", "20220101", &["CT", "SR"]),
+            prior("trigger", "20240101", &["CT"]),
+        ];
+        let selected = select_priors(candidates.clone(), "trigger", &["ct".to_string()]);
+        let uids: Vec<&str> = selected.iter().map(|s| s.study_uid.as_str()).collect();
+        assert_eq!(uids, vec!["3", "1"]);
+
+        let selected = select_priors(candidates, "trigger", &[]);
+        assert_eq!(selected.len(), 3);
+        assert_eq!(selected[0].study_uid, "2");
+    }
+}
@@ -0,0 +1,133 @@
+//! 监听 dicom_state_queue, 对新检查触发历史检查预取
+
+use crate::prefetcher::Prefetcher;
+use common::server_config::AppConfig;
+use common::utils::get_logger;
+use database::dicom_meta::DicomStateMeta;
+use futures::StreamExt;
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::{ClientConfig, Message};
+use slog::{error, info, o, warn};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+
+/// 同一患者在该时间内只触发一次预取(同一检查的每个序列都会产生一条状态消息)
+const DEDUP_WINDOW: Duration = Duration::from_secs(3600);
+const MAX_CONCURRENT_PATIENTS: usize = 4;
+
+/// 记录最近触发过预取的患者, 窗口内重复的触发返回 false
+pub struct RecentPatients {
+    window: Duration,
+    seen: Mutex<HashMap<(String, String), Instant>>,
+}
+
+impl RecentPatients {
+    pub fn new(window: Duration) -> Self {
+        RecentPatients {
+            window,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn check_and_mark(&self, tenant_id: &str, patient_id: &str, now: Instant) -> bool {
+        let Ok(mut seen) = self.seen.lock() else {
+            return true;
+        };
+        seen.retain(|_, at| now.duration_since(*at) < self.window);
+        let key = (tenant_id.to_string(), patient_id.to_string());
+        if seen.contains_key(&key) {
+            return false;
+        }
+        seen.insert(key, now);
+        true
+    }
+}
+
+pub async fn state_listener(config: AppConfig, prefetcher: Arc<Prefetcher>) {
+    let rlogger = get_logger();
+    let logger = rlogger.new(o!("wado-prefetch"=>"state_listener"));
+
+    // 使用独立的消费组, 不影响其它服务消费 dicom_state_queue
+    let group_id = format!("{}-prefetch", config.message_queue.consumer_group_id);
+    let consumer: StreamConsumer = match ClientConfig::new()
+        .set("group.id", group_id.as_str())
+        .set("bootstrap.servers", config.kafka.brokers.as_str())
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", "latest")
+        .set("session.timeout.ms", "6000")
+        .set("enable.partition.eof", "false")
+        .create()
+    {
+        Ok(consumer) => consumer,
+        Err(e) => {
+            error!(logger, "Failed to create prefetch consumer: {}", e);
+            return;
+        }
+    };
+
+    let topic = config.message_queue.topic_dicom_state.as_str();
+    if let Err(e) = consumer.subscribe(&[topic]) {
+        error!(logger, "Failed to subscribe to topic {}: {}", topic, e);
+        return;
+    }
+    info!(logger, "Successfully subscribed to topic: {}", topic);
+
+    let recent = RecentPatients::new(DEDUP_WINDOW);
+    let permits = Arc::new(Semaphore::new(MAX_CONCURRENT_PATIENTS));
+    let mut message_stream = consumer.stream();
+    while let Some(result) = message_stream.next().await {
+        let message = match result {
+            Ok(message) => message,
+            Err(e) => {
+                error!(logger, "Error receiving message: {}", e);
+                continue;
+            }
+        };
+        match message
+            .payload()
+            .map(serde_json::from_slice::<DicomStateMeta>)
+        {
+            Some(Ok(state)) => {
+                if !state.patient_id.as_str().is_empty()
+                    && recent.check_and_mark(
+                        state.tenant_id.as_str(),
+                        state.patient_id.as_str(),
+                        Instant::now(),
+                    )
+                {
+                    let Ok(permit) = Arc::clone(&permits).acquire_owned().await else {
+                        return;
+                    };
+                    let prefetcher = Arc::clone(&prefetcher);
+                    tokio::spawn(async move {
+                        let _permit = permit;
+                        prefetcher.prefetch(&state).await;
+                    });
+                }
+            }
+            Some(Err(e)) => warn!(logger, "Failed to deserialize message: {}", e),
+            None => warn!(logger, "Received message with no payload"),
+        }
+        // 预取是尽力而为的, 失败记录在 dicom_prefetch_task 中, 不重新消费
+        if let Err(e) = consumer.commit_message(&message, CommitMode::Async) {
+            error!(logger, "Failed to commit message: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent_patients() {
+        let recent = RecentPatients::new(Duration::from_secs(60));
+        let now = Instant::now();
+        assert!(recent.check_and_mark("tenant1", "P001", now));
+        assert!(!recent.check_and_mark("tenant1", "P001", now + Duration::from_secs(30)));
+        assert!(recent.check_and_mark("tenant2", "P001", now));
+        assert!(recent.check_and_mark("tenant1", "P001", now + Duration::from_secs(61)));
+    }
+}
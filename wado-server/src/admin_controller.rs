@@ -0,0 +1,216 @@
+use crate::AppState;
+use crate::common_utils::admin_tenants;
+use actix_web::{HttpRequest, HttpResponse, Responder, get, post, web};
+use chrono::NaiveDate;
+use database::dicom_dbprovider::current_time;
+use serde::Deserialize;
+use slog::{error, info, warn};
+use std::cmp::Reverse;
+
+/// 调用方可以访问的租户. 请求指定租户时必须在调用方的租户范围内, 未指定时为调用方的全部租户.
+/// 未配置 admin 或调用方没有可管理的租户时返回 403
+fn scoped_tenants(
+    req: &HttpRequest,
+    app_state: &AppState,
+    requested: Option<&str>,
+) -> Result<Vec<String>, HttpResponse> {
+    let Some(admin) = &app_state.config.admin else {
+        return Err(HttpResponse::Forbidden().body("admin API is not configured"));
+    };
+    let tenants = admin_tenants(req, admin);
+    match requested {
+        Some(tenant_id) if tenants.iter().any(|t| t == tenant_id) => Ok(vec![tenant_id.to_string()]),
+        Some(tenant_id) => {
+            warn!(app_state.log, "tenant {} is not accessible, caller tenants: {:?}", tenant_id, tenants);
+            Err(HttpResponse::Forbidden().body(format!("tenant {} is not accessible", tenant_id)))
+        }
+        None if tenants.is_empty() => {
+            Err(HttpResponse::Forbidden().body("no tenant is accessible"))
+        }
+        None => Ok(tenants),
+    }
+}
+
+/// 返回所有远程 DICOM 节点的最近一次 C-ECHO 结果
+#[utoipa::path(
+    get,
+    responses(
+        (status = 200, description = "DICOM node statuses"),
+        (status = 403, description = "Admin API is not configured"),
+    ),
+    tag = "ADMIN",
+    description = "List configured DICOM nodes with C-ECHO status"
+)]
+#[get("/dicom-nodes")]
+pub async fn list_dicom_nodes(app_state: web::Data<AppState>) -> impl Responder {
+    if app_state.config.admin.is_none() {
+        return HttpResponse::Forbidden().body("admin API is not configured");
+    }
+    HttpResponse::Ok().json(app_state.node_monitor.statuses())
+}
+
+/// 立即对指定节点执行 C-ECHO, 用于排查连通性问题
+#[utoipa::path(
+    post,
+    params(
+        ("name" = String, Path, description = "DICOM node name in dicom_nodes"),
+    ),
+    responses(
+        (status = 200, description = "C-ECHO finished, see reachable/last_error"),
+        (status = 403, description = "Admin API is not configured"),
+        (status = 404, description = "DICOM node not found"),
+    ),
+    tag = "ADMIN",
+    description = "Perform C-ECHO against a DICOM node on demand"
+)]
+#[post("/dicom-nodes/{name}/echo")]
+pub async fn echo_dicom_node(
+    app_state: web::Data<AppState>,
+    name: web::Path<String>,
+) -> impl Responder {
+    if app_state.config.admin.is_none() {
+        return HttpResponse::Forbidden().body("admin API is not configured");
+    }
+    let name = name.into_inner();
+    info!(app_state.log, "echo_dicom_node: {}", name);
+    match app_state.node_monitor.echo(&name).await {
+        Some(status) => HttpResponse::Ok().json(status),
+        None => HttpResponse::NotFound().body(format!("DICOM node {} not found", name)),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct FixityFailureQuery {
+    pub tenant_id: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// 返回定期校验发现的损坏(MISMATCH)或丢失(MISSING)文件
+#[utoipa::path(
+    get,
+    params(
+        ("tenant_id" = Option<String>, Query, description = "Only return failures of this tenant, default all tenants of the caller"),
+        ("limit" = Option<i64>, Query, description = "Maximum number of records, default 100"),
+    ),
+    responses(
+        (status = 200, description = "Fixity failures, most recent first"),
+        (status = 403, description = "Tenant is not accessible"),
+        (status = 500, description = "Database error"),
+    ),
+    tag = "ADMIN",
+    description = "List instances whose files failed fixity verification"
+)]
+#[get("/fixity/failures")]
+pub async fn list_fixity_failures(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    query: web::Query<FixityFailureQuery>,
+) -> impl Responder {
+    let tenants = match scoped_tenants(&req, &app_state, query.tenant_id.as_deref()) {
+        Ok(tenants) => tenants,
+        Err(resp) => return resp,
+    };
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+    let mut failures = Vec::new();
+    for tenant_id in &tenants {
+        match app_state.db.get_fixity_failures(Some(tenant_id), limit).await {
+            Ok(records) => failures.extend(records),
+            Err(e) => {
+                error!(app_state.log, "get_fixity_failures failed: {}", e);
+                return HttpResponse::InternalServerError().body(e.to_string());
+            }
+        }
+    }
+    failures.sort_by_key(|f| Reverse(f.checked_time));
+    failures.truncate(limit as usize);
+    HttpResponse::Ok().json(failures)
+}
+
+#[derive(Deserialize)]
+pub struct TenantUsageQuery {
+    pub tenant_id: Option<String>,
+}
+
+/// 返回租户当前存储用量(字节数、实例数、检查数)
+#[utoipa::path(
+    get,
+    params(
+        ("tenant_id" = Option<String>, Query, description = "Only return usage of this tenant, default all tenants of the caller"),
+    ),
+    responses(
+        (status = 200, description = "Current usage of each tenant"),
+        (status = 403, description = "Tenant is not accessible"),
+        (status = 500, description = "Database error"),
+    ),
+    tag = "ADMIN",
+    description = "List current storage usage per tenant"
+)]
+#[get("/usage")]
+pub async fn list_tenant_usage(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    query: web::Query<TenantUsageQuery>,
+) -> impl Responder {
+    let tenants = match scoped_tenants(&req, &app_state, query.tenant_id.as_deref()) {
+        Ok(tenants) => tenants,
+        Err(resp) => return resp,
+    };
+    let mut usage = Vec::new();
+    for tenant_id in &tenants {
+        match app_state.db.get_tenant_usage(Some(tenant_id)).await {
+            Ok(records) => usage.extend(records),
+            Err(e) => {
+                error!(app_state.log, "get_tenant_usage failed: {}", e);
+                return HttpResponse::InternalServerError().body(e.to_string());
+            }
+        }
+    }
+    HttpResponse::Ok().json(usage)
+}
+
+#[derive(Deserialize)]
+pub struct UsageHistoryQuery {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+/// 返回租户每天的存储用量, 默认最近 30 天
+#[utoipa::path(
+    get,
+    params(
+        ("tenant_id" = String, Path, description = "Tenant ID"),
+        ("from" = Option<String>, Query, description = "Start date, YYYY-MM-DD"),
+        ("to" = Option<String>, Query, description = "End date, YYYY-MM-DD"),
+    ),
+    responses(
+        (status = 200, description = "Daily usage of the tenant"),
+        (status = 403, description = "Tenant is not accessible"),
+        (status = 500, description = "Database error"),
+    ),
+    tag = "ADMIN",
+    description = "Report storage usage of a tenant over time"
+)]
+#[get("/usage/{tenant_id}/history")]
+pub async fn get_tenant_usage_history(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    tenant_id: web::Path<String>,
+    query: web::Query<UsageHistoryQuery>,
+) -> impl Responder {
+    if let Err(resp) = scoped_tenants(&req, &app_state, Some(&tenant_id)) {
+        return resp;
+    }
+    let to = query.to.unwrap_or_else(|| current_time().date());
+    let from = query.from.unwrap_or(to - chrono::Duration::days(30));
+    match app_state
+        .db
+        .get_tenant_usage_history(&tenant_id, from, to)
+        .await
+    {
+        Ok(usage) => HttpResponse::Ok().json(usage),
+        Err(e) => {
+            error!(app_state.log, "get_tenant_usage_history failed: {}", e);
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
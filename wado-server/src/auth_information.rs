@@ -26,4 +26,7 @@ pub(crate) struct Claims {
     pub(crate) realm_access: Option<RealmAccess>, // realm 级别权限
     pub(crate) resource_access: Option<std::collections::HashMap<String, ResourceAccess>>, // 资源级别权限
     pub(crate) scope: Option<String>,
+    /// 其他声明, 如 admin.tenant_claim 指定的租户列表
+    #[serde(flatten)]
+    pub(crate) extra: serde_json::Map<String, Value>,
 }
@@ -1,4 +1,7 @@
-use actix_web::HttpRequest;
+use crate::auth_information::Claims;
+use crate::auth_middleware_kc::extract_values_as_strings;
+use actix_web::{HttpMessage, HttpRequest};
+use common::server_config::AdminConfig;
 use std::collections::HashMap;
 
 // 解析查询字符串，支持重复键
@@ -59,4 +62,65 @@ pub(crate) fn get_tenant_from_handler(req: &HttpRequest) -> String {
             DEFAULT_TENANT_HEADER.to_string() // 默认值或错误处理
         }
     }
-}
\ No newline at end of file
+}
+
+/// 管理接口调用方可以管理的租户.
+/// 配置 tenant_claim 时取自令牌, 令牌中没有租户时为空; 否则为 x-tenant 请求头指定的租户
+pub(crate) fn admin_tenants(req: &HttpRequest, admin: &AdminConfig) -> Vec<String> {
+    match &admin.tenant_claim {
+        Some(json_path) => req
+            .extensions()
+            .get::<Claims>()
+            .and_then(|claims| serde_json::to_value(claims).ok())
+            .map(|claims_json| extract_values_as_strings(&claims_json, json_path))
+            .unwrap_or_default(),
+        None => vec![get_tenant_from_handler(req)],
+    }
+}
+
+/// 发起操作的用户, 未启用认证时为空
+pub(crate) fn requested_by(req: &HttpRequest) -> Option<String> {
+    req.extensions()
+        .get::<Claims>()
+        .and_then(|claims| claims.preferred_username.clone().or(claims.sub.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use common::server_config::RoleRule;
+
+    fn admin_config(tenant_claim: Option<&str>) -> AdminConfig {
+        AdminConfig {
+            permissions: RoleRule {
+                json_path: "$.realm_access.roles".to_string(),
+                required_values: vec!["dicom_admin".to_string()],
+            },
+            tenant_claim: tenant_claim.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_admin_tenants() {
+        let req = TestRequest::default()
+            .insert_header((X_TENANT_HEADER, "t1"))
+            .to_http_request();
+        assert_eq!(admin_tenants(&req, &admin_config(None)), vec!["t1"]);
+        // 配置 tenant_claim 时忽略请求头, 没有令牌则没有租户
+        assert!(admin_tenants(&req, &admin_config(Some("$.tenants"))).is_empty());
+
+        let claims: Claims = serde_json::from_value(serde_json::json!({
+            "iss": "issuer",
+            "aud": "XDICOM",
+            "exp": 0,
+            "tenants": ["t2", "t3"]
+        }))
+        .unwrap();
+        req.extensions_mut().insert(claims);
+        assert_eq!(
+            admin_tenants(&req, &admin_config(Some("$.tenants"))),
+            vec!["t2", "t3"]
+        );
+    }
+}
@@ -1,7 +1,9 @@
 pub(crate) const WADO_RS_CONTEXT_PATH: &str = "/wado-rs";
 pub(crate) const STOW_RS_CONTEXT_PATH: &str = "/stow-rs";
+pub(crate) const ADMIN_CONTEXT_PATH: &str = "/admin";
 pub(crate) const WADO_RS_TAG: &str = "WADO-RS";
 pub(crate) const STOW_RS_TAG: &str = "STOW-RS";
+pub(crate) const QIDO_RS_TAG: &str = "QIDO-RS";
 
 #[allow(dead_code)]
 pub(crate) const WADO_RS_ROLES: &str = "role_patients";
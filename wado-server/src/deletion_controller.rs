@@ -0,0 +1,224 @@
+use crate::common_utils::requested_by;
+use crate::{AppState, common_utils};
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, post, web};
+use common::deletion::{DeletionError, DeletionTarget, delete_objects, restore_deletion};
+use serde::Deserialize;
+use slog::{error, info};
+
+fn error_response(app_state: &AppState, e: DeletionError) -> HttpResponse {
+    match e {
+        DeletionError::Disabled => HttpResponse::Forbidden().body(e.to_string()),
+        DeletionError::NotFound(_) => HttpResponse::NotFound().body(e.to_string()),
+        DeletionError::NotRestorable(..) | DeletionError::LegalHold(_) => {
+            HttpResponse::Conflict().body(e.to_string())
+        }
+        e => {
+            error!(app_state.log, "Deletion failed: {}", e);
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+async fn delete_target(
+    req: &HttpRequest,
+    app_state: &AppState,
+    study_uid: &str,
+    series_uid: Option<&str>,
+    sop_uid: Option<&str>,
+) -> HttpResponse {
+    let tenant_id = common_utils::get_tenant_from_handler(req);
+    let target = DeletionTarget {
+        tenant_id: &tenant_id,
+        study_uid,
+        series_uid,
+        sop_uid,
+    };
+    let user = requested_by(req);
+    info!(
+        app_state.log,
+        "delete {} Tenant ID: {} StudyUID: {} SeriesUID: {:?} SOPUID: {:?} by {:?}",
+        target.level(),
+        tenant_id,
+        study_uid,
+        series_uid,
+        sop_uid,
+        user
+    );
+    match delete_objects(
+        app_state.db.as_ref(),
+        &app_state.redis_helper,
+        &app_state.config,
+        &target,
+        user.as_deref(),
+    )
+    .await
+    {
+        Ok(deletion) => HttpResponse::Ok().json(deletion),
+        Err(e) => error_response(app_state, e),
+    }
+}
+
+/// 删除检查: 文件、数据库记录、JSON 元数据和缓存
+#[utoipa::path(
+    delete,
+    params(
+        ("study_instance_uid" = String, Path, description = "Study Instance UID"),
+        ("x-tenant" = String, Header, description = "Tenant ID from request header"),
+    ),
+    responses(
+        (status = 200, description = "Deleted, returns the deletion record"),
+        (status = 403, description = "Deletion is not enabled"),
+        (status = 404, description = "Study not found"),
+        (status = 409, description = "Study is on legal hold"),
+        (status = 500, description = "Database or storage error"),
+    ),
+    tag = "ADMIN",
+    description = "Delete a study"
+)]
+#[delete("/studies/{study_instance_uid}")]
+pub async fn delete_study(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let study_uid = path.into_inner();
+    delete_target(&req, &app_state, &study_uid, None, None).await
+}
+
+/// 删除序列
+#[utoipa::path(
+    delete,
+    params(
+        ("study_instance_uid" = String, Path, description = "Study Instance UID"),
+        ("series_instance_uid" = String, Path, description = "Series Instance UID"),
+        ("x-tenant" = String, Header, description = "Tenant ID from request header"),
+    ),
+    responses(
+        (status = 200, description = "Deleted, returns the deletion record"),
+        (status = 403, description = "Deletion is not enabled"),
+        (status = 404, description = "Series not found"),
+        (status = 409, description = "Study is on legal hold"),
+        (status = 500, description = "Database or storage error"),
+    ),
+    tag = "ADMIN",
+    description = "Delete a series"
+)]
+#[delete("/studies/{study_instance_uid}/series/{series_instance_uid}")]
+pub async fn delete_series(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (study_uid, series_uid) = path.into_inner();
+    delete_target(&req, &app_state, &study_uid, Some(&series_uid), None).await
+}
+
+/// 删除实例, 序列 JSON 元数据随后由 wado-webworker 重新生成
+#[utoipa::path(
+    delete,
+    params(
+        ("study_instance_uid" = String, Path, description = "Study Instance UID"),
+        ("series_instance_uid" = String, Path, description = "Series Instance UID"),
+        ("sop_instance_uid" = String, Path, description = "SOP Instance UID"),
+        ("x-tenant" = String, Header, description = "Tenant ID from request header"),
+    ),
+    responses(
+        (status = 200, description = "Deleted, returns the deletion record"),
+        (status = 403, description = "Deletion is not enabled"),
+        (status = 404, description = "Instance not found"),
+        (status = 409, description = "Study is on legal hold"),
+        (status = 500, description = "Database or storage error"),
+    ),
+    tag = "ADMIN",
+    description = "Delete an instance"
+)]
+#[delete("/studies/{study_instance_uid}/series/{series_instance_uid}/instances/{sop_instance_uid}")]
+pub async fn delete_instance(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+) -> impl Responder {
+    let (study_uid, series_uid, sop_uid) = path.into_inner();
+    delete_target(&req, &app_state, &study_uid, Some(&series_uid), Some(&sop_uid)).await
+}
+
+#[derive(Deserialize)]
+pub struct DeletionQuery {
+    pub status: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// 返回当前租户的删除记录, 最近的在前
+#[utoipa::path(
+    get,
+    params(
+        ("x-tenant" = String, Header, description = "Tenant ID from request header"),
+        ("status" = Option<String>, Query, description = "TRASHED / PURGED / RESTORED"),
+        ("limit" = Option<i64>, Query, description = "Maximum number of records, default 100"),
+    ),
+    responses(
+        (status = 200, description = "Deletion records"),
+        (status = 500, description = "Database error"),
+    ),
+    tag = "ADMIN",
+    description = "List deletions of the tenant, TRASHED ones can be restored"
+)]
+#[get("/records")]
+pub async fn list_deletions(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    query: web::Query<DeletionQuery>,
+) -> impl Responder {
+    let tenant_id = common_utils::get_tenant_from_handler(&req);
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+    match app_state
+        .db
+        .get_deletions(Some(&tenant_id), query.status.as_deref(), limit)
+        .await
+    {
+        Ok(deletions) => HttpResponse::Ok().json(deletions),
+        Err(e) => {
+            error!(app_state.log, "get_deletions failed: {}", e);
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+/// 从回收站恢复, 文件重新建立索引后可以访问
+#[utoipa::path(
+    post,
+    params(
+        ("deletion_id" = String, Path, description = "Deletion ID"),
+        ("x-tenant" = String, Header, description = "Tenant ID from request header"),
+    ),
+    responses(
+        (status = 200, description = "Restored, returns the deletion record"),
+        (status = 404, description = "Deletion not found"),
+        (status = 409, description = "Deletion is not in the trash"),
+        (status = 500, description = "Storage or message queue error"),
+    ),
+    tag = "ADMIN",
+    description = "Restore a deletion within the trash period"
+)]
+#[post("/records/{deletion_id}/restore")]
+pub async fn restore(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    deletion_id: web::Path<String>,
+) -> impl Responder {
+    let tenant_id = common_utils::get_tenant_from_handler(&req);
+    let deletion_id = deletion_id.into_inner();
+    info!(app_state.log, "restore deletion {} by {:?}", deletion_id, requested_by(&req));
+    // 只能恢复本租户的删除
+    match app_state.db.get_deletion(&deletion_id).await {
+        Ok(Some(deletion)) if deletion.tenant_id.as_str() == tenant_id => {}
+        Ok(_) => {
+            return error_response(&app_state, DeletionError::NotFound(deletion_id));
+        }
+        Err(e) => return error_response(&app_state, e.into()),
+    }
+    match restore_deletion(app_state.db.as_ref(), &app_state.config, &deletion_id).await {
+        Ok(deletion) => HttpResponse::Ok().json(deletion),
+        Err(e) => error_response(&app_state, e),
+    }
+}
@@ -1,11 +1,18 @@
 pub mod common_utils;
 
+mod admin_controller;
 mod apilog_middleware_kc;
 mod auth_information;
 mod auth_middleware_kc;
 mod common_controller;
 mod constants;
+mod deletion_controller;
+mod node_monitor;
+mod patient_controller;
 mod payload_helper;
+mod qido_rs_controller_v1;
+mod retention_controller;
+mod series_access;
 mod stow_rs_controller_v1;
 mod wado_rs_controller_v1;
 mod wado_rs_models;
@@ -20,18 +27,24 @@ use actix_web::{App, HttpResponse, HttpServer, Responder, middleware, web};
 // use crate::auth_middleware_kc::AuthMiddleware;
 use crate::apilog_middleware_kc::ApiLoggerMiddleware;
 use crate::auth_middleware_kc::{AuthMiddleware, update_jwks_task};
-use crate::constants::{STOW_RS_CONTEXT_PATH, WADO_RS_CONTEXT_PATH};
+use crate::constants::{ADMIN_CONTEXT_PATH, STOW_RS_CONTEXT_PATH, WADO_RS_CONTEXT_PATH};
+use crate::node_monitor::NodeMonitor;
+use crate::series_access::SeriesAccessTracker;
 use actix_web::middleware::Logger as DefaultLogger;
 use common::message_sender_kafka::KafkaMessagePublisher;
 use common::message_sender_kafka::MessagePublisher;
+use common::quota::QuotaGuard;
+use common::storage_layout::series_layouts;
 use common::redis_key::RedisHelper;
 use common::server_config::AppConfig;
+use common::storage_tier::spawn_cleanup_interrupted_writes;
 use common::utils::setup_logging;
 use common::{database_factory, server_config};
 use database::dicom_dbprovider::DbProvider;
 use slog;
 use slog::{Logger, error, info};
 use std::sync::Arc;
+use std::time::Duration;
 use utoipa::OpenApi;
 use utoipa_actix_web::{AppExt, scope};
 use utoipa_swagger_ui::SwaggerUi; // 添加 MessagePublisher trait 导入
@@ -62,6 +75,28 @@ fn configure_log() -> Logger {
         common_controller::echo,
         stow_rs_controller_v1::store_instances,
         stow_rs_controller_v1::store_instances_to_study,
+        admin_controller::list_dicom_nodes,
+        admin_controller::echo_dicom_node,
+        admin_controller::list_fixity_failures,
+        admin_controller::list_tenant_usage,
+        admin_controller::get_tenant_usage_history,
+        deletion_controller::delete_study,
+        deletion_controller::delete_series,
+        deletion_controller::delete_instance,
+        deletion_controller::list_deletions,
+        deletion_controller::restore,
+        patient_controller::get_patient,
+        patient_controller::merge_patient,
+        patient_controller::update_patient,
+        patient_controller::move_study,
+        patient_controller::update_study,
+        patient_controller::list_changes,
+        retention_controller::list_candidates,
+        retention_controller::delete_candidate,
+        retention_controller::keep,
+        retention_controller::list_holds,
+        retention_controller::place_hold,
+        retention_controller::release_hold,
         // 添加其他路径...
     ),
     components(
@@ -69,7 +104,9 @@ fn configure_log() -> Logger {
     ),
     tags(
         (name = "STOW-RS", description = "STOW-RS API接口"),
-        (name = "WADO-RS", description = "WADO-RS API接口")
+        (name = "WADO-RS", description = "WADO-RS API接口"),
+        (name = "QIDO-RS", description = "QIDO-RS API接口"),
+        (name = "ADMIN", description = "运维管理接口")
     )
 )]
 struct ApiDoc;
@@ -79,6 +116,9 @@ struct AppState {
     db: Arc<dyn DbProvider + Send + Sync>,
     config: AppConfig,
     redis_helper: RedisHelper,
+    node_monitor: Arc<NodeMonitor>,
+    series_access: Arc<SeriesAccessTracker>,
+    quota: Arc<QuotaGuard>,
     // 可以添加其他配置
 }
 
@@ -115,11 +155,44 @@ async fn main() -> std::io::Result<()> {
     let oauth_config = config.wado_oauth2;
     let reids_conn = g_config.redis.clone();
 
+    // 远程 DICOM 节点健康检查
+    let monitor_config = g_config.node_monitor.clone().unwrap_or_default();
+    let node_monitor = NodeMonitor::new(
+        g_config.dicom_nodes.clone(),
+        monitor_config
+            .calling_ae_title
+            .clone()
+            .unwrap_or_else(|| g_config.dicom_store_scp.ae_title.clone()),
+    );
+    if !g_config.dicom_nodes.is_empty() {
+        tokio::spawn(
+            node_monitor
+                .clone()
+                .run(Duration::from_secs(monitor_config.interval_secs.max(1))),
+        );
+    }
+
+    let db_provider = db_provider as Arc<dyn DbProvider + Send + Sync>; // 正确的类型转换
+    let recall_on_access = g_config
+        .tiering
+        .as_ref()
+        .map(|tiering| tiering.recall_on_access)
+        .unwrap_or(false);
+    let series_access = SeriesAccessTracker::new(db_provider.clone(), recall_on_access);
+    let quota = QuotaGuard::new(g_config.quota.clone(), db_provider.clone());
+    series_layouts().set_db_provider(db_provider.clone());
+
+    // 清理上次异常退出时 STOW-RS 遗留的临时文件
+    spawn_cleanup_interrupted_writes();
+
     let app_state = AppState {
         log: log.clone(),
-        db: db_provider as Arc<dyn DbProvider + Send + Sync>, // 正确的类型转换
+        db: db_provider,
         config: g_config,
         redis_helper: RedisHelper::new(reids_conn),
+        node_monitor,
+        series_access,
+        quota,
     };
 
     // 在创建app_state之后，启动服务器之前添加以下代码
@@ -187,6 +260,35 @@ async fn main() -> std::io::Result<()> {
         let webapi_publisher: Arc<dyn MessagePublisher + Send + Sync> =
             Arc::new(KafkaMessagePublisher::new(api_queue.clone()));
 
+        // 节点、文件校验和存储用量等管理接口校验 admin.permissions
+        let admin_oauth2 = app_state.config.wado_oauth2.clone().map(|mut oauth2| {
+            if let Some(admin) = &app_state.config.admin {
+                oauth2.permissions = Some(admin.permissions.clone());
+            }
+            oauth2
+        });
+        // 删除接口在 wado_oauth2 的基础上校验 deletion.permissions
+        let deletion_oauth2 = app_state.config.wado_oauth2.clone().map(|mut oauth2| {
+            if let Some(deletion) = &app_state.config.deletion {
+                oauth2.permissions = Some(deletion.permissions.clone());
+            }
+            oauth2
+        });
+        // 患者合并和属性修改接口校验 patient_update.permissions
+        let patient_oauth2 = app_state.config.wado_oauth2.clone().map(|mut oauth2| {
+            if let Some(patient_update) = &app_state.config.patient_update {
+                oauth2.permissions = Some(patient_update.permissions.clone());
+            }
+            oauth2
+        });
+        // 数据保留和法律保全接口校验 retention.permissions
+        let retention_oauth2 = app_state.config.wado_oauth2.clone().map(|mut oauth2| {
+            if let Some(retention) = &app_state.config.retention {
+                oauth2.permissions = Some(retention.permissions.clone());
+            }
+            oauth2
+        });
+
         let webapi_publisher_wado_rs = webapi_publisher.clone();
         let webapi_publisher_stow_rs = webapi_publisher.clone();
 
@@ -213,7 +315,8 @@ async fn main() -> std::io::Result<()> {
                             .service(wado_rs_controller_v1::retrieve_study_subseries)
                             .service(wado_rs_controller_v1::retrieve_series_metadata)
                             .service(wado_rs_controller_v1::retrieve_instance)
-                            .service(wado_rs_controller_v1::retrieve_instance_frames),
+                            .service(wado_rs_controller_v1::retrieve_instance_frames)
+                            .service(qido_rs_controller_v1::search_for_studies),
                     ),
             )
             .service(
@@ -236,6 +339,70 @@ async fn main() -> std::io::Result<()> {
                             .service(stow_rs_controller_v1::store_instances_to_study), // .service(stow_rs_controller_v1::echo_v1)
                     ),
             )
+            .service(
+                scope::scope(ADMIN_CONTEXT_PATH)
+                    .wrap(DefaultLogger::default())
+                    .wrap(AuthMiddleware {
+                        logger: app_state.log.clone(),
+                        redis: app_state.redis_helper.clone(),
+                        oauth2_config: app_state.config.wado_oauth2.clone(),
+                    })
+                    .service(
+                        scope::scope("/deletion")
+                            .wrap(AuthMiddleware {
+                                logger: app_state.log.clone(),
+                                redis: app_state.redis_helper.clone(),
+                                oauth2_config: deletion_oauth2,
+                            })
+                            .service(deletion_controller::delete_study)
+                            .service(deletion_controller::delete_series)
+                            .service(deletion_controller::delete_instance)
+                            .service(deletion_controller::list_deletions)
+                            .service(deletion_controller::restore),
+                    )
+                    .service(
+                        scope::scope("/patient")
+                            .wrap(AuthMiddleware {
+                                logger: app_state.log.clone(),
+                                redis: app_state.redis_helper.clone(),
+                                oauth2_config: patient_oauth2,
+                            })
+                            .service(patient_controller::get_patient)
+                            .service(patient_controller::merge_patient)
+                            .service(patient_controller::update_patient)
+                            .service(patient_controller::move_study)
+                            .service(patient_controller::update_study)
+                            .service(patient_controller::list_changes),
+                    )
+                    .service(
+                        scope::scope("/retention")
+                            .wrap(AuthMiddleware {
+                                logger: app_state.log.clone(),
+                                redis: app_state.redis_helper.clone(),
+                                oauth2_config: retention_oauth2,
+                            })
+                            .service(retention_controller::list_candidates)
+                            .service(retention_controller::delete_candidate)
+                            .service(retention_controller::keep)
+                            .service(retention_controller::list_holds)
+                            .service(retention_controller::place_hold)
+                            .service(retention_controller::release_hold),
+                    )
+                    // 放在最后, 避免空前缀的 scope 先匹配 /deletion 等路径
+                    .service(
+                        scope::scope("")
+                            .wrap(AuthMiddleware {
+                                logger: app_state.log.clone(),
+                                redis: app_state.redis_helper.clone(),
+                                oauth2_config: admin_oauth2,
+                            })
+                            .service(admin_controller::list_dicom_nodes)
+                            .service(admin_controller::echo_dicom_node)
+                            .service(admin_controller::list_fixity_failures)
+                            .service(admin_controller::list_tenant_usage)
+                            .service(admin_controller::get_tenant_usage_history),
+                    ),
+            )
             .split_for_parts();
 
         api.info.title = "DICOMWeb API".to_string();
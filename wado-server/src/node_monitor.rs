@@ -0,0 +1,168 @@
+//! 远程 DICOM 节点健康检查: 定期 C-ECHO 所有已配置节点, 记录延迟及最近一次成功时间
+
+use chrono::NaiveDateTime;
+use common::dicom_scu;
+use common::server_config::DicomNodeConfig;
+use common::utils::get_logger;
+use database::dicom_dbprovider::current_time;
+use serde::Serialize;
+use slog::{info, o, warn};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeStatus {
+    pub name: String,
+    pub ae_title: String,
+    pub host: String,
+    pub port: u16,
+    /// 最近一次 C-ECHO 是否成功
+    pub reachable: bool,
+    pub latency_ms: Option<u64>,
+    pub last_checked: Option<NaiveDateTime>,
+    pub last_success: Option<NaiveDateTime>,
+    pub last_error: Option<String>,
+}
+
+impl NodeStatus {
+    fn new(node: &DicomNodeConfig) -> Self {
+        NodeStatus {
+            name: node.name.clone(),
+            ae_title: node.ae_title.clone(),
+            host: node.host.clone(),
+            port: node.port,
+            reachable: false,
+            latency_ms: None,
+            last_checked: None,
+            last_success: None,
+            last_error: None,
+        }
+    }
+
+    /// 记录一次 C-ECHO 结果, 失败时保留上一次成功时间
+    pub fn record(&mut self, result: Result<Duration, String>, now: NaiveDateTime) {
+        self.last_checked = Some(now);
+        match result {
+            Ok(latency) => {
+                self.reachable = true;
+                self.latency_ms = Some(latency.as_millis() as u64);
+                self.last_success = Some(now);
+                self.last_error = None;
+            }
+            Err(e) => {
+                self.reachable = false;
+                self.latency_ms = None;
+                self.last_error = Some(e);
+            }
+        }
+    }
+}
+
+pub struct NodeMonitor {
+    nodes: Vec<DicomNodeConfig>,
+    calling_ae: String,
+    statuses: RwLock<HashMap<String, NodeStatus>>,
+}
+
+impl NodeMonitor {
+    pub fn new(nodes: Vec<DicomNodeConfig>, calling_ae: String) -> Arc<Self> {
+        let statuses = nodes
+            .iter()
+            .map(|node| (node.name.clone(), NodeStatus::new(node)))
+            .collect();
+        Arc::new(NodeMonitor {
+            nodes,
+            calling_ae,
+            statuses: RwLock::new(statuses),
+        })
+    }
+
+    /// 按配置顺序返回所有节点状态
+    pub fn statuses(&self) -> Vec<NodeStatus> {
+        let Ok(statuses) = self.statuses.read() else {
+            return vec![];
+        };
+        self.nodes
+            .iter()
+            .filter_map(|node| statuses.get(&node.name).cloned())
+            .collect()
+    }
+
+    /// 立即 C-ECHO 指定节点并更新状态, 节点不存在时返回 None
+    pub async fn echo(&self, name: &str) -> Option<NodeStatus> {
+        let node = self.nodes.iter().find(|node| node.name == name)?.clone();
+        let calling_ae = self.calling_ae.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let start = Instant::now();
+            dicom_scu::echo(&node, &calling_ae)
+                .map(|_| start.elapsed())
+                .map_err(|e| e.to_string())
+        })
+        .await
+        .unwrap_or_else(|e| Err(format!("C-ECHO task panicked: {}", e)));
+
+        let mut statuses = self.statuses.write().ok()?;
+        let status = statuses.get_mut(name)?;
+        status.record(result, current_time());
+        Some(status.clone())
+    }
+
+    pub async fn run(self: Arc<Self>, interval: Duration) {
+        let rlogger = get_logger();
+        let logger = rlogger.new(o!("wado-server"=>"node_monitor"));
+        info!(
+            logger,
+            "Node monitor started, {} nodes, interval {:?}",
+            self.nodes.len(),
+            interval
+        );
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let checks = self.nodes.iter().map(|node| self.echo(&node.name));
+            for status in futures::future::join_all(checks).await.into_iter().flatten() {
+                if !status.reachable {
+                    warn!(
+                        logger,
+                        "DICOM node {} ({}@{}:{}) is unreachable: {}",
+                        status.name,
+                        status.ae_title,
+                        status.host,
+                        status.port,
+                        status.last_error.as_deref().unwrap_or("")
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_keeps_last_success() {
+        let node = DicomNodeConfig {
+            name: "archive".to_string(),
+            ae_title: "ARCHIVE".to_string(),
+            host: "127.0.0.1".to_string(),
+            port: 104,
+            max_concurrency: 2,
+        };
+        let mut status = NodeStatus::new(&node);
+        let now = current_time();
+        status.record(Ok(Duration::from_millis(12)), now);
+        assert!(status.reachable);
+        assert_eq!(status.latency_ms, Some(12));
+
+        let later = now + chrono::Duration::seconds(60);
+        status.record(Err("connection refused".to_string()), later);
+        assert!(!status.reachable);
+        assert_eq!(status.latency_ms, None);
+        assert_eq!(status.last_checked, Some(later));
+        assert_eq!(status.last_success, Some(now));
+        assert_eq!(status.last_error.as_deref(), Some("connection refused"));
+    }
+}
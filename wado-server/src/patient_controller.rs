@@ -0,0 +1,304 @@
+use crate::common_utils::requested_by;
+use crate::{AppState, common_utils};
+use actix_web::{HttpRequest, HttpResponse, Responder, get, patch, post, web};
+use common::patient_update::{
+    PatientUpdateError, merge_patient as merge_patient_records, move_study as move_study_records,
+    update_patient as update_patient_records, update_study as update_study_records,
+};
+use database::dicom_meta::{DicomAttributeUpdate, DicomChangeAudit};
+use serde::Deserialize;
+use slog::{error, info};
+use utoipa::ToSchema;
+
+fn changed_response(
+    app_state: &AppState,
+    result: Result<Vec<DicomChangeAudit>, PatientUpdateError>,
+) -> HttpResponse {
+    match result {
+        Ok(audits) => HttpResponse::Ok().json(audits),
+        Err(e @ PatientUpdateError::Disabled) => HttpResponse::Forbidden().body(e.to_string()),
+        Err(e @ PatientUpdateError::NotFound(_)) => HttpResponse::NotFound().body(e.to_string()),
+        Err(e @ PatientUpdateError::Invalid(_)) => HttpResponse::BadRequest().body(e.to_string()),
+        Err(e) => {
+            error!(app_state.log, "Patient update failed: {}", e);
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+/// 患者级汇总: 检查数、序列数和实例数
+#[utoipa::path(
+    get,
+    params(
+        ("patient_id" = String, Path, description = "Patient ID"),
+        ("x-tenant" = String, Header, description = "Tenant ID from request header"),
+    ),
+    responses(
+        (status = 200, description = "Patient summary"),
+        (status = 404, description = "Patient not found"),
+        (status = 500, description = "Database error"),
+    ),
+    tag = "ADMIN",
+    description = "Get the study, series and instance counts of a patient"
+)]
+#[get("/patients/{patient_id}")]
+pub async fn get_patient(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let tenant_id = common_utils::get_tenant_from_handler(&req);
+    let patient_id = path.into_inner();
+    match app_state.db.get_patient_meta(&tenant_id, &patient_id).await {
+        Ok(Some(patient)) => HttpResponse::Ok().json(patient),
+        Ok(None) => HttpResponse::NotFound().body(format!("Patient not found: {}", patient_id)),
+        Err(e) => {
+            error!(app_state.log, "get_patient_meta failed: {}", e);
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct MergePatientRequest {
+    pub target_patient_id: String,
+}
+
+/// 将患者合并到目标患者: 所有检查的患者属性改为目标患者的属性
+#[utoipa::path(
+    post,
+    params(
+        ("patient_id" = String, Path, description = "Patient ID to merge"),
+        ("x-tenant" = String, Header, description = "Tenant ID from request header"),
+    ),
+    responses(
+        (status = 200, description = "Merged, returns the change records"),
+        (status = 400, description = "Invalid request"),
+        (status = 403, description = "Patient update is not enabled"),
+        (status = 404, description = "Patient not found"),
+        (status = 500, description = "Database or storage error"),
+    ),
+    tag = "ADMIN",
+    description = "Merge a patient into another patient, body: {\"target_patient_id\": \"...\"}"
+)]
+#[post("/patients/{patient_id}/merge")]
+pub async fn merge_patient(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<String>,
+    body: web::Json<MergePatientRequest>,
+) -> impl Responder {
+    let tenant_id = common_utils::get_tenant_from_handler(&req);
+    let patient_id = path.into_inner();
+    let user = requested_by(&req);
+    info!(
+        app_state.log,
+        "merge patient Tenant ID: {} {} -> {} by {:?}",
+        tenant_id,
+        patient_id,
+        body.target_patient_id,
+        user
+    );
+    let result = merge_patient_records(
+        app_state.db.as_ref(),
+        &app_state.redis_helper,
+        &app_state.config,
+        &tenant_id,
+        &patient_id,
+        &body.target_patient_id,
+        user.as_deref(),
+    )
+    .await;
+    changed_response(&app_state, result)
+}
+
+/// 修改患者属性, 应用到患者的所有检查
+#[utoipa::path(
+    patch,
+    params(
+        ("patient_id" = String, Path, description = "Patient ID"),
+        ("x-tenant" = String, Header, description = "Tenant ID from request header"),
+    ),
+    responses(
+        (status = 200, description = "Updated, returns the change records"),
+        (status = 400, description = "Invalid attributes"),
+        (status = 403, description = "Patient update is not enabled"),
+        (status = 404, description = "Patient not found"),
+        (status = 500, description = "Database or storage error"),
+    ),
+    request_body = Object,
+    tag = "ADMIN",
+    description = "Update patient_id, patient_name, patient_sex or patient_birth_date of all studies of a patient"
+)]
+#[patch("/patients/{patient_id}")]
+pub async fn update_patient(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<String>,
+    body: web::Json<DicomAttributeUpdate>,
+) -> impl Responder {
+    let tenant_id = common_utils::get_tenant_from_handler(&req);
+    let patient_id = path.into_inner();
+    let user = requested_by(&req);
+    info!(
+        app_state.log,
+        "update patient Tenant ID: {} PatientID: {} by {:?}", tenant_id, patient_id, user
+    );
+    let result = update_patient_records(
+        app_state.db.as_ref(),
+        &app_state.redis_helper,
+        &app_state.config,
+        &tenant_id,
+        &patient_id,
+        &body,
+        user.as_deref(),
+    )
+    .await;
+    changed_response(&app_state, result)
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct MoveStudyRequest {
+    pub patient_id: String,
+}
+
+/// 将检查移动到另一个患者下
+#[utoipa::path(
+    post,
+    params(
+        ("study_instance_uid" = String, Path, description = "Study Instance UID"),
+        ("x-tenant" = String, Header, description = "Tenant ID from request header"),
+    ),
+    responses(
+        (status = 200, description = "Moved, returns the change records"),
+        (status = 403, description = "Patient update is not enabled"),
+        (status = 404, description = "Study or patient not found"),
+        (status = 500, description = "Database or storage error"),
+    ),
+    tag = "ADMIN",
+    description = "Move a study to another patient, body: {\"patient_id\": \"...\"}"
+)]
+#[post("/studies/{study_instance_uid}/move")]
+pub async fn move_study(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<String>,
+    body: web::Json<MoveStudyRequest>,
+) -> impl Responder {
+    let tenant_id = common_utils::get_tenant_from_handler(&req);
+    let study_uid = path.into_inner();
+    let user = requested_by(&req);
+    info!(
+        app_state.log,
+        "move study Tenant ID: {} StudyUID: {} -> {} by {:?}",
+        tenant_id,
+        study_uid,
+        body.patient_id,
+        user
+    );
+    let result = move_study_records(
+        app_state.db.as_ref(),
+        &app_state.redis_helper,
+        &app_state.config,
+        &tenant_id,
+        &study_uid,
+        &body.patient_id,
+        user.as_deref(),
+    )
+    .await;
+    changed_response(&app_state, result)
+}
+
+/// 修改检查属性
+#[utoipa::path(
+    patch,
+    params(
+        ("study_instance_uid" = String, Path, description = "Study Instance UID"),
+        ("x-tenant" = String, Header, description = "Tenant ID from request header"),
+    ),
+    responses(
+        (status = 200, description = "Updated, returns the change records"),
+        (status = 400, description = "Invalid attributes"),
+        (status = 403, description = "Patient update is not enabled"),
+        (status = 404, description = "Study not found"),
+        (status = 500, description = "Database or storage error"),
+    ),
+    request_body = Object,
+    tag = "ADMIN",
+    description = "Update accession_number, study_id or study_description of a study"
+)]
+#[patch("/studies/{study_instance_uid}")]
+pub async fn update_study(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<String>,
+    body: web::Json<DicomAttributeUpdate>,
+) -> impl Responder {
+    let tenant_id = common_utils::get_tenant_from_handler(&req);
+    let study_uid = path.into_inner();
+    let user = requested_by(&req);
+    info!(
+        app_state.log,
+        "update study Tenant ID: {} StudyUID: {} by {:?}", tenant_id, study_uid, user
+    );
+    let result = update_study_records(
+        app_state.db.as_ref(),
+        &app_state.redis_helper,
+        &app_state.config,
+        &tenant_id,
+        &study_uid,
+        &body,
+        user.as_deref(),
+    )
+    .await;
+    changed_response(&app_state, result)
+}
+
+#[derive(Deserialize)]
+pub struct ChangeQuery {
+    pub patient_id: Option<String>,
+    pub study_uid: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// 返回当前租户的修改记录, 最近的在前
+#[utoipa::path(
+    get,
+    params(
+        ("x-tenant" = String, Header, description = "Tenant ID from request header"),
+        ("patient_id" = Option<String>, Query, description = "Patient ID before or after the change"),
+        ("study_uid" = Option<String>, Query, description = "Study Instance UID"),
+        ("limit" = Option<i64>, Query, description = "Maximum number of records, default 100"),
+    ),
+    responses(
+        (status = 200, description = "Change records with before/after values"),
+        (status = 500, description = "Database error"),
+    ),
+    tag = "ADMIN",
+    description = "List patient and study changes of the tenant"
+)]
+#[get("/records")]
+pub async fn list_changes(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    query: web::Query<ChangeQuery>,
+) -> impl Responder {
+    let tenant_id = common_utils::get_tenant_from_handler(&req);
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+    match app_state
+        .db
+        .get_change_audits(
+            &tenant_id,
+            query.patient_id.as_deref(),
+            query.study_uid.as_deref(),
+            limit,
+        )
+        .await
+    {
+        Ok(audits) => HttpResponse::Ok().json(audits),
+        Err(e) => {
+            error!(app_state.log, "get_change_audits failed: {}", e);
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use crate::constants::QIDO_RS_TAG;
+use crate::{AppState, common_utils};
+use actix_web::{HttpRequest, HttpResponse, Responder, get, web};
+use chrono::NaiveDate;
+use database::dicom_meta::{DicomStudyMeta, DicomStudyQuery};
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use slog::{error, info};
+
+static ACCEPT_DICOM_JSON_TYPE: &str = "application/dicom+json";
+
+/// 未指定 limit 时返回的检查数, 也是 limit 的上限
+const MAX_STUDY_RESULTS: i64 = 1000;
+
+#[derive(Deserialize)]
+pub struct SearchStudiesQuery {
+    #[serde(rename = "StudyInstanceUID")]
+    pub study_uid: Option<String>,
+    #[serde(rename = "PatientID")]
+    pub patient_id: Option<String>,
+    #[serde(rename = "AccessionNumber")]
+    pub accession_number: Option<String>,
+    #[serde(rename = "StudyDate")]
+    pub study_date: Option<String>,
+    #[serde(rename = "ModalitiesInStudy")]
+    pub modality: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// 解析 StudyDate: YYYYMMDD 或 YYYYMMDD-YYYYMMDD, 范围的任一端可以为空
+fn parse_study_date(value: &str) -> Option<(Option<NaiveDate>, Option<NaiveDate>)> {
+    let parse = |s: &str| -> Option<Option<NaiveDate>> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Some(None);
+        }
+        NaiveDate::parse_from_str(s, "%Y%m%d").ok().map(Some)
+    };
+    match value.split_once('-') {
+        Some((from, to)) => Some((parse(from)?, parse(to)?)),
+        None => {
+            let date = parse(value)?;
+            Some((date, date))
+        }
+    }
+}
+
+fn dicom_element(vr: &str, value: Option<Value>) -> Value {
+    match value {
+        Some(value) => json!({ "vr": vr, "Value": [value] }),
+        None => json!({ "vr": vr }),
+    }
+}
+
+/// 检查级汇总转为 QIDO-RS 返回的 DICOM JSON
+fn study_to_json(study: &DicomStudyMeta) -> Value {
+    let text = |s: Option<&str>| s.filter(|s| !s.is_empty()).map(|s| json!(s));
+    let mut attrs = Map::new();
+    attrs.insert(
+        "00080020".to_string(),
+        dicom_element(
+            "DA",
+            Some(json!(study.study_date.format("%Y%m%d").to_string())),
+        ),
+    );
+    attrs.insert(
+        "00080030".to_string(),
+        dicom_element(
+            "TM",
+            study
+                .study_time
+                .map(|time| json!(time.format("%H%M%S").to_string())),
+        ),
+    );
+    attrs.insert(
+        "00080050".to_string(),
+        dicom_element(
+            "SH",
+            text(study.accession_number.as_ref().map(|s| s.as_str())),
+        ),
+    );
+    let modalities = if study.modalities_in_study.is_empty() {
+        json!({ "vr": "CS" })
+    } else {
+        json!({ "vr": "CS", "Value": study.modalities_in_study })
+    };
+    attrs.insert("00080061".to_string(), modalities);
+    attrs.insert(
+        "00081030".to_string(),
+        dicom_element(
+            "LO",
+            text(study.study_description.as_ref().map(|s| s.as_str())),
+        ),
+    );
+    attrs.insert(
+        "00100010".to_string(),
+        dicom_element(
+            "PN",
+            text(study.patient_name.as_ref().map(|s| s.as_str()))
+                .map(|name| json!({ "Alphabetic": name })),
+        ),
+    );
+    attrs.insert(
+        "00100020".to_string(),
+        dicom_element("LO", text(Some(study.patient_id.as_str()))),
+    );
+    attrs.insert(
+        "00100030".to_string(),
+        dicom_element(
+            "DA",
+            study
+                .patient_birth_date
+                .map(|date| json!(date.format("%Y%m%d").to_string())),
+        ),
+    );
+    attrs.insert(
+        "00100040".to_string(),
+        dicom_element("CS", text(study.patient_sex.as_ref().map(|s| s.as_str()))),
+    );
+    attrs.insert(
+        "0020000D".to_string(),
+        dicom_element("UI", Some(json!(study.study_uid.as_str()))),
+    );
+    attrs.insert(
+        "00200010".to_string(),
+        dicom_element("SH", text(study.study_id.as_ref().map(|s| s.as_str()))),
+    );
+    attrs.insert(
+        "00201206".to_string(),
+        dicom_element("IS", Some(json!(study.number_of_series))),
+    );
+    attrs.insert(
+        "00201208".to_string(),
+        dicom_element("IS", Some(json!(study.number_of_instances))),
+    );
+    Value::Object(attrs)
+}
+
+/// 按条件查询检查, 数据来自检查级汇总表
+#[utoipa::path(
+    get,
+    params(
+        ("StudyInstanceUID" = Option<String>, Query, description = "Study Instance UID"),
+        ("PatientID" = Option<String>, Query, description = "Patient ID"),
+        ("AccessionNumber" = Option<String>, Query, description = "Accession Number"),
+        ("StudyDate" = Option<String>, Query, description = "Study Date, YYYYMMDD or YYYYMMDD-YYYYMMDD"),
+        ("ModalitiesInStudy" = Option<String>, Query, description = "Modality contained in the study"),
+        ("limit" = Option<i64>, Query, description = "Maximum number of results, at most 1000"),
+        ("offset" = Option<i64>, Query, description = "Number of results to skip"),
+        ("x-tenant" = String, Header, description = "Tenant ID from request header"),
+        ("Authorization" = Option<String>, Header, description = "Optional JWT Access Token in Bearer format")
+    ),
+    responses(
+        (status = 200, description = "Matching studies", content_type = "application/dicom+json"),
+        (status = 400, description = "Invalid query parameter"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = QIDO_RS_TAG,
+    description = "Search for Studies in DICOM JSON format",
+)]
+#[get("/studies")]
+async fn search_for_studies(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    query: web::Query<SearchStudiesQuery>,
+) -> impl Responder {
+    let log = app_state.log.clone();
+    let tenant_id = common_utils::get_tenant_from_handler(&req);
+    info!(log, "search_for_studies Tenant ID: {}", tenant_id);
+
+    let result = match &query.study_uid {
+        Some(study_uid) => app_state
+            .db
+            .get_study_meta(&tenant_id, study_uid)
+            .await
+            .map(|study| study.into_iter().collect()),
+        None => {
+            let (study_date_from, study_date_to) = match query.study_date.as_deref() {
+                None => (None, None),
+                Some(value) => match parse_study_date(value) {
+                    Some(range) => range,
+                    None => {
+                        return HttpResponse::BadRequest()
+                            .body(format!("search_for_studies invalid StudyDate: {}", value));
+                    }
+                },
+            };
+            let study_query = DicomStudyQuery {
+                patient_id: query.patient_id.clone(),
+                accession_number: query.accession_number.clone(),
+                study_date_from,
+                study_date_to,
+                modality: query.modality.clone(),
+                limit: query
+                    .limit
+                    .unwrap_or(MAX_STUDY_RESULTS)
+                    .clamp(1, MAX_STUDY_RESULTS),
+                offset: query.offset.unwrap_or(0).max(0),
+            };
+            app_state
+                .db
+                .search_study_metas(&tenant_id, &study_query)
+                .await
+        }
+    };
+    match result {
+        Ok(studies) => {
+            let items: Vec<Value> = studies.iter().map(study_to_json).collect();
+            HttpResponse::Ok()
+                .content_type(ACCEPT_DICOM_JSON_TYPE)
+                .body(Value::Array(items).to_string())
+        }
+        Err(e) => {
+            error!(log, "search_for_studies failed: {}", e);
+            HttpResponse::InternalServerError()
+                .body(format!("search_for_studies failed: {}", e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveTime;
+    use database::dicom_dbtype::BoundedString;
+
+    #[test]
+    fn test_parse_study_date() {
+        let date = |d: u32| NaiveDate::from_ymd_opt(2024, 3, d);
+        assert_eq!(parse_study_date("20240301"), Some((date(1), date(1))));
+        assert_eq!(
+            parse_study_date("20240301-20240310"),
+            Some((date(1), date(10)))
+        );
+        assert_eq!(parse_study_date("-20240310"), Some((None, date(10))));
+        assert_eq!(parse_study_date("20240301-"), Some((date(1), None)));
+        assert_eq!(parse_study_date("2024-03-01"), None);
+        assert_eq!(parse_study_date("abc"), None);
+    }
+
+    #[test]
+    fn test_study_to_json() {
+        let study = DicomStudyMeta {
+            tenant_id: BoundedString::<64>::make_str("t1"),
+            study_uid: BoundedString::<64>::make_str("1.2.3"),
+            patient_id: BoundedString::<64>::make_str("PID0001"),
+            patient_name: Some(BoundedString::<64>::make_str("TEST^PATIENT")),
+            patient_sex: None,
+            patient_birth_date: NaiveDate::from_ymd_opt(1990, 1, 2),
+            study_date: NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+            study_time: NaiveTime::from_hms_opt(8, 30, 5),
+            accession_number: Some(BoundedString::<16>::make_str("ACC1")),
+            study_id: None,
+            study_description: None,
+            modalities_in_study: vec!["CT".to_string(), "PT".to_string()],
+            number_of_series: 2,
+            number_of_instances: 10,
+            updated_time: chrono::Utc::now().naive_utc(),
+        };
+        let json = study_to_json(&study);
+        assert_eq!(json["0020000D"], json!({ "vr": "UI", "Value": ["1.2.3"] }));
+        assert_eq!(json["00100020"]["Value"], json!(["PID0001"]));
+        assert_eq!(
+            json["00100010"]["Value"],
+            json!([{ "Alphabetic": "TEST^PATIENT" }])
+        );
+        assert_eq!(json["00100030"]["Value"], json!(["19900102"]));
+        assert_eq!(json["00100040"], json!({ "vr": "CS" }));
+        assert_eq!(json["00080020"]["Value"], json!(["20240301"]));
+        assert_eq!(json["00080030"]["Value"], json!(["083005"]));
+        assert_eq!(json["00080050"]["Value"], json!(["ACC1"]));
+        assert_eq!(json["00080061"]["Value"], json!(["CT", "PT"]));
+        assert_eq!(json["00201206"]["Value"], json!([2]));
+        assert_eq!(json["00201208"]["Value"], json!([10]));
+    }
+}
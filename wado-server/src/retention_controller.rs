@@ -0,0 +1,281 @@
+use crate::common_utils::requested_by;
+use crate::{AppState, common_utils};
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, post, web};
+use common::deletion::DeletionError;
+use common::retention::{
+    RetentionError, approve_candidate, keep_candidate, place_legal_hold, release_legal_hold,
+};
+use serde::Deserialize;
+use slog::{error, info};
+use utoipa::ToSchema;
+
+fn error_response(app_state: &AppState, e: RetentionError) -> HttpResponse {
+    match e {
+        RetentionError::Disabled => HttpResponse::Forbidden().body(e.to_string()),
+        RetentionError::NotFound(_) => HttpResponse::NotFound().body(e.to_string()),
+        RetentionError::Invalid(_) => HttpResponse::BadRequest().body(e.to_string()),
+        RetentionError::NotPending(..) | RetentionError::Deletion(DeletionError::LegalHold(_)) => {
+            HttpResponse::Conflict().body(e.to_string())
+        }
+        e => {
+            error!(app_state.log, "Retention request failed: {}", e);
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CandidateQuery {
+    pub status: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// 返回当前租户保留期已过的检查, 到期最早的在前
+#[utoipa::path(
+    get,
+    params(
+        ("x-tenant" = String, Header, description = "Tenant ID from request header"),
+        ("status" = Option<String>, Query, description = "PENDING / DELETED / KEPT"),
+        ("limit" = Option<i64>, Query, description = "Maximum number of records, default 100"),
+    ),
+    responses(
+        (status = 200, description = "Retention candidates"),
+        (status = 500, description = "Database error"),
+    ),
+    tag = "ADMIN",
+    description = "List studies past their retention period"
+)]
+#[get("/candidates")]
+pub async fn list_candidates(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    query: web::Query<CandidateQuery>,
+) -> impl Responder {
+    let tenant_id = common_utils::get_tenant_from_handler(&req);
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+    match app_state
+        .db
+        .get_retention_candidates(Some(&tenant_id), query.status.as_deref(), limit)
+        .await
+    {
+        Ok(candidates) => HttpResponse::Ok().json(candidates),
+        Err(e) => {
+            error!(app_state.log, "get_retention_candidates failed: {}", e);
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+/// 审核通过, 通过删除流程删除检查
+#[utoipa::path(
+    post,
+    params(
+        ("study_instance_uid" = String, Path, description = "Study Instance UID"),
+        ("x-tenant" = String, Header, description = "Tenant ID from request header"),
+    ),
+    responses(
+        (status = 200, description = "Deleted, returns the candidate"),
+        (status = 403, description = "Retention is not enabled"),
+        (status = 404, description = "Candidate not found"),
+        (status = 409, description = "Candidate is not PENDING or the study is on legal hold"),
+        (status = 500, description = "Database or storage error"),
+    ),
+    tag = "ADMIN",
+    description = "Approve the deletion of a study past its retention period"
+)]
+#[post("/candidates/{study_instance_uid}/delete")]
+pub async fn delete_candidate(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let tenant_id = common_utils::get_tenant_from_handler(&req);
+    let study_uid = path.into_inner();
+    let user = requested_by(&req);
+    info!(
+        app_state.log,
+        "approve retention deletion Tenant ID: {} StudyUID: {} by {:?}", tenant_id, study_uid, user
+    );
+    match approve_candidate(
+        app_state.db.as_ref(),
+        &app_state.redis_helper,
+        &app_state.config,
+        &tenant_id,
+        &study_uid,
+        user.as_deref(),
+    )
+    .await
+    {
+        Ok(candidate) => HttpResponse::Ok().json(candidate),
+        Err(e) => error_response(&app_state, e),
+    }
+}
+
+/// 审核决定保留, 检查不再作为候选
+#[utoipa::path(
+    post,
+    params(
+        ("study_instance_uid" = String, Path, description = "Study Instance UID"),
+        ("x-tenant" = String, Header, description = "Tenant ID from request header"),
+    ),
+    responses(
+        (status = 200, description = "Kept, returns the candidate"),
+        (status = 403, description = "Retention is not enabled"),
+        (status = 404, description = "Candidate not found"),
+        (status = 409, description = "Candidate is not PENDING"),
+        (status = 500, description = "Database error"),
+    ),
+    tag = "ADMIN",
+    description = "Keep a study past its retention period"
+)]
+#[post("/candidates/{study_instance_uid}/keep")]
+pub async fn keep(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let tenant_id = common_utils::get_tenant_from_handler(&req);
+    let study_uid = path.into_inner();
+    info!(
+        app_state.log,
+        "keep retention candidate Tenant ID: {} StudyUID: {} by {:?}",
+        tenant_id,
+        study_uid,
+        requested_by(&req)
+    );
+    match keep_candidate(
+        app_state.db.as_ref(),
+        &app_state.config,
+        &tenant_id,
+        &study_uid,
+    )
+    .await
+    {
+        Ok(candidate) => HttpResponse::Ok().json(candidate),
+        Err(e) => error_response(&app_state, e),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct HoldQuery {
+    pub include_released: Option<bool>,
+    pub limit: Option<i64>,
+}
+
+/// 返回当前租户的法律保全, 最近的在前
+#[utoipa::path(
+    get,
+    params(
+        ("x-tenant" = String, Header, description = "Tenant ID from request header"),
+        ("include_released" = Option<bool>, Query, description = "Include released holds, default false"),
+        ("limit" = Option<i64>, Query, description = "Maximum number of records, default 100"),
+    ),
+    responses(
+        (status = 200, description = "Legal holds"),
+        (status = 500, description = "Database error"),
+    ),
+    tag = "ADMIN",
+    description = "List legal holds of the tenant"
+)]
+#[get("/holds")]
+pub async fn list_holds(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    query: web::Query<HoldQuery>,
+) -> impl Responder {
+    let tenant_id = common_utils::get_tenant_from_handler(&req);
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+    match app_state
+        .db
+        .get_legal_holds(&tenant_id, query.include_released.unwrap_or(false), limit)
+        .await
+    {
+        Ok(holds) => HttpResponse::Ok().json(holds),
+        Err(e) => {
+            error!(app_state.log, "get_legal_holds failed: {}", e);
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct LegalHoldRequest {
+    pub patient_id: Option<String>,
+    pub study_uid: Option<String>,
+    pub reason: String,
+}
+
+/// 对患者或检查设置法律保全, 解除前不能删除
+#[utoipa::path(
+    post,
+    params(
+        ("x-tenant" = String, Header, description = "Tenant ID from request header"),
+    ),
+    responses(
+        (status = 200, description = "Placed, returns the legal hold"),
+        (status = 400, description = "Invalid request"),
+        (status = 403, description = "Retention is not enabled"),
+        (status = 500, description = "Database error"),
+    ),
+    tag = "ADMIN",
+    description = "Place a legal hold, body: {\"patient_id\": \"...\", \"study_uid\": \"...\", \"reason\": \"...\"}"
+)]
+#[post("/holds")]
+pub async fn place_hold(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    body: web::Json<LegalHoldRequest>,
+) -> impl Responder {
+    let tenant_id = common_utils::get_tenant_from_handler(&req);
+    match place_legal_hold(
+        app_state.db.as_ref(),
+        &app_state.config,
+        &tenant_id,
+        body.patient_id.as_deref(),
+        body.study_uid.as_deref(),
+        &body.reason,
+        requested_by(&req).as_deref(),
+    )
+    .await
+    {
+        Ok(hold) => HttpResponse::Ok().json(hold),
+        Err(e) => error_response(&app_state, e),
+    }
+}
+
+/// 解除法律保全
+#[utoipa::path(
+    delete,
+    params(
+        ("hold_id" = String, Path, description = "Legal hold ID"),
+        ("x-tenant" = String, Header, description = "Tenant ID from request header"),
+    ),
+    responses(
+        (status = 204, description = "Released"),
+        (status = 403, description = "Retention is not enabled"),
+        (status = 404, description = "Active legal hold not found"),
+        (status = 500, description = "Database error"),
+    ),
+    tag = "ADMIN",
+    description = "Release a legal hold"
+)]
+#[delete("/holds/{hold_id}")]
+pub async fn release_hold(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    hold_id: web::Path<String>,
+) -> impl Responder {
+    let tenant_id = common_utils::get_tenant_from_handler(&req);
+    match release_legal_hold(
+        app_state.db.as_ref(),
+        &app_state.config,
+        &tenant_id,
+        &hold_id,
+        requested_by(&req).as_deref(),
+    )
+    .await
+    {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => error_response(&app_state, e),
+    }
+}
@@ -0,0 +1,155 @@
+//! 记录 WADO 序列访问时间, 供分层存储迁移策略使用.
+//! 访问位于非热存储层的序列时, 可按配置在后台迁回热存储层.
+
+use common::server_config::HOT_TIER;
+use common::storage_tier::tiered_storage;
+use common::utils::get_logger;
+use database::dicom_dbprovider::{DbProvider, current_time};
+use database::dicom_dbtype::BoundedString;
+use database::dicom_meta::{DicomSeriesTier, DicomStateMeta};
+use slog::{info, o, warn};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// 同一序列在该时间内只更新一次访问时间, 避免每张图像都写数据库
+const TOUCH_INTERVAL: Duration = Duration::from_secs(600);
+/// 超过该数量时清理过期的访问记录
+const MAX_TRACKED_SERIES: usize = 10000;
+
+pub struct SeriesAccessTracker {
+    db: Arc<dyn DbProvider + Send + Sync>,
+    recall_on_access: bool,
+    last_touch: Mutex<HashMap<String, Instant>>,
+    recalling: Mutex<HashSet<String>>,
+}
+
+impl SeriesAccessTracker {
+    pub fn new(db: Arc<dyn DbProvider + Send + Sync>, recall_on_access: bool) -> Arc<Self> {
+        Arc::new(SeriesAccessTracker {
+            db,
+            recall_on_access,
+            last_touch: Mutex::new(HashMap::new()),
+            recalling: Mutex::new(HashSet::new()),
+        })
+    }
+
+    fn should_touch(&self, series_key: &str, now: Instant) -> bool {
+        let mut last_touch = self.last_touch.lock().unwrap();
+        if let Some(last) = last_touch.get(series_key)
+            && now.duration_since(*last) < TOUCH_INTERVAL
+        {
+            return false;
+        }
+        if last_touch.len() >= MAX_TRACKED_SERIES {
+            last_touch.retain(|_, last| now.duration_since(*last) < TOUCH_INTERVAL);
+        }
+        last_touch.insert(series_key.to_string(), now);
+        true
+    }
+
+    /// 在后台更新序列访问时间, 不阻塞 WADO 请求
+    pub fn touch(&self, series_key: &str, series_info: &DicomStateMeta) {
+        if !self.should_touch(series_key, Instant::now()) {
+            return;
+        }
+        let db = self.db.clone();
+        let series_info = series_info.clone();
+        tokio::spawn(async move {
+            if let Err(e) = db
+                .touch_series_access(
+                    series_info.tenant_id.as_str(),
+                    series_info.study_uid.as_str(),
+                    series_info.series_uid.as_str(),
+                    series_info.study_date_origin.as_str(),
+                    current_time(),
+                )
+                .await
+            {
+                let rlogger = get_logger();
+                let logger = rlogger.new(o!("wado-server"=>"touch_series_access"));
+                warn!(
+                    logger,
+                    "Failed to update access time of series {}: {}", series_info.series_uid, e
+                );
+            }
+        });
+    }
+
+    /// 数据从非热存储层读取时调用, 按配置将整个序列迁回热存储层
+    pub fn accessed_from(
+        self: &Arc<Self>,
+        tier: &str,
+        series_key: &str,
+        series_info: &DicomStateMeta,
+    ) {
+        if !self.recall_on_access || tier == HOT_TIER {
+            return;
+        }
+        // 同一序列只启动一个迁回任务
+        if !self.recalling.lock().unwrap().insert(series_key.to_string()) {
+            return;
+        }
+        let tracker = self.clone();
+        let tier = tier.to_string();
+        let series_key = series_key.to_string();
+        let series_info = series_info.clone();
+        tokio::spawn(async move {
+            tracker.recall(&tier, &series_key, &series_info).await;
+            tracker.recalling.lock().unwrap().remove(&series_key);
+        });
+    }
+
+    async fn recall(&self, tier: &str, series_key: &str, series_info: &DicomStateMeta) {
+        let rlogger = get_logger();
+        let logger = rlogger.new(o!("wado-server"=>"recall_series"));
+        let count = match tiered_storage().migrate(series_key, tier, HOT_TIER).await {
+            Ok(count) => count,
+            Err(e) => {
+                warn!(logger, "Failed to recall series {} from {}: {}", series_key, tier, e);
+                return;
+            }
+        };
+        let now = current_time();
+        let series_tier = DicomSeriesTier {
+            tenant_id: series_info.tenant_id.clone(),
+            study_uid: series_info.study_uid.clone(),
+            series_uid: series_info.series_uid.clone(),
+            study_date_origin: series_info.study_date_origin.clone(),
+            tier: BoundedString::make_str(HOT_TIER),
+            last_access_time: now,
+            updated_time: now,
+            storage_layout: series_info.storage_layout,
+        };
+        match self.db.save_series_tier(&series_tier).await {
+            Ok(()) => info!(
+                logger,
+                "Recalled {} files of series {} from {}", count, series_key, tier
+            ),
+            Err(e) => warn!(
+                logger,
+                "Recalled series {} but failed to update tier: {}", series_key, e
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use database::dicom_pg::PgDbProvider;
+    use database::dicom_dbprovider::DbPoolOptions;
+
+    #[test]
+    fn test_should_touch() {
+        let db = Arc::new(
+            PgDbProvider::new("postgresql://localhost/unused", &DbPoolOptions::default()).unwrap(),
+        );
+        let tracker = SeriesAccessTracker::new(db, false);
+        let now = Instant::now();
+        assert!(tracker.should_touch("t1/20240101/st/se1", now));
+        assert!(!tracker.should_touch("t1/20240101/st/se1", now + Duration::from_secs(60)));
+        assert!(tracker.should_touch("t1/20240101/st/se2", now));
+        assert!(tracker.should_touch("t1/20240101/st/se1", now + TOUCH_INTERVAL));
+    }
+}
@@ -1,6 +1,5 @@
 use actix_web::{HttpRequest, HttpResponse, Result, http::header, post, web};
 use chrono::Datelike;
-use std::fs;
 
 use futures_util::StreamExt as _;
 // use dicom_object::open_file; // 如果需要解析 DICOM，取消注释
@@ -8,8 +7,12 @@ use crate::AppState;
 use crate::constants::STOW_RS_TAG;
 use common::dicom_file_handler::{classify_and_publish_dicom_messages, process_dicom_memobject};
 use common::dicom_utils::{get_date_value_dicom, get_text_value};
+use common::fixity::compute_checksum;
 use common::message_sender_kafka::KafkaMessagePublisher;
-use common::storage_config::{StorageConfig, dicom_file_path};
+use common::quota::QuotaStatus;
+use common::storage_backend::dicom_backend;
+use common::storage_config::StorageConfig;
+use common::storage_layout::series_layouts;
 use database::dicom_meta::DicomStoreMeta;
 use dicom_dictionary_std::tags;
 use dicom_object::DefaultDicomObject;
@@ -502,30 +505,48 @@ async fn process_multipart_fields(
                                 );
                             }
 
-                            let dir_path = match storage_confg.make_series_dicom_dir(
+                            let study_uid = tag_study_uid.unwrap();
+                            let series_uid = seris_instance_uid.unwrap();
+                            // 已有序列沿用原来的目录布局
+                            let storage_layout = series_layouts()
+                                .resolve(tenant_id, &study_uid, series_uid.as_str())
+                                .await;
+                            let filepath = match storage_confg.make_dicom_file_key(
+                                storage_layout,
                                 tenant_id,
-                                &study_date.unwrap().to_string(),
-                                tag_study_uid.unwrap().as_str(),
-                                seris_instance_uid.unwrap().as_str(),
-                                true,
+                                &study_date.unwrap(),
+                                &study_uid,
+                                series_uid.as_str(),
+                                sop_inst_uid.unwrap().as_str(),
                             ) {
-                                Ok(path) => path,
-                                Err(_e) => {
+                                Ok(filepath) => filepath,
+                                Err(e) => {
+                                    error!(log, "Failed to make DICOM file path: {}", e);
                                     return Err(HttpResponse::InternalServerError()
-                                        .body("Failed to create series directory"));
+                                        .body("Failed to save DICOM file"));
                                 }
                             };
-
-                            let filepath =
-                                dicom_file_path(&dir_path, sop_inst_uid.unwrap().as_str());
-                            fs::write(&filepath, datax.into_inner())
-                                .expect("write data to disk failed !");
-
+                            let file_data = datax.into_inner().to_vec();
+                            let file_size = file_data.len() as u64;
+                            let checksum = compute_checksum(&file_data);
+                            let reservation = app_state.quota.check(tenant_id, file_size).await;
+                            if reservation.status() == QuotaStatus::HardExceeded {
+                                return Err(HttpResponse::InsufficientStorage()
+                                    .body(format!("Storage quota of tenant {} exceeded", tenant_id)));
+                            }
+                            if let Err(e) = dicom_backend().put(&filepath, file_data).await {
+                                error!(log, "Failed to save DICOM file {}: {}", &filepath, e);
+                                return Err(HttpResponse::InternalServerError()
+                                    .body("Failed to save DICOM file"));
+                            }
 
                             info!(log, "Saved DICOM file to {}", &filepath);
                             match process_dicom_memobject(
                                 &mut loaded_object,
                                 &filepath,
+                                file_size,
+                                &checksum,
+                                storage_layout,
                                 tenant_id,
                                 &storage_confg,
                             )
@@ -539,6 +560,7 @@ async fn process_multipart_fields(
                                     );
                                     metas.push(dicom_meta);
                                     files.push(filepath);
+                                    reservation.commit();
                                 }
                                 Err(e) => {
                                     warn!(
@@ -8,14 +8,17 @@ use actix_web::http::header::ACCEPT;
 use actix_web::{HttpMessage, HttpRequest, HttpResponse, Responder, get, web, web::Path};
 use common::dicom_json_helper;
 use common::redis_key::RedisHelper;
-use common::storage_config::{StorageConfig, dicom_file_path};
+use common::rejection;
+use common::storage_backend::json_backend;
+use common::storage_config::StorageConfig;
+use common::storage_tier::tiered_storage;
 use database::dicom_meta::{DicomJsonMeta, DicomStateMeta};
 use dicom_dictionary_std::tags;
 use dicom_object::OpenFileOptions;
 // use permission_macros::permission_required;
 use common::dicom_json_helper::generate_series_json;
 use slog::{error, info};
-use std::path::PathBuf;
+use std::collections::HashSet;
 use std::time::Instant;
 
 static ACCEPT_DICOM_JSON_TYPE: &str = "application/dicom+json";
@@ -89,6 +92,20 @@ async fn get_study_info_with_cache(
         }
     }
 }
+// 被拒绝说明撤回的实例, 不出现在元数据中也不能读取
+async fn get_rejected_sop_uids(
+    tenant_id: &str,
+    study_uid: &str,
+    app_state: &web::Data<AppState>,
+) -> Result<HashSet<String>, HttpResponse> {
+    rejection::rejected_sop_uids(app_state.db.as_ref(), tenant_id, study_uid)
+        .await
+        .map_err(|e| {
+            error!(app_state.log, "Failed to retrieve rejected instances: {}", e);
+            HttpResponse::InternalServerError()
+                .body(format!("Failed to retrieve rejected instances: {}", e))
+        })
+}
 async fn get_series_json_meta(
     tenant_id: &str,
     study_uid: &str,
@@ -185,20 +202,20 @@ async fn retrieve_study_metadata(
     }
 
     //  从缓存中加载study_info
-    let study_info = match get_study_info_with_cache(&tenant_id, &study_uid, &app_state ).await
+    let study_infos = match get_study_info_with_cache(&tenant_id, &study_uid, &app_state ).await
     {
         Ok(info) => info,
         Err(response) => return response,
     };
-    if study_info.is_empty() {
+    if study_infos.is_empty() {
         return HttpResponse::NotFound().body(format!(
             "retrieve_study_metadata Study not found in database retry after 30 seconds: {},{}",
             tenant_id, study_uid
         ));
     }
 
-    info!(log, "Study Info: {:?}", study_info.first());
-    let study_info = match study_info.first() {
+    info!(log, "Study Info: {:?}", study_infos.first());
+    let study_info = match study_infos.first() {
         Some(v) => v,
         None => {
             return HttpResponse::NotFound().body(format!(
@@ -209,47 +226,43 @@ async fn retrieve_study_metadata(
     };
 
     let storage_config = StorageConfig::make_storage_config(&app_state.config);
-
-    let json_path = match storage_config.json_metadata_path_for_study(study_info, false) {
-        Ok(v) => v,
-        Err(e) => {
-            return HttpResponse::InternalServerError()
-                .body(format!("Failed to compute json_metadata_for_study: {}", e));
+    let json_backend = json_backend();
+    let json_path = storage_config.json_metadata_key_for_study(study_info);
+    for series_info in &study_infos {
+        if let Ok(series_key) = storage_config.dicom_series_key(series_info) {
+            app_state.series_access.touch(&series_key, series_info);
         }
-    };
-
-    let dicom_dir = match storage_config.dicom_study_dir(study_info, false) {
-        Ok(v) => v,
-        Err(e) => {
-            return HttpResponse::InternalServerError()
-                .body(format!("Failed to compute DICOM directory: {}", e));
-        }
-    };
+    }
 
     // 判断JSON是否生成
     let db_json = get_series_json_meta(&tenant_id, &study_uid, &study_uid, &app_state).await;
-    if db_json.is_some() && std::path::Path::new(&json_path).exists() {
-        let read_context = std::fs::read_to_string(&json_path);
-        if read_context.is_ok() {
-            return HttpResponse::Ok()
-                .content_type(ACCEPT_DICOM_JSON_TYPE)
-                .body(read_context.unwrap());
-        }
+    if db_json.is_some()
+        && let Ok(content) = json_backend.get(&json_path).await
+    {
+        return HttpResponse::Ok()
+            .content_type(ACCEPT_DICOM_JSON_TYPE)
+            .body(content);
     }
     // 重新生成JSON
-    if !std::path::Path::new(&json_path).exists() {
-        let dicom_path = PathBuf::from(&dicom_dir);
-        let json_path = PathBuf::from(&json_path);
-        info!(log, "DICOM directory: {:?}", dicom_path);
-        if let Err(e) = dicom_json_helper::generate_study_json(&tenant_id,study_uid.as_str(), &dicom_path, &json_path) {
-            return HttpResponse::InternalServerError().body(format!(
+    if !json_backend.exists(&json_path).await.unwrap_or(false) {
+        info!(log, "DICOM directory: {:?}", storage_config.dicom_study_key(study_info));
+        let rejected = match get_rejected_sop_uids(&tenant_id, &study_uid, &app_state).await {
+            Ok(rejected) => rejected,
+            Err(response) => return response,
+        };
+        return match dicom_json_helper::generate_study_json_from_storage(&study_infos, &rejected)
+            .await
+        {
+            Ok(content) => HttpResponse::Ok()
+                .content_type(ACCEPT_DICOM_JSON_TYPE)
+                .body(content),
+            Err(e) => HttpResponse::InternalServerError().body(format!(
                 "retrieve_study_metadata Failed to generate JSON file: {}: {}",
-                json_path.display(),
-                e
-            ));
-        }
+                json_path, e
+            )),
+        };
     }
-    match std::fs::read_to_string(&json_path) {
+    match json_backend.get(&json_path).await {
         Ok(content) => HttpResponse::Ok()
             .content_type(ACCEPT_DICOM_JSON_TYPE)
             .body(content),
@@ -484,30 +497,26 @@ async fn retrieve_series_metadata(
     }
 
     let storage_config = StorageConfig::make_storage_config(&app_state.config );
+    if let Ok(series_key) = storage_config.dicom_series_key(&series_info) {
+        app_state.series_access.touch(&series_key, &series_info);
+    }
 
-    let json_file_path = match storage_config.json_metadata_path_for_series(&series_info, true) {
-        Ok(v) => v,
-        Err(e) => {
-            return HttpResponse::InternalServerError()
-                .body(format!("Failed to generate JSON directory: {}", e));
-        }
-    };
+    let json_file_path = storage_config.json_metadata_key_for_series(&series_info);
     //如果json_file_path 存在,则输出json
-    if std::path::Path::new(&json_file_path).exists() {
-        match std::fs::read_to_string(&json_file_path) {
-            Ok(json_content) => {
-                info!(log, "JSON file found: {}", json_file_path);
-                return HttpResponse::Ok()
-                    .content_type(ACCEPT_DICOM_JSON_TYPE)
-                    .body(json_content);
-            }
-            Err(_) => {}
-        }
+    if let Ok(json_content) = json_backend().get(&json_file_path).await {
+        info!(log, "JSON file found: {}", json_file_path);
+        return HttpResponse::Ok()
+            .content_type(ACCEPT_DICOM_JSON_TYPE)
+            .body(json_content);
     }
 
     info!(log, "Study Info: {:?}", study_info);
 
-    match generate_series_json(&series_info).await {
+    let rejected = match get_rejected_sop_uids(&tenant_id, &study_uid, &app_state).await {
+        Ok(rejected) => rejected,
+        Err(response) => return response,
+    };
+    match generate_series_json(&series_info, &rejected).await {
         Ok(json_str) => HttpResponse::Ok()
             .content_type(ACCEPT_DICOM_JSON_TYPE)
             .body(json_str),
@@ -657,18 +666,41 @@ async fn retrieve_instance_impl(
 
     info!(log, "Series Info: {:?}", series_info);
 
+    match get_rejected_sop_uids(&tenant_id, &study_uid, &app_state).await {
+        Ok(rejected) if rejected.contains(&sop_uid) => {
+            return HttpResponse::NotFound()
+                .body(format!("Instance has been rejected: {}", sop_uid));
+        }
+        Ok(_) => {}
+        Err(response) => return response,
+    }
+
     let storage_config = StorageConfig::make_storage_config(&app_state.config );
 
-    let dicom_dir = match storage_config.dicom_series_dir(&series_info, false) {
+    let (dicom_dir, dicom_file) = match storage_config
+        .dicom_series_key(&series_info)
+        .and_then(|dir| Ok((dir, storage_config.dicom_file_key(&series_info, &sop_uid)?)))
+    {
         Ok(v) => v,
         Err(e) => {
             return HttpResponse::InternalServerError()
-                .body(format!("Failed to generate DICOM directory: {}", e));
+                .body(format!("Failed to locate DICOM file {}: {}", sop_uid, e));
         }
     };
-
-    let dicom_file = dicom_file_path(&dicom_dir, &sop_uid);
-    match OpenFileOptions::new().open_file(&dicom_file) {
+    // 文件可能已迁移到其他存储层
+    let local_file = match tiered_storage().local_file(&dicom_file).await {
+        Ok((tier, local_file)) => {
+            app_state.series_access.touch(&dicom_dir, &series_info);
+            app_state
+                .series_access
+                .accessed_from(&tier, &dicom_dir, &series_info);
+            local_file
+        }
+        Err(_) => {
+            return HttpResponse::NotFound().body(format!("DICOM file not found: {}", &dicom_file));
+        }
+    };
+    match OpenFileOptions::new().open_file(local_file.path()) {
         Ok(obj) => match obj.get(tags::PIXEL_DATA) {
             Some(element) => match element.to_bytes() {
                 Ok(pxl_data) => HttpResponse::Ok()
@@ -0,0 +1,302 @@
+//! Calling / Called AE 访问控制
+
+use common::logevents::AssociationRejectEvent;
+use common::message_sender::MessagePublisher;
+use common::message_sender_kafka::KafkaMessagePublisher;
+use common::server_config::AeRegistryEntry;
+use common::utils::get_logger;
+use dicom_ul::association::server::AccessControl;
+use dicom_ul::pdu::{AssociationRJServiceUserReason, UserIdentity};
+use slog::{o, warn};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+/// C-STORE 响应状态: Refused: SOP Class not supported
+pub const STATUS_SOP_CLASS_NOT_SUPPORTED: u16 = 0x0122;
+
+/// 被拒绝的关联信息, 关联建立失败后用于写入收图日志
+#[derive(Debug, Clone)]
+pub struct AccessRejection {
+    pub calling_ae: String,
+    pub called_ae: String,
+    pub reason: AssociationRJServiceUserReason,
+}
+
+/// 基于 AE 注册表的访问控制, 每个连接创建一个实例(需要对端 IP)
+#[derive(Debug, Clone)]
+pub struct AeAccessControl {
+    registry: Vec<AeRegistryEntry>,
+    peer_ip: IpAddr,
+    expected_calling_ae: Option<String>,
+    rejection: Arc<Mutex<Option<AccessRejection>>>,
+}
+
+impl AeAccessControl {
+    pub fn new(registry: Vec<AeRegistryEntry>, peer_ip: IpAddr) -> Self {
+        AeAccessControl {
+            registry,
+            peer_ip,
+            expected_calling_ae: None,
+            rejection: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// TLS 客户端证书绑定了 AE 时, 只允许以该 Calling AE 建立关联
+    pub fn with_expected_calling_ae(mut self, ae_title: Option<String>) -> Self {
+        self.expected_calling_ae = ae_title;
+        self
+    }
+
+    /// 返回拒绝信息的共享句柄, ae_access_control 会消费掉 self
+    pub fn rejection(&self) -> Arc<Mutex<Option<AccessRejection>>> {
+        Arc::clone(&self.rejection)
+    }
+}
+
+impl AccessControl for AeAccessControl {
+    fn check_access(
+        &self,
+        this_ae_title: &str,
+        calling_ae_title: &str,
+        called_ae_title: &str,
+        _user_identity: Option<&UserIdentity>,
+    ) -> Result<(), AssociationRJServiceUserReason> {
+        let result = match &self.expected_calling_ae {
+            Some(expected) if expected.trim() != calling_ae_title.trim() => {
+                Err(AssociationRJServiceUserReason::CallingAETitleNotRecognized)
+            }
+            _ => check_ae(
+                &self.registry,
+                self.peer_ip,
+                this_ae_title,
+                calling_ae_title,
+                called_ae_title,
+            ),
+        };
+        if let Err(reason) = &result {
+            if let Ok(mut rejection) = self.rejection.lock() {
+                *rejection = Some(AccessRejection {
+                    calling_ae: calling_ae_title.trim().to_string(),
+                    called_ae: called_ae_title.trim().to_string(),
+                    reason: reason.clone(),
+                });
+            }
+        }
+        result
+    }
+}
+
+/// 校验 Called AE 与本地 AE 一致, 且 Calling AE 在注册表中并来自允许的地址.
+/// 注册表为空时只校验 Called AE.
+pub fn check_ae(
+    registry: &[AeRegistryEntry],
+    peer_ip: IpAddr,
+    this_ae_title: &str,
+    calling_ae_title: &str,
+    called_ae_title: &str,
+) -> Result<(), AssociationRJServiceUserReason> {
+    if called_ae_title.trim() != this_ae_title.trim() {
+        return Err(AssociationRJServiceUserReason::CalledAETitleNotRecognized);
+    }
+    if registry.is_empty() {
+        return Ok(());
+    }
+    match find_entry(registry, calling_ae_title, peer_ip) {
+        Some(_) => Ok(()),
+        None => Err(AssociationRJServiceUserReason::CallingAETitleNotRecognized),
+    }
+}
+
+/// 按 Calling AE 及来源地址查找注册表项
+pub fn find_entry<'a>(
+    registry: &'a [AeRegistryEntry],
+    calling_ae_title: &str,
+    peer_ip: IpAddr,
+) -> Option<&'a AeRegistryEntry> {
+    let calling_ae_title = calling_ae_title.trim();
+    registry.iter().find(|entry| {
+        entry.ae_title.trim() == calling_ae_title
+            && (entry.ip_ranges.is_empty()
+                || entry.ip_ranges.iter().any(|range| ip_in_range(peer_ip, range)))
+    })
+}
+
+/// 判断 IP 是否在指定范围内, range 支持单个地址或 CIDR
+pub fn ip_in_range(ip: IpAddr, range: &str) -> bool {
+    let range = range.trim();
+    let (addr, prefix) = match range.split_once('/') {
+        Some((addr, prefix)) => match prefix.parse::<u32>() {
+            Ok(prefix) => (addr, Some(prefix)),
+            Err(_) => return false,
+        },
+        None => (range, None),
+    };
+    let Ok(network) = addr.parse::<IpAddr>() else {
+        return false;
+    };
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let prefix = prefix.unwrap_or(32);
+            if prefix > 32 {
+                return false;
+            }
+            let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let prefix = prefix.unwrap_or(128);
+            if prefix > 128 {
+                return false;
+            }
+            let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+/// 注册表项未限制 SOP Class 或包含该 SOP Class 时允许存储
+pub fn is_sop_class_allowed(entry: Option<&AeRegistryEntry>, sop_class_uid: &str) -> bool {
+    match entry {
+        None => true,
+        Some(entry) => {
+            entry.sop_classes.is_empty()
+                || entry
+                    .sop_classes
+                    .iter()
+                    .any(|uid| uid.trim() == sop_class_uid.trim_end_matches('\0'))
+        }
+    }
+}
+
+/// sop_class_uid 为空表示关联被拒绝, 否则表示该 SOP Class 的 C-STORE 被拒绝
+pub fn make_reject_event(
+    tenant_id: &str,
+    calling_ae: &str,
+    called_ae: &str,
+    source_ip: &str,
+    sop_class_uid: &str,
+    reason: &str,
+) -> AssociationRejectEvent {
+    AssociationRejectEvent {
+        trace_id: uuid::Uuid::new_v4().to_string(),
+        event_type: if sop_class_uid.is_empty() {
+            "association_rejected".to_string()
+        } else {
+            "cstore_rejected".to_string()
+        },
+        tenant_id: tenant_id.to_string(),
+        calling_ae: calling_ae.to_string(),
+        called_ae: called_ae.to_string(),
+        source_ip: source_ip.to_string(),
+        sop_class_uid: sop_class_uid.to_string(),
+        reason: reason.to_string(),
+        created_time: chrono::Local::now().naive_local(),
+    }
+}
+
+/// 将拒绝事件写入收图日志队列
+pub async fn publish_reject_event(
+    log_producer: &KafkaMessagePublisher,
+    event: AssociationRejectEvent,
+) {
+    let rlogger = get_logger();
+    let logger = rlogger.new(o!("wado-storescp"=>"access_control"));
+    warn!(
+        logger,
+        "Rejected {} from {}: calling AE: {}, called AE: {}, SOP class: {}",
+        event.event_type,
+        event.source_ip,
+        event.calling_ae,
+        event.called_ae,
+        event.sop_class_uid;
+        "reason" => &event.reason
+    );
+    if let Err(e) = log_producer.send_reject_messages(&[event]).await {
+        warn!(logger, "Failed to publish reject event: {}", e);
+    }
+}
+
+/// 关联建立失败时, 若是访问控制拒绝则写入收图日志
+pub async fn report_association_rejection(
+    rejection: &Arc<Mutex<Option<AccessRejection>>>,
+    log_producer: &KafkaMessagePublisher,
+    source_ip: &str,
+) {
+    let rejection = match rejection.lock() {
+        Ok(mut rejection) => rejection.take(),
+        Err(_) => None,
+    };
+    if let Some(rejection) = rejection {
+        let event = make_reject_event(
+            "",
+            &rejection.calling_ae,
+            &rejection.called_ae,
+            source_ip,
+            "",
+            &format!("{:?}", rejection.reason),
+        );
+        publish_reject_event(log_producer, event).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(ae_title: &str, ip_ranges: &[&str], sop_classes: &[&str]) -> AeRegistryEntry {
+        AeRegistryEntry {
+            ae_title: ae_title.to_string(),
+            ip_ranges: ip_ranges.iter().map(|s| s.to_string()).collect(),
+            sop_classes: sop_classes.iter().map(|s| s.to_string()).collect(),
+            tenant_id: "tenant1".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_ip_in_range() {
+        let ip: IpAddr = "192.168.1.20".parse().unwrap();
+        assert!(ip_in_range(ip, "192.168.1.0/24"));
+        assert!(ip_in_range(ip, "192.168.1.20"));
+        assert!(ip_in_range(ip, "0.0.0.0/0"));
+        assert!(!ip_in_range(ip, "192.168.2.0/24"));
+        assert!(!ip_in_range(ip, "192.168.1.0/33"));
+        assert!(!ip_in_range(ip, "::1"));
+    }
+
+    #[test]
+    fn test_check_ae() {
+        let registry = vec![entry("CT01", &["10.0.0.0/8"], &[])];
+        let ip: IpAddr = "10.1.2.3".parse().unwrap();
+        let other_ip: IpAddr = "172.16.0.1".parse().unwrap();
+
+        assert!(check_ae(&registry, ip, "STORE-SCP", "CT01", "STORE-SCP").is_ok());
+        assert_eq!(
+            check_ae(&registry, ip, "STORE-SCP", "CT01", "OTHER"),
+            Err(AssociationRJServiceUserReason::CalledAETitleNotRecognized)
+        );
+        assert_eq!(
+            check_ae(&registry, ip, "STORE-SCP", "MR01", "STORE-SCP"),
+            Err(AssociationRJServiceUserReason::CallingAETitleNotRecognized)
+        );
+        assert_eq!(
+            check_ae(&registry, other_ip, "STORE-SCP", "CT01", "STORE-SCP"),
+            Err(AssociationRJServiceUserReason::CallingAETitleNotRecognized)
+        );
+        // 注册表为空时只校验 Called AE
+        assert!(check_ae(&[], other_ip, "STORE-SCP", "ANY", "STORE-SCP").is_ok());
+    }
+
+    #[test]
+    fn test_is_sop_class_allowed() {
+        let ct = "1.2.840.10008.5.1.4.1.1.2";
+        let mr = "1.2.840.10008.5.1.4.1.1.4";
+        let limited = entry("CT01", &[], &[ct]);
+        let unlimited = entry("CT02", &[], &[]);
+
+        assert!(is_sop_class_allowed(Some(&limited), ct));
+        assert!(!is_sop_class_allowed(Some(&limited), mr));
+        assert!(is_sop_class_allowed(Some(&unlimited), mr));
+        assert!(is_sop_class_allowed(None, mr));
+    }
+}
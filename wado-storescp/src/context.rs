@@ -0,0 +1,44 @@
+//! 所有关联共享的运行时上下文
+
+use crate::limits::AssociationLimiter;
+use common::message_sender_kafka::KafkaMessagePublisher;
+use common::quota::QuotaGuard;
+use common::server_config::AppConfig;
+use std::sync::Arc;
+
+/// 配置、Kafka 生产者、并发限制及配额检查在进程内只创建一次, 由所有关联共享
+pub struct ScpContext {
+    pub app_config: AppConfig,
+    pub storage_producer: KafkaMessagePublisher,
+    pub log_producer: KafkaMessagePublisher,
+    /// 未配置 topic_study_complete 时为 None, 不发布检查完成事件
+    pub study_complete_producer: Option<KafkaMessagePublisher>,
+    pub limiter: Arc<AssociationLimiter>,
+    pub quota: Arc<QuotaGuard>,
+}
+
+impl ScpContext {
+    pub fn new(app_config: AppConfig, quota: Arc<QuotaGuard>) -> Arc<Self> {
+        let queue_config = &app_config.message_queue;
+        let storage_producer = KafkaMessagePublisher::new(queue_config.topic_main.clone());
+        let log_producer = KafkaMessagePublisher::new(queue_config.topic_dicom_receive.clone());
+        let study_complete_producer = queue_config
+            .topic_study_complete
+            .as_ref()
+            .filter(|topic| !topic.is_empty())
+            .map(|topic| KafkaMessagePublisher::new(topic.clone()));
+        let limits = app_config
+            .dicom_store_scp
+            .limits
+            .clone()
+            .unwrap_or_default();
+        Arc::new(ScpContext {
+            limiter: AssociationLimiter::new(limits),
+            quota,
+            app_config,
+            storage_producer,
+            log_producer,
+            study_complete_producer,
+        })
+    }
+}
@@ -0,0 +1,268 @@
+//! 关联并发限制及 ARTIM 超时
+//!
+//! 关联建立前通过 peek 读取 A-ASSOCIATE-RQ 中的 Calling AE (不消费数据),
+//! 超过全局或单 AE 上限时直接回复 A-ASSOCIATE-RJ (transient) 并关闭连接.
+
+use common::server_config::AssociationLimitsConfig;
+use dicom_ul::pdu::{
+    write_pdu, AssociationRJ, AssociationRJResult, AssociationRJServiceProviderPresentationReason,
+    AssociationRJSource, Pdu,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// A-ASSOCIATE-RQ: PDU 头(6) + 协议版本(2) + 保留(2) + Called AE(16) + Calling AE(16)
+const ASSOCIATE_RQ_PREFIX_LEN: usize = 42;
+const ASSOCIATE_RQ_PDU_TYPE: u8 = 0x01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitRejection {
+    GlobalLimit,
+    AeLimit,
+}
+
+pub struct AssociationLimiter {
+    config: AssociationLimitsConfig,
+    global: Arc<Semaphore>,
+    per_ae: Mutex<HashMap<String, usize>>,
+}
+
+/// 关联许可, 关联结束时释放
+pub struct AssociationPermit {
+    limiter: Arc<AssociationLimiter>,
+    calling_ae: String,
+    _global: OwnedSemaphorePermit,
+}
+
+impl Drop for AssociationPermit {
+    fn drop(&mut self) {
+        self.limiter.release_ae(&self.calling_ae);
+    }
+}
+
+impl AssociationLimiter {
+    pub fn new(config: AssociationLimitsConfig) -> Arc<Self> {
+        Arc::new(AssociationLimiter {
+            global: Arc::new(Semaphore::new(config.max_associations.max(1))),
+            config,
+            per_ae: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn artim_timeout(&self) -> Duration {
+        Duration::from_secs(self.config.artim_timeout_secs)
+    }
+
+    /// 0 表示不启用空闲超时
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        match self.config.idle_timeout_secs {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    fn try_acquire_ae(&self, calling_ae: &str) -> bool {
+        if self.config.max_associations_per_ae == 0 {
+            return true;
+        }
+        let Ok(mut per_ae) = self.per_ae.lock() else {
+            return true;
+        };
+        let count = per_ae.entry(calling_ae.to_string()).or_insert(0);
+        if *count >= self.config.max_associations_per_ae {
+            return false;
+        }
+        *count += 1;
+        true
+    }
+
+    fn release_ae(&self, calling_ae: &str) {
+        if self.config.max_associations_per_ae == 0 {
+            return;
+        }
+        if let Ok(mut per_ae) = self.per_ae.lock() {
+            if let Some(count) = per_ae.get_mut(calling_ae) {
+                *count = count.saturating_sub(1);
+                if *count == 0 {
+                    per_ae.remove(calling_ae);
+                }
+            }
+        }
+    }
+
+    /// 先检查单 AE 上限, 再获取全局许可; 全局许可不足时最多排队 queue_timeout_secs
+    pub async fn acquire(
+        self: &Arc<Self>,
+        calling_ae: &str,
+    ) -> Result<AssociationPermit, LimitRejection> {
+        if !self.try_acquire_ae(calling_ae) {
+            return Err(LimitRejection::AeLimit);
+        }
+        let global = match Arc::clone(&self.global).try_acquire_owned() {
+            Ok(permit) => Some(permit),
+            Err(_) if self.config.queue_timeout_secs > 0 => {
+                let queue_timeout = Duration::from_secs(self.config.queue_timeout_secs);
+                match tokio::time::timeout(queue_timeout, Arc::clone(&self.global).acquire_owned())
+                    .await
+                {
+                    Ok(Ok(permit)) => Some(permit),
+                    _ => None,
+                }
+            }
+            Err(_) => None,
+        };
+        match global {
+            Some(permit) => Ok(AssociationPermit {
+                limiter: Arc::clone(self),
+                calling_ae: calling_ae.to_string(),
+                _global: permit,
+            }),
+            None => {
+                self.release_ae(calling_ae);
+                Err(LimitRejection::GlobalLimit)
+            }
+        }
+    }
+}
+
+/// 在 ARTIM 超时内等待 A-ASSOCIATE-RQ 到达并解析 Calling AE, 不消费套接字数据.
+/// 超时或不是 A-ASSOCIATE-RQ 时返回 None.
+pub async fn peek_calling_ae(socket: &TcpStream, artim_timeout: Duration) -> Option<String> {
+    let mut buf = [0u8; ASSOCIATE_RQ_PREFIX_LEN];
+    let peek = async {
+        loop {
+            let n = socket.peek(&mut buf).await.ok()?;
+            if n == 0 {
+                return None;
+            }
+            if n >= ASSOCIATE_RQ_PREFIX_LEN {
+                return Some(());
+            }
+            // 数据尚未完整到达, 稍后重试
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    };
+    tokio::time::timeout(artim_timeout, peek).await.ok()??;
+    parse_calling_ae(&buf)
+}
+
+fn parse_calling_ae(buf: &[u8]) -> Option<String> {
+    if buf.len() < ASSOCIATE_RQ_PREFIX_LEN || buf[0] != ASSOCIATE_RQ_PDU_TYPE {
+        return None;
+    }
+    let calling_ae = String::from_utf8_lossy(&buf[26..42]).trim().to_string();
+    Some(calling_ae)
+}
+
+/// 回复 A-ASSOCIATE-RJ (transient, local limit exceeded)
+pub async fn reject_transient(socket: &mut TcpStream) -> std::io::Result<()> {
+    let pdu = Pdu::AssociationRJ(AssociationRJ {
+        result: AssociationRJResult::Transient,
+        source: AssociationRJSource::ServiceProviderPresentation(
+            AssociationRJServiceProviderPresentationReason::LocalLimitExceeded,
+        ),
+    });
+    let mut data = Vec::new();
+    write_pdu(&mut data, &pdu)
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    socket.write_all(&data).await?;
+    socket.shutdown().await
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdmitError {
+    /// ARTIM 超时内未收到 A-ASSOCIATE-RQ
+    ArtimTimeout,
+    Limit {
+        calling_ae: String,
+        rejection: LimitRejection,
+    },
+}
+
+/// 关联准入: 在 ARTIM 超时内读取 Calling AE 并获取并发许可,
+/// 超过上限时回复 A-ASSOCIATE-RJ (transient)
+pub async fn admit(
+    limiter: &Arc<AssociationLimiter>,
+    socket: &mut TcpStream,
+) -> Result<(String, AssociationPermit), AdmitError> {
+    let calling_ae = peek_calling_ae(socket, limiter.artim_timeout())
+        .await
+        .ok_or(AdmitError::ArtimTimeout)?;
+    match limiter.acquire(&calling_ae).await {
+        Ok(permit) => Ok((calling_ae, permit)),
+        Err(rejection) => {
+            let _ = reject_transient(socket).await;
+            Err(AdmitError::Limit {
+                calling_ae,
+                rejection,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_config(max_associations: usize, max_associations_per_ae: usize) -> AssociationLimitsConfig {
+        AssociationLimitsConfig {
+            max_associations,
+            max_associations_per_ae,
+            queue_timeout_secs: 0,
+            artim_timeout_secs: 30,
+            idle_timeout_secs: 300,
+        }
+    }
+
+    #[test]
+    fn test_idle_timeout_disabled() {
+        let mut config = make_config(1, 0);
+        assert_eq!(
+            AssociationLimiter::new(config.clone()).idle_timeout(),
+            Some(Duration::from_secs(300))
+        );
+        config.idle_timeout_secs = 0;
+        assert_eq!(AssociationLimiter::new(config).idle_timeout(), None);
+    }
+
+    #[test]
+    fn test_parse_calling_ae() {
+        let mut buf = vec![0u8; ASSOCIATE_RQ_PREFIX_LEN];
+        buf[0] = ASSOCIATE_RQ_PDU_TYPE;
+        buf[10..26].copy_from_slice(b"STORE-SCP       ");
+        buf[26..42].copy_from_slice(b"CT01            ");
+        assert_eq!(parse_calling_ae(&buf), Some("CT01".to_string()));
+
+        buf[0] = 0x05;
+        assert_eq!(parse_calling_ae(&buf), None);
+    }
+
+    #[tokio::test]
+    async fn test_per_ae_limit() {
+        let limiter = AssociationLimiter::new(make_config(10, 1));
+        let permit = limiter.acquire("CT01").await.unwrap();
+        assert_eq!(
+            limiter.acquire("CT01").await.err(),
+            Some(LimitRejection::AeLimit)
+        );
+        assert!(limiter.acquire("MR01").await.is_ok());
+        drop(permit);
+        assert!(limiter.acquire("CT01").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_global_limit() {
+        let limiter = AssociationLimiter::new(make_config(1, 0));
+        let permit = limiter.acquire("CT01").await.unwrap();
+        assert_eq!(
+            limiter.acquire("MR01").await.err(),
+            Some(LimitRejection::GlobalLimit)
+        );
+        drop(permit);
+        assert!(limiter.acquire("MR01").await.is_ok());
+    }
+}
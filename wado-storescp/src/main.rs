@@ -1,22 +1,37 @@
 use clap::Parser;
+use common::database_factory::create_db_instance;
+use common::quota::QuotaGuard;
 use common::server_config;
+use common::server_config::DicomTlsConfig;
+use common::storage_layout::series_layouts;
+use common::storage_tier::spawn_cleanup_interrupted_writes;
 use common::utils::{get_logger, setup_logging};
 use dicom_core::{dicom_value, DataElement, VR};
 use dicom_dictionary_std::tags;
 use dicom_encoding::{snafu};
 use dicom_object::{InMemDicomObject, StandardDataDictionary};
-use slog::{error, info, o};
+use slog::{error, info, o, warn};
 use snafu::Report;
 use std::{
-    net::{Ipv4Addr, SocketAddrV4},
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    sync::Arc,
 };
 
+mod access_control;
+mod context;
+mod limits;
+mod mpps;
+mod scp_policy;
 mod store_async;
 mod store_sync;
+mod tls;
 mod transfer;
 
 use store_async::run_store_async;
 use store_sync::run_store_sync;
+use context::ScpContext;
+use limits::AdmitError;
+use tls::PeerIdentity;
 
 /// DICOM C-STORE SCP
 #[derive(Debug, Parser)]
@@ -57,10 +72,22 @@ struct App {
 }
 
 
+/// C-STORE-RQ 命令
+const C_STORE_RQ: u16 = 0x0001;
+/// C-STORE 成功
+const STATUS_STORE_SUCCESS: u16 = 0x0000;
+/// C-STORE 失败: Refused: Out of Resources, 实例未能保存(磁盘已满、存储不可用等)
+const STATUS_OUT_OF_RESOURCES: u16 = 0xA700;
+/// 不支持的 DIMSE 命令: Unrecognized Operation
+const STATUS_UNRECOGNIZED_OPERATION: u16 = 0x0211;
+/// C-CANCEL-RQ 不需要响应
+const C_CANCEL_RQ: u16 = 0x0FFF;
+
 fn create_cstore_response(
     message_id: u16,
     sop_class_uid: &str,
     sop_instance_uid: &str,
+    status: u16,
 ) -> InMemDicomObject<StandardDataDictionary> {
     InMemDicomObject::command_from_element_iter([
         DataElement::new(
@@ -79,7 +106,7 @@ fn create_cstore_response(
             VR::US,
             dicom_value!(U16, [0x0101]),
         ),
-        DataElement::new(tags::STATUS, VR::US, dicom_value!(U16, [0x0000])),
+        DataElement::new(tags::STATUS, VR::US, dicom_value!(U16, [status])),
         DataElement::new(
             tags::AFFECTED_SOP_INSTANCE_UID,
             VR::UI,
@@ -105,6 +132,39 @@ fn create_cecho_response(message_id: u16) -> InMemDicomObject<StandardDataDictio
     ])
 }
 
+/// 不支持的请求命令返回 Unrecognized Operation, 避免 SCU 等到 DIMSE 超时.
+/// 响应命令及 C-CANCEL-RQ 不需要回复, 返回 None
+fn create_unrecognized_response(
+    command_field: u16,
+    message_id: u16,
+) -> Option<InMemDicomObject<StandardDataDictionary>> {
+    if command_field & 0x8000 != 0 || command_field == C_CANCEL_RQ {
+        return None;
+    }
+    Some(InMemDicomObject::command_from_element_iter([
+        DataElement::new(
+            tags::COMMAND_FIELD,
+            VR::US,
+            dicom_value!(U16, [command_field | 0x8000]),
+        ),
+        DataElement::new(
+            tags::MESSAGE_ID_BEING_RESPONDED_TO,
+            VR::US,
+            dicom_value!(U16, [message_id]),
+        ),
+        DataElement::new(
+            tags::COMMAND_DATA_SET_TYPE,
+            VR::US,
+            dicom_value!(U16, [0x0101]),
+        ),
+        DataElement::new(
+            tags::STATUS,
+            VR::US,
+            dicom_value!(U16, [STATUS_UNRECOGNIZED_OPERATION]),
+        ),
+    ]))
+}
+
 #[tokio::main]
 async fn main() {
     let log = setup_logging("dicom-store-scp");
@@ -117,12 +177,37 @@ async fn main() {
         }
     };
     let mut app = App::parse();
-    let scp_config = config.dicom_store_scp;
+    let scp_config = config.dicom_store_scp.clone();
 
     app.port = scp_config.port;
 
     app.calling_ae_title = scp_config.ae_title;
 
+    let tls_config = scp_config.tls;
+    // 配额检查和序列布局查询共享一个数据库连接池, 都不需要时不连接数据库
+    let layouts = series_layouts();
+    let db = if config.quota.is_some() || layouts.needs_db_provider() {
+        match create_db_instance(&config.main_database).await {
+            Ok(db) => Some(db),
+            Err(e) => {
+                error!(log, "create_db_instance failed: {:?}", e);
+                std::process::exit(-2);
+            }
+        }
+    } else {
+        None
+    };
+    let quota = match (&config.quota, &db) {
+        (Some(quota_config), Some(db)) => QuotaGuard::new(Some(quota_config.clone()), db.clone()),
+        _ => QuotaGuard::disabled(),
+    };
+    if let Some(db) = db {
+        layouts.set_db_provider(db);
+    }
+    let ctx = ScpContext::new(config, quota);
+    // 清理上次异常退出时遗留的临时文件
+    spawn_cleanup_interrupted_writes();
+
     info!(log, "License Server Validation Success");
 
     match app.non_blocking {
@@ -130,7 +215,7 @@ async fn main() {
             info!(log, "工作在同步模式");
             // 使用已有的tokio运行时
             //可以设置最大并发连接数等参数
-            run_sync(app).await.unwrap_or_else(|e| {
+            run_sync(app, ctx, tls_config).await.unwrap_or_else(|e| {
                 error!(log, "{:?}", e);
                 std::process::exit(-2);
             });
@@ -149,7 +234,7 @@ async fn main() {
 
             std::thread::spawn(move || {
                 rt.block_on(async {
-                    run_async(app).await.unwrap_or_else(|e| {
+                    run_async(app, ctx, tls_config).await.unwrap_or_else(|e| {
                         error!(log, "{:?}", e);
                         std::process::exit(-2);
                     });
@@ -161,11 +246,15 @@ async fn main() {
     }
 }
 
-async fn run_async(args: App) -> Result<(), Box<dyn std::error::Error>> {
-    use std::sync::Arc;
+async fn run_async(
+    args: App,
+    ctx: Arc<ScpContext>,
+    tls_config: Option<DicomTlsConfig>,
+) -> Result<(), Box<dyn std::error::Error>> {
     let args = Arc::new(args);
     let rlogger = get_logger();
     let logger = rlogger.new(o!("wado-storescp"=>"run_async"));
+    spawn_tls_listener(args.clone(), ctx.clone(), tls_config);
     let listen_addr = SocketAddrV4::new(Ipv4Addr::from(0), args.port);
     let listener = tokio::net::TcpListener::bind(listen_addr).await?;
     info!(
@@ -174,53 +263,199 @@ async fn run_async(args: App) -> Result<(), Box<dyn std::error::Error>> {
     );
 
     loop {
-        let (socket, _addr) = listener.accept().await?;
+        let (mut socket, addr) = listener.accept().await?;
         let args = args.clone();
+        let ctx = ctx.clone();
         let logs = logger.clone();
         tokio::task::spawn(async move {
-            if let Err(e) = run_store_async(socket, &args).await {
+            let Some(_permit) = admit_association(&ctx, &mut socket, addr).await else {
+                return;
+            };
+            if let Err(e) = run_store_async(socket, &args, &ctx, PeerIdentity::plain(addr)).await
+            {
                 error!(logs, "{}", Report::from_error(e));
             }
         });
     }
 }
 
-async fn run_sync(args: App) -> Result<(), Box<dyn std::error::Error>> {
+async fn run_sync(
+    args: App,
+    ctx: Arc<ScpContext>,
+    tls_config: Option<DicomTlsConfig>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let args = Arc::new(args);
     let rlogger = get_logger();
     let logger = rlogger.new(o!("wado-storescp"=>"run_sync"));
+    spawn_tls_listener(args.clone(), ctx.clone(), tls_config);
     let listen_addr = SocketAddrV4::new(Ipv4Addr::from(0), args.port);
-    let listener = std::net::TcpListener::bind(listen_addr)?;
+    let listener = tokio::net::TcpListener::bind(listen_addr).await?;
     info!(
         &logger,
         "{} listening on: tcp://{}", &args.calling_ae_title, listen_addr
     );
 
-    for stream in listener.incoming() {
-        match stream {
-            Ok(scu_stream) => {
-                let tcp_logger = logger.clone();
-                if let Err(e) = run_store_sync(scu_stream, &args).await {
-                    error!(&tcp_logger, "{}", snafu::Report::from_error(e));
-                }
-            }
-            Err(e) => {
-                error!(&logger, "{}", snafu::Report::from_error(e));
+    // 与异步模式相同先做准入控制, 并发关联数由 AssociationLimiter 限制
+    loop {
+        let (mut socket, addr) = listener.accept().await?;
+        let args = args.clone();
+        let ctx = ctx.clone();
+        let logs = logger.clone();
+        tokio::task::spawn(async move {
+            let Some(_permit) = admit_association(&ctx, &mut socket, addr).await else {
+                return;
+            };
+            run_store_blocking(socket, args, ctx, PeerIdentity::plain(addr), logs).await;
+        });
+    }
+}
+
+/// 配置了 TLS 时, 在独立端口上启动 TLS 监听
+fn spawn_tls_listener(args: Arc<App>, ctx: Arc<ScpContext>, tls_config: Option<DicomTlsConfig>) {
+    if let Some(tls_config) = tls_config {
+        tokio::task::spawn(async move {
+            if let Err(e) = run_tls(args, ctx, tls_config).await {
+                let logger = get_logger();
+                error!(logger, "DICOM TLS listener failed: {}", e);
             }
-        }
+        });
     }
+}
+
+async fn run_tls(
+    args: Arc<App>,
+    ctx: Arc<ScpContext>,
+    tls_config: DicomTlsConfig,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let rlogger = get_logger();
+    let logger = rlogger.new(o!("wado-storescp"=>"run_tls"));
+    let acceptor = tls::build_acceptor(&tls_config)?;
+    let listen_addr = SocketAddrV4::new(Ipv4Addr::from(0), tls_config.port);
+    let listener = tokio::net::TcpListener::bind(listen_addr).await?;
+    info!(
+        &logger,
+        "{} listening on: tls://{}", &args.calling_ae_title, listen_addr
+    );
 
-    Ok(())
+    let tls_config = Arc::new(tls_config);
+    loop {
+        let (socket, addr) = listener.accept().await?;
+        let args = args.clone();
+        let acceptor = acceptor.clone();
+        let tls_config = tls_config.clone();
+        let ctx = ctx.clone();
+        let logs = logger.clone();
+        tokio::task::spawn(async move {
+            let (mut stream, peer) = match tls::accept_tls(&acceptor, &tls_config, socket, addr).await
+            {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!(logs, "TLS handshake with {} failed: {}", addr, e);
+                    return;
+                }
+            };
+            info!(
+                logs,
+                "TLS association from {}, certificate subject: {:?}", addr, peer.subject_cn
+            );
+            let Some(_permit) = admit_association(&ctx, &mut stream, addr).await else {
+                return;
+            };
+            if !args.non_blocking {
+                run_store_blocking(stream, args, ctx, peer, logs).await;
+            } else if let Err(e) = run_store_async(stream, &args, &ctx, peer).await {
+                error!(logs, "{}", Report::from_error(e));
+            }
+        });
+    }
 }
 
+/// 同步模式的关联在阻塞线程池中处理, 避免阻塞的套接字读写占用异步运行时的工作线程.
+/// Whatever 不能跨线程传递, 错误在阻塞线程内记录.
+async fn run_store_blocking(
+    stream: tokio::net::TcpStream,
+    args: Arc<App>,
+    ctx: Arc<ScpContext>,
+    peer: PeerIdentity,
+    logger: slog::Logger,
+) {
+    let std_stream = match stream.into_std() {
+        Ok(std_stream) => std_stream,
+        Err(e) => {
+            warn!(logger, "Failed to convert stream: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = std_stream.set_nonblocking(false) {
+        warn!(logger, "Failed to set blocking mode: {}", e);
+        return;
+    }
+    let handle = tokio::runtime::Handle::current();
+    let task_logger = logger.clone();
+    let task = tokio::task::spawn_blocking(move || {
+        if let Err(e) = handle.block_on(run_store_sync(std_stream, &args, &ctx, peer)) {
+            error!(task_logger, "{}", Report::from_error(e));
+        }
+    });
+    if let Err(e) = task.await {
+        error!(logger, "Sync store task failed: {}", e);
+    }
+}
 
+/// 关联准入控制, 被拒绝时写入收图日志
+async fn admit_association(
+    ctx: &ScpContext,
+    socket: &mut tokio::net::TcpStream,
+    addr: SocketAddr,
+) -> Option<limits::AssociationPermit> {
+    let rlogger = get_logger();
+    let logger = rlogger.new(o!("wado-storescp"=>"admit_association"));
+    match limits::admit(&ctx.limiter, socket).await {
+        Ok((_calling_ae, permit)) => Some(permit),
+        Err(AdmitError::ArtimTimeout) => {
+            warn!(logger, "No A-ASSOCIATE-RQ from {} within ARTIM timeout", addr);
+            None
+        }
+        Err(AdmitError::Limit {
+            calling_ae,
+            rejection,
+        }) => {
+            let event = access_control::make_reject_event(
+                "",
+                &calling_ae,
+                &ctx.app_config.dicom_store_scp.ae_title,
+                &addr.ip().to_string(),
+                "",
+                &format!("{:?}", rejection),
+            );
+            access_control::publish_reject_event(&ctx.log_producer, event).await;
+            None
+        }
+    }
+}
 
 #[cfg(test)]
 mod tests {
-    use crate::App;
+    use crate::{create_unrecognized_response, App, STATUS_UNRECOGNIZED_OPERATION};
     use clap::CommandFactory;
+    use dicom_dictionary_std::tags;
 
     #[test]
     fn verify_cli() {
         App::command().debug_assert();
     }
+
+    #[test]
+    fn test_unrecognized_response() {
+        // C-FIND-RQ
+        let rsp = create_unrecognized_response(0x0020, 7).unwrap();
+        let field = |tag| rsp.element(tag).unwrap().uint16().unwrap();
+        assert_eq!(field(tags::COMMAND_FIELD), 0x8020);
+        assert_eq!(field(tags::MESSAGE_ID_BEING_RESPONDED_TO), 7);
+        assert_eq!(field(tags::STATUS), STATUS_UNRECOGNIZED_OPERATION);
+
+        // 响应命令及 C-CANCEL-RQ 不回复
+        assert!(create_unrecognized_response(0x8001, 7).is_none());
+        assert!(create_unrecognized_response(0x0FFF, 7).is_none());
+    }
 }
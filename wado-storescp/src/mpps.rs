@@ -0,0 +1,485 @@
+//! MPPS (Modality Performed Procedure Step) N-CREATE / N-SET SCP
+
+use crate::context::ScpContext;
+use chrono::{NaiveDateTime, NaiveTime};
+use common::database_factory::create_db_instance;
+use common::dicom_utils::{
+    get_bounder_string, get_date_value_dicom, get_text_value, get_time_value_dicom,
+};
+use common::logevents::StudyCompleteEvent;
+use common::message_sender::MessagePublisher;
+use common::message_sender_kafka::KafkaMessagePublisher;
+use common::utils::get_logger;
+use database::dicom_dbprovider::{DbError, DbProvider};
+use database::dicom_dbtype::BoundedString;
+use database::dicom_meta::DicomMppsMeta;
+use dicom_core::{dicom_value, DataElement, Tag, VR};
+use dicom_dictionary_std::tags;
+use dicom_object::{InMemDicomObject, StandardDataDictionary};
+use dicom_transfer_syntax_registry::TransferSyntaxRegistry;
+use dicom_encoding::TransferSyntaxIndex;
+use slog::{info, o, warn};
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+
+pub const MPPS_SOP_CLASS_UID: &str = "1.2.840.10008.3.1.2.3.3";
+
+pub const N_CREATE_RQ: u16 = 0x0140;
+pub const N_SET_RQ: u16 = 0x0120;
+const N_CREATE_RSP: u16 = 0x8140;
+const N_SET_RSP: u16 = 0x8120;
+
+pub const STATUS_SUCCESS: u16 = 0x0000;
+/// Processing failure, 包括修改已处于 COMPLETED/DISCONTINUED 状态的 MPPS
+pub const STATUS_PROCESSING_FAILURE: u16 = 0x0110;
+pub const STATUS_NO_SUCH_OBJECT_INSTANCE: u16 = 0x0112;
+pub const STATUS_DUPLICATE_SOP_INSTANCE: u16 = 0x0111;
+
+/// 数据库实例持有连接池, 所有关联共享
+static MPPS_DB: OnceCell<Arc<dyn DbProvider>> = OnceCell::const_new();
+
+pub fn is_mpps_command(command_field: u16) -> bool {
+    command_field == N_CREATE_RQ || command_field == N_SET_RQ
+}
+
+/// 从 N-CREATE-RQ / N-SET-RQ 命令集中解析出的请求信息, 等待数据集到达后处理
+#[derive(Debug, Clone)]
+pub struct MppsRequest {
+    pub command_field: u16,
+    pub message_id: u16,
+    pub sop_instance_uid: String,
+    /// 命令集缺少 Message ID 或 N-SET 缺少 Requested SOP Instance UID,
+    /// 数据集到达后不处理, 直接回复失败
+    pub invalid: bool,
+}
+
+impl MppsRequest {
+    pub fn from_command(command_field: u16, obj: &InMemDicomObject) -> Self {
+        let message_id = obj
+            .element(tags::MESSAGE_ID)
+            .ok()
+            .and_then(|e| e.to_int::<u16>().ok());
+        // N-CREATE 使用 Affected SOP Instance UID(可选), N-SET 使用 Requested SOP Instance UID
+        let uid_tag = if command_field == N_CREATE_RQ {
+            tags::AFFECTED_SOP_INSTANCE_UID
+        } else {
+            tags::REQUESTED_SOP_INSTANCE_UID
+        };
+        let sop_instance_uid = match get_text_value(obj, uid_tag) {
+            Some(uid) if !uid.is_empty() => Some(uid),
+            _ if command_field == N_CREATE_RQ => {
+                Some(format!("2.25.{}", uuid::Uuid::new_v4().as_u128()))
+            }
+            _ => None,
+        };
+        MppsRequest {
+            command_field,
+            message_id: message_id.unwrap_or(0),
+            invalid: message_id.is_none() || sop_instance_uid.is_none(),
+            sop_instance_uid: sop_instance_uid.unwrap_or_default(),
+        }
+    }
+}
+
+pub fn create_ncreate_response(
+    message_id: u16,
+    sop_instance_uid: &str,
+    status: u16,
+) -> InMemDicomObject<StandardDataDictionary> {
+    create_mpps_response(N_CREATE_RSP, message_id, sop_instance_uid, status)
+}
+
+pub fn create_nset_response(
+    message_id: u16,
+    sop_instance_uid: &str,
+    status: u16,
+) -> InMemDicomObject<StandardDataDictionary> {
+    create_mpps_response(N_SET_RSP, message_id, sop_instance_uid, status)
+}
+
+fn create_mpps_response(
+    command_field: u16,
+    message_id: u16,
+    sop_instance_uid: &str,
+    status: u16,
+) -> InMemDicomObject<StandardDataDictionary> {
+    InMemDicomObject::command_from_element_iter([
+        DataElement::new(
+            tags::AFFECTED_SOP_CLASS_UID,
+            VR::UI,
+            dicom_value!(Str, MPPS_SOP_CLASS_UID),
+        ),
+        DataElement::new(
+            tags::COMMAND_FIELD,
+            VR::US,
+            dicom_value!(U16, [command_field]),
+        ),
+        DataElement::new(
+            tags::MESSAGE_ID_BEING_RESPONDED_TO,
+            VR::US,
+            dicom_value!(U16, [message_id]),
+        ),
+        DataElement::new(
+            tags::COMMAND_DATA_SET_TYPE,
+            VR::US,
+            dicom_value!(U16, [0x0101]),
+        ),
+        DataElement::new(tags::STATUS, VR::US, dicom_value!(U16, [status])),
+        DataElement::new(
+            tags::AFFECTED_SOP_INSTANCE_UID,
+            VR::UI,
+            dicom_value!(Str, sop_instance_uid),
+        ),
+    ])
+}
+
+fn get_datetime(obj: &InMemDicomObject, date_tag: Tag, time_tag: Tag) -> Option<NaiveDateTime> {
+    let date = get_date_value_dicom(obj, date_tag)?;
+    let time = get_time_value_dicom(obj, time_tag).unwrap_or(NaiveTime::MIN);
+    Some(NaiveDateTime::new(date, time))
+}
+
+fn get_sequence_items(obj: &InMemDicomObject, tag: Tag) -> Vec<InMemDicomObject> {
+    obj.element(tag)
+        .ok()
+        .and_then(|e| e.items())
+        .map(|items| items.to_vec())
+        .unwrap_or_default()
+}
+
+fn get_referenced_series(obj: &InMemDicomObject) -> Vec<String> {
+    get_sequence_items(obj, tags::PERFORMED_SERIES_SEQUENCE)
+        .iter()
+        .filter_map(|item| get_text_value(item, tags::SERIES_INSTANCE_UID))
+        .filter(|uid| !uid.is_empty())
+        .collect()
+}
+
+/// 根据 N-CREATE 数据集生成 MPPS 记录
+pub fn make_mpps_meta(
+    tenant_id: &str,
+    sop_instance_uid: &str,
+    obj: &InMemDicomObject,
+    source_ip: &str,
+    source_ae: &str,
+) -> DicomMppsMeta {
+    let scheduled = get_sequence_items(obj, tags::SCHEDULED_STEP_ATTRIBUTES_SEQUENCE);
+    let study_uid = scheduled
+        .iter()
+        .find_map(|item| get_bounder_string::<64>(item, tags::STUDY_INSTANCE_UID));
+    let accession_number = scheduled
+        .iter()
+        .find_map(|item| get_bounder_string::<16>(item, tags::ACCESSION_NUMBER));
+
+    let now = chrono::Local::now().naive_local();
+    DicomMppsMeta {
+        tenant_id: BoundedString::<64>::make_str(tenant_id),
+        sop_instance_uid: BoundedString::<64>::make_str(sop_instance_uid),
+        patient_id: get_bounder_string::<64>(obj, tags::PATIENT_ID),
+        study_uid,
+        accession_number,
+        modality: get_bounder_string::<16>(obj, tags::MODALITY),
+        pps_id: get_bounder_string::<16>(obj, tags::PERFORMED_PROCEDURE_STEP_ID),
+        pps_status: get_bounder_string::<16>(obj, tags::PERFORMED_PROCEDURE_STEP_STATUS)
+            .unwrap_or_else(|| BoundedString::make_str(DicomMppsMeta::STATUS_IN_PROGRESS)),
+        pps_description: get_bounder_string::<64>(
+            obj,
+            tags::PERFORMED_PROCEDURE_STEP_DESCRIPTION,
+        ),
+        performed_station_ae: get_bounder_string::<16>(obj, tags::PERFORMED_STATION_AE_TITLE),
+        start_datetime: get_datetime(
+            obj,
+            tags::PERFORMED_PROCEDURE_STEP_START_DATE,
+            tags::PERFORMED_PROCEDURE_STEP_START_TIME,
+        ),
+        end_datetime: get_datetime(
+            obj,
+            tags::PERFORMED_PROCEDURE_STEP_END_DATE,
+            tags::PERFORMED_PROCEDURE_STEP_END_TIME,
+        ),
+        referenced_series: get_referenced_series(obj),
+        source_ip: BoundedString::<24>::make_str(source_ip),
+        source_ae: BoundedString::<64>::make_str(source_ae),
+        created_time: now,
+        updated_time: now,
+    }
+}
+
+/// 将 N-SET 数据集中的修改合并到已有的 MPPS 记录
+pub fn apply_mpps_set(meta: &mut DicomMppsMeta, obj: &InMemDicomObject) {
+    if let Some(status) = get_bounder_string::<16>(obj, tags::PERFORMED_PROCEDURE_STEP_STATUS) {
+        meta.pps_status = status;
+    }
+    if let Some(description) =
+        get_bounder_string::<64>(obj, tags::PERFORMED_PROCEDURE_STEP_DESCRIPTION)
+    {
+        meta.pps_description = Some(description);
+    }
+    if let Some(end) = get_datetime(
+        obj,
+        tags::PERFORMED_PROCEDURE_STEP_END_DATE,
+        tags::PERFORMED_PROCEDURE_STEP_END_TIME,
+    ) {
+        meta.end_datetime = Some(end);
+    }
+    let series = get_referenced_series(obj);
+    if !series.is_empty() {
+        meta.referenced_series = series;
+    }
+    meta.updated_time = chrono::Local::now().naive_local();
+}
+
+/// 处理 MPPS 请求, 返回需要回复给 SCU 的响应命令
+pub async fn handle_mpps_request(
+    request: &MppsRequest,
+    buffer: &[u8],
+    ts_uid: &str,
+    tenant_id: &str,
+    source_ip: &str,
+    source_ae: &str,
+    ctx: &ScpContext,
+) -> InMemDicomObject<StandardDataDictionary> {
+    let status = if request.invalid {
+        STATUS_PROCESSING_FAILURE
+    } else {
+        process_mpps(request, buffer, ts_uid, tenant_id, source_ip, source_ae, ctx).await
+    };
+    if request.command_field == N_CREATE_RQ {
+        create_ncreate_response(request.message_id, &request.sop_instance_uid, status)
+    } else {
+        create_nset_response(request.message_id, &request.sop_instance_uid, status)
+    }
+}
+
+async fn process_mpps(
+    request: &MppsRequest,
+    buffer: &[u8],
+    ts_uid: &str,
+    tenant_id: &str,
+    source_ip: &str,
+    source_ae: &str,
+    ctx: &ScpContext,
+) -> u16 {
+    let rlogger = get_logger();
+    let logger = rlogger.new(o!("wado-storescp"=>"mpps"));
+
+    let ts = match TransferSyntaxRegistry.get(ts_uid.trim_end_matches('\0')) {
+        Some(ts) => ts,
+        None => {
+            warn!(logger, "Unsupported transfer syntax for MPPS: {}", ts_uid);
+            return STATUS_PROCESSING_FAILURE;
+        }
+    };
+    let obj = match InMemDicomObject::read_dataset_with_ts(buffer, ts) {
+        Ok(obj) => obj,
+        Err(e) => {
+            warn!(logger, "Failed to read MPPS dataset: {}", e);
+            return STATUS_PROCESSING_FAILURE;
+        }
+    };
+
+    let db = match MPPS_DB
+        .get_or_try_init(|| create_db_instance(&ctx.app_config.main_database))
+        .await
+    {
+        Ok(db) => db,
+        Err(e) => {
+            warn!(logger, "Failed to create database instance: {}", e);
+            return STATUS_PROCESSING_FAILURE;
+        }
+    };
+
+    let existing = db.get_mpps_info(tenant_id, &request.sop_instance_uid).await;
+    let meta = if request.command_field == N_CREATE_RQ {
+        if existing.is_ok() {
+            warn!(logger, "MPPS instance already exists: {}", request.sop_instance_uid);
+            return STATUS_DUPLICATE_SOP_INSTANCE;
+        }
+        make_mpps_meta(tenant_id, &request.sop_instance_uid, &obj, source_ip, source_ae)
+    } else {
+        match existing {
+            Ok(mut meta) => {
+                if meta.is_final() {
+                    warn!(
+                        logger,
+                        "MPPS {} is already {}, N-SET rejected",
+                        request.sop_instance_uid,
+                        meta.pps_status
+                    );
+                    return STATUS_PROCESSING_FAILURE;
+                }
+                apply_mpps_set(&mut meta, &obj);
+                meta
+            }
+            Err(DbError::RecordNotExists(_)) => {
+                warn!(logger, "MPPS instance not found: {}", request.sop_instance_uid);
+                return STATUS_NO_SUCH_OBJECT_INSTANCE;
+            }
+            Err(e) => {
+                warn!(logger, "Failed to query MPPS: {}", e);
+                return STATUS_PROCESSING_FAILURE;
+            }
+        }
+    };
+
+    if let Err(e) = db.save_mpps_info(&meta).await {
+        warn!(logger, "Failed to save MPPS {}: {}", request.sop_instance_uid, e);
+        return STATUS_PROCESSING_FAILURE;
+    }
+    info!(
+        logger,
+        "MPPS {} saved with status {}", request.sop_instance_uid, meta.pps_status
+    );
+
+    if meta.pps_status.as_str() == DicomMppsMeta::STATUS_COMPLETED {
+        if let Some(producer) = &ctx.study_complete_producer {
+            publish_study_complete(&meta, producer).await;
+        }
+    }
+    STATUS_SUCCESS
+}
+
+async fn publish_study_complete(meta: &DicomMppsMeta, producer: &KafkaMessagePublisher) {
+    let rlogger = get_logger();
+    let logger = rlogger.new(o!("wado-storescp"=>"publish_study_complete"));
+    let study_uid = match &meta.study_uid {
+        Some(uid) => uid.as_str().to_string(),
+        None => {
+            warn!(
+                logger,
+                "MPPS {} has no StudyInstanceUID, skip study complete event",
+                meta.sop_instance_uid
+            );
+            return;
+        }
+    };
+    let event = StudyCompleteEvent {
+        tenant_id: meta.tenant_id.as_str().to_string(),
+        mpps_uid: meta.sop_instance_uid.as_str().to_string(),
+        patient_id: meta
+            .patient_id
+            .as_ref()
+            .map(|v| v.as_str().to_string())
+            .unwrap_or_default(),
+        study_uid,
+        accession_number: meta
+            .accession_number
+            .as_ref()
+            .map(|v| v.as_str().to_string())
+            .unwrap_or_default(),
+        referenced_series: meta.referenced_series.clone(),
+        completed_time: meta.updated_time,
+    };
+    if let Err(e) = producer.send_study_complete_messages(&[event]).await {
+        warn!(logger, "Failed to publish study complete event: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom_core::value::DataSetSequence;
+    use dicom_core::PrimitiveValue;
+
+    fn create_ncreate_dataset() -> InMemDicomObject {
+        let scheduled = InMemDicomObject::from_element_iter([
+            DataElement::new(
+                tags::STUDY_INSTANCE_UID,
+                VR::UI,
+                PrimitiveValue::from("1.2.3.4"),
+            ),
+            DataElement::new(tags::ACCESSION_NUMBER, VR::SH, PrimitiveValue::from("ACC001")),
+        ]);
+        InMemDicomObject::from_element_iter([
+            DataElement::new(tags::PATIENT_ID, VR::LO, PrimitiveValue::from("P001")),
+            DataElement::new(tags::MODALITY, VR::CS, PrimitiveValue::from("CT")),
+            DataElement::new(
+                tags::PERFORMED_PROCEDURE_STEP_STATUS,
+                VR::CS,
+                PrimitiveValue::from("IN PROGRESS"),
+            ),
+            DataElement::new(
+                tags::PERFORMED_PROCEDURE_STEP_START_DATE,
+                VR::DA,
+                PrimitiveValue::from("20251125"),
+            ),
+            DataElement::new(
+                tags::PERFORMED_PROCEDURE_STEP_START_TIME,
+                VR::TM,
+                PrimitiveValue::from("143025"),
+            ),
+            DataElement::new(
+                tags::SCHEDULED_STEP_ATTRIBUTES_SEQUENCE,
+                VR::SQ,
+                DataSetSequence::from(vec![scheduled]),
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_make_mpps_meta() {
+        let obj = create_ncreate_dataset();
+        let meta = make_mpps_meta("tenant1", "1.2.3.4.5", &obj, "127.0.0.1", "CT01");
+
+        assert_eq!(meta.pps_status.as_str(), DicomMppsMeta::STATUS_IN_PROGRESS);
+        assert_eq!(meta.patient_id.unwrap().as_str(), "P001");
+        assert_eq!(meta.study_uid.unwrap().as_str(), "1.2.3.4");
+        assert_eq!(meta.accession_number.unwrap().as_str(), "ACC001");
+        assert!(meta.start_datetime.is_some());
+        assert!(meta.referenced_series.is_empty());
+    }
+
+    #[test]
+    fn test_apply_mpps_set() {
+        let obj = create_ncreate_dataset();
+        let mut meta = make_mpps_meta("tenant1", "1.2.3.4.5", &obj, "127.0.0.1", "CT01");
+        assert!(!meta.is_final());
+
+        let series = InMemDicomObject::from_element_iter([DataElement::new(
+            tags::SERIES_INSTANCE_UID,
+            VR::UI,
+            PrimitiveValue::from("1.2.3.4.1"),
+        )]);
+        let set = InMemDicomObject::from_element_iter([
+            DataElement::new(
+                tags::PERFORMED_PROCEDURE_STEP_STATUS,
+                VR::CS,
+                PrimitiveValue::from("COMPLETED"),
+            ),
+            DataElement::new(
+                tags::PERFORMED_SERIES_SEQUENCE,
+                VR::SQ,
+                DataSetSequence::from(vec![series]),
+            ),
+        ]);
+        apply_mpps_set(&mut meta, &set);
+
+        assert!(meta.is_final());
+        assert_eq!(meta.referenced_series, vec!["1.2.3.4.1".to_string()]);
+    }
+
+    #[test]
+    fn test_invalid_nset_command() {
+        let command = InMemDicomObject::command_from_element_iter([DataElement::new(
+            tags::MESSAGE_ID,
+            VR::US,
+            dicom_value!(U16, [3]),
+        )]);
+        let request = MppsRequest::from_command(N_SET_RQ, &command);
+        assert!(request.invalid);
+        assert_eq!(request.message_id, 3);
+
+        let request = MppsRequest::from_command(N_CREATE_RQ, &command);
+        assert!(!request.invalid);
+        assert!(request.sop_instance_uid.starts_with("2.25."));
+    }
+
+    #[test]
+    fn test_create_nset_response() {
+        let rsp = create_nset_response(7, "1.2.3", STATUS_NO_SUCH_OBJECT_INSTANCE);
+        let command = rsp.element(tags::COMMAND_FIELD).unwrap().uint16().unwrap();
+        let status = rsp.element(tags::STATUS).unwrap().uint16().unwrap();
+        assert_eq!(command, N_SET_RSP);
+        assert_eq!(status, STATUS_NO_SUCH_OBJECT_INSTANCE);
+    }
+}
@@ -0,0 +1,253 @@
+//! SOP Class 及传输语法接受策略
+
+use crate::transfer::{ABSTRACT_SYNTAXES, ALL_STORAGE_SOP_CLASSES, SERVICE_SYNTAXES};
+use common::server_config::ScpAcceptanceConfig;
+use dicom_encoding::TransferSyntaxIndex;
+use dicom_transfer_syntax_registry::TransferSyntaxRegistry;
+use dicom_ul::association::server::AccessControl;
+use dicom_ul::association::ServerAssociationOptions;
+use std::collections::HashMap;
+
+const UNCOMPRESSED_TRANSFER_SYNTAXES: &[&str] = &["1.2.840.10008.1.2", "1.2.840.10008.1.2.1"];
+
+#[derive(Debug, Clone)]
+pub struct AcceptancePolicy {
+    abstract_syntaxes: Vec<String>,
+    transfer_syntaxes: Vec<String>,
+    /// SOP Class 单独配置的传输语法, 按优先顺序, 仅作建议.
+    /// dicom-ul 按 SCU 提议的顺序选择传输语法, 已接受的表示上下文不能再拒绝数据
+    class_transfer_syntaxes: HashMap<String, Vec<String>>,
+}
+
+fn push_unique(list: &mut Vec<String>, uid: &str) {
+    let uid = uid.trim();
+    if !uid.is_empty() && !list.iter().any(|v| v == uid) {
+        list.push(uid.to_string());
+    }
+}
+
+fn is_supported_transfer_syntax(uid: &str) -> bool {
+    TransferSyntaxRegistry
+        .get(uid)
+        .map(|ts| !ts.is_unsupported())
+        .unwrap_or(false)
+}
+
+impl AcceptancePolicy {
+    /// 未配置策略时与原有行为一致: 内置 SOP Class 列表 + 全部已支持的传输语法
+    pub fn from_config(config: Option<&ScpAcceptanceConfig>, uncompressed_only: bool) -> Self {
+        let mut abstract_syntaxes = vec![];
+        let mut class_transfer_syntaxes = HashMap::new();
+        match config {
+            Some(config) if config.include_all_storage || !config.sop_classes.is_empty() => {
+                if config.include_all_storage {
+                    for uid in ALL_STORAGE_SOP_CLASSES {
+                        push_unique(&mut abstract_syntaxes, uid);
+                    }
+                }
+                for class in &config.sop_classes {
+                    push_unique(&mut abstract_syntaxes, &class.uid);
+                    if !class.transfer_syntaxes.is_empty() {
+                        class_transfer_syntaxes
+                            .insert(class.uid.trim().to_string(), class.transfer_syntaxes.clone());
+                    }
+                }
+                for uid in SERVICE_SYNTAXES {
+                    push_unique(&mut abstract_syntaxes, uid);
+                }
+            }
+            _ => {
+                for uid in ABSTRACT_SYNTAXES {
+                    push_unique(&mut abstract_syntaxes, uid);
+                }
+            }
+        }
+
+        let mut transfer_syntaxes = vec![];
+        if uncompressed_only {
+            for uid in UNCOMPRESSED_TRANSFER_SYNTAXES {
+                push_unique(&mut transfer_syntaxes, uid);
+            }
+        } else {
+            let configured = config
+                .map(|c| c.transfer_syntaxes.as_slice())
+                .unwrap_or_default();
+            if configured.is_empty() {
+                for ts in TransferSyntaxRegistry.iter() {
+                    if !ts.is_unsupported() {
+                        push_unique(&mut transfer_syntaxes, ts.uid());
+                    }
+                }
+            } else {
+                for uid in configured {
+                    if is_supported_transfer_syntax(uid.trim()) {
+                        push_unique(&mut transfer_syntaxes, uid);
+                    }
+                }
+            }
+            // SOP Class 单独配置的传输语法也需要在关联协商时提供
+            for uids in class_transfer_syntaxes.values() {
+                for uid in uids {
+                    if is_supported_transfer_syntax(uid.trim()) {
+                        push_unique(&mut transfer_syntaxes, uid);
+                    }
+                }
+            }
+        }
+
+        AcceptancePolicy {
+            abstract_syntaxes,
+            transfer_syntaxes,
+            class_transfer_syntaxes,
+        }
+    }
+
+    pub fn apply<'a, A>(
+        &self,
+        mut options: ServerAssociationOptions<'a, A>,
+    ) -> ServerAssociationOptions<'a, A>
+    where
+        A: AccessControl,
+    {
+        for uid in &self.transfer_syntaxes {
+            options = options.with_transfer_syntax(uid.clone());
+        }
+        for uid in &self.abstract_syntaxes {
+            options = options.with_abstract_syntax(uid.clone());
+        }
+        options
+    }
+
+    #[cfg(test)]
+    pub fn abstract_syntaxes(&self) -> &[String] {
+        &self.abstract_syntaxes
+    }
+
+    #[cfg(test)]
+    pub fn transfer_syntaxes(&self) -> &[String] {
+        &self.transfer_syntaxes
+    }
+
+    /// 协商得到的传输语法是否为该 SOP Class 的首选传输语法, 不符合时只记录日志
+    pub fn is_preferred_transfer_syntax(&self, sop_class_uid: &str, ts_uid: &str) -> bool {
+        let sop_class_uid = sop_class_uid.trim_end_matches('\0').trim();
+        let ts_uid = ts_uid.trim_end_matches('\0').trim();
+        match self.class_transfer_syntaxes.get(sop_class_uid) {
+            None => true,
+            Some(uids) => uids.iter().any(|uid| uid.trim() == ts_uid),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::server_config::SopClassPolicy;
+
+    const CT: &str = "1.2.840.10008.5.1.4.1.1.2";
+    const SEG: &str = "1.2.840.10008.5.1.4.1.1.66.4";
+    const EXPLICIT_LE: &str = "1.2.840.10008.1.2.1";
+    const IMPLICIT_LE: &str = "1.2.840.10008.1.2";
+
+    #[test]
+    fn test_default_policy() {
+        let policy = AcceptancePolicy::from_config(None, true);
+        assert_eq!(policy.abstract_syntaxes().len(), ABSTRACT_SYNTAXES.len());
+        assert_eq!(policy.transfer_syntaxes(), &[IMPLICIT_LE, EXPLICIT_LE]);
+        assert!(policy.is_preferred_transfer_syntax(CT, IMPLICIT_LE));
+    }
+
+    #[test]
+    fn test_configured_policy() {
+        let config = ScpAcceptanceConfig {
+            include_all_storage: false,
+            sop_classes: vec![
+                SopClassPolicy {
+                    uid: CT.to_string(),
+                    transfer_syntaxes: vec![EXPLICIT_LE.to_string()],
+                },
+                SopClassPolicy {
+                    uid: SEG.to_string(),
+                    transfer_syntaxes: vec![],
+                },
+            ],
+            transfer_syntaxes: vec![IMPLICIT_LE.to_string(), "1.2.3.invalid".to_string()],
+        };
+        let policy = AcceptancePolicy::from_config(Some(&config), false);
+
+        assert!(policy.abstract_syntaxes().iter().any(|uid| uid == SEG));
+        // 非存储类服务始终接受
+        assert!(
+            policy
+                .abstract_syntaxes()
+                .iter()
+                .any(|uid| uid == "1.2.840.10008.1.1")
+        );
+        assert_eq!(policy.transfer_syntaxes(), &[IMPLICIT_LE, EXPLICIT_LE]);
+        assert!(policy.is_preferred_transfer_syntax(CT, EXPLICIT_LE));
+        assert!(!policy.is_preferred_transfer_syntax(CT, IMPLICIT_LE));
+        assert!(policy.is_preferred_transfer_syntax(SEG, IMPLICIT_LE));
+    }
+
+    #[test]
+    fn test_class_transfer_syntax_is_advisory() {
+        use dicom_ul::association::client::ClientAssociationOptions;
+        use dicom_ul::pdu::PresentationContextResultReason;
+        use std::net::TcpListener;
+
+        let config = ScpAcceptanceConfig {
+            include_all_storage: false,
+            sop_classes: vec![SopClassPolicy {
+                uid: CT.to_string(),
+                transfer_syntaxes: vec![EXPLICIT_LE.to_string()],
+            }],
+            transfer_syntaxes: vec![],
+        };
+        let policy = AcceptancePolicy::from_config(Some(&config), false);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_policy = policy.clone();
+        let server = std::thread::spawn(move || {
+            let (socket, _) = listener.accept().unwrap();
+            let options = server_policy.apply(
+                ServerAssociationOptions::new()
+                    .accept_any()
+                    .ae_title("STORE-SCP"),
+            );
+            let association = options.establish(socket).unwrap();
+            association.presentation_contexts().to_vec()
+        });
+
+        let client = ClientAssociationOptions::new()
+            .calling_ae_title("STORE-SCU")
+            .called_ae_title("STORE-SCP")
+            .with_presentation_context(CT, vec![IMPLICIT_LE, EXPLICIT_LE])
+            .establish(addr)
+            .unwrap();
+        let contexts = server.join().unwrap();
+        client.abort().ok();
+
+        // dicom-ul 按 SCU 的顺序选择传输语法, 表示上下文被接受
+        assert_eq!(contexts.len(), 1);
+        assert_eq!(contexts[0].reason, PresentationContextResultReason::Acceptance);
+        assert_eq!(contexts[0].transfer_syntax.trim_end_matches('\0'), IMPLICIT_LE);
+        // 非首选传输语法只作提示, 不影响已接受上下文上的数据
+        assert!(!policy.is_preferred_transfer_syntax(CT, &contexts[0].transfer_syntax));
+    }
+
+    #[test]
+    fn test_include_all_storage() {
+        let config = ScpAcceptanceConfig {
+            include_all_storage: true,
+            sop_classes: vec![],
+            transfer_syntaxes: vec![],
+        };
+        let policy = AcceptancePolicy::from_config(Some(&config), false);
+        assert_eq!(
+            policy.abstract_syntaxes().len(),
+            ALL_STORAGE_SOP_CLASSES.len() + SERVICE_SYNTAXES.len()
+        );
+        assert!(!policy.transfer_syntaxes().is_empty());
+    }
+}
@@ -1,20 +1,32 @@
-use crate::{create_cecho_response, create_cstore_response, transfer::ABSTRACT_SYNTAXES, App};
+use crate::{
+    create_cecho_response, create_cstore_response, create_unrecognized_response, App, C_STORE_RQ,
+    STATUS_OUT_OF_RESOURCES, STATUS_STORE_SUCCESS,
+};
 
 use common::dicom_file_handler::{classify_and_publish_dicom_messages, process_dicom_buffer};
-use common::message_sender_kafka::KafkaMessagePublisher;
+use common::quota::QuotaStatus;
 use common::storage_config::StorageConfig;
+use crate::access_control::{self, AeAccessControl};
+use crate::context::ScpContext;
+use crate::mpps::{self, MppsRequest};
+use crate::scp_policy::AcceptancePolicy;
+use crate::tls::PeerIdentity;
 use common::utils::get_logger;
-use common::{dicom_file_handler, server_config};
+use common::dicom_file_handler;
 use dicom_core::Tag;
 use dicom_dictionary_std::tags;
 use dicom_encoding::snafu::{OptionExt, Report, ResultExt, Whatever};
 use dicom_object::InMemDicomObject;
-use dicom_transfer_syntax_registry::TransferSyntaxRegistry;
 use dicom_ul::{pdu::PDataValueType, Pdu};
 use slog::{debug, info, o, warn};
 use std::net::TcpStream;
 
-pub async fn run_store_sync(scu_stream: TcpStream, args: &App) -> Result<(), Whatever> {
+pub async fn run_store_sync(
+    scu_stream: TcpStream,
+    args: &App,
+    ctx: &ScpContext,
+    peer_identity: PeerIdentity,
+) -> Result<(), Whatever> {
     let App {
         verbose,
         calling_ae_title,
@@ -27,14 +39,11 @@ pub async fn run_store_sync(scu_stream: TcpStream, args: &App) -> Result<(), Wha
         non_blocking: _non_blocking,
     } = args;
     let verbose = *verbose;
-    let peer = scu_stream.peer_addr().unwrap();
-    let app_config = server_config::load_config().whatever_context("failed to load config")?;
-    let queue_config = &app_config.message_queue;
-    let queue_topic_main = &queue_config.topic_main.as_str();
-    let queue_topic_log = &queue_config.topic_dicom_receive.as_str();
-
-    let storage_producer = KafkaMessagePublisher::new(queue_topic_main.parse().unwrap());
-    let log_producer = KafkaMessagePublisher::new(queue_topic_log.parse().unwrap());
+    let peer = peer_identity.addr;
+    // 配置与 Kafka 生产者在所有关联间共享
+    let app_config = &ctx.app_config;
+    let storage_producer = &ctx.storage_producer;
+    let log_producer = &ctx.log_producer;
     let ip_address = peer.ip().to_string();
 
     let mut instance_buffer: Vec<u8> = Vec::with_capacity(1024 * 1024);
@@ -42,32 +51,29 @@ pub async fn run_store_sync(scu_stream: TcpStream, args: &App) -> Result<(), Wha
     let mut sop_class_uid = "".to_string();
     let mut sop_instance_uid = "".to_string();
     let mut tenant_id = "1234567890".to_string();
-    let mut options = dicom_ul::association::ServerAssociationOptions::new()
-        .accept_any()
+    let ae_registry = &app_config.dicom_store_scp.ae_registry;
+    let access_control = AeAccessControl::new(ae_registry.clone(), peer.ip())
+        .with_expected_calling_ae(peer_identity.ae_title.clone());
+    let rejection = access_control.rejection();
+    let options = dicom_ul::association::ServerAssociationOptions::new()
+        .ae_access_control(access_control)
         .ae_title(calling_ae_title)
         .strict(*strict)
         .max_pdu_length(*max_pdu_length)
         .promiscuous(*promiscuous);
 
-    if *uncompressed_only {
-        options = options
-            .with_transfer_syntax("1.2.840.10008.1.2")
-            .with_transfer_syntax("1.2.840.10008.1.2.1");
-    } else {
-        for ts in TransferSyntaxRegistry.iter() {
-            if !ts.is_unsupported() {
-                options = options.with_transfer_syntax(ts.uid());
-            }
-        }
-    };
+    let policy = AcceptancePolicy::from_config(
+        app_config.dicom_store_scp.acceptance.as_ref(),
+        *uncompressed_only,
+    );
+    let options = policy.apply(options);
 
-    for uid in ABSTRACT_SYNTAXES {
-        options = options.with_abstract_syntax(*uid);
+    let establish_result = options.establish(scu_stream);
+    if establish_result.is_err() {
+        access_control::report_association_rejection(&rejection, log_producer, &ip_address)
+            .await;
     }
-
-    let mut association = options
-        .establish(scu_stream)
-        .whatever_context("could not establish association")?;
+    let mut association = establish_result.whatever_context("could not establish association")?;
 
     let rlogger = get_logger();
     let logger = rlogger.new(o!("wado-storescp"=>"run_store_sync"));
@@ -82,9 +88,29 @@ pub async fn run_store_sync(scu_stream: TcpStream, args: &App) -> Result<(), Wha
         association.presentation_contexts()
     );
 
-    let storage_config = StorageConfig::make_storage_config(&app_config);
+    let storage_config = StorageConfig::make_storage_config(app_config);
     let client_ae_title = association.client_ae_title().to_string();
+    let mut mpps_request: Option<MppsRequest> = None;
+    // 只有 C-STORE-RQ 之后的数据集进入存储流程
+    let mut store_pending = false;
+    let ae_entry =
+        access_control::find_entry(ae_registry, &client_ae_title, peer.ip()).cloned();
+    // TLS 证书映射的租户优先, 其次是 AE 注册表中配置的租户
+    let fixed_tenant_id = peer_identity
+        .tenant_id
+        .clone()
+        .or_else(|| ae_entry.as_ref().map(|entry| entry.tenant_id.clone()));
+    if let Some(fixed_tenant_id) = &fixed_tenant_id {
+        tenant_id = fixed_tenant_id.clone();
+    }
     let mut dicom_message_lists = vec![];
+    // 空闲超时: 超过该时间未收到数据时 receive 返回错误并结束关联
+    if let Err(e) = association
+        .inner_stream()
+        .set_read_timeout(ctx.limiter.idle_timeout())
+    {
+        warn!(logger, "Failed to set idle timeout: {}", e);
+    }
     loop {
         match association.receive() {
             Ok(mut pdu) => {
@@ -119,6 +145,7 @@ pub async fn run_store_sync(scu_stream: TcpStream, args: &App) -> Result<(), Wha
                                     .whatever_context("Missing Command Field")?
                                     .uint16()
                                     .whatever_context("Command Field is not an integer")?;
+                                store_pending = command_field == C_STORE_RQ;
 
                                 if command_field == 0x0030 {
                                     // Handle C-ECHO-RQ
@@ -143,7 +170,18 @@ pub async fn run_store_sync(scu_stream: TcpStream, args: &App) -> Result<(), Wha
                                     association.send(&pdu_response).whatever_context(
                                         "failed to send C-ECHO response object to SCU",
                                     )?;
-                                } else {
+                                } else if mpps::is_mpps_command(command_field) {
+                                    // MPPS N-CREATE / N-SET, 等待数据集到达后处理, 命令无效时回复失败
+                                    let request = MppsRequest::from_command(command_field, &obj);
+                                    if request.invalid {
+                                        warn!(
+                                            logger,
+                                            "Invalid MPPS request, command field: {:#06x}",
+                                            command_field
+                                        );
+                                    }
+                                    mpps_request = Some(request);
+                                } else if command_field == C_STORE_RQ {
                                     msgid = obj
                                         .element(tags::MESSAGE_ID)
                                         .whatever_context("Missing Message ID")?
@@ -184,8 +222,50 @@ pub async fn run_store_sync(scu_stream: TcpStream, args: &App) -> Result<(), Wha
                                     } else {
                                         tenant_id = "1234567890".to_string();
                                     }
-                                }
+                                    // 证书或注册表中配置的租户优先, 防止设备写入其他租户
+                                    if let Some(fixed_tenant_id) = &fixed_tenant_id {
+                                        if &tenant_id != fixed_tenant_id {
+                                            warn!(
+                                                logger,
+                                                "Tenant ID {} overridden by peer identity: {}",
+                                                tenant_id,
+                                                fixed_tenant_id
+                                            );
+                                            tenant_id = fixed_tenant_id.clone();
+                                        }
+                                    }
+                                } else {
+                                    warn!(
+                                        logger,
+                                        "Unsupported DIMSE command {:#06x}, data set will be dropped",
+                                        command_field
+                                    );
+                                    let message_id = obj
+                                        .element(tags::MESSAGE_ID)
+                                        .ok()
+                                        .and_then(|e| e.to_int::<u16>().ok())
+                                        .unwrap_or_default();
+                                    if let Some(rsp) =
+                                        create_unrecognized_response(command_field, message_id)
+                                    {
+                                        let mut rsp_data = Vec::new();
+                                        rsp.write_dataset_with_ts(&mut rsp_data, &ts)
+                                            .whatever_context("could not write response object")?;
 
+                                        let pdu_response = Pdu::PData {
+                                            data: vec![dicom_ul::pdu::PDataValue {
+                                                presentation_context_id: data_value
+                                                    .presentation_context_id,
+                                                value_type: PDataValueType::Command,
+                                                is_last: true,
+                                                data: rsp_data,
+                                            }],
+                                        };
+                                        association
+                                            .send(&pdu_response)
+                                            .whatever_context("failed to send response object to SCU")?;
+                                    }
+                                }
                                 instance_buffer.clear();
                             } else if data_value.value_type == PDataValueType::Data
                                 && data_value.is_last
@@ -199,43 +279,155 @@ pub async fn run_store_sync(scu_stream: TcpStream, args: &App) -> Result<(), Wha
                                     .whatever_context("missing presentation context")?;
                                 let ts = &presentation_context.transfer_syntax;
 
+                                if let Some(request) = mpps_request.take() {
+                                    let rsp = mpps::handle_mpps_request(
+                                        &request,
+                                        &instance_buffer,
+                                        ts,
+                                        &tenant_id,
+                                        &ip_address,
+                                        &client_ae_title,
+                                        ctx,
+                                    )
+                                    .await;
+                                    instance_buffer.clear();
+
+                                    // commands are always in implicit VR LE
+                                    let ts =
+                                        dicom_transfer_syntax_registry::entries::IMPLICIT_VR_LITTLE_ENDIAN
+                                            .erased();
+                                    let mut rsp_data = Vec::new();
+                                    rsp.write_dataset_with_ts(&mut rsp_data, &ts)
+                                        .whatever_context("could not write MPPS response object")?;
+
+                                    let pdu_response = Pdu::PData {
+                                        data: vec![dicom_ul::pdu::PDataValue {
+                                            presentation_context_id: data_value
+                                                .presentation_context_id,
+                                            value_type: PDataValueType::Command,
+                                            is_last: true,
+                                            data: rsp_data,
+                                        }],
+                                    };
+                                    association
+                                        .send(&pdu_response)
+                                        .whatever_context("failed to send MPPS response to SCU")?;
+                                    continue;
+                                }
+                                if !store_pending {
+                                    warn!(logger, "Dropping data set that does not follow a C-STORE-RQ");
+                                    instance_buffer.clear();
+                                    continue;
+                                }
+                                store_pending = false;
+
+                                if !policy.is_preferred_transfer_syntax(&sop_class_uid, ts) {
+                                    warn!(
+                                        logger,
+                                        "Transfer syntax {} is not preferred for SOP Class {}",
+                                        ts,
+                                        sop_class_uid
+                                    );
+                                }
+                                if !access_control::is_sop_class_allowed(
+                                    ae_entry.as_ref(),
+                                    &sop_class_uid,
+                                ) {
+                                    instance_buffer.clear();
+                                    let event = access_control::make_reject_event(
+                                        &tenant_id,
+                                        &client_ae_title,
+                                        calling_ae_title,
+                                        &ip_address,
+                                        &sop_class_uid,
+                                        "SOPClassNotAllowed",
+                                    );
+                                    access_control::publish_reject_event(log_producer, event).await;
+
+                                    // commands are always in implicit VR LE
+                                    let ts =
+                                        dicom_transfer_syntax_registry::entries::IMPLICIT_VR_LITTLE_ENDIAN
+                                            .erased();
+                                    let obj = create_cstore_response(
+                                        msgid,
+                                        &sop_class_uid,
+                                        &sop_instance_uid,
+                                        access_control::STATUS_SOP_CLASS_NOT_SUPPORTED,
+                                    );
+                                    let mut obj_data = Vec::new();
+                                    obj.write_dataset_with_ts(&mut obj_data, &ts)
+                                        .whatever_context("could not write response object")?;
+
+                                    let pdu_response = Pdu::PData {
+                                        data: vec![dicom_ul::pdu::PDataValue {
+                                            presentation_context_id: data_value
+                                                .presentation_context_id,
+                                            value_type: PDataValueType::Command,
+                                            is_last: true,
+                                            data: obj_data,
+                                        }],
+                                    };
+                                    association
+                                        .send(&pdu_response)
+                                        .whatever_context("failed to send response object to SCU")?;
+                                    continue;
+                                }
+
                                 // let obj = InMemDicomObject::read_dataset_with_ts(
                                 //     instance_buffer.as_slice(),
                                 //     TransferSyntaxRegistry.get(ts).unwrap(),
                                 // )
                                 // .whatever_context("failed to read DICOM data object")?;
 
-                                match process_dicom_buffer(
-                                    &instance_buffer,
-                                    &tenant_id,
-                                    ts,
-                                    &sop_instance_uid,
-                                    &sop_class_uid,
-                                    ip_address.clone(),
-                                    client_ae_title.clone(),
-                                    &storage_config,
-                                )
-                                .await
-                                {
-                                    Ok(obj_meta) => {
-                                        info!(
-                                            &logger,
-                                            "Successfully processed DICOM file for SOP instance {}",
-                                            sop_instance_uid
-                                        );
-                                        // 继续执行后续操作（发送C-STORE响应等）
-                                        dicom_message_lists.push(obj_meta);
-                                    }
-                                    Err(e) => {
-                                        warn!(
-                                            &logger,
-                                            "Failed to process DICOM file for SOP instance {}: {}",
-                                            sop_instance_uid,
-                                            e
-                                        );
-                                        // 可以选择是否继续执行后续操作，或者返回错误
+                                // 超出租户硬配额时拒绝, 通知 SCU 存储空间不足
+                                let reservation = ctx.quota.check(&tenant_id, instance_buffer.len() as u64).await;
+                                let store_status = if reservation.status() == QuotaStatus::HardExceeded {
+                                    let event = access_control::make_reject_event(
+                                        &tenant_id,
+                                        &client_ae_title,
+                                        calling_ae_title,
+                                        &ip_address,
+                                        &sop_class_uid,
+                                        "QuotaExceeded",
+                                    );
+                                    access_control::publish_reject_event(log_producer, event).await;
+                                    STATUS_OUT_OF_RESOURCES
+                                } else {
+                                    match process_dicom_buffer(
+                                        &instance_buffer,
+                                        &tenant_id,
+                                        ts,
+                                        &sop_instance_uid,
+                                        &sop_class_uid,
+                                        ip_address.clone(),
+                                        client_ae_title.clone(),
+                                        &storage_config,
+                                    )
+                                    .await
+                                    {
+                                        Ok(obj_meta) => {
+                                            info!(
+                                                &logger,
+                                                "Successfully processed DICOM file for SOP instance {}",
+                                                sop_instance_uid
+                                            );
+                                            // 继续执行后续操作（发送C-STORE响应等）
+                                            dicom_message_lists.push(obj_meta);
+                                            reservation.commit();
+                                            STATUS_STORE_SUCCESS
+                                        }
+                                        Err(e) => {
+                                            warn!(
+                                                &logger,
+                                                "Failed to process DICOM file for SOP instance {}: {}",
+                                                sop_instance_uid,
+                                                e
+                                            );
+                                            // 实例未保存, 通知 SCU 失败以便重发
+                                            STATUS_OUT_OF_RESOURCES
+                                        }
                                     }
-                                }
+                                };
 
                                 if dicom_message_lists.len() >= 10 {
                                     // 根据 SUPPORTED_TRANSFER_SYNTAXES 和 DicomObjectMeta.transfer_syntax_uid 对 dicom_message_lists 分为2组,
@@ -245,8 +437,8 @@ pub async fn run_store_sync(scu_stream: TcpStream, args: &App) -> Result<(), Wha
 
                                     match classify_and_publish_dicom_messages(
                                         &dicom_message_lists,
-                                        &storage_producer,
-                                        &log_producer,
+                                        storage_producer,
+                                        log_producer,
                                     )
                                     .await
                                     {
@@ -273,6 +465,7 @@ pub async fn run_store_sync(scu_stream: TcpStream, args: &App) -> Result<(), Wha
                                     msgid,
                                     &sop_class_uid,
                                     &sop_instance_uid,
+                                    store_status,
                                 );
 
                                 let mut obj_data = Vec::new();
@@ -361,8 +554,8 @@ pub async fn run_store_sync(scu_stream: TcpStream, args: &App) -> Result<(), Wha
 
         match dicom_file_handler::classify_and_publish_dicom_messages(
             &dicom_message_lists,
-            &storage_producer,
-            &log_producer,
+            storage_producer,
+            log_producer,
         )
         .await
         {
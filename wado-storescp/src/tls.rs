@@ -0,0 +1,139 @@
+//! DICOM TLS 监听
+//!
+//! dicom-ul 的关联只接受 TcpStream, 因此 TLS 握手完成后通过进程内的 socketpair
+//! 将解密后的数据转发给普通的 SCP 处理流程, 对端地址及证书身份通过 PeerIdentity 传递.
+//! socketpair 不监听任何端口, 其他进程无法接入.
+
+use common::server_config::{DicomTlsConfig, TlsSubjectMapping};
+use openssl::error::ErrorStack;
+use openssl::nid::Nid;
+use openssl::ssl::{Ssl, SslAcceptor, SslFiletype, SslMethod, SslVerifyMode};
+use openssl::x509::X509Ref;
+use std::net::SocketAddr;
+use std::os::fd::{FromRawFd, IntoRawFd};
+use std::os::unix::net::UnixStream;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_openssl::SslStream;
+
+/// 连接的真实对端信息, TLS 连接时由证书映射得到租户和 AE
+#[derive(Debug, Clone)]
+pub struct PeerIdentity {
+    pub addr: SocketAddr,
+    pub subject_cn: Option<String>,
+    pub tenant_id: Option<String>,
+    pub ae_title: Option<String>,
+}
+
+impl PeerIdentity {
+    pub fn plain(addr: SocketAddr) -> Self {
+        PeerIdentity {
+            addr,
+            subject_cn: None,
+            tenant_id: None,
+            ae_title: None,
+        }
+    }
+}
+
+pub fn build_acceptor(tls_config: &DicomTlsConfig) -> Result<Arc<SslAcceptor>, ErrorStack> {
+    let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls())?;
+    builder.set_certificate_chain_file(&tls_config.cert_file)?;
+    builder.set_private_key_file(&tls_config.key_file, SslFiletype::PEM)?;
+    builder.check_private_key()?;
+    if let Some(ca_file) = &tls_config.ca_file {
+        builder.set_ca_file(ca_file)?;
+        builder.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+    }
+    Ok(Arc::new(builder.build()))
+}
+
+fn subject_common_name(cert: &X509Ref) -> Option<String> {
+    cert.subject_name()
+        .entries_by_nid(Nid::COMMONNAME)
+        .next()
+        .and_then(|entry| entry.data().as_utf8().ok())
+        .map(|cn| cn.to_string())
+}
+
+pub fn find_subject_mapping<'a>(
+    mappings: &'a [TlsSubjectMapping],
+    subject_cn: &str,
+) -> Option<&'a TlsSubjectMapping> {
+    mappings.iter().find(|m| m.subject_cn == subject_cn)
+}
+
+/// 完成 TLS 握手, 返回 socketpair 的 SCP 一端及对端身份.
+/// socketpair 与 TLS 连接之间的数据转发在后台任务中进行.
+pub async fn accept_tls(
+    acceptor: &SslAcceptor,
+    tls_config: &DicomTlsConfig,
+    socket: TcpStream,
+    addr: SocketAddr,
+) -> Result<(TcpStream, PeerIdentity), Box<dyn std::error::Error + Send + Sync>> {
+    let ssl = Ssl::new(acceptor.context())?;
+    let mut tls_stream = SslStream::new(ssl, socket)?;
+    Pin::new(&mut tls_stream).accept().await?;
+
+    let subject_cn = tls_stream
+        .ssl()
+        .peer_certificate()
+        .and_then(|cert| subject_common_name(&cert));
+    let mapping = subject_cn
+        .as_deref()
+        .and_then(|cn| find_subject_mapping(&tls_config.subject_mappings, cn));
+    let peer = PeerIdentity {
+        addr,
+        tenant_id: mapping.map(|m| m.tenant_id.clone()),
+        ae_title: mapping.and_then(|m| m.ae_title.clone()),
+        subject_cn,
+    };
+
+    let (scp_end, bridge_end) = UnixStream::pair()?;
+    scp_end.set_nonblocking(true)?;
+    bridge_end.set_nonblocking(true)?;
+    // SAFETY: 文件描述符来自刚创建的 socketpair, 所有权转移给 TcpStream;
+    // 关联只对其读写、设置超时及关闭, 这些操作对 Unix 套接字同样有效
+    let scp_end = unsafe { std::net::TcpStream::from_raw_fd(scp_end.into_raw_fd()) };
+    let scp_end = TcpStream::from_std(scp_end)?;
+    let mut bridge_end = tokio::net::UnixStream::from_std(bridge_end)?;
+
+    tokio::spawn(async move {
+        // 任意一端关闭后结束转发
+        let _ = tokio::io::copy_bidirectional(&mut tls_stream, &mut bridge_end).await;
+    });
+
+    Ok((scp_end, peer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_subject_mapping() {
+        let mappings = vec![TlsSubjectMapping {
+            subject_cn: "ct01.medical.org".to_string(),
+            tenant_id: "tenant1".to_string(),
+            ae_title: Some("CT01".to_string()),
+        }];
+
+        let mapping = find_subject_mapping(&mappings, "ct01.medical.org").unwrap();
+        assert_eq!(mapping.tenant_id, "tenant1");
+        assert_eq!(mapping.ae_title.as_deref(), Some("CT01"));
+        assert!(find_subject_mapping(&mappings, "mr01.medical.org").is_none());
+    }
+
+    #[test]
+    fn test_build_acceptor_missing_files() {
+        let tls_config = DicomTlsConfig {
+            port: 2762,
+            cert_file: "/nonexistent/tls.crt".to_string(),
+            key_file: "/nonexistent/tls.key".to_string(),
+            ca_file: None,
+            subject_mappings: vec![],
+        };
+        assert!(build_acceptor(&tls_config).is_err());
+    }
+}
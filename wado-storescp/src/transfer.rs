@@ -40,4 +40,218 @@ pub static ABSTRACT_SYNTAXES: &[&str] = &[
     ENHANCED_SR_STORAGE,
     COMPREHENSIVE_SR_STORAGE,
     VERIFICATION,
+    MODALITY_PERFORMED_PROCEDURE_STEP,
 ];
+
+/// 非存储类服务, 无论策略如何配置都会接受
+pub static SERVICE_SYNTAXES: &[&str] = &[VERIFICATION, MODALITY_PERFORMED_PROCEDURE_STEP];
+
+/// DICOM 标准 (PS3.4 Annex B.5) 中的存储类 SOP Class, 配置 include_all_storage 时使用.
+/// 取自 dicom-dictionary-std 中 1.2.840.10008.5.1.4.1.1 下未退役的 Storage SOP Class,
+/// 升级字典后由 test_all_storage_sop_classes 校验
+pub static ALL_STORAGE_SOP_CLASSES: &[&str] = &[
+    COMPUTED_RADIOGRAPHY_IMAGE_STORAGE,
+    DIGITAL_X_RAY_IMAGE_STORAGE_FOR_PRESENTATION,
+    DIGITAL_X_RAY_IMAGE_STORAGE_FOR_PROCESSING,
+    DIGITAL_MAMMOGRAPHY_X_RAY_IMAGE_STORAGE_FOR_PRESENTATION,
+    DIGITAL_MAMMOGRAPHY_X_RAY_IMAGE_STORAGE_FOR_PROCESSING,
+    DIGITAL_INTRA_ORAL_X_RAY_IMAGE_STORAGE_FOR_PRESENTATION,
+    DIGITAL_INTRA_ORAL_X_RAY_IMAGE_STORAGE_FOR_PROCESSING,
+    ENCAPSULATED_PDF_STORAGE,
+    ENCAPSULATED_CDA_STORAGE,
+    ENCAPSULATED_STL_STORAGE,
+    ENCAPSULATED_OBJ_STORAGE,
+    ENCAPSULATED_MTL_STORAGE,
+    GRAYSCALE_SOFTCOPY_PRESENTATION_STATE_STORAGE,
+    SEGMENTED_VOLUME_RENDERING_VOLUMETRIC_PRESENTATION_STATE_STORAGE,
+    MULTIPLE_VOLUME_RENDERING_VOLUMETRIC_PRESENTATION_STATE_STORAGE,
+    VARIABLE_MODALITY_LUT_SOFTCOPY_PRESENTATION_STATE_STORAGE,
+    COLOR_SOFTCOPY_PRESENTATION_STATE_STORAGE,
+    PSEUDO_COLOR_SOFTCOPY_PRESENTATION_STATE_STORAGE,
+    BLENDING_SOFTCOPY_PRESENTATION_STATE_STORAGE,
+    XAXRF_GRAYSCALE_SOFTCOPY_PRESENTATION_STATE_STORAGE,
+    GRAYSCALE_PLANAR_MPR_VOLUMETRIC_PRESENTATION_STATE_STORAGE,
+    COMPOSITING_PLANAR_MPR_VOLUMETRIC_PRESENTATION_STATE_STORAGE,
+    ADVANCED_BLENDING_PRESENTATION_STATE_STORAGE,
+    VOLUME_RENDERING_VOLUMETRIC_PRESENTATION_STATE_STORAGE,
+    X_RAY_ANGIOGRAPHIC_IMAGE_STORAGE,
+    ENHANCED_XA_IMAGE_STORAGE,
+    X_RAY_RADIOFLUOROSCOPIC_IMAGE_STORAGE,
+    ENHANCED_XRF_IMAGE_STORAGE,
+    POSITRON_EMISSION_TOMOGRAPHY_IMAGE_STORAGE,
+    LEGACY_CONVERTED_ENHANCED_PET_IMAGE_STORAGE,
+    X_RAY3_D_ANGIOGRAPHIC_IMAGE_STORAGE,
+    X_RAY3_D_CRANIOFACIAL_IMAGE_STORAGE,
+    BREAST_TOMOSYNTHESIS_IMAGE_STORAGE,
+    BREAST_PROJECTION_X_RAY_IMAGE_STORAGE_FOR_PRESENTATION,
+    BREAST_PROJECTION_X_RAY_IMAGE_STORAGE_FOR_PROCESSING,
+    ENHANCED_PET_IMAGE_STORAGE,
+    BASIC_STRUCTURED_DISPLAY_STORAGE,
+    INTRAVASCULAR_OPTICAL_COHERENCE_TOMOGRAPHY_IMAGE_STORAGE_FOR_PRESENTATION,
+    INTRAVASCULAR_OPTICAL_COHERENCE_TOMOGRAPHY_IMAGE_STORAGE_FOR_PROCESSING,
+    CT_IMAGE_STORAGE,
+    ENHANCED_CT_IMAGE_STORAGE,
+    LEGACY_CONVERTED_ENHANCED_CT_IMAGE_STORAGE,
+    NUCLEAR_MEDICINE_IMAGE_STORAGE,
+    CT_DEFINED_PROCEDURE_PROTOCOL_STORAGE,
+    CT_PERFORMED_PROCEDURE_PROTOCOL_STORAGE,
+    PROTOCOL_APPROVAL_STORAGE,
+    XA_DEFINED_PROCEDURE_PROTOCOL_STORAGE,
+    XA_PERFORMED_PROCEDURE_PROTOCOL_STORAGE,
+    INVENTORY_STORAGE,
+    ULTRASOUND_MULTI_FRAME_IMAGE_STORAGE,
+    PARAMETRIC_MAP_STORAGE,
+    MR_IMAGE_STORAGE,
+    ENHANCED_MR_IMAGE_STORAGE,
+    MR_SPECTROSCOPY_STORAGE,
+    ENHANCED_MR_COLOR_IMAGE_STORAGE,
+    LEGACY_CONVERTED_ENHANCED_MR_IMAGE_STORAGE,
+    RT_IMAGE_STORAGE,
+    RT_PHYSICIAN_INTENT_STORAGE,
+    RT_SEGMENT_ANNOTATION_STORAGE,
+    RT_RADIATION_SET_STORAGE,
+    C_ARM_PHOTON_ELECTRON_RADIATION_STORAGE,
+    TOMOTHERAPEUTIC_RADIATION_STORAGE,
+    ROBOTIC_ARM_RADIATION_STORAGE,
+    RT_RADIATION_RECORD_SET_STORAGE,
+    RT_RADIATION_SALVAGE_RECORD_STORAGE,
+    TOMOTHERAPEUTIC_RADIATION_RECORD_STORAGE,
+    C_ARM_PHOTON_ELECTRON_RADIATION_RECORD_STORAGE,
+    RT_DOSE_STORAGE,
+    ROBOTIC_RADIATION_RECORD_STORAGE,
+    RT_RADIATION_SET_DELIVERY_INSTRUCTION_STORAGE,
+    RT_TREATMENT_PREPARATION_STORAGE,
+    ENHANCED_RT_IMAGE_STORAGE,
+    ENHANCED_CONTINUOUS_RT_IMAGE_STORAGE,
+    RT_PATIENT_POSITION_ACQUISITION_INSTRUCTION_STORAGE,
+    RT_STRUCTURE_SET_STORAGE,
+    RT_BEAMS_TREATMENT_RECORD_STORAGE,
+    RT_PLAN_STORAGE,
+    RT_BRACHY_TREATMENT_RECORD_STORAGE,
+    RT_TREATMENT_SUMMARY_RECORD_STORAGE,
+    RT_ION_PLAN_STORAGE,
+    RT_ION_BEAMS_TREATMENT_RECORD_STORAGE,
+    DICOSCT_IMAGE_STORAGE,
+    DICOS_DIGITAL_X_RAY_IMAGE_STORAGE_FOR_PRESENTATION,
+    DICOS_DIGITAL_X_RAY_IMAGE_STORAGE_FOR_PROCESSING,
+    DICOS_THREAT_DETECTION_REPORT_STORAGE,
+    DICOS2DAIT_STORAGE,
+    DICOS3DAIT_STORAGE,
+    DICOS_QUADRUPOLE_RESONANCE_STORAGE,
+    ULTRASOUND_IMAGE_STORAGE,
+    ENHANCED_US_VOLUME_STORAGE,
+    PHOTOACOUSTIC_IMAGE_STORAGE,
+    EDDY_CURRENT_IMAGE_STORAGE,
+    EDDY_CURRENT_MULTI_FRAME_IMAGE_STORAGE,
+    THERMOGRAPHY_IMAGE_STORAGE,
+    THERMOGRAPHY_MULTI_FRAME_IMAGE_STORAGE,
+    ULTRASOUND_WAVEFORM_STORAGE,
+    RAW_DATA_STORAGE,
+    SPATIAL_REGISTRATION_STORAGE,
+    SPATIAL_FIDUCIALS_STORAGE,
+    DEFORMABLE_SPATIAL_REGISTRATION_STORAGE,
+    SEGMENTATION_STORAGE,
+    SURFACE_SEGMENTATION_STORAGE,
+    TRACTOGRAPHY_RESULTS_STORAGE,
+    LABEL_MAP_SEGMENTATION_STORAGE,
+    HEIGHT_MAP_SEGMENTATION_STORAGE,
+    REAL_WORLD_VALUE_MAPPING_STORAGE,
+    SURFACE_SCAN_MESH_STORAGE,
+    SURFACE_SCAN_POINT_CLOUD_STORAGE,
+    SECONDARY_CAPTURE_IMAGE_STORAGE,
+    MULTI_FRAME_SINGLE_BIT_SECONDARY_CAPTURE_IMAGE_STORAGE,
+    MULTI_FRAME_GRAYSCALE_BYTE_SECONDARY_CAPTURE_IMAGE_STORAGE,
+    MULTI_FRAME_GRAYSCALE_WORD_SECONDARY_CAPTURE_IMAGE_STORAGE,
+    MULTI_FRAME_TRUE_COLOR_SECONDARY_CAPTURE_IMAGE_STORAGE,
+    VL_ENDOSCOPIC_IMAGE_STORAGE,
+    VIDEO_ENDOSCOPIC_IMAGE_STORAGE,
+    VL_MICROSCOPIC_IMAGE_STORAGE,
+    VIDEO_MICROSCOPIC_IMAGE_STORAGE,
+    VL_SLIDE_COORDINATES_MICROSCOPIC_IMAGE_STORAGE,
+    VL_PHOTOGRAPHIC_IMAGE_STORAGE,
+    VIDEO_PHOTOGRAPHIC_IMAGE_STORAGE,
+    OPHTHALMIC_PHOTOGRAPHY8_BIT_IMAGE_STORAGE,
+    OPHTHALMIC_PHOTOGRAPHY16_BIT_IMAGE_STORAGE,
+    STEREOMETRIC_RELATIONSHIP_STORAGE,
+    OPHTHALMIC_TOMOGRAPHY_IMAGE_STORAGE,
+    WIDE_FIELD_OPHTHALMIC_PHOTOGRAPHY_STEREOGRAPHIC_PROJECTION_IMAGE_STORAGE,
+    WIDE_FIELD_OPHTHALMIC_PHOTOGRAPHY3_D_COORDINATES_IMAGE_STORAGE,
+    OPHTHALMIC_OPTICAL_COHERENCE_TOMOGRAPHY_EN_FACE_IMAGE_STORAGE,
+    OPHTHALMIC_OPTICAL_COHERENCE_TOMOGRAPHY_BSCAN_VOLUME_ANALYSIS_STORAGE,
+    VL_WHOLE_SLIDE_MICROSCOPY_IMAGE_STORAGE,
+    DERMOSCOPIC_PHOTOGRAPHY_IMAGE_STORAGE,
+    CONFOCAL_MICROSCOPY_IMAGE_STORAGE,
+    CONFOCAL_MICROSCOPY_TILED_PYRAMIDAL_IMAGE_STORAGE,
+    LENSOMETRY_MEASUREMENTS_STORAGE,
+    AUTOREFRACTION_MEASUREMENTS_STORAGE,
+    KERATOMETRY_MEASUREMENTS_STORAGE,
+    SUBJECTIVE_REFRACTION_MEASUREMENTS_STORAGE,
+    VISUAL_ACUITY_MEASUREMENTS_STORAGE,
+    SPECTACLE_PRESCRIPTION_REPORT_STORAGE,
+    OPHTHALMIC_AXIAL_MEASUREMENTS_STORAGE,
+    INTRAOCULAR_LENS_CALCULATIONS_STORAGE,
+    MACULAR_GRID_THICKNESS_AND_VOLUME_REPORT_STORAGE,
+    OPHTHALMIC_VISUAL_FIELD_STATIC_PERIMETRY_MEASUREMENTS_STORAGE,
+    OPHTHALMIC_THICKNESS_MAP_STORAGE,
+    CORNEAL_TOPOGRAPHY_MAP_STORAGE,
+    BASIC_TEXT_SR_STORAGE,
+    ENHANCED_SR_STORAGE,
+    COMPREHENSIVE_SR_STORAGE,
+    COMPREHENSIVE3_DSR_STORAGE,
+    EXTENSIBLE_SR_STORAGE,
+    PROCEDURE_LOG_STORAGE,
+    MAMMOGRAPHY_CADSR_STORAGE,
+    KEY_OBJECT_SELECTION_DOCUMENT_STORAGE,
+    CHEST_CADSR_STORAGE,
+    X_RAY_RADIATION_DOSE_SR_STORAGE,
+    RADIOPHARMACEUTICAL_RADIATION_DOSE_SR_STORAGE,
+    COLON_CADSR_STORAGE,
+    IMPLANTATION_PLAN_SR_STORAGE,
+    ACQUISITION_CONTEXT_SR_STORAGE,
+    SIMPLIFIED_ADULT_ECHO_SR_STORAGE,
+    PATIENT_RADIATION_DOSE_SR_STORAGE,
+    PLANNED_IMAGING_AGENT_ADMINISTRATION_SR_STORAGE,
+    PERFORMED_IMAGING_AGENT_ADMINISTRATION_SR_STORAGE,
+    ENHANCED_X_RAY_RADIATION_DOSE_SR_STORAGE,
+    WAVEFORM_ANNOTATION_SR_STORAGE,
+    TWELVE_LEAD_ECG_WAVEFORM_STORAGE,
+    GENERAL_ECG_WAVEFORM_STORAGE,
+    AMBULATORY_ECG_WAVEFORM_STORAGE,
+    GENERAL32BIT_ECG_WAVEFORM_STORAGE,
+    WAVEFORM_PRESENTATION_STATE_STORAGE,
+    WAVEFORM_ACQUISITION_PRESENTATION_STATE_STORAGE,
+    HEMODYNAMIC_WAVEFORM_STORAGE,
+    CARDIAC_ELECTROPHYSIOLOGY_WAVEFORM_STORAGE,
+    BASIC_VOICE_AUDIO_WAVEFORM_STORAGE,
+    GENERAL_AUDIO_WAVEFORM_STORAGE,
+    ARTERIAL_PULSE_WAVEFORM_STORAGE,
+    RESPIRATORY_WAVEFORM_STORAGE,
+    MULTICHANNEL_RESPIRATORY_WAVEFORM_STORAGE,
+    ROUTINE_SCALP_ELECTROENCEPHALOGRAM_WAVEFORM_STORAGE,
+    ELECTROMYOGRAM_WAVEFORM_STORAGE,
+    ELECTROOCULOGRAM_WAVEFORM_STORAGE,
+    SLEEP_ELECTROENCEPHALOGRAM_WAVEFORM_STORAGE,
+    BODY_POSITION_WAVEFORM_STORAGE,
+    CONTENT_ASSESSMENT_RESULTS_STORAGE,
+    MICROSCOPY_BULK_SIMPLE_ANNOTATIONS_STORAGE,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom_core::dictionary::{UidDictionary, UidType};
+    use dicom_dictionary_std::StandardSopClassDictionary;
+
+    #[test]
+    fn test_all_storage_sop_classes() {
+        for uid in ALL_STORAGE_SOP_CLASSES {
+            let entry = StandardSopClassDictionary
+                .by_uid(uid)
+                .unwrap_or_else(|| panic!("{} is not a standard SOP class", uid));
+            assert_eq!(entry.r#type, UidType::SopClass);
+            assert!(!entry.retired, "{} is retired", entry.name);
+            assert!(entry.name.contains("Storage"), "{}", entry.name);
+            assert!(uid.starts_with("1.2.840.10008.5.1.4.1.1."));
+        }
+    }
+}
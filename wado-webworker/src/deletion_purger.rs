@@ -0,0 +1,22 @@
+use crate::AppState;
+use common::deletion::purge_expired_deletions;
+use common::server_config::DeletionConfig;
+use slog::{error, info};
+use tokio::time::{Duration, interval};
+
+const PURGE_BATCH_SIZE: i64 = 100;
+
+// 定期清除回收站保留期已过的文件
+pub(crate) async fn deletion_purge_task(app_state: AppState, deletion: DeletionConfig) {
+    let mut interval = interval(Duration::from_secs(deletion.purge_interval_secs.max(60)));
+    loop {
+        interval.tick().await;
+        match purge_expired_deletions(app_state.db.as_ref(), &app_state.config, PURGE_BATCH_SIZE)
+            .await
+        {
+            Ok(0) => {}
+            Ok(purged) => info!(app_state.log, "Purged {} expired deletions", purged),
+            Err(e) => error!(app_state.log, "Purge expired deletions failed: {}", e),
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use crate::AppState;
+use crate::json_creator::{get_cpu_usage, get_memory_usage};
+use common::fixity::compute_checksum;
+use common::logevents::FixityEvent;
+use common::message_sender_kafka::{KafkaMessagePublisher, MessagePublisher};
+use common::server_config::FixityConfig;
+use common::storage_backend::StorageError;
+use common::storage_tier::tiered_storage;
+use database::dicom_dbprovider::current_time;
+use database::dicom_dbtype::BoundedString;
+use database::dicom_meta::DicomFixityCheck;
+use slog::{error, info, warn};
+use sysinfo::{System, SystemExt};
+use tokio::time::{Duration, interval};
+
+// 定期重新计算文件校验值, 发现损坏或丢失的文件
+pub(crate) async fn fixity_check_task(app_state: AppState, fixity: FixityConfig) {
+    let mut interval = interval(Duration::from_secs(fixity.interval_secs.max(60)));
+    let mut sys = System::new_all();
+    loop {
+        interval.tick().await;
+
+        // 与 JSON 生成任务相同, 系统繁忙时跳过本轮
+        sys.refresh_all();
+        let cpu_usage = get_cpu_usage(&sys);
+        let memory_usage = get_memory_usage(&sys);
+        if cpu_usage >= fixity.cpu_usage as f32 || memory_usage >= fixity.memory_usage as f32 {
+            info!(
+                app_state.log,
+                "System busy, skip fixity check - CPU: {:.2}%, Memory: {:.2}%",
+                cpu_usage,
+                memory_usage
+            );
+            continue;
+        }
+
+        match verify_batch(&app_state, &fixity).await {
+            Ok((0, _)) => {}
+            Ok((checked, failed)) => info!(
+                app_state.log,
+                "Fixity check: verified {} files, {} failed", checked, failed
+            ),
+            Err(e) => error!(app_state.log, "Fixity check failed: {}", e),
+        }
+    }
+}
+
+/// 根据读取结果更新校验记录, 读取失败(非 NotFound)时返回 None, 下一轮重试
+pub fn apply_result(
+    mut check: DicomFixityCheck,
+    result: Result<Vec<u8>, StorageError>,
+) -> Option<DicomFixityCheck> {
+    match result {
+        Ok(data) => {
+            let actual = compute_checksum(&data);
+            check.status = if actual == check.expected_checksum.as_str() {
+                BoundedString::make_str(DicomFixityCheck::STATUS_OK)
+            } else {
+                BoundedString::make_str(DicomFixityCheck::STATUS_MISMATCH)
+            };
+            check.actual_checksum = Some(BoundedString::make(actual));
+        }
+        Err(StorageError::NotFound(_)) => {
+            check.status = BoundedString::make_str(DicomFixityCheck::STATUS_MISSING);
+            check.actual_checksum = None;
+        }
+        Err(_) => return None,
+    }
+    check.checked_time = Some(current_time());
+    Some(check)
+}
+
+async fn verify_batch(
+    app_state: &AppState,
+    fixity: &FixityConfig,
+) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+    let checked_before = current_time() - chrono::Duration::days(fixity.verify_after_days as i64);
+    let candidates = app_state
+        .db
+        .get_fixity_candidates(checked_before, fixity.batch_size)
+        .await?;
+
+    let storage = tiered_storage();
+    let mut results = Vec::with_capacity(candidates.len());
+    for candidate in candidates {
+        // 文件可能已迁移到其他存储层
+        let read = storage
+            .get(candidate.file_path.as_str())
+            .await
+            .map(|(_tier, data)| data);
+        if let Err(e) = &read {
+            warn!(app_state.log, "Failed to read {}: {}", candidate.file_path, e);
+        }
+        if let Some(check) = apply_result(candidate, read) {
+            results.push(check);
+        }
+    }
+    if results.is_empty() {
+        return Ok((0, 0));
+    }
+    app_state.db.save_fixity_checks(&results).await?;
+
+    let failures: Vec<FixityEvent> = results
+        .iter()
+        .filter(|c| c.status.as_str() != DicomFixityCheck::STATUS_OK)
+        .map(|c| FixityEvent {
+            tenant_id: c.tenant_id.as_str().to_string(),
+            study_uid: c.study_uid.as_str().to_string(),
+            series_uid: c.series_uid.as_str().to_string(),
+            sop_uid: c.sop_uid.as_str().to_string(),
+            file_path: c.file_path.as_str().to_string(),
+            expected_checksum: c.expected_checksum.as_str().to_string(),
+            actual_checksum: c.actual_checksum.as_ref().map(|v| v.as_str().to_string()),
+            status: c.status.as_str().to_string(),
+            detected_time: c.checked_time.unwrap_or_else(current_time),
+        })
+        .collect();
+    for event in &failures {
+        error!(
+            app_state.log,
+            "Fixity {}: {} expected {} actual {:?}",
+            event.status,
+            event.file_path,
+            event.expected_checksum,
+            event.actual_checksum
+        );
+    }
+    if !failures.is_empty()
+        && let Some(topic) = &app_state.config.message_queue.topic_fixity
+        && !topic.is_empty()
+    {
+        let producer = KafkaMessagePublisher::new(topic.clone());
+        if let Err(e) = producer.send_fixity_messages(&failures).await {
+            warn!(app_state.log, "Failed to publish fixity events: {}", e);
+        }
+    }
+    Ok((results.len(), failures.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_check(expected: &str) -> DicomFixityCheck {
+        DicomFixityCheck {
+            tenant_id: BoundedString::make_str("tenant1"),
+            study_uid: BoundedString::make_str("1.2.3"),
+            series_uid: BoundedString::make_str("1.2.3.4"),
+            sop_uid: BoundedString::make_str("1.2.3.4.5"),
+            file_path: BoundedString::make_str("tenant1/20240101/st/se/1.2.3.4.5.dcm"),
+            expected_checksum: BoundedString::make_str(expected),
+            actual_checksum: None,
+            status: BoundedString::make_str(DicomFixityCheck::STATUS_PENDING),
+            checked_time: None,
+        }
+    }
+
+    #[test]
+    fn test_apply_result() {
+        let expected = compute_checksum(b"abc");
+        let check = apply_result(make_check(&expected), Ok(b"abc".to_vec())).unwrap();
+        assert_eq!(check.status.as_str(), DicomFixityCheck::STATUS_OK);
+        assert!(check.checked_time.is_some());
+
+        let check = apply_result(make_check(&expected), Ok(b"abd".to_vec())).unwrap();
+        assert_eq!(check.status.as_str(), DicomFixityCheck::STATUS_MISMATCH);
+        assert_eq!(
+            check.actual_checksum.unwrap().as_str(),
+            compute_checksum(b"abd")
+        );
+
+        let check = apply_result(
+            make_check(&expected),
+            Err(StorageError::NotFound("x".to_string())),
+        )
+        .unwrap();
+        assert_eq!(check.status.as_str(), DicomFixityCheck::STATUS_MISSING);
+
+        assert!(apply_result(make_check(&expected), Err(StorageError::Io("x".to_string()))).is_none());
+    }
+}
@@ -1,9 +1,10 @@
 use crate::AppState;
 use common::dicom_json_helper::generate_series_json;
+use common::rejection::rejected_sop_uids;
 use common::server_config::WebWorkerConfig;
 use database::dicom_dbprovider::current_time;
 use database::dicom_dbtype::BoundedString;
-use database::dicom_meta::DicomJsonMeta;
+use database::dicom_meta::{DicomJsonMeta, DicomStateMeta};
 use slog::{error, info};
 use std::ops::Sub;
 use sysinfo::{CpuExt, System, SystemExt};
@@ -51,7 +52,7 @@ pub(crate) async fn background_task_manager(app_state: AppState) {
 }
 
 // 获取CPU使用率
-fn get_cpu_usage(sys: &System) -> f32 {
+pub(crate) fn get_cpu_usage(sys: &System) -> f32 {
     let cpus = sys.cpus();
     if cpus.is_empty() {
         return 0.0;
@@ -62,7 +63,7 @@ fn get_cpu_usage(sys: &System) -> f32 {
 }
 
 // 获取内存使用率
-fn get_memory_usage(sys: &System) -> f32 {
+pub(crate) fn get_memory_usage(sys: &System) -> f32 {
     let total_memory = sys.total_memory();
     let used_memory = sys.used_memory();
 
@@ -98,6 +99,20 @@ async fn execute_background_json_generation(
         pending_records.len()
     );
 
+    generate_json_for_records(app_state, pending_records).await;
+
+    info!(
+        app_state.log,
+        "Background JSON metadata generation completed"
+    );
+    Ok(())
+}
+
+// 为指定的序列记录生成JSON元数据, 并保存生成结果
+pub(crate) async fn generate_json_for_records(
+    app_state: &AppState,
+    pending_records: Vec<DicomStateMeta>,
+) {
     let mut json_mets = vec![];
     // 逐个处理记录
     for record in pending_records {
@@ -130,7 +145,12 @@ async fn execute_background_json_generation(
         };
         // 这里应该调用实际的JSON生成逻辑
         // 可以参考wado_rs_controller.rs中的实现
-        let result_status = match generate_series_json(&record).await {
+        let generated = match rejected_sop_uids(app_state.db.as_ref(), tenant_id, study_uid).await
+        {
+            Ok(rejected) => generate_series_json(&record, &rejected).await,
+            Err(e) => Err(std::io::Error::other(e.to_string())),
+        };
+        let result_status = match generated {
             Ok(_) => {
                 info!(
                     app_state.log,
@@ -198,10 +218,4 @@ async fn execute_background_json_generation(
             error!(app_state.log, "Failed to save JSON metadata records");
         }
     }
-
-    info!(
-        app_state.log,
-        "Background JSON metadata generation completed"
-    );
-    Ok(())
 }
@@ -6,7 +6,14 @@ use database::dicom_dbprovider::DbProvider;
 use slog::{Logger, error, info};
 use std::sync::Arc;
 
+mod deletion_purger;
+mod fixity_checker;
 mod json_creator;
+mod rejection_purger;
+mod retention_evaluator;
+mod storage_gc;
+mod study_complete_listener;
+mod tier_migrator;
 #[derive(Clone)]
 struct AppState {
     log: Logger,
@@ -34,6 +41,12 @@ async fn main() -> std::io::Result<()> {
     println!("执行后台工作任务:");
     println!(" 1: 生成 WADO-RS 服务需要的study_metadata ");
     println!(" 2: 根据收图日志更新SeriesRelatedInstance 取值");
+    println!(" 3: 按分层存储策略迁移序列");
+    println!(" 4: 定期校验文件内容");
+    println!(" 5: 定期对账存储与索引");
+    println!(" 6: 清除过期的回收站文件");
+    println!(" 7: 清除数据保留期已过的被拒绝实例");
+    println!(" 8: 按数据保留策略查找到期的检查");
     let log = configure_log();
     let config = server_config::load_config();
     let config = match config {
@@ -71,6 +84,45 @@ async fn main() -> std::io::Result<()> {
         redis_helper: RedisHelper::new(reids_conn),
     };
 
+    tokio::spawn(study_complete_listener::study_complete_listener(
+        app_state.clone(),
+    ));
+    if let Some(tiering) = app_state.config.tiering.clone()
+        && !tiering.policies.is_empty()
+    {
+        tokio::spawn(tier_migrator::tier_migration_task(
+            app_state.clone(),
+            tiering,
+        ));
+    }
+    if let Some(fixity) = app_state.config.fixity.clone() {
+        tokio::spawn(fixity_checker::fixity_check_task(app_state.clone(), fixity));
+    }
+    if let Some(gc) = app_state.config.gc.clone() {
+        tokio::spawn(storage_gc::storage_gc_task(app_state.clone(), gc));
+    }
+    if let Some(deletion) = app_state.config.deletion.clone()
+        && deletion.trash_days > 0
+    {
+        tokio::spawn(deletion_purger::deletion_purge_task(
+            app_state.clone(),
+            deletion,
+        ));
+    }
+    if let Some(rejection) = app_state.config.rejection.clone() {
+        tokio::spawn(rejection_purger::rejection_purge_task(
+            app_state.clone(),
+            rejection,
+        ));
+    }
+    if let Some(retention) = app_state.config.retention.clone()
+        && !retention.policies.is_empty()
+    {
+        tokio::spawn(retention_evaluator::retention_task(
+            app_state.clone(),
+            retention,
+        ));
+    }
     json_creator::background_task_manager(app_state).await;
     Ok(())
 }
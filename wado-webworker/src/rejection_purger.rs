@@ -0,0 +1,25 @@
+use crate::AppState;
+use common::rejection::purge_rejected_instances;
+use common::server_config::RejectionConfig;
+use slog::{error, info};
+use tokio::time::{Duration, interval};
+
+// 定期删除因数据保留期已过被拒绝的实例
+pub(crate) async fn rejection_purge_task(app_state: AppState, rejection: RejectionConfig) {
+    let mut interval = interval(Duration::from_secs(rejection.purge_interval_secs.max(60)));
+    loop {
+        interval.tick().await;
+        match purge_rejected_instances(
+            app_state.db.as_ref(),
+            &app_state.redis_helper,
+            &app_state.config,
+            rejection.batch_size,
+        )
+        .await
+        {
+            Ok(0) => {}
+            Ok(purged) => info!(app_state.log, "Purged {} rejected instances", purged),
+            Err(e) => error!(app_state.log, "Purge rejected instances failed: {}", e),
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use crate::AppState;
+use common::retention::{auto_delete_candidates, find_retention_candidates};
+use common::server_config::RetentionConfig;
+use database::dicom_dbprovider::current_time;
+use slog::{error, info};
+use tokio::time::{Duration, interval};
+
+// 定期按保留策略查找到期的检查, 配置 auto_delete 时直接删除
+pub(crate) async fn retention_task(app_state: AppState, retention: RetentionConfig) {
+    let mut interval = interval(Duration::from_secs(retention.interval_secs.max(60)));
+    loop {
+        interval.tick().await;
+        let today = current_time().date();
+        match find_retention_candidates(app_state.db.as_ref(), &retention, today).await {
+            Ok(0) => {}
+            Ok(found) => info!(
+                app_state.log,
+                "Found {} studies past their retention period", found
+            ),
+            Err(e) => error!(app_state.log, "Retention evaluation failed: {}", e),
+        }
+        if !retention.auto_delete {
+            continue;
+        }
+        match auto_delete_candidates(
+            app_state.db.as_ref(),
+            &app_state.redis_helper,
+            &app_state.config,
+            retention.batch_size,
+        )
+        .await
+        {
+            Ok(0) => {}
+            Ok(deleted) => info!(app_state.log, "Deleted {} expired studies", deleted),
+            Err(e) => error!(app_state.log, "Retention auto delete failed: {}", e),
+        }
+    }
+}
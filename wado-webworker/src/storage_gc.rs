@@ -0,0 +1,38 @@
+use crate::AppState;
+use common::reconcile::{ReconcileOptions, reconcile};
+use common::server_config::GcConfig;
+use slog::{error, info, warn};
+use tokio::time::{Duration, interval};
+
+// 定期对账存储与索引, 处理孤立文件和悬空索引
+pub(crate) async fn storage_gc_task(app_state: AppState, gc: GcConfig) {
+    let options = ReconcileOptions::from(&gc);
+    let mut interval = interval(Duration::from_secs(gc.interval_secs.max(3600)));
+    loop {
+        interval.tick().await;
+        match reconcile(app_state.db.as_ref(), &app_state.config, &options).await {
+            Ok(report) => {
+                info!(
+                    app_state.log,
+                    "Storage reconcile: scanned {} files, {} records",
+                    report.scanned_files,
+                    report.scanned_records
+                );
+                if !report.orphans.is_empty() || !report.dangling.is_empty() {
+                    warn!(
+                        app_state.log,
+                        "Storage reconcile: {} orphan files ({} reindexed, {} quarantined, {} within grace), {} dangling records ({} marked missing), dry_run: {}",
+                        report.orphans.len(),
+                        report.reindexed,
+                        report.quarantined,
+                        report.skipped_recent,
+                        report.dangling.len(),
+                        report.marked_missing,
+                        options.dry_run
+                    );
+                }
+            }
+            Err(e) => error!(app_state.log, "Storage reconcile failed: {}", e),
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use crate::AppState;
+use crate::json_creator::generate_json_for_records;
+use common::logevents::StudyCompleteEvent;
+use futures::StreamExt;
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::{ClientConfig, Message};
+use slog::{Logger, error, info, warn};
+use std::future::Future;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// 检查完成事件可能早于序列入库到达, 依次等待后重试
+const RETRY_DELAYS: [Duration; 5] = [
+    Duration::from_secs(5),
+    Duration::from_secs(15),
+    Duration::from_secs(30),
+    Duration::from_secs(60),
+    Duration::from_secs(120),
+];
+
+// 监听检查完成事件(MPPS COMPLETED), 收到后立即生成该检查所有序列的JSON元数据
+pub(crate) async fn study_complete_listener(app_state: AppState) {
+    let topic = match &app_state.config.message_queue.topic_study_complete {
+        Some(topic) if !topic.is_empty() => topic.clone(),
+        _ => {
+            info!(
+                app_state.log,
+                "topic_study_complete not configured, study complete listener disabled"
+            );
+            return;
+        }
+    };
+
+    // 使用独立的消费组, 避免与 wado-consumer 争抢分区
+    let group_id = format!(
+        "{}-webworker",
+        app_state.config.message_queue.consumer_group_id
+    );
+    let consumer: StreamConsumer = match ClientConfig::new()
+        .set("group.id", group_id.as_str())
+        .set("bootstrap.servers", app_state.config.kafka.brokers.as_str())
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", "earliest")
+        .set("session.timeout.ms", "6000")
+        .set("enable.partition.eof", "false")
+        .create()
+    {
+        Ok(consumer) => consumer,
+        Err(e) => {
+            error!(app_state.log, "Failed to create study complete consumer: {}", e);
+            return;
+        }
+    };
+
+    if let Err(e) = consumer.subscribe(&[topic.as_str()]) {
+        error!(app_state.log, "Failed to subscribe to topic {}: {}", topic, e);
+        return;
+    }
+    info!(app_state.log, "Successfully subscribed to topic: {}", topic);
+
+    let mut message_stream = consumer.stream();
+    while let Some(result) = message_stream.next().await {
+        let message = match result {
+            Ok(message) => message,
+            Err(e) => {
+                error!(app_state.log, "Error receiving message: {}", e);
+                continue;
+            }
+        };
+        match message
+            .payload()
+            .map(serde_json::from_slice::<StudyCompleteEvent>)
+        {
+            Some(Ok(event)) => {
+                let state = app_state.clone();
+                let study_uid = event.study_uid.clone();
+                dispatch_with_retry(app_state.log.clone(), study_uid, &RETRY_DELAYS, move || {
+                    let state = state.clone();
+                    let event = event.clone();
+                    async move { handle_study_complete(&state, &event).await }
+                })
+                .await;
+            }
+            Some(Err(e)) => error!(app_state.log, "Failed to deserialize message: {}", e),
+            None => error!(app_state.log, "Received message with no payload"),
+        }
+        // 偏移量独立提交, 失败的事件在后台重试, 不阻塞其他检查的事件
+        if let Err(e) = consumer.commit_message(&message, CommitMode::Async) {
+            error!(app_state.log, "Failed to commit message: {}", e);
+        }
+    }
+}
+
+/// 先处理一次, 失败时转入后台任务按 delays 重试, 返回后台任务句柄.
+/// 重试全部失败的序列由定时任务兜底
+async fn dispatch_with_retry<F, Fut>(
+    log: Logger,
+    study_uid: String,
+    delays: &'static [Duration],
+    handle: F,
+) -> Option<JoinHandle<bool>>
+where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = bool> + Send,
+{
+    if handle().await {
+        return None;
+    }
+    Some(tokio::spawn(async move {
+        for delay in delays {
+            info!(
+                log,
+                "Retry study complete event of study {} in {}s",
+                study_uid,
+                delay.as_secs()
+            );
+            tokio::time::sleep(*delay).await;
+            if handle().await {
+                return true;
+            }
+        }
+        error!(
+            log,
+            "Give up study complete event of study {} after {} retries",
+            study_uid,
+            delays.len()
+        );
+        false
+    }))
+}
+
+/// 返回 false 表示序列尚未入库或查询失败, 需要重试
+async fn handle_study_complete(app_state: &AppState, event: &StudyCompleteEvent) -> bool {
+    info!(
+        app_state.log,
+        "Study complete event, tenant: {}, study: {}, mpps: {}",
+        event.tenant_id,
+        event.study_uid,
+        event.mpps_uid
+    );
+    let records = match app_state
+        .db
+        .get_state_metaes(&event.tenant_id, &event.study_uid)
+        .await
+    {
+        Ok(records) => records,
+        Err(e) => {
+            error!(
+                app_state.log,
+                "Failed to get series of study {}: {}", event.study_uid, e
+            );
+            return false;
+        }
+    };
+    if records.is_empty() {
+        warn!(
+            app_state.log,
+            "No series found for completed study: {}", event.study_uid
+        );
+        return false;
+    }
+    generate_json_for_records(app_state, records).await;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    const TEST_DELAYS: [Duration; 3] = [Duration::from_millis(50); 3];
+
+    #[tokio::test]
+    async fn test_retry_does_not_block_other_events() {
+        let log = Logger::root(slog::Discard, slog::o!());
+
+        // 检查 A 的序列尚未入库
+        let ready = Arc::new(AtomicBool::new(false));
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let (r, a) = (ready.clone(), attempts.clone());
+        let pending = dispatch_with_retry(log.clone(), "A".to_string(), &TEST_DELAYS, move || {
+            let (r, a) = (r.clone(), a.clone());
+            async move {
+                a.fetch_add(1, Ordering::SeqCst);
+                r.load(Ordering::SeqCst)
+            }
+        })
+        .await
+        .expect("study A should be retried in background");
+
+        // A 等待重试期间, B 的事件立即得到处理
+        let processed = Arc::new(AtomicUsize::new(0));
+        let p = processed.clone();
+        let retry = dispatch_with_retry(log, "B".to_string(), &TEST_DELAYS, move || {
+            let p = p.clone();
+            async move {
+                p.fetch_add(1, Ordering::SeqCst);
+                true
+            }
+        })
+        .await;
+        assert!(retry.is_none());
+        assert_eq!(processed.load(Ordering::SeqCst), 1);
+        assert!(!pending.is_finished());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        ready.store(true, Ordering::SeqCst);
+        assert!(pending.await.unwrap());
+        assert!(attempts.load(Ordering::SeqCst) >= 2);
+    }
+}
@@ -0,0 +1,79 @@
+use crate::AppState;
+use common::server_config::{TierPolicy, TieringConfig};
+use common::storage_config::StorageConfig;
+use common::storage_tier::tiered_storage;
+use database::dicom_dbprovider::current_time;
+use database::dicom_dbtype::BoundedString;
+use slog::{error, info, warn};
+use tokio::time::{Duration, interval};
+
+// 按策略将长时间未访问的序列迁移到其他存储层
+pub(crate) async fn tier_migration_task(app_state: AppState, tiering: TieringConfig) {
+    let mut interval = interval(Duration::from_secs(tiering.interval_secs.max(60)));
+    loop {
+        interval.tick().await;
+        for policy in &tiering.policies {
+            match migrate_by_policy(&app_state, policy, tiering.batch_size).await {
+                Ok(0) => {}
+                Ok(count) => info!(
+                    app_state.log,
+                    "Tiering policy {}: migrated {} series from {} to {}",
+                    policy.name,
+                    count,
+                    policy.from_tier,
+                    policy.to_tier
+                ),
+                Err(e) => error!(app_state.log, "Tiering policy {} failed: {}", policy.name, e),
+            }
+        }
+    }
+}
+
+async fn migrate_by_policy(
+    app_state: &AppState,
+    policy: &TierPolicy,
+    batch_size: i64,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let accessed_before = current_time() - chrono::Duration::days(policy.after_days as i64);
+    let candidates = app_state
+        .db
+        .get_series_tier_candidates(
+            &policy.from_tier,
+            &policy.tenant_ids,
+            accessed_before,
+            batch_size,
+        )
+        .await?;
+
+    let storage = tiered_storage();
+    let storage_config = StorageConfig::make_storage_config(&app_state.config);
+    let mut migrated = 0;
+    for mut series in candidates {
+        let series_key = match storage_config.make_series_dicom_key(
+            series.storage_layout,
+            series.tenant_id.as_str(),
+            series.study_date_origin.as_str(),
+            series.study_uid.as_str(),
+            series.series_uid.as_str(),
+        ) {
+            Ok(series_key) => series_key,
+            Err(e) => {
+                warn!(app_state.log, "Failed to locate series {}: {}", series.series_uid, e);
+                continue;
+            }
+        };
+        // 先迁移文件再更新记录, 中途失败时文件仍可从原存储层读取
+        if let Err(e) = storage
+            .migrate(&series_key, &policy.from_tier, &policy.to_tier)
+            .await
+        {
+            warn!(app_state.log, "Failed to migrate series {}: {}", series_key, e);
+            continue;
+        }
+        series.tier = BoundedString::make_str(&policy.to_tier);
+        series.updated_time = current_time();
+        app_state.db.save_series_tier(&series).await?;
+        migrated += 1;
+    }
+    Ok(migrated)
+}